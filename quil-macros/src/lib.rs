@@ -0,0 +1,205 @@
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! The [`quil!`] macro, for writing Quil programs within Rust code which are parsed when the code
+//! is compiled, so that a syntax error is a compiler error rather than a panic at run time.
+//!
+//! The expanded code refers to the `quil_rs` crate, which must be a dependency of the crate the
+//! macro is used in.
+
+use std::str::FromStr;
+
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+use quil_rs::program::{ProgramError, SyntaxError};
+use quil_rs::Program;
+use quote::{quote, quote_spanned};
+
+/// Parse a Quil program when compiling, and expand to an expression which evaluates to that
+/// [`Program`](quil_rs::Program).
+///
+/// The program may be written as a string literal, or directly as tokens. Tokens are read as
+/// Quil text with the line breaks and spacing they are written with, relative to the first
+/// token, so that indented blocks such as those of `DEFGATE` keep their indentation. Instructions
+/// on the same line may be separated by semicolons. A program which Rust cannot split into
+/// tokens, such as one with an unbalanced parenthesis in a comment, must be written as a string.
+///
+/// A program which cannot be parsed is a compiler error, reported at the line at fault where it
+/// is known. The program is parsed again when the expression is evaluated, which cannot fail.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_macros::quil;
+///
+/// let bell = quil! {
+///     DECLARE ro BIT[2]
+///     H 0; CNOT 0 1
+///     MEASURE 0 ro[0]
+///     MEASURE 1 ro[1]
+/// };
+/// assert_eq!(bell.instructions.len(), 4);
+///
+/// let same = quil!("DECLARE ro BIT[2]\nH 0\nCNOT 0 1\nMEASURE 0 ro[0]\nMEASURE 1 ro[1]");
+/// assert_eq!(bell, same);
+/// ```
+///
+/// ```rust,compile_fail
+/// use quil_macros::quil;
+///
+/// let program = quil!("H(0");
+/// ```
+#[proc_macro]
+pub fn quil(input: TokenStream) -> TokenStream {
+    let source = match syn::parse::<syn::LitStr>(input.clone()) {
+        Ok(literal) => Source {
+            text: literal.value(),
+            spans: vec![],
+            default_span: literal.span(),
+        },
+        Err(_) => Source::from_tokens(input),
+    };
+
+    match Program::from_str(&source.text) {
+        Ok(_) => {
+            let text = &source.text;
+            quote! {
+                <::quil_rs::Program as ::std::str::FromStr>::from_str(#text)
+                    .expect("the program was parsed when it was compiled")
+            }
+        }
+        Err(error) => {
+            let span = source.span_of(&error);
+            let message = format!("invalid Quil program: {error}");
+            quote_spanned! {span=> compile_error!(#message) }
+        }
+    }
+    .into()
+}
+
+/// The Quil text given to the macro, along with where in the Rust source each part of it is.
+struct Source {
+    text: String,
+    /// The line and column of the text each token starts at, and its span, in order.
+    spans: Vec<((u32, usize), proc_macro2::Span)>,
+    /// The span to report errors at if no token is known to start where they were found.
+    default_span: proc_macro2::Span,
+}
+
+impl Source {
+    /// Write `tokens` as Quil text, spaced as they were written.
+    fn from_tokens(tokens: TokenStream) -> Self {
+        let mut writer = Writer::default();
+        writer.write_stream(tokens);
+        let default_span = writer
+            .spans
+            .first()
+            .map_or_else(proc_macro2::Span::call_site, |(_, span)| *span);
+        Self {
+            text: writer.text,
+            spans: writer.spans,
+            default_span,
+        }
+    }
+
+    /// The span to report `error` at.
+    fn span_of(&self, error: &ProgramError<Program>) -> proc_macro2::Span {
+        let (line, column) = match error {
+            ProgramError::Syntax(SyntaxError::LexError(error)) => (error.line(), error.column()),
+            ProgramError::Syntax(SyntaxError::ParseError(error)) => (error.line(), error.column()),
+            _ => return self.default_span,
+        };
+        self.spans
+            .iter()
+            .find(|(location, _)| *location == (line, column))
+            .or_else(|| {
+                self.spans
+                    .iter()
+                    .find(|((token_line, _), _)| *token_line == line)
+            })
+            .map_or(self.default_span, |(_, span)| *span)
+    }
+}
+
+/// Builds Quil text from tokens, spacing them by where they are in the Rust source.
+#[derive(Default)]
+struct Writer {
+    text: String,
+    spans: Vec<((u32, usize), proc_macro2::Span)>,
+    /// The line of the text being written, counted from 1.
+    line: u32,
+    /// The source line and column at the end of the last token written.
+    end: (usize, usize),
+    /// The source line and column at the start of the last token written.
+    start: (usize, usize),
+    /// The source column of the first token, which is written at the start of its line.
+    first_column: usize,
+}
+
+impl Writer {
+    fn write_stream(&mut self, tokens: TokenStream) {
+        for token in tokens {
+            match token {
+                TokenTree::Group(group) => {
+                    let (open, close) = match group.delimiter() {
+                        Delimiter::Parenthesis => ("(", ")"),
+                        Delimiter::Bracket => ("[", "]"),
+                        Delimiter::Brace => ("{", "}"),
+                        Delimiter::None => ("", ""),
+                    };
+                    self.write(open, group.span_open());
+                    self.write_stream(group.stream());
+                    self.write(close, group.span_close());
+                }
+                TokenTree::Ident(ident) => self.write(&ident.to_string(), ident.span()),
+                TokenTree::Punct(punct) => self.write(&punct.to_string(), punct.span()),
+                TokenTree::Literal(literal) => self.write(&literal.to_string(), literal.span()),
+            }
+        }
+    }
+
+    /// Write `text`, which starts at `span`, after the text written so far.
+    fn write(&mut self, text: &str, span: Span) {
+        if text.is_empty() {
+            return;
+        }
+        let start = (span.line(), span.column());
+        if self.spans.is_empty() {
+            self.line = 1;
+            self.end = start;
+            self.first_column = start.1;
+        } else if start.0 > self.end.0 {
+            for _ in self.end.0..start.0 {
+                self.text.push('\n');
+            }
+            self.line += (start.0 - self.end.0) as u32;
+            self.text
+                .push_str(&" ".repeat(start.1.saturating_sub(self.first_column)));
+        } else if start == self.start {
+            // Tokens created by other macros may all share one span, and must still be
+            // separated.
+            self.text.push(' ');
+        } else {
+            self.text
+                .push_str(&" ".repeat(start.1.saturating_sub(self.end.1)));
+        }
+
+        let column = self.text.len() - self.text.rfind('\n').map_or(0, |newline| newline + 1) + 1;
+        self.spans.push(((self.line, column), span.into()));
+        self.text.push_str(text);
+        let end = span.end();
+        self.line += (end.line().saturating_sub(start.0)) as u32;
+        self.start = start;
+        self.end = (end.line(), end.column());
+    }
+}
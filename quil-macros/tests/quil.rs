@@ -0,0 +1,90 @@
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use quil_macros::quil;
+use quil_rs::Program;
+
+fn parse(input: &str) -> Program {
+    input.parse().unwrap()
+}
+
+#[test]
+fn test_string_literal() {
+    assert_eq!(quil!("H 0\nCNOT 0 1"), parse("H 0\nCNOT 0 1"));
+    assert_eq!(
+        quil!(r#"PRAGMA INITIAL_REWIRING "PARTIAL""#),
+        parse("PRAGMA INITIAL_REWIRING \"PARTIAL\"")
+    );
+}
+
+#[test]
+fn test_tokens() {
+    let program = quil! {
+        DECLARE ro BIT[2]
+        DECLARE theta REAL
+        RX(-pi/2) 0; CNOT 0 1
+        RZ(2*theta[0] - 1.5) 1
+        LABEL @loop
+        MEASURE 0 ro[0]
+        JUMP-WHEN @loop ro[0]
+    };
+    let expected = parse(
+        "DECLARE ro BIT[2]\nDECLARE theta REAL\nRX(-pi/2) 0\nCNOT 0 1\nRZ(2*theta[0] - 1.5) 1\nLABEL @loop\nMEASURE 0 ro[0]\nJUMP-WHEN @loop ro[0]",
+    );
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn test_indented_block() {
+    let program = quil! {
+        DEFGATE FLIP:
+            0, 1
+            1, 0
+
+        DEFCIRCUIT BELL a b:
+            H a
+            CNOT a b
+
+        FLIP 0
+        BELL 0 1
+    };
+    let expected = parse(
+        "DEFGATE FLIP:\n    0, 1\n    1, 0\n\nDEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nFLIP 0\nBELL 0 1",
+    );
+    assert_eq!(program, expected);
+}
+
+#[test]
+fn test_pulses() {
+    let program = quil! {
+        DEFFRAME 0 "rf":
+            SAMPLE-RATE: 1.0
+        NONBLOCKING PULSE 0 "rf" flat(duration: 1e-6, iq: 1.0)
+    };
+    let expected = parse(
+        "DEFFRAME 0 \"rf\":\n    SAMPLE-RATE: 1.0\nNONBLOCKING PULSE 0 \"rf\" flat(duration: 1e-6, iq: 1.0)",
+    );
+    assert_eq!(program, expected);
+}
+
+macro_rules! bell {
+    () => {
+        quil!(H 0; CNOT 0 1)
+    };
+}
+
+#[test]
+fn test_within_macro() {
+    assert_eq!(bell!(), parse("H 0\nCNOT 0 1"));
+}
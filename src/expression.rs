@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use lexical::{format, to_string_with_options, WriteFloatOptions};
+use nom::Finish;
 use nom_locate::LocatedSpan;
 use num_complex::Complex64;
 use std::collections::{hash_map::DefaultHasher, HashMap};
@@ -28,24 +29,53 @@ use std::str::FromStr;
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
-use crate::parser::{lex, parse_expression, ParseError};
-use crate::program::{disallow_leftover, ProgramError};
+use crate::parser::{lex, parse_expression_with_span, ParseError, Token, TokenWithLocation};
+use crate::program::{LeftoverError, ProgramError, SourceSpan, SyntaxError};
 use crate::{imag, instruction::MemoryReference, real};
 
+pub mod exact;
+pub mod function;
+
+pub use self::function::{CustomFunction, FunctionRegistry, FunctionRegistryError};
+
 /// The different possible types of errors that could occur during expression evaluation.
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
 pub enum EvaluationError {
-    /// There wasn't enough information to completely evaluate an expression.
-    Incomplete,
+    /// The expression refers to a variable which was given no value.
+    #[error("variable %{0} has no value")]
+    UnboundVariable(String),
+    /// The expression reads from a memory region which was given no values.
+    #[error("memory region {0} has no values")]
+    UnboundMemoryRegion(String),
+    /// The expression reads past the end of the values given for a memory region.
+    #[error("{reference} is out of range, as only {length} values were given for it")]
+    MemoryIndexOutOfRange {
+        reference: MemoryReference,
+        length: usize,
+    },
     /// An operation expected a real number but received a complex one.
+    #[error("expected a real number")]
     NumberNotReal,
     /// An operation expected a number but received a different type of expression.
+    #[error("expected a number")]
     NotANumber,
 }
 
+/// The values of memory regions, keyed on region name, as read by [`Expression::evaluate`]. Each
+/// region's values are given in order of index.
+pub type MemoryValues<'a> = HashMap<&'a str, Vec<f64>>;
+
 #[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Expression {
     Address(MemoryReference),
+    /// A call to a function registered in a [`FunctionRegistry`].
+    #[cfg_attr(feature = "arbitrary", arbitrary(skip))]
+    CustomFunctionCall {
+        function: CustomFunction,
+        arguments: Vec<Expression>,
+    },
     FunctionCall {
         function: ExpressionFunction,
         expression: Box<Expression>,
@@ -55,7 +85,10 @@ pub enum Expression {
         operator: InfixOperator,
         right: Box<Expression>,
     },
-    Number(num_complex::Complex64),
+    Number(
+        #[cfg_attr(feature = "arbitrary", arbitrary(with = arbitrary_complex))]
+        num_complex::Complex64,
+    ),
     PiConstant,
     Prefix {
         operator: PrefixOperator,
@@ -64,32 +97,47 @@ pub enum Expression {
     Variable(String),
 }
 
-/// Hash value helper: turn a hashable thing into a u64.
-fn hash_to_u64<T: Hash>(t: &T) -> u64 {
-    let mut s = DefaultHasher::new();
-    t.hash(&mut s);
-    s.finish()
+/// Generate a complex number from its real and imaginary parts, for `arbitrary`, which does not
+/// implement `Arbitrary` for types from other crates.
+#[cfg(feature = "arbitrary")]
+fn arbitrary_complex(u: &mut arbitrary::Unstructured) -> arbitrary::Result<num_complex::Complex64> {
+    Ok(num_complex::Complex64::new(u.arbitrary()?, u.arbitrary()?))
 }
 
-impl Hash for Expression {
-    // Implemented by hand since we can't derive with f64s hidden inside.
-    // Also to understand when things should be the same, like with commutativity (`1 + 2 == 2 + 1`).
-    // See https://github.com/rigetti/quil-rust/issues/27
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        use std::cmp::{max_by_key, min_by_key};
+/// The bits of the real and imaginary parts of `number`, with zero parts (of either sign) as
+/// zero, since f64 isn't hashable and distinct zeros should not tell numbers apart.
+/// The docs claim the bits are rather portable: https://doc.rust-lang.org/std/primitive.f64.html#method.to_bits
+fn number_bits(number: &Complex64) -> (u64, u64) {
+    let bits = |part: f64| if part == 0f64 { 0 } else { part.to_bits() };
+    (bits(number.re), bits(number.im))
+}
+
+impl Expression {
+    /// Hash the expression as it is written, without putting it in canonical form first.
+    fn hash_structure<H: Hasher>(&self, state: &mut H) {
         use Expression::*;
         match self {
             Address(m) => {
                 "Address".hash(state);
                 m.hash(state);
             }
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => {
+                "CustomFunctionCall".hash(state);
+                function.hash(state);
+                arguments
+                    .iter()
+                    .for_each(|argument| argument.hash_structure(state));
+            }
             FunctionCall {
                 function,
                 expression,
             } => {
                 "FunctionCall".hash(state);
                 function.hash(state);
-                expression.hash(state);
+                expression.hash_structure(state);
             }
             Infix {
                 left,
@@ -98,33 +146,12 @@ impl Hash for Expression {
             } => {
                 "Infix".hash(state);
                 operator.hash(state);
-                match operator {
-                    InfixOperator::Plus | InfixOperator::Star => {
-                        // commutative, so put left & right in decreasing order by hash value
-                        let (a, b) = (
-                            min_by_key(left, right, hash_to_u64),
-                            max_by_key(left, right, hash_to_u64),
-                        );
-                        a.hash(state);
-                        b.hash(state);
-                    }
-                    _ => {
-                        left.hash(state);
-                        right.hash(state);
-                    }
-                }
+                left.hash_structure(state);
+                right.hash_structure(state);
             }
             Number(n) => {
                 "Number".hash(state);
-                // Skip zero values (akin to `format_complex`).
-                // Also, since f64 isn't hashable, use the u64 binary representation.
-                // The docs claim this is rather portable: https://doc.rust-lang.org/std/primitive.f64.html#method.to_bits
-                if n.re.abs() > 0f64 {
-                    n.re.to_bits().hash(state)
-                }
-                if n.im.abs() > 0f64 {
-                    n.im.to_bits().hash(state)
-                }
+                number_bits(n).hash(state);
             }
             PiConstant => {
                 "PiConstant".hash(state);
@@ -135,7 +162,7 @@ impl Hash for Expression {
             } => {
                 "Prefix".hash(state);
                 operator.hash(state);
-                expression.hash(state);
+                expression.hash_structure(state);
             }
             Variable(v) => {
                 "Variable".hash(state);
@@ -143,17 +170,107 @@ impl Hash for Expression {
             }
         }
     }
+
+    /// The hash of the expression as it is written, by which the operands of sums and products of
+    /// canonical expressions are ordered.
+    fn structure_hash(&self) -> u64 {
+        let mut state = DefaultHasher::new();
+        self.hash_structure(&mut state);
+        state.finish()
+    }
+
+    /// Whether the two expressions are written the same, without putting them in canonical form.
+    fn eq_structure(&self, other: &Self) -> bool {
+        use Expression::*;
+        match (self, other) {
+            (Address(a), Address(b)) => a == b,
+            (
+                CustomFunctionCall {
+                    function: a_function,
+                    arguments: a,
+                },
+                CustomFunctionCall {
+                    function: b_function,
+                    arguments: b,
+                },
+            ) => {
+                a_function == b_function
+                    && a.len() == b.len()
+                    && a.iter().zip(b).all(|(a, b)| a.eq_structure(b))
+            }
+            (
+                FunctionCall {
+                    function: a_function,
+                    expression: a,
+                },
+                FunctionCall {
+                    function: b_function,
+                    expression: b,
+                },
+            ) => a_function == b_function && a.eq_structure(b),
+            (
+                Infix {
+                    left: a_left,
+                    operator: a_operator,
+                    right: a_right,
+                },
+                Infix {
+                    left: b_left,
+                    operator: b_operator,
+                    right: b_right,
+                },
+            ) => {
+                a_operator == b_operator
+                    && a_left.eq_structure(b_left)
+                    && a_right.eq_structure(b_right)
+            }
+            (Number(a), Number(b)) => number_bits(a) == number_bits(b),
+            (PiConstant, PiConstant) => true,
+            (
+                Prefix {
+                    operator: a_operator,
+                    expression: a,
+                },
+                Prefix {
+                    operator: b_operator,
+                    expression: b,
+                },
+            ) => a_operator == b_operator && a.eq_structure(b),
+            (Variable(a), Variable(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl Hash for Expression {
+    // Implemented by hand since we can't derive with f64s hidden inside, and so that expressions
+    // which are the same up to commutativity and associativity (`1 + 2 == 2 + 1`) hash the same.
+    // See https://github.com/rigetti/quil-rust/issues/27
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.clone().into_canonical().hash_structure(state)
+    }
 }
 
 impl PartialEq for Expression {
-    // Partial equality by hash value
+    // Equality of canonical forms, consistent with `Hash`.
     fn eq(&self, other: &Self) -> bool {
-        hash_to_u64(self) == hash_to_u64(other)
+        self.eq_structure(other)
+            || self
+                .clone()
+                .into_canonical()
+                .eq_structure(&other.clone().into_canonical())
     }
 }
 
 impl Eq for Expression {}
 
+/// A real number.
+impl From<f64> for Expression {
+    fn from(value: f64) -> Self {
+        Expression::Number(real!(value))
+    }
+}
+
 macro_rules! impl_expr_op {
     ($name:ident, $name_assign:ident, $function:ident, $function_assign:ident, $operator:ident) => {
         impl $name for Expression {
@@ -212,6 +329,31 @@ fn calculate_function(
     }
 }
 
+/// Push the canonical form of each operand of `expression`, a sum or product if `operator` is
+/// [`InfixOperator::Plus`] or [`InfixOperator::Star`], onto `operands`, looking through nested
+/// sums or products with the same operator.
+fn collect_operands(
+    expression: Expression,
+    operator: &InfixOperator,
+    operands: &mut Vec<Expression>,
+) {
+    match expression {
+        Expression::Infix {
+            left,
+            operator: inner,
+            right,
+        } if inner == *operator => {
+            collect_operands(*left, operator, operands);
+            collect_operands(*right, operator, operands);
+        }
+        Expression::Prefix {
+            operator: PrefixOperator::Plus,
+            expression,
+        } => collect_operands(*expression, operator, operands),
+        other => operands.push(other.into_canonical()),
+    }
+}
+
 /// Is this a small floating point number?
 #[inline(always)]
 fn is_small(x: f64) -> bool {
@@ -236,7 +378,16 @@ impl Expression {
     pub fn simplify(&mut self) {
         use Expression::*;
 
+        if self.to_exact_operation().is_some() {
+            let value = self.evaluate(&HashMap::new(), &HashMap::new());
+            *self = Number(value.expect("an expression with an exact value is constant"));
+            return;
+        }
+
         match self {
+            CustomFunctionCall { arguments, .. } => {
+                arguments.iter_mut().for_each(Expression::simplify);
+            }
             FunctionCall {
                 function,
                 expression,
@@ -278,6 +429,17 @@ impl Expression {
         }
     }
 
+    /// The exact value of this expression if it is an operation, rather than a single number,
+    /// which [`Expression::to_exact`] can compute the value of.
+    fn to_exact_operation(&self) -> Option<exact::ExactValue> {
+        match self {
+            Expression::FunctionCall { .. }
+            | Expression::Infix { .. }
+            | Expression::Prefix { .. } => self.to_exact(),
+            _ => None,
+        }
+    }
+
     /// Consume the expression, simplifying it as much as possible.
     ///
     /// # Example
@@ -296,8 +458,100 @@ impl Expression {
         self
     }
 
-    /// Evaluate an expression, expecting that it may be fully reduced to a single complex number.
-    /// If it cannot be reduced to a complex number, return an error.
+    /// Consume the expression, returning its canonical form, in which expressions which differ
+    /// only in the order and grouping of the operands of sums and products are written the same:
+    /// nested sums and products are flattened, their operands put in a fixed order, and then
+    /// grouped from the left. A prefix `+` is also removed. Expressions are equal, and hash the
+    /// same, exactly when their canonical forms are written the same.
+    ///
+    /// Unlike [`Expression::simplify`], the canonical form computes nothing, so `1 + 2` is not
+    /// equal to `3`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::expression::Expression;
+    /// use std::str::FromStr;
+    ///
+    /// let first = Expression::from_str("(pi/2) + (%theta + %phi)").unwrap();
+    /// let second = Expression::from_str("(%phi + %theta) + (pi/2)").unwrap();
+    ///
+    /// assert_eq!(first.clone().into_canonical().to_string(), second.clone().into_canonical().to_string());
+    /// assert_eq!(first, second);
+    /// ```
+    pub fn into_canonical(self) -> Self {
+        use Expression::*;
+
+        match self {
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => CustomFunctionCall {
+                function,
+                arguments: arguments
+                    .into_iter()
+                    .map(Expression::into_canonical)
+                    .collect(),
+            },
+            FunctionCall {
+                function,
+                expression,
+            } => FunctionCall {
+                function,
+                expression: Box::new(expression.into_canonical()),
+            },
+            Infix {
+                left,
+                operator: operator @ (InfixOperator::Plus | InfixOperator::Star),
+                right,
+            } => {
+                let mut operands = vec![];
+                collect_operands(*left, &operator, &mut operands);
+                collect_operands(*right, &operator, &mut operands);
+                operands.sort_by_cached_key(Expression::structure_hash);
+                let mut operands = operands.into_iter();
+                let first = operands.next().expect("a sum or product has operands");
+                operands.fold(first, |left, right| Infix {
+                    left: Box::new(left),
+                    operator: operator.clone(),
+                    right: Box::new(right),
+                })
+            }
+            Infix {
+                left,
+                operator,
+                right,
+            } => Infix {
+                left: Box::new(left.into_canonical()),
+                operator,
+                right: Box::new(right.into_canonical()),
+            },
+            Prefix {
+                operator: PrefixOperator::Plus,
+                expression,
+            } => expression.into_canonical(),
+            Prefix {
+                operator,
+                expression,
+            } => Prefix {
+                operator,
+                expression: Box::new(expression.into_canonical()),
+            },
+            other => other,
+        }
+    }
+
+    /// Write the expression as Quil, formatting its numbers with `format`.
+    pub fn to_string_with_format(&self, format: FloatFormat) -> String {
+        format.scope(|| self.to_string())
+    }
+
+    /// Evaluate an expression, expecting that it may be fully reduced to a single complex number,
+    /// using the values of `variables` and of memory given in `memory_references`. If it cannot
+    /// be, return an error naming the variable or memory it found no value for.
+    ///
+    /// Constant parts of the expression are computed exactly where [`Expression::to_exact`] can
+    /// compute them, so that `pi/2 - pi/2` is exactly zero, and in floating point otherwise.
     ///
     /// # Example
     ///
@@ -322,11 +576,26 @@ impl Expression {
     pub fn evaluate(
         &self,
         variables: &HashMap<String, num_complex::Complex64>,
-        memory_references: &HashMap<&str, Vec<f64>>,
+        memory_references: &MemoryValues,
     ) -> Result<num_complex::Complex64, EvaluationError> {
         use Expression::*;
 
-        match self {
+        let exact = self.to_exact_operation().map(|exact| exact.to_f64());
+        if let Some(value) = exact.filter(|value| *value != 0f64) {
+            return Ok(real!(value));
+        }
+
+        let value = match self {
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.evaluate(variables, memory_references))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(function.call(&arguments))
+            }
             FunctionCall {
                 function,
                 expression,
@@ -357,17 +626,32 @@ impl Expression {
             }
             Variable(identifier) => match variables.get(identifier.as_str()) {
                 Some(value) => Ok(*value),
-                None => Err(EvaluationError::Incomplete),
+                None => Err(EvaluationError::UnboundVariable(identifier.clone())),
             },
-            Address(memory_reference) => memory_references
-                .get(memory_reference.name.as_str())
-                .and_then(|values| {
-                    let value = values.get(memory_reference.index as usize)?;
-                    Some(real!(*value))
-                })
-                .ok_or(EvaluationError::Incomplete),
+            Address(memory_reference) => {
+                let values = memory_references
+                    .get(memory_reference.name.as_str())
+                    .ok_or_else(|| {
+                        EvaluationError::UnboundMemoryRegion(memory_reference.name.to_string())
+                    })?;
+                match values.get(memory_reference.index as usize) {
+                    Some(value) => Ok(real!(*value)),
+                    None => Err(EvaluationError::MemoryIndexOutOfRange {
+                        reference: memory_reference.clone(),
+                        length: values.len(),
+                    }),
+                }
+            }
             PiConstant => Ok(real!(PI)),
             Number(number) => Ok(*number),
+        }?;
+
+        // Exact arithmetic has no negative zero, so an exact zero takes its sign from the value
+        // computed in floating point, as `-0` should be `-0.0`.
+        match exact {
+            Some(_) if value.re == 0f64 => Ok(real!(value.re)),
+            Some(_) => Ok(real!(0f64)),
+            None => Ok(value),
         }
     }
 
@@ -395,6 +679,16 @@ impl Expression {
         use Expression::*;
 
         match self {
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => CustomFunctionCall {
+                function,
+                arguments: arguments
+                    .into_iter()
+                    .map(|argument| argument.substitute_variables(variable_values))
+                    .collect(),
+            },
             FunctionCall {
                 function,
                 expression,
@@ -442,24 +736,363 @@ impl Expression {
     }
 }
 
+/// An expression of the form `a·m[i] + b·n[j] + ... + c`: an affine function of memory values
+/// `m[i]`, `n[j]`, ... with complex coefficients and a complex constant term.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AffineExpression {
+    /// The coefficient of each memory reference. No coefficient is zero.
+    pub terms: HashMap<MemoryReference, num_complex::Complex64>,
+    /// The constant term.
+    pub constant: num_complex::Complex64,
+}
+
+impl AffineExpression {
+    fn constant(constant: num_complex::Complex64) -> Self {
+        Self {
+            terms: HashMap::new(),
+            constant,
+        }
+    }
+
+    /// Return the constant value of this expression, if it references no memory.
+    pub fn as_constant(&self) -> Option<num_complex::Complex64> {
+        self.terms.is_empty().then_some(self.constant)
+    }
+
+    fn scale(mut self, factor: num_complex::Complex64) -> Self {
+        self.terms
+            .values_mut()
+            .for_each(|coefficient| *coefficient *= factor);
+        self.terms
+            .retain(|_, coefficient| *coefficient != real!(0f64));
+        self.constant *= factor;
+        self
+    }
+
+    fn sum(mut self, other: Self) -> Self {
+        for (reference, coefficient) in other.terms {
+            *self.terms.entry(reference).or_default() += coefficient;
+        }
+        self.terms
+            .retain(|_, coefficient| *coefficient != real!(0f64));
+        self.constant += other.constant;
+        self
+    }
+}
+
+impl Expression {
+    /// Express this expression as an affine function of the memory values it references, as
+    /// required by backends which update gate parameters from memory at execution time.
+    ///
+    /// Return `None` if the expression is not affine in memory values, such as `theta[0]^2` or
+    /// `cos(theta[0])`, or if it contains variables.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::expression::Expression;
+    /// use quil_rs::instruction::MemoryReference;
+    /// use std::str::FromStr;
+    /// use num_complex::Complex64;
+    ///
+    /// let expression = Expression::from_str("(2 * (theta[0] + 1)) / 4").unwrap();
+    /// let affine = expression.to_affine().unwrap();
+    ///
+    /// let theta = MemoryReference { name: "theta".into(), index: 0 };
+    /// assert_eq!(affine.terms[&theta], Complex64::from(0.5));
+    /// assert_eq!(affine.constant, Complex64::from(0.5));
+    ///
+    /// assert!(Expression::from_str("cos(theta[0])").unwrap().to_affine().is_none());
+    /// ```
+    pub fn to_affine(&self) -> Option<AffineExpression> {
+        use Expression::*;
+
+        match self {
+            Address(memory_reference) => Some(AffineExpression {
+                terms: HashMap::from([(memory_reference.clone(), real!(1f64))]),
+                constant: real!(0f64),
+            }),
+            FunctionCall {
+                function,
+                expression,
+            } => {
+                let argument = expression.to_affine()?.as_constant()?;
+                Some(AffineExpression::constant(calculate_function(
+                    function, &argument,
+                )))
+            }
+            Infix {
+                left,
+                operator,
+                right,
+            } => {
+                let left = left.to_affine()?;
+                let right = right.to_affine()?;
+                match operator {
+                    InfixOperator::Plus => Some(left.sum(right)),
+                    InfixOperator::Minus => Some(left.sum(right.scale(real!(-1f64)))),
+                    InfixOperator::Star => match (left.as_constant(), right.as_constant()) {
+                        (Some(factor), _) => Some(right.scale(factor)),
+                        (None, Some(factor)) => Some(left.scale(factor)),
+                        (None, None) => None,
+                    },
+                    InfixOperator::Slash => match right.as_constant() {
+                        Some(divisor) if divisor != real!(0f64) => Some(left.scale(divisor.inv())),
+                        _ => None,
+                    },
+                    InfixOperator::Caret => {
+                        let base = left.as_constant()?;
+                        let exponent = right.as_constant()?;
+                        Some(AffineExpression::constant(calculate_infix(
+                            &base, operator, &exponent,
+                        )))
+                    }
+                }
+            }
+            Prefix {
+                operator,
+                expression,
+            } => {
+                let affine = expression.to_affine()?;
+                match operator {
+                    PrefixOperator::Plus => Some(affine),
+                    PrefixOperator::Minus => Some(affine.scale(real!(-1f64))),
+                }
+            }
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => {
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| argument.to_affine()?.as_constant())
+                    .collect::<Option<Vec<_>>>()?;
+                Some(AffineExpression::constant(function.call(&arguments)))
+            }
+            Variable(_) => None,
+            PiConstant => Some(AffineExpression::constant(real!(PI))),
+            Number(number) => Some(AffineExpression::constant(*number)),
+        }
+    }
+}
+
+impl Expression {
+    /// Parse an expression from all of `input`, along with the span of `input` it was parsed
+    /// from, which excludes any whitespace around it. Fails if `input` cannot be lexed, does not
+    /// start with an expression, or has anything but whitespace after the expression; the error
+    /// locates the problem within `input`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::expression::Expression;
+    /// use quil_rs::program::SyntaxError;
+    ///
+    /// let input = "  %theta / 2 ";
+    /// let (expression, span) = Expression::parse(input).unwrap();
+    /// assert_eq!(expression.to_string(), "(%theta/2)");
+    /// assert_eq!(&input[span.range()], "%theta / 2");
+    ///
+    /// match Expression::parse("pi 2") {
+    ///     Err(SyntaxError::Leftover(error)) => assert_eq!(error.column(), 4),
+    ///     other => panic!("expected leftover input, found {other:?}"),
+    /// }
+    /// ```
+    pub fn parse(input: &str) -> Result<(Self, SourceSpan), SyntaxError<Self>> {
+        let is_whitespace = |token: &TokenWithLocation| {
+            matches!(token.as_token(), Token::NewLine | Token::Indentation)
+        };
+        let tokens = lex(LocatedSpan::new(input))?;
+        let start = tokens
+            .iter()
+            .take_while(|token| is_whitespace(token))
+            .count();
+        let result =
+            parse_expression_with_span(&tokens[start..]).map_err(ParseError::from_nom_internal_err);
+        match result.finish() {
+            Ok((leftover, (expression, span))) if leftover.iter().all(is_whitespace) => {
+                Ok((expression, span))
+            }
+            Ok((leftover, (expression, _))) => {
+                Err(SyntaxError::from(LeftoverError::new(leftover, expression)))
+            }
+            Err(error) => Err(SyntaxError::from(error)),
+        }
+    }
+}
+
 impl FromStr for Expression {
     type Err = ProgramError<Self>;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let input = LocatedSpan::new(s);
-        let tokens = lex(input)?;
-        disallow_leftover(parse_expression(&tokens).map_err(ParseError::from_nom_internal_err))
+        let (expression, _) = Self::parse(s)?;
+        Ok(expression)
     }
 }
 
-/// Format a num_complex::Complex64 value in a way that omits the real or imaginary part when
-/// reasonable. That is:
+/// A policy for writing floating-point numbers as text, used wherever a number in an
+/// [`Expression`] is written out: the Quil text of instructions and programs, and LaTeX diagrams.
 ///
-/// - When imaginary is set but real is 0, show only imaginary
-/// - When imaginary is 0, show real only
-/// - When both are non-zero, show with the correct operator in between
-#[inline(always)]
-fn format_complex(value: &Complex64) -> String {
+/// Numbers are written in the [`FloatFormat::Shortest`] form unless another is selected for the
+/// duration of a call with [`FloatFormat::scope`], [`FormattingOptions::scope`], or a method such
+/// as [`Expression::to_string_with_format`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum FloatFormat {
+    /// The shortest decimal form which reads back as the same number, switching to scientific
+    /// notation for very large and very small magnitudes.
+    #[default]
+    Shortest,
+    /// Decimal notation with the given number of digits after the decimal point.
+    Fixed(usize),
+    /// Scientific notation with the given number of digits after the decimal point of the
+    /// mantissa, such as `1.50e-6`.
+    Scientific(usize),
+    /// The [`FloatFormat::Shortest`] form, except that negative zero is written as `-0`, so that
+    /// every real number reads back as itself: for any `f64` value `x`, the text of
+    /// `Expression::from(x)` parses as an expression which evaluates to exactly `x`, or to NaN if
+    /// `x` is NaN. Complex numbers with a negative zero part may not read back as themselves.
+    Lossless,
+}
+
+/// How numbers are written wherever an [`Expression`] is written out as text.
+///
+/// The options in effect on the current thread are the default ones unless others are selected
+/// for the duration of a call with [`FormattingOptions::scope`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct FormattingOptions {
+    /// How to write floating-point numbers, with what precision and notation.
+    pub float_format: FloatFormat,
+    /// Whether to write a real number which is a simple multiple of π, such as
+    /// `1.5707963267948966`, as that multiple, such as `(pi/2)`. As expressions with π are
+    /// evaluated exactly, the text still evaluates to the same number.
+    pub pi_multiples: bool,
+}
+
+thread_local! {
+    static FORMATTING_OPTIONS: std::cell::Cell<FormattingOptions> =
+        const {
+            std::cell::Cell::new(FormattingOptions {
+                float_format: FloatFormat::Shortest,
+                pi_multiples: false,
+            })
+        };
+}
+
+/// Restores the options in effect before a [`FormattingOptions::scope`] when dropped, even if the
+/// scoped closure panics.
+struct FormattingOptionsGuard(FormattingOptions);
+
+impl Drop for FormattingOptionsGuard {
+    fn drop(&mut self) {
+        FORMATTING_OPTIONS.with(|options| options.set(self.0));
+    }
+}
+
+impl FormattingOptions {
+    /// Call `f`, writing all numbers formatted on the current thread during the call with these
+    /// options.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::expression::{FloatFormat, FormattingOptions};
+    /// use quil_rs::Program;
+    /// use std::str::FromStr;
+    ///
+    /// let program = Program::from_str("RX(1.5707963267948966) 0\nRZ(0.25) 0").unwrap();
+    /// let options = FormattingOptions {
+    ///     float_format: FloatFormat::Fixed(2),
+    ///     pi_multiples: true,
+    /// };
+    /// let quil = options.scope(|| program.to_string(true));
+    ///
+    /// assert_eq!(quil, "RX((pi/2)) 0\nRZ(0.25) 0\n");
+    /// ```
+    pub fn scope<T>(self, f: impl FnOnce() -> T) -> T {
+        let _guard =
+            FormattingOptionsGuard(FORMATTING_OPTIONS.with(|options| options.replace(self)));
+        f()
+    }
+
+    /// The options in effect on the current thread.
+    pub fn current() -> Self {
+        FORMATTING_OPTIONS.with(|options| options.get())
+    }
+}
+
+impl FloatFormat {
+    /// Call `f`, writing all numbers formatted on the current thread during the call with this
+    /// format, and otherwise with the [`FormattingOptions`] already in effect.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::{expression::FloatFormat, Program};
+    /// use std::str::FromStr;
+    ///
+    /// let program = Program::from_str("RX(0.5) 0").unwrap();
+    /// let quil = FloatFormat::Fixed(3).scope(|| program.to_string(true));
+    ///
+    /// assert_eq!(quil, "RX(0.500) 0\n");
+    /// ```
+    pub fn scope<T>(self, f: impl FnOnce() -> T) -> T {
+        FormattingOptions {
+            float_format: self,
+            ..FormattingOptions::current()
+        }
+        .scope(f)
+    }
+
+    /// The format in effect on the current thread.
+    pub fn current() -> Self {
+        FormattingOptions::current().float_format
+    }
+
+    /// Write `value` in this format.
+    pub fn format(self, value: f64) -> String {
+        match self {
+            FloatFormat::Shortest => format_shortest(value),
+            FloatFormat::Fixed(precision) => format!("{value:.precision$}"),
+            FloatFormat::Scientific(precision) => format!("{value:.precision$e}"),
+            FloatFormat::Lossless if value == 0f64 && value.is_sign_negative() => "-0".to_owned(),
+            FloatFormat::Lossless => format_shortest(value),
+        }
+    }
+}
+
+/// The largest denominator of the multiples of π which [`FormattingOptions::pi_multiples`] writes
+/// numbers as.
+const MAX_PI_DENOMINATOR: i64 = 16;
+
+/// Write `value` as a multiple of π, such as `(3*pi/4)`, if it is exactly the floating-point
+/// value of one of at most four times π with a denominator up to [`MAX_PI_DENOMINATOR`].
+fn format_pi_multiple(value: f64) -> Option<String> {
+    if value == 0f64 || !value.is_finite() || value.abs() > 4.0 * PI {
+        return None;
+    }
+    (1..=MAX_PI_DENOMINATOR).find_map(|denominator| {
+        let numerator = (value / PI * denominator as f64).round() as i64;
+        let multiple = exact::ExactValue {
+            rational: exact::Rational::ZERO,
+            pi: exact::Rational::new(numerator, denominator)?,
+        };
+        // Only the smallest denominator is in lowest terms, and so is found first.
+        if multiple.to_f64() != value {
+            return None;
+        }
+        Some(match (numerator, denominator) {
+            (1, 1) => "pi".to_owned(),
+            (-1, 1) => "(-pi)".to_owned(),
+            (numerator, 1) => format!("({numerator}*pi)"),
+            (1, denominator) => format!("(pi/{denominator})"),
+            (-1, denominator) => format!("(-pi/{denominator})"),
+            (numerator, denominator) => format!("({numerator}*pi/{denominator})"),
+        })
+    })
+}
+
+/// Write `value` in the shortest form which reads back as the same number.
+fn format_shortest(value: f64) -> String {
     const FORMAT: u128 = format::STANDARD;
     // Safety:
     // This uses `build_unchecked`, which is safe as long as `is_valid` is true, and
@@ -484,18 +1117,39 @@ fn format_complex(value: &Complex64) -> String {
         assert!(options.is_valid());
         options
     };
+    to_string_with_options::<_, FORMAT>(value, &OPTIONS)
+}
+
+/// Format a num_complex::Complex64 value in a way that omits the real or imaginary part when
+/// reasonable, using the [`FormattingOptions`] in effect. That is:
+///
+/// - When imaginary is set but real is 0, show only imaginary
+/// - When imaginary is 0, show real only
+/// - When both are non-zero, show with the correct operator in between
+#[inline(always)]
+fn format_complex(value: &Complex64) -> String {
+    let options = FormattingOptions::current();
+    let format = options.float_format;
     if value.re == 0f64 && value.im == 0f64 {
-        "0".to_owned()
+        match format {
+            FloatFormat::Shortest => "0".to_owned(),
+            FloatFormat::Lossless => format.format(value.re),
+            _ => format.format(0f64),
+        }
     } else if value.im == 0f64 {
-        to_string_with_options::<_, FORMAT>(value.re, &OPTIONS)
+        options
+            .pi_multiples
+            .then(|| format_pi_multiple(value.re))
+            .flatten()
+            .unwrap_or_else(|| format.format(value.re))
     } else if value.re == 0f64 {
-        to_string_with_options::<_, FORMAT>(value.im, &OPTIONS) + "i"
+        format.format(value.im) + "i"
     } else {
-        let mut out = to_string_with_options::<_, FORMAT>(value.re, &OPTIONS);
+        let mut out = format.format(value.re);
         if value.im > 0f64 {
             out.push('+')
         }
-        out.push_str(&to_string_with_options::<_, FORMAT>(value.im, &OPTIONS));
+        out.push_str(&format.format(value.im));
         out.push('i');
         out
     }
@@ -506,6 +1160,13 @@ impl fmt::Display for Expression {
         use Expression::*;
         match self {
             Address(memory_reference) => write!(f, "{}", memory_reference),
+            CustomFunctionCall {
+                function,
+                arguments,
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                write!(f, "{}({})", function, arguments.join(", "))
+            }
             FunctionCall {
                 function,
                 expression,
@@ -528,6 +1189,8 @@ impl fmt::Display for Expression {
 
 /// A function defined within Quil syntax.
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub enum ExpressionFunction {
     Cis,
@@ -555,6 +1218,8 @@ impl fmt::Display for ExpressionFunction {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub enum PrefixOperator {
     Plus,
@@ -576,6 +1241,8 @@ impl fmt::Display for PrefixOperator {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub enum InfixOperator {
     Caret,
@@ -616,6 +1283,106 @@ mod tests {
 
     use super::*;
 
+    #[rstest::rstest]
+    #[case(FloatFormat::Shortest, "(1.5*1e-7)", "2.5-1i")]
+    #[case(FloatFormat::Fixed(2), "(1.50*0.00)", "2.50-1.00i")]
+    #[case(FloatFormat::Scientific(1), "(1.5e0*1.0e-7)", "2.5e0-1.0e0i")]
+    #[case(FloatFormat::Lossless, "(1.5*1e-7)", "2.5-1i")]
+    fn to_string_with_format(
+        #[case] format: FloatFormat,
+        #[case] expected_product: &str,
+        #[case] expected_complex: &str,
+    ) {
+        let product = Expression::Infix {
+            left: Box::new(Expression::Number(real!(1.5))),
+            operator: InfixOperator::Star,
+            right: Box::new(Expression::Number(real!(1e-7))),
+        };
+        assert_eq!(product.to_string_with_format(format), expected_product);
+        let complex = Expression::Number(Complex64::new(2.5, -1.0));
+        assert_eq!(complex.to_string_with_format(format), expected_complex);
+        assert_eq!(FloatFormat::current(), FloatFormat::Shortest);
+
+        // Every format reads back as an expression.
+        let reparsed = Expression::from_str(expected_product).unwrap();
+        assert_eq!(reparsed.to_string_with_format(format), expected_product);
+    }
+
+    #[rstest::rstest]
+    #[case(0.0)]
+    #[case(-0.0)]
+    #[case(-1.5)]
+    #[case(0.1)]
+    #[case(5e-324)]
+    #[case(f64::MAX)]
+    #[case(f64::MIN)]
+    #[case(f64::INFINITY)]
+    #[case(f64::NEG_INFINITY)]
+    #[case(f64::NAN)]
+    fn lossless_round_trip(#[case] value: f64) {
+        let text = Expression::Number(real!(value)).to_string_with_format(FloatFormat::Lossless);
+        let reparsed = Expression::from_str(&text).unwrap();
+        let evaluated = reparsed.evaluate(&HashMap::new(), &HashMap::new()).unwrap();
+        if value.is_nan() {
+            assert!(evaluated.re.is_nan(), "{text} evaluates to {evaluated}");
+        } else {
+            assert_eq!(
+                evaluated.re.to_bits(),
+                value.to_bits(),
+                "{text} evaluates to {evaluated}"
+            );
+        }
+    }
+
+    #[rstest::rstest]
+    #[case(PI, "pi")]
+    #[case(-PI, "(-pi)")]
+    #[case(PI / 2.0, "(pi/2)")]
+    #[case(-PI / 4.0, "(-pi/4)")]
+    #[case(3.0 * PI / 4.0, "(3*pi/4)")]
+    #[case(2.0 * PI, "(2*pi)")]
+    #[case(0.0, "0")]
+    #[case(1.5, "1.5")]
+    #[case(PI / 17.0, "0.18479956785822313")]
+    fn pi_multiples(#[case] value: f64, #[case] expected: &str) {
+        let options = FormattingOptions {
+            pi_multiples: true,
+            ..Default::default()
+        };
+        let text = options.scope(|| Expression::Number(real!(value)).to_string());
+        assert_eq!(text, expected);
+        assert_eq!(FormattingOptions::current(), FormattingOptions::default());
+
+        // What is written evaluates to the same number.
+        let reparsed = Expression::from_str(&text).unwrap();
+        assert_eq!(
+            reparsed.evaluate(&HashMap::new(), &HashMap::new()),
+            Ok(real!(value))
+        );
+    }
+
+    #[test]
+    fn formatting_options_scope() {
+        let options = FormattingOptions {
+            float_format: FloatFormat::Fixed(1),
+            pi_multiples: true,
+        };
+        options.scope(|| {
+            // Selecting a float format keeps the other options in effect.
+            FloatFormat::Scientific(2).scope(|| {
+                assert_eq!(
+                    FormattingOptions::current(),
+                    FormattingOptions {
+                        float_format: FloatFormat::Scientific(2),
+                        pi_multiples: true,
+                    }
+                );
+            });
+            assert_eq!(FormattingOptions::current(), options);
+        });
+        assert_eq!(FormattingOptions::current(), FormattingOptions::default());
+    }
+
     #[test]
     fn simplify_and_evaluate() {
         use Expression::*;
@@ -898,6 +1665,91 @@ mod tests {
 
     }
 
+    #[rstest::rstest]
+    #[case("%theta * 2", Ok(real!(3.0)))]
+    #[case("cos(pi) + ro[1]", Ok(real!(-0.5)))]
+    #[case("%phi + 1", Err(EvaluationError::UnboundVariable("phi".to_string())))]
+    #[case("2 * gamma[0]", Err(EvaluationError::UnboundMemoryRegion("gamma".to_string())))]
+    #[case(
+        "ro[2]",
+        Err(EvaluationError::MemoryIndexOutOfRange {
+            reference: MemoryReference { name: "ro".into(), index: 2 },
+            length: 2,
+        })
+    )]
+    fn evaluate_with_bindings(
+        #[case] input: &str,
+        #[case] expected: Result<Complex64, EvaluationError>,
+    ) {
+        let variables = HashMap::from([("theta".to_string(), real!(1.5))]);
+        let memory_values = MemoryValues::from([("ro", vec![0.0, 0.5])]);
+        let expression = Expression::from_str(input).unwrap();
+        assert_eq!(expression.evaluate(&variables, &memory_values), expected);
+    }
+
+    #[rstest::rstest]
+    #[case("(pi/2) + %theta", "%theta + (pi/2)", true)]
+    #[case("(%a + %b) + %c", "%a + (%c + %b)", true)]
+    #[case("2 * (%a * cos(%b + 1))", "(cos(1 + %b) * %a) * 2", true)]
+    #[case("-(%a + %b)", "-(%b + %a)", true)]
+    #[case("(%a + %b) * %c", "%c * (%b + %a)", true)]
+    #[case("%a - %b", "%b - %a", false)]
+    #[case("%a / %b", "%b / %a", false)]
+    #[case("(%a + %b) * %c", "(%a * %c) + (%b * %c)", false)]
+    #[case("1 + 2", "3", false)]
+    #[case("1", "1i", false)]
+    fn canonical_equality(#[case] left: &str, #[case] right: &str, #[case] equal: bool) {
+        let left = Expression::from_str(left).unwrap();
+        let right = Expression::from_str(right).unwrap();
+        assert_eq!(left == right, equal);
+        assert_eq!(
+            left.clone().into_canonical().to_string() == right.clone().into_canonical().to_string(),
+            equal
+        );
+        assert_eq!(HashSet::from([left]).contains(&right), equal);
+    }
+
+    #[rstest::rstest]
+    #[case("pi", "pi", (1, 1), (1, 3))]
+    #[case("  (%a + 1) * ro[2]\t", "(%a + 1) * ro[2]", (1, 3), (1, 19))]
+    #[case("cos(%theta)  ", "cos(%theta)", (1, 1), (1, 12))]
+    #[case("\n\t-%x\n", "-%x", (2, 2), (2, 5))]
+    fn parse_with_span(
+        #[case] input: &str,
+        #[case] text: &str,
+        #[case] start: (u32, usize),
+        #[case] end: (u32, usize),
+    ) {
+        let (expression, span) = Expression::parse(input).unwrap();
+        assert_eq!(expression, Expression::from_str(text).unwrap());
+        assert_eq!(&input[span.range()], text);
+        assert_eq!((span.start.line, span.start.column), start);
+        assert_eq!((span.end.line, span.end.column), end);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(matches!(
+            Expression::parse(""),
+            Err(SyntaxError::ParseError(_))
+        ));
+        assert!(matches!(
+            Expression::parse("1 +"),
+            Err(SyntaxError::ParseError(_))
+        ));
+        assert!(matches!(
+            Expression::parse("1 $"),
+            Err(SyntaxError::LexError(_))
+        ));
+        match Expression::parse("%a * 2 )") {
+            Err(SyntaxError::Leftover(error)) => {
+                assert_eq!((error.line(), error.column()), (1, 8));
+                assert_eq!(error.recover().to_string(), "(%a*2)");
+            }
+            other => panic!("expected leftover input, found {other:?}"),
+        }
+    }
+
     #[test]
     fn specific_to_real_tests() {
         for (input, expected) in vec![
@@ -935,4 +1787,29 @@ mod tests {
             assert_eq!(format_complex(x), *s);
         }
     }
+
+    #[test]
+    fn to_affine() {
+        for (input, affine) in [
+            ("1 + 2", true),
+            ("-theta[0]", true),
+            ("(theta[0] * 3) - theta[0]", true),
+            ("(theta[0] / 2) + sqrt(4)", true),
+            ("theta[0] * theta[1]", false),
+            ("1 / theta[0]", false),
+            ("theta[0] / 0", false),
+            ("exp(theta[0])", false),
+            ("%alpha", false),
+        ] {
+            let expression = Expression::from_str(input).unwrap();
+            assert_eq!(expression.to_affine().is_some(), affine, "{input}");
+        }
+
+        // Terms which cancel out are dropped.
+        let expression = Expression::from_str("(theta[0] + 1) - theta[0]").unwrap();
+        assert_eq!(
+            expression.to_affine().unwrap().as_constant(),
+            Some(real!(1.0))
+        );
+    }
 }
@@ -0,0 +1,487 @@
+//! Exact arithmetic on the values of constant expressions which are rational numbers plus rational
+//! multiples of π, so that values such as `pi/2 - pi/2` or `sin(pi)` evaluate to exactly zero
+//! rather than to the small residue floating-point arithmetic leaves.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+use super::{Expression, ExpressionFunction, InfixOperator, PrefixOperator};
+
+/// The largest power an exact value is raised to; larger powers are computed in floating point.
+const MAX_EXPONENT: i64 = 64;
+
+/// A rational number in lowest terms, with a positive denominator. Arithmetic on rationals
+/// returns `None` where the result would not fit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Rational {
+    numerator: i64,
+    denominator: i64,
+}
+
+fn gcd(mut a: i128, mut b: i128) -> i128 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a.abs()
+}
+
+impl Rational {
+    pub const ZERO: Self = Self {
+        numerator: 0,
+        denominator: 1,
+    };
+    pub const ONE: Self = Self {
+        numerator: 1,
+        denominator: 1,
+    };
+    pub const MINUS_ONE: Self = Self {
+        numerator: -1,
+        denominator: 1,
+    };
+
+    /// The rational `numerator / denominator` in lowest terms, if `denominator` is not zero and
+    /// that fits.
+    pub fn new(numerator: i64, denominator: i64) -> Option<Self> {
+        Self::reduce(numerator.into(), denominator.into())
+    }
+
+    fn reduce(numerator: i128, denominator: i128) -> Option<Self> {
+        if denominator == 0 {
+            return None;
+        }
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Some(Self {
+            numerator: (numerator / divisor).try_into().ok()?,
+            denominator: (denominator / divisor).try_into().ok()?,
+        })
+    }
+
+    pub fn numerator(&self) -> i64 {
+        self.numerator
+    }
+
+    pub fn denominator(&self) -> i64 {
+        self.denominator
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    /// The integer this is, if it is one.
+    pub fn to_integer(&self) -> Option<i64> {
+        (self.denominator == 1).then_some(self.numerator)
+    }
+
+    /// The floating-point number nearest this, so that a number converted by
+    /// [`Rational::from_f64`] converts back to itself.
+    pub fn to_f64(&self) -> f64 {
+        const EXACT_LIMIT: i64 = 1 << f64::MANTISSA_DIGITS;
+        if self.numerator.abs() <= EXACT_LIMIT && self.denominator <= EXACT_LIMIT {
+            // Both are exact, and division rounds correctly.
+            return self.numerator as f64 / self.denominator as f64;
+        }
+        // Otherwise, write out enough decimal digits to round correctly when read.
+        let (numerator, denominator) = (i128::from(self.numerator), i128::from(self.denominator));
+        let mut written = format!("{}.", numerator / denominator);
+        if numerator < 0 && numerator / denominator == 0 {
+            written.insert(0, '-');
+        }
+        let mut remainder = (numerator % denominator).abs();
+        for _ in 0..40 {
+            remainder *= 10;
+            written.push(char::from(b'0' + (remainder / denominator) as u8));
+            remainder %= denominator;
+        }
+        written.parse().expect("decimal digits are a valid number")
+    }
+
+    /// The rational with the decimal expansion `value` is written with as Quil, if it fits.
+    pub fn from_f64(value: f64) -> Option<Self> {
+        if !value.is_finite() {
+            return None;
+        }
+        // The shortest form which reads back as `value`, such as `1.5e-1`.
+        let written = format!("{value:e}");
+        let (mantissa, exponent) = written.split_once('e')?;
+        let exponent: i32 = exponent.parse().ok()?;
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let digits: i128 = format!("{whole}{fraction}").parse().ok()?;
+        let scale = exponent - fraction.len() as i32;
+        let power = 10i128.checked_pow(scale.unsigned_abs())?;
+        if scale >= 0 {
+            Self::reduce(digits.checked_mul(power)?, 1)
+        } else {
+            Self::reduce(digits, power)
+        }
+    }
+
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let (a, b, c, d) = self.widened(other);
+        Self::reduce(a.checked_mul(d)?.checked_add(c.checked_mul(b)?)?, b * d)
+    }
+
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        let (a, b, c, d) = self.widened(other);
+        Self::reduce(a.checked_mul(d)?.checked_sub(c.checked_mul(b)?)?, b * d)
+    }
+
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let (a, b, c, d) = self.widened(other);
+        Self::reduce(a * c, b * d)
+    }
+
+    pub fn checked_div(self, other: Self) -> Option<Self> {
+        let (a, b, c, d) = self.widened(other);
+        Self::reduce(a * d, b * c)
+    }
+
+    /// This rational raised to the power `exponent`, where `0` is not raised to powers below one.
+    pub fn checked_pow(self, exponent: i64) -> Option<Self> {
+        if exponent.abs() > MAX_EXPONENT || (self.is_zero() && exponent <= 0) {
+            return None;
+        }
+        let mut result = Self::ONE;
+        for _ in 0..exponent.abs() {
+            result = result.checked_mul(self)?;
+        }
+        if exponent < 0 {
+            Self::ONE.checked_div(result)
+        } else {
+            Some(result)
+        }
+    }
+
+    /// The rational square root of this, if it has one.
+    pub fn sqrt(self) -> Option<Self> {
+        let root = |value: i64| {
+            let root = (value as f64).sqrt().round() as i64;
+            (root.checked_mul(root) == Some(value)).then_some(root)
+        };
+        if self.numerator < 0 {
+            return None;
+        }
+        Self::new(root(self.numerator)?, root(self.denominator)?)
+    }
+
+    fn widened(self, other: Self) -> (i128, i128, i128, i128) {
+        (
+            self.numerator.into(),
+            self.denominator.into(),
+            other.numerator.into(),
+            other.denominator.into(),
+        )
+    }
+}
+
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.denominator == 1 {
+            write!(f, "{}", self.numerator)
+        } else {
+            write!(f, "{}/{}", self.numerator, self.denominator)
+        }
+    }
+}
+
+/// An exact real value `rational + pi·π`, as computed for a constant expression by
+/// [`Expression::to_exact`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ExactValue {
+    pub rational: Rational,
+    /// The rational multiple of π in the value.
+    pub pi: Rational,
+}
+
+impl ExactValue {
+    fn rational(rational: Rational) -> Self {
+        Self {
+            rational,
+            pi: Rational::ZERO,
+        }
+    }
+
+    /// The rational this is, if there is no multiple of π in it.
+    pub fn to_rational(&self) -> Option<Rational> {
+        self.pi.is_zero().then_some(self.rational)
+    }
+
+    /// The value as a floating-point number, which is exactly zero if this is.
+    pub fn to_f64(&self) -> f64 {
+        self.rational.to_f64() + self.pi.to_f64() * PI
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        Some(Self {
+            rational: self.rational.checked_add(other.rational)?,
+            pi: self.pi.checked_add(other.pi)?,
+        })
+    }
+
+    fn scale(self, factor: Rational) -> Option<Self> {
+        Some(Self {
+            rational: self.rational.checked_mul(factor)?,
+            pi: self.pi.checked_mul(factor)?,
+        })
+    }
+
+    fn checked_mul(self, other: Self) -> Option<Self> {
+        match (self.to_rational(), other.to_rational()) {
+            (Some(factor), _) => other.scale(factor),
+            (None, Some(factor)) => self.scale(factor),
+            // A multiple of π² is not exact.
+            (None, None) => None,
+        }
+    }
+
+    fn checked_div(self, other: Self) -> Option<Self> {
+        match other.to_rational() {
+            Some(divisor) if !divisor.is_zero() => self.scale(Rational::ONE.checked_div(divisor)?),
+            Some(_) => None,
+            None if self.rational.is_zero() && other.rational.is_zero() => {
+                Some(Self::rational(self.pi.checked_div(other.pi)?))
+            }
+            None => None,
+        }
+    }
+
+    fn checked_pow(self, exponent: Self) -> Option<Self> {
+        let base = self.to_rational()?;
+        let exponent = exponent.to_rational()?.to_integer()?;
+        Some(Self::rational(base.checked_pow(exponent)?))
+    }
+
+    /// The sine of this, if it is a multiple of π/6 at which the sine is rational.
+    fn sin(self) -> Option<Self> {
+        if !self.rational.is_zero() {
+            return None;
+        }
+        let sixths = self.pi.checked_mul(Rational::new(6, 1)?)?.to_integer()?;
+        let (numerator, denominator) = match sixths.rem_euclid(12) {
+            0 | 6 => (0, 1),
+            1 | 5 => (1, 2),
+            3 => (1, 1),
+            7 | 11 => (-1, 2),
+            9 => (-1, 1),
+            // The sine is ±√3/2.
+            _ => return None,
+        };
+        Some(Self::rational(Rational::new(numerator, denominator)?))
+    }
+
+    /// The cosine of this, if it is a multiple of π/6 at which the cosine is rational.
+    fn cos(self) -> Option<Self> {
+        self.checked_add(Self {
+            rational: Rational::ZERO,
+            pi: Rational::new(1, 2)?,
+        })?
+        .sin()
+    }
+
+    fn apply(self, function: &ExpressionFunction) -> Option<Self> {
+        use ExpressionFunction::*;
+        match function {
+            Sine => self.sin(),
+            Cosine => self.cos(),
+            // Only real values are exact, which `cis` gives where the sine is zero.
+            Cis => {
+                self.sin()
+                    .filter(|sine| *sine == Self::rational(Rational::ZERO))?;
+                self.cos()
+            }
+            Exponent => {
+                (self == Self::rational(Rational::ZERO)).then_some(Self::rational(Rational::ONE))
+            }
+            SquareRoot => Some(Self::rational(self.to_rational()?.sqrt()?)),
+        }
+    }
+}
+
+impl fmt::Display for ExactValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.rational.is_zero(), self.pi.is_zero()) {
+            (_, true) => write!(f, "{}", self.rational),
+            (true, false) => write!(f, "{}*pi", self.pi),
+            (false, false) => write!(f, "{}+{}*pi", self.rational, self.pi),
+        }
+    }
+}
+
+impl Expression {
+    /// The exact value of this expression, if it is a constant which is a rational number plus
+    /// a rational multiple of π, and which can be computed exactly: numbers are taken to be the
+    /// decimals they are written as, and only values of functions which are themselves rational,
+    /// such as `sin(pi/6)` or `sqrt(9/4)`, are exact.
+    ///
+    /// [`Expression::evaluate`] and [`Expression::simplify`] compute the values of expressions
+    /// exactly where they can, and in floating point otherwise.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::expression::Expression;
+    /// use std::str::FromStr;
+    ///
+    /// let expression = Expression::from_str("(pi/2) - (pi/2)").unwrap();
+    /// assert_eq!(expression.to_exact().unwrap().to_f64(), 0.0);
+    ///
+    /// let expression = Expression::from_str("(3*pi/4) + 0.5").unwrap();
+    /// assert_eq!(expression.to_exact().unwrap().to_string(), "1/2+3/4*pi");
+    ///
+    /// assert!(Expression::from_str("pi*pi").unwrap().to_exact().is_none());
+    /// ```
+    pub fn to_exact(&self) -> Option<ExactValue> {
+        use Expression::*;
+        match self {
+            Number(number) if number.im == 0f64 => {
+                Some(ExactValue::rational(Rational::from_f64(number.re)?))
+            }
+            PiConstant => Some(ExactValue {
+                rational: Rational::ZERO,
+                pi: Rational::ONE,
+            }),
+            FunctionCall {
+                function,
+                expression,
+            } => expression.to_exact()?.apply(function),
+            Infix {
+                left,
+                operator,
+                right,
+            } => {
+                let left = left.to_exact()?;
+                let right = right.to_exact()?;
+                match operator {
+                    InfixOperator::Plus => left.checked_add(right),
+                    InfixOperator::Minus => left.checked_add(right.scale(Rational::MINUS_ONE)?),
+                    InfixOperator::Star => left.checked_mul(right),
+                    InfixOperator::Slash => left.checked_div(right),
+                    InfixOperator::Caret => left.checked_pow(right),
+                }
+            }
+            Prefix {
+                operator,
+                expression,
+            } => {
+                let value = expression.to_exact()?;
+                match operator {
+                    PrefixOperator::Plus => Some(value),
+                    PrefixOperator::Minus => value.scale(Rational::MINUS_ONE),
+                }
+            }
+            Number(_) | Address(_) | Variable(_) | CustomFunctionCall { .. } => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{ExactValue, Rational};
+    use crate::expression::Expression;
+    use crate::real;
+
+    fn exact(rational: (i64, i64), pi: (i64, i64)) -> ExactValue {
+        ExactValue {
+            rational: Rational::new(rational.0, rational.1).unwrap(),
+            pi: Rational::new(pi.0, pi.1).unwrap(),
+        }
+    }
+
+    #[rstest]
+    #[case("(pi/2) - (pi/2)", Some(exact((0, 1), (0, 1))))]
+    #[case("0.1 + 0.2", Some(exact((3, 10), (0, 1))))]
+    #[case("((2*pi)/3) + 1.5", Some(exact((3, 2), (2, 3))))]
+    #[case("-pi/4", Some(exact((0, 1), (-1, 4))))]
+    #[case("(pi/2)/pi", Some(exact((1, 2), (0, 1))))]
+    #[case("sin(pi)", Some(exact((0, 1), (0, 1))))]
+    #[case("sin(-pi/6)", Some(exact((-1, 2), (0, 1))))]
+    #[case("cos(5*pi/3)", Some(exact((1, 2), (0, 1))))]
+    #[case("cis(3*pi)", Some(exact((-1, 1), (0, 1))))]
+    #[case("sqrt(2.25)", Some(exact((3, 2), (0, 1))))]
+    #[case("exp(pi - pi)", Some(exact((1, 1), (0, 1))))]
+    #[case("sin(pi/3)", None)]
+    #[case("cis(pi/2)", None)]
+    #[case("sqrt(2)", None)]
+    #[case("pi*pi", None)]
+    #[case("1/(pi - pi)", None)]
+    #[case("1i", None)]
+    #[case("%theta + pi", None)]
+    #[case("ro[0]", None)]
+    #[case("1e300 * 1e300", None)]
+    fn to_exact(#[case] input: &str, #[case] expected: Option<ExactValue>) {
+        let expression = Expression::from_str(input).unwrap();
+        assert_eq!(expression.to_exact(), expected);
+    }
+
+    #[test]
+    fn evaluate_and_simplify_exactly() {
+        let expression = Expression::from_str("(pi/2) - (pi/2)").unwrap();
+        let value = expression.evaluate(&HashMap::new(), &HashMap::new());
+        assert_eq!(value, Ok(real!(0.0)));
+
+        let variables = HashMap::from([("theta".to_string(), real!(2.0))]);
+        let expression = Expression::from_str("%theta * sin(pi)").unwrap();
+        assert_eq!(
+            expression.evaluate(&variables, &HashMap::new()),
+            Ok(real!(0.0))
+        );
+
+        let simplified = Expression::from_str("%theta + cos(pi/2)")
+            .unwrap()
+            .into_simplified();
+        assert_eq!(
+            simplified,
+            Expression::Variable("theta".to_string()) + Expression::from(0.0)
+        );
+    }
+
+    #[test]
+    fn to_exact_power() {
+        let power = |base: f64, exponent: f64| {
+            (Expression::from(base) ^ Expression::from(exponent)).to_exact()
+        };
+        assert_eq!(power(0.5, -2.0), Some(exact((4, 1), (0, 1))));
+        assert_eq!(power(0.0, -1.0), None);
+        assert_eq!(power(2.0, 0.5), None);
+        assert_eq!(power(2.0, 100.0), None);
+        let pi_squared = Expression::PiConstant ^ Expression::from(2.0);
+        assert_eq!(pi_squared.to_exact(), None);
+    }
+
+    #[rstest]
+    #[case(0.0, Some((0, 1)))]
+    #[case(-2.5, Some((-5, 2)))]
+    #[case(0.1, Some((1, 10)))]
+    #[case(std::f64::consts::FRAC_PI_2, Some((7853981633974483, 5000000000000000)))]
+    #[case(1e18, Some((1000000000000000000, 1)))]
+    #[case(-54762097508.719315, Some((-10952419501743863, 200000)))]
+    #[case(1e19, None)]
+    #[case(f64::NAN, None)]
+    fn rational_from_f64(#[case] value: f64, #[case] expected: Option<(i64, i64)>) {
+        let expected =
+            expected.map(|(numerator, denominator)| Rational::new(numerator, denominator).unwrap());
+        assert_eq!(Rational::from_f64(value), expected);
+        if let Some(rational) = expected {
+            assert_eq!(rational.to_f64(), value);
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Functions which programs may call in expressions beyond those Quil defines, registered in a
+//! [`FunctionRegistry`] by the embedding application.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::panic::RefUnwindSafe;
+use std::sync::Arc;
+
+use num_complex::Complex64;
+
+/// The names which cannot be registered, as they already mean something in expressions.
+const RESERVED_NAMES: [&str; 7] = ["cis", "cos", "exp", "i", "pi", "sin", "sqrt"];
+
+/// Functions are shareable across threads, and unwind safe, so that the expressions, instructions
+/// and programs which hold them are as well.
+type Callback = dyn Fn(&[Complex64]) -> Complex64 + Send + Sync + RefUnwindSafe;
+
+struct Definition {
+    name: String,
+    arity: usize,
+    callback: Box<Callback>,
+}
+
+/// A function registered in a [`FunctionRegistry`], which an
+/// [`Expression::CustomFunctionCall`](super::Expression::CustomFunctionCall) calls. Functions are
+/// equal, and hash the same, when they have the same name and arity.
+#[derive(Clone)]
+pub struct CustomFunction(Arc<Definition>);
+
+impl CustomFunction {
+    pub fn name(&self) -> &str {
+        &self.0.name
+    }
+
+    /// The number of arguments the function takes.
+    pub fn arity(&self) -> usize {
+        self.0.arity
+    }
+
+    /// Compute the value of the function for `arguments`, of which there are [`Self::arity`].
+    pub fn call(&self, arguments: &[Complex64]) -> Complex64 {
+        (self.0.callback)(arguments)
+    }
+}
+
+impl fmt::Debug for CustomFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomFunction")
+            .field("name", &self.0.name)
+            .field("arity", &self.0.arity)
+            .finish_non_exhaustive()
+    }
+}
+
+impl fmt::Display for CustomFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.name)
+    }
+}
+
+impl PartialEq for CustomFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.name == other.0.name && self.0.arity == other.0.arity
+    }
+}
+
+impl Eq for CustomFunction {}
+
+impl Hash for CustomFunction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.name.hash(state);
+        self.0.arity.hash(state);
+    }
+}
+
+/// A function is serialized as its name, and deserialized as the function of that name in the
+/// [`FunctionRegistry`] in effect.
+#[cfg(feature = "serde")]
+impl serde::Serialize for CustomFunction {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CustomFunction {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        FunctionRegistry::lookup(&name)
+            .ok_or_else(|| serde::de::Error::custom(format!("function {name} is not registered")))
+    }
+}
+
+/// Why a function could not be registered.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum FunctionRegistryError {
+    #[error("{0} already means something in expressions, and cannot name a function")]
+    ReservedName(String),
+
+    #[error("{0} is not an identifier, and cannot name a function")]
+    InvalidName(String),
+}
+
+thread_local! {
+    static FUNCTION_REGISTRY: RefCell<FunctionRegistry> = RefCell::new(FunctionRegistry::new());
+}
+
+/// Restores the registry in effect before a [`FunctionRegistry::scope`] when dropped, even if
+/// the scoped closure panics.
+struct FunctionRegistryGuard(Option<FunctionRegistry>);
+
+impl Drop for FunctionRegistryGuard {
+    fn drop(&mut self) {
+        if let Some(registry) = self.0.take() {
+            FUNCTION_REGISTRY.with(|current| *current.borrow_mut() = registry);
+        }
+    }
+}
+
+/// Named functions, beyond those Quil defines, which expressions may call.
+///
+/// Expressions are parsed with the functions of the registry in effect on the current thread,
+/// which is empty unless one is selected for the duration of a call with
+/// [`FunctionRegistry::scope`]. A call to a registered function is parsed as an
+/// [`Expression::CustomFunctionCall`](super::Expression::CustomFunctionCall) which holds the
+/// function itself, so that it can be written out and evaluated outside of the scope.
+///
+/// The registry in effect is state of the current thread, as are the [`FloatFormat`] and
+/// [`FormattingOptions`] with which numbers are written: a registry selected on one thread is
+/// not seen by parsing on another, such as a worker of a thread pool, which must select it in
+/// turn. [`FunctionRegistry::parse`] takes the registry as an argument instead.
+///
+/// [`FloatFormat`]: super::FloatFormat
+/// [`FormattingOptions`]: super::FormattingOptions
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use quil_rs::expression::{Expression, FunctionRegistry};
+/// use quil_rs::Program;
+///
+/// let mut registry = FunctionRegistry::new();
+/// registry
+///     .register("hypot", 2, |arguments| (arguments[0] * arguments[0] + arguments[1] * arguments[1]).sqrt())
+///     .unwrap();
+///
+/// let program: Program = registry.scope(|| "RX(hypot(3, 4)) 0".parse()).unwrap();
+/// assert_eq!(program.to_string(true), "RX(hypot(3, 4)) 0\n");
+///
+/// let expression: Expression = registry.scope(|| "hypot(3, 4) / 2".parse()).unwrap();
+/// let value = expression.evaluate(&HashMap::new(), &HashMap::new()).unwrap();
+/// assert_eq!(value.re, 2.5);
+///
+/// // Outside of the scope, the function is not known.
+/// assert!("RX(hypot(3, 4)) 0".parse::<Program>().is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct FunctionRegistry {
+    functions: HashMap<String, CustomFunction>,
+}
+
+impl FunctionRegistry {
+    /// A registry of no functions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the function `name` of `arity` arguments, computed by `callback`, replacing any
+    /// function already registered by that name. Neither the names of Quil's own functions, nor
+    /// `pi` and `i`, can be registered.
+    pub fn register(
+        &mut self,
+        name: &str,
+        arity: usize,
+        callback: impl Fn(&[Complex64]) -> Complex64 + Send + Sync + RefUnwindSafe + 'static,
+    ) -> Result<(), FunctionRegistryError> {
+        if RESERVED_NAMES.contains(&name) {
+            return Err(FunctionRegistryError::ReservedName(name.to_string()));
+        }
+        // As Quil identifiers are.
+        let is_identifier = name
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+            && !name.ends_with('-');
+        if !is_identifier {
+            return Err(FunctionRegistryError::InvalidName(name.to_string()));
+        }
+        let function = CustomFunction(Arc::new(Definition {
+            name: name.to_string(),
+            arity,
+            callback: Box::new(callback),
+        }));
+        self.functions.insert(name.to_string(), function);
+        Ok(())
+    }
+
+    /// The function registered as `name`, if any.
+    pub fn get(&self, name: &str) -> Option<&CustomFunction> {
+        self.functions.get(name)
+    }
+
+    /// Run `f` with this as the registry in effect on the current thread, restoring the previous
+    /// registry afterwards.
+    pub fn scope<T>(&self, f: impl FnOnce() -> T) -> T {
+        let previous = FUNCTION_REGISTRY.with(|current| current.replace(self.clone()));
+        let _guard = FunctionRegistryGuard(Some(previous));
+        f()
+    }
+
+    /// Parse `s`, such as an [`Expression`](super::Expression) or a
+    /// [`Program`](crate::Program), calling the functions of this registry, whatever registry is
+    /// otherwise in effect on the current thread.
+    pub fn parse<T: std::str::FromStr>(&self, s: &str) -> Result<T, T::Err> {
+        self.scope(|| s.parse())
+    }
+
+    /// The function registered as `name` in the registry in effect on the current thread.
+    pub(crate) fn lookup(name: &str) -> Option<CustomFunction> {
+        FUNCTION_REGISTRY.with(|current| current.borrow().get(name).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::{FunctionRegistry, FunctionRegistryError};
+    use crate::expression::Expression;
+    use crate::{real, Program};
+
+    fn registry() -> FunctionRegistry {
+        let mut registry = FunctionRegistry::new();
+        registry
+            .register("double", 1, |arguments| arguments[0] * 2.0)
+            .unwrap();
+        registry
+            .register("clamp", 3, |arguments| {
+                real!(arguments[0].re.clamp(arguments[1].re, arguments[2].re))
+            })
+            .unwrap();
+        registry.register("seed", 0, |_| real!(0.25)).unwrap();
+        registry
+    }
+
+    #[rstest]
+    #[case("double(%theta)", "double(%theta)")]
+    #[case("clamp(ro[0] * 2, -1, 1)", "clamp((ro[0]*2), (-1), 1)")]
+    #[case("seed() + cos(double(pi))", "(seed()+cos(double(pi)))")]
+    fn parse_and_display(#[case] input: &str, #[case] expected: &str) {
+        let expression = registry().scope(|| Expression::from_str(input).unwrap());
+        assert_eq!(expression.to_string(), expected);
+        // What is written reads back as the same expression.
+        let reparsed = registry().scope(|| Expression::from_str(expected).unwrap());
+        assert_eq!(reparsed, expression);
+    }
+
+    #[rstest]
+    #[case("double(1, 2)")]
+    #[case("clamp(1)")]
+    #[case("seed(1)")]
+    #[case("triple(1)")]
+    fn parse_invalid(#[case] input: &str) {
+        assert!(registry().scope(|| Expression::from_str(input).is_err()));
+    }
+
+    #[test]
+    fn evaluate() {
+        let expression = registry()
+            .scope(|| Expression::from_str("clamp(double(%theta), 0, ro[0]) + seed()").unwrap());
+        let variables = HashMap::from([("theta".to_string(), real!(1.5))]);
+        let memory = HashMap::from([("ro", vec![2.5])]);
+        assert_eq!(expression.evaluate(&variables, &memory), Ok(real!(2.75)));
+        assert_eq!(
+            expression.clone().into_simplified().to_string(),
+            "(clamp(double(%theta), 0, ro[0])+0.25)"
+        );
+        let constant = registry().scope(|| Expression::from_str("double(1i)").unwrap());
+        assert_eq!(
+            constant.into_simplified(),
+            Expression::Number(Complex64::new(0.0, 2.0))
+        );
+    }
+
+    #[test]
+    fn program_round_trip() {
+        let input =
+            "DECLARE ro REAL[1]\nRX(double(ro[0])) 0\nSHIFT-PHASE 0 \"rf\" clamp(%phase, 0, pi)\n";
+        let program: Program = registry().scope(|| input.parse().unwrap());
+        assert_eq!(program.to_string(true), input);
+    }
+
+    #[rstest]
+    #[case("sin", FunctionRegistryError::ReservedName("sin".to_string()))]
+    #[case("pi", FunctionRegistryError::ReservedName("pi".to_string()))]
+    #[case("2f", FunctionRegistryError::InvalidName("2f".to_string()))]
+    #[case("f-", FunctionRegistryError::InvalidName("f-".to_string()))]
+    #[case("", FunctionRegistryError::InvalidName("".to_string()))]
+    fn register_invalid(#[case] name: &str, #[case] expected: FunctionRegistryError) {
+        let mut registry = FunctionRegistry::new();
+        assert_eq!(
+            registry.register(name, 1, |arguments| arguments[0]),
+            Err(expected)
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let expression = registry()
+            .scope(|| Expression::from_str("double(%theta) + 1"))
+            .unwrap();
+        let json = serde_json::to_string(&expression).unwrap();
+        let deserialized: Expression = registry().scope(|| serde_json::from_str(&json)).unwrap();
+        assert_eq!(deserialized, expression);
+        assert!(serde_json::from_str::<Expression>(&json).is_err());
+    }
+
+    #[test]
+    fn parse_with_registry() {
+        let expression: Expression = registry().parse("double(2)").unwrap();
+        assert_eq!(expression.to_string(), "double(2)");
+        assert!(FunctionRegistry::new()
+            .parse::<Expression>("double(2)")
+            .is_err());
+    }
+
+    #[test]
+    fn unwind_safe() {
+        fn assert_unwind_safe<T: std::panic::RefUnwindSafe + std::panic::UnwindSafe>() {}
+        assert_unwind_safe::<Expression>();
+        assert_unwind_safe::<crate::instruction::Instruction>();
+        assert_unwind_safe::<Program>();
+
+        let program: Program = registry().parse("RX(double(0.5)) 0").unwrap();
+        let text = std::panic::catch_unwind(|| program.to_string(true)).unwrap();
+        assert_eq!(text, "RX(double(0.5)) 0\n");
+    }
+
+    #[test]
+    fn scope_is_restored() {
+        let outer = registry();
+        let inner = FunctionRegistry::new();
+        outer.scope(|| {
+            assert!(inner.scope(|| Expression::from_str("double(1)").is_err()));
+            assert!(Expression::from_str("double(1)").is_ok());
+        });
+        assert!(Expression::from_str("double(1)").is_err());
+    }
+}
@@ -0,0 +1,119 @@
+//! Entry points for fuzzing the parser.
+//!
+//! The parser never panics: any input which is not valid Quil is rejected with an error. A fuzz
+//! target checking this, with [`cargo fuzz`](https://github.com/rust-fuzz/cargo-fuzz), may be
+//! written as:
+//!
+//! ```ignore
+//! #![no_main]
+//! libfuzzer_sys::fuzz_target!(|data: &[u8]| quil_rs::fuzz::parse(data));
+//! ```
+//!
+//! With the `arbitrary` feature, [`Instruction`](crate::instruction::Instruction), [`Program`],
+//! and the types within them implement [`arbitrary::Arbitrary`](https://docs.rs/arbitrary), so
+//! that code which consumes them can be fuzzed without going through Quil text. Arbitrary
+//! instructions need not be valid Quil: names may contain spaces, and gates may be applied to no
+//! qubits.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use crate::expression::Expression;
+use crate::program::ParserOptions;
+use crate::{lexer, Program};
+
+/// Run `data`, read as UTF-8 with any invalid sequences replaced, through each of the ways this
+/// crate parses Quil text, discarding the results.
+pub fn parse(data: &[u8]) {
+    let input = String::from_utf8_lossy(data);
+    let _ = lexer::lex(&input);
+    let _ = Expression::from_str(&input);
+    let _ = Program::from_str(&input);
+    let _ = Program::parse_with_recovery(&input);
+    let _ = Program::parse_with_options(&input, &ParserOptions::default());
+    let _ = Program::parse_with_options(
+        &input,
+        &ParserOptions {
+            case_insensitive_keywords: true,
+            ..ParserOptions::default()
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+    use rstest::rstest;
+
+    use super::parse;
+    use crate::program::ProgramError;
+    use crate::Program;
+
+    #[rstest]
+    #[case("ADD ro[0] +1")]
+    #[case("EQ ro[0] ro[1] +1.5")]
+    #[case("MOVE ro[0] -9223372036854775809")]
+    #[case("H 99999999999999999999999")]
+    #[case("NONBLOCKING H 0")]
+    #[case("NONBLOCKING")]
+    fn test_invalid_input_is_an_error(#[case] input: &str) {
+        assert!(input.parse::<Program>().is_err());
+        parse(input.as_bytes());
+    }
+
+    #[test]
+    fn test_deeply_nested_expression() {
+        let nested = |depth| format!("RX({}1{}) 0", "(".repeat(depth), ")".repeat(depth));
+        assert!(nested(100).parse::<Program>().is_ok());
+        assert!(matches!(
+            nested(10_000).parse::<Program>(),
+            Err(ProgramError::Syntax(_))
+        ));
+        parse(nested(10_000).as_bytes());
+    }
+
+    #[test]
+    fn test_integer_bounds() {
+        let program: Program = "MOVE ro[0] -9223372036854775808".parse().unwrap();
+        assert_eq!(
+            program.to_string(false),
+            "MOVE ro[0] -9223372036854775808\n"
+        );
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn test_arbitrary_program() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let data: Vec<u8> = (0..4096u32).map(|i| (i * 7 + 1) as u8).collect();
+        let program = Program::arbitrary(&mut Unstructured::new(&data)).unwrap();
+        assert!(!program.to_instructions(true).is_empty());
+        let _ = program.to_string(true);
+    }
+
+    proptest! {
+        #[test]
+        fn test_parse_does_not_panic(data in prop::collection::vec(any::<u8>(), 0..256)) {
+            parse(&data);
+        }
+
+        #[test]
+        fn test_parse_quil_like_text_does_not_panic(input in "([A-Z]{1,8}|[0-9]{1,25}|[ \\n\\t()\\[\\]:;,+*/^%@\"#-]|pi|i|q|ro|NONBLOCKING|DECLARE|DEFCAL|DEFCIRCUIT| {4})*") {
+            parse(input.as_bytes());
+        }
+    }
+}
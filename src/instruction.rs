@@ -13,7 +13,6 @@
 // limitations under the License.
 
 use nom_locate::LocatedSpan;
-use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::HashSet;
 use std::str::FromStr;
@@ -26,7 +25,23 @@ use crate::program::{disallow_leftover, frame::FrameMatchCondition, SyntaxError}
 #[cfg(test)]
 use proptest_derive::Arbitrary;
 
+mod extern_call;
+pub mod gates;
+mod identifier;
+mod pragma;
+pub mod unitary;
+pub mod visit;
+
+pub use self::extern_call::{
+    Call, CallArgument, ExternParameter, ExternParameterType, ExternSignature,
+};
+pub use self::identifier::Identifier;
+pub use self::pragma::{PragmaError, PragmaParser, PragmaRegistry, RewiringStrategy, TypedPragma};
+pub use self::visit::{InstructionRewriter, InstructionVisitor};
+
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ArithmeticOperand {
     LiteralInteger(i64),
     LiteralReal(f64),
@@ -37,13 +52,17 @@ impl fmt::Display for ArithmeticOperand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self {
             ArithmeticOperand::LiteralInteger(value) => write!(f, "{}", value),
-            ArithmeticOperand::LiteralReal(value) => write!(f, "{}", value),
+            // Debug formatting keeps the decimal point of whole numbers, so that they are read
+            // back as reals rather than integers.
+            ArithmeticOperand::LiteralReal(value) => write!(f, "{:?}", value),
             ArithmeticOperand::MemoryReference(value) => write!(f, "{}", value),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ArithmeticOperator {
     Add,
     Subtract,
@@ -63,6 +82,8 @@ impl fmt::Display for ArithmeticOperator {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperand {
     LiteralInteger(i64),
     MemoryReference(MemoryReference),
@@ -78,6 +99,8 @@ impl fmt::Display for BinaryOperand {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum BinaryOperator {
     And,
     Ior,
@@ -94,6 +117,8 @@ impl fmt::Display for BinaryOperator {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum UnaryOperator {
     Neg,
     Not,
@@ -109,6 +134,8 @@ impl fmt::Display for UnaryOperator {
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComparisonOperand {
     LiteralInteger(i64),
     LiteralReal(f64),
@@ -119,13 +146,17 @@ impl fmt::Display for ComparisonOperand {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match &self {
             ComparisonOperand::LiteralInteger(value) => write!(f, "{}", value),
-            ComparisonOperand::LiteralReal(value) => write!(f, "{}", value),
+            // Debug formatting keeps the decimal point of whole numbers, so that they are read
+            // back as reals rather than integers.
+            ComparisonOperand::LiteralReal(value) => write!(f, "{:?}", value),
             ComparisonOperand::MemoryReference(value) => write!(f, "{}", value),
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComparisonOperator {
     Equal,
     GreaterThanOrEqual,
@@ -147,6 +178,8 @@ impl fmt::Display for ComparisonOperator {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum AttributeValue {
     String(String),
     Expression(Expression),
@@ -165,23 +198,29 @@ impl fmt::Display for AttributeValue {
 pub type FrameAttributes = HashMap<String, AttributeValue>;
 
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Calibration {
     pub instructions: Vec<Instruction>,
     pub modifiers: Vec<GateModifier>,
-    pub name: String,
+    pub name: Identifier,
     pub parameters: Vec<Expression>,
     pub qubits: Vec<Qubit>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Convert {
     pub from: MemoryReference,
     pub to: MemoryReference,
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameIdentifier {
-    pub name: String,
+    pub name: Identifier,
     pub qubits: Vec<Qubit>,
 }
 
@@ -192,11 +231,15 @@ impl fmt::Display for FrameIdentifier {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Include {
     pub filename: String,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GateModifier {
     Controlled,
     Dagger,
@@ -222,6 +265,7 @@ impl fmt::Display for GateModifier {
 pub enum GateType {
     Matrix,
     Permutation,
+    PauliSum,
 }
 
 impl fmt::Display for GateType {
@@ -233,12 +277,15 @@ impl fmt::Display for GateType {
             match self {
                 Matrix => "MATRIX",
                 Permutation => "PERMUTATION",
+                PauliSum => "PAULI-SUM",
             }
         )
     }
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ScalarType {
     Bit,
     Integer,
@@ -263,6 +310,8 @@ impl fmt::Display for ScalarType {
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Vector {
     pub data_type: ScalarType,
     pub length: u64,
@@ -275,8 +324,10 @@ impl fmt::Display for Vector {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WaveformInvocation {
-    pub name: String,
+    pub name: Identifier,
     pub parameters: HashMap<String, Expression>,
 }
 
@@ -323,9 +374,11 @@ mod waveform_invocation_tests {
 }
 
 #[derive(Clone, Debug, Hash, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[cfg_attr(test, derive(Arbitrary))]
 pub struct MemoryReference {
-    pub name: String,
+    pub name: Identifier,
     pub index: u64,
 }
 
@@ -350,16 +403,20 @@ impl FromStr for MemoryReference {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Gate {
-    pub name: String,
+    pub name: Identifier,
     pub parameters: Vec<Expression>,
     pub qubits: Vec<Qubit>,
     pub modifiers: Vec<GateModifier>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CircuitDefinition {
-    pub name: String,
+    pub name: Identifier,
     pub parameters: Vec<String>,
     // These cannot be fixed qubits and thus are not typed as `Qubit`
     pub qubit_variables: Vec<String>,
@@ -367,37 +424,169 @@ pub struct CircuitDefinition {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum GateSpecification {
     Matrix(Vec<Vec<Expression>>),
     Permutation(Vec<u64>),
+    PauliSum(PauliSum),
+}
+
+impl GateSpecification {
+    /// The form the gate is defined in, as written after `AS` in its `DEFGATE`.
+    pub fn gate_type(&self) -> GateType {
+        match self {
+            GateSpecification::Matrix(_) => GateType::Matrix,
+            GateSpecification::Permutation(_) => GateType::Permutation,
+            GateSpecification::PauliSum(_) => GateType::PauliSum,
+        }
+    }
+}
+
+/// A single-qubit Pauli operator, as used in the terms of a [`PauliSum`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum PauliGate {
+    I,
+    X,
+    Y,
+    Z,
+}
+
+impl PauliGate {
+    /// The Pauli operator written as `c`, if any.
+    pub fn from_char(c: char) -> Option<Self> {
+        match c {
+            'I' => Some(PauliGate::I),
+            'X' => Some(PauliGate::X),
+            'Y' => Some(PauliGate::Y),
+            'Z' => Some(PauliGate::Z),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for PauliGate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PauliGate::I => write!(f, "I"),
+            PauliGate::X => write!(f, "X"),
+            PauliGate::Y => write!(f, "Y"),
+            PauliGate::Z => write!(f, "Z"),
+        }
+    }
+}
+
+/// One term of a [`PauliSum`]: a product of Pauli operators on some of the gate's arguments,
+/// weighted by `expression`, as in `ZZ(%theta/4) p q`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PauliTerm {
+    pub arguments: Vec<(PauliGate, String)>,
+    pub expression: Expression,
+}
+
+impl fmt::Display for PauliTerm {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let word: String = self
+            .arguments
+            .iter()
+            .map(|(gate, _)| gate.to_string())
+            .collect();
+        let arguments: Vec<&str> = self
+            .arguments
+            .iter()
+            .map(|(_, argument)| argument.as_str())
+            .collect();
+        write!(f, "{word}({}) {}", self.expression, arguments.join(" "))
+    }
+}
+
+/// A gate defined `AS PAULI-SUM`: the generator of the gate, as a sum of Pauli terms over the
+/// named `arguments` of the gate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PauliSum {
+    pub arguments: Vec<String>,
+    pub terms: Vec<PauliTerm>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct GateDefinition {
-    pub name: String,
+    pub name: Identifier,
     pub parameters: Vec<String>,
     pub specification: GateSpecification,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Declaration {
-    pub name: String,
+    pub name: Identifier,
     pub size: Vector,
-    pub sharing: Option<String>,
+    pub sharing: Option<Sharing>,
+}
+
+/// The memory of another region which a declared region occupies, as by
+/// `DECLARE name TYPE[length] SHARING other OFFSET 2 REAL`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Sharing {
+    /// The name of the region whose memory is shared.
+    pub name: Identifier,
+    /// How far into that region the declared region begins, as counts of elements of each type.
+    pub offsets: Vec<Offset>,
+}
+
+impl fmt::Display for Sharing {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SHARING {}", self.name)?;
+        for offset in &self.offsets {
+            write!(f, " {}", offset)?;
+        }
+        Ok(())
+    }
+}
+
+/// An offset into a shared memory region: `count` elements of `data_type`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Offset {
+    pub count: u64,
+    pub data_type: ScalarType,
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "OFFSET {} {}", self.count, self.data_type)
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Measurement {
     pub qubit: Qubit,
     pub target: Option<MemoryReference>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Reset {
     pub qubit: Option<Qubit>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Capture {
     pub blocking: bool,
     pub frame: FrameIdentifier,
@@ -406,6 +595,8 @@ pub struct Capture {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Delay {
     pub duration: Expression,
     pub frame_names: Vec<String>,
@@ -413,31 +604,44 @@ pub struct Delay {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Fence {
     pub qubits: Vec<Qubit>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct FrameDefinition {
     pub identifier: FrameIdentifier,
     pub attributes: HashMap<String, AttributeValue>,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct MeasureCalibrationDefinition {
+    /// The qubit measured: fixed, a variable standing for any qubit, or `None` for any qubit.
     pub qubit: Option<Qubit>,
-    pub parameter: String,
+    /// The name standing for the memory reference into which the result is written, if the
+    /// calibration is for measurements with one; `None` for those which discard the result.
+    pub parameter: Option<String>,
     pub instructions: Vec<Instruction>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pragma {
-    pub name: String,
+    pub name: Identifier,
     pub arguments: Vec<PragmaArgument>,
     pub data: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum PragmaArgument {
     Identifier(String),
     Integer(u64),
@@ -453,6 +657,8 @@ impl fmt::Display for PragmaArgument {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pulse {
     pub blocking: bool,
     pub frame: FrameIdentifier,
@@ -460,6 +666,8 @@ pub struct Pulse {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RawCapture {
     pub blocking: bool,
     pub frame: FrameIdentifier,
@@ -468,123 +676,226 @@ pub struct RawCapture {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetFrequency {
     pub frame: FrameIdentifier,
     pub frequency: Expression,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetPhase {
     pub frame: FrameIdentifier,
     pub phase: Expression,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SetScale {
     pub frame: FrameIdentifier,
     pub scale: Expression,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShiftFrequency {
     pub frame: FrameIdentifier,
     pub frequency: Expression,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ShiftPhase {
     pub frame: FrameIdentifier,
     pub phase: Expression,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SwapPhases {
     pub frame_1: FrameIdentifier,
     pub frame_2: FrameIdentifier,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct WaveformDefinition {
-    pub name: String,
+    pub name: Identifier,
     pub definition: Waveform,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Arithmetic {
     pub operator: ArithmeticOperator,
-    pub destination: ArithmeticOperand,
+    pub destination: MemoryReference,
     pub source: ArithmeticOperand,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Comparison {
     pub operator: ComparisonOperator,
     pub operands: (MemoryReference, MemoryReference, ComparisonOperand),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct BinaryLogic {
     pub operator: BinaryOperator,
     pub operands: (MemoryReference, BinaryOperand),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct UnaryLogic {
     pub operator: UnaryOperator,
     pub operand: MemoryReference,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Label(pub String);
+/// A label, as defined by `LABEL` and targeted by jumps.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Label {
+    /// A label with a name, written `@name`.
+    Fixed(Identifier),
+    /// A label yet to be named, such as one within a template which is expanded more than once.
+    /// Each is named by [`Program::resolve_label_placeholders`](crate::Program), and cannot be
+    /// serialized before it is.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    Placeholder(LabelPlaceholder),
+}
+
+impl fmt::Display for Label {
+    /// A label is written without its `@`. A placeholder is written as its base name within
+    /// angle brackets, which is not valid Quil.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Label::Fixed(name) => write!(f, "{name}"),
+            Label::Placeholder(placeholder) => write!(f, "<{}>", placeholder.base()),
+        }
+    }
+}
+
+/// A label yet to be named. Placeholders are distinct from one another, even if they have the
+/// same base name; each copy of one is the same placeholder.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::instruction::LabelPlaceholder;
+///
+/// let placeholder = LabelPlaceholder::new("loop");
+/// assert_eq!(placeholder, placeholder.clone());
+/// assert_ne!(placeholder, LabelPlaceholder::new("loop"));
+/// ```
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+pub struct LabelPlaceholder(std::sync::Arc<String>);
+
+impl LabelPlaceholder {
+    /// A new placeholder, which is named after `base` where it can be.
+    pub fn new(base: impl Into<String>) -> Self {
+        Self(std::sync::Arc::new(base.into()))
+    }
+
+    /// The name the placeholder is named after where that name is not already in use.
+    pub fn base(&self) -> &str {
+        &self.0
+    }
+}
+
+impl PartialEq for LabelPlaceholder {
+    fn eq(&self, other: &Self) -> bool {
+        std::sync::Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for LabelPlaceholder {}
+
+impl std::hash::Hash for LabelPlaceholder {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        std::sync::Arc::as_ptr(&self.0).hash(state)
+    }
+}
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Move {
-    pub destination: ArithmeticOperand,
+    pub destination: MemoryReference,
     pub source: ArithmeticOperand,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Exchange {
-    pub left: ArithmeticOperand,
-    pub right: ArithmeticOperand,
+    pub left: MemoryReference,
+    pub right: MemoryReference,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Load {
     pub destination: MemoryReference,
-    pub source: String,
+    pub source: Identifier,
     pub offset: MemoryReference,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Store {
-    pub destination: String,
+    pub destination: Identifier,
     pub offset: MemoryReference,
     pub source: ArithmeticOperand,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Jump {
-    pub target: String,
+    pub target: Label,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct JumpWhen {
-    pub target: String,
+    pub target: Label,
     pub condition: MemoryReference,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct JumpUnless {
-    pub target: String,
+    pub target: Label,
     pub condition: MemoryReference,
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Instruction {
     Arithmetic(Arithmetic),
     BinaryLogic(BinaryLogic),
     CalibrationDefinition(Calibration),
+    Call(Call),
     Capture(Capture),
     CircuitDefinition(CircuitDefinition),
     Convert(Convert),
@@ -619,6 +930,7 @@ pub enum Instruction {
     Store(Store),
     SwapPhases(SwapPhases),
     UnaryLogic(UnaryLogic),
+    Wait,
     WaveformDefinition(WaveformDefinition),
 }
 
@@ -657,6 +969,7 @@ impl From<&Instruction> for InstructionRole {
             | Instruction::ShiftPhase(_)
             | Instruction::SwapPhases(_) => InstructionRole::RFControl,
             Instruction::Arithmetic(_)
+            | Instruction::Call(_)
             | Instruction::Comparison(_)
             | Instruction::Convert(_)
             | Instruction::BinaryLogic(_)
@@ -670,9 +983,69 @@ impl From<&Instruction> for InstructionRole {
             Instruction::Halt
             | Instruction::Jump(_)
             | Instruction::JumpWhen(_)
-            | Instruction::JumpUnless(_) => InstructionRole::ControlFlow,
+            | Instruction::JumpUnless(_)
+            | Instruction::Wait => InstructionRole::ControlFlow,
+        }
+    }
+}
+
+/// How execution proceeds once an instruction has been executed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ControlFlow<'a> {
+    /// Execution continues with the next instruction.
+    FallThrough,
+    /// Execution pauses until an external event, then continues with the next instruction, as
+    /// after `WAIT`.
+    Block,
+    /// Execution ends, as after `HALT`.
+    Halt,
+    /// Execution continues at the label `target`, as after `JUMP`.
+    Jump { target: &'a Label },
+    /// Execution continues at the label `target` if `condition` is non-zero and
+    /// `jump_if_condition_true`, or is zero and not `jump_if_condition_true`, and with the next
+    /// instruction otherwise, as after `JUMP-WHEN` and `JUMP-UNLESS`.
+    ConditionalJump {
+        target: &'a Label,
+        condition: &'a MemoryReference,
+        jump_if_condition_true: bool,
+    },
+}
+
+impl<'a> ControlFlow<'a> {
+    /// Whether execution may continue with the next instruction.
+    pub fn falls_through(&self) -> bool {
+        matches!(
+            self,
+            Self::FallThrough | Self::Block | Self::ConditionalJump { .. }
+        )
+    }
+
+    /// Whether execution ends.
+    pub fn halts(&self) -> bool {
+        matches!(self, Self::Halt)
+    }
+
+    /// Whether execution pauses until an external event.
+    pub fn blocks(&self) -> bool {
+        matches!(self, Self::Block)
+    }
+
+    /// The label execution may continue at, if not with the next instruction.
+    pub fn jump_target(&self) -> Option<&'a Label> {
+        match self {
+            Self::Jump { target } | Self::ConditionalJump { target, .. } => Some(target),
+            Self::FallThrough | Self::Block | Self::Halt => None,
         }
     }
+
+    /// Whether the instruction ends a basic block: a run of instructions which, once entered, are
+    /// executed in order to their end.
+    pub fn ends_basic_block(&self) -> bool {
+        matches!(
+            self,
+            Self::Halt | Self::Jump { .. } | Self::ConditionalJump { .. }
+        )
+    }
 }
 
 pub fn format_instructions(values: &[Instruction]) -> String {
@@ -717,8 +1090,8 @@ pub fn get_expression_parameter_string(parameters: &[Expression]) -> String {
         return String::from("");
     }
 
-    let parameter_str: String = parameters.iter().map(|e| format!("{}", e)).collect();
-    format!("({})", parameter_str)
+    let parameter_str: Vec<String> = parameters.iter().map(|e| format!("{}", e)).collect();
+    format!("({})", parameter_str.join(", "))
 }
 
 pub fn get_string_parameter_string(parameters: &[String]) -> String {
@@ -738,6 +1111,7 @@ impl fmt::Display for Instruction {
                 destination,
                 source,
             }) => write!(f, "{} {} {}", operator, destination, source),
+            Instruction::Call(call) => write!(f, "{}", call),
             Instruction::CalibrationDefinition(calibration) => {
                 let parameter_str = get_expression_parameter_string(&calibration.parameters);
                 write!(
@@ -798,7 +1172,7 @@ impl fmt::Display for Instruction {
             }) => {
                 write!(f, "DECLARE {} {}", name, size)?;
                 match sharing {
-                    Some(shared) => write!(f, "SHARING {}", shared)?,
+                    Some(shared) => write!(f, " {}", shared)?,
                     None => {}
                 }
                 Ok(())
@@ -855,15 +1229,20 @@ impl fmt::Display for Instruction {
                 specification,
             }) => {
                 let parameter_str: String = parameters.iter().map(|p| p.to_string()).collect();
+                let argument_str: String = match specification {
+                    GateSpecification::PauliSum(PauliSum { arguments, .. }) => arguments
+                        .iter()
+                        .map(|argument| format!(" {argument}"))
+                        .collect(),
+                    _ => String::new(),
+                };
                 writeln!(
                     f,
-                    "DEFGATE {}{} AS {}:",
+                    "DEFGATE {}{}{} AS {}:",
                     name,
                     parameter_str,
-                    match specification {
-                        GateSpecification::Matrix(_) => "MATRIX",
-                        GateSpecification::Permutation(_) => "PERMUTATION",
-                    }
+                    argument_str,
+                    specification.gate_type()
                 )?;
                 match specification {
                     GateSpecification::Matrix(matrix) => {
@@ -889,6 +1268,11 @@ impl fmt::Display for Instruction {
                                 .join(", ")
                         )?;
                     }
+                    GateSpecification::PauliSum(PauliSum { terms, .. }) => {
+                        for term in terms {
+                            writeln!(f, "\t{term}")?;
+                        }
+                    }
                 }
                 Ok(())
             }
@@ -908,13 +1292,11 @@ impl fmt::Display for Instruction {
                     }
                     None => {}
                 }
+                if let Some(parameter) = parameter {
+                    write!(f, " {}", parameter)?;
+                }
 
-                writeln!(
-                    f,
-                    " {}:\n\t{}",
-                    parameter,
-                    format_instructions(instructions)
-                )
+                writeln!(f, ":\n\t{}", format_instructions(instructions))
             }
             Instruction::Measurement(Measurement { qubit, target }) => match target {
                 Some(reference) => write!(f, "MEASURE {} {}", qubit, reference),
@@ -1014,6 +1396,7 @@ impl fmt::Display for Instruction {
             ),
             Instruction::Halt => write!(f, "HALT"),
             Instruction::Nop => write!(f, "NOP"),
+            Instruction::Wait => write!(f, "WAIT"),
             Instruction::Jump(Jump { target }) => write!(f, "JUMP @{}", target),
             Instruction::JumpUnless(JumpUnless { condition, target }) => {
                 write!(f, "JUMP-UNLESS @{} {}", target, condition)
@@ -1021,7 +1404,7 @@ impl fmt::Display for Instruction {
             Instruction::JumpWhen(JumpWhen { condition, target }) => {
                 write!(f, "JUMP-WHEN @{} {}", target, condition)
             }
-            Instruction::Label(Label(label)) => write!(f, "LABEL @{}", label),
+            Instruction::Label(label) => write!(f, "LABEL @{}", label),
             Instruction::Comparison(Comparison { operator, operands }) => {
                 write!(
                     f,
@@ -1041,15 +1424,67 @@ impl fmt::Display for Instruction {
 
 #[cfg(test)]
 mod test_instruction_display {
+    use rstest::rstest;
+
     use crate::instruction::PragmaArgument;
 
     use super::{Instruction, Pragma};
 
+    #[rstest]
+    #[case("NEG ro[0]")]
+    #[case("NOT ro[1]")]
+    #[case("AND ro[0] ro[1]")]
+    #[case("IOR ro[0] -3")]
+    #[case("XOR ro[0] 7")]
+    #[case("MOVE ro[0] theta[1]")]
+    #[case("MOVE ro[0] 1.0")]
+    #[case("MOVE ro[0] -2")]
+    #[case("EXCHANGE ro[0] theta[2]")]
+    #[case("CONVERT ro[0] theta[0]")]
+    #[case("ADD ro[0] 2")]
+    #[case("SUB ro[0] theta[0]")]
+    #[case("MUL ro[0] -1.5")]
+    #[case("DIV ro[0] 4.0")]
+    #[case("LOAD ro[0] theta idx[1]")]
+    #[case("STORE theta idx[1] 0.5")]
+    #[case("STORE theta idx[1] ro[0]")]
+    #[case("EQ ro[0] theta[0] theta[1]")]
+    #[case("GT ro[0] theta[0] 1")]
+    #[case("GE ro[0] theta[0] 1e-7")]
+    #[case("LT ro[0] theta[0] -1.0")]
+    #[case("LE ro[0] theta[0] ro[1]")]
+    fn classical_round_trip(#[case] input: &str) {
+        let instruction = Instruction::parse(input).unwrap();
+        assert_eq!(instruction.to_string(), input);
+        assert_eq!(
+            Instruction::parse(&instruction.to_string()),
+            Ok(instruction)
+        );
+    }
+
+    #[rstest]
+    #[case("NEG 1")]
+    #[case("NOT 1.0")]
+    #[case("AND 1 ro[0]")]
+    #[case("XOR ro[0] 1.5")]
+    #[case("MOVE 1 ro[0]")]
+    #[case("EXCHANGE ro[0] 1")]
+    #[case("CONVERT ro[0] 1.0")]
+    #[case("ADD 1 ro[0]")]
+    #[case("LOAD ro[0] 1 idx[0]")]
+    #[case("LOAD ro[0] theta 1")]
+    #[case("STORE theta 1 ro[0]")]
+    #[case("EQ 1 ro[0] ro[1]")]
+    #[case("LT ro[0] 1 ro[1]")]
+    fn classical_invalid_operands(#[case] input: &str) {
+        assert!(Instruction::parse(input).is_err());
+    }
+
     #[test]
     fn pragma() {
         assert_eq!(
             Instruction::Pragma(Pragma {
-                name: String::from("INITIAL_REWIRING"),
+                name: "INITIAL_REWIRING".into(),
                 arguments: vec![],
                 data: Some(String::from("PARTIAL")),
             })
@@ -1058,7 +1493,7 @@ mod test_instruction_display {
         );
         assert_eq!(
             Instruction::Pragma(Pragma {
-                name: String::from("LOAD-MEMORY"),
+                name: "LOAD-MEMORY".into(),
                 arguments: vec![PragmaArgument::Identifier("q0".to_string())],
                 data: Some(String::from("addr")),
             })
@@ -1067,7 +1502,7 @@ mod test_instruction_display {
         );
         assert_eq!(
             Instruction::Pragma(Pragma {
-                name: String::from("PRESERVE_BLOCK"),
+                name: "PRESERVE_BLOCK".into(),
                 arguments: vec![],
                 data: None,
             })
@@ -1077,7 +1512,9 @@ mod test_instruction_display {
     }
 }
 
-#[derive(Clone, Debug, Eq, Hash, PartialEq, Serialize, Deserialize)]
+#[derive(Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Qubit {
     Fixed(u64),
     Variable(String),
@@ -1094,12 +1531,50 @@ impl fmt::Display for Qubit {
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Waveform {
     pub matrix: Vec<Expression>,
     pub parameters: Vec<String>,
 }
 
 impl Instruction {
+    /// How execution proceeds once this instruction has been executed. Instructions within
+    /// blocks, such as those of a `DEFCAL`, are not considered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::{ControlFlow, Instruction, Label};
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "JUMP-UNLESS @end ro[0]".parse().unwrap();
+    /// let control_flow = program.instructions[0].control_flow();
+    /// assert_eq!(control_flow.jump_target(), Some(&Label::Fixed("end".into())));
+    /// assert!(control_flow.falls_through());
+    /// assert_eq!(Instruction::Halt.control_flow(), ControlFlow::Halt);
+    /// ```
+    pub fn control_flow(&self) -> ControlFlow<'_> {
+        match self {
+            Instruction::Halt => ControlFlow::Halt,
+            Instruction::Wait => ControlFlow::Block,
+            Instruction::Jump(Jump { target }) => ControlFlow::Jump { target },
+            Instruction::JumpWhen(JumpWhen { target, condition }) => ControlFlow::ConditionalJump {
+                target,
+                condition,
+                jump_if_condition_true: true,
+            },
+            Instruction::JumpUnless(JumpUnless { target, condition }) => {
+                ControlFlow::ConditionalJump {
+                    target,
+                    condition,
+                    jump_if_condition_true: false,
+                }
+            }
+            _ => ControlFlow::FallThrough,
+        }
+    }
+
     /// Apply the provided closure to this instruction, mutating any `Expression`s within.
     /// Does not affect instructions without `Expression`s within.
     /// Does not traverse or mutate instructions nested within blocks (such as
@@ -1173,6 +1648,14 @@ impl Instruction {
                     }
                 }
             }
+            Instruction::GateDefinition(GateDefinition {
+                specification: GateSpecification::PauliSum(PauliSum { terms, .. }),
+                ..
+            }) => {
+                for term in terms {
+                    closure(&mut term.expression);
+                }
+            }
             _ => {}
         }
     }
@@ -1246,6 +1729,7 @@ impl Instruction {
             Instruction::Arithmetic(_)
             | Instruction::BinaryLogic(_)
             | Instruction::CalibrationDefinition(_)
+            | Instruction::Call(_)
             | Instruction::CircuitDefinition(_)
             | Instruction::Comparison(_)
             | Instruction::Convert(_)
@@ -1268,6 +1752,7 @@ impl Instruction {
             | Instruction::Pragma(_)
             | Instruction::Store(_)
             | Instruction::UnaryLogic(_)
+            | Instruction::Wait
             | Instruction::WaveformDefinition(_) => None,
         }
     }
@@ -1305,7 +1790,56 @@ mod tests {
 
     use crate::{expression::Expression, Program};
 
-    use super::MemoryReference;
+    use super::{ControlFlow, Instruction, Label, MemoryReference};
+
+    #[rstest]
+    #[case("H 0", ControlFlow::FallThrough, true, None)]
+    #[case("NOP", ControlFlow::FallThrough, true, None)]
+    #[case("WAIT", ControlFlow::Block, true, None)]
+    #[case("HALT", ControlFlow::Halt, false, None)]
+    #[case("JUMP @start", ControlFlow::Jump { target: &Label::Fixed("start".into()) }, false, Some("start"))]
+    #[case(
+        "JUMP-WHEN @end ro[1]",
+        ControlFlow::ConditionalJump {
+            target: &Label::Fixed("end".into()),
+            condition: &MemoryReference { name: "ro".into(), index: 1 },
+            jump_if_condition_true: true,
+        },
+        true,
+        Some("end")
+    )]
+    #[case(
+        "JUMP-UNLESS @end ro[1]",
+        ControlFlow::ConditionalJump {
+            target: &Label::Fixed("end".into()),
+            condition: &MemoryReference { name: "ro".into(), index: 1 },
+            jump_if_condition_true: false,
+        },
+        true,
+        Some("end")
+    )]
+    fn control_flow(
+        #[case] input: &str,
+        #[case] expected: ControlFlow,
+        #[case] falls_through: bool,
+        #[case] jump_target: Option<&str>,
+    ) {
+        let instruction = Instruction::parse(input).unwrap();
+        let control_flow = instruction.control_flow();
+        assert_eq!(control_flow, expected);
+        assert_eq!(control_flow.falls_through(), falls_through);
+        assert_eq!(
+            control_flow.jump_target().map(Label::to_string).as_deref(),
+            jump_target
+        );
+        assert_eq!(control_flow.halts(), input == "HALT");
+        assert_eq!(control_flow.blocks(), input == "WAIT");
+        assert_eq!(
+            control_flow.ends_basic_block(),
+            !matches!(control_flow, ControlFlow::FallThrough | ControlFlow::Block)
+        );
+        assert_eq!(instruction.to_string(), input);
+    }
 
     #[test]
     fn apply_to_expressions() {
@@ -1331,16 +1865,16 @@ RX(%a) 0",
     }
 
     #[rstest(input, expected,
-        case("_", MemoryReference { name: "_".to_string(), index: 0 }),
-        case("a", MemoryReference { name: "a".to_string(), index: 0 }),
-        case("a---b", MemoryReference { name: "a---b".to_string(), index: 0 }),
-        case("_a_b_", MemoryReference { name: "_a_b_".to_string(), index: 0 }),
-        case("a-2_b-2", MemoryReference { name: "a-2_b-2".to_string(), index: 0 }),
-        case("_[0]", MemoryReference { name: "_".to_string(), index: 0 }),
-        case("a[1]", MemoryReference { name: "a".to_string(), index: 1 }),
-        case("a---b[2]", MemoryReference { name: "a---b".to_string(), index: 2 }),
-        case("_a_b_[3]", MemoryReference { name: "_a_b_".to_string(), index: 3 }),
-        case("a-2_b-2[4]", MemoryReference { name: "a-2_b-2".to_string(), index: 4 }),
+        case("_", MemoryReference { name: "_".into(), index: 0 }),
+        case("a", MemoryReference { name: "a".into(), index: 0 }),
+        case("a---b", MemoryReference { name: "a---b".into(), index: 0 }),
+        case("_a_b_", MemoryReference { name: "_a_b_".into(), index: 0 }),
+        case("a-2_b-2", MemoryReference { name: "a-2_b-2".into(), index: 0 }),
+        case("_[0]", MemoryReference { name: "_".into(), index: 0 }),
+        case("a[1]", MemoryReference { name: "a".into(), index: 1 }),
+        case("a---b[2]", MemoryReference { name: "a---b".into(), index: 2 }),
+        case("_a_b_[3]", MemoryReference { name: "_a_b_".into(), index: 3 }),
+        case("a-2_b-2[4]", MemoryReference { name: "a-2_b-2".into(), index: 4 }),
     )]
     fn it_parses_memory_reference_from_str(input: &str, expected: MemoryReference) {
         assert_eq!(MemoryReference::from_str(input), Ok(expected));
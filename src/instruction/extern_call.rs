@@ -0,0 +1,308 @@
+//! Classical subroutines declared with `PRAGMA EXTERN` and invoked with `CALL`.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::lexer::{lex, DataType, Token};
+
+use super::{MemoryReference, ScalarType, Vector};
+
+/// An invocation of an extern subroutine: `CALL name arguments...`.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Call {
+    pub name: String,
+    pub arguments: Vec<CallArgument>,
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "CALL {}", self.name)?;
+        for argument in &self.arguments {
+            write!(f, " {argument}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An argument of a [`Call`]: the name of a memory region, an element of one, or a literal.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "arbitrary", derive(arbitrary::Arbitrary))]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum CallArgument {
+    Identifier(String),
+    LiteralInteger(i64),
+    LiteralReal(f64),
+    MemoryReference(MemoryReference),
+}
+
+impl fmt::Display for CallArgument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CallArgument::Identifier(value) => write!(f, "{}", value),
+            CallArgument::LiteralInteger(value) => write!(f, "{}", value),
+            // Debug formatting keeps the decimal point of whole numbers, so that they are read
+            // back as reals rather than integers.
+            CallArgument::LiteralReal(value) => write!(f, "{:?}", value),
+            CallArgument::MemoryReference(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The type of a parameter of an extern subroutine.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ExternParameterType {
+    /// A single value, such as `REAL`.
+    Scalar(ScalarType),
+    /// A vector of a fixed length, such as `REAL[4]`.
+    FixedLengthVector(Vector),
+    /// A vector of any length, such as `REAL[]`.
+    VariableLengthVector(ScalarType),
+}
+
+impl fmt::Display for ExternParameterType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Scalar(data_type) => write!(f, "{data_type}"),
+            Self::FixedLengthVector(vector) => write!(f, "{vector}"),
+            Self::VariableLengthVector(data_type) => write!(f, "{data_type}[]"),
+        }
+    }
+}
+
+/// A named parameter of an extern subroutine, which the subroutine may write to if `mutable`.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExternParameter {
+    pub name: String,
+    pub mutable: bool,
+    pub data_type: ExternParameterType,
+}
+
+impl fmt::Display for ExternParameter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} : ", self.name)?;
+        if self.mutable {
+            write!(f, "mut ")?;
+        }
+        write!(f, "{}", self.data_type)
+    }
+}
+
+/// The signature of an extern subroutine, as written in the quoted data of its `PRAGMA EXTERN`,
+/// such as `"REAL (angle : REAL, results : mut BIT[])"`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct ExternSignature {
+    /// The type of the value the subroutine returns, if any, which is written to the first
+    /// argument of each call.
+    pub return_type: Option<ScalarType>,
+    pub parameters: Vec<ExternParameter>,
+}
+
+impl fmt::Display for ExternSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(return_type) = &self.return_type {
+            write!(f, "{return_type}")?;
+            if self.parameters.is_empty() {
+                return Ok(());
+            }
+            write!(f, " ")?;
+        }
+        let parameters: Vec<String> = self.parameters.iter().map(|p| p.to_string()).collect();
+        write!(f, "({})", parameters.join(", "))
+    }
+}
+
+fn scalar_type(data_type: &DataType) -> ScalarType {
+    match data_type {
+        DataType::Bit => ScalarType::Bit,
+        DataType::Integer => ScalarType::Integer,
+        DataType::Octet => ScalarType::Octet,
+        DataType::Real => ScalarType::Real,
+    }
+}
+
+/// Parse the type of a parameter from the start of `tokens`, returning the tokens after it.
+fn parse_parameter_type(tokens: &[Token]) -> Result<(ExternParameterType, &[Token]), String> {
+    let (data_type, tokens) = match tokens {
+        [Token::DataType(data_type), tokens @ ..] => (scalar_type(data_type), tokens),
+        _ => return Err("expected a parameter type".to_string()),
+    };
+    Ok(match tokens {
+        [Token::LBracket, Token::RBracket, tokens @ ..] => {
+            (ExternParameterType::VariableLengthVector(data_type), tokens)
+        }
+        [Token::LBracket, Token::Integer(length), Token::RBracket, tokens @ ..] => (
+            ExternParameterType::FixedLengthVector(Vector {
+                data_type,
+                length: *length,
+            }),
+            tokens,
+        ),
+        [Token::LBracket, ..] => return Err("expected a vector length".to_string()),
+        _ => (ExternParameterType::Scalar(data_type), tokens),
+    })
+}
+
+/// Parse a parameter from the start of `tokens`, returning the tokens after it.
+fn parse_parameter(tokens: &[Token]) -> Result<(ExternParameter, &[Token]), String> {
+    let (name, tokens) = match tokens {
+        [Token::Identifier(name), Token::Colon, tokens @ ..] => (name.clone(), tokens),
+        _ => return Err("expected a parameter name followed by a colon".to_string()),
+    };
+    let (mutable, tokens) = match tokens {
+        [Token::Identifier(keyword), tokens @ ..] if keyword == "mut" => (true, tokens),
+        _ => (false, tokens),
+    };
+    let (data_type, tokens) = parse_parameter_type(tokens)?;
+    Ok((
+        ExternParameter {
+            name,
+            mutable,
+            data_type,
+        },
+        tokens,
+    ))
+}
+
+impl FromStr for ExternSignature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens: Vec<Token> = lex(s)
+            .map_err(|error| error.to_string())?
+            .into_iter()
+            .map(|token| token.token)
+            .collect();
+        let (return_type, mut tokens) = match tokens.as_slice() {
+            [Token::DataType(data_type), tokens @ ..] => (Some(scalar_type(data_type)), tokens),
+            tokens => (None, tokens),
+        };
+
+        let mut parameters = vec![];
+        if let [Token::LParenthesis, rest @ ..] = tokens {
+            tokens = rest;
+            if let [Token::RParenthesis, rest @ ..] = tokens {
+                tokens = rest;
+            } else {
+                loop {
+                    let (parameter, rest) = parse_parameter(tokens)?;
+                    parameters.push(parameter);
+                    tokens = match rest {
+                        [Token::Comma, rest @ ..] => rest,
+                        [Token::RParenthesis, rest @ ..] => {
+                            tokens = rest;
+                            break;
+                        }
+                        _ => return Err("expected a comma or closing parenthesis".to_string()),
+                    };
+                }
+            }
+        } else if return_type.is_none() {
+            return Err("expected a return type or parameters".to_string());
+        }
+
+        if !tokens.is_empty() {
+            return Err("unexpected text after the signature".to_string());
+        }
+        Ok(Self {
+            return_type,
+            parameters,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{ExternParameter, ExternParameterType, ExternSignature};
+    use crate::instruction::{Instruction, ScalarType, Vector};
+    use crate::Program;
+
+    #[test]
+    fn test_program_round_trip() {
+        let input = "DECLARE ro REAL[2]\nDECLARE seed INTEGER[1]\nPRAGMA EXTERN rng \"REAL (seed : mut INTEGER)\"\nCALL rng ro[1] seed\nCALL log 1 -0.5\n";
+        let program: Program = input.parse().unwrap();
+        assert_eq!(program.to_string(true), input);
+    }
+
+    #[test]
+    fn test_memory_accesses() {
+        let call = Instruction::parse("CALL rng ro[1] seed 3").unwrap();
+        let accesses = call.get_memory_accesses();
+        let mut writes: Vec<_> = accesses.writes.into_iter().collect();
+        writes.sort();
+        assert_eq!(writes, vec!["ro", "seed"]);
+        assert_eq!(accesses.reads.len(), 2);
+    }
+
+    #[test]
+    fn test_parse_signature() {
+        let signature: ExternSignature =
+            "REAL (angle : REAL, counts : mut INTEGER[4], bits : BIT[])"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            signature,
+            ExternSignature {
+                return_type: Some(ScalarType::Real),
+                parameters: vec![
+                    ExternParameter {
+                        name: "angle".to_string(),
+                        mutable: false,
+                        data_type: ExternParameterType::Scalar(ScalarType::Real),
+                    },
+                    ExternParameter {
+                        name: "counts".to_string(),
+                        mutable: true,
+                        data_type: ExternParameterType::FixedLengthVector(Vector {
+                            data_type: ScalarType::Integer,
+                            length: 4,
+                        }),
+                    },
+                    ExternParameter {
+                        name: "bits".to_string(),
+                        mutable: false,
+                        data_type: ExternParameterType::VariableLengthVector(ScalarType::Bit),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[rstest]
+    #[case("REAL (angle : REAL, counts : mut INTEGER[4], bits : BIT[])")]
+    #[case("(result : mut OCTET)")]
+    #[case("INTEGER")]
+    #[case("()")]
+    fn test_signature_round_trip(#[case] input: &str) {
+        let signature: ExternSignature = input.parse().unwrap();
+        assert_eq!(signature.to_string(), input);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("REAL (angle REAL)")]
+    #[case("(angle : REAL")]
+    #[case("(angle : REAL[x])")]
+    #[case("(angle : mut)")]
+    #[case("REAL REAL")]
+    fn test_invalid_signature(#[case] input: &str) {
+        assert!(input.parse::<ExternSignature>().is_err());
+    }
+}
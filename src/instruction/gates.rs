@@ -0,0 +1,529 @@
+//! Constructors for the gates of the Quil standard gate set, which check that each is given the
+//! numbers of parameters and qubits it takes. See the [Quil spec](https://quil-lang.github.io/).
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use thiserror::Error;
+
+use crate::expression::{Expression, PrefixOperator};
+
+use super::{Gate, GateModifier, Qubit};
+
+/// Why a [`Gate`] could not be constructed.
+#[derive(Clone, Debug, PartialEq, Eq, Error)]
+pub enum GateError {
+    #[error("{name} takes {expected} parameters but was given {found}")]
+    ParameterCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("{name} takes {expected} qubits but was given {found}")]
+    QubitCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error("{name} must act on at least one qubit")]
+    NoQubits { name: String },
+
+    #[error("{name} was given qubit {qubit} more than once")]
+    DuplicateQubit { name: String, qubit: Qubit },
+}
+
+/// The numbers of parameters and qubits taken by the Quil standard gate `name`, if it is one.
+pub(crate) fn standard_gate_arity(name: &str) -> Option<(usize, usize)> {
+    Some(match name {
+        "I" | "X" | "Y" | "Z" | "H" | "S" | "T" => (0, 1),
+        "PHASE" | "RX" | "RY" | "RZ" => (1, 1),
+        "CZ" | "CNOT" | "SWAP" | "ISWAP" => (0, 2),
+        "CPHASE00" | "CPHASE01" | "CPHASE10" | "CPHASE" | "PSWAP" | "XY" => (1, 2),
+        "CCNOT" | "CSWAP" => (0, 3),
+        _ => return None,
+    })
+}
+
+/// Fail unless a gate named `name`, whose definition takes the `(parameters, qubits)` of
+/// `arity`, is given the numbers of parameters and qubits it takes with `modifiers` applied.
+pub(crate) fn check_arity(
+    name: &str,
+    arity: (usize, usize),
+    parameter_count: usize,
+    qubit_count: usize,
+    modifiers: &[GateModifier],
+) -> Result<(), GateError> {
+    let (mut expected_parameters, mut expected_qubits) = arity;
+    for modifier in modifiers {
+        match modifier {
+            GateModifier::Controlled => expected_qubits += 1,
+            GateModifier::Dagger => {}
+            GateModifier::Forked => {
+                expected_qubits += 1;
+                expected_parameters *= 2;
+            }
+        }
+    }
+    if parameter_count != expected_parameters {
+        return Err(GateError::ParameterCount {
+            name: name.to_string(),
+            expected: expected_parameters,
+            found: parameter_count,
+        });
+    }
+    if qubit_count != expected_qubits {
+        return Err(GateError::QubitCount {
+            name: name.to_string(),
+            expected: expected_qubits,
+            found: qubit_count,
+        });
+    }
+    Ok(())
+}
+
+/// Fail unless a gate named `name` acts on at least one qubit, and on no qubit twice.
+pub(crate) fn check_qubits(name: &str, qubits: &[Qubit]) -> Result<(), GateError> {
+    if qubits.is_empty() {
+        return Err(GateError::NoQubits {
+            name: name.to_string(),
+        });
+    }
+    for (index, qubit) in qubits.iter().enumerate() {
+        if qubits[..index].contains(qubit) {
+            return Err(GateError::DuplicateQubit {
+                name: name.to_string(),
+                qubit: qubit.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// The negation of `expression`, written without a double negative.
+fn negate(expression: Expression) -> Expression {
+    match expression {
+        Expression::Number(number) => Expression::Number(-number),
+        Expression::Prefix {
+            operator: PrefixOperator::Minus,
+            expression,
+        } => *expression,
+        expression => Expression::Prefix {
+            operator: PrefixOperator::Minus,
+            expression: Box::new(expression),
+        },
+    }
+}
+
+impl From<u64> for Qubit {
+    fn from(index: u64) -> Self {
+        Qubit::Fixed(index)
+    }
+}
+
+impl From<&str> for Qubit {
+    fn from(variable: &str) -> Self {
+        Qubit::Variable(variable.to_string())
+    }
+}
+
+impl Gate {
+    /// A gate named `name`, with the given parameters, qubits, and modifiers, which must act on
+    /// distinct qubits. If `name` is a Quil standard gate, it must also be given the numbers of
+    /// parameters and qubits it takes, counting a qubit for each `CONTROLLED` or `FORKED`
+    /// modifier, and twice the parameters for each `FORKED` modifier.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::{Gate, GateModifier, Instruction, Qubit};
+    /// use quil_rs::instruction::gates::GateError;
+    ///
+    /// let gate = Gate::new("X", vec![], vec![Qubit::Fixed(0), Qubit::Fixed(1)], vec![GateModifier::Controlled]);
+    /// assert_eq!(Instruction::Gate(gate.unwrap()).to_string(), "CONTROLLED X 0 1");
+    ///
+    /// let gate = Gate::new("CNOT", vec![], vec![Qubit::Fixed(0)], vec![]);
+    /// assert_eq!(gate, Err(GateError::QubitCount { name: "CNOT".to_string(), expected: 2, found: 1 }));
+    /// ```
+    pub fn new(
+        name: &str,
+        parameters: Vec<Expression>,
+        qubits: Vec<Qubit>,
+        modifiers: Vec<GateModifier>,
+    ) -> Result<Self, GateError> {
+        if let Some(arity) = standard_gate_arity(name) {
+            check_arity(name, arity, parameters.len(), qubits.len(), &modifiers)?;
+        }
+
+        check_qubits(name, &qubits)?;
+
+        Ok(Self {
+            name: name.into(),
+            parameters,
+            qubits,
+            modifiers,
+        })
+    }
+
+    /// A standard gate which takes no parameters and one qubit, which cannot be invalid.
+    fn single_qubit(name: &str, qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit_rotation(name, vec![], qubit)
+    }
+
+    /// A standard gate which takes `parameters` and one qubit, which cannot be invalid.
+    fn single_qubit_rotation(
+        name: &str,
+        parameters: Vec<Expression>,
+        qubit: impl Into<Qubit>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            parameters,
+            qubits: vec![qubit.into()],
+            modifiers: vec![],
+        }
+    }
+
+    /// The identity gate, `I`.
+    pub fn i(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("I", qubit)
+    }
+
+    /// The Pauli X gate.
+    pub fn x(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("X", qubit)
+    }
+
+    /// The Pauli Y gate.
+    pub fn y(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("Y", qubit)
+    }
+
+    /// The Pauli Z gate.
+    pub fn z(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("Z", qubit)
+    }
+
+    /// The Hadamard gate.
+    pub fn h(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("H", qubit)
+    }
+
+    /// The phase gate `S`, a rotation of π/2 about the Z axis.
+    pub fn s(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("S", qubit)
+    }
+
+    /// The `T` gate, a rotation of π/4 about the Z axis.
+    pub fn t(qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit("T", qubit)
+    }
+
+    /// The `PHASE` gate, which shifts the phase of the |1⟩ state by `angle`.
+    pub fn phase(angle: impl Into<Expression>, qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit_rotation("PHASE", vec![angle.into()], qubit)
+    }
+
+    /// A rotation of `angle` about the X axis.
+    pub fn rx(angle: impl Into<Expression>, qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit_rotation("RX", vec![angle.into()], qubit)
+    }
+
+    /// A rotation of `angle` about the Y axis.
+    pub fn ry(angle: impl Into<Expression>, qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit_rotation("RY", vec![angle.into()], qubit)
+    }
+
+    /// A rotation of `angle` about the Z axis.
+    pub fn rz(angle: impl Into<Expression>, qubit: impl Into<Qubit>) -> Self {
+        Self::single_qubit_rotation("RZ", vec![angle.into()], qubit)
+    }
+
+    /// The controlled Z gate. Fails if the qubits are the same.
+    pub fn cz(control: impl Into<Qubit>, target: impl Into<Qubit>) -> Result<Self, GateError> {
+        Self::new("CZ", vec![], vec![control.into(), target.into()], vec![])
+    }
+
+    /// The controlled X gate. Fails if the qubits are the same.
+    pub fn cnot(control: impl Into<Qubit>, target: impl Into<Qubit>) -> Result<Self, GateError> {
+        Self::new("CNOT", vec![], vec![control.into(), target.into()], vec![])
+    }
+
+    /// The gate exchanging the states of two qubits. Fails if the qubits are the same.
+    pub fn swap(a: impl Into<Qubit>, b: impl Into<Qubit>) -> Result<Self, GateError> {
+        Self::new("SWAP", vec![], vec![a.into(), b.into()], vec![])
+    }
+
+    /// The gate exchanging the states of two qubits, with a phase of i on the `|01⟩` and `|10⟩`
+    /// states. Fails if the qubits are the same.
+    pub fn iswap(a: impl Into<Qubit>, b: impl Into<Qubit>) -> Result<Self, GateError> {
+        Self::new("ISWAP", vec![], vec![a.into(), b.into()], vec![])
+    }
+
+    /// The controlled `PHASE` gate, which shifts the phase of the `|11⟩` state by `angle`.
+    /// Fails if the qubits are the same.
+    pub fn cphase(
+        angle: impl Into<Expression>,
+        control: impl Into<Qubit>,
+        target: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("CPHASE", angle, control, target)
+    }
+
+    /// The gate which shifts the phase of the `|00⟩` state by `angle`. Fails if the qubits are
+    /// the same.
+    pub fn cphase00(
+        angle: impl Into<Expression>,
+        control: impl Into<Qubit>,
+        target: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("CPHASE00", angle, control, target)
+    }
+
+    /// The gate which shifts the phase of the `|01⟩` state by `angle`. Fails if the qubits are
+    /// the same.
+    pub fn cphase01(
+        angle: impl Into<Expression>,
+        control: impl Into<Qubit>,
+        target: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("CPHASE01", angle, control, target)
+    }
+
+    /// The gate which shifts the phase of the `|10⟩` state by `angle`. Fails if the qubits are
+    /// the same.
+    pub fn cphase10(
+        angle: impl Into<Expression>,
+        control: impl Into<Qubit>,
+        target: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("CPHASE10", angle, control, target)
+    }
+
+    /// The gate exchanging the states of two qubits, with a phase of `angle` on the `|01⟩` and
+    /// `|10⟩` states. Fails if the qubits are the same.
+    pub fn pswap(
+        angle: impl Into<Expression>,
+        a: impl Into<Qubit>,
+        b: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("PSWAP", angle, a, b)
+    }
+
+    /// The XY interaction, a rotation of `angle` between the `|01⟩` and `|10⟩` states. Fails if
+    /// the qubits are the same.
+    pub fn xy(
+        angle: impl Into<Expression>,
+        a: impl Into<Qubit>,
+        b: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::two_qubit_rotation("XY", angle, a, b)
+    }
+
+    /// The Toffoli gate, which flips `target` if both controls are in the |1⟩ state. Fails if
+    /// any of the qubits are the same.
+    pub fn ccnot(
+        control_a: impl Into<Qubit>,
+        control_b: impl Into<Qubit>,
+        target: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::new(
+            "CCNOT",
+            vec![],
+            vec![control_a.into(), control_b.into(), target.into()],
+            vec![],
+        )
+    }
+
+    /// The Fredkin gate, which exchanges the states of `a` and `b` if `control` is in the |1⟩
+    /// state. Fails if any of the qubits are the same.
+    pub fn cswap(
+        control: impl Into<Qubit>,
+        a: impl Into<Qubit>,
+        b: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::new(
+            "CSWAP",
+            vec![],
+            vec![control.into(), a.into(), b.into()],
+            vec![],
+        )
+    }
+
+    /// The inverse of the gate. A `DAGGER` modifier is removed if the gate has one; otherwise the
+    /// inverse of a standard gate is written as a standard gate where it can be, by negating
+    /// the angles of rotations, and any other gate is given a `DAGGER` modifier. The gate's name
+    /// is taken to be that of the standard gate; [`Program::dagger`](crate::Program::dagger)
+    /// inverts gates which a program defines itself.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::{Gate, Instruction};
+    ///
+    /// let inverse = |gate: Gate| Instruction::Gate(gate.dagger()).to_string();
+    /// assert_eq!(inverse(Gate::rx(0.5, 0)), "RX(-0.5) 0");
+    /// assert_eq!(inverse(Gate::cnot(0, 1).unwrap()), "CNOT 0 1");
+    /// assert_eq!(inverse(Gate::s(0)), "DAGGER S 0");
+    /// assert_eq!(inverse(Gate::s(0).dagger()), "S 0");
+    /// ```
+    pub fn dagger(&self) -> Self {
+        let mut inverse = self.clone();
+        // DAGGER commutes with CONTROLLED and FORKED, so any may be cancelled.
+        if let Some(index) = inverse
+            .modifiers
+            .iter()
+            .position(|modifier| *modifier == GateModifier::Dagger)
+        {
+            inverse.modifiers.remove(index);
+            return inverse;
+        }
+
+        match self.name.as_str() {
+            "I" | "X" | "Y" | "Z" | "H" | "CZ" | "CNOT" | "SWAP" | "CCNOT" | "CSWAP" => {}
+            "PHASE" | "RX" | "RY" | "RZ" | "CPHASE00" | "CPHASE01" | "CPHASE10" | "CPHASE"
+            | "PSWAP" | "XY" => {
+                for parameter in &mut inverse.parameters {
+                    *parameter = negate(parameter.clone());
+                }
+            }
+            _ => inverse.modifiers.insert(0, GateModifier::Dagger),
+        }
+        inverse
+    }
+
+    /// A standard gate which takes one parameter and two qubits.
+    fn two_qubit_rotation(
+        name: &str,
+        angle: impl Into<Expression>,
+        a: impl Into<Qubit>,
+        b: impl Into<Qubit>,
+    ) -> Result<Self, GateError> {
+        Self::new(name, vec![angle.into()], vec![a.into(), b.into()], vec![])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{standard_gate_arity, GateError};
+    use crate::expression::Expression;
+    use crate::instruction::unitary::Matrix;
+    use crate::instruction::{Gate, GateModifier, Instruction, Qubit};
+
+    #[rstest]
+    #[case(Gate::i(0), "I 0")]
+    #[case(Gate::x(1), "X 1")]
+    #[case(Gate::y("q"), "Y q")]
+    #[case(Gate::z(0), "Z 0")]
+    #[case(Gate::h(0), "H 0")]
+    #[case(Gate::s(0), "S 0")]
+    #[case(Gate::t(0), "T 0")]
+    #[case(Gate::phase(0.5, 0), "PHASE(0.5) 0")]
+    #[case(Gate::rx(Expression::PiConstant, 0), "RX(pi) 0")]
+    #[case(Gate::ry(1.0, 0), "RY(1) 0")]
+    #[case(Gate::rz(Expression::Variable("theta".to_string()), "q"), "RZ(%theta) q")]
+    #[case(Gate::cz(0, 1).unwrap(), "CZ 0 1")]
+    #[case(Gate::cnot(0, 1).unwrap(), "CNOT 0 1")]
+    #[case(Gate::swap(0, 1).unwrap(), "SWAP 0 1")]
+    #[case(Gate::iswap(0, 1).unwrap(), "ISWAP 0 1")]
+    #[case(Gate::cphase(0.5, 0, 1).unwrap(), "CPHASE(0.5) 0 1")]
+    #[case(Gate::cphase00(0.5, 0, 1).unwrap(), "CPHASE00(0.5) 0 1")]
+    #[case(Gate::cphase01(0.5, 0, 1).unwrap(), "CPHASE01(0.5) 0 1")]
+    #[case(Gate::cphase10(0.5, 0, 1).unwrap(), "CPHASE10(0.5) 0 1")]
+    #[case(Gate::pswap(0.5, 0, 1).unwrap(), "PSWAP(0.5) 0 1")]
+    #[case(Gate::xy(0.5, "a", "b").unwrap(), "XY(0.5) a b")]
+    #[case(Gate::ccnot(0, 1, 2).unwrap(), "CCNOT 0 1 2")]
+    #[case(Gate::cswap(0, 1, 2).unwrap(), "CSWAP 0 1 2")]
+    fn test_constructors(#[case] gate: Gate, #[case] expected: &str) {
+        assert_eq!(
+            standard_gate_arity(&gate.name),
+            Some((gate.parameters.len(), gate.qubits.len()))
+        );
+        assert_eq!(Instruction::Gate(gate).to_string(), expected);
+    }
+
+    #[rstest]
+    #[case(
+        Gate::cnot(0, 0),
+        GateError::DuplicateQubit { name: "CNOT".to_string(), qubit: Qubit::Fixed(0) }
+    )]
+    #[case(
+        Gate::cswap("a", "b", "a"),
+        GateError::DuplicateQubit { name: "CSWAP".to_string(), qubit: Qubit::Variable("a".to_string()) }
+    )]
+    #[case(
+        Gate::new("RX", vec![], vec![Qubit::Fixed(0)], vec![]),
+        GateError::ParameterCount { name: "RX".to_string(), expected: 1, found: 0 }
+    )]
+    #[case(
+        Gate::new("RX", vec![Expression::PiConstant], vec![Qubit::Fixed(0)], vec![GateModifier::Forked]),
+        GateError::ParameterCount { name: "RX".to_string(), expected: 2, found: 1 }
+    )]
+    #[case(
+        Gate::new("H", vec![], vec![Qubit::Fixed(0)], vec![GateModifier::Controlled]),
+        GateError::QubitCount { name: "H".to_string(), expected: 2, found: 1 }
+    )]
+    #[case(
+        Gate::new("MYGATE", vec![], vec![], vec![]),
+        GateError::NoQubits { name: "MYGATE".to_string() }
+    )]
+    fn test_invalid(#[case] gate: Result<Gate, GateError>, #[case] expected: GateError) {
+        assert_eq!(gate, Err(expected));
+    }
+
+    #[rstest]
+    #[case("H 0", "H 0")]
+    #[case("CCNOT 0 1 2", "CCNOT 0 1 2")]
+    #[case("RX(pi/2) 0", "RX((-(pi/2))) 0")]
+    #[case("RZ(-%theta) 0", "RZ(%theta) 0")]
+    #[case("FORKED XY(0.5, 1.5) 0 1 2", "FORKED XY(-0.5, -1.5) 0 1 2")]
+    #[case("CONTROLLED PHASE(1) 0 1", "CONTROLLED PHASE(-1) 0 1")]
+    #[case("T 0", "DAGGER T 0")]
+    #[case("CONTROLLED MYGATE 0 1", "DAGGER CONTROLLED MYGATE 0 1")]
+    #[case("CONTROLLED DAGGER ISWAP 0 1 2", "CONTROLLED ISWAP 0 1 2")]
+    fn test_dagger(#[case] input: &str, #[case] expected: &str) {
+        let gate = match Instruction::parse(input).unwrap() {
+            Instruction::Gate(gate) => gate,
+            other => panic!("expected a gate but found {other}"),
+        };
+        let inverse = gate.dagger();
+        assert_eq!(Instruction::Gate(inverse.clone()).to_string(), expected);
+
+        // Gates which are not standard, or have variable parameters, have no known matrix.
+        if let (Ok(matrix), Ok(inverse)) = (gate.to_unitary(3), inverse.to_unitary(3)) {
+            assert!((&inverse * &matrix).approx_eq(&Matrix::identity(8), 1e-10));
+        }
+    }
+
+    #[test]
+    fn test_new() {
+        let gate = Gate::new(
+            "RX",
+            vec![Expression::PiConstant, Expression::PiConstant],
+            vec![Qubit::Fixed(0), Qubit::Fixed(1)],
+            vec![GateModifier::Dagger, GateModifier::Forked],
+        )
+        .unwrap();
+        assert_eq!(
+            Instruction::Gate(gate).to_string(),
+            "DAGGER FORKED RX(pi, pi) 0 1"
+        );
+
+        let gate = Gate::new("MYGATE", vec![], vec![Qubit::Fixed(2)], vec![]).unwrap();
+        assert_eq!(Instruction::Gate(gate).to_string(), "MYGATE 2");
+    }
+}
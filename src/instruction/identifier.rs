@@ -0,0 +1,326 @@
+//! Interned names of gates, memory regions, labels, frames, waveforms, and pragmas.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::borrow::Borrow;
+use std::cmp::Ordering;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::mem::ManuallyDrop;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock};
+
+/// The number of sets the interned text is split across.
+const SHARDS: usize = 64;
+
+/// The set holding the text `name` if any [`Identifier`] with it is in use. The text of every
+/// identifier is stored once, in one of [`SHARDS`] sets chosen by its hash, so that threads
+/// creating and dropping identifiers with different text rarely wait on the same lock.
+fn shard(name: &str) -> MutexGuard<'static, HashSet<Arc<str>>> {
+    static INTERNED: OnceLock<[Mutex<HashSet<Arc<str>>>; SHARDS]> = OnceLock::new();
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    INTERNED.get_or_init(|| std::array::from_fn(|_| Mutex::default()))
+        [hasher.finish() as usize % SHARDS]
+        .lock()
+        // The set is never left inconsistent, so is still usable after a panic elsewhere.
+        .unwrap_or_else(|error| error.into_inner())
+}
+
+/// A name in a program, such as that of a gate, memory region, label, frame, waveform, or pragma.
+///
+/// The text of each name is stored once, however many instructions use it, and shared by every
+/// identifier with that text. Cloning an identifier is cheap, and comparing two identifiers
+/// for equality takes the same time however long they are. Otherwise, an identifier behaves as
+/// the string it holds: it dereferences to `str`, and is compared, ordered, hashed, and written
+/// just as that string would be.
+///
+/// Creating an identifier from text, and dropping one, each take a lock on the set which holds
+/// that text. The text is split across many such sets, so threads parsing at
+/// once wait on one another only when they happen to use names kept in the same set.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::instruction::Identifier;
+///
+/// let name = Identifier::from("RX");
+/// assert_eq!(name, "RX");
+/// assert_eq!(name, Identifier::from(String::from("RX")));
+/// assert!(name.starts_with('R'));
+/// ```
+#[derive(Clone)]
+pub struct Identifier(ManuallyDrop<Arc<str>>);
+
+impl Identifier {
+    /// The identifier with the text `name`.
+    pub fn new(name: &str) -> Self {
+        let mut shard = shard(name);
+        let text = match shard.get(name) {
+            Some(text) => text.clone(),
+            None => {
+                let text: Arc<str> = Arc::from(name);
+                shard.insert(text.clone());
+                text
+            }
+        };
+        Self(ManuallyDrop::new(text))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Drop for Identifier {
+    fn drop(&mut self) {
+        // Forget the text once the last identifier with it is dropped. Each identifier releases
+        // its text with the set locked, so that of two dropped at once on different threads, the
+        // second to take the lock sees that the set alone holds the text; and no identifier with
+        // the text can be created until the lock is released.
+        let mut shard = shard(&self.0);
+        // SAFETY: the text is taken only here, and `self.0` is not used again.
+        let text = unsafe { ManuallyDrop::take(&mut self.0) };
+        if Arc::strong_count(&text) == 2 {
+            shard.remove(&*text);
+        }
+        drop(text);
+    }
+}
+
+impl Default for Identifier {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Deref for Identifier {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl AsRef<str> for Identifier {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Borrow<str> for Identifier {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<&str> for Identifier {
+    fn from(name: &str) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<&String> for Identifier {
+    fn from(name: &String) -> Self {
+        Self::new(name)
+    }
+}
+
+impl From<String> for Identifier {
+    fn from(name: String) -> Self {
+        Self::new(&name)
+    }
+}
+
+impl From<Identifier> for String {
+    fn from(identifier: Identifier) -> Self {
+        identifier.as_str().to_string()
+    }
+}
+
+impl PartialEq for Identifier {
+    fn eq(&self, other: &Self) -> bool {
+        // Identifiers with the same text share it.
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Identifier {}
+
+impl PartialEq<str> for Identifier {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<&str> for Identifier {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialEq<String> for Identifier {
+    fn eq(&self, other: &String) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<Identifier> for str {
+    fn eq(&self, other: &Identifier) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<Identifier> for &str {
+    fn eq(&self, other: &Identifier) -> bool {
+        *self == other.as_str()
+    }
+}
+
+impl PartialEq<Identifier> for String {
+    fn eq(&self, other: &Identifier) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialOrd for Identifier {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Identifier {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl PartialOrd<&str> for Identifier {
+    fn partial_cmp(&self, other: &&str) -> Option<Ordering> {
+        Some(self.as_str().cmp(*other))
+    }
+}
+
+impl Hash for Identifier {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // As the text does, so that maps keyed by identifiers can be looked up by `str`.
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for Identifier {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for Identifier {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Identifier {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Identifier {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        String::arbitrary(u).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+impl proptest::arbitrary::Arbitrary for Identifier {
+    type Parameters = ();
+    type Strategy = proptest::strategy::BoxedStrategy<Self>;
+
+    fn arbitrary_with(_: ()) -> Self::Strategy {
+        use proptest::strategy::Strategy;
+        proptest::arbitrary::any::<String>()
+            .prop_map(Self::from)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::{Arc, Barrier};
+    use std::thread;
+
+    use super::{shard, Identifier};
+
+    #[test]
+    fn shared_text() {
+        let name = Identifier::from("shared_text");
+        let other = Identifier::from(String::from("shared_text"));
+        assert!(Arc::ptr_eq(&*name.0, &*other.0));
+        assert_eq!(name, other);
+        assert_ne!(name, Identifier::from("shared_text2"));
+        assert!(name < "shared_text2");
+
+        let map = HashMap::from([(name, 1)]);
+        assert_eq!(map.get("shared_text"), Some(&1));
+    }
+
+    #[test]
+    fn text_is_forgotten() {
+        let name = Identifier::from("text_is_forgotten");
+        let clone = name.clone();
+        drop(name);
+        assert!(shard("text_is_forgotten").contains("text_is_forgotten"));
+        drop(clone);
+        assert!(!shard("text_is_forgotten").contains("text_is_forgotten"));
+    }
+
+    #[test]
+    fn text_is_forgotten_across_threads() {
+        // Clones of one identifier are dropped on several threads at once, many times over.
+        const THREADS: usize = 4;
+        let barrier = Barrier::new(THREADS);
+        for round in 0..2000 {
+            let name = format!("text_is_forgotten_across_threads_{round}");
+            let identifier = Identifier::from(name.as_str());
+            let clones = vec![identifier; THREADS];
+            thread::scope(|scope| {
+                for clone in clones {
+                    let barrier = &barrier;
+                    scope.spawn(move || {
+                        barrier.wait();
+                        drop(clone);
+                    });
+                }
+            });
+            assert!(
+                !shard(&name).contains(name.as_str()),
+                "{name} was not forgotten"
+            );
+        }
+    }
+}
@@ -0,0 +1,564 @@
+//! Typed interpretations of well-known `PRAGMA`s, and a registry through which other pragmas may
+//! be given types of their own.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::fmt;
+use std::str::FromStr;
+
+use num_complex::Complex64;
+
+use crate::expression::Expression;
+
+use super::{ExternSignature, Pragma, PragmaArgument};
+
+/// Errors which may occur while interpreting a pragma.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid PRAGMA {name}: {message}")]
+pub struct PragmaError {
+    /// The name of the pragma.
+    pub name: String,
+    /// Why the pragma could not be interpreted.
+    pub message: String,
+}
+
+impl PragmaError {
+    /// An error interpreting `pragma`, explained by `message`.
+    pub fn new(pragma: &Pragma, message: impl Into<String>) -> Self {
+        Self {
+            name: pragma.name.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+/// How the compiler chooses the initial mapping of logical to physical qubits, as set by
+/// `PRAGMA INITIAL_REWIRING`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RewiringStrategy {
+    Naive,
+    Random,
+    Partial,
+    Greedy,
+}
+
+impl RewiringStrategy {
+    /// The name of the strategy as written in the pragma.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Naive => "NAIVE",
+            Self::Random => "RANDOM",
+            Self::Partial => "PARTIAL",
+            Self::Greedy => "GREEDY",
+        }
+    }
+}
+
+impl FromStr for RewiringStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "NAIVE" => Ok(Self::Naive),
+            "RANDOM" => Ok(Self::Random),
+            "PARTIAL" => Ok(Self::Partial),
+            "GREEDY" => Ok(Self::Greedy),
+            other => Err(format!("unknown rewiring strategy {other:?}")),
+        }
+    }
+}
+
+impl fmt::Display for RewiringStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+const INITIAL_REWIRING: &str = "INITIAL_REWIRING";
+const READOUT_POVM: &str = "READOUT-POVM";
+const ADD_KRAUS: &str = "ADD-KRAUS";
+const PRESERVE_BLOCK: &str = "PRESERVE_BLOCK";
+const END_PRESERVE_BLOCK: &str = "END_PRESERVE_BLOCK";
+const COMMUTING_BLOCKS: &str = "COMMUTING_BLOCKS";
+const END_COMMUTING_BLOCKS: &str = "END_COMMUTING_BLOCKS";
+const BLOCK: &str = "BLOCK";
+const END_BLOCK: &str = "END_BLOCK";
+const EXTERN: &str = "EXTERN";
+
+/// A pragma interpreted according to its name, either as one of the pragmas known to this crate,
+/// as a pragma of type `T` parsed by a [`PragmaRegistry`], or left as written.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TypedPragma<T = Infallible> {
+    /// `PRAGMA INITIAL_REWIRING "strategy"`
+    InitialRewiring(RewiringStrategy),
+    /// `PRAGMA READOUT-POVM qubit "(p00 p01 p10 p11)"`: the probabilities of reading out each
+    /// value given each state of the qubit, where `p01` is the probability of reading 0 from 1.
+    ReadoutPovm { qubit: u64, matrix: [f64; 4] },
+    /// `PRAGMA ADD-KRAUS gate qubits... "(entries...)"`: a Kraus operator applied along with each
+    /// application of `gate` to `qubits`, given as its matrix in row-major order.
+    AddKraus {
+        gate: String,
+        qubits: Vec<u64>,
+        matrix: Vec<Complex64>,
+    },
+    /// `PRAGMA PRESERVE_BLOCK`
+    PreserveBlock,
+    /// `PRAGMA END_PRESERVE_BLOCK`
+    EndPreserveBlock,
+    /// `PRAGMA COMMUTING_BLOCKS`
+    CommutingBlocks,
+    /// `PRAGMA END_COMMUTING_BLOCKS`
+    EndCommutingBlocks,
+    /// `PRAGMA BLOCK`
+    Block,
+    /// `PRAGMA END_BLOCK`
+    EndBlock,
+    /// `PRAGMA EXTERN name "signature"`: a classical subroutine which may be invoked with `CALL`,
+    /// whose signature may be omitted.
+    Extern {
+        name: String,
+        signature: Option<ExternSignature>,
+    },
+    /// A pragma parsed by a parser added to a [`PragmaRegistry`].
+    Custom(T),
+    /// A pragma which is not known, kept as written.
+    Other(Pragma),
+}
+
+/// The qubit indices of `arguments`.
+fn fixed_qubits(pragma: &Pragma, arguments: &[PragmaArgument]) -> Result<Vec<u64>, PragmaError> {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            PragmaArgument::Integer(index) => Ok(*index),
+            PragmaArgument::Identifier(name) => Err(PragmaError::new(
+                pragma,
+                format!("expected a qubit index but found {name}"),
+            )),
+        })
+        .collect()
+}
+
+/// The numbers of a matrix written as `(a b c ...)` in the data of `pragma`.
+fn matrix_entries(pragma: &Pragma) -> Result<Vec<Complex64>, PragmaError> {
+    let data = pragma
+        .data
+        .as_deref()
+        .ok_or_else(|| PragmaError::new(pragma, "expected a matrix"))?;
+    let entries = data
+        .trim()
+        .strip_prefix('(')
+        .and_then(|data| data.strip_suffix(')'))
+        .ok_or_else(|| PragmaError::new(pragma, "expected a matrix within parentheses"))?;
+    entries
+        .split_whitespace()
+        .map(|entry| {
+            Expression::from_str(entry)
+                .ok()
+                .and_then(|expression| expression.evaluate(&HashMap::new(), &HashMap::new()).ok())
+                .ok_or_else(|| PragmaError::new(pragma, format!("{entry} is not a number")))
+        })
+        .collect()
+}
+
+/// Fail unless `pragma` has neither arguments nor data.
+fn expect_bare(pragma: &Pragma) -> Result<(), PragmaError> {
+    if pragma.arguments.is_empty() && pragma.data.is_none() {
+        Ok(())
+    } else {
+        Err(PragmaError::new(pragma, "expected no arguments"))
+    }
+}
+
+impl TypedPragma {
+    /// Interpret `pragma` as one of the pragmas known to this crate, or leave it as written if it
+    /// is not known.
+    pub fn parse(pragma: &Pragma) -> Result<Self, PragmaError> {
+        match pragma.name.as_str() {
+            INITIAL_REWIRING => {
+                let strategy = match (pragma.arguments.as_slice(), &pragma.data) {
+                    ([], Some(data)) => data
+                        .parse()
+                        .map_err(|message| PragmaError::new(pragma, message))?,
+                    _ => return Err(PragmaError::new(pragma, "expected a quoted strategy")),
+                };
+                Ok(Self::InitialRewiring(strategy))
+            }
+            READOUT_POVM => {
+                let qubit = match fixed_qubits(pragma, &pragma.arguments)?.as_slice() {
+                    [qubit] => *qubit,
+                    _ => return Err(PragmaError::new(pragma, "expected one qubit")),
+                };
+                let entries = matrix_entries(pragma)?;
+                let mut matrix = [0.0; 4];
+                if entries.len() != matrix.len() || entries.iter().any(|entry| entry.im != 0.0) {
+                    return Err(PragmaError::new(pragma, "expected four real numbers"));
+                }
+                for (value, entry) in matrix.iter_mut().zip(entries) {
+                    *value = entry.re;
+                }
+                Ok(Self::ReadoutPovm { qubit, matrix })
+            }
+            ADD_KRAUS => {
+                let (gate, qubits) = match pragma.arguments.split_first() {
+                    Some((PragmaArgument::Identifier(gate), qubits)) => {
+                        (gate.clone(), fixed_qubits(pragma, qubits)?)
+                    }
+                    _ => return Err(PragmaError::new(pragma, "expected a gate name")),
+                };
+                if qubits.is_empty() {
+                    return Err(PragmaError::new(pragma, "expected at least one qubit"));
+                }
+                let matrix = matrix_entries(pragma)?;
+                let dimension = 1usize
+                    .checked_shl(qubits.len() as u32)
+                    .ok_or_else(|| PragmaError::new(pragma, "too many qubits"))?;
+                if dimension.checked_mul(dimension) != Some(matrix.len()) {
+                    return Err(PragmaError::new(
+                        pragma,
+                        format!("expected a {dimension}x{dimension} matrix"),
+                    ));
+                }
+                Ok(Self::AddKraus {
+                    gate,
+                    qubits,
+                    matrix,
+                })
+            }
+            PRESERVE_BLOCK => expect_bare(pragma).map(|_| Self::PreserveBlock),
+            END_PRESERVE_BLOCK => expect_bare(pragma).map(|_| Self::EndPreserveBlock),
+            COMMUTING_BLOCKS => expect_bare(pragma).map(|_| Self::CommutingBlocks),
+            END_COMMUTING_BLOCKS => expect_bare(pragma).map(|_| Self::EndCommutingBlocks),
+            BLOCK => expect_bare(pragma).map(|_| Self::Block),
+            END_BLOCK => expect_bare(pragma).map(|_| Self::EndBlock),
+            EXTERN => {
+                let name = match pragma.arguments.as_slice() {
+                    [PragmaArgument::Identifier(name)] => name.clone(),
+                    _ => return Err(PragmaError::new(pragma, "expected a subroutine name")),
+                };
+                let signature = pragma
+                    .data
+                    .as_deref()
+                    .map(ExternSignature::from_str)
+                    .transpose()
+                    .map_err(|message| {
+                        PragmaError::new(pragma, format!("invalid signature: {message}"))
+                    })?;
+                Ok(Self::Extern { name, signature })
+            }
+            _ => Ok(Self::Other(pragma.clone())),
+        }
+    }
+}
+
+impl<T> TypedPragma<T> {
+    /// Write the pragma back out as a [`Pragma`], or return `None` for a custom pragma, whose type
+    /// this crate does not know how to write.
+    pub fn to_pragma(&self) -> Option<Pragma> {
+        let pragma = |name: &str, arguments: Vec<PragmaArgument>, data: Option<String>| Pragma {
+            name: name.into(),
+            arguments,
+            data,
+        };
+        let matrix = |entries: Vec<String>| Some(format!("({})", entries.join(" ")));
+        Some(match self {
+            Self::InitialRewiring(strategy) => {
+                pragma(INITIAL_REWIRING, vec![], Some(strategy.to_string()))
+            }
+            Self::ReadoutPovm {
+                qubit,
+                matrix: entries,
+            } => pragma(
+                READOUT_POVM,
+                vec![PragmaArgument::Integer(*qubit)],
+                matrix(
+                    entries
+                        .iter()
+                        .map(|entry| Expression::Number((*entry).into()).to_string())
+                        .collect(),
+                ),
+            ),
+            Self::AddKraus {
+                gate,
+                qubits,
+                matrix: entries,
+            } => pragma(
+                ADD_KRAUS,
+                std::iter::once(PragmaArgument::Identifier(gate.clone()))
+                    .chain(qubits.iter().copied().map(PragmaArgument::Integer))
+                    .collect(),
+                matrix(
+                    entries
+                        .iter()
+                        .map(|entry| Expression::Number(*entry).to_string())
+                        .collect(),
+                ),
+            ),
+            Self::PreserveBlock => pragma(PRESERVE_BLOCK, vec![], None),
+            Self::EndPreserveBlock => pragma(END_PRESERVE_BLOCK, vec![], None),
+            Self::CommutingBlocks => pragma(COMMUTING_BLOCKS, vec![], None),
+            Self::EndCommutingBlocks => pragma(END_COMMUTING_BLOCKS, vec![], None),
+            Self::Block => pragma(BLOCK, vec![], None),
+            Self::EndBlock => pragma(END_BLOCK, vec![], None),
+            Self::Extern { name, signature } => pragma(
+                EXTERN,
+                vec![PragmaArgument::Identifier(name.clone())],
+                signature.as_ref().map(ExternSignature::to_string),
+            ),
+            Self::Custom(_) => return None,
+            Self::Other(pragma) => pragma.clone(),
+        })
+    }
+}
+
+/// A function which interprets the pragmas of a namespace as `T`.
+pub type PragmaParser<T> = Box<dyn Fn(&Pragma) -> Result<T, PragmaError> + Send + Sync>;
+
+/// Parsers for the pragmas of namespaces defined outside of this crate, each of which
+/// interprets the pragmas whose names start with its namespace as a [`TypedPragma::Custom`].
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::instruction::{Pragma, PragmaError, PragmaRegistry, TypedPragma};
+///
+/// let mut registry = PragmaRegistry::new();
+/// registry.register("ACME-", |pragma: &Pragma| Ok(pragma.name.len()));
+///
+/// let pragma = Pragma {
+///     name: "ACME-ROCKET".into(),
+///     arguments: vec![],
+///     data: None,
+/// };
+/// assert_eq!(registry.parse(&pragma), Ok(TypedPragma::Custom(11)));
+/// ```
+pub struct PragmaRegistry<T = Infallible> {
+    parsers: Vec<(String, PragmaParser<T>)>,
+}
+
+impl<T> Default for PragmaRegistry<T> {
+    fn default() -> Self {
+        Self { parsers: vec![] }
+    }
+}
+
+impl<T> fmt::Debug for PragmaRegistry<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PragmaRegistry")
+            .field(
+                "namespaces",
+                &self
+                    .parsers
+                    .iter()
+                    .map(|(namespace, _)| namespace)
+                    .collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<T> PragmaRegistry<T> {
+    /// A registry without any parsers, which only interprets the pragmas known to this crate.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Interpret the pragmas whose names start with `namespace` with `parser`, rather than any
+    /// parser for a shorter namespace or the parsers of this crate.
+    pub fn register(
+        &mut self,
+        namespace: impl Into<String>,
+        parser: impl Fn(&Pragma) -> Result<T, PragmaError> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let namespace = namespace.into();
+        self.parsers.retain(|(existing, _)| *existing != namespace);
+        self.parsers.push((namespace, Box::new(parser)));
+        self
+    }
+
+    /// Interpret `pragma` with the parser of the longest namespace its name starts with, or as
+    /// by [`TypedPragma::parse`] if there is none.
+    pub fn parse(&self, pragma: &Pragma) -> Result<TypedPragma<T>, PragmaError> {
+        let parser = self
+            .parsers
+            .iter()
+            .filter(|(namespace, _)| pragma.name.starts_with(namespace.as_str()))
+            .max_by_key(|(namespace, _)| namespace.len());
+        match parser {
+            Some((_, parser)) => parser(pragma).map(TypedPragma::Custom),
+            None => TypedPragma::parse(pragma).map(TypedPragma::into_custom),
+        }
+    }
+}
+
+impl TypedPragma {
+    /// Change the type of custom pragmas, of which there are none.
+    fn into_custom<T>(self) -> TypedPragma<T> {
+        match self {
+            Self::InitialRewiring(strategy) => TypedPragma::InitialRewiring(strategy),
+            Self::ReadoutPovm { qubit, matrix } => TypedPragma::ReadoutPovm { qubit, matrix },
+            Self::AddKraus {
+                gate,
+                qubits,
+                matrix,
+            } => TypedPragma::AddKraus {
+                gate,
+                qubits,
+                matrix,
+            },
+            Self::PreserveBlock => TypedPragma::PreserveBlock,
+            Self::EndPreserveBlock => TypedPragma::EndPreserveBlock,
+            Self::CommutingBlocks => TypedPragma::CommutingBlocks,
+            Self::EndCommutingBlocks => TypedPragma::EndCommutingBlocks,
+            Self::Block => TypedPragma::Block,
+            Self::EndBlock => TypedPragma::EndBlock,
+            Self::Extern { name, signature } => TypedPragma::Extern { name, signature },
+            Self::Custom(never) => match never {},
+            Self::Other(pragma) => TypedPragma::Other(pragma),
+        }
+    }
+}
+
+impl Pragma {
+    /// Interpret the pragma as one of the pragmas known to this crate, as by
+    /// [`TypedPragma::parse`].
+    pub fn to_typed(&self) -> Result<TypedPragma, PragmaError> {
+        TypedPragma::parse(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::{PragmaError, PragmaRegistry, RewiringStrategy, TypedPragma};
+    use crate::instruction::{
+        ExternParameter, ExternParameterType, ExternSignature, Instruction, Pragma, ScalarType,
+    };
+
+    fn pragma(input: &str) -> Pragma {
+        match Instruction::parse(input) {
+            Ok(Instruction::Pragma(pragma)) => pragma,
+            other => panic!("expected a pragma but found {other:?}"),
+        }
+    }
+
+    #[rstest]
+    #[case(
+        "PRAGMA INITIAL_REWIRING \"GREEDY\"",
+        TypedPragma::InitialRewiring(RewiringStrategy::Greedy)
+    )]
+    #[case(
+        "PRAGMA READOUT-POVM 1 \"(0.9 0.2 0.1 0.8)\"",
+        TypedPragma::ReadoutPovm { qubit: 1, matrix: [0.9, 0.2, 0.1, 0.8] }
+    )]
+    #[case(
+        "PRAGMA ADD-KRAUS X 0 \"(0 1i 1 0)\"",
+        TypedPragma::AddKraus {
+            gate: "X".to_string(),
+            qubits: vec![0],
+            matrix: vec![
+                Complex64::new(0.0, 0.0),
+                Complex64::new(0.0, 1.0),
+                Complex64::new(1.0, 0.0),
+                Complex64::new(0.0, 0.0),
+            ],
+        }
+    )]
+    #[case("PRAGMA PRESERVE_BLOCK", TypedPragma::PreserveBlock)]
+    #[case("PRAGMA END_PRESERVE_BLOCK", TypedPragma::EndPreserveBlock)]
+    #[case("PRAGMA COMMUTING_BLOCKS", TypedPragma::CommutingBlocks)]
+    #[case("PRAGMA END_BLOCK", TypedPragma::EndBlock)]
+    #[case(
+        "PRAGMA EXTERN rng \"INTEGER (seed : mut INTEGER)\"",
+        TypedPragma::Extern {
+            name: "rng".to_string(),
+            signature: Some(ExternSignature {
+                return_type: Some(ScalarType::Integer),
+                parameters: vec![ExternParameter {
+                    name: "seed".to_string(),
+                    mutable: true,
+                    data_type: ExternParameterType::Scalar(ScalarType::Integer),
+                }],
+            }),
+        }
+    )]
+    #[case(
+        "PRAGMA EXTERN reset_all",
+        TypedPragma::Extern { name: "reset_all".to_string(), signature: None }
+    )]
+    #[case(
+        "PRAGMA FILTER-NODE q0 \"data\"",
+        TypedPragma::Other(pragma("PRAGMA FILTER-NODE q0 \"data\""))
+    )]
+    fn test_parse(#[case] input: &str, #[case] expected: TypedPragma) {
+        let pragma = pragma(input);
+        let typed = pragma.to_typed().unwrap();
+        assert_eq!(typed, expected);
+        assert_eq!(typed.to_pragma().unwrap().to_typed().unwrap(), typed);
+    }
+
+    #[rstest]
+    #[case("PRAGMA INITIAL_REWIRING \"SIDEWAYS\"")]
+    #[case("PRAGMA INITIAL_REWIRING NAIVE")]
+    #[case("PRAGMA READOUT-POVM q \"(0.9 0.2 0.1 0.8)\"")]
+    #[case("PRAGMA READOUT-POVM 0 \"(0.9 0.2 0.1)\"")]
+    #[case("PRAGMA READOUT-POVM 0 \"(0.9 0.2i 0.1 0.8)\"")]
+    #[case("PRAGMA ADD-KRAUS X 0 1 \"(0 1 1 0)\"")]
+    #[case("PRAGMA ADD-KRAUS X 0 \"0 1 1 0\"")]
+    #[case("PRAGMA ADD-KRAUS X 0 \"(0 one 1 0)\"")]
+    #[case("PRAGMA ADD-KRAUS 0 \"(0 1 1 0)\"")]
+    #[case("PRAGMA PRESERVE_BLOCK 0")]
+    #[case("PRAGMA EXTERN \"REAL\"")]
+    #[case("PRAGMA EXTERN rng \"REAL (seed REAL)\"")]
+    fn test_invalid(#[case] input: &str) {
+        assert!(pragma(input).to_typed().is_err());
+    }
+
+    #[test]
+    fn test_registry() {
+        let mut registry = PragmaRegistry::new();
+        registry
+            .register("ACME", |_: &Pragma| Ok("acme"))
+            .register("ACME-ROCKET", |pragma: &Pragma| {
+                Err(PragmaError::new(pragma, "out of fuel"))
+            })
+            .register("END_BLOCK", |_: &Pragma| Ok("overridden"));
+
+        assert_eq!(
+            registry.parse(&pragma("PRAGMA ACME-ANVIL")),
+            Ok(TypedPragma::Custom("acme"))
+        );
+        assert_eq!(
+            registry.parse(&pragma("PRAGMA ACME-ROCKET")),
+            Err(PragmaError {
+                name: "ACME-ROCKET".to_string(),
+                message: "out of fuel".to_string(),
+            })
+        );
+        assert_eq!(
+            registry.parse(&pragma("PRAGMA END_BLOCK")),
+            Ok(TypedPragma::Custom("overridden"))
+        );
+        assert_eq!(
+            registry.parse(&pragma("PRAGMA BLOCK")),
+            Ok(TypedPragma::Block)
+        );
+        assert_eq!(TypedPragma::Custom("acme").to_pragma(), None);
+    }
+}
@@ -0,0 +1,805 @@
+//! The unitary matrices of gates, for simulating and verifying programs of a few qubits.
+//!
+//! The basis states of `n` qubits are numbered so that qubit `q` is bit `q` of the number: the
+//! state in which only qubit 0 is excited is the second, and that in which only qubit 1 is
+//! excited is the third. Within the matrix of a gate acting on qubits `a b ...`, as written in a
+//! `DEFGATE`, the first qubit is the most significant bit, following the Quil specification.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::f64::consts::FRAC_1_SQRT_2;
+use std::ops::{Index, IndexMut, Mul};
+
+use num_complex::Complex64;
+use thiserror::Error;
+
+use crate::expression::{EvaluationError, Expression};
+use crate::{imag, real};
+
+use super::gates::{check_arity, check_qubits, standard_gate_arity, GateError};
+use super::{Gate, GateDefinition, GateModifier, GateSpecification, PauliGate, PauliSum, Qubit};
+
+/// Why the unitary matrix of a gate could not be computed.
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum UnitaryError {
+    #[error(transparent)]
+    Gate(#[from] GateError),
+
+    #[error("gate {0} is neither a standard gate nor defined")]
+    UnknownGate(String),
+
+    #[error("qubit {0} is a variable, and only fixed qubits can be simulated")]
+    VariableQubit(String),
+
+    #[error("qubit {qubit} is not one of the {qubit_count} qubits simulated")]
+    QubitOutOfRange { qubit: u64, qubit_count: u64 },
+
+    #[error("{0} qubits are too many to simulate")]
+    TooManyQubits(u64),
+
+    #[error("parameter {expression} could not be evaluated: {error}")]
+    Evaluation {
+        expression: Expression,
+        error: EvaluationError,
+    },
+
+    #[error("gate {name} is not a valid definition: {message}")]
+    InvalidDefinition { name: String, message: String },
+}
+
+pub type UnitaryResult<T> = Result<T, UnitaryError>;
+
+/// The most qubits a matrix can be computed for, such that the number of its elements can be
+/// counted.
+pub(crate) const MAX_QUBIT_COUNT: u64 = usize::BITS as u64 / 2 - 1;
+
+/// A square matrix of complex numbers, such as that of a gate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Matrix {
+    size: usize,
+    /// The elements, row by row.
+    elements: Vec<Complex64>,
+}
+
+impl Matrix {
+    /// The matrix of `size` rows in which every element is zero.
+    pub fn zeros(size: usize) -> Self {
+        Self {
+            size,
+            elements: vec![Complex64::default(); size * size],
+        }
+    }
+
+    /// The identity matrix of `size` rows.
+    pub fn identity(size: usize) -> Self {
+        let mut matrix = Self::zeros(size);
+        for index in 0..size {
+            matrix[(index, index)] = real!(1.0);
+        }
+        matrix
+    }
+
+    /// The matrix with the given rows, unless they do not form a square.
+    pub fn from_rows(rows: Vec<Vec<Complex64>>) -> Option<Self> {
+        let size = rows.len();
+        if rows.iter().any(|row| row.len() != size) {
+            return None;
+        }
+        Some(Self {
+            size,
+            elements: rows.into_iter().flatten().collect(),
+        })
+    }
+
+    /// The number of rows, which is also the number of columns.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// The rows of the matrix, in order.
+    pub fn rows(&self) -> impl Iterator<Item = &[Complex64]> {
+        self.elements.chunks(self.size.max(1))
+    }
+
+    /// The conjugate transpose, which is the inverse of a unitary matrix.
+    pub fn dagger(&self) -> Self {
+        let mut result = Self::zeros(self.size);
+        for row in 0..self.size {
+            for column in 0..self.size {
+                result[(column, row)] = self[(row, column)].conj();
+            }
+        }
+        result
+    }
+
+    /// The matrix acting as `self` on the states in which a new most significant control
+    /// qubit is excited, and as the identity otherwise.
+    fn controlled(&self) -> Self {
+        Self::select(&Self::identity(self.size), self)
+    }
+
+    /// The matrix acting as `zero` on the states in which a new most significant qubit is in
+    /// its ground state, and as `one` on those in which it is excited.
+    fn select(zero: &Self, one: &Self) -> Self {
+        let size = zero.size;
+        let mut result = Self::zeros(2 * size);
+        for row in 0..size {
+            for column in 0..size {
+                result[(row, column)] = zero[(row, column)];
+                result[(size + row, size + column)] = one[(row, column)];
+            }
+        }
+        result
+    }
+
+    /// The Kronecker product, in which `self` acts on the more significant qubits.
+    fn kron(&self, other: &Self) -> Self {
+        let size = self.size * other.size;
+        let mut result = Self::zeros(size);
+        for row in 0..size {
+            for column in 0..size {
+                result[(row, column)] = self[(row / other.size, column / other.size)]
+                    * other[(row % other.size, column % other.size)];
+            }
+        }
+        result
+    }
+
+    /// The largest sum of the magnitudes of the elements of a row.
+    fn norm(&self) -> f64 {
+        self.rows()
+            .map(|row| row.iter().map(|element| element.norm()).sum())
+            .fold(0.0, f64::max)
+    }
+
+    /// The exponential of the matrix, by scaling and squaring its Taylor series.
+    fn exp(&self) -> Self {
+        let mut squarings = 0;
+        let mut scaled = self.clone();
+        while scaled.norm() > 0.5 {
+            scaled
+                .elements
+                .iter_mut()
+                .for_each(|element| *element /= 2.0);
+            squarings += 1;
+        }
+
+        let mut result = Self::identity(self.size);
+        let mut term = Self::identity(self.size);
+        for order in 1..=20 {
+            term = &term * &scaled;
+            term.elements
+                .iter_mut()
+                .for_each(|element| *element /= order as f64);
+            for (element, addend) in result.elements.iter_mut().zip(&term.elements) {
+                *element += addend;
+            }
+        }
+        for _ in 0..squarings {
+            result = &result * &result;
+        }
+        result
+    }
+
+    /// Whether every element of `self` is within `tolerance` of that of `other`.
+    pub fn approx_eq(&self, other: &Self, tolerance: f64) -> bool {
+        self.size == other.size
+            && self
+                .elements
+                .iter()
+                .zip(&other.elements)
+                .all(|(a, b)| (a - b).norm() <= tolerance)
+    }
+
+    /// Whether the matrix is unitary, within `tolerance`.
+    pub fn is_unitary(&self, tolerance: f64) -> bool {
+        (&self.dagger() * self).approx_eq(&Self::identity(self.size), tolerance)
+    }
+}
+
+impl Index<(usize, usize)> for Matrix {
+    type Output = Complex64;
+
+    fn index(&self, (row, column): (usize, usize)) -> &Complex64 {
+        &self.elements[row * self.size + column]
+    }
+}
+
+impl IndexMut<(usize, usize)> for Matrix {
+    fn index_mut(&mut self, (row, column): (usize, usize)) -> &mut Complex64 {
+        &mut self.elements[row * self.size + column]
+    }
+}
+
+impl Mul for &Matrix {
+    type Output = Matrix;
+
+    fn mul(self, other: &Matrix) -> Matrix {
+        let mut result = Matrix::zeros(self.size);
+        for row in 0..self.size {
+            for inner in 0..self.size {
+                let left = self[(row, inner)];
+                if left == Complex64::default() {
+                    continue;
+                }
+                for column in 0..self.size {
+                    result[(row, column)] += left * other[(inner, column)];
+                }
+            }
+        }
+        result
+    }
+}
+
+/// The matrix of the standard gate `name`, given the parameters it takes, if it is one.
+fn standard_gate_matrix(name: &str, parameters: &[Complex64]) -> Option<Matrix> {
+    let one = real!(1.0);
+    let zero = Complex64::default();
+    let i = imag!(1.0);
+    let diagonal = |elements: &[Complex64]| {
+        let mut matrix = Matrix::zeros(elements.len());
+        for (index, element) in elements.iter().enumerate() {
+            matrix[(index, index)] = *element;
+        }
+        matrix
+    };
+    let permutation = |permutation: &[usize]| {
+        let mut matrix = Matrix::zeros(permutation.len());
+        for (column, row) in permutation.iter().enumerate() {
+            matrix[(*row, column)] = one;
+        }
+        matrix
+    };
+    let phase = || (i * parameters[0]).exp();
+    let half = || parameters[0] / 2.0;
+    let rows = |rows: [[Complex64; 2]; 2]| Matrix::from_rows(rows.map(Vec::from).to_vec());
+
+    Some(match name {
+        "I" => Matrix::identity(2),
+        "X" => permutation(&[1, 0]),
+        "Y" => rows([[zero, -i], [i, zero]])?,
+        "Z" => diagonal(&[one, -one]),
+        "H" => rows([
+            [real!(FRAC_1_SQRT_2), real!(FRAC_1_SQRT_2)],
+            [real!(FRAC_1_SQRT_2), real!(-FRAC_1_SQRT_2)],
+        ])?,
+        "S" => diagonal(&[one, i]),
+        "T" => diagonal(&[one, Complex64::from_polar(1.0, std::f64::consts::FRAC_PI_4)]),
+        "PHASE" => diagonal(&[one, phase()]),
+        "RX" => rows([
+            [half().cos(), -i * half().sin()],
+            [-i * half().sin(), half().cos()],
+        ])?,
+        "RY" => rows([[half().cos(), -half().sin()], [half().sin(), half().cos()]])?,
+        "RZ" => diagonal(&[(-i * half()).exp(), (i * half()).exp()]),
+        "CZ" => diagonal(&[one, one, one, -one]),
+        "CNOT" => permutation(&[0, 1, 3, 2]),
+        "SWAP" => permutation(&[0, 2, 1, 3]),
+        "ISWAP" => {
+            let mut matrix = diagonal(&[one, zero, zero, one]);
+            matrix[(1, 2)] = i;
+            matrix[(2, 1)] = i;
+            matrix
+        }
+        "CPHASE00" => diagonal(&[phase(), one, one, one]),
+        "CPHASE01" => diagonal(&[one, phase(), one, one]),
+        "CPHASE10" => diagonal(&[one, one, phase(), one]),
+        "CPHASE" => diagonal(&[one, one, one, phase()]),
+        "PSWAP" => {
+            let mut matrix = diagonal(&[one, zero, zero, one]);
+            matrix[(1, 2)] = phase();
+            matrix[(2, 1)] = phase();
+            matrix
+        }
+        "XY" => {
+            let mut matrix = diagonal(&[one, half().cos(), half().cos(), one]);
+            matrix[(1, 2)] = i * half().sin();
+            matrix[(2, 1)] = i * half().sin();
+            matrix
+        }
+        "CCNOT" => permutation(&[0, 1, 2, 3, 4, 5, 7, 6]),
+        "CSWAP" => permutation(&[0, 1, 2, 3, 4, 6, 5, 7]),
+        _ => return None,
+    })
+}
+
+/// The matrix of a single-qubit Pauli operator.
+fn pauli_matrix(pauli: PauliGate) -> Matrix {
+    let name = match pauli {
+        PauliGate::I => "I",
+        PauliGate::X => "X",
+        PauliGate::Y => "Y",
+        PauliGate::Z => "Z",
+    };
+    standard_gate_matrix(name, &[]).expect("Pauli operators are standard gates")
+}
+
+/// The number of qubits acted on by a matrix or permutation of `size` rows, unless it is not a
+/// power of two.
+fn qubits_for_size(size: usize) -> Option<usize> {
+    (size >= 2 && size.is_power_of_two()).then(|| size.trailing_zeros() as usize)
+}
+
+/// Evaluate `expression`, which may refer to `variables` but not to memory.
+fn evaluate(
+    expression: &Expression,
+    variables: &HashMap<String, Complex64>,
+) -> UnitaryResult<Complex64> {
+    expression
+        .evaluate(variables, &HashMap::new())
+        .map_err(|error| UnitaryError::Evaluation {
+            expression: expression.clone(),
+            error,
+        })
+}
+
+impl GateDefinition {
+    /// The numbers of parameters and qubits the gate takes, unless it is not a valid
+    /// definition.
+    fn arity(&self) -> UnitaryResult<(usize, usize)> {
+        let invalid = |message: &str| UnitaryError::InvalidDefinition {
+            name: self.name.to_string(),
+            message: message.to_string(),
+        };
+        let qubits = match &self.specification {
+            GateSpecification::Matrix(rows) => qubits_for_size(rows.len())
+                .ok_or_else(|| invalid("the size of its matrix is not a power of two"))?,
+            GateSpecification::Permutation(permutation) => qubits_for_size(permutation.len())
+                .ok_or_else(|| invalid("the size of its permutation is not a power of two"))?,
+            GateSpecification::PauliSum(PauliSum { arguments, .. }) => arguments.len(),
+        };
+        Ok((self.parameters.len(), qubits))
+    }
+
+    /// The matrix of the gate, with `parameters` given for the parameters of the definition, in
+    /// order. The matrix of a gate defined `AS PAULI-SUM` is the exponential of `-i/2` times
+    /// the sum, so that `ZX(-%theta/2) p q` and `IX(%theta/2) p q` define a controlled `RX`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use num_complex::Complex64;
+    /// use quil_rs::instruction::{GateDefinition, GateSpecification};
+    ///
+    /// let definition = GateDefinition {
+    ///     name: "FLIP".into(),
+    ///     parameters: vec![],
+    ///     specification: GateSpecification::Permutation(vec![1, 0]),
+    /// };
+    /// let matrix = definition.to_unitary(&[]).unwrap();
+    /// assert_eq!(matrix[(0, 1)], Complex64::new(1.0, 0.0));
+    /// assert_eq!(matrix[(0, 0)], Complex64::new(0.0, 0.0));
+    /// ```
+    pub fn to_unitary(&self, parameters: &[Complex64]) -> UnitaryResult<Matrix> {
+        let (parameter_count, _) = self.arity()?;
+        if parameters.len() != parameter_count {
+            return Err(GateError::ParameterCount {
+                name: self.name.to_string(),
+                expected: parameter_count,
+                found: parameters.len(),
+            }
+            .into());
+        }
+        let variables: HashMap<String, Complex64> = self
+            .parameters
+            .iter()
+            .cloned()
+            .zip(parameters.iter().copied())
+            .collect();
+        let invalid = |message: String| UnitaryError::InvalidDefinition {
+            name: self.name.to_string(),
+            message,
+        };
+
+        match &self.specification {
+            GateSpecification::Matrix(rows) => {
+                let rows = rows
+                    .iter()
+                    .map(|row| row.iter().map(|cell| evaluate(cell, &variables)).collect())
+                    .collect::<UnitaryResult<_>>()?;
+                Matrix::from_rows(rows).ok_or_else(|| invalid("its matrix is not square".into()))
+            }
+            GateSpecification::Permutation(permutation) => {
+                let mut matrix = Matrix::zeros(permutation.len());
+                for (column, row) in permutation.iter().enumerate() {
+                    let row = *row as usize;
+                    if row >= permutation.len() {
+                        return Err(invalid(format!("{row} is out of range of its permutation")));
+                    }
+                    if permutation[..column].contains(&(row as u64)) {
+                        return Err(invalid("its permutation maps two states to one".into()));
+                    }
+                    matrix[(row, column)] = real!(1.0);
+                }
+                Ok(matrix)
+            }
+            GateSpecification::PauliSum(PauliSum { arguments, terms }) => {
+                let size = 1 << arguments.len();
+                let mut sum = Matrix::zeros(size);
+                for term in terms {
+                    let mut paulis = vec![PauliGate::I; arguments.len()];
+                    for (pauli, argument) in &term.arguments {
+                        let position = arguments
+                            .iter()
+                            .position(|name| name == argument)
+                            .ok_or_else(|| {
+                                invalid(format!("{argument} is not one of its arguments"))
+                            })?;
+                        paulis[position] = *pauli;
+                    }
+                    let product = paulis
+                        .into_iter()
+                        .map(pauli_matrix)
+                        .fold(Matrix::identity(1), |product, pauli| product.kron(&pauli));
+                    let coefficient = evaluate(&term.expression, &variables)? * imag!(-0.5);
+                    for (element, addend) in sum.elements.iter_mut().zip(&product.elements) {
+                        *element += coefficient * addend;
+                    }
+                }
+                Ok(sum.exp())
+            }
+        }
+    }
+}
+
+impl Gate {
+    /// The matrix of the gate as it acts on `qubit_count` qubits, numbered from 0, with its
+    /// parameters evaluated and its modifiers applied. The gate must be a standard gate, with
+    /// parameters which do not refer to variables or memory; see the [module](self)
+    /// documentation for how basis states are ordered.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use num_complex::Complex64;
+    /// use quil_rs::instruction::Gate;
+    ///
+    /// // X on qubit 1 takes the state |00⟩ to |10⟩, the third basis state.
+    /// let matrix = Gate::x(1).to_unitary(2).unwrap();
+    /// assert_eq!(matrix[(2, 0)], Complex64::new(1.0, 0.0));
+    /// ```
+    pub fn to_unitary(&self, qubit_count: u64) -> UnitaryResult<Matrix> {
+        gate_unitary(self, qubit_count, &HashMap::new())
+    }
+}
+
+/// The matrix of `gate` as it acts on `qubit_count` qubits, as for [`Gate::to_unitary`], except
+/// that it may be one of the `definitions`, keyed by name, which take precedence over the
+/// standard gates.
+pub(crate) fn gate_unitary(
+    gate: &Gate,
+    qubit_count: u64,
+    definitions: &HashMap<&str, &GateDefinition>,
+) -> UnitaryResult<Matrix> {
+    let (matrix, qubits) = local_unitary(gate, qubit_count, definitions)?;
+    Ok(lift(&matrix, &qubits, qubit_count as usize))
+}
+
+/// The matrix of `gate` as it acts on its own qubits, as for [`gate_unitary`], along with the
+/// indices of those qubits, the first of which is the most significant bit of the matrix.
+pub(crate) fn local_unitary(
+    gate: &Gate,
+    qubit_count: u64,
+    definitions: &HashMap<&str, &GateDefinition>,
+) -> UnitaryResult<(Matrix, Vec<usize>)> {
+    if qubit_count > MAX_QUBIT_COUNT {
+        return Err(UnitaryError::TooManyQubits(qubit_count));
+    }
+    check_qubits(&gate.name, &gate.qubits)?;
+    let qubits = gate
+        .qubits
+        .iter()
+        .map(|qubit| match qubit {
+            Qubit::Fixed(index) if *index < qubit_count => Ok(*index as usize),
+            Qubit::Fixed(index) => Err(UnitaryError::QubitOutOfRange {
+                qubit: *index,
+                qubit_count,
+            }),
+            Qubit::Variable(name) => Err(UnitaryError::VariableQubit(name.clone())),
+        })
+        .collect::<UnitaryResult<Vec<_>>>()?;
+    let parameters = gate
+        .parameters
+        .iter()
+        .map(|parameter| evaluate(parameter, &HashMap::new()))
+        .collect::<UnitaryResult<Vec<_>>>()?;
+
+    let definition = definitions.get(gate.name.as_str());
+    let arity = match definition {
+        Some(definition) => definition.arity()?,
+        None => standard_gate_arity(&gate.name)
+            .ok_or_else(|| UnitaryError::UnknownGate(gate.name.to_string()))?,
+    };
+    check_arity(
+        &gate.name,
+        arity,
+        parameters.len(),
+        qubits.len(),
+        &gate.modifiers,
+    )?;
+
+    let base = |parameters: &[Complex64]| match definition {
+        Some(definition) => definition.to_unitary(parameters),
+        None => Ok(standard_gate_matrix(&gate.name, parameters)
+            .expect("the arity of the gate was found among the standard gates")),
+    };
+    let matrix = modified_unitary(&gate.modifiers, &parameters, &base)?;
+    Ok((matrix, qubits))
+}
+
+/// The matrix of a gate with `modifiers`, outermost first, given the matrix of the gate
+/// without them for the given parameters.
+fn modified_unitary(
+    modifiers: &[GateModifier],
+    parameters: &[Complex64],
+    base: &dyn Fn(&[Complex64]) -> UnitaryResult<Matrix>,
+) -> UnitaryResult<Matrix> {
+    let (modifier, modifiers) = match modifiers.split_first() {
+        Some(split) => split,
+        None => return base(parameters),
+    };
+    Ok(match modifier {
+        GateModifier::Controlled => modified_unitary(modifiers, parameters, base)?.controlled(),
+        GateModifier::Dagger => modified_unitary(modifiers, parameters, base)?.dagger(),
+        GateModifier::Forked => {
+            let (zero, one) = parameters.split_at(parameters.len() / 2);
+            Matrix::select(
+                &modified_unitary(modifiers, zero, base)?,
+                &modified_unitary(modifiers, one, base)?,
+            )
+        }
+    })
+}
+
+/// The bits of `index` for `qubits`, the first of which is the most significant.
+fn gate_bits(index: usize, qubits: &[usize]) -> usize {
+    qubits
+        .iter()
+        .fold(0, |bits, qubit| (bits << 1) | ((index >> qubit) & 1))
+}
+
+/// The index in which `qubits` have the values of `bits`, the most significant of which is for
+/// the first qubit, and all other qubits are in their ground states.
+fn spread(bits: usize, qubits: &[usize]) -> usize {
+    qubits
+        .iter()
+        .rev()
+        .enumerate()
+        .fold(0, |index, (bit, qubit)| {
+            index | (((bits >> bit) & 1) << qubit)
+        })
+}
+
+/// The matrix acting as `matrix` on `qubits`, the first of which is its most significant, and as
+/// the identity on the others of `qubit_count` qubits.
+fn lift(matrix: &Matrix, qubits: &[usize], qubit_count: usize) -> Matrix {
+    let mask = spread(matrix.size() - 1, qubits);
+    let size = 1 << qubit_count;
+    let mut result = Matrix::zeros(size);
+    for column in 0..size {
+        let gate_column = gate_bits(column, qubits);
+        let rest = column & !mask;
+        for gate_row in 0..matrix.size() {
+            result[(rest | spread(gate_row, qubits), column)] = matrix[(gate_row, gate_column)];
+        }
+    }
+    result
+}
+
+/// Apply `matrix`, acting on `qubits` as for [`lift`], to `state`, a vector of amplitudes of
+/// each basis state.
+pub(crate) fn apply(matrix: &Matrix, qubits: &[usize], state: &mut [Complex64]) {
+    let mask = spread(matrix.size() - 1, qubits);
+    let indices: Vec<usize> = (0..matrix.size())
+        .map(|bits| spread(bits, qubits))
+        .collect();
+    let mut amplitudes = vec![Complex64::default(); matrix.size()];
+    for rest in (0..state.len()).filter(|index| index & mask == 0) {
+        for (amplitude, index) in amplitudes.iter_mut().zip(&indices) {
+            *amplitude = state[rest | index];
+        }
+        for (row, index) in matrix.rows().zip(&indices) {
+            state[rest | index] = row.iter().zip(&amplitudes).map(|(a, b)| a * b).sum();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::PI;
+
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::{Matrix, UnitaryError};
+    use crate::instruction::gates::GateError;
+    use crate::instruction::{Gate, GateDefinition, GateModifier, Instruction, Qubit};
+    use crate::{imag, real, Program};
+
+    const TOLERANCE: f64 = 1e-10;
+
+    fn gate(input: &str) -> Gate {
+        match Instruction::parse(input).unwrap() {
+            Instruction::Gate(gate) => gate,
+            other => panic!("expected a gate but found {other}"),
+        }
+    }
+
+    fn definition(input: &str) -> GateDefinition {
+        let program: Program = input.parse().unwrap();
+        match &program.instructions[0] {
+            Instruction::GateDefinition(definition) => definition.clone(),
+            other => panic!("expected a gate definition but found {other}"),
+        }
+    }
+
+    fn matrix(rows: &[&[Complex64]]) -> Matrix {
+        Matrix::from_rows(rows.iter().map(|row| row.to_vec()).collect()).unwrap()
+    }
+
+    #[rstest]
+    #[case("I 0")]
+    #[case("X 0")]
+    #[case("Y 0")]
+    #[case("Z 0")]
+    #[case("H 0")]
+    #[case("S 0")]
+    #[case("T 0")]
+    #[case("PHASE(pi/3) 0")]
+    #[case("RX(0.1) 0")]
+    #[case("RY(0.2) 0")]
+    #[case("RZ(0.3) 0")]
+    #[case("CZ 0 1")]
+    #[case("CNOT 1 0")]
+    #[case("SWAP 0 2")]
+    #[case("ISWAP 0 1")]
+    #[case("CPHASE00(0.4) 0 1")]
+    #[case("CPHASE01(0.4) 0 1")]
+    #[case("CPHASE10(0.4) 0 1")]
+    #[case("CPHASE(0.4) 0 1")]
+    #[case("PSWAP(0.5) 0 1")]
+    #[case("XY(0.6) 0 1")]
+    #[case("CCNOT 0 1 2")]
+    #[case("CSWAP 2 0 1")]
+    #[case("DAGGER CONTROLLED RX(0.7) 2 0")]
+    #[case("FORKED RZ(0.1, 0.2) 1 0")]
+    fn test_unitary(#[case] input: &str) {
+        let gate = gate(input);
+        let matrix = gate.to_unitary(3).unwrap();
+        assert_eq!(matrix.size(), 8);
+        assert!(matrix.is_unitary(TOLERANCE));
+
+        let mut inverse = gate.clone();
+        inverse.modifiers.insert(0, GateModifier::Dagger);
+        let product = &inverse.to_unitary(3).unwrap() * &matrix;
+        assert!(product.approx_eq(&Matrix::identity(8), TOLERANCE));
+    }
+
+    #[test]
+    fn test_qubit_order() {
+        // The control of CNOT is its first qubit, and qubit 0 is the least significant bit.
+        let one = real!(1.0);
+        let zero = real!(0.0);
+        let cnot = gate("CNOT 0 1").to_unitary(2).unwrap();
+        let expected = matrix(&[
+            &[one, zero, zero, zero],
+            &[zero, zero, zero, one],
+            &[zero, zero, one, zero],
+            &[zero, one, zero, zero],
+        ]);
+        assert_eq!(cnot, expected);
+        assert_eq!(gate("CONTROLLED X 0 1").to_unitary(2).unwrap(), expected);
+    }
+
+    #[rstest]
+    #[case("RX(pi) 0", "X 0", -imag!(1.0))]
+    #[case("RZ(pi/2) 0", "S 0", Complex64::from_polar(1.0, -PI / 4.0))]
+    #[case("PHASE(pi) 1", "Z 1", real!(1.0))]
+    #[case("CONTROLLED Z 0 1", "CZ 0 1", real!(1.0))]
+    #[case("CONTROLLED CNOT 0 1 2", "CCNOT 0 1 2", real!(1.0))]
+    #[case("CONTROLLED SWAP 2 1 0", "CSWAP 2 1 0", real!(1.0))]
+    #[case("DAGGER S 0", "RZ(-pi/2) 0", Complex64::from_polar(1.0, -PI / 4.0))]
+    #[case("FORKED RX(0, pi) 1 0", "CONTROLLED RX(pi) 1 0", real!(1.0))]
+    #[case("XY(pi) 0 1", "ISWAP 0 1", real!(1.0))]
+    #[case("PSWAP(0) 0 1", "SWAP 0 1", real!(1.0))]
+    fn test_equivalent(#[case] left: &str, #[case] right: &str, #[case] phase: Complex64) {
+        // The matrices are equal up to a global phase, so that `left` is `phase` times `right`.
+        let left = gate(left).to_unitary(3).unwrap();
+        let right = gate(right).to_unitary(3).unwrap();
+        let scaled = Matrix::from_rows(
+            right
+                .rows()
+                .map(|row| row.iter().map(|element| phase * element).collect())
+                .collect(),
+        )
+        .unwrap();
+        assert!(left.approx_eq(&scaled, TOLERANCE), "{left:?}\n{scaled:?}");
+    }
+
+    #[test]
+    fn test_definitions() {
+        let flip = definition("DEFGATE FLIP:\n    0, 1\n    1, 0\n");
+        assert_eq!(
+            flip.to_unitary(&[]).unwrap(),
+            Gate::x(0).to_unitary(1).unwrap()
+        );
+
+        let permutation = definition("DEFGATE CNOT2 AS PERMUTATION:\n    0, 1, 3, 2\n");
+        assert_eq!(
+            permutation.to_unitary(&[]).unwrap(),
+            gate("CNOT 1 0").to_unitary(2).unwrap()
+        );
+
+        let rotation = definition(
+            "DEFGATE ROT(%theta):\n    cos(%theta/2), -i*sin(%theta/2)\n    -i*sin(%theta/2), cos(%theta/2)\n",
+        );
+        assert!(rotation
+            .to_unitary(&[real!(0.3)])
+            .unwrap()
+            .approx_eq(&gate("RX(0.3) 0").to_unitary(1).unwrap(), TOLERANCE));
+
+        let crx = definition(
+            "DEFGATE CRX(%theta) p q AS PAULI-SUM:\n    ZX(-%theta/2) p q\n    IX(%theta/2) p q\n",
+        );
+        assert!(crx.to_unitary(&[real!(0.8)]).unwrap().approx_eq(
+            &gate("CONTROLLED RX(0.8) 1 0").to_unitary(2).unwrap(),
+            TOLERANCE
+        ));
+    }
+
+    #[rstest]
+    #[case("FOO 0", 1, UnitaryError::UnknownGate("FOO".to_string()))]
+    #[case("X q", 1, UnitaryError::VariableQubit("q".to_string()))]
+    #[case("X 3", 2, UnitaryError::QubitOutOfRange { qubit: 3, qubit_count: 2 })]
+    #[case(
+        "CNOT 1 1",
+        2,
+        UnitaryError::Gate(GateError::DuplicateQubit { name: "CNOT".to_string(), qubit: Qubit::Fixed(1) })
+    )]
+    #[case(
+        "RX 0",
+        1,
+        UnitaryError::Gate(GateError::ParameterCount { name: "RX".to_string(), expected: 1, found: 0 })
+    )]
+    #[case(
+        "CONTROLLED X 0",
+        1,
+        UnitaryError::Gate(GateError::QubitCount { name: "X".to_string(), expected: 2, found: 1 })
+    )]
+    #[case("X 0", 64, UnitaryError::TooManyQubits(64))]
+    fn test_invalid(#[case] input: &str, #[case] qubit_count: u64, #[case] expected: UnitaryError) {
+        assert_eq!(gate(input).to_unitary(qubit_count), Err(expected));
+    }
+
+    #[test]
+    fn test_unevaluated_parameter() {
+        assert!(matches!(
+            gate("RX(%theta) 0").to_unitary(1),
+            Err(UnitaryError::Evaluation { .. })
+        ));
+    }
+
+    #[rstest]
+    #[case("DEFGATE BAD:\n    1, 0, 0\n    0, 1, 0\n    0, 0, 1\n")]
+    #[case("DEFGATE BAD AS PERMUTATION:\n    0, 0\n")]
+    #[case("DEFGATE BAD AS PERMUTATION:\n    0, 2\n")]
+    fn test_invalid_definition(#[case] input: &str) {
+        assert!(matches!(
+            definition(input).to_unitary(&[]),
+            Err(UnitaryError::InvalidDefinition { .. })
+        ));
+    }
+}
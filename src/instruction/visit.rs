@@ -0,0 +1,814 @@
+//! Traversing the parts of programs and instructions, to inspect them with an
+//! [`InstructionVisitor`] or change them in place with an [`InstructionRewriter`].
+//!
+//! Each method of the two traits is called on one kind of part, and by default walks the parts
+//! within it with the `walk_` function of the same name. A pass implements the methods for the
+//! parts it acts on, calling the `walk_` function from its own implementation if it should also
+//! reach the parts within them, and every other part is walked over for it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::expression::Expression;
+use crate::Program;
+
+use super::{
+    Arithmetic, ArithmeticOperand, AttributeValue, BinaryLogic, BinaryOperand, Calibration, Call,
+    CallArgument, Capture, CircuitDefinition, Comparison, ComparisonOperand, Convert, Delay,
+    Exchange, Fence, FrameDefinition, FrameIdentifier, Gate, GateDefinition, GateSpecification,
+    Instruction, Jump, JumpUnless, JumpWhen, Label, Load, MeasureCalibrationDefinition,
+    Measurement, MemoryReference, Move, PauliSum, Pulse, Qubit, RawCapture, Reset, SetFrequency,
+    SetPhase, SetScale, ShiftFrequency, ShiftPhase, Store, SwapPhases, UnaryLogic,
+    WaveformDefinition, WaveformInvocation,
+};
+
+/// A pass which inspects the parts of a program.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::instruction::{visit::InstructionVisitor, Qubit};
+/// use quil_rs::Program;
+///
+/// /// Counts the uses of each qubit.
+/// #[derive(Default)]
+/// struct QubitUses(Vec<Qubit>);
+///
+/// impl InstructionVisitor for QubitUses {
+///     fn visit_qubit(&mut self, qubit: &Qubit) {
+///         self.0.push(qubit.clone());
+///     }
+/// }
+///
+/// let program: Program = "H 0\nCNOT 0 1\nMEASURE 1".parse().unwrap();
+/// let mut uses = QubitUses::default();
+/// uses.visit_program(&program);
+/// assert_eq!(uses.0, vec![Qubit::Fixed(0), Qubit::Fixed(0), Qubit::Fixed(1), Qubit::Fixed(1)]);
+/// ```
+pub trait InstructionVisitor {
+    /// Visit every instruction of the program, its headers first, in the order of
+    /// [`Program::to_instructions`].
+    fn visit_program(&mut self, program: &Program) {
+        walk_program(self, program)
+    }
+
+    fn visit_instruction(&mut self, instruction: &Instruction) {
+        walk_instruction(self, instruction)
+    }
+
+    fn visit_gate(&mut self, gate: &Gate) {
+        walk_gate(self, gate)
+    }
+
+    fn visit_frame_identifier(&mut self, frame: &FrameIdentifier) {
+        walk_frame_identifier(self, frame)
+    }
+
+    fn visit_expression(&mut self, expression: &Expression) {
+        walk_expression(self, expression)
+    }
+
+    /// Visit a label defined by `LABEL` or targeted by a jump.
+    fn visit_label(&mut self, _label: &Label) {}
+
+    fn visit_qubit(&mut self, _qubit: &Qubit) {}
+
+    fn visit_memory_reference(&mut self, _memory_reference: &MemoryReference) {}
+}
+
+pub fn walk_program<V: InstructionVisitor + ?Sized>(visitor: &mut V, program: &Program) {
+    for instruction in &program.to_instructions(true) {
+        visitor.visit_instruction(instruction);
+    }
+}
+
+/// Visit the parts of `instruction`, including the instructions within blocks such as those of
+/// a `DEFCAL`.
+pub fn walk_instruction<V: InstructionVisitor + ?Sized>(
+    visitor: &mut V,
+    instruction: &Instruction,
+) {
+    match instruction {
+        Instruction::Arithmetic(Arithmetic {
+            destination,
+            source,
+            ..
+        })
+        | Instruction::Move(Move {
+            destination,
+            source,
+        }) => {
+            visitor.visit_memory_reference(destination);
+            visit_arithmetic_operand(visitor, source);
+        }
+        Instruction::BinaryLogic(BinaryLogic {
+            operands: (destination, source),
+            ..
+        }) => {
+            visitor.visit_memory_reference(destination);
+            if let BinaryOperand::MemoryReference(source) = source {
+                visitor.visit_memory_reference(source);
+            }
+        }
+        Instruction::CalibrationDefinition(Calibration {
+            instructions,
+            parameters,
+            qubits,
+            ..
+        }) => {
+            parameters
+                .iter()
+                .for_each(|parameter| visitor.visit_expression(parameter));
+            qubits.iter().for_each(|qubit| visitor.visit_qubit(qubit));
+            instructions
+                .iter()
+                .for_each(|instruction| visitor.visit_instruction(instruction));
+        }
+        Instruction::Call(Call { arguments, .. }) => {
+            for argument in arguments {
+                if let CallArgument::MemoryReference(reference) = argument {
+                    visitor.visit_memory_reference(reference);
+                }
+            }
+        }
+        Instruction::Capture(Capture {
+            frame,
+            memory_reference,
+            waveform,
+            ..
+        }) => {
+            visitor.visit_frame_identifier(frame);
+            visitor.visit_memory_reference(memory_reference);
+            visit_waveform_invocation(visitor, waveform);
+        }
+        Instruction::CircuitDefinition(CircuitDefinition { instructions, .. }) => instructions
+            .iter()
+            .for_each(|instruction| visitor.visit_instruction(instruction)),
+        Instruction::Convert(Convert { from, to }) => {
+            visitor.visit_memory_reference(to);
+            visitor.visit_memory_reference(from);
+        }
+        Instruction::Comparison(Comparison {
+            operands: (destination, left, right),
+            ..
+        }) => {
+            visitor.visit_memory_reference(destination);
+            visitor.visit_memory_reference(left);
+            if let ComparisonOperand::MemoryReference(right) = right {
+                visitor.visit_memory_reference(right);
+            }
+        }
+        Instruction::Delay(Delay {
+            duration, qubits, ..
+        }) => {
+            visitor.visit_expression(duration);
+            qubits.iter().for_each(|qubit| visitor.visit_qubit(qubit));
+        }
+        Instruction::Exchange(Exchange { left, right }) => {
+            visitor.visit_memory_reference(left);
+            visitor.visit_memory_reference(right);
+        }
+        Instruction::Fence(Fence { qubits }) => {
+            qubits.iter().for_each(|qubit| visitor.visit_qubit(qubit))
+        }
+        Instruction::FrameDefinition(FrameDefinition {
+            identifier,
+            attributes,
+        }) => {
+            visitor.visit_frame_identifier(identifier);
+            for value in attributes.values() {
+                if let AttributeValue::Expression(expression) = value {
+                    visitor.visit_expression(expression);
+                }
+            }
+        }
+        Instruction::Gate(gate) => visitor.visit_gate(gate),
+        Instruction::GateDefinition(GateDefinition { specification, .. }) => match specification {
+            GateSpecification::Matrix(matrix) => matrix
+                .iter()
+                .flatten()
+                .for_each(|element| visitor.visit_expression(element)),
+            GateSpecification::Permutation(_) => {}
+            GateSpecification::PauliSum(PauliSum { terms, .. }) => terms
+                .iter()
+                .for_each(|term| visitor.visit_expression(&term.expression)),
+        },
+        Instruction::Jump(Jump { target }) => visitor.visit_label(target),
+        Instruction::JumpUnless(JumpUnless { target, condition })
+        | Instruction::JumpWhen(JumpWhen { target, condition }) => {
+            visitor.visit_label(target);
+            visitor.visit_memory_reference(condition);
+        }
+        Instruction::Label(label) => visitor.visit_label(label),
+        Instruction::Load(Load {
+            destination,
+            offset,
+            ..
+        }) => {
+            visitor.visit_memory_reference(destination);
+            visitor.visit_memory_reference(offset);
+        }
+        Instruction::MeasureCalibrationDefinition(MeasureCalibrationDefinition {
+            qubit,
+            instructions,
+            ..
+        }) => {
+            if let Some(qubit) = qubit {
+                visitor.visit_qubit(qubit);
+            }
+            instructions
+                .iter()
+                .for_each(|instruction| visitor.visit_instruction(instruction));
+        }
+        Instruction::Measurement(Measurement { qubit, target }) => {
+            visitor.visit_qubit(qubit);
+            if let Some(target) = target {
+                visitor.visit_memory_reference(target);
+            }
+        }
+        Instruction::Pulse(Pulse {
+            frame, waveform, ..
+        }) => {
+            visitor.visit_frame_identifier(frame);
+            visit_waveform_invocation(visitor, waveform);
+        }
+        Instruction::RawCapture(RawCapture {
+            frame,
+            duration,
+            memory_reference,
+            ..
+        }) => {
+            visitor.visit_frame_identifier(frame);
+            visitor.visit_expression(duration);
+            visitor.visit_memory_reference(memory_reference);
+        }
+        Instruction::Reset(Reset { qubit }) => {
+            if let Some(qubit) = qubit {
+                visitor.visit_qubit(qubit);
+            }
+        }
+        Instruction::SetFrequency(SetFrequency {
+            frame,
+            frequency: expression,
+        })
+        | Instruction::SetPhase(SetPhase {
+            frame,
+            phase: expression,
+        })
+        | Instruction::SetScale(SetScale {
+            frame,
+            scale: expression,
+        })
+        | Instruction::ShiftFrequency(ShiftFrequency {
+            frame,
+            frequency: expression,
+        })
+        | Instruction::ShiftPhase(ShiftPhase {
+            frame,
+            phase: expression,
+        }) => {
+            visitor.visit_frame_identifier(frame);
+            visitor.visit_expression(expression);
+        }
+        Instruction::Store(Store { offset, source, .. }) => {
+            visitor.visit_memory_reference(offset);
+            visit_arithmetic_operand(visitor, source);
+        }
+        Instruction::SwapPhases(SwapPhases { frame_1, frame_2 }) => {
+            visitor.visit_frame_identifier(frame_1);
+            visitor.visit_frame_identifier(frame_2);
+        }
+        Instruction::UnaryLogic(UnaryLogic { operand, .. }) => {
+            visitor.visit_memory_reference(operand)
+        }
+        Instruction::WaveformDefinition(WaveformDefinition { definition, .. }) => definition
+            .matrix
+            .iter()
+            .for_each(|element| visitor.visit_expression(element)),
+        Instruction::Declaration(_)
+        | Instruction::Halt
+        | Instruction::Include(_)
+        | Instruction::Nop
+        | Instruction::Pragma(_)
+        | Instruction::Wait => {}
+    }
+}
+
+pub fn walk_gate<V: InstructionVisitor + ?Sized>(visitor: &mut V, gate: &Gate) {
+    gate.parameters
+        .iter()
+        .for_each(|parameter| visitor.visit_expression(parameter));
+    gate.qubits
+        .iter()
+        .for_each(|qubit| visitor.visit_qubit(qubit));
+}
+
+pub fn walk_frame_identifier<V: InstructionVisitor + ?Sized>(
+    visitor: &mut V,
+    frame: &FrameIdentifier,
+) {
+    frame
+        .qubits
+        .iter()
+        .for_each(|qubit| visitor.visit_qubit(qubit));
+}
+
+/// Visit the expressions nested within `expression`, and the memory it reads.
+pub fn walk_expression<V: InstructionVisitor + ?Sized>(visitor: &mut V, expression: &Expression) {
+    match expression {
+        Expression::Address(reference) => visitor.visit_memory_reference(reference),
+        Expression::CustomFunctionCall { arguments, .. } => arguments
+            .iter()
+            .for_each(|argument| visitor.visit_expression(argument)),
+        Expression::FunctionCall { expression, .. } | Expression::Prefix { expression, .. } => {
+            visitor.visit_expression(expression)
+        }
+        Expression::Infix { left, right, .. } => {
+            visitor.visit_expression(left);
+            visitor.visit_expression(right);
+        }
+        Expression::Number(_) | Expression::PiConstant | Expression::Variable(_) => {}
+    }
+}
+
+fn visit_arithmetic_operand<V: InstructionVisitor + ?Sized>(
+    visitor: &mut V,
+    operand: &ArithmeticOperand,
+) {
+    if let ArithmeticOperand::MemoryReference(reference) = operand {
+        visitor.visit_memory_reference(reference);
+    }
+}
+
+fn visit_waveform_invocation<V: InstructionVisitor + ?Sized>(
+    visitor: &mut V,
+    waveform: &WaveformInvocation,
+) {
+    waveform
+        .parameters
+        .values()
+        .for_each(|parameter| visitor.visit_expression(parameter));
+}
+
+/// A pass which changes the parts of a program in place. The parts are walked in the same order
+/// as by an [`InstructionVisitor`].
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::instruction::{visit::{walk_gate_mut, InstructionRewriter}, Gate};
+/// use quil_rs::Program;
+///
+/// /// Writes `CNOT` gates as `CX`.
+/// struct RenameCnot;
+///
+/// impl InstructionRewriter for RenameCnot {
+///     fn rewrite_gate(&mut self, gate: &mut Gate) {
+///         if gate.name == "CNOT" {
+///             gate.name = "CX".into();
+///         }
+///         walk_gate_mut(self, gate);
+///     }
+/// }
+///
+/// let mut program: Program = "H 0\nCNOT 0 1".parse().unwrap();
+/// RenameCnot.rewrite_program(&mut program);
+/// assert_eq!(program.to_string(true), "H 0\nCX 0 1\n");
+/// ```
+pub trait InstructionRewriter {
+    /// Rewrite every instruction of the program, its headers included. The program is rebuilt
+    /// from the rewritten instructions, as by [`Program::from_instructions`], and so no longer
+    /// records the text it was parsed from.
+    fn rewrite_program(&mut self, program: &mut Program) {
+        walk_program_mut(self, program)
+    }
+
+    fn rewrite_instruction(&mut self, instruction: &mut Instruction) {
+        walk_instruction_mut(self, instruction)
+    }
+
+    fn rewrite_gate(&mut self, gate: &mut Gate) {
+        walk_gate_mut(self, gate)
+    }
+
+    fn rewrite_frame_identifier(&mut self, frame: &mut FrameIdentifier) {
+        walk_frame_identifier_mut(self, frame)
+    }
+
+    fn rewrite_expression(&mut self, expression: &mut Expression) {
+        walk_expression_mut(self, expression)
+    }
+
+    /// Rewrite a label defined by `LABEL` or targeted by a jump.
+    fn rewrite_label(&mut self, _label: &mut Label) {}
+
+    fn rewrite_qubit(&mut self, _qubit: &mut Qubit) {}
+
+    fn rewrite_memory_reference(&mut self, _memory_reference: &mut MemoryReference) {}
+}
+
+pub fn walk_program_mut<R: InstructionRewriter + ?Sized>(rewriter: &mut R, program: &mut Program) {
+    let mut instructions = program.to_instructions(true);
+    instructions
+        .iter_mut()
+        .for_each(|instruction| rewriter.rewrite_instruction(instruction));
+    *program = Program::from_instructions(instructions);
+}
+
+/// Rewrite the parts of `instruction`, including the instructions within blocks such as those
+/// of a `DEFCAL`.
+pub fn walk_instruction_mut<R: InstructionRewriter + ?Sized>(
+    rewriter: &mut R,
+    instruction: &mut Instruction,
+) {
+    match instruction {
+        Instruction::Arithmetic(Arithmetic {
+            destination,
+            source,
+            ..
+        })
+        | Instruction::Move(Move {
+            destination,
+            source,
+        }) => {
+            rewriter.rewrite_memory_reference(destination);
+            rewrite_arithmetic_operand(rewriter, source);
+        }
+        Instruction::BinaryLogic(BinaryLogic {
+            operands: (destination, source),
+            ..
+        }) => {
+            rewriter.rewrite_memory_reference(destination);
+            if let BinaryOperand::MemoryReference(source) = source {
+                rewriter.rewrite_memory_reference(source);
+            }
+        }
+        Instruction::CalibrationDefinition(Calibration {
+            instructions,
+            parameters,
+            qubits,
+            ..
+        }) => {
+            parameters
+                .iter_mut()
+                .for_each(|parameter| rewriter.rewrite_expression(parameter));
+            qubits
+                .iter_mut()
+                .for_each(|qubit| rewriter.rewrite_qubit(qubit));
+            instructions
+                .iter_mut()
+                .for_each(|instruction| rewriter.rewrite_instruction(instruction));
+        }
+        Instruction::Call(Call { arguments, .. }) => {
+            for argument in arguments {
+                if let CallArgument::MemoryReference(reference) = argument {
+                    rewriter.rewrite_memory_reference(reference);
+                }
+            }
+        }
+        Instruction::Capture(Capture {
+            frame,
+            memory_reference,
+            waveform,
+            ..
+        }) => {
+            rewriter.rewrite_frame_identifier(frame);
+            rewriter.rewrite_memory_reference(memory_reference);
+            rewrite_waveform_invocation(rewriter, waveform);
+        }
+        Instruction::CircuitDefinition(CircuitDefinition { instructions, .. }) => instructions
+            .iter_mut()
+            .for_each(|instruction| rewriter.rewrite_instruction(instruction)),
+        Instruction::Convert(Convert { from, to }) => {
+            rewriter.rewrite_memory_reference(to);
+            rewriter.rewrite_memory_reference(from);
+        }
+        Instruction::Comparison(Comparison {
+            operands: (destination, left, right),
+            ..
+        }) => {
+            rewriter.rewrite_memory_reference(destination);
+            rewriter.rewrite_memory_reference(left);
+            if let ComparisonOperand::MemoryReference(right) = right {
+                rewriter.rewrite_memory_reference(right);
+            }
+        }
+        Instruction::Delay(Delay {
+            duration, qubits, ..
+        }) => {
+            rewriter.rewrite_expression(duration);
+            qubits
+                .iter_mut()
+                .for_each(|qubit| rewriter.rewrite_qubit(qubit));
+        }
+        Instruction::Exchange(Exchange { left, right }) => {
+            rewriter.rewrite_memory_reference(left);
+            rewriter.rewrite_memory_reference(right);
+        }
+        Instruction::Fence(Fence { qubits }) => qubits
+            .iter_mut()
+            .for_each(|qubit| rewriter.rewrite_qubit(qubit)),
+        Instruction::FrameDefinition(FrameDefinition {
+            identifier,
+            attributes,
+        }) => {
+            rewriter.rewrite_frame_identifier(identifier);
+            for value in attributes.values_mut() {
+                if let AttributeValue::Expression(expression) = value {
+                    rewriter.rewrite_expression(expression);
+                }
+            }
+        }
+        Instruction::Gate(gate) => rewriter.rewrite_gate(gate),
+        Instruction::GateDefinition(GateDefinition { specification, .. }) => match specification {
+            GateSpecification::Matrix(matrix) => matrix
+                .iter_mut()
+                .flatten()
+                .for_each(|element| rewriter.rewrite_expression(element)),
+            GateSpecification::Permutation(_) => {}
+            GateSpecification::PauliSum(PauliSum { terms, .. }) => terms
+                .iter_mut()
+                .for_each(|term| rewriter.rewrite_expression(&mut term.expression)),
+        },
+        Instruction::Jump(Jump { target }) => rewriter.rewrite_label(target),
+        Instruction::JumpUnless(JumpUnless { target, condition })
+        | Instruction::JumpWhen(JumpWhen { target, condition }) => {
+            rewriter.rewrite_label(target);
+            rewriter.rewrite_memory_reference(condition);
+        }
+        Instruction::Label(label) => rewriter.rewrite_label(label),
+        Instruction::Load(Load {
+            destination,
+            offset,
+            ..
+        }) => {
+            rewriter.rewrite_memory_reference(destination);
+            rewriter.rewrite_memory_reference(offset);
+        }
+        Instruction::MeasureCalibrationDefinition(MeasureCalibrationDefinition {
+            qubit,
+            instructions,
+            ..
+        }) => {
+            if let Some(qubit) = qubit {
+                rewriter.rewrite_qubit(qubit);
+            }
+            instructions
+                .iter_mut()
+                .for_each(|instruction| rewriter.rewrite_instruction(instruction));
+        }
+        Instruction::Measurement(Measurement { qubit, target }) => {
+            rewriter.rewrite_qubit(qubit);
+            if let Some(target) = target {
+                rewriter.rewrite_memory_reference(target);
+            }
+        }
+        Instruction::Pulse(Pulse {
+            frame, waveform, ..
+        }) => {
+            rewriter.rewrite_frame_identifier(frame);
+            rewrite_waveform_invocation(rewriter, waveform);
+        }
+        Instruction::RawCapture(RawCapture {
+            frame,
+            duration,
+            memory_reference,
+            ..
+        }) => {
+            rewriter.rewrite_frame_identifier(frame);
+            rewriter.rewrite_expression(duration);
+            rewriter.rewrite_memory_reference(memory_reference);
+        }
+        Instruction::Reset(Reset { qubit }) => {
+            if let Some(qubit) = qubit {
+                rewriter.rewrite_qubit(qubit);
+            }
+        }
+        Instruction::SetFrequency(SetFrequency {
+            frame,
+            frequency: expression,
+        })
+        | Instruction::SetPhase(SetPhase {
+            frame,
+            phase: expression,
+        })
+        | Instruction::SetScale(SetScale {
+            frame,
+            scale: expression,
+        })
+        | Instruction::ShiftFrequency(ShiftFrequency {
+            frame,
+            frequency: expression,
+        })
+        | Instruction::ShiftPhase(ShiftPhase {
+            frame,
+            phase: expression,
+        }) => {
+            rewriter.rewrite_frame_identifier(frame);
+            rewriter.rewrite_expression(expression);
+        }
+        Instruction::Store(Store { offset, source, .. }) => {
+            rewriter.rewrite_memory_reference(offset);
+            rewrite_arithmetic_operand(rewriter, source);
+        }
+        Instruction::SwapPhases(SwapPhases { frame_1, frame_2 }) => {
+            rewriter.rewrite_frame_identifier(frame_1);
+            rewriter.rewrite_frame_identifier(frame_2);
+        }
+        Instruction::UnaryLogic(UnaryLogic { operand, .. }) => {
+            rewriter.rewrite_memory_reference(operand)
+        }
+        Instruction::WaveformDefinition(WaveformDefinition { definition, .. }) => definition
+            .matrix
+            .iter_mut()
+            .for_each(|element| rewriter.rewrite_expression(element)),
+        Instruction::Declaration(_)
+        | Instruction::Halt
+        | Instruction::Include(_)
+        | Instruction::Nop
+        | Instruction::Pragma(_)
+        | Instruction::Wait => {}
+    }
+}
+
+pub fn walk_gate_mut<R: InstructionRewriter + ?Sized>(rewriter: &mut R, gate: &mut Gate) {
+    gate.parameters
+        .iter_mut()
+        .for_each(|parameter| rewriter.rewrite_expression(parameter));
+    gate.qubits
+        .iter_mut()
+        .for_each(|qubit| rewriter.rewrite_qubit(qubit));
+}
+
+pub fn walk_frame_identifier_mut<R: InstructionRewriter + ?Sized>(
+    rewriter: &mut R,
+    frame: &mut FrameIdentifier,
+) {
+    frame
+        .qubits
+        .iter_mut()
+        .for_each(|qubit| rewriter.rewrite_qubit(qubit));
+}
+
+/// Rewrite the expressions nested within `expression`, and the memory it reads.
+pub fn walk_expression_mut<R: InstructionRewriter + ?Sized>(
+    rewriter: &mut R,
+    expression: &mut Expression,
+) {
+    match expression {
+        Expression::Address(reference) => rewriter.rewrite_memory_reference(reference),
+        Expression::CustomFunctionCall { arguments, .. } => arguments
+            .iter_mut()
+            .for_each(|argument| rewriter.rewrite_expression(argument)),
+        Expression::FunctionCall { expression, .. } | Expression::Prefix { expression, .. } => {
+            rewriter.rewrite_expression(expression)
+        }
+        Expression::Infix { left, right, .. } => {
+            rewriter.rewrite_expression(left);
+            rewriter.rewrite_expression(right);
+        }
+        Expression::Number(_) | Expression::PiConstant | Expression::Variable(_) => {}
+    }
+}
+
+fn rewrite_arithmetic_operand<R: InstructionRewriter + ?Sized>(
+    rewriter: &mut R,
+    operand: &mut ArithmeticOperand,
+) {
+    if let ArithmeticOperand::MemoryReference(reference) = operand {
+        rewriter.rewrite_memory_reference(reference);
+    }
+}
+
+fn rewrite_waveform_invocation<R: InstructionRewriter + ?Sized>(
+    rewriter: &mut R,
+    waveform: &mut WaveformInvocation,
+) {
+    waveform
+        .parameters
+        .values_mut()
+        .for_each(|parameter| rewriter.rewrite_expression(parameter));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{walk_expression_mut, InstructionRewriter, InstructionVisitor};
+    use crate::expression::Expression;
+    use crate::instruction::{MemoryReference, Qubit};
+    use crate::Program;
+
+    const PROGRAM: &str = r#"DECLARE theta REAL[2]
+DEFFRAME 1 "rf":
+    CENTER-FREQUENCY: 2*pi
+DEFCAL RX(%angle) 1:
+    SHIFT-PHASE 1 "rf" %angle
+DEFCIRCUIT BELL a b:
+    H a
+    CNOT a b
+
+RX(theta[0] + %phi) 1
+BELL 0 1
+MEASURE 0 theta[1]
+DELAY 0 "rf" %phi
+"#;
+
+    /// Collects the qubits, memory references, and variables it visits, in order.
+    #[derive(Default)]
+    struct Collector {
+        qubits: Vec<Qubit>,
+        memory_references: Vec<MemoryReference>,
+        variables: Vec<String>,
+    }
+
+    impl InstructionVisitor for Collector {
+        fn visit_expression(&mut self, expression: &Expression) {
+            if let Expression::Variable(variable) = expression {
+                self.variables.push(variable.clone());
+            }
+            super::walk_expression(self, expression);
+        }
+
+        fn visit_qubit(&mut self, qubit: &Qubit) {
+            self.qubits.push(qubit.clone());
+        }
+
+        fn visit_memory_reference(&mut self, memory_reference: &MemoryReference) {
+            self.memory_references.push(memory_reference.clone());
+        }
+    }
+
+    #[test]
+    fn test_visitor() {
+        let program: Program = PROGRAM.parse().unwrap();
+        let mut collector = Collector::default();
+        collector.visit_program(&program);
+
+        let qubits: Vec<String> = collector.qubits.iter().map(Qubit::to_string).collect();
+        assert_eq!(
+            qubits,
+            ["1", "1", "1", "a", "a", "b", "1", "0", "1", "0", "0"]
+        );
+        let memory_references: Vec<String> = collector
+            .memory_references
+            .iter()
+            .map(MemoryReference::to_string)
+            .collect();
+        assert_eq!(memory_references, ["theta[0]", "theta[1]"]);
+        assert_eq!(collector.variables, ["angle", "angle", "phi", "phi"]);
+    }
+
+    /// Renumbers qubits, and replaces variables with values.
+    struct Rewriter {
+        qubits: HashMap<u64, u64>,
+        values: HashMap<&'static str, f64>,
+    }
+
+    impl InstructionRewriter for Rewriter {
+        fn rewrite_expression(&mut self, expression: &mut Expression) {
+            match expression {
+                Expression::Variable(variable) if self.values.contains_key(variable.as_str()) => {
+                    *expression = Expression::from(self.values[variable.as_str()]);
+                }
+                _ => walk_expression_mut(self, expression),
+            }
+        }
+
+        fn rewrite_qubit(&mut self, qubit: &mut Qubit) {
+            if let Qubit::Fixed(index) = qubit {
+                *index = self.qubits.get(index).copied().unwrap_or(*index);
+            }
+        }
+    }
+
+    #[test]
+    fn test_rewriter() {
+        let mut program: Program = PROGRAM.parse().unwrap();
+        let mut rewriter = Rewriter {
+            qubits: HashMap::from([(0, 5), (1, 6)]),
+            values: HashMap::from([("phi", 0.5)]),
+        };
+        rewriter.rewrite_program(&mut program);
+
+        let expected: Program = PROGRAM
+            .replace(" 0", " 5")
+            .replace(" 1", " 6")
+            .replace("%phi", "0.5")
+            .parse()
+            .unwrap();
+        assert_eq!(program, expected);
+        assert_eq!(program.get_source_text(0), None);
+    }
+}
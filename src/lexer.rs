@@ -0,0 +1,164 @@
+//! The tokens of Quil text, for tools such as syntax highlighters which read Quil without
+//! parsing it into instructions.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use nom_locate::LocatedSpan;
+
+pub use crate::parser::{Command, DataType, LexError, LexErrorKind, Modifier, Operator, Token};
+use crate::program::{SourceLocation, SourceSpan};
+
+/// The broad categories of tokens, such as a highlighter might color alike.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum TokenKind {
+    /// A reserved word, such as `DEFGATE`, `DAGGER`, or `BIT`.
+    Keyword,
+    /// The name of a gate, frame, memory region, or qubit variable.
+    Identifier,
+    /// An integer or real number.
+    Number,
+    /// A quoted string.
+    String,
+    /// A comment, from `#` to the end of the line.
+    Comment,
+    /// A label, such as `@loop`.
+    Label,
+    /// A parameter variable, such as `%theta`.
+    Variable,
+    /// An arithmetic operator.
+    Operator,
+    /// Brackets, parentheses, and separators.
+    Punctuation,
+    /// The indentation of a line within a block.
+    Indentation,
+    /// The end of a line.
+    NewLine,
+}
+
+impl From<&Token> for TokenKind {
+    fn from(token: &Token) -> Self {
+        match token {
+            Token::As
+            | Token::Command(_)
+            | Token::DataType(_)
+            | Token::Matrix
+            | Token::Modifier(_)
+            | Token::NonBlocking
+            | Token::PauliSum
+            | Token::Permutation
+            | Token::Sharing => Self::Keyword,
+            Token::Identifier(_) => Self::Identifier,
+            Token::Float(_) | Token::Integer(_) => Self::Number,
+            Token::String(_) => Self::String,
+            Token::Comment(_) => Self::Comment,
+            Token::Label(_) => Self::Label,
+            Token::Variable(_) => Self::Variable,
+            Token::Operator(_) => Self::Operator,
+            Token::Colon
+            | Token::Comma
+            | Token::LBracket
+            | Token::LParenthesis
+            | Token::RBracket
+            | Token::RParenthesis
+            | Token::Semicolon => Self::Punctuation,
+            Token::Indentation => Self::Indentation,
+            Token::NewLine => Self::NewLine,
+        }
+    }
+}
+
+/// A token, along with the span of the text it was lexed from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: SourceSpan,
+}
+
+impl SpannedToken {
+    /// The category of the token.
+    pub fn kind(&self) -> TokenKind {
+        TokenKind::from(&self.token)
+    }
+}
+
+/// Split `input` into tokens, in order. Spaces between tokens are skipped, but the indentation
+/// of lines within blocks and the ends of lines are tokens of their own, as are comments. A
+/// single [`Token::NewLine`] spans the end of a line along with any blank lines after it.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::lexer::{lex, TokenKind};
+///
+/// let tokens = lex("RX(pi/2) 0 # rotate").unwrap();
+/// let kinds: Vec<_> = tokens.iter().map(|token| token.kind()).collect();
+/// assert_eq!(kinds[..3], [TokenKind::Identifier, TokenKind::Punctuation, TokenKind::Identifier]);
+/// assert_eq!(kinds.last(), Some(&TokenKind::Comment));
+/// assert_eq!(tokens[0].span.to_string(), "1:1-1:3");
+/// ```
+pub fn lex(input: &str) -> Result<Vec<SpannedToken>, LexError> {
+    let tokens = crate::parser::lex(LocatedSpan::new(input))?;
+    // Each location is found by advancing past the text since the last token, rather than from
+    // the start of its line, so that lexing long lines takes linear time.
+    let mut location = SourceLocation::default();
+    Ok(tokens
+        .into_iter()
+        .map(|token| {
+            let start = location.advance(&input[location.offset..token.offset()]);
+            let end = start.advance(token.text());
+            location = end;
+            SpannedToken {
+                token: token.into_token(),
+                span: SourceSpan { start, end },
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lex, Command, Token, TokenKind};
+
+    #[test]
+    fn test_lex() {
+        let input = "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1 # entangle\nPULSE 0 \"rf\" flat(duration: 1e-6)";
+        let tokens = lex(input).unwrap();
+        for token in &tokens {
+            let text = &input[token.span.range()];
+            match &token.token {
+                Token::Comment(comment) => assert_eq!(text, format!("#{comment}")),
+                Token::Indentation => assert_eq!(text, "    "),
+                Token::NewLine => assert!(text.contains('\n') && text.trim().is_empty()),
+                Token::String(string) => assert_eq!(text, format!("\"{string}\"")),
+                Token::Float(_) => assert_eq!(text, "1e-6"),
+                other => assert_eq!(text, other.to_string()),
+            }
+        }
+
+        assert_eq!(tokens[0].token, Token::Command(Command::DefCircuit));
+        assert_eq!(tokens[0].kind(), TokenKind::Keyword);
+        let comment = tokens
+            .iter()
+            .find(|token| token.kind() == TokenKind::Comment)
+            .unwrap();
+        assert_eq!(comment.span.to_string(), "5:10-5:20");
+    }
+
+    #[test]
+    fn test_lex_error() {
+        let error = lex("H 0\nX 1 $").unwrap_err();
+        assert_eq!((error.line(), error.column()), (2, 5));
+    }
+}
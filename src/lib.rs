@@ -19,8 +19,16 @@
 //!
 //! * Builder utilities for Quil [programs], [instructions], and [expressions]
 //! * A [parser] and [serializer] for converting Quil to and from text strings
+//! * A [lexer] for splitting Quil text into tokens
+//! * A `quil!` macro, in the `quil-macros` crate, for embedding Quil programs in Rust code which
+//!   are parsed when it is compiled
+//! * Entry points for [fuzzing] the parser, and, with the `arbitrary` feature, arbitrary
+//!   instructions and programs for fuzzing code which consumes them
+//! * With the `serde` feature, serialization of programs, instructions, and expressions to and
+//!   from formats such as JSON
 //! * A [constructor for timing graphs], for understanding and debugging Quil-T
 //!   pulse control programs
+//! * A [LaTeX circuit diagram generator], for drawing Quil programs with Quantikz
 //!
 //! This crate is still early in its development and does not fully support all
 //! Quil features, nor claim a stable API. Prior to `v1.0`, minor-version changes
@@ -29,14 +37,19 @@
 //! [changelog](https://github.com/rigetti/quil-rust/releases) when upgrading.
 //!
 //! [constructor for timing graphs]: crate::program::graph::ScheduledProgram#method.get_dot_format
+//! [LaTeX circuit diagram generator]: crate::program::latex::Latex
 //! [expressions]: crate::expression::Expression
+//! [fuzzing]: crate::fuzz
 //! [instructions]: crate::instruction::Instruction
+//! [lexer]: crate::lexer::lex
 //! [parser]: crate::program::Program#method.from_str
 //! [programs]: crate::program::Program
 //! [serializer]: crate::program::Program#method.to_string
 
 pub mod expression;
+pub mod fuzz;
 pub mod instruction;
+pub mod lexer;
 mod macros;
 pub(crate) mod parser;
 pub mod program;
@@ -14,23 +14,23 @@
 
 use nom::{
     branch::alt,
-    combinator::{map, opt},
+    combinator::{map, opt, peek, verify},
     multi::{many0, many1, separated_list0, separated_list1},
-    sequence::{delimited, pair, preceded, tuple},
+    sequence::{delimited, pair, preceded, terminated, tuple},
 };
 
 use crate::{
-    instruction::{Convert, GateSpecification, GateType, Include, PragmaArgument},
+    instruction::{Convert, GateSpecification, GateType, Include, PauliSum, PragmaArgument},
     parser::common::parse_variable_qubit,
 };
 
 use crate::instruction::{
-    Arithmetic, ArithmeticOperand, ArithmeticOperator, BinaryLogic, BinaryOperator, Calibration,
-    Capture, CircuitDefinition, Comparison, ComparisonOperator, Declaration, Delay, Exchange,
-    Fence, FrameDefinition, GateDefinition, Instruction, Jump, JumpUnless, JumpWhen, Label, Load,
-    MeasureCalibrationDefinition, Measurement, Move, Pragma, Pulse, RawCapture, Reset,
-    SetFrequency, SetPhase, SetScale, ShiftFrequency, ShiftPhase, Store, UnaryLogic, UnaryOperator,
-    Waveform, WaveformDefinition,
+    Arithmetic, ArithmeticOperator, BinaryLogic, BinaryOperator, Calibration, Call, Capture,
+    CircuitDefinition, Comparison, ComparisonOperator, Declaration, Delay, Exchange, Fence,
+    FrameDefinition, GateDefinition, Instruction, Jump, JumpUnless, JumpWhen, Label, Load,
+    MeasureCalibrationDefinition, Measurement, Move, Offset, Pragma, Pulse, Qubit, RawCapture,
+    Reset, SetFrequency, SetPhase, SetScale, Sharing, ShiftFrequency, ShiftPhase, Store,
+    UnaryLogic, UnaryOperator, Waveform, WaveformDefinition,
 };
 use crate::parser::instruction::parse_block;
 use crate::parser::InternalParserResult;
@@ -38,10 +38,10 @@ use crate::token;
 
 use super::{
     common::{
-        parse_arithmetic_operand, parse_binary_logic_operand, parse_comparison_operand,
+        self, parse_arithmetic_operand, parse_binary_logic_operand, parse_comparison_operand,
         parse_frame_attribute, parse_frame_identifier, parse_gate_modifier, parse_matrix,
-        parse_memory_reference, parse_permutation, parse_qubit, parse_vector,
-        parse_waveform_invocation, parse_waveform_name,
+        parse_memory_reference, parse_pauli_terms, parse_permutation, parse_qubit,
+        parse_scalar_type, parse_vector, parse_waveform_invocation, parse_waveform_name,
     },
     expression::parse_expression,
     ParserInput,
@@ -53,8 +53,7 @@ pub(crate) fn parse_arithmetic(
     operator: ArithmeticOperator,
     input: ParserInput,
 ) -> InternalParserResult<Instruction> {
-    let (input, destination_memory_reference) = parse_memory_reference(input)?;
-    let destination = ArithmeticOperand::MemoryReference(destination_memory_reference);
+    let (input, destination) = parse_memory_reference(input)?;
     let (input, source) = parse_arithmetic_operand(input)?;
 
     Ok((
@@ -122,16 +121,41 @@ pub(crate) fn parse_logical_unary(
 pub(crate) fn parse_declare<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
     let (input, name) = token!(Identifier(v))(input)?;
     let (input, size) = parse_vector(input)?;
+    let (input, sharing) = opt(parse_sharing)(input)?;
     Ok((
         input,
         Instruction::Declaration(Declaration {
-            name,
-            sharing: None,
+            name: name.into(),
+            sharing,
             size,
         }),
     ))
 }
 
+/// Parse the `SHARING` clause of a `DECLARE` instruction, such as `SHARING theta OFFSET 1 REAL`.
+fn parse_sharing<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Sharing> {
+    let (input, _) = token!(Sharing)(input)?;
+    let (input, name) = token!(Identifier(v))(input)?;
+    let (input, offsets) = many0(parse_offset)(input)?;
+    Ok((
+        input,
+        Sharing {
+            name: name.into(),
+            offsets,
+        },
+    ))
+}
+
+/// Parse an `OFFSET` of a `SHARING` clause, such as `OFFSET 1 REAL`.
+fn parse_offset<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Offset> {
+    let (input, _) = verify(token!(Identifier(v)), |keyword: &String| {
+        keyword == "OFFSET"
+    })(input)?;
+    let (input, count) = token!(Integer(v))(input)?;
+    let (input, data_type) = parse_scalar_type(input)?;
+    Ok((input, Offset { count, data_type }))
+}
+
 /// Parse the contents of a `CAPTURE` instruction.
 ///
 /// Unlike most other instructions, this can be _prefixed_ with the NONBLOCKING keyword,
@@ -194,7 +218,7 @@ pub(crate) fn parse_defcal_gate<'a>(
         Instruction::CalibrationDefinition(Calibration {
             instructions,
             modifiers,
-            name,
+            name: name.into(),
             parameters,
             qubits,
         }),
@@ -206,9 +230,12 @@ pub(crate) fn parse_defcal_measure<'a>(
     input: ParserInput<'a>,
 ) -> InternalParserResult<'a, Instruction> {
     let (input, params) = pair(parse_qubit, opt(token!(Identifier(v))))(input)?;
+    // With a single argument, a fixed qubit is the qubit measured, with no destination, while
+    // a name is the destination of a measurement of any qubit.
     let (qubit, destination) = match params {
-        (qubit, Some(destination)) => (Some(qubit), destination),
-        (destination, None) => (None, destination.to_string()),
+        (qubit, Some(destination)) => (Some(qubit), Some(destination)),
+        (Qubit::Fixed(qubit), None) => (Some(Qubit::Fixed(qubit)), None),
+        (Qubit::Variable(destination), None) => (None, Some(destination)),
     };
     let (input, _) = token!(Colon)(input)?;
     let (input, instructions) = parse_block(input)?;
@@ -246,11 +273,17 @@ pub(crate) fn parse_defgate<'a>(input: ParserInput<'a>) -> InternalParserResult<
         separated_list1(token!(Comma), token!(Variable(v))),
         token!(RParenthesis),
     ))(input)?;
+    // Only a gate defined as a Pauli sum names its arguments.
+    let (input, arguments) = opt(terminated(
+        many1(token!(Identifier(v))),
+        peek(tuple((token!(As), token!(PauliSum)))),
+    ))(input)?;
     let (input, gate_type) = opt(preceded(
         token!(As),
         alt((
             map(token!(Matrix), |()| GateType::Matrix),
             map(token!(Permutation), |()| GateType::Permutation),
+            map(token!(PauliSum), |()| GateType::PauliSum),
         )),
     ))(input)?;
     let (input, _) = token!(Colon)(input)?;
@@ -259,12 +292,18 @@ pub(crate) fn parse_defgate<'a>(input: ParserInput<'a>) -> InternalParserResult<
     let (input, specification) = match gate_type {
         GateType::Matrix => map(parse_matrix, GateSpecification::Matrix)(input)?,
         GateType::Permutation => map(parse_permutation, GateSpecification::Permutation)(input)?,
+        GateType::PauliSum => map(parse_pauli_terms, |terms| {
+            GateSpecification::PauliSum(PauliSum {
+                arguments: arguments.clone().unwrap_or_default(),
+                terms,
+            })
+        })(input)?,
     };
 
     Ok((
         input,
         Instruction::GateDefinition(GateDefinition {
-            name,
+            name: name.into(),
             parameters: parameters.unwrap_or_default(),
             specification,
         }),
@@ -289,7 +328,7 @@ pub(crate) fn parse_defwaveform<'a>(
     Ok((
         input,
         Instruction::WaveformDefinition(WaveformDefinition {
-            name,
+            name: name.into(),
             definition: Waveform { matrix, parameters },
         }),
     ))
@@ -312,7 +351,7 @@ pub(crate) fn parse_defcircuit<'a>(
     Ok((
         input,
         Instruction::CircuitDefinition(CircuitDefinition {
-            name,
+            name: name.into(),
             parameters,
             qubit_variables,
             instructions,
@@ -341,13 +380,7 @@ pub(crate) fn parse_exchange(input: ParserInput) -> InternalParserResult<Instruc
     let (input, left) = parse_memory_reference(input)?;
     let (input, right) = parse_memory_reference(input)?;
 
-    Ok((
-        input,
-        Instruction::Exchange(Exchange {
-            left: ArithmeticOperand::MemoryReference(left),
-            right: ArithmeticOperand::MemoryReference(right),
-        }),
-    ))
+    Ok((input, Instruction::Exchange(Exchange { left, right })))
 }
 
 /// Parse the contents of a `FENCE` instruction.
@@ -360,14 +393,25 @@ pub(crate) fn parse_fence(input: ParserInput) -> InternalParserResult<Instructio
 /// Parse the contents of a `JUMP` instruction.
 pub(crate) fn parse_jump<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
     let (input, target) = token!(Label(v))(input)?;
-    Ok((input, Instruction::Jump(Jump { target })))
+    Ok((
+        input,
+        Instruction::Jump(Jump {
+            target: Label::Fixed(target.into()),
+        }),
+    ))
 }
 
 /// Parse the contents of a `JUMP-WHEN` instruction.
 pub(crate) fn parse_jump_when<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
     let (input, target) = token!(Label(v))(input)?;
     let (input, condition) = parse_memory_reference(input)?;
-    Ok((input, Instruction::JumpWhen(JumpWhen { target, condition })))
+    Ok((
+        input,
+        Instruction::JumpWhen(JumpWhen {
+            target: Label::Fixed(target.into()),
+            condition,
+        }),
+    ))
 }
 
 /// Parse the contents of a `JUMP-UNLESS` instruction.
@@ -378,19 +422,22 @@ pub(crate) fn parse_jump_unless<'a>(
     let (input, condition) = parse_memory_reference(input)?;
     Ok((
         input,
-        Instruction::JumpUnless(JumpUnless { target, condition }),
+        Instruction::JumpUnless(JumpUnless {
+            target: Label::Fixed(target.into()),
+            condition,
+        }),
     ))
 }
 
 /// Parse the contents of a `DECLARE` instruction.
 pub(crate) fn parse_label<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
     let (input, name) = token!(Label(v))(input)?;
-    Ok((input, Instruction::Label(Label(name))))
+    Ok((input, Instruction::Label(Label::Fixed(name.into()))))
 }
 
 /// Parse the contents of a `MOVE` instruction.
 pub(crate) fn parse_move(input: ParserInput) -> InternalParserResult<Instruction> {
-    let (input, destination) = parse_arithmetic_operand(input)?;
+    let (input, destination) = parse_memory_reference(input)?;
     let (input, source) = parse_arithmetic_operand(input)?;
     Ok((
         input,
@@ -411,7 +458,7 @@ pub(crate) fn parse_load<'a>(input: ParserInput<'a>) -> InternalParserResult<'a,
         input,
         Instruction::Load(Load {
             destination,
-            source,
+            source: source.into(),
             offset,
         }),
     ))
@@ -426,7 +473,7 @@ pub(crate) fn parse_store<'a>(input: ParserInput<'a>) -> InternalParserResult<'a
     Ok((
         input,
         Instruction::Store(Store {
-            destination,
+            destination: destination.into(),
             offset,
             source,
         }),
@@ -444,7 +491,7 @@ pub(crate) fn parse_pragma<'a>(input: ParserInput<'a>) -> InternalParserResult<'
     Ok((
         input,
         Instruction::Pragma(Pragma {
-            name: pragma_type,
+            name: pragma_type.into(),
             arguments,
             data,
         }),
@@ -553,6 +600,13 @@ pub(crate) fn parse_measurement(input: ParserInput) -> InternalParserResult<Inst
     ))
 }
 
+/// Parse the contents of a `CALL` instruction.
+pub(crate) fn parse_call<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
+    let (input, name) = token!(Identifier(v))(input)?;
+    let (input, arguments) = many0(common::parse_call_argument)(input)?;
+    Ok((input, Instruction::Call(Call { name, arguments })))
+}
+
 /// Parse the contents of an `INCLUDE` instruction.
 pub(crate) fn parse_include<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Instruction> {
     let (input, filename) = token!(String(v))(input)?;
@@ -562,13 +616,15 @@ pub(crate) fn parse_include<'a>(input: ParserInput<'a>) -> InternalParserResult<
 #[cfg(test)]
 mod tests {
     use crate::expression::{Expression, ExpressionFunction, InfixOperator, PrefixOperator};
-    use crate::instruction::{GateDefinition, GateSpecification, PragmaArgument};
+    use crate::instruction::{
+        GateDefinition, GateSpecification, PauliGate, PauliSum, PauliTerm, PragmaArgument,
+    };
     use crate::parser::lexer::lex;
     use crate::{imag, real};
     use crate::{
         instruction::{
             CircuitDefinition, Declaration, Gate, Instruction, Measurement, MemoryReference,
-            Pragma, Qubit, ScalarType, Vector,
+            Offset, Pragma, Qubit, ScalarType, Sharing, Vector,
         },
         make_test,
     };
@@ -580,7 +636,7 @@ mod tests {
         parse_declare,
         "ro BIT",
         Instruction::Declaration(Declaration {
-            name: "ro".to_owned(),
+            name: "ro".into(),
             sharing: None,
             size: Vector {
                 data_type: ScalarType::Bit,
@@ -589,12 +645,38 @@ mod tests {
         })
     );
 
+    make_test!(
+        declare_instruction_sharing,
+        parse_declare,
+        "theta REAL[2] SHARING params OFFSET 1 REAL OFFSET 2 OCTET",
+        Instruction::Declaration(Declaration {
+            name: "theta".into(),
+            sharing: Some(Sharing {
+                name: "params".into(),
+                offsets: vec![
+                    Offset {
+                        count: 1,
+                        data_type: ScalarType::Real
+                    },
+                    Offset {
+                        count: 2,
+                        data_type: ScalarType::Octet
+                    }
+                ]
+            }),
+            size: Vector {
+                data_type: ScalarType::Real,
+                length: 2
+            }
+        })
+    );
+
     make_test!(
         declare_instruction_length_n,
         parse_declare,
         "ro INTEGER[5]",
         Instruction::Declaration(Declaration {
-            name: "ro".to_owned(),
+            name: "ro".into(),
             sharing: None,
             size: Vector {
                 data_type: ScalarType::Integer,
@@ -610,7 +692,7 @@ mod tests {
         Instruction::Measurement(Measurement {
             qubit: Qubit::Fixed(0),
             target: Some(MemoryReference {
-                name: String::from("ro"),
+                name: "ro".into(),
                 index: 0
             })
         })
@@ -633,7 +715,7 @@ mod tests {
         Instruction::Measurement(Measurement {
             qubit: Qubit::Variable(String::from("q0")),
             target: Some(MemoryReference {
-                name: String::from("ro"),
+                name: "ro".into(),
                 index: 0
             })
         })
@@ -654,7 +736,7 @@ mod tests {
         parse_pragma,
         "FILTER-NODE q35_unclassified \"{'module':'lodgepole.filters.io','filter_type':'DataBuffer','source':'q35_ro_rx/filter','publish':true,'params':{},'_type':'FilterNode'}\"",
         Instruction::Pragma(Pragma {
-            name: "FILTER-NODE".to_owned(),
+            name: "FILTER-NODE".into(),
             arguments: vec![PragmaArgument::Identifier("q35_unclassified".to_string())],
             data: Some("{'module':'lodgepole.filters.io','filter_type':'DataBuffer','source':'q35_ro_rx/filter','publish':true,'params':{},'_type':'FilterNode'}".to_owned())
         })
@@ -665,7 +747,7 @@ mod tests {
         parse_pragma,
         r#"FILTER-NODE q35_unclassified "{\"module\":\"lodgepole.filters.io\",\"filter_type\":\"DataBuffer\",\"source\":\"q35_ro_rx/filter\",\"publish\":true,\"params\":{},\"_type\":\"FilterNode\"}""#,
         Instruction::Pragma(Pragma {
-            name: "FILTER-NODE".to_owned(),
+            name: "FILTER-NODE".into(),
             arguments: vec![PragmaArgument::Identifier("q35_unclassified".to_string())],
             data: Some(r#"{"module":"lodgepole.filters.io","filter_type":"DataBuffer","source":"q35_ro_rx/filter","publish":true,"params":{},"_type":"FilterNode"}"#.to_owned())
         })
@@ -676,7 +758,7 @@ mod tests {
         parse_pragma,
         "READOUT-POVM 0 \"(0.9 0.19999999999999996 0.09999999999999998 0.8)\"",
         Instruction::Pragma(Pragma {
-            name: "READOUT-POVM".to_string(),
+            name: "READOUT-POVM".into(),
             arguments: vec![PragmaArgument::Integer(0)],
             data: Some("(0.9 0.19999999999999996 0.09999999999999998 0.8)".to_string()),
         })
@@ -687,7 +769,7 @@ mod tests {
         parse_pragma,
         "NAME identifier 0 \"data\"",
         Instruction::Pragma(Pragma {
-            name: "NAME".to_string(),
+            name: "NAME".into(),
             arguments: vec![
                 PragmaArgument::Identifier("identifier".to_string()),
                 PragmaArgument::Integer(0)
@@ -703,18 +785,18 @@ mod tests {
     H a
     CNOT a b",
         Instruction::CircuitDefinition(CircuitDefinition {
-            name: "BELL".to_owned(),
+            name: "BELL".into(),
             parameters: vec![],
             qubit_variables: vec!["a".to_owned(), "b".to_owned()],
             instructions: vec![
                 Instruction::Gate(Gate {
-                    name: "H".to_owned(),
+                    name: "H".into(),
                     parameters: vec![],
                     qubits: vec![Qubit::Variable("a".to_owned())],
                     modifiers: vec![],
                 }),
                 Instruction::Gate(Gate {
-                    name: "CNOT".to_owned(),
+                    name: "CNOT".into(),
                     parameters: vec![],
                     qubits: vec![
                         Qubit::Variable("a".to_owned()),
@@ -735,30 +817,30 @@ mod tests {
     RZ(%a) a
     CNOT a b",
         Instruction::CircuitDefinition(CircuitDefinition {
-            name: "BELL".to_owned(),
+            name: "BELL".into(),
             parameters: vec!["a".to_owned()],
             qubit_variables: vec!["a".to_owned(), "b".to_owned()],
             instructions: vec![
                 Instruction::Gate(Gate {
-                    name: "RZ".to_owned(),
+                    name: "RZ".into(),
                     parameters: vec![Expression::Variable("a".to_owned())],
                     qubits: vec![Qubit::Variable("a".to_owned())],
                     modifiers: vec![],
                 }),
                 Instruction::Gate(Gate {
-                    name: "RX".to_owned(),
+                    name: "RX".into(),
                     parameters: vec![Expression::Variable("a".to_owned())],
                     qubits: vec![Qubit::Variable("a".to_owned())],
                     modifiers: vec![],
                 }),
                 Instruction::Gate(Gate {
-                    name: "RZ".to_owned(),
+                    name: "RZ".into(),
                     parameters: vec![Expression::Variable("a".to_owned())],
                     qubits: vec![Qubit::Variable("a".to_owned())],
                     modifiers: vec![],
                 }),
                 Instruction::Gate(Gate {
-                    name: "CNOT".to_owned(),
+                    name: "CNOT".into(),
                     parameters: vec![],
                     qubits: vec![
                         Qubit::Variable("a".to_owned()),
@@ -801,7 +883,7 @@ mod tests {
             };
 
             Instruction::GateDefinition(GateDefinition {
-                name: "H".to_string(),
+                name: "H".into(),
                 parameters: vec![],
                 specification: GateSpecification::Matrix(vec![
                     vec![expression.clone(), expression.clone()],
@@ -818,7 +900,7 @@ mod tests {
     cos(%theta/2), -i*sin(%theta/2)
     -i*sin(%theta/2), cos(%theta/2)"#,
         Instruction::GateDefinition(GateDefinition {
-            name: "RX".to_string(),
+            name: "RX".into(),
             parameters: vec!["theta".to_string()],
             specification: GateSpecification::Matrix(vec![
                 vec![
@@ -881,9 +963,64 @@ mod tests {
         r#"CCNOT AS PERMUTATION:
     0, 1, 2, 3, 4, 5, 7, 6"#,
         Instruction::GateDefinition(GateDefinition {
-            name: "CCNOT".to_string(),
+            name: "CCNOT".into(),
             parameters: vec![],
             specification: GateSpecification::Permutation(vec![0, 1, 2, 3, 4, 5, 7, 6]),
         })
     );
+
+    make_test!(
+        defgate_pauli_sum,
+        parse_defgate,
+        r#"PAULI_SUM_GATE(%theta) p q AS PAULI-SUM:
+    ZZ(-%theta/4) p q
+    Y(%theta/4) p"#,
+        Instruction::GateDefinition(GateDefinition {
+            name: "PAULI_SUM_GATE".into(),
+            parameters: vec!["theta".to_string()],
+            specification: GateSpecification::PauliSum(PauliSum {
+                arguments: vec!["p".to_string(), "q".to_string()],
+                terms: vec![
+                    PauliTerm {
+                        arguments: vec![
+                            (PauliGate::Z, "p".to_string()),
+                            (PauliGate::Z, "q".to_string())
+                        ],
+                        expression: Expression::Infix {
+                            left: Box::new(Expression::Prefix {
+                                operator: PrefixOperator::Minus,
+                                expression: Box::new(Expression::Variable("theta".to_string())),
+                            }),
+                            operator: InfixOperator::Slash,
+                            right: Box::new(Expression::Number(real!(4.0))),
+                        },
+                    },
+                    PauliTerm {
+                        arguments: vec![(PauliGate::Y, "p".to_string())],
+                        expression: Expression::Infix {
+                            left: Box::new(Expression::Variable("theta".to_string())),
+                            operator: InfixOperator::Slash,
+                            right: Box::new(Expression::Number(real!(4.0))),
+                        },
+                    },
+                ],
+            }),
+        })
+    );
+
+    #[test]
+    fn defgate_pauli_sum_invalid_term() {
+        for input in [
+            "G p q AS PAULI-SUM:\n    ZA(1) p q",
+            "G p q AS PAULI-SUM:\n    ZZ(1) p",
+            "G p AS MATRIX:\n    1, 0\n    0, 1",
+        ] {
+            let tokens = lex(nom_locate::LocatedSpan::new(input)).unwrap();
+            let parsed = parse_defgate(&tokens);
+            assert!(
+                !matches!(parsed, Ok((remainder, _)) if remainder.is_empty()),
+                "{input:?} should not parse"
+            );
+        }
+    }
 }
@@ -25,10 +25,10 @@ use crate::{
     expected_token,
     expression::Expression,
     instruction::{
-        ArithmeticOperand, AttributeValue, BinaryOperand, ComparisonOperand, FrameIdentifier,
-        GateModifier, MemoryReference, Qubit, ScalarType, Vector, WaveformInvocation,
+        ArithmeticOperand, AttributeValue, BinaryOperand, CallArgument, ComparisonOperand,
+        FrameIdentifier, GateModifier, MemoryReference, PauliGate, PauliTerm, Qubit, ScalarType,
+        Vector, WaveformInvocation,
     },
-    parser::lexer::Operator,
     token,
 };
 
@@ -41,34 +41,40 @@ use super::{
     ParserInput,
 };
 
+/// Parse a literal real number, which may be negated.
+fn parse_signed_real<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, f64> {
+    map(
+        tuple((opt(token!(Operator(Operator::Minus))), token!(Float(v)))),
+        |(minus, v)| if minus.is_some() { -v } else { v },
+    )(input)
+}
+
+/// Parse a literal integer, which may be negated. Fails if the integer does not fit in an `i64`.
+fn parse_signed_integer<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, i64> {
+    let (remainder, (minus, v)) =
+        tuple((opt(token!(Operator(Operator::Minus))), token!(Integer(v))))(input)?;
+    let value = if minus.is_some() {
+        -i128::from(v)
+    } else {
+        i128::from(v)
+    };
+    match i64::try_from(value) {
+        Ok(value) => Ok((remainder, value)),
+        Err(_) => Err(nom::Err::Failure(InternalParseError::from_kind(
+            input,
+            ParserErrorKind::UnsupportedPrecision,
+        ))),
+    }
+}
+
 /// Parse the operand of an arithmetic instruction, which may be a literal integer, literal real
 /// number, or memory reference.
 pub(crate) fn parse_arithmetic_operand<'a>(
     input: ParserInput<'a>,
 ) -> InternalParserResult<'a, ArithmeticOperand> {
     alt((
-        map(
-            tuple((opt(token!(Operator(o))), token!(Float(v)))),
-            |(op, v)| {
-                let sign = match op {
-                    None => 1f64,
-                    Some(Operator::Minus) => -1f64,
-                    _ => panic!("Implement this error"), // TODO
-                };
-                ArithmeticOperand::LiteralReal(sign * v)
-            },
-        ),
-        map(
-            tuple((opt(token!(Operator(o))), token!(Integer(v)))),
-            |(op, v)| {
-                let sign = match op {
-                    None => 1,
-                    Some(Operator::Minus) => -1,
-                    _ => panic!("Implement this error"), // TODO
-                };
-                ArithmeticOperand::LiteralInteger(sign * (v as i64))
-            },
-        ),
+        map(parse_signed_real, ArithmeticOperand::LiteralReal),
+        map(parse_signed_integer, ArithmeticOperand::LiteralInteger),
         map(parse_memory_reference, ArithmeticOperand::MemoryReference),
     ))(input)
 }
@@ -79,29 +85,25 @@ pub(crate) fn parse_comparison_operand<'a>(
     input: ParserInput<'a>,
 ) -> InternalParserResult<'a, ComparisonOperand> {
     alt((
+        map(parse_signed_real, ComparisonOperand::LiteralReal),
+        map(parse_signed_integer, ComparisonOperand::LiteralInteger),
+        map(parse_memory_reference, ComparisonOperand::MemoryReference),
+    ))(input)
+}
+
+/// Parse an argument of a `CALL` instruction, which may be a literal integer, literal real
+/// number, memory reference, or the name of a memory region.
+pub(crate) fn parse_call_argument<'a>(
+    input: ParserInput<'a>,
+) -> InternalParserResult<'a, CallArgument> {
+    alt((
+        map(parse_signed_real, CallArgument::LiteralReal),
+        map(parse_signed_integer, CallArgument::LiteralInteger),
         map(
-            tuple((opt(token!(Operator(o))), token!(Float(v)))),
-            |(op, v)| {
-                let sign = match op {
-                    None => 1f64,
-                    Some(Operator::Minus) => -1f64,
-                    _ => panic!("Implement this error"), // TODO
-                };
-                ComparisonOperand::LiteralReal(sign * v)
-            },
-        ),
-        map(
-            tuple((opt(token!(Operator(o))), token!(Integer(v)))),
-            |(op, v)| {
-                let sign = match op {
-                    None => 1,
-                    Some(Operator::Minus) => -1,
-                    _ => panic!("Implement this error"), // TODO
-                };
-                ComparisonOperand::LiteralInteger(sign * (v as i64))
-            },
+            parse_memory_reference_with_brackets,
+            CallArgument::MemoryReference,
         ),
-        map(parse_memory_reference, ComparisonOperand::MemoryReference),
+        map(token!(Identifier(v)), CallArgument::Identifier),
     ))(input)
 }
 
@@ -110,17 +112,7 @@ pub(crate) fn parse_binary_logic_operand<'a>(
     input: ParserInput<'a>,
 ) -> InternalParserResult<'a, BinaryOperand> {
     alt((
-        map(
-            tuple((opt(token!(Operator(o))), token!(Integer(v)))),
-            |(op, v)| {
-                let sign = match op {
-                    None => 1,
-                    Some(Operator::Minus) => -1,
-                    _ => panic!("Implement this error"), // TODO
-                };
-                BinaryOperand::LiteralInteger(sign * (v as i64))
-            },
-        ),
+        map(parse_signed_integer, BinaryOperand::LiteralInteger),
         map(parse_memory_reference, BinaryOperand::MemoryReference),
     ))(input)
 }
@@ -149,7 +141,13 @@ pub(crate) fn parse_frame_identifier<'a>(
     let (input, qubits) = many1(parse_qubit)(input)?;
     let (input, name) = token!(String(v))(input)?;
 
-    Ok((input, FrameIdentifier { name, qubits }))
+    Ok((
+        input,
+        FrameIdentifier {
+            name: name.into(),
+            qubits,
+        },
+    ))
 }
 
 /// Parse a gate modifier prefix, such as `CONTROLLED`.
@@ -194,6 +192,48 @@ pub(crate) fn parse_permutation<'a>(input: ParserInput<'a>) -> InternalParserRes
     )(input)
 }
 
+/// Parse one term of a `DEFGATE ... AS PAULI-SUM`, such as `ZZ(%theta/4) p q`: a word of Pauli
+/// operators, one for each of the arguments which follow the parenthesized coefficient.
+pub(crate) fn parse_pauli_term<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, PauliTerm> {
+    let start = input;
+    let (input, word) = token!(Identifier(v))(input)?;
+    let (input, expression) =
+        delimited(token!(LParenthesis), parse_expression, token!(RParenthesis))(input)?;
+    let (input, arguments) = many1(token!(Identifier(v)))(input)?;
+
+    let gates: Option<Vec<PauliGate>> = word.chars().map(PauliGate::from_char).collect();
+    match gates {
+        Some(gates) if gates.len() == arguments.len() => Ok((
+            input,
+            PauliTerm {
+                arguments: gates.into_iter().zip(arguments).collect(),
+                expression,
+            },
+        )),
+        _ => Err(nom::Err::Failure(InternalParseError::from_kind(
+            start,
+            ParserErrorKind::ExpectedToken {
+                actual: Token::Identifier(word),
+                expected: "a word of Pauli operators (I, X, Y, Z), one for each argument"
+                    .to_owned(),
+            },
+        ))),
+    }
+}
+
+/// Parse the terms of a `DEFGATE ... AS PAULI-SUM`, one on each indented line.
+pub(crate) fn parse_pauli_terms<'a>(
+    input: ParserInput<'a>,
+) -> InternalParserResult<'a, Vec<PauliTerm>> {
+    preceded(
+        token!(NewLine),
+        separated_list1(
+            token!(NewLine),
+            preceded(token!(Indentation), parse_pauli_term),
+        ),
+    )(input)
+}
+
 /// Parse a reference to a memory location, such as `ro[5]`, with optional brackets
 /// (i.e, `ro` allowed).
 pub(crate) fn parse_memory_reference<'a>(
@@ -206,7 +246,13 @@ pub(crate) fn parse_memory_reference<'a>(
         token!(RBracket),
     ))(input)?;
     let index = index.unwrap_or(0);
-    Ok((input, MemoryReference { name, index }))
+    Ok((
+        input,
+        MemoryReference {
+            name: name.into(),
+            index,
+        },
+    ))
 }
 
 /// Parse a reference to a memory location, such as `ro[5]` requiring the brackets
@@ -216,7 +262,13 @@ pub(crate) fn parse_memory_reference_with_brackets<'a>(
 ) -> InternalParserResult<'a, MemoryReference> {
     let (input, name) = token!(Identifier(v))(input)?;
     let (input, index) = delimited(token!(LBracket), token!(Integer(v)), token!(RBracket))(input)?;
-    Ok((input, MemoryReference { name, index }))
+    Ok((
+        input,
+        MemoryReference {
+            name: name.into(),
+            index,
+        },
+    ))
 }
 
 /// Parse a named argument key-value pair, such as `foo: 42`.
@@ -241,7 +293,13 @@ pub(crate) fn parse_waveform_invocation<'a>(
     let parameter_tuples = parameter_tuples.unwrap_or_default();
     let parameters: HashMap<_, _> = parameter_tuples.into_iter().collect();
 
-    Ok((input, WaveformInvocation { name, parameters }))
+    Ok((
+        input,
+        WaveformInvocation {
+            name: name.into(),
+            parameters,
+        },
+    ))
 }
 
 /// Parse a single qubit, which may be an integer (`1`), variable (`%q1`), or identifier (`q1`).
@@ -281,14 +339,7 @@ pub(crate) fn parse_variable_qubit(input: ParserInput) -> InternalParserResult<S
 
 /// Parse a "vector" which is an integer index, such as `[0]`
 pub(crate) fn parse_vector<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, Vector> {
-    let (input, data_type_token) = token!(DataType(v))(input)?;
-
-    let data_type = match data_type_token {
-        DataType::Bit => ScalarType::Bit,
-        DataType::Integer => ScalarType::Integer,
-        DataType::Real => ScalarType::Real,
-        DataType::Octet => ScalarType::Octet,
-    };
+    let (input, data_type) = parse_scalar_type(input)?;
 
     let (input, length) = opt(delimited(
         token!(LBracket),
@@ -300,6 +351,21 @@ pub(crate) fn parse_vector<'a>(input: ParserInput<'a>) -> InternalParserResult<'
     Ok((input, Vector { data_type, length }))
 }
 
+/// Parse a data type, such as `REAL`.
+pub(crate) fn parse_scalar_type<'a>(
+    input: ParserInput<'a>,
+) -> InternalParserResult<'a, ScalarType> {
+    let (input, data_type_token) = token!(DataType(v))(input)?;
+
+    let data_type = match data_type_token {
+        DataType::Bit => ScalarType::Bit,
+        DataType::Integer => ScalarType::Integer,
+        DataType::Real => ScalarType::Real,
+        DataType::Octet => ScalarType::Octet,
+    };
+    Ok((input, data_type))
+}
+
 /// Parse a waveform name which may look like `custom` or `q20_q27_xy/sqrtiSWAP`
 pub(crate) fn parse_waveform_name<'a>(input: ParserInput<'a>) -> InternalParserResult<'a, String> {
     use crate::parser::lexer::Operator::Slash;
@@ -402,7 +468,7 @@ mod tests {
                 (
                     "c".to_owned(),
                     Expression::Address(MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 0
                     })
                 )
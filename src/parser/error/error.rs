@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use crate::parser::error::kind::ErrorKind;
-use crate::parser::error::{ErrorInput, GenericParseError, InternalError};
+use crate::parser::error::{ErrorInput, Excerpt, GenericParseError, InternalError};
 use nom::error::{Error as NomError, ParseError};
 use std::convert::Infallible;
 use std::fmt;
@@ -30,8 +30,9 @@ use thiserror::__private::AsDynError;
 /// The standard output (i.e., with `format!("{}", err)`) outputs just this error, including file
 /// location information.
 ///
-/// The alternate output (i.e., with `format!("{:#}", err)`) outputs this error as well a
-/// user-readable backtrace of the errors that caused this one.
+/// The alternate output (i.e., with `format!("{:#}", err)`) outputs this error, followed by the
+/// line of input it was found in with the text at fault underlined, as well a user-readable
+/// backtrace of the errors that caused this one.
 ///
 /// When displaying errors to end-users, prefer the alternate format for easier debugging.
 #[derive(Debug)]
@@ -42,6 +43,7 @@ where
     line: u32,
     column: usize,
     snippet: String,
+    excerpt: Option<Box<Excerpt>>,
     kind: ErrorKind<E>,
     previous: Option<Box<dyn std::error::Error + 'static + Send + Sync>>,
 }
@@ -90,6 +92,16 @@ where
         error.map(Self::from)
     }
 
+    /// The line of the input at which the error was found, counted from 1.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The column of the line at which the error was found, counted from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
     /// Attach a previous error to this one.
     pub(crate) fn with_previous<E2>(mut self, previous: E2) -> Self
     where
@@ -106,10 +118,12 @@ where
         let line = input.line();
         let column = input.column();
         let snippet = input.snippet();
+        let excerpt = input.excerpt().map(Box::new);
         Self {
             line,
             column,
             snippet,
+            excerpt,
             kind,
             previous: None,
         }
@@ -146,6 +160,9 @@ where
             self.line, self.column, self.snippet, self.kind
         )?;
         if f.alternate() {
+            if let Some(excerpt) = &self.excerpt {
+                write!(f, "\n{}", excerpt)?;
+            }
             if let Some(previous) = &self.previous {
                 write!(f, "\n\tcause: {}", previous)?;
             }
@@ -0,0 +1,84 @@
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+/// The line of input in which an error was found, shown beneath the error with the text at fault
+/// underlined:
+///
+/// ```text
+///   |
+/// 2 | MEASURE 0 ro[
+///   |             ^
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Excerpt {
+    /// the number of the line, counted from 1
+    line: u32,
+    /// the column at which the text at fault starts, counted in characters from 1
+    column: usize,
+    /// the text of the line, without its line break
+    text: String,
+    /// the number of characters at fault
+    width: usize,
+}
+
+impl Excerpt {
+    pub(crate) fn new(line: u32, column: usize, text: &[u8], width: usize) -> Option<Self> {
+        let text = std::str::from_utf8(text).ok()?;
+        Some(Self {
+            line,
+            column,
+            text: text.trim_end_matches('\r').to_string(),
+            width: width.max(1),
+        })
+    }
+}
+
+impl fmt::Display for Excerpt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let number = self.line.to_string();
+        let gutter = " ".repeat(number.len());
+        // Tabs before the text at fault are kept, so that the underline lines up with it.
+        let indent: String = self
+            .text
+            .chars()
+            .take(self.column.saturating_sub(1))
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        writeln!(f, "{gutter} |")?;
+        writeln!(f, "{number} | {}", self.text)?;
+        write!(f, "{gutter} | {indent}{}", "^".repeat(self.width))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Excerpt;
+
+    #[test]
+    fn test_display() {
+        let excerpt = Excerpt::new(12, 6, b"\tRX(theta) 0", 5).unwrap();
+        assert_eq!(
+            excerpt.to_string(),
+            "   |\n12 | \tRX(theta) 0\n   | \t    ^^^^^"
+        );
+    }
+
+    #[test]
+    fn test_at_end_of_line() {
+        let excerpt = Excerpt::new(1, 4, b"H 0", 0).unwrap();
+        assert_eq!(excerpt.to_string(), "  |\n1 | H 0\n  |    ^");
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::parser::error::Excerpt;
 use crate::parser::lexer::LexInput;
 use crate::parser::{ParserInput, TokenWithLocation};
 
@@ -25,6 +26,9 @@ pub trait ErrorInput {
     fn snippet(&self) -> String;
     /// Whether there is anything left in the input.
     fn is_empty(&self) -> bool;
+    /// The line of input where the error occurred, with the text at fault underlined, or `None`
+    /// at the end of the input.
+    fn excerpt(&self) -> Option<Excerpt>;
 }
 
 impl ErrorInput for LexInput<'_> {
@@ -51,6 +55,10 @@ impl ErrorInput for LexInput<'_> {
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    fn excerpt(&self) -> Option<Excerpt> {
+        Excerpt::new(self.line(), self.column(), self.get_line_beginning(), 1)
+    }
 }
 
 impl ErrorInput for ParserInput<'_> {
@@ -72,6 +80,17 @@ impl ErrorInput for ParserInput<'_> {
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
+
+    fn excerpt(&self) -> Option<Excerpt> {
+        let token = self.first()?;
+        let width = token.text().split('\n').next().unwrap_or_default();
+        Excerpt::new(
+            token.line(),
+            token.column(),
+            token.line_text(),
+            width.chars().count(),
+        )
+    }
 }
 
 impl ErrorInput for Vec<TokenWithLocation<'_>> {
@@ -90,4 +109,8 @@ impl ErrorInput for Vec<TokenWithLocation<'_>> {
     fn is_empty(&self) -> bool {
         self.as_slice().is_empty()
     }
+
+    fn excerpt(&self) -> Option<Excerpt> {
+        self.as_slice().excerpt()
+    }
 }
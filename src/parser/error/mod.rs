@@ -14,6 +14,7 @@
 
 #[allow(clippy::module_inception)]
 mod error;
+mod excerpt;
 mod input;
 mod internal;
 mod kind;
@@ -21,6 +22,7 @@ mod kind;
 use super::lexer::{Command, Token};
 
 pub use error::Error;
+pub(crate) use excerpt::Excerpt;
 pub(crate) use input::ErrorInput;
 pub use internal::GenericParseError;
 pub(crate) use internal::InternalError;
@@ -62,4 +64,16 @@ pub enum ParserErrorKind {
     /// Literals specified in the input cannot be supported without loss of precision
     #[error("using this literal will result in loss of precision")]
     UnsupportedPrecision,
+
+    /// An expression was nested within more than this many parentheses and function calls
+    #[error("expression is nested more than {0} levels deep")]
+    NestingTooDeep(usize),
+
+    /// A registered function was called with the wrong number of arguments
+    #[error("function {function} takes {expected} arguments, but was given {actual}")]
+    WrongArgumentCount {
+        function: String,
+        expected: usize,
+        actual: usize,
+    },
 }
@@ -14,19 +14,27 @@
 
 use nom::combinator::opt;
 
-use crate::parser::InternalParserResult;
+use crate::parser::{InternalParseError, InternalParserResult, ParserErrorKind};
 use crate::{
     expected_token,
-    expression::{Expression, ExpressionFunction, InfixOperator, PrefixOperator},
+    expression::{
+        CustomFunction, Expression, ExpressionFunction, FunctionRegistry, InfixOperator,
+        PrefixOperator,
+    },
     imag,
     instruction::MemoryReference,
     parser::common::parse_memory_reference_with_brackets,
+    program::SourceSpan,
     token, unexpected_eof,
 };
 
 use super::lexer::{Operator, Token};
 use super::ParserInput;
 
+/// The number of parentheses and function calls an expression may be nested within. Parsing is
+/// recursive, so without a limit, deeply nested input would overflow the stack.
+pub(crate) const MAX_NESTING_DEPTH: usize = 128;
+
 #[derive(Debug, PartialEq, PartialOrd)]
 enum Precedence {
     Lowest,
@@ -59,11 +67,34 @@ fn get_precedence(input: ParserInput) -> Precedence {
 /// Parse an expression at the head of the current input, for as long as the expression continues.
 /// Return an error only if the first token(s) do not form an expression.
 pub(crate) fn parse_expression(input: ParserInput) -> InternalParserResult<Expression> {
-    parse(input, Precedence::Lowest)
+    parse(input, Precedence::Lowest, 0)
+}
+
+/// Parse an expression as [`parse_expression`] does, along with the span of the tokens it
+/// consumed.
+pub(crate) fn parse_expression_with_span(
+    input: ParserInput,
+) -> InternalParserResult<(Expression, SourceSpan)> {
+    let (remainder, expression) = parse_expression(input)?;
+    Ok((
+        remainder,
+        (expression, super::consumed_span(input, remainder)),
+    ))
 }
 
-/// Recursively parse an expression as long as operator precedence is satisfied.
-fn parse(input: ParserInput, precedence: Precedence) -> InternalParserResult<Expression> {
+/// Recursively parse an expression as long as operator precedence is satisfied. `depth` is the
+/// number of parentheses and function calls the expression is nested within.
+fn parse(
+    input: ParserInput,
+    precedence: Precedence,
+    depth: usize,
+) -> InternalParserResult<Expression> {
+    if depth > MAX_NESTING_DEPTH {
+        return Err(nom::Err::Failure(InternalParseError::from_kind(
+            input,
+            ParserErrorKind::NestingTooDeep(MAX_NESTING_DEPTH),
+        )));
+    }
     let (input, prefix) = opt(parse_prefix)(input)?;
     let (mut input, mut left) = match super::split_first_token(input) {
         None => unexpected_eof!(input),
@@ -84,8 +115,8 @@ fn parse(input: ParserInput, precedence: Precedence) -> InternalParserResult<Exp
         Some((Token::Variable(name), remainder)) => {
             Ok((remainder, Expression::Variable(name.clone())))
         }
-        Some((Token::Identifier(_), _)) => parse_expression_identifier(input),
-        Some((Token::LParenthesis, remainder)) => parse_grouped_expression(remainder),
+        Some((Token::Identifier(_), _)) => parse_expression_identifier(input, depth),
+        Some((Token::LParenthesis, remainder)) => parse_grouped_expression(remainder, depth + 1),
         Some((token, _)) => {
             expected_token!(input, token, "expression".to_owned())
         }
@@ -102,7 +133,7 @@ fn parse(input: ParserInput, precedence: Precedence) -> InternalParserResult<Exp
         match super::first_token(input) {
             None => return Ok((input, left)),
             Some(Token::Operator(_)) => {
-                let (remainder, expression) = parse_infix(input, left)?;
+                let (remainder, expression) = parse_infix(input, left, depth)?;
                 left = expression;
                 input = remainder;
             }
@@ -126,9 +157,10 @@ fn parse_i(input: ParserInput) -> InternalParserResult<()> {
 fn parse_function_call<'a>(
     input: ParserInput<'a>,
     function: ExpressionFunction,
+    depth: usize,
 ) -> InternalParserResult<'a, Expression> {
     let (input, _) = token!(LParenthesis)(input)?;
-    let (input, expression) = parse(input, Precedence::Lowest)?; // TODO: different precedence?
+    let (input, expression) = parse(input, Precedence::Lowest, depth + 1)?; // TODO: different precedence?
     let (input, _) = token!(RParenthesis)(input)?;
     Ok((
         input,
@@ -139,14 +171,70 @@ fn parse_function_call<'a>(
     ))
 }
 
+/// Given a function registered in the [`FunctionRegistry`] in effect, parse the comma-separated
+/// arguments within its parentheses.
+fn parse_custom_function_call<'a>(
+    input: ParserInput<'a>,
+    function: CustomFunction,
+    depth: usize,
+) -> InternalParserResult<'a, Expression> {
+    let start = input;
+    let (mut input, _) = token!(LParenthesis)(input)?;
+    let mut arguments = vec![];
+    if let Some((Token::RParenthesis, remainder)) = super::split_first_token(input) {
+        input = remainder;
+    } else {
+        loop {
+            let (remainder, argument) = parse(input, Precedence::Lowest, depth + 1)?;
+            arguments.push(argument);
+            input = match super::split_first_token(remainder) {
+                None => return unexpected_eof!(remainder),
+                Some((Token::Comma, remainder)) => remainder,
+                Some((Token::RParenthesis, remainder)) => {
+                    input = remainder;
+                    break;
+                }
+                Some((other_token, _)) => {
+                    return expected_token!(
+                        remainder,
+                        other_token,
+                        "comma or right parenthesis".to_owned()
+                    )
+                }
+            };
+        }
+    }
+    if arguments.len() != function.arity() {
+        return Err(nom::Err::Failure(InternalParseError::from_kind(
+            start,
+            ParserErrorKind::WrongArgumentCount {
+                function: function.name().to_string(),
+                expected: function.arity(),
+                actual: arguments.len(),
+            },
+        )));
+    }
+    Ok((
+        input,
+        Expression::CustomFunctionCall {
+            function,
+            arguments,
+        },
+    ))
+}
+
 /// Identifiers have to be handled specially because some have special meaning.
 ///
 /// By order of precedence:
 ///
 /// 1. Memory references with brackets
 /// 2. Special function and constant identifiers
-/// 3. Anything else is considered to be a memory reference without index brackets
-fn parse_expression_identifier(input: ParserInput) -> InternalParserResult<Expression> {
+/// 3. Calls to functions registered in the [`FunctionRegistry`] in effect
+/// 4. Anything else is considered to be a memory reference without index brackets
+fn parse_expression_identifier(
+    input: ParserInput,
+    depth: usize,
+) -> InternalParserResult<Expression> {
     let (input, memory_reference) = opt(parse_memory_reference_with_brackets)(input)?;
     if let Some(memory_reference) = memory_reference {
         return Ok((input, Expression::Address(memory_reference)));
@@ -155,20 +243,27 @@ fn parse_expression_identifier(input: ParserInput) -> InternalParserResult<Expre
     match super::split_first_token(input) {
         None => unexpected_eof!(input),
         Some((Token::Identifier(ident), remainder)) => match ident.as_str() {
-            "cis" => parse_function_call(remainder, ExpressionFunction::Cis),
-            "cos" => parse_function_call(remainder, ExpressionFunction::Cosine),
-            "exp" => parse_function_call(remainder, ExpressionFunction::Exponent),
+            "cis" => parse_function_call(remainder, ExpressionFunction::Cis, depth),
+            "cos" => parse_function_call(remainder, ExpressionFunction::Cosine, depth),
+            "exp" => parse_function_call(remainder, ExpressionFunction::Exponent, depth),
             "i" => Ok((remainder, Expression::Number(imag!(1f64)))),
             "pi" => Ok((remainder, Expression::PiConstant)),
-            "sin" => parse_function_call(remainder, ExpressionFunction::Sine),
-            "sqrt" => parse_function_call(remainder, ExpressionFunction::SquareRoot),
-            name => Ok((
-                remainder,
-                Expression::Address(MemoryReference {
-                    name: name.to_owned(),
-                    index: 0,
-                }),
-            )),
+            "sin" => parse_function_call(remainder, ExpressionFunction::Sine, depth),
+            "sqrt" => parse_function_call(remainder, ExpressionFunction::SquareRoot, depth),
+            name => match FunctionRegistry::lookup(name) {
+                Some(function)
+                    if matches!(super::first_token(remainder), Some(Token::LParenthesis)) =>
+                {
+                    parse_custom_function_call(remainder, function, depth)
+                }
+                _ => Ok((
+                    remainder,
+                    Expression::Address(MemoryReference {
+                        name: name.into(),
+                        index: 0,
+                    }),
+                )),
+            },
         },
         Some((other_token, _)) => expected_token!(input, other_token, "identifier".to_owned()),
     }
@@ -176,8 +271,8 @@ fn parse_expression_identifier(input: ParserInput) -> InternalParserResult<Expre
 
 /// To be called following an opening parenthesis, this will parse the expression to its end
 /// and then expect a closing right parenthesis.
-fn parse_grouped_expression(input: ParserInput) -> InternalParserResult<Expression> {
-    let (input, expression) = parse(input, Precedence::Lowest)?;
+fn parse_grouped_expression(input: ParserInput, depth: usize) -> InternalParserResult<Expression> {
+    let (input, expression) = parse(input, Precedence::Lowest, depth)?;
     match super::split_first_token(input) {
         None => unexpected_eof!(input),
         Some((Token::RParenthesis, remainder)) => Ok((remainder, expression)),
@@ -189,7 +284,11 @@ fn parse_grouped_expression(input: ParserInput) -> InternalParserResult<Expressi
 
 /// Parse an infix operator and then the expression to the right of the operator, and return the
 /// resulting infixed expression.
-fn parse_infix(input: ParserInput, left: Expression) -> InternalParserResult<Expression> {
+fn parse_infix(
+    input: ParserInput,
+    left: Expression,
+    depth: usize,
+) -> InternalParserResult<Expression> {
     match super::split_first_token(input) {
         None => unexpected_eof!(input),
         Some((Token::Operator(token_operator), remainder)) => {
@@ -201,7 +300,7 @@ fn parse_infix(input: ParserInput, left: Expression) -> InternalParserResult<Exp
                 Operator::Star => InfixOperator::Star,
             };
             let precedence = get_precedence(remainder);
-            let (remainder, right) = parse(remainder, precedence)?;
+            let (remainder, right) = parse(remainder, precedence, depth)?;
             let infix_expression = Expression::Infix {
                 left: Box::new(left),
                 operator: expression_operator,
@@ -38,7 +38,7 @@ pub(crate) fn parse_gate<'a>(input: ParserInput<'a>) -> InternalParserResult<'a,
     Ok((
         input,
         Instruction::Gate(Gate {
-            name,
+            name: name.into(),
             parameters,
             qubits,
             modifiers,
@@ -59,7 +59,7 @@ mod test {
         parse_gate,
         "DAGGER CONTROLLED RX(pi) 0 1",
         Instruction::Gate(Gate {
-            name: "RX".to_string(),
+            name: "RX".into(),
             parameters: vec![Expression::PiConstant],
             qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
             modifiers: vec![GateModifier::Dagger, GateModifier::Controlled],
@@ -18,11 +18,12 @@ use nom::{
     sequence::{delimited, preceded},
 };
 
-use crate::parser::{extract_nom_err, InternalParseError, InternalParserResult};
+use crate::parser::{extract_nom_err, InternalParseError, InternalParserResult, ParseError};
 use crate::{
     instruction::{
         ArithmeticOperator, BinaryOperator, ComparisonOperator, Instruction, UnaryOperator,
     },
+    program::SourceSpan,
     token,
 };
 
@@ -31,9 +32,12 @@ use super::{
     error::ParserErrorKind,
     gate,
     lexer::{Command, Token},
-    ParserInput,
+    ParserInput, TokenWithLocation,
 };
 
+/// The commands which may follow `NONBLOCKING`, as named in errors.
+const NONBLOCKING_COMMANDS: &str = "PULSE, CAPTURE, or RAW-CAPTURE";
+
 /// Parse the next instructon from the input, skipping past leading newlines, comments, and semicolons.
 pub(crate) fn parse_instruction(input: ParserInput) -> InternalParserResult<Instruction> {
     let (input, _) = common::skip_newlines_and_comments(input)?;
@@ -66,7 +70,9 @@ pub(crate) fn parse_instruction(input: ParserInput) -> InternalParserResult<Inst
             Command::LT => command::parse_comparison(ComparisonOperator::LessThan, remainder),
             Command::Fence => command::parse_fence(remainder),
             Command::Halt => Ok((remainder, Instruction::Halt)),
+            Command::Wait => Ok((remainder, Instruction::Wait)),
             Command::Include => command::parse_include(remainder),
+            Command::Call => command::parse_call(remainder),
             Command::Ior => command::parse_logical_binary(BinaryOperator::Ior, remainder),
             Command::Jump => command::parse_jump(remainder),
             Command::JumpUnless => command::parse_jump_unless(remainder),
@@ -107,9 +113,25 @@ pub(crate) fn parse_instruction(input: ParserInput) -> InternalParserResult<Inst
                 Command::Pulse => command::parse_pulse(remainder, false),
                 Command::Capture => command::parse_capture(remainder, false),
                 Command::RawCapture => command::parse_raw_capture(remainder, false),
-                _ => todo!(),
+                _ => Err(nom::Err::Failure(InternalParseError::from_kind(
+                    &input[..1],
+                    ParserErrorKind::ExpectedToken {
+                        actual: Token::Command(*command),
+                        expected: NONBLOCKING_COMMANDS.to_owned(),
+                    },
+                ))),
             },
-            _ => todo!(),
+            Some((other_token, _)) => Err(nom::Err::Failure(InternalParseError::from_kind(
+                &input[..1],
+                ParserErrorKind::ExpectedToken {
+                    actual: other_token.clone(),
+                    expected: NONBLOCKING_COMMANDS.to_owned(),
+                },
+            ))),
+            None => Err(nom::Err::Failure(InternalParseError::from_kind(
+                &input[..1],
+                ParserErrorKind::UnexpectedEOF(NONBLOCKING_COMMANDS),
+            ))),
         },
         Some((Token::Identifier(_), _)) | Some((Token::Modifier(_), _)) => gate::parse_gate(input),
         Some((_, _)) => Err(nom::Err::Failure(InternalParseError::from_kind(
@@ -129,6 +151,84 @@ pub(crate) fn parse_instructions(input: ParserInput) -> InternalParserResult<Vec
     ))(input)
 }
 
+/// Parse all instructions from the input as [`parse_instructions`] does, pairing each one with
+/// the span of the text it was parsed from (excluding surrounding whitespace and comments).
+pub(crate) fn parse_instructions_with_spans(
+    input: ParserInput,
+) -> InternalParserResult<Vec<(Instruction, SourceSpan)>> {
+    all_consuming(delimited(
+        common::skip_newlines_and_comments,
+        many0(parse_instruction_with_span),
+        common::skip_newlines_and_comments,
+    ))(input)
+}
+
+/// Parse all instructions from the input as [`parse_instructions_with_spans`] does, but rather
+/// than stopping at the first instruction which cannot be parsed, record its error and carry on
+/// from the next line which is not indented, so that an invalid block is skipped as a whole. An
+/// instruction followed by tokens which cannot be parsed on the same line is skipped as well.
+///
+/// Return the instructions which could be parsed, and the errors found, in order.
+pub(crate) fn parse_instructions_with_recovery(
+    mut input: ParserInput,
+) -> (Vec<(Instruction, SourceSpan)>, Vec<ParseError>) {
+    let mut instructions = vec![];
+    let mut errors = vec![];
+    loop {
+        if let Ok((remainder, _)) = common::skip_newlines_and_comments(input) {
+            input = remainder;
+        }
+        if input.is_empty() {
+            break;
+        }
+        let parsed = parse_instruction_with_span(input).and_then(|(remainder, parsed)| {
+            match remainder.first().map(TokenWithLocation::as_token) {
+                None | Some(Token::NewLine | Token::Semicolon | Token::Comment(_)) => {
+                    Ok((remainder, parsed))
+                }
+                _ => parse_instruction(remainder).map(|_| (remainder, parsed)),
+            }
+        });
+        match parsed {
+            Ok((remainder, parsed)) => {
+                instructions.push(parsed);
+                input = remainder;
+            }
+            Err(error) => {
+                errors.push(ParseError::from(extract_nom_err(error)));
+                input = skip_past_error(input);
+            }
+        }
+    }
+    (instructions, errors)
+}
+
+/// Skip the first token of `input`, which starts an instruction which cannot be parsed, and every
+/// token up to the end of the line (or the next semicolon), or of the indented lines which follow
+/// it.
+fn skip_past_error(input: ParserInput) -> ParserInput {
+    let mut remainder = input.get(1..).unwrap_or_default();
+    while let Some((first, rest)) = remainder.split_first() {
+        let indented = matches!(rest.first(), Some(next) if *next == Token::Indentation);
+        if matches!(first.as_token(), Token::NewLine | Token::Semicolon) && !indented {
+            return rest;
+        }
+        remainder = rest;
+    }
+    remainder
+}
+
+/// Parse the next instruction from the input as [`parse_instruction`] does, along with the span
+/// of the tokens it consumed.
+fn parse_instruction_with_span(
+    input: ParserInput,
+) -> InternalParserResult<(Instruction, SourceSpan)> {
+    let (input, _) = common::skip_newlines_and_comments(input)?;
+    let (remainder, instruction) = parse_instruction(input)?;
+    let span = super::consumed_span(input, remainder);
+    Ok((remainder, (instruction, span)))
+}
+
 /// Parse a block of indented "block instructions."
 pub(crate) fn parse_block(input: ParserInput) -> InternalParserResult<Vec<Instruction>> {
     many1(parse_block_instruction)(input)
@@ -154,15 +254,18 @@ mod tests {
     use crate::expression::{Expression, InfixOperator, PrefixOperator};
     use crate::instruction::{
         Arithmetic, ArithmeticOperand, ArithmeticOperator, AttributeValue, BinaryLogic,
-        BinaryOperand, BinaryOperator, Calibration, Capture, Comparison, ComparisonOperand,
-        ComparisonOperator, Convert, FrameDefinition, FrameIdentifier, Gate, GateDefinition,
-        GateSpecification, Include, Instruction, Jump, JumpWhen, Label, MemoryReference, Move,
-        Pulse, Qubit, RawCapture, Reset, SetFrequency, SetPhase, SetScale, ShiftFrequency,
-        ShiftPhase, UnaryLogic, UnaryOperator, Waveform, WaveformDefinition, WaveformInvocation,
+        BinaryOperand, BinaryOperator, Calibration, Call, CallArgument, Capture, Comparison,
+        ComparisonOperand, ComparisonOperator, Convert, FrameDefinition, FrameIdentifier, Gate,
+        GateDefinition, GateSpecification, Include, Instruction, Jump, JumpWhen, Label,
+        MemoryReference, Move, Pulse, Qubit, RawCapture, Reset, SetFrequency, SetPhase, SetScale,
+        ShiftFrequency, ShiftPhase, UnaryLogic, UnaryOperator, Waveform, WaveformDefinition,
+        WaveformInvocation,
     };
     use crate::parser::lexer::lex;
     use crate::{make_test, real, Program};
 
+    use super::parse_instructions_with_spans;
+
     use super::parse_instructions;
 
     make_test!(
@@ -171,19 +274,19 @@ mod tests {
         "X 0; Y 1\nZ 2",
         vec![
             Instruction::Gate(Gate {
-                name: "X".to_owned(),
+                name: "X".into(),
                 parameters: vec![],
                 qubits: vec![Qubit::Fixed(0)],
                 modifiers: vec![],
             }),
             Instruction::Gate(Gate {
-                name: "Y".to_owned(),
+                name: "Y".into(),
                 parameters: vec![],
                 qubits: vec![Qubit::Fixed(1)],
                 modifiers: vec![],
             }),
             Instruction::Gate(Gate {
-                name: "Z".to_owned(),
+                name: "Z".into(),
                 parameters: vec![],
                 qubits: vec![Qubit::Fixed(2)],
                 modifiers: vec![],
@@ -198,44 +301,44 @@ mod tests {
         vec![
             Instruction::Arithmetic(Arithmetic {
                 operator: ArithmeticOperator::Add,
-                destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                destination: MemoryReference {
+                    name: "ro".into(),
                     index: 0
-                }),
+                },
                 source: ArithmeticOperand::LiteralInteger(2),
             }),
             Instruction::Arithmetic(Arithmetic {
                 operator: ArithmeticOperator::Multiply,
-                destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                destination: MemoryReference {
+                    name: "ro".into(),
                     index: 0
-                }),
+                },
                 source: ArithmeticOperand::LiteralReal(1.0),
             }),
             Instruction::Arithmetic(Arithmetic {
                 operator: ArithmeticOperator::Subtract,
-                destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                destination: MemoryReference {
+                    name: "ro".into(),
                     index: 1
-                }),
+                },
                 source: ArithmeticOperand::LiteralInteger(-3),
             }),
             Instruction::Arithmetic(Arithmetic {
                 operator: ArithmeticOperator::Divide,
-                destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                destination: MemoryReference {
+                    name: "ro".into(),
                     index: 1
-                }),
+                },
                 source: ArithmeticOperand::LiteralReal(-1f64),
             }),
             Instruction::Arithmetic(Arithmetic {
                 operator: ArithmeticOperator::Add,
-                destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                destination: MemoryReference {
+                    name: "ro".into(),
                     index: 1
-                }),
+                },
                 source: ArithmeticOperand::MemoryReference(MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 2
                 }),
             })
@@ -251,11 +354,11 @@ mod tests {
                 operator: ComparisonOperator::Equal,
                 operands: (
                     MemoryReference {
-                        name: "dest".to_owned(),
+                        name: "dest".into(),
                         index: 0
                     },
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 0
                     },
                     ComparisonOperand::LiteralInteger(0)
@@ -265,11 +368,11 @@ mod tests {
                 operator: ComparisonOperator::LessThan,
                 operands: (
                     MemoryReference {
-                        name: "dest".to_owned(),
+                        name: "dest".into(),
                         index: 0
                     },
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 1
                     },
                     ComparisonOperand::LiteralInteger(-1)
@@ -279,11 +382,11 @@ mod tests {
                 operator: ComparisonOperator::LessThanOrEqual,
                 operands: (
                     MemoryReference {
-                        name: "dest".to_owned(),
+                        name: "dest".into(),
                         index: 0
                     },
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 0
                     },
                     ComparisonOperand::LiteralReal(1.2)
@@ -293,11 +396,11 @@ mod tests {
                 operator: ComparisonOperator::GreaterThan,
                 operands: (
                     MemoryReference {
-                        name: "dest".to_owned(),
+                        name: "dest".into(),
                         index: 0
                     },
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 2
                     },
                     ComparisonOperand::LiteralReal(0.000001)
@@ -307,15 +410,15 @@ mod tests {
                 operator: ComparisonOperator::GreaterThanOrEqual,
                 operands: (
                     MemoryReference {
-                        name: "dest".to_owned(),
+                        name: "dest".into(),
                         index: 0
                     },
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 0
                     },
                     ComparisonOperand::MemoryReference(MemoryReference {
-                        name: "x".to_owned(),
+                        name: "x".into(),
                         index: 0
                     }),
                 )
@@ -349,7 +452,7 @@ mod tests {
                 operator: BinaryOperator::And,
                 operands: (
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 0
                     },
                     BinaryOperand::LiteralInteger(1)
@@ -359,11 +462,11 @@ mod tests {
                 operator: BinaryOperator::Ior,
                 operands: (
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 1
                     },
                     BinaryOperand::MemoryReference(MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 2
                     })
                 )
@@ -372,7 +475,7 @@ mod tests {
                 operator: BinaryOperator::Xor,
                 operands: (
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 1
                     },
                     BinaryOperand::LiteralInteger(0)
@@ -382,11 +485,11 @@ mod tests {
                 operator: BinaryOperator::And,
                 operands: (
                     MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 1
                     },
                     BinaryOperand::MemoryReference(MemoryReference {
-                        name: "ro".to_owned(),
+                        name: "ro".into(),
                         index: 2
                     })
                 )
@@ -411,28 +514,28 @@ mod tests {
             Instruction::UnaryLogic(UnaryLogic {
                 operator: UnaryOperator::Not,
                 operand: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0,
                 }
             }),
             Instruction::UnaryLogic(UnaryLogic {
                 operator: UnaryOperator::Neg,
                 operand: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0,
                 }
             }),
             Instruction::UnaryLogic(UnaryLogic {
                 operator: UnaryOperator::Not,
                 operand: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 1,
                 }
             }),
             Instruction::UnaryLogic(UnaryLogic {
                 operator: UnaryOperator::Neg,
                 operand: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 1,
                 }
             }),
@@ -458,54 +561,54 @@ mod tests {
             Instruction::Capture(Capture {
                 blocking: true,
                 frame: FrameIdentifier {
-                    name: "rx".to_owned(),
+                    name: "rx".into(),
                     qubits: vec![Qubit::Fixed(0)]
                 },
                 waveform: WaveformInvocation {
-                    name: "my_custom_waveform".to_owned(),
+                    name: "my_custom_waveform".into(),
                     parameters: HashMap::new()
                 },
                 memory_reference: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0
                 }
             }),
             Instruction::RawCapture(RawCapture {
                 blocking: true,
                 frame: FrameIdentifier {
-                    name: "rx".to_owned(),
+                    name: "rx".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)]
                 },
                 duration: Expression::Number(real![2e9]),
                 memory_reference: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0
                 }
             }),
             Instruction::Capture(Capture {
                 blocking: false,
                 frame: FrameIdentifier {
-                    name: "rx".to_owned(),
+                    name: "rx".into(),
                     qubits: vec![Qubit::Fixed(0)]
                 },
                 waveform: WaveformInvocation {
-                    name: "my_custom_waveform".to_owned(),
+                    name: "my_custom_waveform".into(),
                     parameters: vec![("a".to_owned(), Expression::Number(real!(1f64)))].into_iter().collect()
                 },
                 memory_reference: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0
                 }
             }),
             Instruction::RawCapture(RawCapture {
                 blocking: false,
                 frame: FrameIdentifier {
-                    name: "rx".to_owned(),
+                    name: "rx".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)]
                 },
                 duration: Expression::Number(real![2e9]),
                 memory_reference: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0
                 }
             })
@@ -519,7 +622,7 @@ mod tests {
         parse_instructions,
         "# Questions:\nX 0",
         vec![Instruction::Gate(Gate {
-            name: "X".to_owned(),
+            name: "X".into(),
             parameters: vec![],
             qubits: vec![Qubit::Fixed(0)],
             modifiers: vec![],
@@ -531,7 +634,7 @@ mod tests {
         parse_instructions,
         "DEFFRAME 0 \"ro_rx\":\n\tDIRECTION: \"rx\"\n\n# (Pdb) settings.gates[GateID(name=\"x180\", targets=(0,))]\n\n",
         vec![Instruction::FrameDefinition(FrameDefinition {
-            identifier: FrameIdentifier { name: "ro_rx".to_owned(), qubits: vec![Qubit::Fixed(0)] },
+            identifier: FrameIdentifier { name: "ro_rx".into(), qubits: vec![Qubit::Fixed(0)] },
             attributes: [("DIRECTION".to_owned(), AttributeValue::String("rx".to_owned()))].iter().cloned().collect()
         })]);
 
@@ -540,7 +643,7 @@ mod tests {
         parse_instructions,
         "RX 0",
         vec![Instruction::Gate(Gate {
-            name: "RX".to_owned(),
+            name: "RX".into(),
             parameters: vec![],
             qubits: vec![Qubit::Fixed(0)],
             modifiers: vec![],
@@ -552,7 +655,7 @@ mod tests {
         parse_instructions,
         "RX(pi) 10",
         vec![Instruction::Gate(Gate {
-            name: "RX".to_owned(),
+            name: "RX".into(),
             parameters: vec![Expression::PiConstant],
             qubits: vec![Qubit::Fixed(10)],
             modifiers: vec![],
@@ -564,18 +667,18 @@ mod tests {
         parse_instructions,
         "DEFCAL RX(%theta) %qubit:\n\tPULSE 1 \"xy\" custom_waveform(a: 1)",
         vec![Instruction::CalibrationDefinition(Calibration {
-            name: "RX".to_owned(),
+            name: "RX".into(),
             parameters: vec![Expression::Variable("theta".to_owned())],
             qubits: vec![Qubit::Variable("qubit".to_owned())],
             modifiers: vec![],
             instructions: vec![Instruction::Pulse(Pulse {
                 blocking: true,
                 frame: FrameIdentifier {
-                    name: "xy".to_owned(),
+                    name: "xy".into(),
                     qubits: vec![Qubit::Fixed(1)]
                 },
                 waveform: WaveformInvocation {
-                    name: "custom_waveform".to_owned(),
+                    name: "custom_waveform".into(),
                     parameters: [("a".to_owned(), Expression::Number(crate::real![1f64]))]
                         .iter()
                         .cloned()
@@ -591,7 +694,7 @@ mod tests {
         "DEFFRAME 0 \"rx\":\n\tINITIAL-FREQUENCY: 2e9",
         vec![Instruction::FrameDefinition(FrameDefinition {
             identifier: FrameIdentifier {
-                name: "rx".to_owned(),
+                name: "rx".into(),
                 qubits: vec![Qubit::Fixed(0)]
             },
             attributes: [(
@@ -609,14 +712,14 @@ mod tests {
         parse_instructions,
         "LABEL @hello\nJUMP @hello\nJUMP-WHEN @hello ro",
         vec![
-            Instruction::Label(Label("hello".to_owned())),
+            Instruction::Label(Label::Fixed("hello".into())),
             Instruction::Jump(Jump {
-                target: "hello".to_owned()
+                target: Label::Fixed("hello".into())
             }),
             Instruction::JumpWhen(JumpWhen {
-                target: "hello".to_owned(),
+                target: Label::Fixed("hello".into()),
                 condition: MemoryReference {
-                    name: "ro".to_owned(),
+                    name: "ro".into(),
                     index: 0
                 }
             })
@@ -631,33 +734,33 @@ mod tests {
             Instruction::Pulse(Pulse {
                 blocking: true,
                 frame: FrameIdentifier {
-                    name: "xy".to_owned(),
+                    name: "xy".into(),
                     qubits: vec![Qubit::Fixed(0)]
                 },
                 waveform: WaveformInvocation {
-                    name: "custom".to_owned(),
+                    name: "custom".into(),
                     parameters: HashMap::new()
                 }
             }),
             Instruction::Pulse(Pulse {
                 blocking: false,
                 frame: FrameIdentifier {
-                    name: "xy".to_owned(),
+                    name: "xy".into(),
                     qubits: vec![Qubit::Fixed(0)]
                 },
                 waveform: WaveformInvocation {
-                    name: "custom".to_owned(),
+                    name: "custom".into(),
                     parameters: HashMap::new()
                 }
             }),
             Instruction::Pulse(Pulse {
                 blocking: true,
                 frame: FrameIdentifier {
-                    name: "xy".to_owned(),
+                    name: "xy".into(),
                     qubits: vec![Qubit::Fixed(0)]
                 },
                 waveform: WaveformInvocation {
-                    name: "custom".to_owned(),
+                    name: "custom".into(),
                     parameters: vec![("a".to_owned(), Expression::Number(real!(1f64)))]
                         .into_iter()
                         .collect()
@@ -671,10 +774,10 @@ mod tests {
         parse_instructions,
         "MOVE a 1.0",
         vec![Instruction::Move(Move {
-            destination: ArithmeticOperand::MemoryReference(MemoryReference {
-                name: "a".to_owned(),
+            destination: MemoryReference {
+                name: "a".into(),
                 index: 0
-            }),
+            },
             source: ArithmeticOperand::LiteralReal(1.0)
         })]
     );
@@ -696,7 +799,7 @@ mod tests {
         parse_instructions,
         "DEFWAVEFORM q44_q45_cphase/sqrtCPHASE:\n\t0.0, 0.0, 0.00027685415721916584",
         vec![Instruction::WaveformDefinition(WaveformDefinition {
-            name: "q44_q45_cphase/sqrtCPHASE".to_owned(),
+            name: "q44_q45_cphase/sqrtCPHASE".into(),
             definition: Waveform {
                 matrix: vec![
                     Expression::Number(real!(0.0)),
@@ -713,7 +816,7 @@ mod tests {
         parse_instructions,
         "DEFGATE H:\n\t1/sqrt(2), 1/sqrt(2)\n\t1/sqrt(2), -1/sqrt(2)\n",
         vec![Instruction::GateDefinition(GateDefinition {
-            name: "H".to_string(),
+            name: "H".into(),
             parameters: vec![],
             specification: GateSpecification::Matrix(vec![
                 vec![
@@ -765,11 +868,11 @@ mod tests {
         "CONVERT theta unadjusted-theta[1]",
         vec![Instruction::Convert(Convert {
             from: MemoryReference {
-                name: "unadjusted-theta".to_string(),
+                name: "unadjusted-theta".into(),
                 index: 1
             },
             to: MemoryReference {
-                name: "theta".to_string(),
+                name: "theta".into(),
                 index: 0
             },
         })]
@@ -786,6 +889,37 @@ mod tests {
 
     make_test!(nop, parse_instructions, r#"NOP"#, vec![Instruction::Nop]);
 
+    make_test!(
+        wait,
+        parse_instructions,
+        "WAIT\nHALT",
+        vec![Instruction::Wait, Instruction::Halt]
+    );
+
+    make_test!(
+        call,
+        parse_instructions,
+        "CALL rng seed ro[1] -2 0.5\nCALL reset_all",
+        vec![
+            Instruction::Call(Call {
+                name: "rng".to_string(),
+                arguments: vec![
+                    CallArgument::Identifier("seed".to_string()),
+                    CallArgument::MemoryReference(MemoryReference {
+                        name: "ro".into(),
+                        index: 1
+                    }),
+                    CallArgument::LiteralInteger(-2),
+                    CallArgument::LiteralReal(0.5),
+                ],
+            }),
+            Instruction::Call(Call {
+                name: "reset_all".to_string(),
+                arguments: vec![],
+            }),
+        ]
+    );
+
     #[test]
     fn parse_set_phase() {
         let input = LocatedSpan::new(r#"SET-PHASE 0 "rf" 1.0; SET-PHASE 0 1 "rf" theta"#);
@@ -794,18 +928,18 @@ mod tests {
         let expected = vec![
             Instruction::SetPhase(SetPhase {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0)],
                 },
                 phase: Expression::Number(real!(1.0)),
             }),
             Instruction::SetPhase(SetPhase {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
                 },
                 phase: Expression::Address(MemoryReference {
-                    name: String::from("theta"),
+                    name: "theta".into(),
                     index: 0,
                 }),
             }),
@@ -822,18 +956,18 @@ mod tests {
         let expected = vec![
             Instruction::SetScale(SetScale {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0)],
                 },
                 scale: Expression::Number(real!(1.0)),
             }),
             Instruction::SetScale(SetScale {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
                 },
                 scale: Expression::Address(MemoryReference {
-                    name: String::from("theta"),
+                    name: "theta".into(),
                     index: 0,
                 }),
             }),
@@ -850,18 +984,18 @@ mod tests {
         let expected = vec![
             Instruction::SetFrequency(SetFrequency {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0)],
                 },
                 frequency: Expression::Number(real!(1.0)),
             }),
             Instruction::SetFrequency(SetFrequency {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
                 },
                 frequency: Expression::Address(MemoryReference {
-                    name: String::from("theta"),
+                    name: "theta".into(),
                     index: 0,
                 }),
             }),
@@ -879,18 +1013,18 @@ mod tests {
         let expected = vec![
             Instruction::ShiftFrequency(ShiftFrequency {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0)],
                 },
                 frequency: Expression::Number(real!(1.0)),
             }),
             Instruction::ShiftFrequency(ShiftFrequency {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
                 },
                 frequency: Expression::Address(MemoryReference {
-                    name: String::from("theta"),
+                    name: "theta".into(),
                     index: 0,
                 }),
             }),
@@ -907,18 +1041,18 @@ mod tests {
         let expected = vec![
             Instruction::ShiftPhase(ShiftPhase {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0)],
                 },
                 phase: Expression::Number(real!(1.0)),
             }),
             Instruction::ShiftPhase(ShiftPhase {
                 frame: FrameIdentifier {
-                    name: String::from("rf"),
+                    name: "rf".into(),
                     qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
                 },
                 phase: Expression::Address(MemoryReference {
-                    name: String::from("theta"),
+                    name: "theta".into(),
                     index: 0,
                 }),
             }),
@@ -927,6 +1061,28 @@ mod tests {
         assert_eq!(parsed, expected);
     }
 
+    #[test]
+    fn parse_spans() {
+        let input = "H 0 # hadamard\n\nDEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL  0 1\n";
+        let tokens = lex(LocatedSpan::new(input)).unwrap();
+        let (_, parsed) = parse_instructions_with_spans(&tokens).unwrap();
+        let spans: Vec<_> = parsed
+            .iter()
+            .map(|(_, span)| (&input[span.range()], span.to_string()))
+            .collect();
+        assert_eq!(
+            spans,
+            vec![
+                ("H 0", "1:1-1:4".to_string()),
+                (
+                    "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b",
+                    "3:1-5:13".to_string()
+                ),
+                ("BELL  0 1", "7:1-7:10".to_string()),
+            ]
+        );
+    }
+
     /// Assert that when a program is converted to a string, the conversion of
     /// that string into a program produces a program identical to the original
     /// program.
@@ -936,6 +1092,11 @@ mod tests {
             r#"DEFCAL MEASURE 0 dest:
 	DECLARE iq REAL[2]
 	CAPTURE 0 "out" flat(duration: 1.0, iqs: (2.0+3.0i)) iq[0]"#,
+            "DEFCAL MEASURE 0:\n\tNOP\n",
+            "DECLARE params REAL[4]\nDECLARE theta REAL[2] SHARING params OFFSET 1 REAL\n",
+            "DEFCAL MEASURE q dest:\n\tNOP\n",
+            "DEFCAL MEASURE dest:\n\tNOP\n",
+            "DEFGATE PAULISUM p q AS PAULI-SUM:\n\tZZ(-pi/4) p q\n\tY(1.5) p\n",
         ];
 
         for input in inputs {
@@ -33,4 +33,7 @@ pub enum LexErrorKind {
     /// Encountered an unexpected EOF
     #[error("unexpected EOF while parsing")]
     UnexpectedEOF,
+    /// An integer literal was too large to be represented.
+    #[error("integer literal is too large")]
+    IntegerOverflow,
 }
@@ -41,6 +41,7 @@ pub use error::{LexError, LexErrorKind};
 pub enum Command {
     Add,
     And,
+    Call,
     Capture,
     Convert,
     Declare,
@@ -88,6 +89,7 @@ pub enum Command {
     ShiftPhase,
     Store,
     Sub,
+    Wait,
     Xor,
 }
 
@@ -135,6 +137,53 @@ pub(crate) fn lex(input: LexInput) -> Result<Vec<TokenWithLocation>, LexError> {
         .map_err(LexError::from)
 }
 
+/// Lex a string like [`lex`], but recognize keywords written in any case, such as `measure` or
+/// `Defgate`. Other identifiers, including the names of gates, keep their case.
+pub(crate) fn lex_case_insensitive(input: LexInput) -> Result<Vec<TokenWithLocation>, LexError> {
+    let mut tokens = lex(input)?;
+    for token in &mut tokens {
+        if let Token::Identifier(identifier) = token.as_token() {
+            if let Some(keyword) = recognize_keyword(&identifier.to_ascii_uppercase()) {
+                *token.as_token_mut() = keyword;
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+/// Lex as much of a string as possible, as [`lex`] would. Each line which cannot be lexed is
+/// replaced with a comment of the same length, so that the locations of the tokens on other lines
+/// are unchanged, and the error found in it is returned. A line which cannot be replaced in this
+/// way is dropped from the input, along with the lines after it.
+///
+/// Return the input as replaced, which can be lexed, with the errors found in order.
+pub(crate) fn blank_unlexable_lines(input: &str) -> (String, Vec<LexError>) {
+    let mut text = input.to_string();
+    let mut errors = vec![];
+    while let Err(error) = lex(LocatedSpan::new(&text)) {
+        let start: usize = text
+            .split_inclusive('\n')
+            .take(error.line() as usize - 1)
+            .map(str::len)
+            .sum();
+        let end = text[start..]
+            .find('\n')
+            .map_or(text.len(), |end| start + end);
+        let blank = match end - start {
+            0 => String::new(),
+            1 => " ".to_string(),
+            length => format!("#{}", " ".repeat(length - 1)),
+        };
+        if text[start..end] == blank {
+            text.truncate(start);
+        } else {
+            text.replace_range(start..end, &blank);
+        }
+        errors.push(error);
+    }
+    (text, errors)
+}
+
 fn _lex(input: LexInput) -> InternalLexResult<Vec<TokenWithLocation>> {
     terminated(
         many0(alt(
@@ -221,7 +270,9 @@ fn recognize_command_or_identifier(identifier: String) -> Token {
         "JUMP" => Token::Command(Jump),
         "RESET" => Token::Command(Reset),
         "NOP" => Token::Command(Nop),
+        "WAIT" => Token::Command(Wait),
         "INCLUDE" => Token::Command(Include),
+        "CALL" => Token::Command(Call),
         "PRAGMA" => Token::Command(Pragma),
         "DECLARE" => Token::Command(Declare),
         "CAPTURE" => Token::Command(Capture),
@@ -242,6 +293,30 @@ fn recognize_command_or_identifier(identifier: String) -> Token {
     }
 }
 
+/// If the given uppercase identifier matches any keyword, return that keyword.
+fn recognize_keyword(identifier: &str) -> Option<Token> {
+    let token = match identifier {
+        "AS" => Token::As,
+        "BIT" => Token::DataType(DataType::Bit),
+        "INTEGER" => Token::DataType(DataType::Integer),
+        "OCTET" => Token::DataType(DataType::Octet),
+        "REAL" => Token::DataType(DataType::Real),
+        "CONTROLLED" => Token::Modifier(Modifier::Controlled),
+        "DAGGER" => Token::Modifier(Modifier::Dagger),
+        "FORKED" => Token::Modifier(Modifier::Forked),
+        "MATRIX" => Token::Matrix,
+        "NONBLOCKING" => Token::NonBlocking,
+        "PAULI-SUM" => Token::PauliSum,
+        "PERMUTATION" => Token::Permutation,
+        "SHARING" => Token::Sharing,
+        _ => match recognize_command_or_identifier(identifier.to_string()) {
+            token @ Token::Command(_) => token,
+            _ => return None,
+        },
+    };
+    Some(token)
+}
+
 fn is_valid_identifier_leading_character(chr: char) -> bool {
     chr.is_ascii_alphabetic() || chr == '_'
 }
@@ -250,8 +325,9 @@ fn is_valid_identifier_end_character(chr: char) -> bool {
     is_valid_identifier_leading_character(chr) || chr.is_ascii_digit()
 }
 
+/// Identifiers may contain `.` to separate a namespace from a name, such as `mylib.MYGATE`.
 fn is_valid_identifier_middle_character(chr: char) -> bool {
-    is_valid_identifier_end_character(chr) || chr == '-'
+    is_valid_identifier_end_character(chr) || chr == '-' || chr == '.'
 }
 
 fn lex_identifier_raw(input: LexInput) -> InternalLexResult<String> {
@@ -299,7 +375,12 @@ fn lex_number(input: LexInput) -> InternalLexResult {
     Ok((
         input,
         match integer_parse_result {
-            Ok(_) => Token::Integer(float_string.parse::<u64>().unwrap()),
+            Ok(_) => Token::Integer(float_string.parse::<u64>().map_err(|_| {
+                nom::Err::Failure(InternalLexError::from_kind(
+                    float_string,
+                    LexErrorKind::IntegerOverflow,
+                ))
+            })?),
             Err(_) => Token::Float(double(float_string)?.1),
         },
     ))
@@ -314,6 +395,7 @@ fn lex_modifier(input: LexInput) -> InternalLexResult {
             value(Token::Modifier(Modifier::Controlled), tag("CONTROLLED")),
             value(Token::Modifier(Modifier::Dagger), tag("DAGGER")),
             value(Token::Modifier(Modifier::Forked), tag("FORKED")),
+            value(Token::PauliSum, tag("PAULI-SUM")),
             value(Token::Permutation, tag("PERMUTATION")),
             value(Token::Sharing, tag("SHARING")),
         ),
@@ -383,7 +465,24 @@ mod tests {
     use nom_locate::LocatedSpan;
     use rstest::*;
 
-    use super::{lex, Command, Operator, Token};
+    use super::{
+        blank_unlexable_lines, lex, lex_case_insensitive, Command, DataType, Modifier, Operator,
+        Token,
+    };
+
+    #[rstest]
+    #[case("H 0\nX 1", "H 0\nX 1", vec![])]
+    #[case("H 0\nX ` 1\nY 2", "H 0\n#    \nY 2", vec![2])]
+    #[case("`\n\"unterminated", " \n#            ", vec![1, 2])]
+    fn blank_lines(#[case] input: &str, #[case] expected: &str, #[case] lines: Vec<u32>) {
+        let (text, errors) = blank_unlexable_lines(input);
+        assert_eq!(text, expected);
+        assert_eq!(
+            errors.iter().map(|error| error.line()).collect::<Vec<_>>(),
+            lines
+        );
+        assert!(lex(LocatedSpan::new(&text)).is_ok());
+    }
 
     #[test]
     fn comment() {
@@ -417,6 +516,30 @@ mod tests {
         )
     }
 
+    #[test]
+    fn case_insensitive_keywords() {
+        let input = LocatedSpan::new("measure q ro[0]\ndagger h bit Real nonblocking Jump-When");
+        let tokens = lex_case_insensitive(input).unwrap();
+        assert_eq!(
+            tokens,
+            vec![
+                Token::Command(Command::Measure),
+                Token::Identifier("q".to_owned()),
+                Token::Identifier("ro".to_owned()),
+                Token::LBracket,
+                Token::Integer(0),
+                Token::RBracket,
+                Token::NewLine,
+                Token::Modifier(Modifier::Dagger),
+                Token::Identifier("h".to_owned()),
+                Token::DataType(DataType::Bit),
+                Token::DataType(DataType::Real),
+                Token::NonBlocking,
+                Token::Command(Command::JumpWhen),
+            ]
+        )
+    }
+
     #[test]
     fn number() {
         let input = LocatedSpan::new("2 2i 2.0 2e3 2.0e3 (1+2i)");
@@ -542,6 +665,7 @@ mod tests {
         case("_", vec![Token::Identifier("_".to_string())]),
         case("a", vec![Token::Identifier("a".to_string())]),
         case("_a-2_b-2_", vec![Token::Identifier("_a-2_b-2_".to_string())]),
+        case("mylib.MYGATE", vec![Token::Identifier("mylib.MYGATE".to_string())]),
     )]
     fn it_lexes_identifier(input: &str, expected: Vec<Token>) {
         let input = LocatedSpan::new(input);
@@ -553,6 +677,7 @@ mod tests {
         case("a-", vec![Token::Identifier("_-".to_string())]),
         case("-a", vec![Token::Identifier("-a".to_string())]),
         case("a\\", vec![Token::Identifier("_\\".to_string())]),
+        case("a.", vec![Token::Identifier("a.".to_string())]),
     )]
     fn it_fails_to_lex_identifier(input: &str, not_expected: Vec<Token>) {
         let input = LocatedSpan::new(input);
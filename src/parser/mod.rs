@@ -14,9 +14,13 @@
 
 use nom::IResult;
 
-pub(crate) use expression::parse_expression;
-pub(crate) use instruction::parse_instructions;
-pub(crate) use lexer::lex;
+use crate::program::{SourceLocation, SourceSpan};
+
+pub(crate) use expression::parse_expression_with_span;
+pub(crate) use instruction::{
+    parse_instructions, parse_instructions_with_recovery, parse_instructions_with_spans,
+};
+pub(crate) use lexer::{blank_unlexable_lines, lex, lex_case_insensitive};
 
 mod command;
 mod gate;
@@ -29,9 +33,9 @@ pub(crate) mod instruction;
 mod lexer;
 mod token;
 
-pub(crate) use error::{ErrorInput, InternalParseError};
+pub(crate) use error::{ErrorInput, Excerpt, InternalParseError};
 pub use error::{ParseError, ParserErrorKind};
-pub use lexer::{LexError, LexErrorKind};
+pub use lexer::{Command, DataType, LexError, LexErrorKind, Modifier, Operator};
 pub use token::{Token, TokenWithLocation};
 
 type ParserInput<'a> = &'a [TokenWithLocation<'a>];
@@ -52,6 +56,29 @@ pub(crate) fn first_token(input: ParserInput) -> Option<&Token> {
     input.first().map(TokenWithLocation::as_token)
 }
 
+/// The span of the tokens of `input` which were consumed to leave `remainder`, from the start of
+/// the first to the end of the last, excluding any whitespace after it.
+pub(crate) fn consumed_span(input: ParserInput, remainder: ParserInput) -> SourceSpan {
+    match input.first() {
+        Some(first) => {
+            let text = first.remaining_input();
+            let start = SourceLocation {
+                offset: first.offset(),
+                line: first.line(),
+                column: first.column(),
+            };
+            let end = remainder
+                .first()
+                .map_or(start.offset + text.len(), |next| next.offset());
+            SourceSpan {
+                start,
+                end: start.advance(text[..end - start.offset].trim_end()),
+            }
+        }
+        None => SourceSpan::default(),
+    }
+}
+
 /// Extracts the actual error from [`nom::Err`].
 ///
 /// Instead of using this with [`Result::map_err`], use [`nom::Finish::finish`].
@@ -7,6 +7,8 @@ use std::fmt::Formatter;
 pub struct TokenWithLocation<'a> {
     token: Token,
     original_input: LexInput<'a>,
+    /// the length in bytes of the text of the token
+    length: usize,
 }
 
 impl PartialEq<Token> for TokenWithLocation<'_> {
@@ -15,12 +17,17 @@ impl PartialEq<Token> for TokenWithLocation<'_> {
     }
 }
 
-impl TokenWithLocation<'_> {
+impl<'a> TokenWithLocation<'a> {
     /// Returns a reference to the contained token.
     pub fn as_token(&self) -> &Token {
         &self.token
     }
 
+    /// Returns a mutable reference to the contained token.
+    pub(crate) fn as_token_mut(&mut self) -> &mut Token {
+        &mut self.token
+    }
+
     /// Converts this `TokenWithLocation` into the contained [`Token`].
     pub fn into_token(self) -> Token {
         self.token
@@ -35,6 +42,26 @@ impl TokenWithLocation<'_> {
     pub fn column(&self) -> usize {
         self.original_input.get_utf8_column()
     }
+
+    /// The byte offset of this token from the start of the input.
+    pub(crate) fn offset(&self) -> usize {
+        self.original_input.location_offset()
+    }
+
+    /// The remainder of the input, starting with this token.
+    pub(crate) fn remaining_input(&self) -> &'a str {
+        self.original_input.fragment()
+    }
+
+    /// The text of the line this token appears on.
+    pub(crate) fn line_text(&self) -> &[u8] {
+        self.original_input.get_line_beginning()
+    }
+
+    /// The text of this token.
+    pub(crate) fn text(&self) -> &'a str {
+        &self.original_input.fragment()[..self.length]
+    }
 }
 
 impl nom::InputLength for TokenWithLocation<'_> {
@@ -60,6 +87,7 @@ where
                 TokenWithLocation {
                     token,
                     original_input: input,
+                    length: input.fragment().len() - leftover.fragment().len(),
                 },
             )
         })
@@ -86,6 +114,7 @@ pub enum Token {
     Modifier(Modifier),
     NewLine,
     Operator(Operator),
+    PauliSum,
     Permutation,
     RBracket,
     RParenthesis,
@@ -116,6 +145,7 @@ impl fmt::Display for Token {
             Token::Modifier(m) => write!(f, "{}", m),
             Token::NewLine => write!(f, "NEWLINE"),
             Token::Operator(op) => write!(f, "{}", op),
+            Token::PauliSum => write!(f, "PAULI-SUM"),
             Token::Permutation => write!(f, "PERMUTATION"),
             Token::RBracket => write!(f, "]"),
             Token::RParenthesis => write!(f, ")"),
@@ -148,6 +178,7 @@ impl fmt::Debug for Token {
             Token::Modifier(m) => write!(f, "MODIFIER({})", m),
             Token::NewLine => write!(f, "NEWLINE"),
             Token::Operator(op) => write!(f, "OPERATOR({})", op),
+            Token::PauliSum => write!(f, "{}", self),
             Token::Permutation => write!(f, "{}", self),
             Token::RBracket => write!(f, "RBRACKET"),
             Token::RParenthesis => write!(f, "RPAREN"),
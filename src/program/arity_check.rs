@@ -0,0 +1,331 @@
+//! Check that gate invocations match the arity of their definitions.
+//!
+//! Each gate applied in a program must be given as many qubits and parameters as its definition,
+//! whether that is a `DEFGATE` or `DEFCIRCUIT` in the program or a gate from the Quil standard
+//! gate set. See the [Quil spec](https://quil-lang.github.io/).
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use thiserror::Error;
+
+use crate::instruction::{
+    gates, CircuitDefinition, Gate, GateDefinition, GateModifier, GateSpecification, Instruction,
+};
+use crate::Program;
+
+/// Where a gate is defined.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefinitionSite {
+    /// The gate is one of the Quil standard gates.
+    StandardGate,
+    /// The gate is defined by the `DEFGATE` or `DEFCIRCUIT` at this index of the program's
+    /// instructions.
+    Instruction(usize),
+}
+
+impl fmt::Display for DefinitionSite {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefinitionSite::StandardGate => write!(f, "the Quil standard gate set"),
+            DefinitionSite::Instruction(index) => {
+                write!(f, "the definition at instruction {index}")
+            }
+        }
+    }
+}
+
+/// Different types of errors that can occur during arity checking.
+#[derive(Debug, Error)]
+pub enum ArityError {
+    #[error(
+        "In instruction {instruction}: expected {expected} qubits, as given by {site}, but found {found}."
+    )]
+    QubitCountMismatch {
+        instruction: Box<Instruction>,
+        site: DefinitionSite,
+        expected: usize,
+        found: usize,
+    },
+
+    #[error(
+        "In instruction {instruction}: expected {expected} parameters, as given by {site}, but found {found}."
+    )]
+    ParameterCountMismatch {
+        instruction: Box<Instruction>,
+        site: DefinitionSite,
+        expected: usize,
+        found: usize,
+    },
+}
+
+pub type ArityResult<T> = Result<T, ArityError>;
+
+/// The number of parameters and qubits taken by a gate, before any modifiers are applied.
+#[derive(Clone, Copy, Debug)]
+struct Arity {
+    parameters: usize,
+    qubits: usize,
+    site: DefinitionSite,
+}
+
+impl Arity {
+    fn new(parameters: usize, qubits: usize, site: DefinitionSite) -> Self {
+        Self {
+            parameters,
+            qubits,
+            site,
+        }
+    }
+}
+
+/// Return the arity of the Quil standard gate `name`, if it is one.
+fn standard_gate_arity(name: &str) -> Option<Arity> {
+    gates::standard_gate_arity(name)
+        .map(|(parameters, qubits)| Arity::new(parameters, qubits, DefinitionSite::StandardGate))
+}
+
+/// The number of qubits acted on by a gate whose matrix or permutation has `size` rows.
+fn qubits_for_size(size: usize) -> usize {
+    size.checked_ilog2().unwrap_or(0) as usize
+}
+
+/// Collect the arity of every gate and circuit defined in `program`. Definitions within the
+/// program take precedence over the standard gates of the same name.
+fn defined_arities(program: &Program) -> HashMap<&str, Arity> {
+    let mut arities = HashMap::new();
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        let site = DefinitionSite::Instruction(index);
+        match instruction {
+            Instruction::GateDefinition(GateDefinition {
+                name,
+                parameters,
+                specification,
+            }) => {
+                let qubits = match specification {
+                    GateSpecification::Matrix(rows) => qubits_for_size(rows.len()),
+                    GateSpecification::Permutation(permutation) => {
+                        qubits_for_size(permutation.len())
+                    }
+                    GateSpecification::PauliSum(pauli_sum) => pauli_sum.arguments.len(),
+                };
+                arities.insert(name.as_str(), Arity::new(parameters.len(), qubits, site));
+            }
+            Instruction::CircuitDefinition(CircuitDefinition {
+                name,
+                parameters,
+                qubit_variables,
+                ..
+            }) => {
+                arities.insert(
+                    name.as_str(),
+                    Arity::new(parameters.len(), qubit_variables.len(), site),
+                );
+            }
+            _ => {}
+        }
+    }
+    arities
+}
+
+/// Check a single gate invocation against the arity of its definition, if it has one.
+fn arity_check_gate(
+    instruction: &Instruction,
+    gate: &Gate,
+    arities: &HashMap<&str, Arity>,
+) -> ArityResult<()> {
+    let arity = match arities
+        .get(gate.name.as_str())
+        .copied()
+        .or_else(|| standard_gate_arity(&gate.name))
+    {
+        Some(arity) => arity,
+        None => return Ok(()),
+    };
+
+    // Each CONTROLLED modifier adds a control qubit; each FORKED modifier adds a qubit and doubles
+    // the number of parameters.
+    let mut expected_qubits = arity.qubits;
+    let mut expected_parameters = arity.parameters;
+    for modifier in &gate.modifiers {
+        match modifier {
+            GateModifier::Controlled => expected_qubits += 1,
+            GateModifier::Dagger => {}
+            GateModifier::Forked => {
+                expected_qubits += 1;
+                expected_parameters *= 2;
+            }
+        }
+    }
+
+    if gate.parameters.len() != expected_parameters {
+        return Err(ArityError::ParameterCountMismatch {
+            instruction: Box::new(instruction.clone()),
+            site: arity.site,
+            expected: expected_parameters,
+            found: gate.parameters.len(),
+        });
+    }
+    if gate.qubits.len() != expected_qubits {
+        return Err(ArityError::QubitCountMismatch {
+            instruction: Box::new(instruction.clone()),
+            site: arity.site,
+            expected: expected_qubits,
+            found: gate.qubits.len(),
+        });
+    }
+    Ok(())
+}
+
+/// Check every gate in `instructions`, including those within the bodies of circuit definitions.
+fn arity_check_instructions(
+    instructions: &[Instruction],
+    arities: &HashMap<&str, Arity>,
+) -> ArityResult<()> {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Gate(gate) => arity_check_gate(instruction, gate, arities)?,
+            Instruction::CircuitDefinition(definition) => {
+                arity_check_instructions(&definition.instructions, arities)?
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Check that every gate applied in the given program, including within the bodies of its
+/// circuit definitions, has as many qubits and parameters as its definition calls for. Gates
+/// without a definition in the program or the Quil standard gate set are not checked.
+pub fn arity_check(program: &Program) -> ArityResult<()> {
+    let arities = defined_arities(program);
+    arity_check_instructions(&program.instructions, &arities)
+}
+
+/// Check every instruction of `program` like [`arity_check`], returning the first error found in
+/// each instruction along with the index of the instruction, rather than stopping at the first.
+pub(crate) fn arity_errors(program: &Program) -> Vec<(usize, ArityError)> {
+    let arities = defined_arities(program);
+    program
+        .instructions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, instruction)| {
+            arity_check_instructions(std::slice::from_ref(instruction), &arities)
+                .err()
+                .map(|error| (index, error))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+    use rstest::*;
+    use std::str::FromStr;
+
+    #[rstest]
+    #[case("H 0", true)]
+    #[case("H 0 1", false)]
+    #[case("H(pi) 0", false)]
+    #[case("RX(pi) 0", true)]
+    #[case("RX 0", false)]
+    #[case("CNOT 0", false)]
+    #[case("CPHASE(pi) 0 1", true)]
+    #[case("CONTROLLED RX(pi) 0 1", true)]
+    #[case("CONTROLLED RX(pi) 0", false)]
+    #[case("DAGGER CONTROLLED CONTROLLED X 0 1 2", true)]
+    #[case("FORKED RX(pi, pi/2) 0 1", true)]
+    #[case("FORKED RX(pi) 0 1", false)]
+    #[case("UNDEFINED(pi) 0 1 2", true)]
+    fn test_standard_gates(#[case] input: &str, #[case] ok: bool) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(arity_check(&program).is_ok(), ok, "{input}");
+    }
+
+    #[test]
+    fn test_defined_gate() {
+        let program = Program::from_str(
+            r#"DEFGATE MYGATE(%theta):
+    1, 0, 0, 0
+    0, 1, 0, 0
+    0, 0, 1, 0
+    0, 0, 0, %theta
+
+MYGATE(pi) 0 1
+MYGATE(pi) 0
+"#,
+        )
+        .unwrap();
+
+        match arity_check(&program) {
+            Err(ArityError::QubitCountMismatch {
+                site,
+                expected,
+                found,
+                ..
+            }) => {
+                assert_eq!(site, DefinitionSite::Instruction(0));
+                assert_eq!((expected, found), (2, 1));
+            }
+            other => panic!("expected a qubit count mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_circuit_definition() {
+        let program = Program::from_str(
+            r#"DEFGATE H:
+    1, 0
+    0, -1
+
+DEFCIRCUIT BELL(%theta) a b:
+    H a
+    CNOT a b
+
+BELL 0 1
+"#,
+        )
+        .unwrap();
+
+        let error = arity_check(&program).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "In instruction BELL 0 1: expected 1 parameters, as given by the definition at instruction 1, but found 0."
+        );
+    }
+
+    #[test]
+    fn test_circuit_body() {
+        let program = Program::from_str(
+            r#"DEFCIRCUIT BAD a:
+    CNOT a
+
+BAD 0
+"#,
+        )
+        .unwrap();
+
+        match arity_check(&program) {
+            Err(ArityError::QubitCountMismatch { site, .. }) => {
+                assert_eq!(site, DefinitionSite::StandardGate)
+            }
+            other => panic!("expected a qubit count mismatch, got {other:?}"),
+        }
+    }
+}
@@ -17,8 +17,8 @@ use std::collections::HashMap;
 use crate::{
     expression::Expression,
     instruction::{
-        Calibration, Delay, Gate, GateModifier, Instruction, MeasureCalibrationDefinition,
-        Measurement, Qubit,
+        visit::InstructionRewriter, Calibration, Delay, Gate, GateModifier, Instruction,
+        MeasureCalibrationDefinition, Measurement, MemoryReference, Qubit,
     },
 };
 
@@ -31,27 +31,233 @@ pub struct CalibrationSet {
     measure_calibrations: Vec<MeasureCalibrationDefinition>,
 }
 
-struct MatchedCalibration<'a> {
-    pub calibration: &'a Calibration,
-    pub fixed_qubit_count: usize,
+/// The number of the qubits of `calibration` which are fixed, rather than variable.
+fn fixed_qubit_count(calibration: &Calibration) -> usize {
+    calibration
+        .qubits
+        .iter()
+        .filter(|q| match q {
+            Qubit::Fixed(_) => true,
+            Qubit::Variable(_) => false,
+        })
+        .count()
+}
+
+/// How specific `calibration` is to a measurement, with or without a target: most specific if
+/// it names the qubit measured, rather than being for any qubit, and then more so if it has a
+/// destination exactly when the measurement has a target.
+fn measurement_specificity(calibration: &MeasureCalibrationDefinition, has_target: bool) -> usize {
+    let names_qubit = matches!(calibration.qubit, Some(Qubit::Fixed(_)));
+    2 * usize::from(names_qubit) + usize::from(calibration.parameter.is_some() == has_target)
+}
+
+/// Substitutes the qubit and target of a measurement into the instructions of its calibration.
+struct MeasureCalibrationRewriter<'a> {
+    /// The calibration's qubit variable, if it has one, and the qubit measured.
+    qubit: Option<(&'a str, &'a Qubit)>,
+    /// The calibration's destination, if it has one, and the measurement's target.
+    target: Option<(&'a str, &'a MemoryReference)>,
+}
+
+impl InstructionRewriter for MeasureCalibrationRewriter<'_> {
+    fn rewrite_qubit(&mut self, qubit: &mut Qubit) {
+        if let (Qubit::Variable(name), Some((variable, measured))) = (&*qubit, self.qubit) {
+            if name == variable {
+                *qubit = measured.clone();
+            }
+        }
+    }
+
+    fn rewrite_memory_reference(&mut self, memory_reference: &mut MemoryReference) {
+        if let Some((destination, target)) = self.target {
+            if memory_reference.name == destination {
+                *memory_reference = target.clone();
+            }
+        }
+    }
+}
+
+/// A calibration definition: of a gate, by `DEFCAL`, or of a measurement, by `DEFCAL MEASURE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CalibrationDefinitionRef<'a> {
+    Gate(&'a Calibration),
+    Measurement(&'a MeasureCalibrationDefinition),
+}
+
+/// A calibration which matches an instruction, as reported by [`CalibrationSet::matches`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CalibrationCandidate<'a> {
+    pub calibration: CalibrationDefinitionRef<'a>,
+    /// The position of the calibration among the set's calibrations of its kind, in the order
+    /// they were added.
+    pub index: usize,
+    /// How specific the calibration is to the instruction: for a gate, the number of its qubits
+    /// which are fixed rather than variable; for a measurement, two if it names the qubit rather
+    /// than being for any qubit, plus one if it has a destination exactly when the measurement
+    /// has a target.
+    pub specificity: usize,
 }
 
-impl<'a> MatchedCalibration<'a> {
-    pub fn new(calibration: &'a Calibration) -> Self {
+/// Why a calibration was selected to expand an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SelectionReason {
+    /// No other calibration matches the instruction.
+    OnlyMatch,
+    /// The calibration is more specific than any other which matches.
+    MostSpecific,
+    /// Other calibrations which match are as specific, and this one was added last.
+    AddedLast,
+}
+
+/// Every calibration which matches an instruction, and which of them is selected to expand it:
+/// the most specific, or of those, the one added last.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CalibrationMatches<'a> {
+    /// The matching calibrations, in the order they were added.
+    candidates: Vec<CalibrationCandidate<'a>>,
+}
+
+impl<'a> CalibrationMatches<'a> {
+    /// Every calibration which matches the instruction, in the order they were added.
+    pub fn candidates(&self) -> &[CalibrationCandidate<'a>] {
+        &self.candidates
+    }
+
+    /// The position among the candidates of the selected one, if any.
+    fn selected_position(&self) -> Option<usize> {
+        // Of several maxima, `max_by_key` returns the last.
+        self.candidates
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, candidate)| candidate.specificity)
+            .map(|(position, _)| position)
+    }
+
+    /// The calibration which expands the instruction, if any matches.
+    pub fn selected(&self) -> Option<&CalibrationCandidate<'a>> {
+        self.selected_position()
+            .map(|position| &self.candidates[position])
+    }
+
+    /// Why [`CalibrationMatches::selected`] was selected, if any calibration matches.
+    pub fn reason(&self) -> Option<SelectionReason> {
+        self.selected()?;
+        Some(if self.candidates.len() == 1 {
+            SelectionReason::OnlyMatch
+        } else if self.is_ambiguous() {
+            SelectionReason::AddedLast
+        } else {
+            SelectionReason::MostSpecific
+        })
+    }
+
+    /// The calibrations which match the instruction but are not selected, in the order they were
+    /// added.
+    pub fn shadowed(&self) -> impl Iterator<Item = &CalibrationCandidate<'a>> {
+        let selected = self.selected_position();
+        self.candidates
+            .iter()
+            .enumerate()
+            .filter(move |(position, _)| Some(*position) != selected)
+            .map(|(_, candidate)| candidate)
+    }
+
+    /// The calibrations as specific as the selected one, itself included, in the order they were
+    /// added. If there are several, the selection rests only on that order.
+    pub fn tied(&self) -> Vec<&CalibrationCandidate<'a>> {
+        match self.selected() {
+            Some(selected) => self
+                .candidates
+                .iter()
+                .filter(|candidate| candidate.specificity == selected.specificity)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Whether several calibrations are as specific as the selected one, as for
+    /// [`CalibrationMatches::tied`].
+    pub fn is_ambiguous(&self) -> bool {
+        self.tied().len() > 1
+    }
+}
+
+/// An iterator over the instructions of a program with its calibrations expanded, as by
+/// [`Program::expand_calibrations_iter`](super::Program::expand_calibrations_iter), which expands
+/// each instruction only when it is reached.
+pub struct ExpandedCalibrations<'a> {
+    calibrations: &'a CalibrationSet,
+    instructions: std::slice::Iter<'a, Instruction>,
+    /// The instructions being expanded, outermost first, each with those of its calibration
+    /// which are yet to be yielded.
+    expanding: Vec<(Instruction, std::vec::IntoIter<Instruction>)>,
+    /// Whether an error has been yielded, after which the iterator yields nothing more.
+    failed: bool,
+    /// Whether a measurement with no calibration is an error, rather than yielded as it is.
+    strict: bool,
+}
+
+impl<'a> ExpandedCalibrations<'a> {
+    pub(super) fn new(calibrations: &'a CalibrationSet, instructions: &'a [Instruction]) -> Self {
         Self {
-            calibration,
-            fixed_qubit_count: calibration
-                .qubits
+            calibrations,
+            instructions: instructions.iter(),
+            expanding: vec![],
+            failed: false,
+            strict: false,
+        }
+    }
+
+    /// Make a measurement for which there is no calibration an error,
+    /// [`ProgramError::MissingCalibration`], rather than yielding it unexpanded. Gates with no
+    /// calibration are still yielded as they are.
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl Iterator for ExpandedCalibrations<'_> {
+    type Item = Result<Instruction, ProgramError<super::Program>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        loop {
+            let instruction = match self.expanding.last_mut() {
+                Some((_, remaining)) => match remaining.next() {
+                    Some(instruction) => instruction,
+                    None => {
+                        self.expanding.pop();
+                        continue;
+                    }
+                },
+                None => self.instructions.next()?.clone(),
+            };
+
+            if self
+                .expanding
                 .iter()
-                .filter(|q| match q {
-                    Qubit::Fixed(_) => true,
-                    Qubit::Variable(_) => false,
-                })
-                .count(),
+                .any(|(expanding, _)| *expanding == instruction)
+            {
+                self.failed = true;
+                return Some(Err(ProgramError::RecursiveCalibration(instruction)));
+            }
+            match self.calibrations.expand_once(&instruction) {
+                Some(expanded) => self.expanding.push((instruction, expanded.into_iter())),
+                None if self.strict && matches!(instruction, Instruction::Measurement(_)) => {
+                    self.failed = true;
+                    return Some(Err(ProgramError::MissingCalibration(Box::new(instruction))));
+                }
+                None => return Some(Ok(instruction)),
+            }
         }
     }
 }
 
+impl std::iter::FusedIterator for ExpandedCalibrations<'_> {}
+
 impl CalibrationSet {
     /// Given an instruction, return the instructions to which it is expanded if there is a match.
     /// Recursively calibrate instructions, returning an error if a calibration directly or indirectly
@@ -64,7 +270,36 @@ impl CalibrationSet {
         if previous_calibrations.contains(instruction) {
             return Err(ProgramError::RecursiveCalibration(instruction.clone()));
         }
-        let expanded_once_instructions = match instruction {
+        let expanded_once_instructions = self.expand_once(instruction);
+
+        // Add this instruction to the breadcrumb trail before recursion
+        let mut downstream_previous_calibrations = vec![instruction.clone()];
+        downstream_previous_calibrations.extend_from_slice(previous_calibrations);
+
+        Ok(match expanded_once_instructions {
+            Some(instructions) => {
+                let mut recursively_expanded_instructions = vec![];
+
+                for instruction in instructions {
+                    let expanded_instructions =
+                        self.expand(&instruction, &downstream_previous_calibrations)?;
+                    match expanded_instructions {
+                        Some(instructions) => {
+                            recursively_expanded_instructions.extend(instructions)
+                        }
+                        None => recursively_expanded_instructions.push(instruction),
+                    };
+                }
+                Some(recursively_expanded_instructions)
+            }
+            None => None,
+        })
+    }
+
+    /// The instructions of the calibration for `instruction`, if there is one, with its qubits
+    /// and parameters substituted, but without expanding those instructions in turn.
+    pub(super) fn expand_once(&self, instruction: &Instruction) -> Option<Vec<Instruction>> {
+        match instruction {
             Instruction::Gate(Gate {
                 name,
                 modifiers,
@@ -133,74 +368,41 @@ impl CalibrationSet {
                 }
             }
             Instruction::Measurement(Measurement { qubit, target }) => {
-                // The matching calibration is the last-specified one that matched the target qubit (if any),
-                // or otherwise the last-specified one that specified no qubit.
-                let mut matching_calibration = None;
-                let mut found_matching_calibration_without_qubit = false;
-                for cal in self.measure_calibrations.iter().rev() {
-                    if let Some(cal_qubit) = &cal.qubit {
-                        if cal_qubit == qubit {
-                            matching_calibration = Some(cal);
-                            break;
-                        }
-                    } else if !found_matching_calibration_without_qubit {
-                        matching_calibration = Some(cal);
-                        found_matching_calibration_without_qubit = true;
-                    }
-                }
-
-                match matching_calibration {
-                    Some(calibration) => {
-                        let mut instructions = calibration.instructions.clone();
-                        for instruction in instructions.iter_mut() {
-                            match instruction {
-                                Instruction::Pragma(pragma) => {
-                                    if pragma.name == "LOAD-MEMORY"
-                                        && pragma.data.as_ref() == Some(&calibration.parameter)
-                                    {
-                                        if let Some(target) = target {
-                                            pragma.data = Some(target.to_string())
-                                        }
-                                    }
-                                }
-                                Instruction::Capture(capture) => {
-                                    if let Some(target) = target {
-                                        capture.memory_reference = target.clone()
-                                    }
-                                }
-                                _ => {}
+                // The matching calibration is the last-specified of the most specific which match
+                // the measurement; see `measurement_specificity`.
+                let calibration = self
+                    .measurement_matches(qubit)
+                    .into_iter()
+                    .max_by_key(|(_, calibration)| {
+                        measurement_specificity(calibration, target.is_some())
+                    })
+                    .map(|(_, calibration)| calibration)?;
+
+                let mut rewriter = MeasureCalibrationRewriter {
+                    qubit: match &calibration.qubit {
+                        Some(Qubit::Variable(variable)) => Some((variable.as_str(), qubit)),
+                        _ => None,
+                    },
+                    target: calibration.parameter.as_deref().zip(target.as_ref()),
+                };
+                let mut instructions = calibration.instructions.clone();
+                for instruction in instructions.iter_mut() {
+                    rewriter.rewrite_instruction(instruction);
+                    if let Instruction::Pragma(pragma) = instruction {
+                        if pragma.name == "LOAD-MEMORY"
+                            && pragma.data.is_some()
+                            && pragma.data == calibration.parameter
+                        {
+                            if let Some(target) = target {
+                                pragma.data = Some(target.to_string())
                             }
                         }
-                        Some(instructions)
                     }
-                    None => None,
                 }
+                Some(instructions)
             }
             _ => None,
-        };
-
-        // Add this instruction to the breadcrumb trail before recursion
-        let mut downstream_previous_calibrations = vec![instruction.clone()];
-        downstream_previous_calibrations.extend_from_slice(previous_calibrations);
-
-        Ok(match expanded_once_instructions {
-            Some(instructions) => {
-                let mut recursively_expanded_instructions = vec![];
-
-                for instruction in instructions {
-                    let expanded_instructions =
-                        self.expand(&instruction, &downstream_previous_calibrations)?;
-                    match expanded_instructions {
-                        Some(instructions) => {
-                            recursively_expanded_instructions.extend(instructions)
-                        }
-                        None => recursively_expanded_instructions.push(instruction),
-                    };
-                }
-                Some(recursively_expanded_instructions)
-            }
-            None => None,
-        })
+        }
     }
 
     /// Return the final calibration which matches the gate per the QuilT specification:
@@ -219,79 +421,139 @@ impl CalibrationSet {
         gate_parameters: &[Expression],
         gate_qubits: &[Qubit],
     ) -> Option<&Calibration> {
-        let mut matched_calibration: Option<MatchedCalibration> = None;
-
-        for calibration in &self.calibrations {
-            // Filter out non-matching calibrations: check rules 1-4
-            if calibration.name != gate_name
-                || calibration.modifiers != gate_modifiers
-                || calibration.parameters.len() != gate_parameters.len()
-                || calibration.qubits.len() != gate_qubits.len()
-            {
-                continue;
-            }
-
-            let fixed_qubits_match =
-                calibration
-                    .qubits
-                    .iter()
-                    .enumerate()
-                    .all(|(calibration_index, _)| {
-                        match (
-                            &calibration.qubits[calibration_index],
-                            &gate_qubits[calibration_index],
-                        ) {
-                            // If they're both fixed, test if they're fixed to the same qubit
-                            (
-                                Qubit::Fixed(calibration_fixed_qubit),
-                                Qubit::Fixed(gate_fixed_qubit),
-                            ) => calibration_fixed_qubit == gate_fixed_qubit,
-                            // If the calibration is variable, it matches any fixed qubit
-                            (Qubit::Variable(_), _) => true,
-                            // If the calibration is fixed, but the gate's qubit is variable, it's not a match
-                            (Qubit::Fixed(_), _) => false,
-                        }
-                    });
-            if !fixed_qubits_match {
-                continue;
-            }
+        // Of several maxima, `max_by_key` returns the last.
+        self.gate_matches(gate_modifiers, gate_name, gate_parameters, gate_qubits)
+            .into_iter()
+            .max_by_key(|(_, calibration)| fixed_qubit_count(calibration))
+            .map(|(_, calibration)| calibration)
+    }
 
-            let fixed_parameters_match =
-                calibration
-                    .parameters
-                    .iter()
-                    .enumerate()
-                    .all(|(calibration_index, _)| {
-                        let calibration_parameters = calibration.parameters[calibration_index]
-                            .clone()
-                            .into_simplified();
-                        let gate_parameters =
-                            gate_parameters[calibration_index].clone().into_simplified();
-                        match (calibration_parameters, gate_parameters) {
-                            // If the calibration is variable, it matches any fixed qubit
-                            (Expression::Variable(_), _) => true,
-                            // If the calibration is fixed, but the gate's qubit is variable, it's not a match
-                            (calib, gate) => calib == gate,
-                        }
-                    });
-            if !fixed_parameters_match {
-                continue;
-            }
+    /// The gate calibrations which match a gate, with their positions among them, in the order
+    /// they were added.
+    fn gate_matches(
+        &self,
+        gate_modifiers: &[GateModifier],
+        gate_name: &str,
+        gate_parameters: &[Expression],
+        gate_qubits: &[Qubit],
+    ) -> Vec<(usize, &Calibration)> {
+        self.calibrations
+            .iter()
+            .enumerate()
+            .filter(|(_, calibration)| {
+                // Filter out non-matching calibrations: check rules 1-4
+                if calibration.name != gate_name
+                    || calibration.modifiers != gate_modifiers
+                    || calibration.parameters.len() != gate_parameters.len()
+                    || calibration.qubits.len() != gate_qubits.len()
+                {
+                    return false;
+                }
 
-            matched_calibration = match matched_calibration {
-                None => Some(MatchedCalibration::new(calibration)),
-                Some(previous_match) => {
-                    let potential_match = MatchedCalibration::new(calibration);
-                    if potential_match.fixed_qubit_count >= previous_match.fixed_qubit_count {
-                        Some(potential_match)
-                    } else {
-                        Some(previous_match)
-                    }
+                let fixed_qubits_match =
+                    calibration
+                        .qubits
+                        .iter()
+                        .enumerate()
+                        .all(|(calibration_index, _)| {
+                            match (
+                                &calibration.qubits[calibration_index],
+                                &gate_qubits[calibration_index],
+                            ) {
+                                // If they're both fixed, test if they're fixed to the same qubit
+                                (
+                                    Qubit::Fixed(calibration_fixed_qubit),
+                                    Qubit::Fixed(gate_fixed_qubit),
+                                ) => calibration_fixed_qubit == gate_fixed_qubit,
+                                // If the calibration is variable, it matches any fixed qubit
+                                (Qubit::Variable(_), _) => true,
+                                // If the calibration is fixed, but the gate's qubit is variable, it's not a match
+                                (Qubit::Fixed(_), _) => false,
+                            }
+                        });
+                if !fixed_qubits_match {
+                    return false;
                 }
-            }
-        }
 
-        matched_calibration.map(|m| m.calibration)
+                let fixed_parameters_match =
+                    calibration
+                        .parameters
+                        .iter()
+                        .enumerate()
+                        .all(|(calibration_index, _)| {
+                            let calibration_parameters = calibration.parameters[calibration_index]
+                                .clone()
+                                .into_simplified();
+                            let gate_parameters =
+                                gate_parameters[calibration_index].clone().into_simplified();
+                            match (calibration_parameters, gate_parameters) {
+                                // If the calibration is variable, it matches any fixed qubit
+                                (Expression::Variable(_), _) => true,
+                                // If the calibration is fixed, but the gate's qubit is variable, it's not a match
+                                (calib, gate) => calib == gate,
+                            }
+                        });
+                fixed_parameters_match
+            })
+            .collect()
+    }
+
+    /// The measurement calibrations which match a measurement of `qubit`, with their positions
+    /// among them, in the order they were added: those for `qubit`, and those for any qubit,
+    /// whether by naming none or by a variable. Calibrations match whether or not they have a
+    /// destination, though those which have one exactly when the measurement has a target are
+    /// preferred.
+    fn measurement_matches(&self, qubit: &Qubit) -> Vec<(usize, &MeasureCalibrationDefinition)> {
+        self.measure_calibrations
+            .iter()
+            .enumerate()
+            .filter(|(_, calibration)| match &calibration.qubit {
+                None | Some(Qubit::Variable(_)) => true,
+                Some(calibration_qubit) => calibration_qubit == qubit,
+            })
+            .collect()
+    }
+
+    /// Every calibration which matches `instruction`, a gate or a measurement, and which of them
+    /// is selected to expand it, as by [`CalibrationSet::expand`]. Other instructions match
+    /// none.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::SelectionReason;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DEFCAL X %q:\n    NOP\nDEFCAL X 0:\n    NOP\nX 0".parse().unwrap();
+    /// let matches = program.calibrations.matches(&program.instructions[0]);
+    /// assert_eq!(matches.candidates().len(), 2);
+    /// assert_eq!(matches.selected().unwrap().index, 1);
+    /// assert_eq!(matches.reason(), Some(SelectionReason::MostSpecific));
+    /// assert_eq!(matches.shadowed().next().unwrap().index, 0);
+    /// ```
+    pub fn matches(&self, instruction: &Instruction) -> CalibrationMatches<'_> {
+        let candidates = match instruction {
+            Instruction::Gate(gate) => self
+                .gate_matches(&gate.modifiers, &gate.name, &gate.parameters, &gate.qubits)
+                .into_iter()
+                .map(|(index, calibration)| CalibrationCandidate {
+                    calibration: CalibrationDefinitionRef::Gate(calibration),
+                    index,
+                    specificity: fixed_qubit_count(calibration),
+                })
+                .collect(),
+            Instruction::Measurement(measurement) => self
+                .measurement_matches(&measurement.qubit)
+                .into_iter()
+                .map(|(index, calibration)| CalibrationCandidate {
+                    calibration: CalibrationDefinitionRef::Measurement(calibration),
+                    index,
+                    specificity: measurement_specificity(calibration, measurement.target.is_some()),
+                })
+                .collect(),
+            _ => vec![],
+        };
+        CalibrationMatches { candidates }
     }
 
     /// Return the count of contained calibrations.
@@ -339,6 +601,9 @@ impl CalibrationSet {
 mod tests {
     use std::str::FromStr;
 
+    use rstest::rstest;
+
+    use super::{CalibrationDefinitionRef, SelectionReason};
     use crate::program::Program;
 
     #[test]
@@ -406,6 +671,37 @@ mod tests {
                 input: concat!("DEFCAL I q:\n", "    DELAY q 4e-8\n", "I 0\n",),
                 expected: "DELAY 0 4e-8\n",
             },
+            // A qubit variable and destination are replaced throughout a measure calibration
+            TestCase {
+                input: concat!(
+                    "DEFCAL MEASURE q addr:\n",
+                    "    PULSE q \"ro_tx\" flat(duration: 1, iq: 1)\n",
+                    "    CAPTURE q \"ro_rx\" boxcar_kernel(duration: 1) addr\n",
+                    "    MOVE scratch addr\n",
+                    "MEASURE 2 ro[1]\n"
+                ),
+                expected: concat!(
+                    "PULSE 2 \"ro_tx\" flat(duration: 1, iq: 1)\n",
+                    "CAPTURE 2 \"ro_rx\" boxcar_kernel(duration: 1) ro[1]\n",
+                    "MOVE scratch[0] ro[1]\n"
+                ),
+            },
+            // Calibrations with and without a destination are preferred for measurements with
+            // and without a target, respectively
+            TestCase {
+                input: concat!(
+                    "DEFCAL MEASURE 0:\n",
+                    "    PRAGMA DISCARDED\n",
+                    "DEFCAL MEASURE 0 addr:\n",
+                    "    PRAGMA KEPT\n",
+                    "DEFCAL MEASURE 1:\n",
+                    "    PRAGMA ONLY_DISCARDED\n",
+                    "MEASURE 0\n",
+                    "MEASURE 0 ro\n",
+                    "MEASURE 1 ro\n"
+                ),
+                expected: "PRAGMA DISCARDED\nPRAGMA KEPT\nPRAGMA ONLY_DISCARDED\n",
+            },
         ];
 
         for case in &cases {
@@ -415,6 +711,95 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_expand_calibrations_iter_recursion() {
+        let input = "DEFCAL X 0:
+    Y 0
+DEFCAL Y 0:
+    X 0
+H 1
+X 0
+H 2";
+        let program = Program::from_str(input).unwrap();
+        let mut expanded = program.expand_calibrations_iter();
+        assert_eq!(expanded.next().unwrap().unwrap().to_string(), "H 1");
+        assert_eq!(
+            expanded.next().unwrap().unwrap_err().to_string(),
+            program.expand_calibrations().unwrap_err().to_string()
+        );
+        assert!(expanded.next().is_none());
+    }
+
+    #[rstest]
+    #[case("DEFCAL X 0:\n    NOP\nX 1", vec![], None)]
+    #[case("DEFCAL X 0:\n    NOP\nX 0", vec![0], Some(SelectionReason::OnlyMatch))]
+    #[case(
+        "DEFCAL X 0:\n    NOP\nDEFCAL X %q:\n    NOP\nDEFCAL Y 0:\n    NOP\nX 0",
+        vec![0, 1],
+        Some(SelectionReason::MostSpecific)
+    )]
+    #[case(
+        "DEFCAL RX(%theta) 0:\n    NOP\nDEFCAL RX(pi) 0:\n    NOP\nDEFCAL RX(pi/2) 0:\n    NOP\nRX(pi) 0",
+        vec![0, 1],
+        Some(SelectionReason::AddedLast)
+    )]
+    #[case(
+        "DEFCAL MEASURE 0 addr:\n    NOP\nDEFCAL MEASURE addr:\n    NOP\nDEFCAL MEASURE 1 addr:\n    NOP\nMEASURE 0",
+        vec![0, 1],
+        Some(SelectionReason::MostSpecific)
+    )]
+    fn test_matches(
+        #[case] input: &str,
+        #[case] expected_indices: Vec<usize>,
+        #[case] expected_reason: Option<SelectionReason>,
+    ) {
+        let program = Program::from_str(input).unwrap();
+        let matches = program.calibrations.matches(&program.instructions[0]);
+        let indices: Vec<usize> = matches
+            .candidates()
+            .iter()
+            .map(|candidate| candidate.index)
+            .collect();
+        assert_eq!(indices, expected_indices);
+        assert_eq!(matches.reason(), expected_reason);
+
+        // The selected calibration is the one used to expand the instruction.
+        let expanded = program
+            .calibrations
+            .expand(&program.instructions[0], &[])
+            .unwrap();
+        let selected_instructions =
+            matches
+                .selected()
+                .map(|candidate| match candidate.calibration {
+                    CalibrationDefinitionRef::Gate(calibration) => calibration.instructions.clone(),
+                    CalibrationDefinitionRef::Measurement(calibration) => {
+                        calibration.instructions.clone()
+                    }
+                });
+        assert_eq!(expanded, selected_instructions);
+        assert_eq!(
+            matches.shadowed().count(),
+            expected_indices.len().saturating_sub(1)
+        );
+    }
+
+    #[rstest]
+    #[case("MEASURE 0 ro", None)]
+    #[case("MEASURE 1", Some("instruction MEASURE 1 has no calibration"))]
+    #[case("X 0\nMEASURE 2 ro", Some("instruction MEASURE 2 has no calibration"))]
+    #[case("H 2", None)]
+    fn test_expand_calibrations_strict(#[case] body: &str, #[case] expected_error: Option<&str>) {
+        let input = format!("DEFCAL MEASURE 0 addr:\n    NOP\nDEFCAL X 0:\n    MEASURE 2\n{body}");
+        let program = Program::from_str(&input).unwrap();
+        let error = program
+            .expand_calibrations_strict()
+            .err()
+            .map(|error| error.to_string());
+        assert_eq!(error.as_deref(), expected_error);
+        assert!(program.expand_calibrations().is_ok());
+    }
+
     #[test]
     fn test_eq() {
         let input = "DEFCAL X 0:
@@ -0,0 +1,536 @@
+//! Run every available validation of a program at once and collect all of their findings.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::instruction::{
+    Capture, FrameIdentifier, Instruction, Jump, JumpUnless, JumpWhen, Label, Pulse, RawCapture,
+    SetFrequency, SetPhase, SetScale, ShiftFrequency, ShiftPhase, SwapPhases,
+};
+use crate::Program;
+
+use super::arity_check::arity_errors;
+use super::control_flow_graph::ControlFlowGraph;
+use super::memory_check::MemoryError;
+
+/// How serious a [`Finding`] is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// Worth knowing, but not a problem in itself.
+    Info,
+    /// Likely to be a mistake, though the program may still run.
+    Warning,
+    /// The program is invalid.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Info => write!(f, "info"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// The kind of problem described by a [`Finding`], each with a stable code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FindingCode {
+    /// `E001`: an instruction uses memory of the wrong type, or memory which is not declared.
+    TypeMismatch,
+    /// `E002`: a gate is given the wrong number of qubits or parameters for its definition.
+    ArityMismatch,
+    /// `E003`: a jump targets a label which is not defined.
+    UndefinedJumpTarget,
+    /// `E004`: a label is defined more than once.
+    DuplicateLabel,
+    /// `E005`: an instruction indexes memory past the end of its region.
+    IndexOutOfBounds,
+    /// `W001`: a Quil-T instruction uses a frame which the program does not define.
+    UndefinedFrame,
+    /// `W002`: a memory region is declared but never used.
+    UnusedMemoryRegion,
+    /// `W003`: instructions can never be executed, as no jump or fall-through reaches them.
+    UnreachableCode,
+    /// `W004`: several calibrations match an instruction equally specifically, and so the one
+    /// used is chosen only by the order in which they are defined.
+    AmbiguousCalibration,
+    /// `W005`: a memory region is read before the first instruction which writes it.
+    ReadBeforeWrite,
+}
+
+impl FindingCode {
+    /// The stable code identifying this kind of finding, such as `E001`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FindingCode::TypeMismatch => "E001",
+            FindingCode::ArityMismatch => "E002",
+            FindingCode::UndefinedJumpTarget => "E003",
+            FindingCode::DuplicateLabel => "E004",
+            FindingCode::IndexOutOfBounds => "E005",
+            FindingCode::UndefinedFrame => "W001",
+            FindingCode::UnusedMemoryRegion => "W002",
+            FindingCode::UnreachableCode => "W003",
+            FindingCode::AmbiguousCalibration => "W004",
+            FindingCode::ReadBeforeWrite => "W005",
+        }
+    }
+
+    /// The severity of findings of this kind.
+    pub fn severity(&self) -> Severity {
+        match self {
+            FindingCode::TypeMismatch
+            | FindingCode::ArityMismatch
+            | FindingCode::UndefinedJumpTarget
+            | FindingCode::DuplicateLabel
+            | FindingCode::IndexOutOfBounds => Severity::Error,
+            FindingCode::UndefinedFrame
+            | FindingCode::UnusedMemoryRegion
+            | FindingCode::UnreachableCode
+            | FindingCode::AmbiguousCalibration
+            | FindingCode::ReadBeforeWrite => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for FindingCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// A problem found in a program by [`Program::check`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Finding {
+    pub code: FindingCode,
+    pub severity: Severity,
+    /// The index in [`Program::instructions`] of the instruction the finding concerns, if any.
+    /// Findings about memory declarations, which are not kept among the instructions, have none.
+    pub instruction_index: Option<usize>,
+    pub message: String,
+}
+
+impl Finding {
+    fn new(code: FindingCode, instruction_index: Option<usize>, message: String) -> Self {
+        Self {
+            code,
+            severity: code.severity(),
+            instruction_index,
+            message,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}[{}]: {}", self.severity, self.code, self.message)
+    }
+}
+
+/// Which checks [`Program::check`] runs, and which findings it reports.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CheckOptions {
+    /// Check the data types of classical instructions, as [`super::type_check::type_check`] does,
+    /// and that the memory each instruction uses is declared.
+    pub type_check: bool,
+    /// Check that each index into memory lies within its region, and that no region is read
+    /// before it is first written, as [`Program::validate_memory`] does.
+    pub memory_check: bool,
+    /// Check the qubit and parameter counts of gates, as [`super::arity_check::arity_check`] does.
+    pub arity_check: bool,
+    /// Check control flow, as [`Program::validate_control_flow`] does.
+    pub control_flow: bool,
+    /// Check that Quil-T instructions use frames defined by the program, and that no instruction
+    /// is matched by several calibrations equally specifically.
+    pub quilt: bool,
+    /// Report code which is valid but likely to be a mistake, such as unused memory regions.
+    pub lints: bool,
+    /// Only report findings at least this severe.
+    pub minimum_severity: Severity,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            type_check: true,
+            memory_check: true,
+            arity_check: true,
+            control_flow: true,
+            quilt: true,
+            lints: true,
+            minimum_severity: Severity::Info,
+        }
+    }
+}
+
+/// Return the frames used by `instruction`, if it is a Quil-T instruction which names them.
+fn named_frames(instruction: &Instruction) -> Vec<&FrameIdentifier> {
+    match instruction {
+        Instruction::Capture(Capture { frame, .. })
+        | Instruction::Pulse(Pulse { frame, .. })
+        | Instruction::RawCapture(RawCapture { frame, .. })
+        | Instruction::SetFrequency(SetFrequency { frame, .. })
+        | Instruction::SetPhase(SetPhase { frame, .. })
+        | Instruction::SetScale(SetScale { frame, .. })
+        | Instruction::ShiftFrequency(ShiftFrequency { frame, .. })
+        | Instruction::ShiftPhase(ShiftPhase { frame, .. }) => vec![frame],
+        Instruction::SwapPhases(SwapPhases { frame_1, frame_2 }) => vec![frame_1, frame_2],
+        _ => vec![],
+    }
+}
+
+fn check_labels(program: &Program, findings: &mut Vec<Finding>) {
+    let mut labels: HashMap<&Label, usize> = HashMap::new();
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        if let Instruction::Label(name) = instruction {
+            if let Some(first) = labels.insert(name, index) {
+                labels.insert(name, first);
+                findings.push(Finding::new(
+                    FindingCode::DuplicateLabel,
+                    Some(index),
+                    format!("label @{name} is already defined by instruction {first}"),
+                ));
+            }
+        }
+    }
+
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        let target = match instruction {
+            Instruction::Jump(Jump { target })
+            | Instruction::JumpWhen(JumpWhen { target, .. })
+            | Instruction::JumpUnless(JumpUnless { target, .. }) => target,
+            _ => continue,
+        };
+        if !labels.contains_key(target) {
+            findings.push(Finding::new(
+                FindingCode::UndefinedJumpTarget,
+                Some(index),
+                format!("In instruction {instruction}: label @{target} is not defined"),
+            ));
+        }
+    }
+}
+
+/// Report each block of `graph` which is never reached from the start of the program, unless it
+/// has no instructions.
+fn check_reachability(program: &Program, graph: &ControlFlowGraph, findings: &mut Vec<Finding>) {
+    for block in graph.unreachable_blocks() {
+        if let Some(first) = block.indices().next() {
+            let instruction = &program.instructions[first];
+            let message = match block.len() {
+                1 => format!("In instruction {instruction}: this instruction can never be reached"),
+                2 => format!(
+                    "In instruction {instruction}: this and the next instruction can never be reached"
+                ),
+                len => format!(
+                    "In instruction {instruction}: this and the next {} instructions can never be reached",
+                    len - 1
+                ),
+            };
+            findings.push(Finding::new(
+                FindingCode::UnreachableCode,
+                Some(first),
+                message,
+            ));
+        }
+    }
+}
+
+fn check_quilt(program: &Program, findings: &mut Vec<Finding>) {
+    for (index, instruction) in program.instructions.iter().enumerate() {
+        let matches = program.calibrations.matches(instruction);
+        if matches.is_ambiguous() {
+            findings.push(Finding::new(
+                FindingCode::AmbiguousCalibration,
+                Some(index),
+                format!(
+                    "In instruction {instruction}: {} calibrations match equally specifically; the last defined is used",
+                    matches.tied().len()
+                ),
+            ));
+        }
+        for frame in named_frames(instruction) {
+            if program.frames.get(frame).is_none() {
+                findings.push(Finding::new(
+                    FindingCode::UndefinedFrame,
+                    Some(index),
+                    format!("In instruction {instruction}: frame {frame} is not defined"),
+                ));
+            }
+        }
+    }
+}
+
+fn check_lints(program: &Program, findings: &mut Vec<Finding>) {
+    let mut used: HashSet<String> = HashSet::new();
+    for instruction in &program.instructions {
+        let accesses = instruction.get_memory_accesses();
+        used.extend(accesses.reads);
+        used.extend(accesses.writes);
+        used.extend(accesses.captures);
+    }
+
+    for name in program.memory_regions.keys() {
+        if !used.contains(name.as_str()) {
+            findings.push(Finding::new(
+                FindingCode::UnusedMemoryRegion,
+                None,
+                format!("memory region {name} is declared but never used"),
+            ));
+        }
+    }
+}
+
+impl Program {
+    /// Check the control flow of the program, reporting each jump to a label which is not
+    /// defined, each label defined more than once, and each block of instructions which can never
+    /// be reached from the start of the program. Findings are listed in the order of the
+    /// instructions they concern.
+    ///
+    /// Unreachable code is only reported once every jump has exactly one target, as the
+    /// program's [control-flow graph](Program::to_cfg) cannot be built until then.
+    pub fn validate_control_flow(&self) -> Vec<Finding> {
+        let mut findings = vec![];
+        check_labels(self, &mut findings);
+        if let Ok(graph) = self.to_cfg() {
+            check_reachability(self, &graph, &mut findings);
+        }
+        findings.sort_by_key(|finding| finding.instruction_index);
+        findings
+    }
+
+    /// Run the checks selected by `options` and return everything they find, rather than
+    /// stopping at the first problem. Findings about instructions are listed in the order of the
+    /// instructions, followed by findings about the program as a whole.
+    ///
+    /// Syntax errors are not among the findings, as a program must be parsed before it can be
+    /// checked.
+    pub fn check(&self, options: &CheckOptions) -> Vec<Finding> {
+        let mut findings = vec![];
+
+        if options.type_check || options.memory_check {
+            for error in self.validate_memory() {
+                let (code, selected) = match error {
+                    MemoryError::UndeclaredRegion { .. } | MemoryError::TypeMismatch { .. } => {
+                        (FindingCode::TypeMismatch, options.type_check)
+                    }
+                    MemoryError::IndexOutOfBounds { .. } => {
+                        (FindingCode::IndexOutOfBounds, options.memory_check)
+                    }
+                    MemoryError::ReadBeforeWrite { .. } => {
+                        (FindingCode::ReadBeforeWrite, options.memory_check)
+                    }
+                };
+                if selected {
+                    findings.push(Finding::new(code, Some(error.index()), error.to_string()));
+                }
+            }
+        }
+        if options.arity_check {
+            findings.extend(arity_errors(self).into_iter().map(|(index, error)| {
+                Finding::new(FindingCode::ArityMismatch, Some(index), error.to_string())
+            }));
+        }
+        if options.control_flow {
+            findings.extend(self.validate_control_flow());
+        }
+        if options.quilt {
+            check_quilt(self, &mut findings);
+        }
+        if options.lints {
+            check_lints(self, &mut findings);
+        }
+
+        findings.retain(|finding| finding.severity >= options.minimum_severity);
+        findings.sort_by_key(|finding| {
+            (
+                finding.instruction_index.is_none(),
+                finding.instruction_index,
+            )
+        });
+        findings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{CheckOptions, FindingCode, Severity};
+    use crate::Program;
+
+    fn codes(input: &str, options: &CheckOptions) -> Vec<(FindingCode, Option<usize>)> {
+        Program::from_str(input)
+            .unwrap()
+            .check(options)
+            .into_iter()
+            .map(|finding| (finding.code, finding.instruction_index))
+            .collect()
+    }
+
+    #[test]
+    fn test_clean_program() {
+        let input = r#"DECLARE ro BIT
+DEFFRAME 0 "rf":
+    SAMPLE-RATE: 1.0
+LABEL @start
+H 0
+SHIFT-PHASE 0 "rf" pi
+MEASURE 0 ro
+JUMP-WHEN @start ro
+"#;
+        assert_eq!(codes(input, &CheckOptions::default()), vec![]);
+    }
+
+    #[test]
+    fn test_all_findings() {
+        let input = r#"DECLARE ro BIT
+DECLARE theta REAL
+DECLARE unused INTEGER
+LABEL @start
+LABEL @start
+RX(theta) 0 1
+MOVE ro theta
+JUMP @end
+PULSE 0 "rf" flat(duration: 1e-6, iq: 1.0)
+CNOT 0
+"#;
+        assert_eq!(
+            codes(input, &CheckOptions::default()),
+            vec![
+                (FindingCode::DuplicateLabel, Some(1)),
+                (FindingCode::ArityMismatch, Some(2)),
+                (FindingCode::TypeMismatch, Some(3)),
+                (FindingCode::UndefinedJumpTarget, Some(4)),
+                (FindingCode::UndefinedFrame, Some(5)),
+                (FindingCode::ArityMismatch, Some(6)),
+                (FindingCode::UnusedMemoryRegion, None),
+            ]
+        );
+
+        let errors_only = CheckOptions {
+            minimum_severity: Severity::Error,
+            ..Default::default()
+        };
+        assert_eq!(
+            codes(input, &errors_only),
+            vec![
+                (FindingCode::DuplicateLabel, Some(1)),
+                (FindingCode::ArityMismatch, Some(2)),
+                (FindingCode::TypeMismatch, Some(3)),
+                (FindingCode::UndefinedJumpTarget, Some(4)),
+                (FindingCode::ArityMismatch, Some(6)),
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "DECLARE ro BIT[2]\nMEASURE 0 ro[18446744073709551615]",
+        vec![(FindingCode::IndexOutOfBounds, Some(0))]
+    )]
+    #[case(
+        "DECLARE count INTEGER\nDECLARE total INTEGER\nMOVE total count\nMOVE count 1",
+        vec![(FindingCode::ReadBeforeWrite, Some(0))]
+    )]
+    #[case(
+        "RX(theta) 0",
+        vec![(FindingCode::TypeMismatch, Some(0))]
+    )]
+    fn test_memory_findings(
+        #[case] input: &str,
+        #[case] expected: Vec<(FindingCode, Option<usize>)>,
+    ) {
+        assert_eq!(codes(input, &CheckOptions::default()), expected);
+
+        let type_check_only = CheckOptions {
+            memory_check: false,
+            ..Default::default()
+        };
+        let expected: Vec<_> = expected
+            .into_iter()
+            .filter(|(code, _)| *code == FindingCode::TypeMismatch)
+            .collect();
+        assert_eq!(codes(input, &type_check_only), expected);
+    }
+
+    #[rstest]
+    #[case("LABEL @a\nH 0\nJUMP @a\nX 0\nY 0\nLABEL @b\nZ 0", vec![(FindingCode::UnreachableCode, Some(3)), (FindingCode::UnreachableCode, Some(6))])]
+    #[case("H 0\nHALT\nX 0", vec![(FindingCode::UnreachableCode, Some(2))])]
+    #[case("JUMP @b\nLABEL @a\nX 0\nLABEL @b\nJUMP @a", vec![])]
+    #[case("JUMP @end\nLABEL @unused\nLABEL @end", vec![])]
+    #[case(
+        "JUMP @b\nX 0\nLABEL @a\nLABEL @a",
+        vec![(FindingCode::UndefinedJumpTarget, Some(0)), (FindingCode::DuplicateLabel, Some(3))]
+    )]
+    fn test_validate_control_flow(
+        #[case] input: &str,
+        #[case] expected: Vec<(FindingCode, Option<usize>)>,
+    ) {
+        let findings = Program::from_str(input).unwrap().validate_control_flow();
+        assert_eq!(
+            findings
+                .iter()
+                .map(|finding| (finding.code, finding.instruction_index))
+                .collect::<Vec<_>>(),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "DEFCAL X 0:\n    NOP\nDEFCAL X 0:\n    NOP\nX 0\nX 1",
+        vec![(FindingCode::AmbiguousCalibration, Some(0))]
+    )]
+    #[case("DEFCAL X %q:\n    NOP\nDEFCAL X 0:\n    NOP\nX 0\nX 1", vec![])]
+    #[case(
+        "DEFCAL MEASURE addr:\n    NOP\nDEFCAL MEASURE addr:\n    NOP\nMEASURE 0",
+        vec![(FindingCode::AmbiguousCalibration, Some(0))]
+    )]
+    #[case("DEFCAL MEASURE addr:\n    NOP\nDEFCAL MEASURE 0 addr:\n    NOP\nMEASURE 0", vec![])]
+    fn test_ambiguous_calibrations(
+        #[case] input: &str,
+        #[case] expected: Vec<(FindingCode, Option<usize>)>,
+    ) {
+        assert_eq!(codes(input, &CheckOptions::default()), expected);
+    }
+
+    #[test]
+    fn test_unreachable_code_message() {
+        let findings = Program::from_str("HALT\nX 0\nY 0\nZ 0")
+            .unwrap()
+            .validate_control_flow();
+        assert_eq!(
+            findings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["warning[W003]: In instruction X 0: this and the next 2 instructions can never be reached"]
+        );
+    }
+
+    #[test]
+    fn test_finding_display() {
+        let findings = Program::from_str("DECLARE ro BIT")
+            .unwrap()
+            .check(&CheckOptions::default());
+        assert_eq!(
+            findings.iter().map(ToString::to_string).collect::<Vec<_>>(),
+            vec!["warning[W002]: memory region ro is declared but never used"]
+        );
+    }
+}
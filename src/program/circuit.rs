@@ -0,0 +1,299 @@
+//! Circuits defined with `DEFCIRCUIT`, and inlining their invocations.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::expression::Expression;
+use crate::instruction::{
+    Capture, CircuitDefinition, Delay, Fence, Gate, Instruction, Measurement, Pulse, Qubit,
+    RawCapture, Reset, SetFrequency, SetPhase, SetScale, ShiftFrequency, ShiftPhase, SwapPhases,
+};
+
+use super::{Program, ProgramError, Result};
+
+/// The qubits acted on by `instruction`, which may be qubit variables within a circuit.
+fn qubits_mut(instruction: &mut Instruction) -> Vec<&mut Qubit> {
+    match instruction {
+        Instruction::Gate(Gate { qubits, .. })
+        | Instruction::Delay(Delay { qubits, .. })
+        | Instruction::Fence(Fence { qubits }) => qubits.iter_mut().collect(),
+        Instruction::Measurement(Measurement { qubit, .. })
+        | Instruction::Reset(Reset { qubit: Some(qubit) }) => vec![qubit],
+        Instruction::Capture(Capture { frame, .. })
+        | Instruction::Pulse(Pulse { frame, .. })
+        | Instruction::RawCapture(RawCapture { frame, .. })
+        | Instruction::SetFrequency(SetFrequency { frame, .. })
+        | Instruction::SetPhase(SetPhase { frame, .. })
+        | Instruction::SetScale(SetScale { frame, .. })
+        | Instruction::ShiftFrequency(ShiftFrequency { frame, .. })
+        | Instruction::ShiftPhase(ShiftPhase { frame, .. }) => frame.qubits.iter_mut().collect(),
+        Instruction::SwapPhases(SwapPhases { frame_1, frame_2 }) => frame_1
+            .qubits
+            .iter_mut()
+            .chain(frame_2.qubits.iter_mut())
+            .collect(),
+        _ => vec![],
+    }
+}
+
+impl CircuitDefinition {
+    /// Return the instructions of the circuit as applied by `gate`, with its qubit variables and
+    /// parameters replaced by the qubits and parameters of the gate, or a message explaining why
+    /// the gate cannot be expanded: because it applies modifiers to the circuit, or does not
+    /// match the number of its qubits or parameters.
+    pub(crate) fn expand(&self, gate: &Gate) -> std::result::Result<Vec<Instruction>, String> {
+        if !gate.modifiers.is_empty() {
+            return Err("modifiers cannot be applied to a circuit".to_string());
+        }
+        if gate.qubits.len() != self.qubit_variables.len() {
+            return Err(format!(
+                "expected {} qubits but found {}",
+                self.qubit_variables.len(),
+                gate.qubits.len()
+            ));
+        }
+        if gate.parameters.len() != self.parameters.len() {
+            return Err(format!(
+                "expected {} parameters but found {}",
+                self.parameters.len(),
+                gate.parameters.len()
+            ));
+        }
+
+        let qubits: HashMap<&str, &Qubit> = self
+            .qubit_variables
+            .iter()
+            .map(String::as_str)
+            .zip(&gate.qubits)
+            .collect();
+        let parameters: HashMap<String, Expression> = self
+            .parameters
+            .iter()
+            .cloned()
+            .zip(gate.parameters.iter().cloned())
+            .collect();
+
+        let mut instructions = self.instructions.clone();
+        for instruction in instructions.iter_mut() {
+            for qubit in qubits_mut(instruction) {
+                if let Qubit::Variable(name) = qubit {
+                    if let Some(expansion) = qubits.get(name.as_str()) {
+                        *qubit = (*expansion).clone();
+                    }
+                }
+            }
+            instruction.apply_to_expressions(|expression| {
+                let previous = std::mem::replace(expression, Expression::PiConstant);
+                *expression = previous.substitute_variables(&parameters);
+            });
+        }
+        Ok(instructions)
+    }
+}
+
+impl Program {
+    /// The circuits defined in the program with `DEFCIRCUIT`, in the order they are defined.
+    ///
+    /// Like gate definitions, circuit definitions are kept among the program's instructions, so
+    /// that they are written out where they were defined.
+    pub fn circuit_definitions(&self) -> impl Iterator<Item = &CircuitDefinition> {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::CircuitDefinition(definition) => Some(definition),
+                _ => None,
+            })
+    }
+
+    /// Return the last definition of the circuit named `name`, if there is one.
+    pub fn get_circuit_definition(&self, name: &str) -> Option<&CircuitDefinition> {
+        self.circuit_definitions()
+            .filter(|definition| definition.name == name)
+            .last()
+    }
+
+    /// Replace each invocation of a circuit defined in the program with the instructions of the
+    /// circuit, substituting the qubits and parameters of the invocation for those of the
+    /// definition. Circuits invoked within circuits are expanded in turn, and the definitions
+    /// themselves are removed.
+    ///
+    /// Fails if a circuit is invoked with modifiers or the wrong number of qubits or parameters,
+    /// or if a circuit invokes itself, directly or indirectly.
+    #[allow(clippy::result_large_err)]
+    pub fn expand_circuits(&self) -> Result<Self> {
+        let circuits: HashMap<&str, &CircuitDefinition> = self
+            .circuit_definitions()
+            .map(|definition| (definition.name.as_str(), definition))
+            .collect();
+
+        let mut expanded_instructions = vec![];
+        for instruction in &self.instructions {
+            if !matches!(instruction, Instruction::CircuitDefinition(_)) {
+                expand_into(
+                    instruction,
+                    &circuits,
+                    &mut vec![],
+                    &mut expanded_instructions,
+                )?;
+            }
+        }
+
+        let mut new_program = self.clone();
+        new_program.instructions = expanded_instructions;
+        new_program.clear_source();
+        Ok(new_program)
+    }
+}
+
+/// Push `instruction` onto `expanded`, or the instructions it expands to if it invokes one of
+/// `circuits`. `expanding` holds the circuits being expanded, innermost last.
+#[allow(clippy::result_large_err)]
+fn expand_into<'c>(
+    instruction: &Instruction,
+    circuits: &HashMap<&str, &'c CircuitDefinition>,
+    expanding: &mut Vec<&'c str>,
+    expanded: &mut Vec<Instruction>,
+) -> std::result::Result<(), ProgramError<Program>> {
+    let (gate, definition) = match instruction {
+        Instruction::Gate(gate) => match circuits.get(gate.name.as_str()) {
+            Some(definition) => (gate, *definition),
+            None => {
+                expanded.push(instruction.clone());
+                return Ok(());
+            }
+        },
+        _ => {
+            expanded.push(instruction.clone());
+            return Ok(());
+        }
+    };
+
+    if expanding.contains(&definition.name.as_str()) {
+        return Err(ProgramError::RecursiveCircuit(Box::new(
+            instruction.clone(),
+        )));
+    }
+    let body =
+        definition
+            .expand(gate)
+            .map_err(|message| ProgramError::InvalidCircuitInvocation {
+                instruction: Box::new(instruction.clone()),
+                message,
+            })?;
+
+    expanding.push(&definition.name);
+    for instruction in &body {
+        expand_into(instruction, circuits, expanding, expanded)?;
+    }
+    expanding.pop();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::instruction::Instruction;
+    use crate::program::ProgramError;
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0", "H 0\n")]
+    #[case(
+        "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nBELL 2 3\n",
+        "H 0\nCNOT 0 1\nH 2\nCNOT 2 3\n"
+    )]
+    #[case(
+        "DEFCIRCUIT ROT(%theta) q:\n    RX(%theta/2) q\n    RZ(%theta) 1\n\nROT(pi) 0\n",
+        "RX((pi/2)) 0\nRZ(pi) 1\n"
+    )]
+    #[case(
+        "DEFCIRCUIT READ q:\n    RESET q\n    MEASURE q\n    DELAY q 1.0\n\nREAD 2\n",
+        "RESET 2\nMEASURE 2\nDELAY 2 1\n"
+    )]
+    #[case(
+        "DEFCIRCUIT INNER(%a) q:\n    RX(%a) q\n\nDEFCIRCUIT OUTER(%b) p r:\n    INNER(%b*2) r\n    CZ p r\n\nOUTER(pi) 0 1\n",
+        "RX((pi*2)) 1\nCZ 0 1\n"
+    )]
+    #[case("X 0\nDEFCIRCUIT LATER q:\n    Y q\n\nLATER 5\n", "X 0\nY 5\n")]
+    fn test_expand_circuits(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let expanded = program.expand_circuits().unwrap();
+        assert_eq!(expanded.to_string(false), expected);
+        assert_eq!(expanded.circuit_definitions().count(), 0);
+    }
+
+    #[rstest]
+    #[case("DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0\n")]
+    #[case("DEFCIRCUIT ROT(%theta) q:\n    RX(%theta) q\n\nROT 0\n")]
+    #[case("DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nDAGGER BELL 0 1\n")]
+    fn test_invalid_invocation(#[case] input: &str) {
+        let program = Program::from_str(input).unwrap();
+        assert!(matches!(
+            program.expand_circuits(),
+            Err(ProgramError::InvalidCircuitInvocation { .. })
+        ));
+    }
+
+    #[rstest]
+    #[case("DEFCIRCUIT LOOP q:\n    LOOP q\n\nLOOP 0\n")]
+    #[case("DEFCIRCUIT A q:\n    B q\n\nDEFCIRCUIT B q:\n    A q\n\nA 0\n")]
+    fn test_recursive_circuit(#[case] input: &str) {
+        let program = Program::from_str(input).unwrap();
+        assert!(matches!(
+            program.expand_circuits(),
+            Err(ProgramError::RecursiveCircuit(_))
+        ));
+    }
+
+    #[test]
+    fn test_expand() {
+        let program = Program::from_str(
+            "DEFCIRCUIT ROT(%theta) a b:\n    RX(%theta/2) a\n    CNOT a b\n    MEASURE b\n\nROT(pi) 0 3\nDAGGER ROT(pi) 0 3",
+        )
+        .unwrap();
+        let (definition, gate, dagger) = match program.instructions.as_slice() {
+            [Instruction::CircuitDefinition(definition), Instruction::Gate(gate), Instruction::Gate(dagger)] => {
+                (definition, gate, dagger)
+            }
+            other => panic!("unexpected instructions: {other:?}"),
+        };
+
+        let expected = Program::from_str("RX(pi/2) 0\nCNOT 0 3\nMEASURE 3").unwrap();
+        assert_eq!(definition.expand(gate), Ok(expected.instructions));
+        assert!(definition.expand(dagger).is_err());
+    }
+
+    #[test]
+    fn test_circuit_definitions() {
+        let program = Program::from_str(
+            "DEFCIRCUIT A q:\n    X q\n\nDEFCIRCUIT B q:\n    Y q\n\nDEFCIRCUIT A q:\n    Z q\n\nA 0\n",
+        )
+        .unwrap();
+        let names: Vec<_> = program
+            .circuit_definitions()
+            .map(|definition| definition.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["A", "B", "A"]);
+        assert_eq!(
+            program.get_circuit_definition("A").unwrap().instructions[0].to_string(),
+            "Z q"
+        );
+        assert!(program.get_circuit_definition("C").is_none());
+    }
+}
@@ -0,0 +1,219 @@
+//! The comments and blank lines around the instructions of a program, recorded when parsing so
+//! that they can be written back out with the program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::instruction::Instruction;
+
+use super::{is_header, Program, SourceSpan};
+
+/// The instruction which comments are attached to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Anchor {
+    /// The instruction at this index of [`Program::instructions`].
+    Body(usize),
+    /// The header, such as a `DECLARE` or `DEFCAL`, written as this Quil. Headers are not kept in
+    /// the order they were parsed, so they are recognized by how they are written instead.
+    Header(String),
+}
+
+impl Anchor {
+    /// The anchor of `instruction`, which is added to the body of the program at `index` unless it
+    /// is a header.
+    pub(crate) fn new(instruction: &Instruction, index: usize) -> Self {
+        if is_header(instruction) {
+            Self::Header(instruction.to_string())
+        } else {
+            Self::Body(index)
+        }
+    }
+}
+
+/// The comments and blank lines of the text a program was parsed from. Each line is kept as it
+/// was written, without leading or trailing whitespace, so that blank lines are empty.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Comments {
+    /// the comment and blank lines before each instruction
+    leading: HashMap<Anchor, Vec<String>>,
+    /// the comment at the end of the last line of each instruction
+    trailing: HashMap<Anchor, String>,
+    /// the comment and blank lines after the last instruction
+    end: Vec<String>,
+}
+
+impl Comments {
+    /// Record the comments and blank lines of `input` outside of the `spans` of its instructions,
+    /// which are given in order.
+    pub(crate) fn record(
+        input: &str,
+        spans: impl IntoIterator<Item = (Anchor, SourceSpan)>,
+    ) -> Self {
+        let mut comments = Self::default();
+        let mut previous: Option<Anchor> = None;
+        let mut offset = 0;
+        let mut lines = vec![];
+        for (anchor, span) in spans {
+            comments.record_gap(
+                &input[offset..span.start.offset],
+                previous.take(),
+                &mut lines,
+            );
+            if !lines.is_empty() {
+                comments
+                    .leading
+                    .entry(anchor.clone())
+                    .or_default()
+                    .append(&mut lines);
+            }
+            offset = span.end.offset;
+            previous = Some(anchor);
+        }
+        comments.record_gap(&input[offset..], previous, &mut lines);
+        comments.end = lines;
+        comments
+    }
+
+    /// Record the comments in `gap`, the text between two instructions: a comment after the
+    /// `previous` instruction on its last line, if there was a previous instruction, and then the
+    /// comment and blank lines in full, which are added to `lines`.
+    fn record_gap(&mut self, gap: &str, previous: Option<Anchor>, lines: &mut Vec<String>) {
+        let mut segments: Vec<&str> = gap.split('\n').collect();
+        if let Some(previous) = previous {
+            let rest_of_line = segments.remove(0);
+            if let Some(start) = rest_of_line.find('#') {
+                self.trailing
+                    .insert(previous, rest_of_line[start..].trim_end().to_string());
+            }
+        }
+        // The last segment is the start of the line of the next instruction.
+        segments.pop();
+        for line in segments {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                lines.push(line.to_string());
+            }
+        }
+    }
+
+    /// Move the comments of the instruction at each index of the body to the index given by
+    /// `reindex`, or drop them if it gives none.
+    pub(crate) fn reindex(&mut self, reindex: impl Fn(usize) -> Option<usize>) {
+        let reindex_anchor = |anchor: Anchor| match anchor {
+            Anchor::Body(index) => reindex(index).map(Anchor::Body),
+            header => Some(header),
+        };
+        self.leading = std::mem::take(&mut self.leading)
+            .into_iter()
+            .filter_map(|(anchor, lines)| Some((reindex_anchor(anchor)?, lines)))
+            .collect();
+        self.trailing = std::mem::take(&mut self.trailing)
+            .into_iter()
+            .filter_map(|(anchor, comment)| Some((reindex_anchor(anchor)?, comment)))
+            .collect();
+    }
+
+    /// Write `instructions` as Quil, one per line, each preceded and followed by the comments
+    /// recorded around it. `headers` is the number of `instructions` before the body of the
+    /// program.
+    pub(crate) fn write(&self, instructions: &[Instruction], headers: usize) -> String {
+        let mut quil = String::new();
+        for (index, instruction) in instructions.iter().enumerate() {
+            let anchor = match index.checked_sub(headers) {
+                Some(index) => Anchor::Body(index),
+                None => Anchor::new(instruction, index),
+            };
+            for line in self.leading.get(&anchor).into_iter().flatten() {
+                quil.push_str(line);
+                quil.push('\n');
+            }
+            quil.push_str(&instruction.to_string());
+            if let Some(comment) = self.trailing.get(&anchor) {
+                quil.push(' ');
+                quil.push_str(comment);
+            }
+            quil.push('\n');
+        }
+        for line in &self.end {
+            quil.push_str(line);
+            quil.push('\n');
+        }
+        quil
+    }
+}
+
+impl Program {
+    /// Write the program as Quil like [`Program::to_string`], along with the comments and blank
+    /// lines of the text it was parsed from, if it was parsed with
+    /// [`Program::parse_with_source_text`]. Comments are written before or after the instructions
+    /// they were found before or after, with headers such as `DECLARE` recognized by how they are
+    /// written.
+    ///
+    /// As with the source text, comments move with their instructions when the body is edited,
+    /// and are kept with an instruction when it is replaced.
+    pub fn to_string_with_comments(&self, include_headers: bool) -> String {
+        let instructions = self.to_instructions(include_headers);
+        let headers = instructions.len() - self.instructions.len();
+        self.comments.write(&instructions, headers)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0\nX 1\n")]
+    #[case("# Prepare a Bell state\n\nH 0 # superpose\nCNOT 0 1\n\n# done\n")]
+    #[case("DECLARE ro BIT[1] # results\n\n# Measure\nMEASURE 0 ro[0]\n")]
+    #[case("# calibrations\nDEFCAL X 0:\n\tNOP\n\n# body\nX 0\n")]
+    fn test_round_trip(#[case] input: &str) {
+        let program = Program::parse_with_source_text(input).unwrap();
+        assert_eq!(program.to_string_with_comments(true), input);
+    }
+
+    #[test]
+    fn test_comments_are_normalized() {
+        let program =
+            Program::parse_with_source_text("  # indented\nH 0; X 1 # both\n   \nY 2").unwrap();
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "# indented\nH 0\nX 1 # both\n\nY 2\n"
+        );
+    }
+
+    #[test]
+    fn test_headers_keep_their_comments() {
+        let input = "# the last region\nDECLARE theta REAL\n# the first region\nDECLARE alpha REAL\nRX(theta) 0";
+        let program = Program::parse_with_source_text(input).unwrap();
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "# the first region\nDECLARE alpha REAL[1]\n# the last region\nDECLARE theta REAL[1]\nRX(theta[0]) 0\n"
+        );
+        assert_eq!(program.to_string_with_comments(false), "RX(theta[0]) 0\n");
+    }
+
+    #[test]
+    fn test_no_comments_without_source_text() {
+        let program: Program = "# comment\nH 0".parse().unwrap();
+        assert_eq!(
+            program.to_string_with_comments(true),
+            program.to_string(true)
+        );
+    }
+}
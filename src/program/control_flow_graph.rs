@@ -0,0 +1,429 @@
+//! The control-flow graph of a program: its basic blocks, and the jumps and fall-throughs between
+//! them.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::visit::{Dfs, EdgeRef};
+use petgraph::Direction;
+
+use crate::instruction::{ControlFlow, Instruction, Label, MemoryReference};
+use crate::Program;
+
+/// Errors which may occur while building the control-flow graph of a program.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum ControlFlowGraphError {
+    #[error("label {0} is defined more than once")]
+    DuplicateLabel(Label),
+
+    #[error("label {0} is jumped to but never defined")]
+    UndefinedLabel(Label),
+}
+
+/// A run of instructions of a program which, once entered, are executed in order to their end.
+///
+/// A block begins at the start of the program, at a `LABEL`, or after an instruction which ends
+/// the block before it, and ends with `JUMP`, `JUMP-WHEN`, `JUMP-UNLESS`, `HALT`, the next
+/// `LABEL`, or the end of the program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BasicBlock<'p> {
+    label: Option<&'p Label>,
+    label_index: Option<usize>,
+    instructions: Vec<(usize, &'p Instruction)>,
+}
+
+impl<'p> BasicBlock<'p> {
+    /// The label at which the block begins, if any.
+    pub fn label(&self) -> Option<&'p Label> {
+        self.label
+    }
+
+    /// The index of the `LABEL` the block begins with among the program's instructions, if any.
+    pub fn label_index(&self) -> Option<usize> {
+        self.label_index
+    }
+
+    /// The instructions of the block, in order, not including the `LABEL` it begins with.
+    pub fn instructions(&self) -> impl Iterator<Item = &'p Instruction> + '_ {
+        self.instructions
+            .iter()
+            .map(|(_, instruction)| *instruction)
+    }
+
+    /// The index of each instruction of the block among the program's instructions.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.instructions.iter().map(|(index, _)| *index)
+    }
+
+    /// How execution proceeds at the end of the block: as after its last instruction if that ends
+    /// the block, and with the next block otherwise.
+    pub fn terminator(&self) -> ControlFlow<'p> {
+        self.instructions
+            .last()
+            .map(|(_, instruction)| instruction.control_flow())
+            .filter(ControlFlow::ends_basic_block)
+            .unwrap_or(ControlFlow::FallThrough)
+    }
+
+    /// The number of instructions in the block.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether the block has no instructions, as does one which only defines a label.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+/// When execution follows an edge of a [`ControlFlowGraph`] from the end of one block to the
+/// start of another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BranchCondition<'p> {
+    /// Whenever the block ends, as after `JUMP` or a block which falls through.
+    Always,
+    /// When the memory is non-zero, as for the target of `JUMP-WHEN` and the block after
+    /// `JUMP-UNLESS`.
+    NonZero(&'p MemoryReference),
+    /// When the memory is zero, as for the target of `JUMP-UNLESS` and the block after
+    /// `JUMP-WHEN`.
+    Zero(&'p MemoryReference),
+}
+
+impl<'p> BranchCondition<'p> {
+    /// The condition which holds whenever `self` does not.
+    fn negate(self) -> Self {
+        match self {
+            Self::Always => Self::Always,
+            Self::NonZero(reference) => Self::Zero(reference),
+            Self::Zero(reference) => Self::NonZero(reference),
+        }
+    }
+}
+
+/// The basic blocks of a program, connected by an edge wherever execution may continue from the
+/// end of one block to the start of another. Blocks are numbered in the order they appear in the
+/// program, and execution begins with the first.
+///
+/// Execution ends after a block with no successor for which the condition holds: one ended by
+/// `HALT`, or the last block of the program where it falls through.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::program::control_flow_graph::BranchCondition;
+/// use quil_rs::Program;
+///
+/// let program: Program = "DECLARE ro BIT
+/// LABEL @loop
+/// MEASURE 0 ro
+/// JUMP-WHEN @loop ro
+/// X 0"
+///     .parse()
+///     .unwrap();
+/// let graph = program.to_cfg().unwrap();
+///
+/// assert_eq!(graph.len(), 2);
+/// let successors: Vec<_> = graph.successors(0).collect();
+/// assert_eq!(successors.len(), 2);
+/// assert!(successors.contains(&(0, BranchCondition::NonZero(&"ro".parse().unwrap()))));
+/// assert!(successors.contains(&(1, BranchCondition::Zero(&"ro".parse().unwrap()))));
+/// ```
+#[derive(Clone, Debug)]
+pub struct ControlFlowGraph<'p> {
+    graph: DiGraph<BasicBlock<'p>, BranchCondition<'p>>,
+}
+
+impl<'p> ControlFlowGraph<'p> {
+    /// The block numbered `index`, if there is one.
+    pub fn block(&self, index: usize) -> Option<&BasicBlock<'p>> {
+        self.graph.node_weight(NodeIndex::new(index))
+    }
+
+    /// The blocks of the program, in the order they appear in it.
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock<'p>> + '_ {
+        self.graph.node_weights()
+    }
+
+    /// The number of the block which begins at `label`, if any.
+    pub fn block_of_label(&self, label: &Label) -> Option<usize> {
+        self.graph
+            .node_indices()
+            .find(|index| self.graph[*index].label == Some(label))
+            .map(NodeIndex::index)
+    }
+
+    /// The blocks execution may continue with after the block numbered `index`, each with the
+    /// condition under which it does.
+    pub fn successors(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = (usize, BranchCondition<'p>)> + '_ {
+        self.graph
+            .edges_directed(NodeIndex::new(index), Direction::Outgoing)
+            .map(|edge| (edge.target().index(), *edge.weight()))
+    }
+
+    /// The blocks after which execution may continue with the block numbered `index`, each with
+    /// the condition under which it does.
+    pub fn predecessors(
+        &self,
+        index: usize,
+    ) -> impl Iterator<Item = (usize, BranchCondition<'p>)> + '_ {
+        self.graph
+            .edges_directed(NodeIndex::new(index), Direction::Incoming)
+            .map(|edge| (edge.source().index(), *edge.weight()))
+    }
+
+    /// The blocks which can never be reached from the first block of the program, whichever way
+    /// its branches go, in the order they appear in it.
+    pub fn unreachable_blocks(&self) -> impl Iterator<Item = &BasicBlock<'p>> + '_ {
+        let mut reached = HashSet::new();
+        if !self.is_empty() {
+            let mut dfs = Dfs::new(&self.graph, NodeIndex::new(0));
+            while let Some(node) = dfs.next(&self.graph) {
+                reached.insert(node);
+            }
+        }
+        self.graph
+            .node_indices()
+            .filter(move |node| !reached.contains(node))
+            .map(|node| &self.graph[node])
+    }
+
+    /// The graph itself, for use with the algorithms of [`petgraph`], in which the index of each
+    /// node is the number of its block.
+    pub fn graph(&self) -> &DiGraph<BasicBlock<'p>, BranchCondition<'p>> {
+        &self.graph
+    }
+
+    /// The number of blocks.
+    pub fn len(&self) -> usize {
+        self.graph.node_count()
+    }
+
+    /// Whether there are no blocks, as in a program with no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.graph.node_count() == 0
+    }
+}
+
+impl Program {
+    /// Build the control-flow graph of the program's instructions.
+    ///
+    /// Instructions which only declare or define something are kept in the blocks they appear
+    /// in, as are blocks which can never be reached.
+    pub fn to_cfg(&self) -> Result<ControlFlowGraph<'_>, ControlFlowGraphError> {
+        let mut blocks = vec![];
+        let mut current = BasicBlock {
+            label: None,
+            label_index: None,
+            instructions: vec![],
+        };
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if let Instruction::Label(label) = instruction {
+                let previous = std::mem::replace(
+                    &mut current,
+                    BasicBlock {
+                        label: Some(label),
+                        label_index: Some(index),
+                        instructions: vec![],
+                    },
+                );
+                if previous.label.is_some() || !previous.is_empty() {
+                    blocks.push(previous);
+                }
+                continue;
+            }
+            current.instructions.push((index, instruction));
+            if instruction.control_flow().ends_basic_block() {
+                blocks.push(std::mem::replace(
+                    &mut current,
+                    BasicBlock {
+                        label: None,
+                        label_index: None,
+                        instructions: vec![],
+                    },
+                ));
+            }
+        }
+        if current.label.is_some() || !current.is_empty() {
+            blocks.push(current);
+        }
+
+        let mut labels = HashMap::new();
+        for (index, block) in blocks.iter().enumerate() {
+            if let Some(label) = block.label {
+                if labels.insert(label, index).is_some() {
+                    return Err(ControlFlowGraphError::DuplicateLabel(label.clone()));
+                }
+            }
+        }
+
+        let mut edges = vec![];
+        for (index, block) in blocks.iter().enumerate() {
+            let control_flow = block.terminator();
+            let mut fall_through_condition = BranchCondition::Always;
+            if let Some(target) = control_flow.jump_target() {
+                let target_index = *labels
+                    .get(target)
+                    .ok_or_else(|| ControlFlowGraphError::UndefinedLabel(target.clone()))?;
+                let condition = match control_flow {
+                    ControlFlow::ConditionalJump {
+                        condition,
+                        jump_if_condition_true: true,
+                        ..
+                    } => BranchCondition::NonZero(condition),
+                    ControlFlow::ConditionalJump { condition, .. } => {
+                        BranchCondition::Zero(condition)
+                    }
+                    _ => BranchCondition::Always,
+                };
+                edges.push((index, target_index, condition));
+                fall_through_condition = condition.negate();
+            }
+            if control_flow.falls_through() && index + 1 < blocks.len() {
+                edges.push((index, index + 1, fall_through_condition));
+            }
+        }
+
+        let mut graph = DiGraph::with_capacity(blocks.len(), edges.len());
+        for block in blocks {
+            graph.add_node(block);
+        }
+        for (source, target, condition) in edges {
+            graph.add_edge(NodeIndex::new(source), NodeIndex::new(target), condition);
+        }
+        Ok(ControlFlowGraph { graph })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{BranchCondition, ControlFlowGraphError};
+    use crate::instruction::{ControlFlow, Label};
+    use crate::Program;
+
+    /// The successors of a block, with whether the edge to each is conditional.
+    type Edges = Vec<(usize, bool)>;
+
+    /// The indices of the instructions of each block of `input`, and the successors of each block
+    /// with whether the edge to each is conditional.
+    fn blocks_and_edges(input: &str) -> (Vec<Vec<usize>>, Vec<Edges>) {
+        let program = Program::from_str(input).unwrap();
+        let graph = program.to_cfg().unwrap();
+        let blocks = graph
+            .blocks()
+            .map(|block| block.indices().collect())
+            .collect();
+        let edges = (0..graph.len())
+            .map(|index| {
+                let mut successors: Vec<_> = graph
+                    .successors(index)
+                    .map(|(target, condition)| (target, condition != BranchCondition::Always))
+                    .collect();
+                successors.sort();
+                successors
+            })
+            .collect();
+        (blocks, edges)
+    }
+
+    #[rstest]
+    #[case("", vec![], vec![])]
+    #[case("H 0\nX 1", vec![vec![0, 1]], vec![vec![]])]
+    #[case("H 0\nLABEL @a\nX 0", vec![vec![0], vec![2]], vec![vec![(1, false)], vec![]])]
+    #[case(
+        "LABEL @a\nH 0\nJUMP @a\nX 0",
+        vec![vec![1, 2], vec![3]],
+        vec![vec![(0, false)], vec![]]
+    )]
+    #[case(
+        "DECLARE ro BIT\nLABEL @a\nMEASURE 0 ro\nJUMP-UNLESS @end ro\nX 0\nJUMP @a\nLABEL @end",
+        vec![vec![1, 2], vec![3, 4], vec![]],
+        vec![vec![(1, true), (2, true)], vec![(0, false)], vec![]]
+    )]
+    #[case("H 0\nHALT\nX 0", vec![vec![0, 1], vec![2]], vec![vec![], vec![]])]
+    #[case("LABEL @a\nLABEL @b\nWAIT", vec![vec![], vec![2]], vec![vec![(1, false)], vec![]])]
+    fn test_to_cfg(
+        #[case] input: &str,
+        #[case] expected_blocks: Vec<Vec<usize>>,
+        #[case] expected_edges: Vec<Vec<(usize, bool)>>,
+    ) {
+        let (blocks, edges) = blocks_and_edges(input);
+        assert_eq!(blocks, expected_blocks);
+        assert_eq!(edges, expected_edges);
+    }
+
+    #[test]
+    fn test_branch_conditions() {
+        let program =
+            Program::from_str("DECLARE ro BIT\nLABEL @a\nJUMP-WHEN @b ro\nH 0\nLABEL @b\nX 0")
+                .unwrap();
+        let graph = program.to_cfg().unwrap();
+        let ro = "ro".parse().unwrap();
+        assert_eq!(graph.len(), 3);
+        assert_eq!(graph.block_of_label(&Label::Fixed("b".into())), Some(2));
+        assert_eq!(
+            graph.block(0).unwrap().label(),
+            Some(&Label::Fixed("a".into()))
+        );
+        assert!(matches!(
+            graph.block(0).unwrap().terminator(),
+            ControlFlow::ConditionalJump { .. }
+        ));
+        assert_eq!(
+            graph.block(1).unwrap().terminator(),
+            ControlFlow::FallThrough
+        );
+
+        let mut successors: Vec<_> = graph.successors(0).collect();
+        successors.sort_by_key(|(target, _)| *target);
+        assert_eq!(
+            successors,
+            vec![
+                (1, BranchCondition::Zero(&ro)),
+                (2, BranchCondition::NonZero(&ro))
+            ]
+        );
+        let mut predecessors: Vec<_> = graph.predecessors(2).collect();
+        predecessors.sort_by_key(|(source, _)| *source);
+        assert_eq!(
+            predecessors,
+            vec![
+                (0, BranchCondition::NonZero(&ro)),
+                (1, BranchCondition::Always)
+            ]
+        );
+        assert!(!petgraph::algo::is_cyclic_directed(graph.graph()));
+    }
+
+    #[rstest]
+    #[case("JUMP @missing", ControlFlowGraphError::UndefinedLabel(Label::Fixed("missing".into())))]
+    #[case(
+        "LABEL @a\nH 0\nLABEL @a",
+        ControlFlowGraphError::DuplicateLabel(Label::Fixed("a".into()))
+    )]
+    fn test_invalid_cfg(#[case] input: &str, #[case] expected: ControlFlowGraphError) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.to_cfg().unwrap_err(), expected);
+    }
+}
@@ -0,0 +1,363 @@
+//! The dependency graph of a program's instructions: which instructions must be applied before
+//! which others, given the qubits and memory they use, and how the program may be scheduled as a
+//! result.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use petgraph::graph::{DiGraph, NodeIndex};
+use petgraph::Direction;
+
+use crate::instruction::{
+    Capture, Delay, Fence, Gate, Instruction, Measurement, Pulse, Qubit, RawCapture, Reset,
+    SetFrequency, SetPhase, SetScale, ShiftFrequency, ShiftPhase, SwapPhases,
+};
+use crate::Program;
+
+/// What an instruction uses while it is applied.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(super) enum Resource {
+    Qubit(Qubit),
+    Memory(String),
+}
+
+/// The resources `instruction` uses, split into those it only reads and those it also changes,
+/// or `None` if its dependencies cannot be told from what it uses, so that it must be ordered
+/// with respect to every other instruction.
+pub(super) fn resources(instruction: &Instruction) -> Option<(Vec<Resource>, Vec<Resource>)> {
+    let qubits: Vec<Qubit> = match instruction {
+        Instruction::Gate(Gate { qubits, .. })
+        | Instruction::Delay(Delay { qubits, .. })
+        | Instruction::Fence(Fence { qubits }) => {
+            if qubits.is_empty() {
+                // A `FENCE` on no qubits is a fence on all of them.
+                return None;
+            }
+            qubits.clone()
+        }
+        Instruction::Measurement(Measurement { qubit, .. })
+        | Instruction::Reset(Reset { qubit: Some(qubit) }) => vec![qubit.clone()],
+        Instruction::Capture(Capture { frame, .. })
+        | Instruction::Pulse(Pulse { frame, .. })
+        | Instruction::RawCapture(RawCapture { frame, .. })
+        | Instruction::SetFrequency(SetFrequency { frame, .. })
+        | Instruction::SetPhase(SetPhase { frame, .. })
+        | Instruction::SetScale(SetScale { frame, .. })
+        | Instruction::ShiftFrequency(ShiftFrequency { frame, .. })
+        | Instruction::ShiftPhase(ShiftPhase { frame, .. }) => frame.qubits.clone(),
+        Instruction::SwapPhases(SwapPhases { frame_1, frame_2 }) => frame_1
+            .qubits
+            .iter()
+            .chain(&frame_2.qubits)
+            .cloned()
+            .collect(),
+        Instruction::Arithmetic(_)
+        | Instruction::BinaryLogic(_)
+        | Instruction::Comparison(_)
+        | Instruction::Convert(_)
+        | Instruction::Exchange(_)
+        | Instruction::Load(_)
+        | Instruction::Move(_)
+        | Instruction::Store(_)
+        | Instruction::UnaryLogic(_) => vec![],
+        _ => return None,
+    };
+
+    let accesses = instruction.get_memory_accesses();
+    let reads = accesses
+        .reads
+        .difference(&accesses.writes)
+        .cloned()
+        .map(Resource::Memory)
+        .collect();
+    let writes = qubits
+        .into_iter()
+        .map(Resource::Qubit)
+        .chain(
+            accesses
+                .writes
+                .into_iter()
+                .chain(accesses.captures)
+                .map(Resource::Memory),
+        )
+        .collect();
+    Some((reads, writes))
+}
+
+/// The instructions of a program, connected by an edge from each instruction to every later one
+/// which must wait for it to be applied.
+///
+/// An instruction depends on the last earlier instruction which uses any of the same qubits, or
+/// writes memory it reads or writes, and on each earlier instruction since then which reads memory
+/// it writes. Instructions whose dependencies cannot be told from the qubits and memory they use,
+/// such as control flow, pragmas, and a `FENCE` or `RESET` of every qubit, are ordered with
+/// respect to every other instruction.
+///
+/// # Example
+///
+/// ```rust
+/// use quil_rs::Program;
+///
+/// let program: Program = "H 0\nH 1\nCNOT 0 1\nX 2\nY 2".parse().unwrap();
+/// let dag = program.to_dag();
+///
+/// assert_eq!(dag.logical_time(2), Some(1));
+/// assert_eq!(dag.depth(), 2);
+/// assert_eq!(
+///     dag.to_packed_program().to_string(false),
+///     "H 0\nH 1\nX 2\nCNOT 0 1\nY 2\n"
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct InstructionDag<'p> {
+    program: &'p Program,
+    /// The node of each instruction is that numbered by its index among the program's
+    /// instructions.
+    graph: DiGraph<(), ()>,
+    /// The logical time of each instruction.
+    times: Vec<usize>,
+}
+
+impl<'p> InstructionDag<'p> {
+    fn new(program: &'p Program) -> Self {
+        let mut graph = DiGraph::with_capacity(program.instructions.len(), 0);
+        let mut writers: HashMap<Resource, usize> = HashMap::new();
+        let mut readers: HashMap<Resource, Vec<usize>> = HashMap::new();
+        // The last instruction ordered with respect to every other, and every instruction since.
+        let mut barrier: Option<usize> = None;
+        let mut since_barrier: Vec<usize> = vec![];
+
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            graph.add_node(());
+            let mut dependencies = HashSet::new();
+            match resources(instruction) {
+                Some((reads, writes)) => {
+                    dependencies.extend(barrier);
+                    for resource in reads {
+                        dependencies.extend(writers.get(&resource));
+                        readers.entry(resource).or_default().push(index);
+                    }
+                    for resource in writes {
+                        dependencies.extend(writers.get(&resource));
+                        dependencies.extend(readers.remove(&resource).into_iter().flatten());
+                        writers.insert(resource, index);
+                    }
+                    since_barrier.push(index);
+                }
+                None => {
+                    if since_barrier.is_empty() {
+                        dependencies.extend(barrier);
+                    }
+                    dependencies.extend(since_barrier.drain(..));
+                    writers.clear();
+                    readers.clear();
+                    barrier = Some(index);
+                }
+            }
+            dependencies.remove(&index);
+
+            let mut dependencies: Vec<usize> = dependencies.into_iter().collect();
+            dependencies.sort_unstable();
+            for dependency in dependencies {
+                graph.add_edge(NodeIndex::new(dependency), NodeIndex::new(index), ());
+            }
+        }
+
+        // Every dependency comes before its dependent among the instructions.
+        let mut times = vec![0; graph.node_count()];
+        for index in 0..times.len() {
+            times[index] = graph
+                .neighbors_directed(NodeIndex::new(index), Direction::Incoming)
+                .map(|dependency| times[dependency.index()] + 1)
+                .max()
+                .unwrap_or(0);
+        }
+
+        Self {
+            program,
+            graph,
+            times,
+        }
+    }
+
+    /// The indices of the instructions which the instruction at `index` directly depends on, in
+    /// the order they appear in the program.
+    pub fn dependencies(&self, index: usize) -> Vec<usize> {
+        self.neighbors(index, Direction::Incoming)
+    }
+
+    /// The indices of the instructions which directly depend on the instruction at `index`, in
+    /// the order they appear in the program.
+    pub fn dependents(&self, index: usize) -> Vec<usize> {
+        self.neighbors(index, Direction::Outgoing)
+    }
+
+    fn neighbors(&self, index: usize, direction: Direction) -> Vec<usize> {
+        if index >= self.len() {
+            return vec![];
+        }
+        let mut neighbors: Vec<usize> = self
+            .graph
+            .neighbors_directed(NodeIndex::new(index), direction)
+            .map(NodeIndex::index)
+            .collect();
+        neighbors.sort_unstable();
+        neighbors
+    }
+
+    /// The logical time of the instruction at `index`: the number of instructions in the longest
+    /// chain of dependencies leading up to it, such that it may be applied at the same time as
+    /// every other instruction of the same logical time. The first instructions have logical time
+    /// zero.
+    pub fn logical_time(&self, index: usize) -> Option<usize> {
+        self.times.get(index).copied()
+    }
+
+    /// The number of distinct logical times taken by the program's instructions, or the length of
+    /// its critical path.
+    pub fn depth(&self) -> usize {
+        self.times.iter().max().map_or(0, |time| time + 1)
+    }
+
+    /// The indices of the instructions in an order in which each comes after those it depends
+    /// on, by logical time and then by their order in the program.
+    pub fn topological_order(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.len()).collect();
+        order.sort_by_key(|index| (self.times[*index], *index));
+        order
+    }
+
+    /// The indices of the instructions in a longest chain of dependencies, from first to last:
+    /// the instructions which, if any were delayed, would delay the whole program. Where there is
+    /// more than one longest chain, that which ends with and follows the earliest instructions is
+    /// returned.
+    pub fn critical_path(&self) -> Vec<usize> {
+        let mut current = (0..self.len()).find(|index| self.times[*index] + 1 == self.depth());
+        let mut path = vec![];
+        while let Some(index) = current {
+            path.push(index);
+            current = self
+                .dependencies(index)
+                .into_iter()
+                .find(|dependency| self.times[*dependency] + 1 == self.times[index]);
+        }
+        path.reverse();
+        path
+    }
+
+    /// A copy of the program with its instructions reordered as in
+    /// [`InstructionDag::topological_order`], so that the instructions of each logical time are
+    /// gathered together. Every instruction is still applied after those it depends on.
+    pub fn to_packed_program(&self) -> Program {
+        let mut program = self.program.clone();
+        program.instructions = self
+            .topological_order()
+            .into_iter()
+            .map(|index| self.program.instructions[index].clone())
+            .collect();
+        program.clear_source();
+        program
+    }
+
+    /// The graph itself, for use with the algorithms of [`petgraph`], in which the index of each
+    /// node is that of its instruction among the program's instructions.
+    pub fn graph(&self) -> &DiGraph<(), ()> {
+        &self.graph
+    }
+
+    /// The number of instructions.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    /// Whether there are no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+}
+
+impl Program {
+    /// Build the dependency graph of the program's instructions, from which the program may be
+    /// scheduled.
+    pub fn to_dag(&self) -> InstructionDag<'_> {
+        InstructionDag::new(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::Program;
+
+    #[rstest]
+    #[case("", vec![], 0)]
+    #[case("H 0\nH 1\nCNOT 0 1\nX 2", vec![0, 0, 1, 0], 2)]
+    #[case("H 0\nCNOT 0 1\nH 0\nX 1", vec![0, 1, 2, 2], 3)]
+    #[case("DECLARE ro BIT\nMEASURE 0 ro\nMEASURE 1 ro", vec![0, 1], 2)]
+    #[case("DECLARE ro REAL\nRX(ro) 0\nRX(ro) 1\nMOVE ro 1.0", vec![0, 0, 1], 2)]
+    #[case("H 0\nFENCE 0 1\nX 1\nX 2", vec![0, 1, 2, 0], 3)]
+    #[case("H 0\nFENCE\nX 1\nX 2", vec![0, 1, 2, 2], 3)]
+    #[case("H 0\nH 1\nLABEL @a\nLABEL @b\nX 0", vec![0, 0, 1, 2, 3], 4)]
+    #[case(
+        "DEFFRAME 0 \"rf\":\n    SAMPLE-RATE: 1.0\nH 0\nSHIFT-PHASE 0 \"rf\" pi\nX 1",
+        vec![0, 1, 0],
+        2
+    )]
+    fn test_logical_times(
+        #[case] input: &str,
+        #[case] expected_times: Vec<usize>,
+        #[case] expected_depth: usize,
+    ) {
+        let program = Program::from_str(input).unwrap();
+        let dag = program.to_dag();
+        let times: Vec<usize> = (0..dag.len())
+            .map(|index| dag.logical_time(index).unwrap())
+            .collect();
+        assert_eq!(times, expected_times);
+        assert_eq!(dag.depth(), expected_depth);
+        assert_eq!(dag.critical_path().len(), expected_depth);
+        assert!(!petgraph::algo::is_cyclic_directed(dag.graph()));
+    }
+
+    #[test]
+    fn test_dependencies() {
+        let program =
+            Program::from_str("DECLARE ro BIT[2]\nH 0\nH 1\nCNOT 0 1\nMEASURE 1 ro[0]\nX 0")
+                .unwrap();
+        let dag = program.to_dag();
+        assert_eq!(dag.dependencies(2), vec![0, 1]);
+        assert_eq!(dag.dependents(2), vec![3, 4]);
+        assert_eq!(dag.dependencies(0), Vec::<usize>::new());
+        assert_eq!(dag.dependents(10), Vec::<usize>::new());
+        assert_eq!(dag.critical_path(), vec![0, 2, 3]);
+        assert_eq!(dag.topological_order(), vec![0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn test_packed_program() {
+        let program = Program::from_str("H 0\nCNOT 0 1\nX 2\nH 3\nY 2\nMEASURE 3").unwrap();
+        let packed = program.to_dag().to_packed_program();
+        assert_eq!(
+            packed.to_string(true),
+            "H 0\nX 2\nH 3\nCNOT 0 1\nY 2\nMEASURE 3\n"
+        );
+        // Packing a packed program changes nothing.
+        assert_eq!(packed.to_dag().to_packed_program(), packed);
+    }
+}
@@ -0,0 +1,514 @@
+//! Removing instructions which have no effect on the outcome of a program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use crate::instruction::{
+    Arithmetic, ArithmeticOperand, ArithmeticOperator, Capture, Gate, Instruction, Measurement,
+    Move, Qubit, RawCapture,
+};
+use crate::Program;
+
+use super::dag::{resources, Resource};
+
+/// Which instructions [`Program::eliminate_dead_code`] removes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DeadCodeOptions {
+    /// Remove instructions which can never be reached, whichever way the program's branches go.
+    pub unreachable_code: bool,
+    /// Remove each gate followed, next on the same qubits, by its inverse, along with that
+    /// inverse, as in `X 0` followed by `X 0`, or `DAGGER S 0` followed by `S 0`. Gates whose
+    /// names the program defines with `DEFGATE` or `DEFCIRCUIT` are never cancelled.
+    pub inverse_pairs: bool,
+    /// Remove instructions which do nothing: `NOP`, a `MOVE` of memory to itself, and an
+    /// addition or subtraction of zero, or multiplication or division by one.
+    pub no_ops: bool,
+    /// Remove gates on qubits which are never measured or captured from, and which take part in
+    /// no gate with a qubit which is. This changes the state the program leaves its qubits in, so
+    /// only applies to programs whose results are all read from memory.
+    pub unmeasured_qubits: bool,
+}
+
+impl Default for DeadCodeOptions {
+    fn default() -> Self {
+        Self {
+            unreachable_code: true,
+            inverse_pairs: true,
+            no_ops: true,
+            unmeasured_qubits: false,
+        }
+    }
+}
+
+/// Why [`Program::eliminate_dead_code`] removed an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum RemovalReason {
+    /// The instruction can never be reached.
+    Unreachable,
+    /// The instruction is a gate cancelled by its inverse, applied next on the same qubits.
+    InversePair,
+    /// The instruction does nothing.
+    NoOp,
+    /// The instruction is a gate on qubits which never affect a measurement.
+    UnmeasuredQubits,
+}
+
+impl fmt::Display for RemovalReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unreachable => write!(f, "unreachable"),
+            Self::InversePair => write!(f, "cancelled by its inverse"),
+            Self::NoOp => write!(f, "does nothing"),
+            Self::UnmeasuredQubits => write!(f, "on unmeasured qubits"),
+        }
+    }
+}
+
+/// An instruction removed by [`Program::eliminate_dead_code`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RemovedInstruction {
+    /// The index of the instruction in the body of the program before it was removed.
+    pub index: usize,
+    pub instruction: Instruction,
+    pub reason: RemovalReason,
+}
+
+/// The instructions removed by [`Program::eliminate_dead_code`], in the order they appeared in
+/// the program.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeadCodeReport {
+    pub removed: Vec<RemovedInstruction>,
+}
+
+impl DeadCodeReport {
+    /// The number of instructions removed.
+    pub fn len(&self) -> usize {
+        self.removed.len()
+    }
+
+    /// Whether no instructions were removed.
+    pub fn is_empty(&self) -> bool {
+        self.removed.is_empty()
+    }
+
+    /// The number of instructions removed for `reason`.
+    pub fn count(&self, reason: RemovalReason) -> usize {
+        self.removed
+            .iter()
+            .filter(|removed| removed.reason == reason)
+            .count()
+    }
+}
+
+impl fmt::Display for DeadCodeReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for removed in &self.removed {
+            writeln!(
+                f,
+                "{}: {} ({})",
+                removed.index, removed.instruction, removed.reason
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `instruction` has no effect at all.
+fn is_no_op(instruction: &Instruction) -> bool {
+    let is_literal = |operand: &ArithmeticOperand, value: f64| match operand {
+        ArithmeticOperand::LiteralInteger(literal) => *literal as f64 == value,
+        ArithmeticOperand::LiteralReal(literal) => *literal == value,
+        ArithmeticOperand::MemoryReference(_) => false,
+    };
+    match instruction {
+        Instruction::Nop => true,
+        Instruction::Move(Move {
+            destination,
+            source: ArithmeticOperand::MemoryReference(source),
+        }) => destination == source,
+        Instruction::Arithmetic(Arithmetic {
+            operator, source, ..
+        }) => match operator {
+            ArithmeticOperator::Add | ArithmeticOperator::Subtract => is_literal(source, 0.0),
+            ArithmeticOperator::Multiply | ArithmeticOperator::Divide => is_literal(source, 1.0),
+        },
+        _ => false,
+    }
+}
+
+/// Whether `second` undoes `first`. Either may be written as the other's
+/// [`dagger`](Gate::dagger), and their parameters are compared once simplified, so that, for
+/// instance, `RX(theta) 0` and `DAGGER RX(theta) 0` are inverses, as are `RX(pi/2) 0` and
+/// `RX(-pi/2) 0`.
+fn are_inverses(first: &Gate, second: &Gate) -> bool {
+    let simplified = |mut gate: Gate| {
+        gate.parameters
+            .iter_mut()
+            .for_each(|parameter| parameter.simplify());
+        gate
+    };
+    simplified(first.dagger()) == simplified(second.clone())
+        || simplified(second.dagger()) == simplified(first.clone())
+}
+
+/// Decides which instructions of a program to remove, recording each by its index.
+struct Eliminator<'p> {
+    program: &'p Program,
+    removed: BTreeMap<usize, RemovalReason>,
+}
+
+impl<'p> Eliminator<'p> {
+    /// The instructions not yet removed, with their indices.
+    fn remaining(&self) -> impl Iterator<Item = (usize, &'p Instruction)> + '_ {
+        self.program
+            .instructions
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !self.removed.contains_key(index))
+    }
+
+    fn remove_unreachable(&mut self) {
+        // Without a control-flow graph, as when a jump's target is undefined, nothing is known to
+        // be unreachable.
+        let Ok(graph) = self.program.to_cfg() else {
+            return;
+        };
+        for block in graph.unreachable_blocks() {
+            for index in block.label_index().into_iter().chain(block.indices()) {
+                self.removed.insert(index, RemovalReason::Unreachable);
+            }
+        }
+    }
+
+    fn remove_no_ops(&mut self) {
+        let no_ops: Vec<usize> = self
+            .remaining()
+            .filter(|(_, instruction)| is_no_op(instruction))
+            .map(|(index, _)| index)
+            .collect();
+        for index in no_ops {
+            self.removed.insert(index, RemovalReason::NoOp);
+        }
+    }
+
+    fn remove_inverse_pairs(&mut self) {
+        // The instructions which changed each qubit or region of memory, since the last
+        // instruction ordered with respect to every other, excluding those cancelled.
+        let mut writers: HashMap<Resource, Vec<usize>> = HashMap::new();
+        let mut cancelled = vec![];
+        // Inverses are known only for the standard gates, not for those the program defines.
+        let defined = self.program.defined_gate_names();
+        for (index, instruction) in self.remaining() {
+            let Some((reads, writes)) = resources(instruction) else {
+                writers.clear();
+                continue;
+            };
+            if let Instruction::Gate(gate) = instruction {
+                // The gate is cancelled by the last gate to change any of its qubits if that
+                // changed all of them, is its inverse, and read memory which has not been
+                // changed since.
+                let previous = writes
+                    .iter()
+                    .map(|resource| writers.get(resource).and_then(|stack| stack.last()))
+                    .reduce(|a, b| if a == b { a } else { None })
+                    .flatten()
+                    .copied();
+                let cancels = previous.is_some_and(|previous| {
+                    let unchanged = reads.iter().all(|resource| {
+                        writers
+                            .get(resource)
+                            .and_then(|stack| stack.last())
+                            .is_none_or(|writer| *writer < previous)
+                    });
+                    match &self.program.instructions[previous] {
+                        Instruction::Gate(other) => {
+                            unchanged
+                                && !defined.contains(gate.name.as_str())
+                                && !defined.contains(other.name.as_str())
+                                && are_inverses(other, gate)
+                        }
+                        _ => false,
+                    }
+                });
+                if let (true, Some(previous)) = (cancels, previous) {
+                    for resource in &writes {
+                        writers.get_mut(resource).and_then(Vec::pop);
+                    }
+                    cancelled.extend([previous, index]);
+                    continue;
+                }
+            }
+            for resource in writes {
+                writers.entry(resource).or_default().push(index);
+            }
+        }
+        for index in cancelled {
+            self.removed.insert(index, RemovalReason::InversePair);
+        }
+    }
+
+    fn remove_unmeasured(&mut self) {
+        let mut measured: HashSet<&Qubit> = HashSet::new();
+        for (_, instruction) in self.remaining() {
+            match instruction {
+                Instruction::Measurement(Measurement { qubit, .. }) => {
+                    measured.insert(qubit);
+                }
+                Instruction::Capture(Capture { frame, .. })
+                | Instruction::RawCapture(RawCapture { frame, .. }) => {
+                    measured.extend(&frame.qubits);
+                }
+                _ => {}
+            }
+        }
+
+        // A qubit affects a measurement if it takes part in any gate with one which does.
+        let gates: Vec<(usize, &Gate)> = self
+            .remaining()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::Gate(gate) => Some((index, gate)),
+                _ => None,
+            })
+            .collect();
+        loop {
+            let before = measured.len();
+            for (_, gate) in &gates {
+                if gate.qubits.iter().any(|qubit| measured.contains(qubit)) {
+                    measured.extend(&gate.qubits);
+                }
+            }
+            if measured.len() == before {
+                break;
+            }
+        }
+
+        for (index, gate) in gates {
+            let unmeasured = gate
+                .qubits
+                .iter()
+                .all(|qubit| matches!(qubit, Qubit::Fixed(_)) && !measured.contains(qubit));
+            if unmeasured {
+                self.removed.insert(index, RemovalReason::UnmeasuredQubits);
+            }
+        }
+    }
+}
+
+impl Program {
+    /// Remove the instructions of the program's body which have no effect on its outcome, as
+    /// chosen by `options`, and report which were removed and why.
+    ///
+    /// Unreachable instructions are removed first, then those which do nothing, then gates on
+    /// unmeasured qubits, and finally pairs of gates which cancel one another, so that pairs
+    /// which only become adjacent once the instructions between them are removed are also
+    /// cancelled. The source text and comments of the remaining instructions are kept.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::{DeadCodeOptions, RemovalReason};
+    /// use quil_rs::Program;
+    ///
+    /// let mut program: Program = "DECLARE ro BIT
+    /// H 0
+    /// X 1
+    /// NOP
+    /// X 1
+    /// MEASURE 0 ro
+    /// HALT
+    /// H 0"
+    ///     .parse()
+    ///     .unwrap();
+    /// let report = program.eliminate_dead_code(&DeadCodeOptions::default());
+    ///
+    /// assert_eq!(program.to_string(false), "H 0\nMEASURE 0 ro[0]\nHALT\n");
+    /// assert_eq!(report.count(RemovalReason::InversePair), 2);
+    /// assert_eq!(report.count(RemovalReason::NoOp), 1);
+    /// assert_eq!(report.count(RemovalReason::Unreachable), 1);
+    /// ```
+    pub fn eliminate_dead_code(&mut self, options: &DeadCodeOptions) -> DeadCodeReport {
+        let mut eliminator = Eliminator {
+            program: self,
+            removed: BTreeMap::new(),
+        };
+        if options.unreachable_code {
+            eliminator.remove_unreachable();
+        }
+        if options.no_ops {
+            eliminator.remove_no_ops();
+        }
+        if options.unmeasured_qubits {
+            eliminator.remove_unmeasured();
+        }
+        if options.inverse_pairs {
+            eliminator.remove_inverse_pairs();
+        }
+
+        let removed = eliminator.removed;
+        let report = DeadCodeReport {
+            removed: removed
+                .iter()
+                .map(|(index, reason)| RemovedInstruction {
+                    index: *index,
+                    instruction: self.instructions[*index].clone(),
+                    reason: *reason,
+                })
+                .collect(),
+        };
+        let mut index = 0;
+        self.retain_instructions(|_| {
+            let keep = !removed.contains_key(&index);
+            index += 1;
+            keep
+        });
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{DeadCodeOptions, RemovalReason};
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0\nX 1\nX 1\nH 0", "")]
+    #[case("H 0\nX 1\nH 0", "X 1\n")]
+    #[case("S 0\nDAGGER S 0\nDAGGER T 0\nT 0", "")]
+    #[case("RX(0.5) 0\nRX(-0.5) 0", "")]
+    #[case("RX(pi/2) 0\nRX(-pi/2) 0", "")]
+    #[case("DECLARE theta REAL\nRX(theta) 0\nDAGGER RX(theta) 0", "")]
+    #[case("RX(0.5) 0\nRX(0.5) 0", "RX(0.5) 0\nRX(0.5) 0\n")]
+    #[case("CNOT 0 1\nCNOT 0 1", "")]
+    #[case("CNOT 0 1\nCNOT 1 0", "CNOT 0 1\nCNOT 1 0\n")]
+    #[case("CNOT 0 1\nX 1\nCNOT 0 1", "CNOT 0 1\nX 1\nCNOT 0 1\n")]
+    #[case("X 0\nMEASURE 0\nX 0", "X 0\nMEASURE 0\nX 0\n")]
+    #[case("X 0\nMEASURE 1\nX 0", "MEASURE 1\n")]
+    #[case("X 0\nPRAGMA NO-OP\nX 0", "X 0\nPRAGMA NO-OP\nX 0\n")]
+    #[case(
+        "DECLARE theta REAL\nRX(theta) 0\nMOVE theta 1.0\nDAGGER RX(theta) 0",
+        "RX(theta[0]) 0\nMOVE theta[0] 1.0\nDAGGER RX(theta[0]) 0\n"
+    )]
+    #[case(
+        "DECLARE theta REAL\nRX(theta) 0\nRX(theta) 1\nDAGGER RX(theta) 0",
+        "RX(theta[0]) 1\n"
+    )]
+    #[case(
+        "DEFGATE H:\n    1, i\n    0, 1\n\nH 0\nH 0",
+        "DEFGATE H AS MATRIX:\n\t1,1i\n\t0,1\n\nH 0\nH 0\n"
+    )]
+    #[case(
+        "DEFCIRCUIT X q:\n    RX(pi/2) q\n\nX 0\nX 0",
+        "DEFCIRCUIT X q:\n\tRX((pi/2)) q\n\nX 0\nX 0\n"
+    )]
+    fn test_inverse_pairs(#[case] input: &str, #[case] expected: &str) {
+        let mut program = Program::from_str(input).unwrap();
+        program.eliminate_dead_code(&DeadCodeOptions::default());
+        assert_eq!(program.to_string(false), expected);
+    }
+
+    #[rstest]
+    #[case("NOP\nH 0\nNOP", "H 0\n")]
+    #[case(
+        "DECLARE a REAL[2]\nMOVE a[0] a[0]\nMOVE a[0] a[1]",
+        "MOVE a[0] a[1]\n"
+    )]
+    #[case(
+        "DECLARE a REAL\nADD a 0\nSUB a 0.0\nMUL a 1\nDIV a 1.0\nADD a 1\nMUL a 0",
+        "ADD a[0] 1\nMUL a[0] 0\n"
+    )]
+    fn test_no_ops(#[case] input: &str, #[case] expected: &str) {
+        let mut program = Program::from_str(input).unwrap();
+        program.eliminate_dead_code(&DeadCodeOptions::default());
+        assert_eq!(program.to_string(false), expected);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let input = "LABEL @start
+H 0
+JUMP @end
+X 0
+LABEL @dead
+Y 0
+JUMP @dead
+LABEL @end
+Z 0
+";
+        let mut program = Program::from_str(input).unwrap();
+        let report = program.eliminate_dead_code(&DeadCodeOptions::default());
+        assert_eq!(
+            program.to_string(false),
+            "LABEL @start\nH 0\nJUMP @end\nLABEL @end\nZ 0\n"
+        );
+        assert_eq!(report.count(RemovalReason::Unreachable), 4);
+        assert_eq!(
+            report.to_string(),
+            "3: X 0 (unreachable)\n4: LABEL @dead (unreachable)\n5: Y 0 (unreachable)\n6: JUMP @dead (unreachable)\n"
+        );
+    }
+
+    #[test]
+    fn test_undefined_label_keeps_code() {
+        let input = "JUMP @missing\nX 0\n";
+        let mut program = Program::from_str(input).unwrap();
+        assert!(program
+            .eliminate_dead_code(&DeadCodeOptions::default())
+            .is_empty());
+        assert_eq!(program.to_string(false), input);
+    }
+
+    #[rstest]
+    #[case("H 0\nH 1\nMEASURE 0", "H 0\nMEASURE 0\n")]
+    #[case(
+        "H 2\nCNOT 2 1\nCNOT 1 0\nH 3\nMEASURE 0",
+        "H 2\nCNOT 2 1\nCNOT 1 0\nMEASURE 0\n"
+    )]
+    #[case("H 0\nH 1", "")]
+    #[case(
+        "DEFFRAME 1 \"ro_rx\":\n    SAMPLE-RATE: 1.0\nDECLARE iq REAL[2]\nH 0\nH 1\nCAPTURE 1 \"ro_rx\" flat(duration: 1e-6, iq: 1.0) iq",
+        "H 1\nCAPTURE 1 \"ro_rx\" flat(duration: 1e-6, iq: 1) iq[0]\n"
+    )]
+    fn test_unmeasured_qubits(#[case] input: &str, #[case] expected: &str) {
+        let mut program = Program::from_str(input).unwrap();
+        let options = DeadCodeOptions {
+            unmeasured_qubits: true,
+            ..Default::default()
+        };
+        program.eliminate_dead_code(&options);
+        assert_eq!(program.to_string(false), expected);
+    }
+
+    #[test]
+    fn test_unmeasured_qubits_off_by_default() {
+        let mut program = Program::from_str("H 0\nH 1\nMEASURE 0").unwrap();
+        assert!(program
+            .eliminate_dead_code(&DeadCodeOptions::default())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_keeps_source_text() {
+        let mut program = Program::parse_with_source_text("NOP\nH  0 # kept\nX 1\nX 1").unwrap();
+        program.eliminate_dead_code(&DeadCodeOptions::default());
+        assert_eq!(program.get_source_text(0), Some("H  0"));
+        assert_eq!(program.to_string_with_comments(false), "H 0 # kept\n");
+    }
+}
@@ -0,0 +1,242 @@
+//! A compact, stable rendering of the structure of a [`Program`] for logs and test failures.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+
+use crate::instruction::{Declaration, Instruction, WaveformDefinition};
+use crate::Program;
+
+/// A node of the rendered tree: one line of text and the nodes nested beneath it.
+struct Node {
+    label: String,
+    children: Vec<Node>,
+}
+
+impl Node {
+    /// Write the children of this node, each prefixed by `indent` and a branch glyph.
+    fn write_children(&self, output: &mut String, indent: &str) {
+        for (i, child) in self.children.iter().enumerate() {
+            let last = i == self.children.len() - 1;
+            let (branch, continuation) = if last {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            // Writing to a String cannot fail.
+            let _ = writeln!(output, "{indent}{branch}{}", child.label);
+            child.write_children(output, &format!("{indent}{continuation}"));
+        }
+    }
+}
+
+/// The name of the kind of `instruction`, as used in the debug tree.
+fn instruction_kind(instruction: &Instruction) -> &'static str {
+    match instruction {
+        Instruction::Arithmetic(_) => "Arithmetic",
+        Instruction::BinaryLogic(_) => "BinaryLogic",
+        Instruction::CalibrationDefinition(_) => "CalibrationDefinition",
+        Instruction::Call(_) => "Call",
+        Instruction::Capture(_) => "Capture",
+        Instruction::CircuitDefinition(_) => "CircuitDefinition",
+        Instruction::Convert(_) => "Convert",
+        Instruction::Comparison(_) => "Comparison",
+        Instruction::Declaration(_) => "Declaration",
+        Instruction::Delay(_) => "Delay",
+        Instruction::Exchange(_) => "Exchange",
+        Instruction::Fence(_) => "Fence",
+        Instruction::FrameDefinition(_) => "FrameDefinition",
+        Instruction::Gate(_) => "Gate",
+        Instruction::GateDefinition(_) => "GateDefinition",
+        Instruction::Halt => "Halt",
+        Instruction::Include(_) => "Include",
+        Instruction::Jump(_) => "Jump",
+        Instruction::JumpUnless(_) => "JumpUnless",
+        Instruction::JumpWhen(_) => "JumpWhen",
+        Instruction::Label(_) => "Label",
+        Instruction::Load(_) => "Load",
+        Instruction::MeasureCalibrationDefinition(_) => "MeasureCalibrationDefinition",
+        Instruction::Measurement(_) => "Measurement",
+        Instruction::Move(_) => "Move",
+        Instruction::Nop => "Nop",
+        Instruction::Pragma(_) => "Pragma",
+        Instruction::Pulse(_) => "Pulse",
+        Instruction::RawCapture(_) => "RawCapture",
+        Instruction::Reset(_) => "Reset",
+        Instruction::SetFrequency(_) => "SetFrequency",
+        Instruction::SetPhase(_) => "SetPhase",
+        Instruction::SetScale(_) => "SetScale",
+        Instruction::ShiftFrequency(_) => "ShiftFrequency",
+        Instruction::ShiftPhase(_) => "ShiftPhase",
+        Instruction::Store(_) => "Store",
+        Instruction::SwapPhases(_) => "SwapPhases",
+        Instruction::UnaryLogic(_) => "UnaryLogic",
+        Instruction::Wait => "Wait",
+        Instruction::WaveformDefinition(_) => "WaveformDefinition",
+    }
+}
+
+/// Build the node for the instruction at `index`. Only the first line of a multi-line instruction
+/// is shown; the instructions in the body of a definition become its children. If the text the
+/// instruction was parsed from is known, its first line is shown after the instruction.
+fn instruction_node(index: usize, instruction: &Instruction, source_text: Option<&str>) -> Node {
+    let rendered = instruction.to_string();
+    let summary = rendered.lines().next().unwrap_or_default();
+    let mut label = format!("[{index}] {}: {summary}", instruction_kind(instruction));
+    if let Some(source_text) = source_text.and_then(|text| text.lines().next()) {
+        let _ = write!(label, " @ {source_text:?}");
+    }
+
+    let body = match instruction {
+        Instruction::CalibrationDefinition(calibration) => calibration.instructions.as_slice(),
+        Instruction::CircuitDefinition(definition) => definition.instructions.as_slice(),
+        Instruction::MeasureCalibrationDefinition(calibration) => {
+            calibration.instructions.as_slice()
+        }
+        _ => &[],
+    };
+
+    Node {
+        label,
+        children: body
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| instruction_node(index, instruction, None))
+            .collect(),
+    }
+}
+
+/// Build the node for one section of the program, holding `instructions`.
+fn section_node(name: &str, instructions: &[Instruction]) -> Node {
+    Node {
+        label: format!("{name} ({})", instructions.len()),
+        children: instructions
+            .iter()
+            .enumerate()
+            .map(|(index, instruction)| instruction_node(index, instruction, None))
+            .collect(),
+    }
+}
+
+impl Program {
+    /// Render the structure of the program as an indented tree, one line per instruction, with
+    /// the index of each instruction within its section and, for programs parsed with
+    /// [`Program::parse_with_source_text`], the text each instruction was parsed from.
+    ///
+    /// Unlike the derived [`Debug`] output, the rendering is compact enough to read at a glance
+    /// and is stable from run to run, so it is suitable for snapshot tests and logs.
+    pub fn to_debug_tree(&self) -> String {
+        let memory_regions: Vec<_> = self
+            .memory_regions
+            .iter()
+            .map(|(name, region)| {
+                Instruction::Declaration(Declaration {
+                    name: name.clone(),
+                    size: region.size.clone(),
+                    sharing: region.sharing.clone(),
+                })
+            })
+            .collect();
+
+        // Frames are stored without an order, so sort them for a stable rendering.
+        let mut frames = self.frames.to_instructions();
+        frames.sort_by_cached_key(ToString::to_string);
+
+        let waveforms: Vec<_> = self
+            .waveforms
+            .iter()
+            .map(|(name, definition)| {
+                Instruction::WaveformDefinition(WaveformDefinition {
+                    name: name.clone(),
+                    definition: definition.clone(),
+                })
+            })
+            .collect();
+
+        let instructions = Node {
+            label: format!("instructions ({})", self.instructions.len()),
+            children: self
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(index, instruction)| {
+                    instruction_node(index, instruction, self.get_source_text(index))
+                })
+                .collect(),
+        };
+
+        let root = Node {
+            label: "Program".to_string(),
+            children: vec![
+                section_node("memory_regions", &memory_regions),
+                section_node("frames", &frames),
+                section_node("waveforms", &waveforms),
+                section_node("calibrations", &self.calibrations.to_instructions()),
+                instructions,
+            ],
+        };
+
+        let mut output = format!("{}\n", root.label);
+        root.write_children(&mut output, "");
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::Program;
+
+    const INPUT: &str = r#"DECLARE ro BIT[2]
+DEFFRAME 0 "rf":
+    SAMPLE-RATE: 1.0
+DEFWAVEFORM wf:
+    1.0, 1.0
+DEFCAL RX(pi/2) 0:
+    PULSE 0 "rf" wf
+DEFCIRCUIT BELL a b:
+    H a
+    CNOT a b
+BELL 0 1
+MEASURE   0 ro[0]
+"#;
+
+    #[test]
+    fn test_debug_tree() {
+        let program = Program::from_str(INPUT).unwrap();
+        insta::assert_snapshot!(program.to_debug_tree());
+    }
+
+    #[test]
+    fn test_debug_tree_with_source_text() {
+        let program = Program::parse_with_source_text(INPUT).unwrap();
+        insta::assert_snapshot!(program.to_debug_tree());
+    }
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(
+            Program::new().to_debug_tree(),
+            "Program
+├── memory_regions (0)
+├── frames (0)
+├── waveforms (0)
+├── calibrations (0)
+└── instructions (0)
+"
+        );
+    }
+}
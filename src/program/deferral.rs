@@ -0,0 +1,365 @@
+//! Defer a program's measurements to its end, for simulators which only measure once the
+//! circuit is done.
+//!
+//! By the principle of deferred measurement, a measurement commutes with whatever follows it
+//! which neither acts on the qubit it measures nor uses its result. A qubit which is acted on
+//! again is first copied, with `CNOT`, to a fresh ancilla which is measured in its place, and
+//! gates run only when a result is 1 (or 0) become gates controlled on the qubit which holds it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use thiserror::Error;
+
+use crate::instruction::{
+    Gate, GateModifier, Instruction, JumpUnless, JumpWhen, Measurement, Qubit, Reset,
+};
+use crate::Program;
+
+use super::feedback::{memory_effects, MemoryLocation};
+
+/// The reasons a program's measurements cannot be deferred, found by
+/// [`Program::defer_measurements`]. Each gives the index of the instruction in
+/// [`Program::instructions`].
+#[derive(Debug, Error)]
+pub enum DeferralError {
+    #[error(
+        "In instruction {index} ({instruction}): only a jump forward over gates, on the result of a measurement, can be deferred."
+    )]
+    UnsupportedControlFlow {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+
+    #[error(
+        "In instruction {index} ({instruction}): the result of a measurement is used before the end of the program."
+    )]
+    ResultUsed {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+
+    #[error("In instruction {index} ({instruction}): this instruction cannot be deferred past.")]
+    UnsupportedInstruction {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+}
+
+impl DeferralError {
+    /// The index in [`Program::instructions`] of the instruction which prevents deferral.
+    pub fn index(&self) -> usize {
+        match self {
+            DeferralError::UnsupportedControlFlow { index, .. }
+            | DeferralError::ResultUsed { index, .. }
+            | DeferralError::UnsupportedInstruction { index, .. } => *index,
+        }
+    }
+}
+
+/// Whether an instruction acts on `qubit`. A `RESET` of every qubit acts on all of them.
+fn acts_on(instruction: &Instruction, qubit: &Qubit) -> bool {
+    matches!(instruction, Instruction::Reset(Reset { qubit: None }))
+        || qubits(instruction).contains(qubit)
+}
+
+/// The qubits an instruction names.
+fn qubits(instruction: &Instruction) -> &[Qubit] {
+    match instruction {
+        Instruction::Gate(gate) => &gate.qubits,
+        Instruction::Measurement(measurement) => std::slice::from_ref(&measurement.qubit),
+        Instruction::Reset(Reset { qubit: Some(qubit) }) => std::slice::from_ref(qubit),
+        Instruction::Delay(delay) => &delay.qubits,
+        Instruction::Fence(fence) => &fence.qubits,
+        Instruction::Pulse(pulse) => &pulse.frame.qubits,
+        _ => &[],
+    }
+}
+
+fn gate(name: &str, qubits: Vec<Qubit>) -> Instruction {
+    Instruction::Gate(Gate {
+        name: name.into(),
+        parameters: vec![],
+        qubits,
+        modifiers: vec![],
+    })
+}
+
+impl Program {
+    /// Move every measurement of the program to its end, so that it may run on a simulator which
+    /// only measures once the circuit is done.
+    ///
+    /// A measurement is moved unchanged if nothing after it acts on its qubit. Otherwise its qubit
+    /// is copied, with `CNOT`, onto an ancilla, which is measured at the end in its place; the
+    /// ancillas are the qubits numbered from one past the highest the program uses. A jump over a
+    /// run of gates, as in
+    ///
+    /// ```text
+    /// JUMP-UNLESS @skip ro[0]
+    /// X 0
+    /// LABEL @skip
+    /// ```
+    ///
+    /// on the result of a measurement becomes those gates, `CONTROLLED` on the qubit which holds
+    /// the result (and conjugated by `X` for `JUMP-WHEN`). The measurements are made at the end
+    /// in the order they were made, so that where two write the same memory, the later still
+    /// does so last.
+    ///
+    /// Measurements cannot be deferred past any other use of their results, past other control
+    /// flow, past `CAPTURE` or `RAW-CAPTURE`, or past a `RESET` of every qubit, which would also
+    /// reset the ancillas; nor can a measurement of a qubit variable. Each is reported as a
+    /// [`DeferralError`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = r#"DECLARE ro BIT[2]
+    /// H 0
+    /// MEASURE 0 ro[0]
+    /// JUMP-UNLESS @skip ro[0]
+    /// X 0
+    /// LABEL @skip
+    /// MEASURE 0 ro[1]
+    /// "#
+    /// .parse()
+    /// .unwrap();
+    /// let deferred = program.defer_measurements().unwrap();
+    /// assert_eq!(
+    ///     deferred.to_string(false),
+    ///     "H 0\nCNOT 0 1\nCONTROLLED X 1 0\nMEASURE 1 ro[0]\nMEASURE 0 ro[1]\n"
+    /// );
+    /// ```
+    pub fn defer_measurements(&self) -> Result<Program, DeferralError> {
+        let mut next_ancilla = self
+            .get_used_qubits()
+            .iter()
+            .filter_map(|qubit| match qubit {
+                Qubit::Fixed(index) => Some(index + 1),
+                Qubit::Variable(_) => None,
+            })
+            .max()
+            .unwrap_or_default();
+        let mut body = vec![];
+        let mut measurements = vec![];
+        // The qubit which holds each measurement result, to be measured at the end.
+        let mut results: BTreeMap<MemoryLocation, Qubit> = BTreeMap::new();
+
+        let uses_result = |instruction: &Instruction, results: &BTreeMap<MemoryLocation, Qubit>| {
+            let effects = memory_effects(instruction);
+            effects
+                .reads
+                .iter()
+                .chain(&effects.writes)
+                .any(|location| results.keys().any(|result| result.overlaps(location)))
+        };
+
+        let mut index = 0;
+        while let Some(instruction) = self.instructions.get(index) {
+            match instruction {
+                Instruction::Measurement(Measurement { qubit, target }) => {
+                    if let Qubit::Variable(_) = qubit {
+                        return Err(DeferralError::UnsupportedInstruction {
+                            index,
+                            instruction: Box::new(instruction.clone()),
+                        });
+                    }
+                    let acted_on_again = self.instructions[index + 1..]
+                        .iter()
+                        .any(|later| acts_on(later, qubit));
+                    let holder = if acted_on_again {
+                        let ancilla = Qubit::Fixed(next_ancilla);
+                        next_ancilla += 1;
+                        body.push(gate("CNOT", vec![qubit.clone(), ancilla.clone()]));
+                        ancilla
+                    } else {
+                        qubit.clone()
+                    };
+                    if let Some(target) = target {
+                        results.insert(MemoryLocation::element(target), holder.clone());
+                    }
+                    measurements.push(Instruction::Measurement(Measurement {
+                        qubit: holder,
+                        target: target.clone(),
+                    }));
+                }
+                Instruction::JumpWhen(JumpWhen { target, condition })
+                | Instruction::JumpUnless(JumpUnless { target, condition }) => {
+                    let unsupported = || DeferralError::UnsupportedControlFlow {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                    };
+                    let control = results
+                        .get(&MemoryLocation::element(condition))
+                        .cloned()
+                        .ok_or_else(unsupported)?;
+                    let end = self.instructions[index + 1..]
+                        .iter()
+                        .position(
+                            |later| matches!(later, Instruction::Label(label) if label == target),
+                        )
+                        .map(|offset| index + 1 + offset)
+                        .ok_or_else(unsupported)?;
+                    // `JUMP-WHEN` skips the gates when the result is 1, so they are controlled
+                    // on its being 0.
+                    let on_zero = matches!(instruction, Instruction::JumpWhen(_));
+                    if on_zero {
+                        body.push(gate("X", vec![control.clone()]));
+                    }
+                    for (offset, skipped) in self.instructions[index + 1..end].iter().enumerate() {
+                        let Instruction::Gate(skipped_gate) = skipped else {
+                            return Err(unsupported());
+                        };
+                        if uses_result(skipped, &results) {
+                            return Err(DeferralError::ResultUsed {
+                                index: index + 1 + offset,
+                                instruction: Box::new(skipped.clone()),
+                            });
+                        }
+                        let mut controlled = skipped_gate.clone();
+                        controlled.modifiers.insert(0, GateModifier::Controlled);
+                        controlled.qubits.insert(0, control.clone());
+                        body.push(Instruction::Gate(controlled));
+                    }
+                    if on_zero {
+                        body.push(gate("X", vec![control]));
+                    }
+                    index = end + 1;
+                    continue;
+                }
+                Instruction::Halt if index + 1 == self.instructions.len() => break,
+                Instruction::Jump(_) | Instruction::Halt => {
+                    return Err(DeferralError::UnsupportedControlFlow {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                    })
+                }
+                Instruction::Capture(_) | Instruction::RawCapture(_) => {
+                    return Err(DeferralError::UnsupportedInstruction {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                    })
+                }
+                Instruction::Reset(Reset { qubit: None }) if !measurements.is_empty() => {
+                    return Err(DeferralError::UnsupportedInstruction {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                    })
+                }
+                other => {
+                    if uses_result(other, &results) {
+                        return Err(DeferralError::ResultUsed {
+                            index,
+                            instruction: Box::new(other.clone()),
+                        });
+                    }
+                    body.push(other.clone());
+                }
+            }
+            index += 1;
+        }
+        body.extend(measurements);
+        if let Some(Instruction::Halt) = self.instructions.last() {
+            body.push(Instruction::Halt);
+        }
+
+        let mut program = self.clone();
+        program.clear_source();
+        program.instructions.clear();
+        for instruction in body {
+            program.add_instruction(instruction);
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::DeferralError;
+    use crate::Program;
+
+    #[rstest]
+    #[case(
+        "DECLARE ro BIT[2]\nMEASURE 0 ro[0]\nH 1\nMEASURE 1 ro[1]",
+        "H 1\nMEASURE 0 ro[0]\nMEASURE 1 ro[1]\n"
+    )]
+    #[case(
+        "DECLARE ro BIT[2]\nH 0\nMEASURE 0 ro[0]\nH 0\nMEASURE 0 ro[1]",
+        "H 0\nCNOT 0 1\nH 0\nMEASURE 1 ro[0]\nMEASURE 0 ro[1]\n"
+    )]
+    #[case(
+        "DECLARE ro BIT\nH 0\nMEASURE 0 ro\nJUMP-UNLESS @skip ro\nX 0\nLABEL @skip\nH 1",
+        "H 0\nCNOT 0 2\nCONTROLLED X 2 0\nH 1\nMEASURE 2 ro[0]\n"
+    )]
+    #[case(
+        "DECLARE ro BIT\nMEASURE 0 ro\nJUMP-WHEN @skip ro\nX 1\nRX(0.5) 1\nLABEL @skip\nHALT",
+        "X 0\nCONTROLLED X 0 1\nCONTROLLED RX(0.5) 0 1\nX 0\nMEASURE 0 ro[0]\nHALT\n"
+    )]
+    #[case("RESET\nMEASURE 0\nX 0", "RESET\nCNOT 0 1\nX 0\nMEASURE 1\n")]
+    fn test_defer_measurements(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let deferred = program.defer_measurements().unwrap();
+        assert_eq!(deferred.to_string(false), expected);
+        assert_eq!(deferred.memory_regions, program.memory_regions);
+    }
+
+    #[rstest]
+    #[case(
+        "DECLARE ro BIT\nDECLARE x BIT\nMEASURE 0 ro\nMOVE x ro",
+        "In instruction 1 (MOVE x[0] ro[0]): the result of a measurement is used before the end of the program."
+    )]
+    #[case(
+        "DECLARE ro BIT\nLABEL @loop\nMEASURE 0 ro\nJUMP-WHEN @loop ro",
+        "In instruction 2 (JUMP-WHEN @loop ro[0]): only a jump forward over gates, on the result of a measurement, can be deferred."
+    )]
+    #[case(
+        "DECLARE ro BIT[2]\nMEASURE 0 ro[0]\nJUMP-WHEN @end ro[0]\nMEASURE 1 ro[1]\nLABEL @end",
+        "In instruction 1 (JUMP-WHEN @end ro[0]): only a jump forward over gates, on the result of a measurement, can be deferred."
+    )]
+    #[case(
+        "DECLARE flag BIT\nMEASURE 0\nJUMP-WHEN @end flag\nX 0\nLABEL @end",
+        "In instruction 1 (JUMP-WHEN @end flag[0]): only a jump forward over gates, on the result of a measurement, can be deferred."
+    )]
+    #[case(
+        "MEASURE 0\nX 0\nRESET",
+        "In instruction 2 (RESET): this instruction cannot be deferred past."
+    )]
+    #[case(
+        "DECLARE ro BIT\nX 0\nMEASURE 0 ro\nRESET",
+        "In instruction 2 (RESET): this instruction cannot be deferred past."
+    )]
+    fn test_defer_measurements_error(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let error = program.defer_measurements().unwrap_err();
+        assert_eq!(error.to_string(), expected);
+    }
+
+    #[test]
+    fn test_defer_measurements_variable_qubit() {
+        let program = Program::from_str("DECLARE ro BIT\nMEASURE q ro").unwrap();
+        let error = program.defer_measurements().unwrap_err();
+        assert!(matches!(
+            error,
+            DeferralError::UnsupportedInstruction { index: 0, .. }
+        ));
+        assert_eq!(error.index(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+//! Simplify the `DELAY` instructions of Quil-T programs before scheduling.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::expression::{Expression, InfixOperator, MemoryValues};
+use crate::instruction::{Delay, Instruction};
+use crate::{real, Program};
+
+/// Whether `a` and `b` delay the same qubits and frames, regardless of the order they are listed in.
+fn delays_same_targets(a: &Delay, b: &Delay) -> bool {
+    a.qubits.iter().collect::<BTreeSet<_>>() == b.qubits.iter().collect::<BTreeSet<_>>()
+        && a.frame_names.iter().collect::<BTreeSet<_>>()
+            == b.frame_names.iter().collect::<BTreeSet<_>>()
+}
+
+/// Whether `duration` is known to be exactly zero.
+fn is_zero(duration: &Expression) -> bool {
+    matches!(duration, Expression::Number(value) if *value == real!(0f64))
+}
+
+/// Reduce `duration` to a number if it can be evaluated using `memory_values`, and simplify it
+/// otherwise.
+fn fold_duration(duration: Expression, memory_values: &MemoryValues) -> Expression {
+    match duration.evaluate(&HashMap::new(), memory_values) {
+        Ok(value) => Expression::Number(value),
+        Err(_) => duration.into_simplified(),
+    }
+}
+
+impl Program {
+    /// Return a copy of the program with its `DELAY` instructions simplified:
+    ///
+    /// - consecutive `DELAY`s on the same qubits and frames are merged into one, with the sum of
+    ///   their durations;
+    /// - durations which reference memory are evaluated using `memory_values`, a snapshot of the
+    ///   values of memory regions keyed on region name, where every value they need is present;
+    /// - `DELAY`s whose duration is zero are removed.
+    ///
+    /// Durations which cannot be evaluated are kept in symbolic form, so the timing of the
+    /// program is unchanged. Pass an empty snapshot to fold only constant durations.
+    pub fn fold_delays(&self, memory_values: &MemoryValues) -> Self {
+        let mut folded: Vec<Instruction> = Vec::with_capacity(self.instructions.len());
+
+        for instruction in &self.instructions {
+            let mut delay = match instruction {
+                Instruction::Delay(delay) => delay.clone(),
+                other => {
+                    folded.push(other.clone());
+                    continue;
+                }
+            };
+            delay.duration = fold_duration(delay.duration, memory_values);
+            if is_zero(&delay.duration) {
+                continue;
+            }
+
+            match folded.last_mut() {
+                Some(Instruction::Delay(previous)) if delays_same_targets(previous, &delay) => {
+                    let sum = Expression::Infix {
+                        left: Box::new(previous.duration.clone()),
+                        operator: InfixOperator::Plus,
+                        right: Box::new(delay.duration),
+                    };
+                    previous.duration = fold_duration(sum, memory_values);
+                    if is_zero(&previous.duration) {
+                        folded.pop();
+                    }
+                }
+                _ => folded.push(Instruction::Delay(delay)),
+            }
+        }
+
+        let mut new_program = self.clone();
+        new_program.instructions = folded;
+        new_program.clear_source();
+        new_program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::Program;
+
+    #[rstest]
+    #[case("DELAY 0 1e-6\nDELAY 0 2e-6", "DELAY 0 3e-6\n")]
+    #[case(
+        "DELAY 0 \"rf\" \"ff\" 1e-6\nDELAY 0 \"ff\" \"rf\" 1e-6",
+        "DELAY 0 \"rf\" \"ff\" 2e-6\n"
+    )]
+    #[case("DELAY 0 1e-6\nDELAY 1 1e-6", "DELAY 0 1e-6\nDELAY 1 1e-6\n")]
+    #[case(
+        "DELAY 0 \"rf\" 1e-6\nDELAY 0 1e-6",
+        "DELAY 0 \"rf\" 1e-6\nDELAY 0 1e-6\n"
+    )]
+    #[case("DELAY 0 1e-6\nH 0\nDELAY 0 1e-6", "DELAY 0 1e-6\nH 0\nDELAY 0 1e-6\n")]
+    #[case("DELAY 0 0.0\nH 0\nDELAY 0 (1e-6 - 1e-6)", "H 0\n")]
+    #[case("DELAY 0 1e-6\nDELAY 0 0.0\nDELAY 0 1e-6", "DELAY 0 2e-6\n")]
+    #[case("DELAY 0 (%t)\nDELAY 0 1e-6", "DELAY 0 (%t+1e-6)\n")]
+    #[case("DELAY 0 (t[1])", "DELAY 0 t[1]\n")]
+    fn test_fold_delays(#[case] input: &str, #[case] expected: &str) {
+        // Durations are parenthesized or written as floats where they would otherwise be parsed
+        // as qubits.
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(
+            program.fold_delays(&HashMap::new()).to_string(false),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_fold_delays_with_memory() {
+        let program =
+            Program::from_str("DECLARE t REAL[2]\nDELAY 0 (t[0])\nDELAY 0 (t[1])\nDELAY 1 (t[0])")
+                .unwrap();
+        let memory_values = HashMap::from([("t", vec![0.0, 4e-6])]);
+        assert_eq!(
+            program.fold_delays(&memory_values).to_string(false),
+            "DELAY 0 4e-6\n"
+        );
+    }
+}
@@ -0,0 +1,436 @@
+//! The differences between two programs, instruction by instruction.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::mem::discriminant;
+
+use indexmap::IndexMap;
+
+use crate::instruction::{
+    Calibration, CircuitDefinition, Declaration, FrameDefinition, GateDefinition, Instruction,
+    MeasureCalibrationDefinition, WaveformDefinition,
+};
+use crate::Program;
+
+/// A difference between the headers of two programs: their declarations of memory, definitions
+/// of gates, circuits, frames, and waveforms, and calibrations.
+///
+/// Headers are matched by what they define, so that, for instance, a region declared with a new
+/// size is reported as modified rather than as one region removed and another added.
+#[derive(Clone, Debug, PartialEq)]
+pub enum HeaderChange {
+    /// A header only the new program has.
+    Added(Instruction),
+    /// A header only the old program has.
+    Removed(Instruction),
+    /// A header both programs have, for the same thing, but which differs between them.
+    Modified { old: Instruction, new: Instruction },
+}
+
+/// A difference between the instructions of the bodies of two programs.
+///
+/// Indices are those of the instructions in [`Program::instructions`] of the program they belong
+/// to: the old program for those deleted, and the new one for those inserted.
+#[derive(Clone, Debug, PartialEq)]
+pub enum InstructionChange {
+    /// An instruction only the new program has.
+    Inserted {
+        index: usize,
+        instruction: Instruction,
+    },
+    /// An instruction only the old program has.
+    Deleted {
+        index: usize,
+        instruction: Instruction,
+    },
+    /// An instruction of the old program replaced by one of the same kind in the new program,
+    /// such as a gate applied with different parameters or to different qubits.
+    Modified {
+        old_index: usize,
+        new_index: usize,
+        old: Instruction,
+        new: Instruction,
+    },
+}
+
+/// The differences between two programs, as returned by [`Program::diff`].
+///
+/// When written, each removed or deleted instruction is prefixed with `-`, and each added or
+/// inserted one with `+`, with both the old and new instruction written for those modified.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgramDiff {
+    /// The differences between the headers of the programs, those of the old program first, in
+    /// the order they are written.
+    pub headers: Vec<HeaderChange>,
+    /// The differences between the bodies of the programs, in the order of their instructions.
+    pub instructions: Vec<InstructionChange>,
+}
+
+impl ProgramDiff {
+    /// Whether the programs have the same headers and instructions.
+    pub fn is_empty(&self) -> bool {
+        self.headers.is_empty() && self.instructions.is_empty()
+    }
+}
+
+/// Write each line of `instruction`, prefixed with `prefix`.
+fn write_prefixed(f: &mut fmt::Formatter, prefix: char, instruction: &Instruction) -> fmt::Result {
+    for line in instruction.to_string().lines() {
+        writeln!(f, "{prefix} {line}")?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for ProgramDiff {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for change in &self.headers {
+            match change {
+                HeaderChange::Added(new) => write_prefixed(f, '+', new)?,
+                HeaderChange::Removed(old) => write_prefixed(f, '-', old)?,
+                HeaderChange::Modified { old, new } => {
+                    write_prefixed(f, '-', old)?;
+                    write_prefixed(f, '+', new)?;
+                }
+            }
+        }
+        for change in &self.instructions {
+            match change {
+                InstructionChange::Inserted { instruction, .. } => {
+                    write_prefixed(f, '+', instruction)?
+                }
+                InstructionChange::Deleted { instruction, .. } => {
+                    write_prefixed(f, '-', instruction)?
+                }
+                InstructionChange::Modified { old, new, .. } => {
+                    write_prefixed(f, '-', old)?;
+                    write_prefixed(f, '+', new)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// What a header defines, by which the headers of two programs are matched, or `None` if
+/// `instruction` is not a header.
+fn header_key(instruction: &Instruction) -> Option<String> {
+    match instruction {
+        Instruction::Declaration(Declaration { name, .. }) => Some(format!("DECLARE {name}")),
+        Instruction::GateDefinition(GateDefinition { name, .. }) => Some(format!("DEFGATE {name}")),
+        Instruction::CircuitDefinition(CircuitDefinition { name, .. }) => {
+            Some(format!("DEFCIRCUIT {name}"))
+        }
+        Instruction::CalibrationDefinition(Calibration {
+            modifiers,
+            name,
+            parameters,
+            qubits,
+            ..
+        }) => Some(format!(
+            "DEFCAL {modifiers:?} {name} {parameters:?} {qubits:?}"
+        )),
+        Instruction::MeasureCalibrationDefinition(MeasureCalibrationDefinition {
+            qubit,
+            parameter,
+            ..
+        }) => Some(format!("DEFCAL MEASURE {qubit:?} {}", parameter.is_some())),
+        Instruction::FrameDefinition(FrameDefinition { identifier, .. }) => {
+            Some(format!("DEFFRAME {identifier:?}"))
+        }
+        Instruction::WaveformDefinition(WaveformDefinition { name, .. }) => {
+            Some(format!("DEFWAVEFORM {name}"))
+        }
+        _ => None,
+    }
+}
+
+/// The headers of `program`, keyed on what they define, including the definitions of gates and
+/// circuits among its instructions.
+fn headers(program: &Program) -> IndexMap<String, Instruction> {
+    program
+        .to_instructions(true)
+        .into_iter()
+        .filter_map(|instruction| header_key(&instruction).map(|key| (key, instruction)))
+        .collect()
+}
+
+fn diff_headers(old: &Program, new: &Program) -> Vec<HeaderChange> {
+    let mut new_headers = headers(new);
+    let mut changes = Vec::new();
+    for (key, old) in headers(old) {
+        match new_headers.shift_remove(&key) {
+            None => changes.push(HeaderChange::Removed(old)),
+            Some(new) if new != old => changes.push(HeaderChange::Modified { old, new }),
+            Some(_) => {}
+        }
+    }
+    changes.extend(new_headers.into_values().map(HeaderChange::Added));
+    changes
+}
+
+/// One step in turning one sequence of instructions into another.
+enum Edit {
+    Keep,
+    Delete(usize),
+    Insert(usize),
+}
+
+/// The shortest sequence of deletions from `old` and insertions from `new` which turns `old`
+/// into `new`, found from their longest common subsequence.
+fn edits(old: &[(usize, &Instruction)], new: &[(usize, &Instruction)]) -> Vec<Edit> {
+    // Only the instructions between those the sequences start and end with in common need to be
+    // compared with each other.
+    let prefix = old
+        .iter()
+        .zip(new)
+        .take_while(|((_, old), (_, new))| old == new)
+        .count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|((_, old), (_, new))| old == new)
+        .count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+
+    // `lengths[i][j]` is the length of the longest common subsequence of `old_middle[i..]` and
+    // `new_middle[j..]`.
+    let mut lengths = vec![vec![0usize; new_middle.len() + 1]; old_middle.len() + 1];
+    for i in (0..old_middle.len()).rev() {
+        for j in (0..new_middle.len()).rev() {
+            lengths[i][j] = if old_middle[i].1 == new_middle[j].1 {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut edits: Vec<Edit> = (0..prefix).map(|_| Edit::Keep).collect();
+    let (mut i, mut j) = (0, 0);
+    while i < old_middle.len() || j < new_middle.len() {
+        if i < old_middle.len() && j < new_middle.len() && old_middle[i].1 == new_middle[j].1 {
+            edits.push(Edit::Keep);
+            i += 1;
+            j += 1;
+        } else if j == new_middle.len()
+            || (i < old_middle.len() && lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            edits.push(Edit::Delete(prefix + i));
+            i += 1;
+        } else {
+            edits.push(Edit::Insert(prefix + j));
+            j += 1;
+        }
+    }
+    edits.extend((0..suffix).map(|_| Edit::Keep));
+    edits
+}
+
+fn diff_instructions(old: &Program, new: &Program) -> Vec<InstructionChange> {
+    let body = |program: &Program| -> Vec<(usize, Instruction)> {
+        program
+            .instructions
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|(_, instruction)| header_key(instruction).is_none())
+            .collect()
+    };
+    let (old, new) = (body(old), body(new));
+    let old_refs: Vec<(usize, &Instruction)> = old.iter().map(|(i, ins)| (*i, ins)).collect();
+    let new_refs: Vec<(usize, &Instruction)> = new.iter().map(|(i, ins)| (*i, ins)).collect();
+
+    let mut changes = Vec::new();
+    let (mut deleted, mut inserted) = (Vec::new(), Vec::new());
+    let mut flush = |deleted: &mut Vec<usize>, inserted: &mut Vec<usize>| {
+        // Pair the instructions replaced between two kept ones with those replacing them, in
+        // order, as modifications where they are of the same kind.
+        for k in 0..deleted.len().max(inserted.len()) {
+            let old = deleted.get(k).map(|&i| &old[i]);
+            let new = inserted.get(k).map(|&j| &new[j]);
+            match (old, new) {
+                (Some((old_index, old)), Some((new_index, new)))
+                    if discriminant(old) == discriminant(new) =>
+                {
+                    changes.push(InstructionChange::Modified {
+                        old_index: *old_index,
+                        new_index: *new_index,
+                        old: old.clone(),
+                        new: new.clone(),
+                    });
+                }
+                (old, new) => {
+                    if let Some((index, instruction)) = old {
+                        changes.push(InstructionChange::Deleted {
+                            index: *index,
+                            instruction: instruction.clone(),
+                        });
+                    }
+                    if let Some((index, instruction)) = new {
+                        changes.push(InstructionChange::Inserted {
+                            index: *index,
+                            instruction: instruction.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        deleted.clear();
+        inserted.clear();
+    };
+    for edit in edits(&old_refs, &new_refs) {
+        match edit {
+            Edit::Keep => flush(&mut deleted, &mut inserted),
+            Edit::Delete(i) => deleted.push(i),
+            Edit::Insert(j) => inserted.push(j),
+        }
+    }
+    flush(&mut deleted, &mut inserted);
+    changes
+}
+
+impl Program {
+    /// The differences between this program and `other`, taking this program as the old one and
+    /// `other` as the new one.
+    ///
+    /// Headers are compared by what they define, whatever their order. Instructions of the body
+    /// are compared in order, finding the fewest insertions and deletions which turn one
+    /// program's into the other's; an instruction deleted and another of the same kind inserted
+    /// in its place are reported as a modification. The definitions of gates and circuits are
+    /// compared as headers. The source text of either program takes no part in the comparison.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::diff::InstructionChange;
+    /// use quil_rs::Program;
+    ///
+    /// let old: Program = "DECLARE ro BIT[1]\nH 0\nRX(pi) 0\nMEASURE 0 ro[0]".parse().unwrap();
+    /// let new: Program = "DECLARE ro BIT[2]\nH 0\nRY(pi) 0\nX 1\nMEASURE 0 ro[0]".parse().unwrap();
+    /// let diff = old.diff(&new);
+    ///
+    /// assert_eq!(diff.headers.len(), 1);
+    /// assert!(matches!(
+    ///     diff.instructions[..],
+    ///     [
+    ///         InstructionChange::Modified { old_index: 1, new_index: 1, .. },
+    ///         InstructionChange::Inserted { index: 2, .. },
+    ///     ]
+    /// ));
+    /// assert_eq!(
+    ///     diff.to_string(),
+    ///     "- DECLARE ro BIT[1]\n+ DECLARE ro BIT[2]\n- RX(pi) 0\n+ RY(pi) 0\n+ X 1\n"
+    /// );
+    /// ```
+    pub fn diff(&self, other: &Program) -> ProgramDiff {
+        ProgramDiff {
+            headers: diff_headers(self, other),
+            instructions: diff_instructions(self, other),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{HeaderChange, InstructionChange};
+    use crate::Program;
+
+    #[test]
+    fn test_identical_programs() {
+        let input = "DECLARE ro BIT\nDEFGATE G:\n    1, 0\n    0, 1\nG 0\nMEASURE 0 ro\n";
+        let program = Program::from_str(input).unwrap();
+        let diff = program.diff(&program.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[rstest]
+    #[case("H 0\nX 1\nY 2", "H 0\nY 2", "- X 1\n")]
+    #[case("H 0\nY 2", "H 0\nX 1\nY 2", "+ X 1\n")]
+    #[case("H 0\nX 1\nY 2", "H 0\nX 2\nY 2", "- X 1\n+ X 2\n")]
+    #[case("H 0\nX 1", "H 0\nMEASURE 1", "- X 1\n+ MEASURE 1\n")]
+    #[case("H 0\nX 1\nH 1", "X 1\nH 1\nH 0", "- H 0\n+ H 0\n")]
+    #[case("", "H 0", "+ H 0\n")]
+    fn test_diff_instructions(#[case] old: &str, #[case] new: &str, #[case] expected: &str) {
+        let old = Program::from_str(old).unwrap();
+        let new = Program::from_str(new).unwrap();
+        assert_eq!(old.diff(&new).to_string(), expected);
+    }
+
+    #[test]
+    fn test_indices() {
+        let old = Program::from_str("DEFGATE G:\n    1, 0\n    0, 1\nH 0\nX 1").unwrap();
+        let new = Program::from_str("H 0\nY 0\nX 1\nRX(pi) 1").unwrap();
+        let diff = old.diff(&new);
+        assert!(matches!(
+            diff.headers[..],
+            [HeaderChange::Removed(
+                crate::instruction::Instruction::GateDefinition(_)
+            )]
+        ));
+        assert!(matches!(
+            diff.instructions[..],
+            [
+                InstructionChange::Inserted { index: 1, .. },
+                InstructionChange::Inserted { index: 3, .. },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_diff_headers() {
+        let old = r#"DECLARE ro BIT[1]
+DECLARE theta REAL
+DEFCAL X 0:
+    PULSE 0 "rf" flat(duration: 1e-6, iq: 1.0)
+DEFCAL X 1:
+    NOP
+X 0
+"#;
+        let new = r#"DECLARE theta REAL
+DECLARE ro BIT[2]
+DEFCAL X 0:
+    PULSE 0 "rf" flat(duration: 2e-6, iq: 1.0)
+DEFCAL MEASURE 0 addr:
+    NOP
+X 0
+"#;
+        let diff = Program::from_str(old)
+            .unwrap()
+            .diff(&Program::from_str(new).unwrap());
+        assert!(diff.instructions.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            r#"- DECLARE ro BIT[1]
++ DECLARE ro BIT[2]
+- DEFCAL X 0:
+- 	PULSE 0 "rf" flat(duration: 1e-6, iq: 1)
++ DEFCAL X 0:
++ 	PULSE 0 "rf" flat(duration: 2e-6, iq: 1)
+- DEFCAL X 1:
+- 	NOP
++ DEFCAL MEASURE 0 addr:
++ 	NOP
+"#
+        );
+    }
+}
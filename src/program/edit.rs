@@ -0,0 +1,202 @@
+//! Editing the body of a program in place, keeping the text and comments it was parsed from
+//! attached to the instructions they were recorded for.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::instruction::Instruction;
+
+use super::{is_header, Program};
+
+impl Program {
+    /// Insert `instruction` into the body of the program at `index`, after which the instructions
+    /// of the body are moved up by one. A header, such as a `DECLARE` or `DEFCAL`, is instead
+    /// added to the headers of the program, as by [`Program::add_instruction`].
+    ///
+    /// # Panics
+    ///
+    /// If `index` is greater than the number of instructions in the body and `instruction` is
+    /// not a header.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::{Gate, Instruction};
+    /// use quil_rs::Program;
+    ///
+    /// let mut program = Program::parse_with_source_text("H 0 # first\nCNOT 0 1").unwrap();
+    /// program.insert_instruction(1, Instruction::Gate(Gate::x(1)));
+    /// assert_eq!(program.get_source_text(2), Some("CNOT 0 1"));
+    /// assert_eq!(program.to_string_with_comments(true), "H 0 # first\nX 1\nCNOT 0 1\n");
+    /// ```
+    pub fn insert_instruction(&mut self, index: usize, instruction: Instruction) {
+        if is_header(&instruction) {
+            self.add_instruction(instruction);
+            return;
+        }
+        self.instructions.insert(index, instruction);
+        self.reindex(|old| Some(if old < index { old } else { old + 1 }));
+    }
+
+    /// Replace the instruction of the body at `index` with `instruction`, returning the
+    /// instruction replaced. The comments of the replaced instruction are kept with its
+    /// replacement, but not its source text. If `instruction` is a header, the instruction is
+    /// instead removed, as by [`Program::remove_instruction`], and the header added to the
+    /// headers of the program.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not the index of an instruction in the body.
+    pub fn replace_instruction(&mut self, index: usize, instruction: Instruction) -> Instruction {
+        if is_header(&instruction) {
+            let removed = self.remove_instruction(index);
+            self.add_instruction(instruction);
+            return removed;
+        }
+        self.source.remove(&index);
+        std::mem::replace(&mut self.instructions[index], instruction)
+    }
+
+    /// Remove and return the instruction of the body at `index`, along with its source text and
+    /// comments, after which the instructions of the body are moved down by one.
+    ///
+    /// # Panics
+    ///
+    /// If `index` is not the index of an instruction in the body.
+    pub fn remove_instruction(&mut self, index: usize) -> Instruction {
+        let removed = self.instructions.remove(index);
+        self.reindex(|old| match old.cmp(&index) {
+            std::cmp::Ordering::Less => Some(old),
+            std::cmp::Ordering::Equal => None,
+            std::cmp::Ordering::Greater => Some(old - 1),
+        });
+        removed
+    }
+
+    /// Keep only the instructions of the body for which `predicate` returns `true`, in order,
+    /// along with their source text and comments.
+    pub fn retain_instructions(&mut self, mut predicate: impl FnMut(&Instruction) -> bool) {
+        let mut indices = HashMap::new();
+        let mut index = 0;
+        self.instructions.retain(|instruction| {
+            let keep = predicate(instruction);
+            if keep {
+                indices.insert(index, indices.len());
+            }
+            index += 1;
+            keep
+        });
+        self.reindex(|old| indices.get(&old).copied());
+    }
+
+    /// Move the source text and comments of the instruction at each index of the body to the
+    /// index given by `reindex`, or drop them if it gives none.
+    fn reindex(&mut self, reindex: impl Fn(usize) -> Option<usize>) {
+        self.source = std::mem::take(&mut self.source)
+            .into_iter()
+            .filter_map(|(index, source)| Some((reindex(index)?, source)))
+            .collect();
+        self.comments.reindex(reindex);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::{Gate, Instruction};
+    use crate::Program;
+
+    const INPUT: &str = "# start\nH 0\nX 1 # flip\nMEASURE 0\n# end\n";
+
+    fn source_texts(program: &Program) -> Vec<Option<&str>> {
+        (0..program.instructions.len())
+            .map(|index| program.get_source_text(index))
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_instruction() {
+        let mut program = Program::parse_with_source_text(INPUT).unwrap();
+        program.insert_instruction(0, Instruction::Gate(Gate::z(2)));
+        program.insert_instruction(4, Instruction::Nop);
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "Z 2\n# start\nH 0\nX 1 # flip\nMEASURE 0\nNOP\n# end\n"
+        );
+        assert_eq!(
+            source_texts(&program),
+            [None, Some("H 0"), Some("X 1"), Some("MEASURE 0"), None]
+        );
+        assert_eq!(program.get_source_span(1).unwrap().start.line, 2);
+
+        let declaration = "DECLARE ro BIT"
+            .parse::<Program>()
+            .unwrap()
+            .to_instructions(true);
+        program.insert_instruction(2, declaration[0].clone());
+        assert_eq!(program.instructions.len(), 5);
+        assert!(program.memory_regions.contains_key("ro"));
+    }
+
+    #[test]
+    fn test_replace_instruction() {
+        let mut program = Program::parse_with_source_text(INPUT).unwrap();
+        let replaced = program.replace_instruction(1, Instruction::Gate(Gate::y(1)));
+        assert_eq!(replaced, Instruction::Gate(Gate::x(1)));
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "# start\nH 0\nY 1 # flip\nMEASURE 0\n# end\n"
+        );
+        assert_eq!(
+            source_texts(&program),
+            [Some("H 0"), None, Some("MEASURE 0")]
+        );
+
+        let declaration = "DECLARE ro BIT"
+            .parse::<Program>()
+            .unwrap()
+            .to_instructions(true);
+        let replaced = program.replace_instruction(0, declaration[0].clone());
+        assert_eq!(replaced, Instruction::Gate(Gate::h(0)));
+        assert_eq!(
+            program.to_string(true),
+            "DECLARE ro BIT[1]\nY 1\nMEASURE 0\n"
+        );
+    }
+
+    #[test]
+    fn test_remove_instruction() {
+        let mut program = Program::parse_with_source_text(INPUT).unwrap();
+        assert_eq!(program.remove_instruction(1), Instruction::Gate(Gate::x(1)));
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "# start\nH 0\nMEASURE 0\n# end\n"
+        );
+        assert_eq!(source_texts(&program), [Some("H 0"), Some("MEASURE 0")]);
+    }
+
+    #[test]
+    fn test_retain_instructions() {
+        let mut program = Program::parse_with_source_text(INPUT).unwrap();
+        program.retain_instructions(
+            |instruction| !matches!(instruction, Instruction::Gate(gate) if gate.name == "H"),
+        );
+        assert_eq!(
+            program.to_string_with_comments(true),
+            "X 1 # flip\nMEASURE 0\n# end\n"
+        );
+        assert_eq!(source_texts(&program), [Some("X 1"), Some("MEASURE 0")]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Comparing programs by what they do, rather than how they are written.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use num_complex::Complex64;
+
+use crate::expression::Expression;
+use crate::instruction::unitary::Matrix;
+use crate::instruction::visit::{walk_expression_mut, InstructionRewriter, InstructionVisitor};
+use crate::instruction::{Instruction, Label, Qubit};
+use crate::Program;
+
+/// The tolerance within which the elements of two unitary matrices are taken to be equal.
+const UNITARY_TOLERANCE: f64 = 1e-9;
+
+/// Which differences between two programs [`Program::equivalent_to`] disregards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EquivalenceOptions {
+    /// Disregard the order of instructions which do not depend on one another, such as gates on
+    /// different qubits, as told by [`Program::to_dag`].
+    pub reorder_independent: bool,
+    /// Disregard the names of labels, so long as each program uses its labels in the same way.
+    pub rename_labels: bool,
+    /// Compare expressions by their simplified forms, so that `pi/2` and `1.5707963267948966`,
+    /// or `theta + (pi/2)` and `theta + 1.5707963267948966`, are taken to be the same.
+    pub canonicalize_expressions: bool,
+    /// If the programs are not otherwise found to be equivalent, but both only apply gates, with
+    /// constant parameters, to fixed qubits numbered less than this, compare their unitary
+    /// matrices, disregarding global phase. The size of the matrices grows exponentially with the
+    /// number of qubits, so this should only be set for small programs.
+    pub unitary_qubit_limit: Option<u64>,
+}
+
+impl Default for EquivalenceOptions {
+    fn default() -> Self {
+        Self {
+            reorder_independent: true,
+            rename_labels: true,
+            canonicalize_expressions: true,
+            unitary_qubit_limit: None,
+        }
+    }
+}
+
+/// Replaces each expression with its simplified, canonical form, with each constant part of it
+/// replaced by its value.
+struct ExpressionCanonicalizer;
+
+impl InstructionRewriter for ExpressionCanonicalizer {
+    fn rewrite_expression(&mut self, expression: &mut Expression) {
+        expression.simplify();
+        if let Ok(value) = expression.evaluate(&HashMap::new(), &HashMap::new()) {
+            *expression = Expression::Number(value);
+            return;
+        }
+        walk_expression_mut(self, expression);
+        *expression = expression.clone().into_canonical();
+    }
+}
+
+/// Collects the names of the labels of a program, in the order they are first found.
+#[derive(Default)]
+struct LabelCollector(Vec<String>);
+
+impl InstructionVisitor for LabelCollector {
+    fn visit_label(&mut self, label: &Label) {
+        if let Label::Fixed(name) = label {
+            if !self.0.iter().any(|seen| seen == name) {
+                self.0.push(name.to_string());
+            }
+        }
+    }
+}
+
+/// Renames each label by the order in which it was first found.
+struct LabelRenamer(HashMap<String, usize>);
+
+impl InstructionRewriter for LabelRenamer {
+    fn rewrite_label(&mut self, label: &mut Label) {
+        if let Label::Fixed(name) = label {
+            if let Some(index) = self.0.get(name.as_str()) {
+                *label = Label::Fixed(format!("{index}").into());
+            }
+        }
+    }
+}
+
+/// `program` with the differences `options` disregards removed.
+fn canonical_program(program: &Program, options: &EquivalenceOptions) -> Program {
+    let mut program = program.clone();
+    program.clear_source();
+    if options.canonicalize_expressions {
+        ExpressionCanonicalizer.rewrite_program(&mut program);
+    }
+    if options.rename_labels {
+        let mut collector = LabelCollector::default();
+        collector.visit_program(&program);
+        let names = collector
+            .0
+            .into_iter()
+            .enumerate()
+            .map(|(index, name)| (name, index))
+            .collect();
+        LabelRenamer(names).rewrite_program(&mut program);
+    }
+    program
+}
+
+/// The instructions of `program`, in order, or, if independent instructions may be reordered,
+/// in an order which is the same for every program which only differs from it in the order of
+/// its independent instructions.
+fn canonical_instructions(program: &Program, options: &EquivalenceOptions) -> Vec<Instruction> {
+    if !options.reorder_independent {
+        return program.instructions.clone();
+    }
+    // Instructions with the same logical time are independent of one another, and every
+    // instruction comes after those it depends on when ordered by logical time, so ordering by
+    // logical time and then by how instructions are written yields the same order for any
+    // reordering of independent instructions.
+    let dag = program.to_dag();
+    let mut keyed: Vec<(usize, String, &Instruction)> = program
+        .instructions
+        .iter()
+        .enumerate()
+        .map(|(index, instruction)| {
+            let time = dag
+                .logical_time(index)
+                .expect("every instruction has a time");
+            (time, instruction.to_string(), instruction)
+        })
+        .collect();
+    keyed.sort_by(|a, b| (a.0, &a.1).cmp(&(b.0, &b.1)));
+    keyed
+        .into_iter()
+        .map(|(_, _, instruction)| instruction.clone())
+        .collect()
+}
+
+/// The number of qubits whose unitary matrix is needed to compare `programs`, or `None` if it is
+/// more than `limit` or any qubit is a variable.
+fn unitary_qubit_count(programs: [&Program; 2], limit: u64) -> Option<u64> {
+    let mut count = 0;
+    for program in programs {
+        for qubit in program.get_used_qubits() {
+            match qubit {
+                Qubit::Fixed(index) => count = count.max(index + 1),
+                Qubit::Variable(_) => return None,
+            }
+        }
+    }
+    (count <= limit).then_some(count)
+}
+
+/// Whether `a` and `b` are equal up to a global phase.
+pub(super) fn equal_up_to_phase(a: &Matrix, b: &Matrix) -> bool {
+    let pivot = a
+        .rows()
+        .flatten()
+        .zip(b.rows().flatten())
+        .find(|(a, _)| a.norm() > UNITARY_TOLERANCE);
+    match pivot {
+        Some((a_pivot, b_pivot)) => {
+            let phase: Complex64 = b_pivot / a_pivot;
+            a.rows()
+                .flatten()
+                .zip(b.rows().flatten())
+                .all(|(a, b)| (a * phase - b).norm() <= UNITARY_TOLERANCE)
+        }
+        None => a.approx_eq(b, UNITARY_TOLERANCE),
+    }
+}
+
+impl Program {
+    /// Whether this program and `other` are the same but for the differences `options`
+    /// disregards: the order of independent instructions, the names of labels, and how
+    /// expressions are written.
+    ///
+    /// This is stricter than comparing what the programs compute, which would take simulating
+    /// them, but less strict than comparing how they are written. Headers, such as calibrations
+    /// and declarations, must be the same but for their order and how their expressions are
+    /// written. Only if [`EquivalenceOptions::unitary_qubit_limit`] is set, and the programs are
+    /// small enough, are programs with different gates compared by their unitary matrices.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::EquivalenceOptions;
+    /// use quil_rs::Program;
+    ///
+    /// let first: Program = "H 0\nX 1\nRX(pi/2) 0\nLABEL @a\nJUMP @a".parse().unwrap();
+    /// let second: Program = "X 1\nH 0\nRX(1.5707963267948966) 0\nLABEL @b\nJUMP @b".parse().unwrap();
+    /// assert!(first.equivalent_to(&second, &EquivalenceOptions::default()));
+    ///
+    /// let first: Program = "H 0\nH 0".parse().unwrap();
+    /// let second: Program = "I 0".parse().unwrap();
+    /// assert!(!first.equivalent_to(&second, &EquivalenceOptions::default()));
+    /// let options = EquivalenceOptions {
+    ///     unitary_qubit_limit: Some(4),
+    ///     ..Default::default()
+    /// };
+    /// assert!(first.equivalent_to(&second, &options));
+    /// ```
+    pub fn equivalent_to(&self, other: &Program, options: &EquivalenceOptions) -> bool {
+        let first = canonical_program(self, options);
+        let second = canonical_program(other, options);
+        let same_headers = first.calibrations == second.calibrations
+            && first.frames == second.frames
+            && first.memory_regions == second.memory_regions
+            && first.waveforms == second.waveforms;
+        if !same_headers {
+            return false;
+        }
+        if canonical_instructions(&first, options) == canonical_instructions(&second, options) {
+            return true;
+        }
+
+        let Some(limit) = options.unitary_qubit_limit else {
+            return false;
+        };
+        let Some(qubit_count) = unitary_qubit_count([self, other], limit) else {
+            return false;
+        };
+        match (self.to_unitary(qubit_count), other.to_unitary(qubit_count)) {
+            (Ok(first), Ok(second)) => equal_up_to_phase(&first, &second),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::EquivalenceOptions;
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0\nX 1", "X 1\nH 0", true)]
+    #[case("H 0\nCNOT 0 1", "CNOT 0 1\nH 0", false)]
+    #[case("H 0\nX 0", "X 0\nH 0", false)]
+    #[case(
+        "DECLARE a BIT\nDECLARE b BIT\nMEASURE 0 a\nMEASURE 1 b",
+        "DECLARE a BIT\nDECLARE b BIT\nMEASURE 1 b\nMEASURE 0 a",
+        true
+    )]
+    #[case(
+        "DECLARE ro BIT\nMEASURE 0 ro\nMEASURE 1 ro",
+        "DECLARE ro BIT\nMEASURE 1 ro\nMEASURE 0 ro",
+        false
+    )]
+    #[case("H 0\nLABEL @a\nX 1", "LABEL @a\nH 0\nX 1", false)]
+    #[case("RX(pi) 0", "RX(2*(pi/2)) 0", true)]
+    #[case(
+        "DECLARE theta REAL\nRX(theta + (pi/2)) 0",
+        "DECLARE theta REAL\nRX(theta + 1.5707963267948966) 0",
+        true
+    )]
+    #[case("RX(pi) 0", "RX(pi/2) 0", false)]
+    #[case("LABEL @a\nJUMP @a\nLABEL @b", "LABEL @x\nJUMP @x\nLABEL @y", true)]
+    #[case("LABEL @a\nJUMP @a\nLABEL @b", "LABEL @x\nJUMP @y\nLABEL @y", false)]
+    #[case("DECLARE ro BIT\nH 0", "DECLARE ro BIT[2]\nH 0", false)]
+    #[case("DECLARE a BIT\nDECLARE b REAL", "DECLARE b REAL\nDECLARE a BIT", true)]
+    #[case("H 0\nH 0", "I 0", false)]
+    fn test_equivalent_to(#[case] first: &str, #[case] second: &str, #[case] expected: bool) {
+        let first = Program::from_str(first).unwrap();
+        let second = Program::from_str(second).unwrap();
+        let options = EquivalenceOptions::default();
+        assert_eq!(first.equivalent_to(&second, &options), expected);
+        assert_eq!(second.equivalent_to(&first, &options), expected);
+    }
+
+    #[rstest]
+    #[case(EquivalenceOptions { reorder_independent: false, ..Default::default() }, "H 0\nX 1", "X 1\nH 0")]
+    #[case(EquivalenceOptions { rename_labels: false, ..Default::default() }, "LABEL @a", "LABEL @b")]
+    #[case(EquivalenceOptions { canonicalize_expressions: false, ..Default::default() }, "RX(pi) 0", "RX(2*(pi/2)) 0")]
+    fn test_strict_options(
+        #[case] options: EquivalenceOptions,
+        #[case] first: &str,
+        #[case] second: &str,
+    ) {
+        let first = Program::from_str(first).unwrap();
+        let second = Program::from_str(second).unwrap();
+        assert!(!first.equivalent_to(&second, &options));
+        assert!(first.equivalent_to(&second, &EquivalenceOptions::default()));
+    }
+
+    #[rstest]
+    #[case("H 0\nH 0", "I 0", Some(1), true)]
+    #[case("Z 0", "RZ(pi) 0", Some(1), true)]
+    #[case("CNOT 0 1", "H 1\nCZ 0 1\nH 1", Some(2), true)]
+    #[case("CNOT 0 1", "CNOT 1 0", Some(2), false)]
+    #[case("CNOT 0 1", "H 1\nCZ 0 1\nH 1", Some(1), false)]
+    #[case("CNOT 0 1", "H 1\nCZ 0 1\nH 1", None, false)]
+    #[case(
+        "DECLARE ro BIT\nMEASURE 0 ro",
+        "DECLARE ro BIT\nMEASURE 0 ro\nI 0",
+        Some(1),
+        false
+    )]
+    #[case("DECLARE ro BIT\nH 0", "DECLARE ro BIT[2]\nH 0", Some(1), false)]
+    fn test_unitary_check(
+        #[case] first: &str,
+        #[case] second: &str,
+        #[case] unitary_qubit_limit: Option<u64>,
+        #[case] expected: bool,
+    ) {
+        let first = Program::from_str(first).unwrap();
+        let second = Program::from_str(second).unwrap();
+        let options = EquivalenceOptions {
+            unitary_qubit_limit,
+            ..Default::default()
+        };
+        assert_eq!(first.equivalent_to(&second, &options), expected);
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::parser::ErrorInput;
+use crate::parser::{ErrorInput, Excerpt};
 use std::fmt;
 
 /// The parser returned success, but there was unexpected leftover input.
@@ -23,6 +23,7 @@ pub struct LeftoverError<O> {
     line: u32,
     column: usize,
     snippet: String,
+    excerpt: Option<Excerpt>,
     parsed: O,
 }
 
@@ -35,7 +36,13 @@ impl<O> fmt::Display for LeftoverError<O> {
             self.line,
             self.column,
             self.snippet,
-        )
+        )?;
+        if f.alternate() {
+            if let Some(excerpt) = &self.excerpt {
+                write!(f, "\n{}", excerpt)?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -51,10 +58,21 @@ impl<O> LeftoverError<O> {
             line: leftover.line(),
             column: leftover.column(),
             snippet: leftover.snippet(),
+            excerpt: leftover.excerpt(),
             parsed,
         }
     }
 
+    /// The line the leftover input starts at, counted from 1.
+    pub fn line(&self) -> u32 {
+        self.line
+    }
+
+    /// The column the leftover input starts at, counted from 1.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
     /// Consumes this error and returns the parsed output.
     pub fn recover(self) -> O {
         self.parsed
@@ -66,6 +84,7 @@ impl<O> LeftoverError<O> {
             line,
             column,
             snippet,
+            excerpt,
             parsed,
         } = self;
         let parsed = map(parsed);
@@ -73,6 +92,7 @@ impl<O> LeftoverError<O> {
             line,
             column,
             snippet,
+            excerpt,
             parsed,
         }
     }
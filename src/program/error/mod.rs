@@ -22,6 +22,7 @@ use std::fmt::Formatter;
 
 use crate::instruction::Instruction;
 use crate::parser::{LexError, ParseError};
+use crate::program::{Requirement, SourceLocation};
 pub use leftover::LeftoverError;
 pub use result::{disallow_leftover, map_parsed, recover};
 pub use syntax::SyntaxError;
@@ -34,6 +35,24 @@ pub enum ProgramError<T> {
         message: String,
     },
     RecursiveCalibration(Instruction),
+    MissingCalibration(Box<Instruction>),
+    InvalidCircuitInvocation {
+        instruction: Box<Instruction>,
+        message: String,
+    },
+    RecursiveCircuit(Box<Instruction>),
+    NotInvertible(Box<Instruction>),
+    UnboundVariables(Vec<String>),
+    IncludeCycle(String),
+    UnresolvedInclude {
+        filename: String,
+        message: String,
+    },
+    UnsupportedConstruct {
+        construct: String,
+        required: Requirement,
+        location: SourceLocation,
+    },
     Syntax(SyntaxError<T>),
 }
 
@@ -79,6 +98,30 @@ impl<T> ProgramError<T> {
                 message,
             },
             Self::RecursiveCalibration(inst) => ProgramError::RecursiveCalibration(inst),
+            Self::MissingCalibration(inst) => ProgramError::MissingCalibration(inst),
+            Self::InvalidCircuitInvocation {
+                instruction,
+                message,
+            } => ProgramError::InvalidCircuitInvocation {
+                instruction,
+                message,
+            },
+            Self::RecursiveCircuit(inst) => ProgramError::RecursiveCircuit(inst),
+            Self::NotInvertible(inst) => ProgramError::NotInvertible(inst),
+            Self::UnboundVariables(variables) => ProgramError::UnboundVariables(variables),
+            Self::IncludeCycle(filename) => ProgramError::IncludeCycle(filename),
+            Self::UnresolvedInclude { filename, message } => {
+                ProgramError::UnresolvedInclude { filename, message }
+            }
+            Self::UnsupportedConstruct {
+                construct,
+                required,
+                location,
+            } => ProgramError::UnsupportedConstruct {
+                construct,
+                required,
+                location,
+            },
             Self::Syntax(err) => ProgramError::Syntax(err.map_parsed(map)),
         }
     }
@@ -97,6 +140,42 @@ where
             Self::RecursiveCalibration(instruction) => {
                 write!(f, "instruction {} expands into itself", instruction)
             }
+            Self::MissingCalibration(instruction) => {
+                write!(f, "instruction {} has no calibration", instruction)
+            }
+            Self::InvalidCircuitInvocation {
+                instruction,
+                message,
+            } => write!(
+                f,
+                "invalid circuit invocation `{}`: {}",
+                instruction, message
+            ),
+            Self::RecursiveCircuit(instruction) => {
+                write!(f, "circuit invocation {} expands into itself", instruction)
+            }
+            Self::NotInvertible(instruction) => {
+                write!(f, "instruction {} cannot be inverted", instruction)
+            }
+            Self::UnboundVariables(variables) => {
+                let variables: Vec<String> = variables.iter().map(|v| format!("%{v}")).collect();
+                write!(f, "variables have no value: {}", variables.join(", "))
+            }
+            Self::IncludeCycle(filename) => {
+                write!(f, "file \"{}\" includes itself", filename)
+            }
+            Self::UnresolvedInclude { filename, message } => {
+                write!(f, "could not include \"{}\": {}", filename, message)
+            }
+            Self::UnsupportedConstruct {
+                construct,
+                required,
+                location,
+            } => write!(
+                f,
+                "{} at line {}, column {} requires {}",
+                construct, location.line, location.column, required
+            ),
             Self::Syntax(err) => fmt::Display::fmt(err, f),
         }
     }
@@ -110,6 +189,14 @@ where
         match self {
             Self::InvalidCalibration { .. } => None,
             Self::RecursiveCalibration(_) => None,
+            Self::MissingCalibration(_) => None,
+            Self::InvalidCircuitInvocation { .. } => None,
+            Self::RecursiveCircuit(_) => None,
+            Self::NotInvertible(_) => None,
+            Self::UnboundVariables(_) => None,
+            Self::IncludeCycle(_) => None,
+            Self::UnresolvedInclude { .. } => None,
+            Self::UnsupportedConstruct { .. } => None,
             Self::Syntax(err) => Some(err),
         }
     }
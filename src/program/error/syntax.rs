@@ -24,7 +24,8 @@ use super::LeftoverError;
 ///
 /// The standard [`Display`](fmt::Display) implementation shows only the initial error.
 /// Use the alternative syntax (e.g., `format!("{:#}", err)` instead of `format!("{}", err)`) to
-/// see a backtrace of errors that caused this one.
+/// see the line of input the error was found in, with the text at fault underlined, and a
+/// backtrace of errors that caused this one.
 ///
 /// See also [`Error`](crate::parser::Error).
 #[derive(Debug, PartialEq)]
@@ -88,3 +89,39 @@ impl<T: fmt::Debug + 'static> Error for SyntaxError<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::program::ProgramError;
+    use crate::Program;
+
+    /// The alternate display of the syntax error found parsing `input`.
+    fn alternate_display(input: &str) -> String {
+        match Program::from_str(input) {
+            Err(ProgramError::Syntax(error)) => format!("{error:#}"),
+            other => panic!("expected a syntax error but found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_display_unclosed_parenthesis() {
+        insta::assert_snapshot!(alternate_display("H 0\nRX(pi 0\n"));
+    }
+
+    #[test]
+    fn test_display_unlexable_character() {
+        insta::assert_snapshot!(alternate_display("H 0\nX 1 $\n"));
+    }
+
+    #[test]
+    fn test_display_indented() {
+        insta::assert_snapshot!(alternate_display("DEFCAL X 0:\n\tPULSE 0 \"rf\" ???\n"));
+    }
+
+    #[test]
+    fn test_display_end_of_line() {
+        insta::assert_snapshot!(alternate_display("MEASURE 0 ro["));
+    }
+}
@@ -0,0 +1,506 @@
+//! Trace how readout results flow through a program's classical memory.
+//!
+//! The results of `MEASURE`, `CAPTURE`, and `RAW-CAPTURE` may be copied and combined by classical
+//! instructions before they decide a `JUMP-WHEN` or `JUMP-UNLESS`, or set the parameters of a gate.
+//! A [`FeedbackGraph`] links each instruction which reads memory to the instructions whose writes
+//! it may read, following the program's control flow, so that the readouts each branch depends on
+//! can be found.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::instruction::visit::InstructionVisitor;
+use crate::instruction::{
+    Arithmetic, ArithmeticOperand, BinaryLogic, BinaryOperand, Call, CallArgument, Capture,
+    Comparison, ComparisonOperand, Convert, Exchange, Instruction, JumpUnless, JumpWhen, Load,
+    Measurement, MemoryReference, Move, RawCapture, Store, UnaryLogic,
+};
+use crate::Program;
+
+use super::control_flow_graph::ControlFlowGraphError;
+use super::memory_check::MemoryReferenceCollector;
+
+/// Memory read or written by an instruction: one element of a region, or, where the instruction
+/// does not say which, any part of it.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MemoryLocation {
+    pub region: String,
+    /// The index of the element within the region, or `None` if it may be any.
+    pub index: Option<u64>,
+}
+
+impl MemoryLocation {
+    pub(super) fn element(reference: &MemoryReference) -> Self {
+        Self {
+            region: reference.name.to_string(),
+            index: Some(reference.index),
+        }
+    }
+
+    fn region(name: &str) -> Self {
+        Self {
+            region: name.to_string(),
+            index: None,
+        }
+    }
+
+    /// Whether the two locations may be the same memory.
+    pub(super) fn overlaps(&self, other: &MemoryLocation) -> bool {
+        self.region == other.region
+            && (self.index.is_none() || other.index.is_none() || self.index == other.index)
+    }
+}
+
+/// The memory an instruction reads, and that which it writes.
+#[derive(Default)]
+pub(super) struct MemoryEffects {
+    pub(super) reads: Vec<MemoryLocation>,
+    pub(super) writes: Vec<MemoryLocation>,
+}
+
+fn arithmetic_operand_reads(operand: &ArithmeticOperand) -> Vec<MemoryLocation> {
+    operand
+        .get_memory_reference()
+        .map(MemoryLocation::element)
+        .into_iter()
+        .collect()
+}
+
+/// The memory `instruction` reads and writes. Instructions which write memory without naming
+/// the element are taken to write any of the region, and instructions not listed here to read
+/// any memory they refer to, as gates do in their parameters.
+pub(super) fn memory_effects(instruction: &Instruction) -> MemoryEffects {
+    let element = MemoryLocation::element;
+    match instruction {
+        Instruction::Measurement(Measurement { target, .. }) => MemoryEffects {
+            reads: vec![],
+            writes: target.iter().map(element).collect(),
+        },
+        Instruction::Capture(Capture {
+            memory_reference, ..
+        })
+        | Instruction::RawCapture(RawCapture {
+            memory_reference, ..
+        }) => {
+            let mut collector = MemoryReferenceCollector::default();
+            collector.visit_instruction(instruction);
+            let written = element(memory_reference);
+            let reads = collector
+                .0
+                .iter()
+                .map(element)
+                .filter(|location| *location != written)
+                .collect();
+            // `RAW-CAPTURE` fills the region from the element it names.
+            let written = match instruction {
+                Instruction::RawCapture(_) => MemoryLocation::region(&written.region),
+                _ => written,
+            };
+            MemoryEffects {
+                reads,
+                writes: vec![written],
+            }
+        }
+        Instruction::Move(Move {
+            destination,
+            source,
+        }) => MemoryEffects {
+            reads: arithmetic_operand_reads(source),
+            writes: vec![element(destination)],
+        },
+        Instruction::Arithmetic(Arithmetic {
+            destination,
+            source,
+            ..
+        }) => {
+            let mut reads = vec![element(destination)];
+            reads.extend(arithmetic_operand_reads(source));
+            MemoryEffects {
+                reads,
+                writes: vec![element(destination)],
+            }
+        }
+        Instruction::Convert(Convert { from, to }) => MemoryEffects {
+            reads: vec![element(from)],
+            writes: vec![element(to)],
+        },
+        Instruction::Exchange(Exchange { left, right }) => MemoryEffects {
+            reads: vec![element(left), element(right)],
+            writes: vec![element(left), element(right)],
+        },
+        Instruction::BinaryLogic(BinaryLogic { operands, .. }) => {
+            let mut reads = vec![element(&operands.0)];
+            if let BinaryOperand::MemoryReference(reference) = &operands.1 {
+                reads.push(element(reference));
+            }
+            MemoryEffects {
+                reads,
+                writes: vec![element(&operands.0)],
+            }
+        }
+        Instruction::UnaryLogic(UnaryLogic { operand, .. }) => MemoryEffects {
+            reads: vec![element(operand)],
+            writes: vec![element(operand)],
+        },
+        Instruction::Comparison(Comparison { operands, .. }) => {
+            let mut reads = vec![element(&operands.1)];
+            if let ComparisonOperand::MemoryReference(reference) = &operands.2 {
+                reads.push(element(reference));
+            }
+            MemoryEffects {
+                reads,
+                writes: vec![element(&operands.0)],
+            }
+        }
+        Instruction::Load(Load {
+            destination,
+            source,
+            offset,
+        }) => MemoryEffects {
+            reads: vec![MemoryLocation::region(source), element(offset)],
+            writes: vec![element(destination)],
+        },
+        Instruction::Store(Store {
+            destination,
+            offset,
+            source,
+        }) => {
+            let mut reads = vec![element(offset)];
+            reads.extend(arithmetic_operand_reads(source));
+            MemoryEffects {
+                reads,
+                writes: vec![MemoryLocation::region(destination)],
+            }
+        }
+        Instruction::Call(Call { arguments, .. }) => {
+            // Without the signature of the subroutine, any memory it is passed may be written.
+            let locations: Vec<MemoryLocation> = arguments
+                .iter()
+                .filter_map(|argument| match argument {
+                    CallArgument::Identifier(name) => Some(MemoryLocation::region(name)),
+                    CallArgument::MemoryReference(reference) => Some(element(reference)),
+                    CallArgument::LiteralInteger(_) | CallArgument::LiteralReal(_) => None,
+                })
+                .collect();
+            MemoryEffects {
+                reads: locations.clone(),
+                writes: locations,
+            }
+        }
+        Instruction::JumpWhen(JumpWhen { condition, .. })
+        | Instruction::JumpUnless(JumpUnless { condition, .. }) => MemoryEffects {
+            reads: vec![element(condition)],
+            writes: vec![],
+        },
+        _ => {
+            let mut collector = MemoryReferenceCollector::default();
+            collector.visit_instruction(instruction);
+            MemoryEffects {
+                reads: collector.0.iter().map(element).collect(),
+                writes: vec![],
+            }
+        }
+    }
+}
+
+/// Whether `instruction` writes a readout result into memory.
+fn is_readout(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::Measurement(Measurement {
+            target: Some(_),
+            ..
+        }) | Instruction::Capture(_)
+            | Instruction::RawCapture(_)
+    )
+}
+
+/// That an instruction may read memory written by another, as found by
+/// [`Program::feedback_graph`].
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DataDependency {
+    /// The index in [`Program::instructions`] of the instruction which writes the memory.
+    pub writer: usize,
+    /// The index of the instruction which reads it.
+    pub reader: usize,
+    /// The memory read, as the reader names it.
+    pub location: MemoryLocation,
+}
+
+/// The data dependencies between the instructions of a program, through its classical memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FeedbackGraph {
+    dependencies: BTreeSet<DataDependency>,
+    readouts: BTreeSet<usize>,
+    branches: BTreeSet<usize>,
+}
+
+impl FeedbackGraph {
+    /// Every data dependency, ordered by writer and then reader.
+    pub fn dependencies(&self) -> impl Iterator<Item = &DataDependency> + '_ {
+        self.dependencies.iter()
+    }
+
+    /// The instructions whose writes the instruction at `index` may directly read.
+    pub fn writers_of(&self, index: usize) -> BTreeSet<usize> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| dependency.reader == index)
+            .map(|dependency| dependency.writer)
+            .collect()
+    }
+
+    /// The instructions which may directly read what the instruction at `index` writes.
+    pub fn readers_of(&self, index: usize) -> BTreeSet<usize> {
+        self.dependencies
+            .iter()
+            .filter(|dependency| dependency.writer == index)
+            .map(|dependency| dependency.reader)
+            .collect()
+    }
+
+    /// The readout instructions, such as `MEASURE`, whose results may flow into the instruction
+    /// at `index`, directly or through classical instructions.
+    pub fn readouts_feeding(&self, index: usize) -> BTreeSet<usize> {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![index];
+        while let Some(current) = pending.pop() {
+            for writer in self.writers_of(current) {
+                if visited.insert(writer) {
+                    pending.push(writer);
+                }
+            }
+        }
+        visited.retain(|writer| self.readouts.contains(writer));
+        visited
+    }
+
+    /// The instructions into which the result of the readout at `index` may flow, directly or
+    /// through classical instructions.
+    pub fn consumers_of(&self, index: usize) -> BTreeSet<usize> {
+        let mut visited = BTreeSet::new();
+        let mut pending = vec![index];
+        while let Some(current) = pending.pop() {
+            for reader in self.readers_of(current) {
+                if visited.insert(reader) {
+                    pending.push(reader);
+                }
+            }
+        }
+        visited
+    }
+
+    /// Each `JUMP-WHEN` and `JUMP-UNLESS` whose condition depends on readout, with the readout
+    /// instructions it depends on, keyed on the index of the jump.
+    pub fn feedback_branches(&self) -> BTreeMap<usize, BTreeSet<usize>> {
+        self.branches
+            .iter()
+            .map(|&branch| (branch, self.readouts_feeding(branch)))
+            .filter(|(_, readouts)| !readouts.is_empty())
+            .collect()
+    }
+}
+
+/// A write of memory by an instruction, which may be read until the memory is overwritten.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Definition {
+    index: usize,
+    location: MemoryLocation,
+}
+
+/// Update the definitions reaching the point after an instruction which writes `writes`: each
+/// definition of exactly the same element is overwritten.
+fn apply_writes(index: usize, writes: &[MemoryLocation], reaching: &mut BTreeSet<Definition>) {
+    for location in writes {
+        if location.index.is_some() {
+            reaching.retain(|definition| definition.location != *location);
+        }
+    }
+    for location in writes {
+        reaching.insert(Definition {
+            index,
+            location: location.clone(),
+        });
+    }
+}
+
+impl Program {
+    /// Trace which instructions may read memory written by which others, following the program's
+    /// control flow: a write reaches a read if some path from one to the other does not write the
+    /// same element again. Instructions which write memory without naming the element, such as
+    /// `STORE`, are taken to write any element of the region, without overwriting earlier writes.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the control-flow graph of the program cannot be built; see [`Program::to_cfg`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT[2]
+    /// DECLARE both BIT
+    /// MEASURE 0 ro[0]
+    /// MEASURE 1 ro[1]
+    /// MOVE both ro[0]
+    /// AND both ro[1]
+    /// JUMP-WHEN @end both
+    /// X 0
+    /// LABEL @end"
+    ///     .parse()
+    ///     .unwrap();
+    /// let graph = program.feedback_graph().unwrap();
+    /// let branches = graph.feedback_branches();
+    /// assert_eq!(branches[&4].iter().copied().collect::<Vec<_>>(), [0, 1]);
+    /// assert_eq!(graph.consumers_of(0).into_iter().collect::<Vec<_>>(), [2, 3, 4]);
+    /// ```
+    pub fn feedback_graph(&self) -> Result<FeedbackGraph, ControlFlowGraphError> {
+        let cfg = self.to_cfg()?;
+        let effects: Vec<MemoryEffects> = self.instructions.iter().map(memory_effects).collect();
+
+        // The definitions reaching the end of each block, iterated until they no longer change.
+        let mut block_out: Vec<BTreeSet<Definition>> = vec![BTreeSet::new(); cfg.len()];
+        let block_in = |block: usize, block_out: &[BTreeSet<Definition>]| {
+            let mut reaching = BTreeSet::new();
+            for (predecessor, _) in cfg.predecessors(block) {
+                reaching.extend(block_out[predecessor].iter().cloned());
+            }
+            reaching
+        };
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (block_index, block) in cfg.blocks().enumerate() {
+                let mut reaching = block_in(block_index, &block_out);
+                for index in block.indices() {
+                    apply_writes(index, &effects[index].writes, &mut reaching);
+                }
+                if reaching != block_out[block_index] {
+                    block_out[block_index] = reaching;
+                    changed = true;
+                }
+            }
+        }
+
+        let mut graph = FeedbackGraph::default();
+        for (block_index, block) in cfg.blocks().enumerate() {
+            let mut reaching = block_in(block_index, &block_out);
+            for index in block.indices() {
+                for location in &effects[index].reads {
+                    for definition in &reaching {
+                        if definition.location.overlaps(location) {
+                            graph.dependencies.insert(DataDependency {
+                                writer: definition.index,
+                                reader: index,
+                                location: location.clone(),
+                            });
+                        }
+                    }
+                }
+                apply_writes(index, &effects[index].writes, &mut reaching);
+            }
+        }
+
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            if is_readout(instruction) {
+                graph.readouts.insert(index);
+            }
+            if matches!(
+                instruction,
+                Instruction::JumpWhen(_) | Instruction::JumpUnless(_)
+            ) {
+                graph.branches.insert(index);
+            }
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::Program;
+
+    #[rstest]
+    // Each measured bit feeds only the branch which reads it.
+    #[case(
+        "DECLARE ro BIT[2]\nMEASURE 0 ro[0]\nMEASURE 1 ro[1]\nJUMP-WHEN @a ro[1]\nLABEL @a\nJUMP-UNLESS @b ro[0]\nLABEL @b",
+        vec![(2, vec![1]), (4, vec![0])]
+    )]
+    // A later measurement overwrites an earlier one of the same bit.
+    #[case(
+        "DECLARE ro BIT\nMEASURE 0 ro\nMEASURE 1 ro\nJUMP-WHEN @a ro\nLABEL @a",
+        vec![(2, vec![1])]
+    )]
+    // Either measurement may reach the branch, depending on which way the first one goes.
+    #[case(
+        "DECLARE ro BIT\nDECLARE flag BIT\nMEASURE 0 ro\nJUMP-WHEN @skip ro\nMEASURE 1 ro\nLABEL @skip\nMOVE flag ro\nJUMP-WHEN @end flag\nLABEL @end",
+        vec![(1, vec![0]), (5, vec![0, 2])]
+    )]
+    // The measurement in a loop reaches its own branch, but that before the loop never does.
+    #[case(
+        "DECLARE ro BIT\nMEASURE 0 ro\nLABEL @loop\nMEASURE 1 ro\nJUMP-WHEN @loop ro",
+        vec![(3, vec![2])]
+    )]
+    // Branches on memory no readout writes do not depend on feedback.
+    #[case(
+        "DECLARE flag BIT\nDECLARE ro BIT\nMEASURE 0 ro\nJUMP-WHEN @a flag\nLABEL @a",
+        vec![]
+    )]
+    fn test_feedback_branches(#[case] input: &str, #[case] expected: Vec<(usize, Vec<usize>)>) {
+        let program = Program::from_str(input).unwrap();
+        let branches = program.feedback_graph().unwrap().feedback_branches();
+        let expected = expected
+            .into_iter()
+            .map(|(branch, readouts)| (branch, readouts.into_iter().collect::<BTreeSet<_>>()))
+            .collect();
+        assert_eq!(branches, expected);
+    }
+
+    #[test]
+    fn test_classical_dependencies() {
+        let program = Program::from_str(
+            "DECLARE ro BIT
+DECLARE count INTEGER
+DECLARE theta REAL
+DECLARE buffer INTEGER[4]
+MEASURE 0 ro
+CONVERT count ro
+ADD count 1
+STORE buffer count count
+LOAD count buffer count
+MUL theta 2.0
+RX(theta) 0",
+        )
+        .unwrap();
+        let graph = program.feedback_graph().unwrap();
+
+        assert_eq!(graph.writers_of(1), BTreeSet::from([0]));
+        assert_eq!(graph.writers_of(2), BTreeSet::from([1]));
+        assert_eq!(graph.writers_of(3), BTreeSet::from([2]));
+        // The LOAD reads the whole region written by the STORE, and the offset written by ADD.
+        assert_eq!(graph.writers_of(4), BTreeSet::from([2, 3]));
+        assert_eq!(graph.consumers_of(0), BTreeSet::from([1, 2, 3, 4]));
+        // The gate's parameter depends on classical memory, but not on readout.
+        assert_eq!(graph.writers_of(6), BTreeSet::from([5]));
+        assert!(graph.readouts_feeding(6).is_empty());
+    }
+}
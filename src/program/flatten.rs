@@ -0,0 +1,694 @@
+//! Flatten a program's classical control flow, for backends which cannot branch.
+//!
+//! The program is run from its first instruction with its classical memory taken as unknown.
+//! Classical instructions whose operands are known are evaluated rather than kept, the
+//! conditions of jumps are decided from the values so found, and jumps are followed, so that a
+//! loop counted by a known value is unrolled. Every other instruction is kept, in the order it
+//! would be executed, with the known values of the memory it reads substituted into it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, HashMap};
+
+use thiserror::Error;
+
+use crate::expression::Expression;
+use crate::instruction::visit::{walk_expression_mut, InstructionRewriter};
+use crate::instruction::{
+    Arithmetic, ArithmeticOperand, ArithmeticOperator, BinaryLogic, BinaryOperand, BinaryOperator,
+    Comparison, ComparisonOperand, ComparisonOperator, Convert, Exchange, Instruction, Jump,
+    JumpUnless, JumpWhen, Load, MemoryReference, Move, ScalarType, Store, UnaryLogic,
+    UnaryOperator,
+};
+use crate::{real, Program};
+
+use super::control_flow_graph::ControlFlowGraphError;
+use super::feedback::{memory_effects, MemoryLocation};
+use super::memory_layout::{element_size, MemoryLayout, MemoryLayoutError};
+
+/// Errors which may occur while flattening a program.
+#[derive(Debug, Error)]
+pub enum FlattenError {
+    #[error(transparent)]
+    ControlFlow(#[from] ControlFlowGraphError),
+
+    #[error(transparent)]
+    MemoryLayout(#[from] MemoryLayoutError),
+
+    #[error("In instruction {index} ({instruction}): the condition is not known before the program runs.")]
+    UnknownCondition {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+
+    #[error("the program did not finish within {limit} steps")]
+    StepLimitExceeded { limit: usize },
+}
+
+/// Options for [`Program::flatten`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlattenOptions {
+    /// The most instructions to execute before giving up, which bounds the length of the
+    /// flattened program and stops a loop which never ends.
+    pub max_steps: usize,
+    /// Whether to write the values computed for memory back to it at the end of the flattened
+    /// program, so that it leaves memory as the original would. If not, only values read by
+    /// instructions which are kept are written.
+    pub preserve_memory: bool,
+}
+
+impl Default for FlattenOptions {
+    fn default() -> Self {
+        Self {
+            max_steps: 100_000,
+            preserve_memory: true,
+        }
+    }
+}
+
+/// A value known to be held in an element of memory.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Integer(i64),
+    Real(f64),
+}
+
+impl Value {
+    fn as_real(self) -> f64 {
+        match self {
+            Value::Integer(value) => value as f64,
+            Value::Real(value) => value,
+        }
+    }
+
+    fn is_nonzero(self) -> bool {
+        match self {
+            Value::Integer(value) => value != 0,
+            Value::Real(value) => value != 0.0,
+        }
+    }
+
+    fn to_operand(self) -> ArithmeticOperand {
+        match self {
+            Value::Integer(value) => ArithmeticOperand::LiteralInteger(value),
+            Value::Real(value) => ArithmeticOperand::LiteralReal(value),
+        }
+    }
+}
+
+/// The bytes an element or region of memory occupies within the buffer of the region it
+/// shares, as laid out by [`Program::memory_layout`], so that regions which share memory by
+/// `SHARING` are seen to overlap.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Span {
+    root: String,
+    start: u64,
+    end: u64,
+}
+
+impl Span {
+    fn overlaps(&self, other: &Span) -> bool {
+        self.root == other.root && self.start < other.end && other.start < self.end
+    }
+}
+
+/// What is known of an element of memory.
+#[derive(Clone, Debug)]
+struct Known {
+    value: Value,
+    /// The element the value was written to, by which it is read back and written to memory.
+    reference: MemoryReference,
+    data_type: ScalarType,
+    /// The offset in bytes just past the end of the element.
+    end: u64,
+    /// Whether the value is yet to be written to memory by the flattened program, having been
+    /// computed by instructions which were evaluated and not kept.
+    pending: bool,
+}
+
+/// Replaces each memory reference in an expression with the value known to be held there.
+struct KnownMemorySubstitution<'a, 'p> {
+    flattener: &'a Flattener<'p>,
+    substituted: bool,
+}
+
+impl InstructionRewriter for KnownMemorySubstitution<'_, '_> {
+    fn rewrite_expression(&mut self, expression: &mut Expression) {
+        if let Expression::Address(reference) = expression {
+            if let Some(value) = self.flattener.value(reference) {
+                *expression = Expression::Number(real!(value.as_real()));
+                self.substituted = true;
+            }
+            return;
+        }
+        walk_expression_mut(self, expression)
+    }
+}
+
+struct Flattener<'p> {
+    program: &'p Program,
+    layout: MemoryLayout,
+    /// What is known of memory, keyed on the root region and byte offset of each element. No two
+    /// elements known overlap.
+    known: BTreeMap<(String, u64), Known>,
+    output: Vec<Instruction>,
+}
+
+impl Flattener<'_> {
+    fn data_type(&self, region: &str) -> Option<ScalarType> {
+        self.program
+            .memory_regions
+            .get(region)
+            .map(|region| region.size.data_type.clone())
+    }
+
+    /// The bytes `reference` occupies, if its region is declared and it lies within it.
+    fn span(&self, reference: &MemoryReference) -> Option<Span> {
+        let region = self.layout.get(reference.name.as_str())?;
+        if reference.index >= region.length {
+            return None;
+        }
+        let size = element_size(&region.data_type);
+        let start = region.byte_offset + reference.index * size;
+        Some(Span {
+            root: region.root.clone(),
+            start,
+            end: start + size,
+        })
+    }
+
+    /// The bytes `location` occupies: those of its element, or of its whole region.
+    fn location_span(&self, location: &MemoryLocation) -> Option<Span> {
+        match location.index {
+            Some(index) => self.span(&MemoryReference {
+                name: location.region.as_str().into(),
+                index,
+            }),
+            None => self.layout.get(&location.region).map(|region| Span {
+                root: region.root.clone(),
+                start: region.byte_offset,
+                end: region.byte_end(),
+            }),
+        }
+    }
+
+    /// The value known to be held in `reference`. A value written through a region of another
+    /// type, or over other bytes, is not known to be held there.
+    fn value(&self, reference: &MemoryReference) -> Option<Value> {
+        let span = self.span(reference)?;
+        let known = self.known.get(&(span.root, span.start))?;
+        let data_type = self.data_type(reference.name.as_str())?;
+        (known.end == span.end && known.data_type == data_type).then_some(known.value)
+    }
+
+    fn arithmetic_operand(&self, operand: &ArithmeticOperand) -> Option<Value> {
+        match operand {
+            ArithmeticOperand::LiteralInteger(value) => Some(Value::Integer(*value)),
+            ArithmeticOperand::LiteralReal(value) => Some(Value::Real(*value)),
+            ArithmeticOperand::MemoryReference(reference) => self.value(reference),
+        }
+    }
+
+    /// The value as it would be stored in `region`, if it can be without being truncated.
+    fn stored_as(&self, region: &str, value: Value) -> Option<Value> {
+        match (self.data_type(region)?, value) {
+            (ScalarType::Real, value) => Some(Value::Real(value.as_real())),
+            (_, Value::Integer(value)) => Some(Value::Integer(value)),
+            (_, Value::Real(_)) => None,
+        }
+    }
+
+    /// The values `instruction` writes to memory, if it is classical and all it reads is known.
+    fn evaluate(&self, instruction: &Instruction) -> Option<Vec<(MemoryReference, Value)>> {
+        let write = |reference: &MemoryReference, value: Value| {
+            self.span(reference)?;
+            Some(vec![(
+                reference.clone(),
+                self.stored_as(reference.name.as_str(), value)?,
+            )])
+        };
+        match instruction {
+            Instruction::Move(Move {
+                destination,
+                source,
+            }) => write(destination, self.arithmetic_operand(source)?),
+            Instruction::Arithmetic(Arithmetic {
+                operator,
+                destination,
+                source,
+            }) => {
+                let left = self.value(destination)?;
+                let right = self.arithmetic_operand(source)?;
+                let value = match (left, right) {
+                    (Value::Integer(left), Value::Integer(right)) => {
+                        Value::Integer(match operator {
+                            ArithmeticOperator::Add => left.checked_add(right)?,
+                            ArithmeticOperator::Subtract => left.checked_sub(right)?,
+                            ArithmeticOperator::Multiply => left.checked_mul(right)?,
+                            ArithmeticOperator::Divide => left.checked_div(right)?,
+                        })
+                    }
+                    (left, right) => {
+                        let (left, right) = (left.as_real(), right.as_real());
+                        Value::Real(match operator {
+                            ArithmeticOperator::Add => left + right,
+                            ArithmeticOperator::Subtract => left - right,
+                            ArithmeticOperator::Multiply => left * right,
+                            ArithmeticOperator::Divide => left / right,
+                        })
+                    }
+                };
+                write(destination, value)
+            }
+            Instruction::BinaryLogic(BinaryLogic {
+                operator,
+                operands: (destination, source),
+            }) => {
+                let Value::Integer(left) = self.value(destination)? else {
+                    return None;
+                };
+                let right = match source {
+                    BinaryOperand::LiteralInteger(value) => *value,
+                    BinaryOperand::MemoryReference(reference) => match self.value(reference)? {
+                        Value::Integer(value) => value,
+                        Value::Real(_) => return None,
+                    },
+                };
+                let value = match operator {
+                    BinaryOperator::And => left & right,
+                    BinaryOperator::Ior => left | right,
+                    BinaryOperator::Xor => left ^ right,
+                };
+                write(destination, Value::Integer(value))
+            }
+            Instruction::UnaryLogic(UnaryLogic { operator, operand }) => {
+                let value = match (operator, self.value(operand)?) {
+                    (UnaryOperator::Neg, Value::Integer(value)) => {
+                        Value::Integer(value.checked_neg()?)
+                    }
+                    (UnaryOperator::Neg, Value::Real(value)) => Value::Real(-value),
+                    (UnaryOperator::Not, Value::Integer(value)) => {
+                        match self.data_type(operand.name.as_str())? {
+                            ScalarType::Bit => Value::Integer((value == 0).into()),
+                            _ => Value::Integer(!value),
+                        }
+                    }
+                    (UnaryOperator::Not, Value::Real(_)) => return None,
+                };
+                write(operand, value)
+            }
+            Instruction::Comparison(Comparison {
+                operator,
+                operands: (destination, left, right),
+            }) => {
+                let left = self.value(left)?;
+                let right = match right {
+                    ComparisonOperand::LiteralInteger(value) => Value::Integer(*value),
+                    ComparisonOperand::LiteralReal(value) => Value::Real(*value),
+                    ComparisonOperand::MemoryReference(reference) => self.value(reference)?,
+                };
+                let ordering = match (left, right) {
+                    (Value::Integer(left), Value::Integer(right)) => left.cmp(&right),
+                    (left, right) => left.as_real().partial_cmp(&right.as_real())?,
+                };
+                let result = match operator {
+                    ComparisonOperator::Equal => ordering.is_eq(),
+                    ComparisonOperator::GreaterThanOrEqual => ordering.is_ge(),
+                    ComparisonOperator::GreaterThan => ordering.is_gt(),
+                    ComparisonOperator::LessThanOrEqual => ordering.is_le(),
+                    ComparisonOperator::LessThan => ordering.is_lt(),
+                };
+                write(destination, Value::Integer(result.into()))
+            }
+            Instruction::Convert(Convert { from, to }) => write(to, self.value(from)?),
+            Instruction::Exchange(Exchange { left, right }) => {
+                let (left_value, right_value) = (self.value(left)?, self.value(right)?);
+                let mut writes = write(left, right_value)?;
+                writes.extend(write(right, left_value)?);
+                Some(writes)
+            }
+            Instruction::Load(Load {
+                destination,
+                source,
+                offset,
+            }) => {
+                let Value::Integer(offset) = self.value(offset)? else {
+                    return None;
+                };
+                let source = MemoryReference {
+                    name: source.as_str().into(),
+                    index: offset.try_into().ok()?,
+                };
+                write(destination, self.value(&source)?)
+            }
+            Instruction::Store(Store {
+                destination,
+                offset,
+                source,
+            }) => {
+                let Value::Integer(offset) = self.value(offset)? else {
+                    return None;
+                };
+                let destination = MemoryReference {
+                    name: destination.as_str().into(),
+                    index: offset.try_into().ok()?,
+                };
+                write(&destination, self.arithmetic_operand(source)?)
+            }
+            _ => None,
+        }
+    }
+
+    /// Write any pending value held in the bytes of `span` to memory.
+    fn materialize(&mut self, span: &Span) {
+        for (&(ref root, start), known) in self.known.iter_mut() {
+            let known_span = Span {
+                root: root.clone(),
+                start,
+                end: known.end,
+            };
+            if known.pending && known_span.overlaps(span) {
+                known.pending = false;
+                self.output.push(Instruction::Move(Move {
+                    destination: known.reference.clone(),
+                    source: known.value.to_operand(),
+                }));
+            }
+        }
+    }
+
+    /// Forget what is known of the bytes of `span`, which are to be written. Values which are
+    /// only partly overwritten are first written to memory, so that the rest of their bytes
+    /// hold what they should; unless `whole` is set, no value is taken to be wholly overwritten.
+    fn forget(&mut self, span: &Span, whole: bool) {
+        let overlapping: Vec<(String, u64)> = self
+            .known
+            .iter()
+            .filter(|(&(ref root, start), known)| {
+                span.overlaps(&Span {
+                    root: root.clone(),
+                    start,
+                    end: known.end,
+                })
+            })
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in overlapping {
+            let Some(known) = self.known.remove(&key) else {
+                continue;
+            };
+            if known.pending && !(whole && key.1 == span.start && known.end == span.end) {
+                self.output.push(Instruction::Move(Move {
+                    destination: known.reference,
+                    source: known.value.to_operand(),
+                }));
+            }
+        }
+    }
+
+    /// Record that `value`, as found by [`Flattener::evaluate`], is held in `reference`.
+    fn assign(&mut self, reference: MemoryReference, value: Value) {
+        let (Some(span), Some(data_type)) = (
+            self.span(&reference),
+            self.data_type(reference.name.as_str()),
+        ) else {
+            return;
+        };
+        self.forget(&span, true);
+        self.known.insert(
+            (span.root, span.start),
+            Known {
+                value,
+                reference,
+                data_type,
+                end: span.end,
+                pending: true,
+            },
+        );
+    }
+
+    /// Keep `instruction`, substituting the memory it reads which is known, first writing that
+    /// which it still reads, and forgetting what it writes.
+    fn emit(&mut self, mut instruction: Instruction) {
+        let mut substitution = KnownMemorySubstitution {
+            flattener: self,
+            substituted: false,
+        };
+        substitution.rewrite_instruction(&mut instruction);
+        if substitution.substituted {
+            instruction.apply_to_expressions(Expression::simplify);
+        }
+
+        let effects = memory_effects(&instruction);
+        for location in &effects.reads {
+            if let Some(span) = self.location_span(location) {
+                self.materialize(&span);
+            }
+        }
+        for location in &effects.writes {
+            if let Some(span) = self.location_span(location) {
+                // An instruction which writes some element of a region, not known which, leaves
+                // the rest as they were.
+                self.forget(&span, location.index.is_some());
+            }
+        }
+        self.output.push(instruction);
+    }
+}
+
+impl Program {
+    /// Flatten the program's classical control flow into a straight line of instructions.
+    ///
+    /// The program is run from its first instruction with nothing known of its memory. Classical
+    /// instructions whose operands are known are evaluated, and not kept; each `JUMP` is
+    /// followed, each `JUMP-WHEN` and `JUMP-UNLESS` is decided by the value of its condition,
+    /// and `LABEL` and `HALT` are dropped. Every other instruction, including a classical one
+    /// which reads memory that is not known, is kept in the order it runs, with known values
+    /// substituted into its parameters. A known value which a kept instruction reads is first
+    /// written to memory with `MOVE`. Memory is tracked by where it lies in the buffers of the
+    /// [`memory_layout`](Program::memory_layout), so that a write to a region is seen by those
+    /// which share its memory.
+    ///
+    /// The program cannot be flattened where its memory cannot be laid out, where a condition
+    /// depends on memory which is not known before the program runs, such as a measurement
+    /// result, or where it runs for more than [`FlattenOptions::max_steps`] instructions.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::flatten::FlattenOptions;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = r#"DECLARE i INTEGER
+    /// DECLARE done BIT
+    /// MOVE i[0] 0
+    /// LABEL @loop
+    /// H 0
+    /// ADD i[0] 1
+    /// EQ done[0] i[0] 2
+    /// JUMP-UNLESS @loop done[0]
+    /// "#
+    /// .parse()
+    /// .unwrap();
+    /// let options = FlattenOptions {
+    ///     preserve_memory: false,
+    ///     ..FlattenOptions::default()
+    /// };
+    /// let flattened = program.flatten(&options).unwrap();
+    /// assert_eq!(flattened.to_string(false), "H 0\nH 0\n");
+    /// ```
+    pub fn flatten(&self, options: &FlattenOptions) -> Result<Program, FlattenError> {
+        self.to_cfg()?;
+        let labels: HashMap<_, _> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::Label(label) => Some((label, index)),
+                _ => None,
+            })
+            .collect();
+
+        let mut flattener = Flattener {
+            program: self,
+            layout: self.memory_layout()?,
+            known: BTreeMap::new(),
+            output: vec![],
+        };
+        let mut index = 0;
+        let mut steps = 0;
+        while let Some(instruction) = self.instructions.get(index) {
+            steps += 1;
+            if steps > options.max_steps {
+                return Err(FlattenError::StepLimitExceeded {
+                    limit: options.max_steps,
+                });
+            }
+            let (target, jump) = match instruction {
+                Instruction::Label(_) => (None, false),
+                Instruction::Halt => break,
+                Instruction::Jump(Jump { target }) => (Some(target), true),
+                Instruction::JumpWhen(JumpWhen { target, condition })
+                | Instruction::JumpUnless(JumpUnless { target, condition }) => {
+                    let value = flattener.value(condition).ok_or_else(|| {
+                        FlattenError::UnknownCondition {
+                            index,
+                            instruction: Box::new(instruction.clone()),
+                        }
+                    })?;
+                    let jump_if_nonzero = matches!(instruction, Instruction::JumpWhen(_));
+                    (Some(target), value.is_nonzero() == jump_if_nonzero)
+                }
+                _ => {
+                    match flattener.evaluate(instruction) {
+                        Some(writes) => {
+                            for (reference, value) in writes {
+                                flattener.assign(reference, value);
+                            }
+                        }
+                        None => flattener.emit(instruction.clone()),
+                    }
+                    (None, false)
+                }
+            };
+            index = match target {
+                // Every label jumped to is defined, as checked in building the graph above.
+                Some(target) if jump => labels[target],
+                _ => index + 1,
+            };
+        }
+
+        if options.preserve_memory {
+            for known in flattener.known.values().filter(|known| known.pending) {
+                flattener.output.push(Instruction::Move(Move {
+                    destination: known.reference.clone(),
+                    source: known.value.to_operand(),
+                }));
+            }
+        }
+
+        let mut program = self.clone();
+        program.clear_source();
+        program.instructions.clear();
+        for instruction in flattener.output {
+            program.add_instruction(instruction);
+        }
+        Ok(program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{FlattenError, FlattenOptions};
+    use crate::Program;
+
+    const COUNTED_LOOP: &str = r#"DECLARE i INTEGER
+DECLARE theta REAL
+DECLARE done BIT
+MOVE i 0
+MOVE theta 0.0
+LABEL @loop
+ADD theta 0.5
+RX(theta) 0
+ADD i 1
+LT done i 3
+JUMP-WHEN @loop done
+"#;
+
+    #[rstest]
+    #[case(
+        COUNTED_LOOP,
+        true,
+        "RX(0.5) 0\nRX(1) 0\nRX(1.5) 0\nMOVE done[0] 0\nMOVE i[0] 3\nMOVE theta[0] 1.5\n"
+    )]
+    #[case(COUNTED_LOOP, false, "RX(0.5) 0\nRX(1) 0\nRX(1.5) 0\n")]
+    #[case(
+        "DECLARE a INTEGER\nDECLARE b INTEGER\nDECLARE p INTEGER\nMOVE a 2\nMUL a 3\nMOVE b a\nSUB b 1\nADD b p\nHALT\nX 0",
+        false,
+        "MOVE b[0] 5\nADD b[0] p[0]\n"
+    )]
+    #[case(
+        "DECLARE ro BIT\nDECLARE x INTEGER\nMOVE x 1\nMEASURE 0 ro\nADD x ro\nRX(x) 0",
+        true,
+        "MEASURE 0 ro[0]\nMOVE x[0] 1\nADD x[0] ro[0]\nRX(x[0]) 0\n"
+    )]
+    #[case(
+        "DECLARE flag BIT\nMOVE flag 1\nJUMP-UNLESS @skip flag\nX 0\nLABEL @skip\nJUMP @end\nY 0\nLABEL @end",
+        false,
+        "X 0\n"
+    )]
+    #[case(
+        "DECLARE a INTEGER[1]\nDECLARE b INTEGER[1] SHARING a\nMOVE a[0] 0\nMOVE b[0] 1\nJUMP-WHEN @skip a[0]\nX 0\nLABEL @skip\nH 0",
+        true,
+        "H 0\nMOVE b[0] 1\n"
+    )]
+    #[case(
+        "DECLARE w INTEGER\nDECLARE o OCTET[8] SHARING w\nMOVE w 1\nMOVE o[1] 2\nRX(w) 0",
+        false,
+        "MOVE w[0] 1\nMOVE o[1] 2\nRX(w[0]) 0\n"
+    )]
+    fn test_flatten(#[case] input: &str, #[case] preserve_memory: bool, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let options = FlattenOptions {
+            preserve_memory,
+            ..FlattenOptions::default()
+        };
+        let flattened = program.flatten(&options).unwrap();
+        let body: String = flattened
+            .instructions
+            .iter()
+            .map(|instruction| format!("{instruction}\n"))
+            .collect();
+        assert_eq!(body, expected);
+        assert_eq!(flattened.memory_regions, program.memory_regions);
+    }
+
+    #[test]
+    fn test_flatten_unknown_condition() {
+        let program =
+            Program::from_str("DECLARE ro BIT\nMEASURE 0 ro\nJUMP-WHEN @end ro\nX 0\nLABEL @end")
+                .unwrap();
+        let error = program.flatten(&FlattenOptions::default()).unwrap_err();
+        assert!(matches!(
+            error,
+            FlattenError::UnknownCondition { index: 1, .. }
+        ));
+    }
+
+    #[test]
+    fn test_flatten_step_limit() {
+        let program = Program::from_str("LABEL @loop\nX 0\nJUMP @loop").unwrap();
+        let options = FlattenOptions {
+            max_steps: 10,
+            ..FlattenOptions::default()
+        };
+        let error = program.flatten(&options).unwrap_err();
+        assert!(matches!(
+            error,
+            FlattenError::StepLimitExceeded { limit: 10 }
+        ));
+    }
+
+    #[test]
+    fn test_flatten_undefined_label() {
+        let program = Program::from_str("JUMP @nowhere").unwrap();
+        let error = program.flatten(&FlattenOptions::default()).unwrap_err();
+        assert!(matches!(error, FlattenError::ControlFlow(_)));
+    }
+}
@@ -48,9 +48,9 @@ impl FrameSet {
 
         match condition {
             FrameMatchCondition::All => keys.collect(),
-            FrameMatchCondition::AnyOfNames(names) => {
-                keys.filter(|&f| names.contains(&f.name)).collect()
-            }
+            FrameMatchCondition::AnyOfNames(names) => keys
+                .filter(|&f| names.iter().any(|name| f.name == *name))
+                .collect(),
             FrameMatchCondition::AnyOfQubits(qubits) => {
                 let any_of_set: HashSet<_> = qubits.iter().collect();
 
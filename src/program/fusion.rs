@@ -0,0 +1,355 @@
+//! Merging and fusing runs of single-qubit gates.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+
+use indexmap::IndexMap;
+
+use crate::expression::{Expression, InfixOperator};
+use crate::instruction::unitary::Matrix;
+use crate::instruction::{Gate, GateModifier, Instruction, Qubit};
+use crate::Program;
+
+use super::dag::{resources, Resource};
+
+/// The tolerance within which angles are taken to be zero, and matrix elements to vanish.
+pub(super) const TOLERANCE: f64 = 1e-12;
+
+/// The standard gates which act on one qubit.
+const SINGLE_QUBIT_GATES: [&str; 11] =
+    ["I", "X", "Y", "Z", "H", "S", "T", "PHASE", "RX", "RY", "RZ"];
+
+/// The standard rotations, whose angles add when they are applied one after another.
+const ROTATIONS: [&str; 4] = ["PHASE", "RX", "RY", "RZ"];
+
+/// The value of `expression`, if it is a real constant.
+pub(super) fn real_value(expression: &Expression) -> Option<f64> {
+    expression
+        .evaluate(&HashMap::new(), &HashMap::new())
+        .ok()
+        .filter(|value| value.im.abs() < TOLERANCE)
+        .map(|value| value.re)
+}
+
+/// `angle` reduced to lie in `(-π, π]`.
+pub(super) fn normalize(angle: f64) -> f64 {
+    let reduced = angle.rem_euclid(2.0 * PI);
+    if reduced > PI {
+        reduced - 2.0 * PI
+    } else {
+        reduced
+    }
+}
+
+/// Whether `angle` is a constant multiple of 2π, so that its rotation only changes the global
+/// phase.
+fn is_full_turn(angle: &Expression) -> bool {
+    real_value(angle).is_some_and(|angle| normalize(angle).abs() < TOLERANCE)
+}
+
+/// Whether `gate` is a rotation with no modifiers.
+fn is_plain_rotation(gate: &Gate) -> bool {
+    gate.modifiers.is_empty() && ROTATIONS.contains(&gate.name.as_str())
+}
+
+/// `run` with each rotation about the same axis as the gate before it merged into that gate, its
+/// angle summed with that gate's, and identities and full turns removed.
+fn merge_rotations(run: Vec<Gate>) -> Vec<Gate> {
+    let mut merged: Vec<Gate> = vec![];
+    for gate in run {
+        if gate.modifiers.is_empty() && gate.name == "I" {
+            continue;
+        }
+        match merged.last_mut() {
+            Some(last)
+                if is_plain_rotation(&gate)
+                    && last.name == gate.name
+                    && is_plain_rotation(last) =>
+            {
+                let mut sum = Expression::Infix {
+                    left: Box::new(last.parameters[0].clone()),
+                    operator: InfixOperator::Plus,
+                    right: Box::new(gate.parameters[0].clone()),
+                };
+                sum.simplify();
+                last.parameters[0] = sum;
+            }
+            _ => merged.push(gate),
+        }
+        if merged
+            .last()
+            .is_some_and(|last| is_plain_rotation(last) && is_full_turn(&last.parameters[0]))
+        {
+            merged.pop();
+        }
+    }
+    merged
+}
+
+/// The fewest of `RZ`, `RY`, and `RZ`, applied in that order to `qubit`, which apply the same
+/// operation as `gates`, up to global phase, or `None` if any gate has a parameter which is not
+/// constant.
+fn euler_decomposition(gates: &[Gate], qubit: &Qubit) -> Option<Vec<Gate>> {
+    // The product of the gates' matrices, each as it acts on its qubit alone.
+    let mut unitary: Option<Matrix> = None;
+    for gate in gates {
+        if gate
+            .parameters
+            .iter()
+            .any(|parameter| real_value(parameter).is_none())
+        {
+            return None;
+        }
+        let local = Gate {
+            qubits: vec![Qubit::Fixed(0)],
+            ..gate.clone()
+        };
+        let matrix = local.to_unitary(1).ok()?;
+        unitary = Some(match unitary {
+            Some(unitary) => &matrix * &unitary,
+            None => matrix,
+        });
+    }
+    let (beta, gamma, delta) = zyz_angles(&unitary?);
+
+    Some(
+        [("RZ", delta), ("RY", gamma), ("RZ", beta)]
+            .into_iter()
+            .map(|(name, angle)| (name, normalize(angle)))
+            .filter(|(_, angle)| angle.abs() >= TOLERANCE)
+            .map(|(name, angle)| match name {
+                "RY" => Gate::ry(angle, qubit.clone()),
+                _ => Gate::rz(angle, qubit.clone()),
+            })
+            .collect(),
+    )
+}
+
+/// The angles `(β, γ, δ)` for which `unitary`, the matrix of an operation on one qubit, is that of
+/// `RZ(β)·RY(γ)·RZ(δ)`, up to global phase.
+pub(super) fn zyz_angles(unitary: &Matrix) -> (f64, f64, f64) {
+    // Scaled to have determinant one, the matrix is ±RZ(β)·RY(γ)·RZ(δ), whose elements have
+    // magnitudes cos(γ/2) on the diagonal and sin(γ/2) off it, and whose lower elements have
+    // phases (β + δ)/2 and (β - δ)/2. The sign only changes β by 2π, and so the global phase.
+    let scale = (unitary[(0, 0)] * unitary[(1, 1)] - unitary[(0, 1)] * unitary[(1, 0)]).sqrt();
+    let (a, c, d) = (
+        unitary[(0, 0)] / scale,
+        unitary[(1, 0)] / scale,
+        unitary[(1, 1)] / scale,
+    );
+    let gamma = 2.0 * c.norm().atan2(a.norm());
+    let (beta, delta) = if c.norm() < TOLERANCE {
+        (2.0 * d.arg(), 0.0)
+    } else if a.norm() < TOLERANCE {
+        (2.0 * c.arg(), 0.0)
+    } else {
+        (d.arg() + c.arg(), d.arg() - c.arg())
+    };
+    (beta, gamma, delta)
+}
+
+/// The fewest gates found to apply the same operation as `run`, a sequence of single-qubit gates
+/// on `qubit`.
+fn fuse(run: Vec<Gate>, qubit: &Qubit) -> Vec<Gate> {
+    let merged = merge_rotations(run);
+    if merged.len() < 2 {
+        return merged;
+    }
+    match euler_decomposition(&merged, qubit) {
+        Some(decomposed) if decomposed.len() < merged.len() => decomposed,
+        _ => merged,
+    }
+}
+
+/// A run of single-qubit gates on one qubit, yet to be fused, and the memory they read.
+#[derive(Default)]
+struct Run {
+    gates: Vec<Gate>,
+    reads: HashSet<Resource>,
+}
+
+impl Program {
+    /// A copy of the program with each run of single-qubit gates on the same qubit replaced by the
+    /// fewest gates found to do the same, up to global phase.
+    ///
+    /// A run is broken by any other instruction which uses its qubit or changes memory its gates
+    /// read, and by any instruction ordered with respect to every other, such as a label or
+    /// jump; only the standard single-qubit gates, such as `H` or `RX`, take part. Within a run,
+    /// adjacent rotations about the same axis are merged by summing their angles, which may be
+    /// symbolic, and identities and rotations by whole turns are removed. If every parameter of
+    /// the run is then constant, the run is replaced by at most three rotations, `RZ`, `RY`, and
+    /// `RZ`, if that takes fewer gates. Each fused run is placed where the instruction which broke
+    /// it was, or at the end of the program.
+    ///
+    /// The copy does not record the text the program was parsed from.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE theta REAL
+    /// RZ(theta) 0
+    /// RZ(0.5) 0
+    /// H 1
+    /// H 1
+    /// X 2
+    /// Y 2
+    /// CNOT 0 2"
+    ///     .parse()
+    ///     .unwrap();
+    /// let fused = program.fuse_single_qubit_gates();
+    /// assert_eq!(
+    ///     fused.to_string(false),
+    ///     "RZ((theta[0]+0.5)) 0\nRZ(3.141592653589793) 2\nCNOT 0 2\n"
+    /// );
+    /// ```
+    pub fn fuse_single_qubit_gates(&self) -> Self {
+        let defined: HashSet<&str> = self
+            .instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::GateDefinition(definition) => Some(definition.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        let is_fusable = |gate: &Gate| {
+            gate.qubits.len() == 1
+                && SINGLE_QUBIT_GATES.contains(&gate.name.as_str())
+                && !defined.contains(gate.name.as_str())
+                && gate
+                    .modifiers
+                    .iter()
+                    .all(|modifier| *modifier == GateModifier::Dagger)
+        };
+
+        let mut instructions = vec![];
+        let mut runs: IndexMap<Qubit, Run> = IndexMap::new();
+        let flush = |instructions: &mut Vec<Instruction>, qubit: Qubit, run: Run| {
+            instructions.extend(fuse(run.gates, &qubit).into_iter().map(Instruction::Gate));
+        };
+
+        for instruction in &self.instructions {
+            let accesses = resources(instruction);
+            if let (Instruction::Gate(gate), Some((reads, _))) = (instruction, &accesses) {
+                if is_fusable(gate) {
+                    let run = runs.entry(gate.qubits[0].clone()).or_default();
+                    run.gates.push(gate.clone());
+                    run.reads.extend(reads.iter().cloned());
+                    continue;
+                }
+            }
+
+            match accesses {
+                None => {
+                    for (qubit, run) in runs.drain(..) {
+                        flush(&mut instructions, qubit, run);
+                    }
+                }
+                Some((reads, writes)) => {
+                    let broken: Vec<Qubit> = runs
+                        .iter()
+                        .filter(|(qubit, run)| {
+                            let qubit = Resource::Qubit((*qubit).clone());
+                            reads.contains(&qubit)
+                                || writes.contains(&qubit)
+                                || writes.iter().any(|write| run.reads.contains(write))
+                        })
+                        .map(|(qubit, _)| qubit.clone())
+                        .collect();
+                    for qubit in broken {
+                        let run = runs.shift_remove(&qubit).expect("the run was just found");
+                        flush(&mut instructions, qubit, run);
+                    }
+                }
+            }
+            instructions.push(instruction.clone());
+        }
+        for (qubit, run) in runs.drain(..) {
+            flush(&mut instructions, qubit, run);
+        }
+
+        let mut program = self.clone();
+        program.instructions = instructions;
+        program.clear_source();
+        program
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use crate::program::EquivalenceOptions;
+    use crate::Program;
+
+    #[rstest]
+    #[case("RZ(0.25) 0\nRZ(0.5) 0", "RZ(0.75) 0\n")]
+    #[case("RX(pi) 0\nRX(pi) 0", "")]
+    #[case("PHASE(pi) 0\nPHASE(pi) 0\nPHASE(0.5) 0", "PHASE(0.5) 0\n")]
+    #[case("RZ(0.5) 0\nRX(0.5) 1\nRZ(0.25) 0", "RZ(0.75) 0\nRX(0.5) 1\n")]
+    #[case("RX(0.5) 0\nRZ(0.5) 0", "RX(0.5) 0\nRZ(0.5) 0\n")]
+    #[case("H 0\nH 0", "")]
+    #[case("H 0\nI 0", "H 0\n")]
+    #[case("H 0\nCNOT 0 1\nH 0", "H 0\nCNOT 0 1\nH 0\n")]
+    #[case("H 0\nLABEL @a\nH 0", "H 0\nLABEL @a\nH 0\n")]
+    #[case(
+        "DECLARE theta REAL\nRZ(theta) 0\nRZ(theta) 0",
+        "RZ((theta[0]+theta[0])) 0\n"
+    )]
+    #[case(
+        "DECLARE theta REAL\nRZ(theta) 0\nMOVE theta 1.0\nRZ(theta) 0",
+        "RZ(theta[0]) 0\nMOVE theta[0] 1.0\nRZ(theta[0]) 0\n"
+    )]
+    #[case(
+        "DECLARE theta REAL\nRZ(theta) 0\nRX(1.0) 1\nMOVE theta 1.0",
+        "RZ(theta[0]) 0\nMOVE theta[0] 1.0\nRX(1) 1\n"
+    )]
+    #[case(
+        "DEFGATE H:\n    1, 0\n    0, 1\n\nH 0\nH 0",
+        "DEFGATE H AS MATRIX:\n\t1,0\n\t0,1\n\nH 0\nH 0\n"
+    )]
+    fn test_fuse_single_qubit_gates(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.fuse_single_qubit_gates().to_string(false), expected);
+    }
+
+    #[rstest]
+    #[case("X 0\nY 0", 1)]
+    #[case("H 0\nT 0\nH 0\nS 0\nDAGGER T 0", 3)]
+    #[case("RX(0.3) 0\nRY(0.7) 0\nRZ(1.1) 0\nRX(-0.2) 0\nH 0", 3)]
+    #[case("S 0\nS 0", 1)]
+    #[case("H 0\nS 0\nH 0\nCNOT 0 1\nT 1\nH 1\nT 1", 7)]
+    #[case("X 0\nX 0\nY 1\nY 1", 0)]
+    #[case("H 0\nZ 0\nH 0", 2)]
+    fn test_fusion_is_equivalent(#[case] input: &str, #[case] expected_gate_count: usize) {
+        let program = Program::from_str(input).unwrap();
+        let fused = program.fuse_single_qubit_gates();
+        assert_eq!(fused.instructions.len(), expected_gate_count);
+        let options = EquivalenceOptions {
+            unitary_qubit_limit: Some(2),
+            ..Default::default()
+        };
+        assert!(
+            fused.equivalent_to(&program, &options),
+            "{input} was fused into {}",
+            fused.to_string(true)
+        );
+    }
+}
@@ -21,8 +21,7 @@ use petgraph::graphmap::GraphMap;
 use petgraph::Directed;
 
 use crate::instruction::{
-    FrameIdentifier, Instruction, Jump, JumpUnless, JumpWhen, Label, MeasureCalibrationDefinition,
-    MemoryReference,
+    ControlFlow, FrameIdentifier, Instruction, Label, MeasureCalibrationDefinition, MemoryReference,
 };
 use crate::{instruction::InstructionRole, program::Program};
 
@@ -456,6 +455,29 @@ pub enum BlockTerminator {
     Halt,
 }
 
+/// The terminator of a block ended by an instruction with the given control flow, which ends a
+/// block only if it jumps or halts.
+impl From<ControlFlow<'_>> for BlockTerminator {
+    fn from(control_flow: ControlFlow) -> Self {
+        match control_flow {
+            ControlFlow::FallThrough | ControlFlow::Block => BlockTerminator::Continue,
+            ControlFlow::Halt => BlockTerminator::Halt,
+            ControlFlow::Jump { target } => BlockTerminator::Unconditional {
+                target: target.to_string(),
+            },
+            ControlFlow::ConditionalJump {
+                target,
+                condition,
+                jump_if_condition_true,
+            } => BlockTerminator::Conditional {
+                condition: condition.clone(),
+                target: target.to_string(),
+                jump_if_condition_true,
+            },
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ScheduledProgram {
     /// All blocks within the ScheduledProgram, keyed on string label.
@@ -490,7 +512,7 @@ fn terminate_working_block(
     match blocks.insert(label.clone(), block) {
         Some(_) => Err(ScheduleError {
             instruction_index,
-            instruction: Instruction::Label(Label(label)),
+            instruction: Instruction::Label(Label::Fixed(label.into())),
             variant: ScheduleErrorVariant::DuplicateLabel,
         }),
         None => Ok(()),
@@ -516,6 +538,7 @@ impl ScheduledProgram {
             let instruction_index = Some(index);
             match instruction {
                 Instruction::Arithmetic(_)
+                | Instruction::Call(_)
                 | Instruction::Comparison(_)
                 | Instruction::BinaryLogic(_)
                 | Instruction::Convert(_)
@@ -526,6 +549,7 @@ impl ScheduledProgram {
                 | Instruction::Include(_)
                 | Instruction::Move(_)
                 | Instruction::Nop
+                | Instruction::Wait
                 | Instruction::Exchange(_)
                 | Instruction::Load(_)
                 | Instruction::Store(_)
@@ -559,7 +583,7 @@ impl ScheduledProgram {
                     working_instructions.push(instruction);
                     Ok(())
                 }
-                Instruction::Label(Label(value)) => {
+                Instruction::Label(label) => {
                     terminate_working_block(
                         None as Option<BlockTerminator>,
                         &mut working_instructions,
@@ -569,53 +593,14 @@ impl ScheduledProgram {
                         instruction_index,
                     )?;
 
-                    working_label = Some(value.clone());
+                    working_label = Some(label.to_string());
                     Ok(())
                 }
-                Instruction::Jump(Jump { target }) => {
-                    terminate_working_block(
-                        Some(BlockTerminator::Unconditional {
-                            target: target.clone(),
-                        }),
-                        &mut working_instructions,
-                        &mut blocks,
-                        &mut working_label,
-                        program,
-                        instruction_index,
-                    )?;
-                    Ok(())
-                }
-                Instruction::JumpWhen(JumpWhen { target, condition }) => {
-                    terminate_working_block(
-                        Some(BlockTerminator::Conditional {
-                            target: target.clone(),
-                            condition: condition.clone(),
-                            jump_if_condition_true: true,
-                        }),
-                        &mut working_instructions,
-                        &mut blocks,
-                        &mut working_label,
-                        program,
-                        instruction_index,
-                    )?;
-                    Ok(())
-                }
-                Instruction::JumpUnless(JumpUnless { target, condition }) => {
-                    terminate_working_block(
-                        Some(BlockTerminator::Conditional {
-                            target: target.clone(),
-                            condition: condition.clone(),
-                            jump_if_condition_true: false,
-                        }),
-                        &mut working_instructions,
-                        &mut blocks,
-                        &mut working_label,
-                        program,
-                        instruction_index,
-                    )
-                }
-                Instruction::Halt => terminate_working_block(
-                    Some(BlockTerminator::Halt),
+                Instruction::Halt
+                | Instruction::Jump(_)
+                | Instruction::JumpUnless(_)
+                | Instruction::JumpWhen(_) => terminate_working_block(
+                    Some(BlockTerminator::from(instruction.control_flow())),
                     &mut working_instructions,
                     &mut blocks,
                     &mut working_label,
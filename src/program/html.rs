@@ -0,0 +1,210 @@
+//! Draw circuit diagrams as HTML tables, for web pages which typeset math with MathJax rather
+//! than running TikZ.
+//!
+//! The diagram is laid out exactly as for LaTeX (see [`crate::program::latex`]), with gate names
+//! and parameters written as LaTeX math between `\(` and `\)` delimiters. Each row of the diagram
+//! is drawn as a table with a row for each wire. Annotations, gate groups, and classical wires are
+//! only drawn by the LaTeX renderer.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+use crate::program::latex::{Command, Diagram, Latex, LatexGenError, RenderSettings, Renderer};
+use crate::Program;
+
+/// The stylesheet drawn with each diagram. Wires are drawn as a line through the middle of each
+/// cell, and the vertical lines of controlled gates through the cells marked `link`, or from the
+/// middle of those marked `link-up` or `link-down`.
+const STYLE: &str = "<style>
+.quil-circuit table { border-collapse: collapse; margin-bottom: 1em; }
+.quil-circuit td, .quil-circuit th { padding: 0 0.5em; height: 2.5em; text-align: center; }
+.quil-circuit td { background: linear-gradient(black, black) center / 100% 1px no-repeat; }
+.quil-circuit td.link { background: linear-gradient(black, black) center / 100% 1px no-repeat, linear-gradient(black, black) center / 1px 100% no-repeat; }
+.quil-circuit td.link-up { background: linear-gradient(black, black) center / 100% 1px no-repeat, linear-gradient(black, black) top / 1px 50% no-repeat; }
+.quil-circuit td.link-down { background: linear-gradient(black, black) center / 100% 1px no-repeat, linear-gradient(black, black) bottom / 1px 50% no-repeat; }
+.quil-circuit td.gate span, .quil-circuit td.meter span { display: inline-block; padding: 0.25em 0.5em; border: 1px solid black; background: white; }
+.quil-circuit td.gate[rowspan] span { display: flex; align-items: center; justify-content: center; height: 100%; box-sizing: border-box; }
+</style>";
+
+/// Draws a diagram as HTML tables, with math written for MathJax.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HtmlRenderer;
+
+/// Escape `text` for use as the content of an HTML element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Write `latex` as inline MathJax math.
+fn math(latex: &str) -> String {
+    format!(r"\({}\)", escape(latex))
+}
+
+/// The ket drawn as the label of the wire of `qubit`.
+fn label(qubit: &Qubit) -> String {
+    match qubit {
+        Qubit::Fixed(index) => math(&format!(r"|q_{{{index}}}\rangle")),
+        Qubit::Variable(name) => math(&format!(r"|\text{{{name}}}\rangle")),
+    }
+}
+
+impl HtmlRenderer {
+    /// Write the table drawing the columns `columns` of every wire of `diagram`.
+    fn render_row(&self, html: &mut String, diagram: &Diagram, columns: Range<u32>) {
+        let wires: Vec<_> = diagram.wires().collect();
+        let commands: Vec<Vec<Command>> = columns
+            .map(|column| wires.iter().map(|wire| wire.get_command(column)).collect())
+            .collect();
+
+        // For each column, the cells covered by a box on a wire above them, which are not drawn,
+        // and the class of the cells a vertical line passes through or ends at.
+        let mut covered = vec![vec![false; wires.len()]; commands.len()];
+        let mut linked = vec![vec![""; wires.len()]; commands.len()];
+        for (column, commands) in commands.iter().enumerate() {
+            for (row, command) in commands.iter().enumerate() {
+                match command {
+                    Command::WideGate { wires, .. } => {
+                        for covered in covered[column].iter_mut().skip(row + 1).take(wires - 1) {
+                            *covered = true;
+                        }
+                    }
+                    Command::Ctrl(offset) => {
+                        let target = (row as i64 + offset) as usize;
+                        let (top, bottom) = (row.min(target), row.max(target));
+                        for linked in linked[column].iter_mut().take(bottom).skip(top + 1) {
+                            *linked = "link";
+                        }
+                        let cells = &mut linked[column];
+                        cells[top] = if cells[top].is_empty() {
+                            "link-down"
+                        } else {
+                            "link"
+                        };
+                        cells[bottom] = if cells[bottom].is_empty() {
+                            "link-up"
+                        } else {
+                            "link"
+                        };
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let _ = writeln!(html, "<table>");
+        for (row, wire) in wires.iter().enumerate() {
+            let _ = write!(html, "<tr><th>{}</th>", label(wire.qubit()));
+            for (column, commands) in commands.iter().enumerate() {
+                if covered[column][row] {
+                    continue;
+                }
+                let class = match linked[column][row] {
+                    "" => String::new(),
+                    linked => format!(r#" class="{linked}""#),
+                };
+                let _ = match &commands[row] {
+                    Command::Gate(text) | Command::StyledGate(text, _) => {
+                        write!(html, r#"<td class="gate"><span>{}</span></td>"#, math(text))
+                    }
+                    Command::WideGate { wires, name, .. } => write!(
+                        html,
+                        r#"<td class="gate" rowspan="{wires}"><span>{}</span></td>"#,
+                        math(name)
+                    ),
+                    Command::Phase(text) => write!(
+                        html,
+                        "<td{class}>{}<br>{}</td>",
+                        math(r"\bullet"),
+                        math(text)
+                    ),
+                    Command::Meter => write!(
+                        html,
+                        r#"<td class="meter"><span>{}</span></td>"#,
+                        math(r"\nearrow")
+                    ),
+                    Command::Ctrl(_) => write!(html, "<td{class}>{}</td>", math(r"\bullet")),
+                    Command::Targ => write!(html, "<td{class}>{}</td>", math(r"\oplus")),
+                    _ => write!(html, "<td{class}></td>"),
+                };
+            }
+            let _ = writeln!(html, "<td></td></tr>");
+        }
+        let _ = writeln!(html, "</table>");
+    }
+}
+
+impl Renderer for HtmlRenderer {
+    fn render(&self, diagram: &Diagram) -> String {
+        let mut html = format!("<div class=\"quil-circuit\">\n{STYLE}\n");
+        for columns in diagram.rows() {
+            self.render_row(&mut html, diagram, columns);
+        }
+        html.push_str("</div>\n");
+        html
+    }
+}
+
+impl Program {
+    /// Draw the gates and measurements of the program as HTML tables, laid out as by
+    /// [`Latex::to_latex`] with `settings`, for a page which typesets the math in each cell with
+    /// MathJax. Each wire is labelled with its qubit.
+    pub fn to_html(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        let layout = self.to_latex_layout(settings)?;
+        Ok(HtmlRenderer.render(layout.diagram()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::escape;
+    use crate::program::latex::{CircuitDisplay, RenderSettings};
+    use crate::Program;
+
+    fn get_html(input: &str, settings: RenderSettings) -> String {
+        Program::from_str(input).unwrap().to_html(settings).unwrap()
+    }
+
+    #[test]
+    fn test_html() {
+        insta::assert_snapshot!(get_html(
+            "H 0\nCNOT 0 2\nRX(pi/2) 1\nMEASURE 2",
+            RenderSettings::default()
+        ));
+    }
+
+    #[test]
+    fn test_html_boxes_and_rows() {
+        insta::assert_snapshot!(get_html(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nX 1\nH 0",
+            RenderSettings {
+                circuit_display: CircuitDisplay::Box,
+                max_columns_per_row: Some(2),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("a<b & c>d"), "a&lt;b &amp; c&gt;d");
+    }
+}
@@ -0,0 +1,260 @@
+//! Resolving `INCLUDE` directives, which insert the instructions of another file into a program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::str::FromStr;
+
+use crate::instruction::{Include, Instruction};
+
+use super::{Program, ProgramError, Result};
+
+/// Finds the Quil text of the files named by `INCLUDE` directives.
+pub trait IncludeResolver {
+    /// Return the text of the file `filename`, as written in the directive, or a message
+    /// explaining why it could not be read.
+    fn resolve(&self, filename: &str) -> std::result::Result<String, String>;
+}
+
+/// Reads included files from the file system, relative to a root directory. Only files within
+/// the root may be read: a filename which is absolute, or which reaches out of the root through
+/// `..` or a symbolic link, is not resolved.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FileSystemResolver {
+    /// The directory relative filenames are read from. If empty, they are read from the working
+    /// directory.
+    pub root: PathBuf,
+}
+
+impl FileSystemResolver {
+    /// A resolver which reads files relative to `root`.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl IncludeResolver for FileSystemResolver {
+    fn resolve(&self, filename: &str) -> std::result::Result<String, String> {
+        let outside_root = || format!("{filename} is not within the include root");
+        let relative = Path::new(filename);
+        if !relative
+            .components()
+            .all(|component| matches!(component, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(outside_root());
+        }
+
+        let root = if self.root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            self.root.as_path()
+        };
+        let root = root.canonicalize().map_err(|error| error.to_string())?;
+        let path = root
+            .join(relative)
+            .canonicalize()
+            .map_err(|error| error.to_string())?;
+        // A symbolic link within the root may still lead out of it.
+        if !path.starts_with(&root) {
+            return Err(outside_root());
+        }
+        std::fs::read_to_string(path).map_err(|error| error.to_string())
+    }
+}
+
+/// Resolves filenames to the text they are keyed on, for programs which are not read from the
+/// file system, such as in tests or embedded use.
+impl IncludeResolver for HashMap<String, String> {
+    fn resolve(&self, filename: &str) -> std::result::Result<String, String> {
+        self.get(filename)
+            .cloned()
+            .ok_or_else(|| "no such file".to_string())
+    }
+}
+
+impl Program {
+    /// Parse `input` like [`Program::from_str`], replacing each `INCLUDE` directive with the
+    /// instructions of the file it names, as found by `resolver`. Included files may include
+    /// other files in turn, but not themselves, directly or indirectly.
+    #[allow(clippy::result_large_err)]
+    pub fn parse_with_includes(input: &str, resolver: &impl IncludeResolver) -> Result<Self> {
+        let mut instructions = vec![];
+        include_into(input, resolver, &mut vec![], &mut instructions)?;
+        Ok(Self::from(instructions))
+    }
+}
+
+/// Parse `input` and push its instructions onto `instructions`, replacing `INCLUDE` directives
+/// with the files they name. `including` holds the files being included, innermost last.
+#[allow(clippy::result_large_err)]
+fn include_into(
+    input: &str,
+    resolver: &impl IncludeResolver,
+    including: &mut Vec<String>,
+    instructions: &mut Vec<Instruction>,
+) -> std::result::Result<(), ProgramError<Program>> {
+    let program = Program::from_str(input)?;
+    for instruction in program.to_instructions(true) {
+        match instruction {
+            Instruction::Include(Include { filename }) => {
+                if including.contains(&filename) {
+                    return Err(ProgramError::IncludeCycle(filename));
+                }
+                let text = resolver.resolve(&filename).map_err(|message| {
+                    ProgramError::UnresolvedInclude {
+                        filename: filename.clone(),
+                        message,
+                    }
+                })?;
+                including.push(filename);
+                include_into(&text, resolver, including, instructions)?;
+                including.pop();
+            }
+            other => instructions.push(other),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::FileSystemResolver;
+    use crate::program::ProgramError;
+    use crate::Program;
+
+    fn files(files: &[(&str, &str)]) -> HashMap<String, String> {
+        files
+            .iter()
+            .map(|(name, text)| (name.to_string(), text.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_with_includes() {
+        let resolver = files(&[
+            (
+                "gates.quil",
+                "DEFGATE ISWAP:\n    1, 0, 0, 0\n    0, 0, i, 0\n    0, i, 0, 0\n    0, 0, 0, 1\n\nINCLUDE \"memory.quil\"\nH 0\n",
+            ),
+            ("memory.quil", "DECLARE ro BIT[2]\n"),
+        ]);
+        let program = Program::parse_with_includes(
+            "X 1\nINCLUDE \"gates.quil\"\nISWAP 0 1\nMEASURE 0 ro[0]",
+            &resolver,
+        )
+        .unwrap();
+        let expected: Program = "DECLARE ro BIT[2]\nX 1\nDEFGATE ISWAP:\n    1, 0, 0, 0\n    0, 0, i, 0\n    0, i, 0, 0\n    0, 0, 0, 1\n\nH 0\nISWAP 0 1\nMEASURE 0 ro[0]"
+            .parse()
+            .unwrap();
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn test_include_twice() {
+        let resolver = files(&[("h.quil", "H 0\n")]);
+        let program =
+            Program::parse_with_includes("INCLUDE \"h.quil\"\nINCLUDE \"h.quil\"", &resolver)
+                .unwrap();
+        assert_eq!(program.to_string(true), "H 0\nH 0\n");
+    }
+
+    #[test]
+    fn test_include_cycle() {
+        let resolver = files(&[
+            ("a.quil", "H 0\nINCLUDE \"b.quil\"\n"),
+            ("b.quil", "INCLUDE \"a.quil\"\n"),
+        ]);
+        assert_eq!(
+            Program::parse_with_includes("INCLUDE \"a.quil\"", &resolver),
+            Err(ProgramError::IncludeCycle("a.quil".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_unresolved_include() {
+        assert_eq!(
+            Program::parse_with_includes("INCLUDE \"missing.quil\"", &files(&[])),
+            Err(ProgramError::UnresolvedInclude {
+                filename: "missing.quil".to_string(),
+                message: "no such file".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_included_syntax_error() {
+        let resolver = files(&[("bad.quil", "H(\n")]);
+        assert!(matches!(
+            Program::parse_with_includes("INCLUDE \"bad.quil\"", &resolver),
+            Err(ProgramError::Syntax(_))
+        ));
+    }
+
+    #[test]
+    fn test_file_system_resolver() {
+        let root = std::env::temp_dir().join(format!("quil-rs-include-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("bell.quil"), "H 0\nCNOT 0 1\n").unwrap();
+
+        let resolver = FileSystemResolver::new(&root);
+        let program = Program::parse_with_includes("INCLUDE \"bell.quil\"", &resolver);
+        let missing = Program::parse_with_includes("INCLUDE \"missing.quil\"", &resolver);
+        std::fs::remove_dir_all(&root).unwrap();
+
+        assert_eq!(program.unwrap().to_string(true), "H 0\nCNOT 0 1\n");
+        assert!(matches!(
+            missing,
+            Err(ProgramError::UnresolvedInclude { .. })
+        ));
+    }
+
+    #[test]
+    fn test_file_system_resolver_stays_within_root() {
+        let parent =
+            std::env::temp_dir().join(format!("quil-rs-include-root-{}", std::process::id()));
+        let root = parent.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(parent.join("secret.quil"), "H 0\n").unwrap();
+        #[cfg(unix)]
+        std::os::unix::fs::symlink(parent.join("secret.quil"), root.join("link.quil")).unwrap();
+
+        let resolver = FileSystemResolver::new(&root);
+        let absolute = parent.join("secret.quil").display().to_string();
+        let mut filenames = vec!["../secret.quil", absolute.as_str()];
+        if cfg!(unix) {
+            filenames.push("link.quil");
+        }
+        let errors: Vec<_> = filenames
+            .iter()
+            .map(|filename| {
+                Program::parse_with_includes(&format!("INCLUDE {filename:?}"), &resolver).err()
+            })
+            .collect();
+        std::fs::remove_dir_all(&parent).unwrap();
+
+        for (filename, error) in filenames.iter().zip(errors) {
+            assert_eq!(
+                error,
+                Some(ProgramError::UnresolvedInclude {
+                    filename: filename.to_string(),
+                    message: format!("{filename} is not within the include root"),
+                })
+            );
+        }
+    }
+}
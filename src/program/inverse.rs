@@ -0,0 +1,123 @@
+//! Inverting programs which only apply gates.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::instruction::{GateModifier, Instruction};
+
+use super::{Program, ProgramError, Result};
+
+impl Program {
+    /// The inverse of the program: its gates in reverse order, each replaced by its
+    /// [`dagger`](crate::instruction::Gate::dagger), or given a `DAGGER` modifier if the program
+    /// defines a gate of its name, whose inverse the standard one's may not be. Circuits are
+    /// expanded first. Declarations
+    /// and gate definitions are kept, before the gates, and pragmas and `NOP`s are reversed along
+    /// with the gates. Fails if the program does anything else, such as measure a qubit, which
+    /// cannot be undone.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "H 0\nCNOT 0 1\nRZ(0.5) 1\nS 1".parse().unwrap();
+    /// let inverse = program.dagger().unwrap();
+    /// assert_eq!(inverse.to_string(true), "DAGGER S 1\nRZ(-0.5) 1\nCNOT 0 1\nH 0\n");
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn dagger(&self) -> Result<Self> {
+        let expanded = self.expand_circuits()?;
+        let defined = expanded.defined_gate_names();
+
+        let mut instructions = vec![];
+        let mut reversed = vec![];
+        for instruction in &expanded.instructions {
+            match instruction {
+                Instruction::Gate(gate)
+                    if defined.contains(gate.name.as_str())
+                        && !gate.modifiers.contains(&GateModifier::Dagger) =>
+                {
+                    let mut inverse = gate.clone();
+                    inverse.modifiers.insert(0, GateModifier::Dagger);
+                    reversed.push(Instruction::Gate(inverse));
+                }
+                Instruction::Gate(gate) => reversed.push(Instruction::Gate(gate.dagger())),
+                Instruction::Nop | Instruction::Pragma(_) => reversed.push(instruction.clone()),
+                Instruction::Declaration(_) | Instruction::GateDefinition(_) => {
+                    instructions.push(instruction.clone())
+                }
+                other => return Err(ProgramError::NotInvertible(Box::new(other.clone()))),
+            }
+        }
+        instructions.extend(reversed.into_iter().rev());
+
+        let mut new_program = self.clone();
+        new_program.instructions = instructions;
+        new_program.clear_source();
+        Ok(new_program)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use crate::instruction::unitary::Matrix;
+    use crate::program::ProgramError;
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0\nCNOT 0 1\nRZ(0.3) 1\nCCNOT 1 0 2\nT 2\nISWAP 2 0\n")]
+    #[case("DEFGATE SQRTX:\n    0.5+0.5i, 0.5-0.5i\n    0.5-0.5i, 0.5+0.5i\n\nSQRTX 1\nCONTROLLED SQRTX 1 2\nDAGGER SQRTX 0\n")]
+    #[case("DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n    S b\n\nDECLARE ro BIT\nPRAGMA INITIAL_REWIRING \"NAIVE\"\nBELL 2 0\nNOP\n")]
+    #[case("DEFGATE H:\n    1, 0\n    0, i\n\nH 0\nCNOT 0 1\nDAGGER H 1\n")]
+    #[case(
+        "DEFGATE RX(%theta):\n    0, cis(%theta)\n    1, 0\n\nRX(0.3) 0\nCONTROLLED RX(0.5) 0 2\n"
+    )]
+    fn test_dagger_inverts(#[case] input: &str) {
+        let program: Program = input.parse().unwrap();
+        let inverse = program.dagger().unwrap();
+        let product = &inverse.to_unitary(3).unwrap() * &program.to_unitary(3).unwrap();
+        assert!(product.approx_eq(&Matrix::identity(8), 1e-10));
+        assert_eq!(
+            inverse.dagger().unwrap(),
+            program.expand_circuits().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_dagger_keeps_definitions() {
+        let program: Program =
+            "DECLARE ro BIT\nPRAGMA A\nX 0\nDEFGATE G:\n    0, 1\n    1, 0\n\nG 0\n"
+                .parse()
+                .unwrap();
+        assert_eq!(
+            program.dagger().unwrap().to_string(true),
+            "DECLARE ro BIT[1]\nDEFGATE G AS MATRIX:\n\t0,1\n\t1,0\n\nDAGGER G 0\nX 0\nPRAGMA A\n"
+        );
+    }
+
+    #[rstest]
+    #[case("DECLARE ro BIT\nH 0\nMEASURE 0 ro[0]")]
+    #[case("RESET")]
+    #[case("LABEL @start\nJUMP @start")]
+    fn test_not_invertible(#[case] input: &str) {
+        let program: Program = input.parse().unwrap();
+        assert!(matches!(
+            program.dagger(),
+            Err(ProgramError::NotInvertible(_))
+        ));
+    }
+}
@@ -0,0 +1,441 @@
+//! Describing the operations a quantum processor supports, and checking programs against them.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use crate::expression::Expression;
+use crate::instruction::{Gate, Instruction, Qubit};
+use crate::Program;
+
+use super::fusion::{normalize, real_value};
+use super::routing::Topology;
+
+/// The tolerance within which a constant parameter is taken to equal a supported value.
+const PARAMETER_TOLERANCE: f64 = 1e-9;
+
+/// The values a parameter of a [`SupportedGate`] may take.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterConstraint {
+    /// Any value, whether constant or computed from memory.
+    Any,
+    /// One of these constant angles, compared modulo 2π.
+    OneOf(Vec<f64>),
+}
+
+impl ParameterConstraint {
+    /// Whether `parameter` is one of the values the constraint allows.
+    pub fn allows(&self, parameter: &Expression) -> bool {
+        match self {
+            Self::Any => true,
+            Self::OneOf(values) => real_value(parameter).is_some_and(|parameter| {
+                values
+                    .iter()
+                    .any(|value| normalize(parameter - value).abs() < PARAMETER_TOLERANCE)
+            }),
+        }
+    }
+}
+
+/// A gate a processor supports on a qubit or pair of qubits, applied with no modifiers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SupportedGate {
+    pub name: String,
+    /// The values allowed for each of the gate's parameters, in order.
+    pub parameters: Vec<ParameterConstraint>,
+}
+
+impl SupportedGate {
+    /// The gate `name`, which takes no parameters.
+    pub fn new(name: &str) -> Self {
+        Self::with_parameters(name, vec![])
+    }
+
+    pub fn with_parameters(name: &str, parameters: Vec<ParameterConstraint>) -> Self {
+        Self {
+            name: name.to_string(),
+            parameters,
+        }
+    }
+
+    /// Whether `gate` applies this gate with no modifiers, whatever the values of its parameters.
+    fn matches_name(&self, gate: &Gate) -> bool {
+        gate.modifiers.is_empty()
+            && gate.name == self.name
+            && gate.parameters.len() == self.parameters.len()
+    }
+}
+
+/// The instruction set architecture of a quantum processor: its qubits, the pairs of them which
+/// are coupled, and the gates it supports on each.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct QuantumProcessor {
+    qubits: BTreeMap<u64, Vec<SupportedGate>>,
+    /// The gates supported on each coupled pair, keyed with the lesser qubit first.
+    edges: BTreeMap<(u64, u64), Vec<SupportedGate>>,
+}
+
+impl QuantumProcessor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The processor with the coupled pairs of `topology`, supporting the same `single_qubit`
+    /// gates on each of its qubits and `two_qubit` gates on each pair.
+    pub fn from_topology(
+        topology: &Topology,
+        single_qubit: &[SupportedGate],
+        two_qubit: &[SupportedGate],
+    ) -> Self {
+        let mut processor = Self::new();
+        for qubit in topology.qubits() {
+            processor.add_qubit(qubit, single_qubit.to_vec());
+        }
+        for (a, b) in topology.edges() {
+            processor.add_edge(a, b, two_qubit.to_vec());
+        }
+        processor
+    }
+
+    /// Add `qubit`, supporting `gates`, replacing the gates it supported if already added.
+    pub fn add_qubit(&mut self, qubit: u64, gates: Vec<SupportedGate>) {
+        self.qubits.insert(qubit, gates);
+    }
+
+    /// Couple qubits `a` and `b`, which are added if they have not been, supporting `gates`
+    /// applied to them in either order, and replacing the gates the pair supported if it was
+    /// already coupled.
+    pub fn add_edge(&mut self, a: u64, b: u64, gates: Vec<SupportedGate>) {
+        self.qubits.entry(a).or_default();
+        self.qubits.entry(b).or_default();
+        self.edges.insert((a.min(b), a.max(b)), gates);
+    }
+
+    /// The processor's qubits, in order.
+    pub fn qubits(&self) -> impl Iterator<Item = u64> + '_ {
+        self.qubits.keys().copied()
+    }
+
+    /// The coupling map of the processor.
+    pub fn topology(&self) -> Topology {
+        self.edges.keys().copied().collect()
+    }
+
+    /// The gates supported on `qubit`, if the processor has it.
+    pub fn qubit_gates(&self, qubit: u64) -> Option<&[SupportedGate]> {
+        self.qubits.get(&qubit).map(Vec::as_slice)
+    }
+
+    /// The gates supported on the pair of `a` and `b`, in either order, if they are coupled.
+    pub fn edge_gates(&self, a: u64, b: u64) -> Option<&[SupportedGate]> {
+        self.edges.get(&(a.min(b), a.max(b))).map(Vec::as_slice)
+    }
+}
+
+/// The ways in which an instruction may not be supported by a [`QuantumProcessor`]. The index
+/// of each instruction is that among the program's instructions.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum IsaError {
+    #[error("instruction {index}, {instruction}, acts on qubit {qubit}, which the processor does not have")]
+    UnknownQubit {
+        index: usize,
+        instruction: Instruction,
+        qubit: u64,
+    },
+
+    #[error("instruction {index}, {instruction}, acts on qubits {first} and {second}, which are not coupled")]
+    Uncoupled {
+        index: usize,
+        instruction: Instruction,
+        first: u64,
+        second: u64,
+    },
+
+    #[error("instruction {index}, {instruction}, acts on more than two qubits")]
+    TooManyQubits {
+        index: usize,
+        instruction: Instruction,
+    },
+
+    #[error("instruction {index}, {instruction}, acts on a qubit variable")]
+    VariableQubit {
+        index: usize,
+        instruction: Instruction,
+    },
+
+    #[error(
+        "instruction {index}, {instruction}, is not a gate the processor supports on its qubits"
+    )]
+    UnsupportedGate {
+        index: usize,
+        instruction: Instruction,
+    },
+
+    #[error("instruction {index}, {instruction}, is given a value for parameter {position} which the processor does not support")]
+    UnsupportedParameter {
+        index: usize,
+        instruction: Instruction,
+        position: usize,
+    },
+}
+
+/// The first way in which `instruction`, at `index`, is not supported by `processor`, if any.
+fn violation(
+    processor: &QuantumProcessor,
+    index: usize,
+    instruction: &Instruction,
+) -> Option<IsaError> {
+    let qubits: Vec<&Qubit> = match instruction {
+        Instruction::Gate(gate) => gate.qubits.iter().collect(),
+        Instruction::Measurement(measurement) => vec![&measurement.qubit],
+        Instruction::Reset(reset) => reset.qubit.iter().collect(),
+        _ => return None,
+    };
+
+    let mut indices = vec![];
+    for qubit in qubits {
+        match qubit {
+            Qubit::Fixed(qubit) if processor.qubits.contains_key(qubit) => indices.push(*qubit),
+            Qubit::Fixed(qubit) => {
+                return Some(IsaError::UnknownQubit {
+                    index,
+                    instruction: instruction.clone(),
+                    qubit: *qubit,
+                })
+            }
+            Qubit::Variable(_) => {
+                return Some(IsaError::VariableQubit {
+                    index,
+                    instruction: instruction.clone(),
+                })
+            }
+        }
+    }
+
+    let Instruction::Gate(gate) = instruction else {
+        return None;
+    };
+    let supported = match *indices.as_slice() {
+        [qubit] => processor.qubit_gates(qubit),
+        [first, second] => match processor.edge_gates(first, second) {
+            Some(gates) => Some(gates),
+            None => {
+                return Some(IsaError::Uncoupled {
+                    index,
+                    instruction: instruction.clone(),
+                    first,
+                    second,
+                })
+            }
+        },
+        _ => {
+            return Some(IsaError::TooManyQubits {
+                index,
+                instruction: instruction.clone(),
+            })
+        }
+    };
+
+    let candidates: Vec<&SupportedGate> = supported
+        .into_iter()
+        .flatten()
+        .filter(|supported| supported.matches_name(gate))
+        .collect();
+    if candidates.is_empty() {
+        return Some(IsaError::UnsupportedGate {
+            index,
+            instruction: instruction.clone(),
+        });
+    }
+    // Report the first parameter which no candidate allows, if no candidate allows them all.
+    let allowed = |candidate: &SupportedGate, position: usize| {
+        candidate.parameters[position].allows(&gate.parameters[position])
+    };
+    if candidates
+        .iter()
+        .any(|candidate| (0..gate.parameters.len()).all(|position| allowed(candidate, position)))
+    {
+        return None;
+    }
+    let position = (0..gate.parameters.len())
+        .find(|&position| {
+            !candidates
+                .iter()
+                .any(|candidate| allowed(candidate, position))
+        })
+        .unwrap_or(0);
+    Some(IsaError::UnsupportedParameter {
+        index,
+        instruction: instruction.clone(),
+        position,
+    })
+}
+
+impl Program {
+    /// Every instruction of the body which `processor` does not support: gates which are not
+    /// among those it supports on their qubits, or are given parameters it does not allow, and
+    /// gates, measurements, and resets on qubits it does not have. Gates are supported only with
+    /// no modifiers, and on at most two qubits, which must be coupled. Circuits are not expanded,
+    /// and so should be expanded first, as by [`Program::expand_circuits`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::f64::consts::FRAC_PI_2;
+    ///
+    /// use quil_rs::program::isa::{
+    ///     IsaError, ParameterConstraint, QuantumProcessor, SupportedGate,
+    /// };
+    /// use quil_rs::program::routing::Topology;
+    /// use quil_rs::Program;
+    ///
+    /// let processor = QuantumProcessor::from_topology(
+    ///     &Topology::line(2),
+    ///     &[
+    ///         SupportedGate::with_parameters("RZ", vec![ParameterConstraint::Any]),
+    ///         SupportedGate::with_parameters(
+    ///             "RX",
+    ///             vec![ParameterConstraint::OneOf(vec![FRAC_PI_2, -FRAC_PI_2])],
+    ///         ),
+    ///     ],
+    ///     &[SupportedGate::new("CZ")],
+    /// );
+    /// let program: Program = "RX(pi/2) 0\nRX(0.5) 0\nCNOT 0 1\nMEASURE 2".parse().unwrap();
+    /// let violations = program.validate_against(&processor);
+    /// assert!(matches!(
+    ///     violations.as_slice(),
+    ///     [
+    ///         IsaError::UnsupportedParameter { index: 1, position: 0, .. },
+    ///         IsaError::UnsupportedGate { index: 2, .. },
+    ///         IsaError::UnknownQubit { index: 3, qubit: 2, .. },
+    ///     ]
+    /// ));
+    /// ```
+    pub fn validate_against(&self, processor: &QuantumProcessor) -> Vec<IsaError> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| violation(processor, index, instruction))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f64::consts::{FRAC_PI_2, PI};
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{IsaError, ParameterConstraint, QuantumProcessor, SupportedGate};
+    use crate::program::routing::Topology;
+    use crate::Program;
+
+    /// Two coupled qubits, and a third, 2, which supports only `X` and is coupled to neither.
+    fn processor() -> QuantumProcessor {
+        let mut processor = QuantumProcessor::from_topology(
+            &Topology::line(2),
+            &[
+                SupportedGate::with_parameters("RZ", vec![ParameterConstraint::Any]),
+                SupportedGate::with_parameters(
+                    "RX",
+                    vec![ParameterConstraint::OneOf(vec![FRAC_PI_2, -FRAC_PI_2])],
+                ),
+                SupportedGate::with_parameters("RX", vec![ParameterConstraint::OneOf(vec![PI])]),
+            ],
+            &[
+                SupportedGate::new("CZ"),
+                SupportedGate::with_parameters("XY", vec![ParameterConstraint::OneOf(vec![PI])]),
+            ],
+        );
+        processor.add_qubit(2, vec![SupportedGate::new("X")]);
+        processor
+    }
+
+    #[test]
+    fn test_processor() {
+        let processor = processor();
+        assert_eq!(processor.qubits().collect::<Vec<_>>(), [0, 1, 2]);
+        assert_eq!(processor.topology(), Topology::line(2));
+        assert_eq!(
+            processor.qubit_gates(2),
+            Some([SupportedGate::new("X")].as_slice())
+        );
+        assert_eq!(processor.edge_gates(1, 0).map(<[_]>::len), Some(2));
+        assert_eq!(processor.edge_gates(1, 2), None);
+    }
+
+    #[rstest]
+    #[case("DECLARE theta REAL\nRZ(theta) 0\nRZ(0.1) 1")]
+    #[case("RX(pi/2) 0\nRX(-pi/2) 1\nRX(3*pi/2) 0\nRX(-pi) 1")]
+    #[case("CZ 0 1\nCZ 1 0\nXY(pi) 1 0")]
+    #[case("X 2\nMEASURE 2\nRESET 2\nRESET")]
+    #[case("DECLARE ro BIT\nMEASURE 0 ro\nLABEL @a\nJUMP @a\nFENCE 0 7")]
+    fn test_supported(#[case] input: &str) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.validate_against(&processor()), []);
+    }
+
+    #[test]
+    fn test_violations() {
+        let program = Program::from_str(
+            "DECLARE theta REAL\nH 0\nRX(theta) 0\nX 0\nCZ 0 2\nDAGGER CZ 0 1\nXY(0.5) 0 1\nCCNOT 0 1 2\nRESET 3",
+        )
+        .unwrap();
+        let instruction = |index: usize| program.instructions[index].clone();
+        assert_eq!(
+            program.validate_against(&processor()),
+            [
+                IsaError::UnsupportedGate {
+                    index: 0,
+                    instruction: instruction(0),
+                },
+                IsaError::UnsupportedParameter {
+                    index: 1,
+                    instruction: instruction(1),
+                    position: 0,
+                },
+                IsaError::UnsupportedGate {
+                    index: 2,
+                    instruction: instruction(2),
+                },
+                IsaError::Uncoupled {
+                    index: 3,
+                    instruction: instruction(3),
+                    first: 0,
+                    second: 2,
+                },
+                IsaError::UnsupportedGate {
+                    index: 4,
+                    instruction: instruction(4),
+                },
+                IsaError::UnsupportedParameter {
+                    index: 5,
+                    instruction: instruction(5),
+                    position: 0,
+                },
+                IsaError::TooManyQubits {
+                    index: 6,
+                    instruction: instruction(6),
+                },
+                IsaError::UnknownQubit {
+                    index: 7,
+                    instruction: instruction(7),
+                    qubit: 3,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,182 @@
+//! Naming the label placeholders of a program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::instruction::{InstructionRewriter, InstructionVisitor, Label, LabelPlaceholder};
+
+use super::Program;
+
+/// Collects the names of the labels of a program, and its placeholders in the order they are
+/// first found.
+#[derive(Default)]
+struct LabelCollector {
+    names: HashSet<String>,
+    placeholders: Vec<LabelPlaceholder>,
+    seen: HashSet<LabelPlaceholder>,
+}
+
+impl InstructionVisitor for LabelCollector {
+    fn visit_label(&mut self, label: &Label) {
+        match label {
+            Label::Fixed(name) => {
+                self.names.insert(name.to_string());
+            }
+            Label::Placeholder(placeholder) => {
+                if self.seen.insert(placeholder.clone()) {
+                    self.placeholders.push(placeholder.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Replaces each placeholder with the label it has been named.
+struct PlaceholderResolver(HashMap<LabelPlaceholder, String>);
+
+impl InstructionRewriter for PlaceholderResolver {
+    fn rewrite_label(&mut self, label: &mut Label) {
+        if let Label::Placeholder(placeholder) = label {
+            *label = Label::Fixed(self.0[placeholder].as_str().into());
+        }
+    }
+}
+
+impl Program {
+    /// Name every [`Label::Placeholder`] in the program, both where it is defined and where it is
+    /// jumped to, including within blocks such as those of a `DEFCIRCUIT`. Each placeholder is
+    /// given a name distinct from every other label: its base name if that is not already in
+    /// use, and otherwise its base name followed by the first number which makes it so, as in
+    /// `@loop_1`. Placeholders are named in the order they are first found in the program.
+    ///
+    /// A program with no placeholders is left as it is; otherwise, it no longer records the text
+    /// it was parsed from.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::{Instruction, Jump, Label, LabelPlaceholder};
+    /// use quil_rs::Program;
+    ///
+    /// let mut program: Program = "LABEL @loop\nJUMP @loop".parse().unwrap();
+    /// let placeholder = Label::Placeholder(LabelPlaceholder::new("loop"));
+    /// program.add_instruction(Instruction::Label(placeholder.clone()));
+    /// program.add_instruction(Instruction::Jump(Jump { target: placeholder }));
+    ///
+    /// program.resolve_label_placeholders();
+    /// assert_eq!(
+    ///     program.to_string(true),
+    ///     "LABEL @loop\nJUMP @loop\nLABEL @loop_1\nJUMP @loop_1\n"
+    /// );
+    /// ```
+    pub fn resolve_label_placeholders(&mut self) {
+        let mut collector = LabelCollector::default();
+        collector.visit_program(self);
+        if collector.placeholders.is_empty() {
+            return;
+        }
+
+        let mut names = collector.names;
+        let mut resolved = HashMap::new();
+        for placeholder in collector.placeholders {
+            let base = placeholder.base();
+            let name = if names.contains(base) {
+                (1..)
+                    .map(|suffix| format!("{base}_{suffix}"))
+                    .find(|name| !names.contains(name))
+                    .expect("some suffix is not in use")
+            } else {
+                base.to_string()
+            };
+            names.insert(name.clone());
+            resolved.insert(placeholder, name);
+        }
+
+        PlaceholderResolver(resolved).rewrite_program(self);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instruction::{
+        CircuitDefinition, Gate, Instruction, Jump, JumpWhen, Label, LabelPlaceholder,
+        MemoryReference,
+    };
+    use crate::Program;
+
+    /// A loop which jumps back to its start while `ro[0]` is set.
+    fn template() -> Vec<Instruction> {
+        let start = Label::Placeholder(LabelPlaceholder::new("loop"));
+        vec![
+            Instruction::Label(start.clone()),
+            Instruction::Gate(Gate::x(0)),
+            Instruction::JumpWhen(JumpWhen {
+                target: start,
+                condition: MemoryReference {
+                    name: "ro".into(),
+                    index: 0,
+                },
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_resolve_label_placeholders() {
+        let mut program: Program = "DECLARE ro BIT\nLABEL @loop_1".parse().unwrap();
+        program.add_instructions(template());
+        program.add_instructions(template());
+        let end = Label::Placeholder(LabelPlaceholder::new("end"));
+        program.add_instruction(Instruction::CircuitDefinition(CircuitDefinition {
+            name: "SKIP".into(),
+            parameters: vec![],
+            qubit_variables: vec![],
+            instructions: vec![
+                Instruction::Jump(Jump {
+                    target: end.clone(),
+                }),
+                Instruction::Label(end),
+            ],
+        }));
+        assert!(program.to_string(true).contains("LABEL @<loop>"));
+
+        program.resolve_label_placeholders();
+        let expected: Program = "DECLARE ro BIT
+LABEL @loop_1
+LABEL @loop
+X 0
+JUMP-WHEN @loop ro[0]
+LABEL @loop_2
+X 0
+JUMP-WHEN @loop_2 ro[0]
+DEFCIRCUIT SKIP:
+    JUMP @end
+    LABEL @end
+
+"
+        .parse()
+        .unwrap();
+        assert_eq!(program, expected);
+    }
+
+    #[test]
+    fn test_resolve_without_placeholders() {
+        let mut program = Program::parse_with_source_text("LABEL @a\nJUMP @a").unwrap();
+        let original = program.clone();
+        program.resolve_label_placeholders();
+        assert_eq!(program.to_string(true), original.to_string(true));
+        assert_eq!(program.get_source_text(0), Some("LABEL @a"));
+    }
+}
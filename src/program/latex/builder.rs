@@ -0,0 +1,268 @@
+//! Build circuit diagrams cell by cell, for circuits which do not correspond to a Quil program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use crate::expression::Expression;
+use crate::instruction::{Instruction, Qubit};
+
+use super::{
+    render_param, Cell, ControlRoles, Diagram, GateCell, GateGroup, LatexGenError, RenderSettings,
+    Wire,
+};
+
+/// Builds a [`Diagram`] one cell at a time. Cells are addressed by the qubit of their wire and
+/// their column; the diagram is as wide as the rightmost column written to.
+///
+/// ```
+/// use quil_rs::instruction::Qubit;
+/// use quil_rs::program::latex::{DiagramBuilder, RenderSettings};
+///
+/// let mut builder = DiagramBuilder::new(RenderSettings::default());
+/// builder
+///     .gate(0, Qubit::Fixed(0), "H")
+///     .gate(1, Qubit::Fixed(1), "X")
+///     .control(1, &[Qubit::Fixed(0)], Qubit::Fixed(1))
+///     .annotate(1, Qubit::Fixed(1), "entangle")
+///     .meter(2, Qubit::Fixed(1));
+/// let diagram = builder.build().unwrap();
+/// assert!(diagram.to_string().contains(r"\targ{}"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DiagramBuilder {
+    diagram: Diagram,
+    /// the number of instructions pushed with [`DiagramBuilder::push_instruction`]
+    instructions: usize,
+}
+
+impl DiagramBuilder {
+    pub fn new(settings: RenderSettings) -> Self {
+        Self {
+            diagram: Diagram {
+                settings,
+                ..Default::default()
+            },
+            instructions: 0,
+        }
+    }
+
+    /// The column following the rightmost cell written so far.
+    pub fn next_column(&self) -> u32 {
+        self.diagram.column
+    }
+
+    /// Return the wire of `qubit`, adding it to the diagram if it isn't present, and widen the
+    /// diagram to include `column`.
+    fn cell(&mut self, column: u32, qubit: Qubit) -> &mut Wire {
+        self.diagram.column = self.diagram.column.max(column + 1);
+        self.diagram
+            .circuit
+            .entry(qubit.clone())
+            .or_insert_with(|| Box::new(Wire::new(qubit)))
+    }
+
+    /// Add an empty wire for `qubit`, if it has none.
+    pub fn wire(&mut self, qubit: Qubit) -> &mut Self {
+        self.diagram
+            .circuit
+            .entry(qubit.clone())
+            .or_insert_with(|| Box::new(Wire::new(qubit)));
+        self
+    }
+
+    /// Draw the gate `name` on the wire of `qubit` at `column`.
+    pub fn gate(&mut self, column: u32, qubit: Qubit, name: &str) -> &mut Self {
+        self.cell(column, qubit)
+            .cells
+            .insert(column, Cell::Gate(GateCell::new(name.to_string(), false)));
+        self
+    }
+
+    /// Draw the gate `name` with `parameters` on the wire of `qubit` at `column`. The parameters
+    /// are rendered according to the builder's [`RenderSettings`].
+    pub fn gate_with_parameters(
+        &mut self,
+        column: u32,
+        qubit: Qubit,
+        name: &str,
+        parameters: &[Expression],
+    ) -> &mut Self {
+        let mut gate = GateCell::new(name.to_string(), false);
+        gate.params = parameters
+            .iter()
+            .map(|parameter| render_param(parameter, &self.diagram.settings, false))
+            .collect();
+        self.cell(column, qubit)
+            .cells
+            .insert(column, Cell::Gate(gate));
+        self
+    }
+
+    /// Draw a measurement on the wire of `qubit` at `column`.
+    pub fn meter(&mut self, column: u32, qubit: Qubit) -> &mut Self {
+        self.cell(column, qubit)
+            .cells
+            .insert(column, Cell::Measurement);
+        self
+    }
+
+    /// Connect the `controls` to `target` at `column`. A `NOT` or `X` gate on the target is drawn
+    /// as `\targ{}`. Only one controlled operation may be drawn per column; a later call replaces
+    /// an earlier one.
+    pub fn control(&mut self, column: u32, controls: &[Qubit], target: Qubit) -> &mut Self {
+        for control in controls {
+            self.cell(column, control.clone());
+        }
+        self.cell(column, target.clone());
+
+        self.diagram.relationships.insert(
+            column,
+            ControlRoles {
+                controls: controls.to_vec(),
+                targets: vec![target],
+            },
+        );
+        self
+    }
+
+    /// Draw the gate of `qubit` at `column` with the Quantikz style options `style`, such as
+    /// `fill=red!20`. The gate must already be drawn.
+    pub fn style(&mut self, column: u32, qubit: Qubit, style: &str) -> &mut Self {
+        if let Some(gate) = self.cell(column, qubit).gate_mut(column) {
+            gate.style = Some(style.to_string());
+        }
+        self
+    }
+
+    /// Write `text` above the cell of `qubit` at `column`.
+    pub fn annotate(&mut self, column: u32, qubit: Qubit, text: &str) -> &mut Self {
+        self.cell(column, qubit)
+            .annotations
+            .insert(column, text.to_string());
+        self
+    }
+
+    /// Draw a box labelled `label` around the wires of `qubits` over `columns`.
+    pub fn group(&mut self, columns: Range<u32>, qubits: &[Qubit], label: &str) -> &mut Self {
+        self.diagram.gate_groups.push(GateGroup {
+            columns,
+            qubits: qubits.to_vec(),
+            label: label.to_string(),
+        });
+        self
+    }
+
+    /// Start a new row of the diagram at `column`.
+    pub fn column_break(&mut self, column: u32) -> &mut Self {
+        self.diagram.column_breaks.insert(column);
+        self
+    }
+
+    /// Lay out `instruction` in the next column, as when rendering a program. Instructions other
+    /// than gates, measurements, and layout pragmas are skipped, but are counted towards the
+    /// indices of the `instruction_styles` setting.
+    pub fn push_instruction(
+        &mut self,
+        instruction: &Instruction,
+    ) -> Result<&mut Self, LatexGenError> {
+        self.diagram
+            .push_instruction(instruction, self.instructions, None)?;
+        self.instructions += 1;
+        Ok(self)
+    }
+
+    /// Complete the diagram, ordering its wires and connecting controls to their targets.
+    pub fn build(self) -> Result<Diagram, LatexGenError> {
+        let mut diagram = self.diagram;
+        diagram.finish()?;
+        Ok(diagram)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::DiagramBuilder;
+    use crate::expression::Expression;
+    use crate::instruction::Qubit;
+    use crate::program::latex::{Command, Latex, LatexGenError, RenderSettings};
+    use crate::Program;
+
+    #[test]
+    fn test_builder() {
+        let mut builder = DiagramBuilder::new(RenderSettings::default());
+        builder
+            .gate(0, Qubit::Fixed(0), "H")
+            .gate_with_parameters(
+                0,
+                Qubit::Variable("anc".to_string()),
+                "RX",
+                &[Expression::from_str("pi/2").unwrap()],
+            )
+            .gate(1, Qubit::Fixed(1), "X")
+            .control(1, &[Qubit::Fixed(0)], Qubit::Fixed(1))
+            .annotate(2, Qubit::Fixed(1), "syndrome")
+            .group(0..2, &[Qubit::Fixed(0), Qubit::Fixed(1)], "bell")
+            .meter(2, Qubit::Fixed(1));
+        let diagram = builder.build().unwrap();
+
+        let qubits: Vec<_> = diagram.wires().map(|wire| wire.qubit().clone()).collect();
+        assert_eq!(
+            qubits,
+            vec![
+                Qubit::Fixed(0),
+                Qubit::Fixed(1),
+                Qubit::Variable("anc".to_string())
+            ]
+        );
+        let target: Vec<_> = diagram
+            .wires()
+            .nth(1)
+            .map(|wire| (0..diagram.column_count()).map(|column| wire.get_command(column)))
+            .unwrap()
+            .collect();
+        assert_eq!(target, vec![Command::Qw, Command::Targ, Command::Meter]);
+        insta::assert_snapshot!(diagram.to_document());
+    }
+
+    #[test]
+    fn test_builder_matches_program() {
+        let input = "H 0\nCNOT 0 1\nRX(pi) 2\nMEASURE 1";
+        let program = Program::from_str(input).unwrap();
+
+        let mut builder = DiagramBuilder::new(RenderSettings::default());
+        for instruction in &program.instructions {
+            builder.push_instruction(instruction).unwrap();
+        }
+        let diagram = builder.build().unwrap();
+
+        assert_eq!(
+            diagram.to_document(),
+            program.to_latex(RenderSettings::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_builder_control_without_target_wire() {
+        let mut builder = DiagramBuilder::new(RenderSettings::default());
+        builder.control(0, &[], Qubit::Fixed(0));
+        assert_eq!(
+            builder.build().unwrap_err(),
+            LatexGenError::FoundCNOTWithNoTarget
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Per-gate costs, such as durations and fidelities, which can be drawn on a circuit diagram.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use crate::instruction::{Gate, Qubit};
+
+/// The cost of applying a gate.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GateCost {
+    /// The duration of the gate, in seconds.
+    pub duration: Option<f64>,
+    /// The fidelity of the gate, between 0 and 1.
+    pub fidelity: Option<f64>,
+}
+
+impl GateCost {
+    /// Render the cost as a short annotation, such as `40 ns, F=0.995`, or `None` if the cost has
+    /// neither a duration nor a fidelity.
+    pub fn to_annotation(&self) -> Option<String> {
+        let mut parts = vec![];
+        if let Some(duration) = self.duration {
+            // Round to a hundredth of a nanosecond to hide floating-point noise.
+            let nanoseconds = (duration * 1e11).round() / 100f64;
+            parts.push(format!("{nanoseconds} ns"));
+        }
+        if let Some(fidelity) = self.fidelity {
+            parts.push(format!("F={fidelity}"));
+        }
+        if parts.is_empty() {
+            None
+        } else {
+            Some(parts.join(", "))
+        }
+    }
+}
+
+/// The costs of the gates a program may apply, keyed on the gate name and, optionally, the qubits
+/// it is applied to.
+#[derive(Clone, Debug, Default)]
+pub struct CostModel {
+    /// Costs of a gate on any qubits, keyed on the gate name.
+    gates: HashMap<String, GateCost>,
+    /// Costs of a gate on specific qubits, which take precedence over those in `gates`.
+    gates_on_qubits: HashMap<(String, Vec<u64>), GateCost>,
+}
+
+impl CostModel {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the cost of the gate `name` wherever it is applied.
+    pub fn set_gate_cost(&mut self, name: &str, cost: GateCost) {
+        self.gates.insert(name.to_string(), cost);
+    }
+
+    /// Set the cost of the gate `name` when applied to exactly `qubits`, in order.
+    pub fn set_gate_cost_on_qubits(&mut self, name: &str, qubits: &[u64], cost: GateCost) {
+        self.gates_on_qubits
+            .insert((name.to_string(), qubits.to_vec()), cost);
+    }
+
+    /// Return the cost of `gate`, preferring a cost set for the specific qubits it acts on.
+    /// Modifiers are not taken into account.
+    pub fn get(&self, gate: &Gate) -> Option<&GateCost> {
+        let qubits: Option<Vec<u64>> = gate
+            .qubits
+            .iter()
+            .map(|qubit| match qubit {
+                Qubit::Fixed(index) => Some(*index),
+                Qubit::Variable(_) => None,
+            })
+            .collect();
+
+        qubits
+            .and_then(|qubits| self.gates_on_qubits.get(&(gate.name.to_string(), qubits)))
+            .or_else(|| self.gates.get(gate.name.as_str()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{CostModel, GateCost};
+    use crate::instruction::Instruction;
+    use crate::Program;
+
+    #[rstest]
+    #[case(GateCost { duration: Some(40e-9), fidelity: None }, Some("40 ns"))]
+    #[case(GateCost { duration: None, fidelity: Some(0.995) }, Some("F=0.995"))]
+    #[case(GateCost { duration: Some(1.5e-7), fidelity: Some(0.99) }, Some("150 ns, F=0.99"))]
+    #[case(GateCost::default(), None)]
+    fn test_annotation(#[case] cost: GateCost, #[case] expected: Option<&str>) {
+        assert_eq!(cost.to_annotation().as_deref(), expected);
+    }
+
+    #[test]
+    fn test_qubit_specific_cost() {
+        let general = GateCost {
+            duration: Some(200e-9),
+            ..Default::default()
+        };
+        let specific = GateCost {
+            duration: Some(180e-9),
+            ..Default::default()
+        };
+        let mut model = CostModel::new();
+        model.set_gate_cost("CZ", general);
+        model.set_gate_cost_on_qubits("CZ", &[0, 1], specific);
+
+        let program = Program::from_str("CZ 0 1\nCZ 1 2\nCZ a b\nH 0").unwrap();
+        let costs: Vec<_> = program
+            .instructions
+            .iter()
+            .map(|instruction| match instruction {
+                Instruction::Gate(gate) => model.get(gate).copied(),
+                _ => unreachable!(),
+            })
+            .collect();
+        assert_eq!(
+            costs,
+            vec![Some(specific), Some(general), Some(general), None]
+        );
+    }
+}
@@ -0,0 +1,209 @@
+//! Render Quil expressions, such as gate parameters, as LaTeX math.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::str::FromStr;
+
+use crate::expression::{Expression, ExpressionFunction, InfixOperator, PrefixOperator};
+
+use super::{escape_text, Symbol};
+
+/// How tightly an expression binds when it appears as the operand of another. An operand which
+/// binds less tightly than its position requires is wrapped in parentheses.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precedence {
+    Sum,
+    Negation,
+    Product,
+    Power,
+    Atom,
+}
+
+fn precedence(expression: &Expression) -> Precedence {
+    match expression {
+        Expression::Infix { operator, .. } => match operator {
+            InfixOperator::Plus | InfixOperator::Minus => Precedence::Sum,
+            InfixOperator::Star => Precedence::Product,
+            // A fraction is drawn as a self-contained block.
+            InfixOperator::Slash => Precedence::Atom,
+            InfixOperator::Caret => Precedence::Power,
+        },
+        Expression::Prefix { .. } => Precedence::Negation,
+        Expression::FunctionCall {
+            function: ExpressionFunction::Exponent,
+            ..
+        } => Precedence::Power,
+        // A complex number is written as a sum of its real and imaginary parts.
+        Expression::Number(value) if value.re != 0f64 && value.im != 0f64 => Precedence::Sum,
+        Expression::Number(value) if value.re < 0f64 || value.im < 0f64 => Precedence::Negation,
+        _ => Precedence::Atom,
+    }
+}
+
+/// Render `expression`, wrapping it in parentheses if it binds less tightly than `minimum`.
+fn render_operand(expression: &Expression, minimum: Precedence) -> String {
+    let rendered = render(expression);
+    if precedence(expression) < minimum {
+        format!(r"\left({rendered}\right)")
+    } else {
+        rendered
+    }
+}
+
+/// Render `expression` as LaTeX math: division is drawn as `\frac`, square roots with `\sqrt`,
+/// exponentials and powers as superscripts, and well-known variable names and `pi` as symbols.
+pub(super) fn render(expression: &Expression) -> String {
+    match expression {
+        Expression::Address(memory_reference) => format!(
+            r"\text{{{}}}[{}]",
+            escape_text(memory_reference.name.as_str()),
+            memory_reference.index
+        ),
+        Expression::CustomFunctionCall {
+            function,
+            arguments,
+        } => {
+            let arguments: Vec<String> = arguments.iter().map(render).collect();
+            format!(
+                r"\mathrm{{{}}}\left({}\right)",
+                function.name(),
+                arguments.join(", ")
+            )
+        }
+        Expression::FunctionCall {
+            function,
+            expression,
+        } => {
+            let argument = render(expression);
+            match function {
+                ExpressionFunction::Cis => format!(r"\mathrm{{cis}}\left({argument}\right)"),
+                ExpressionFunction::Cosine => format!(r"\cos\left({argument}\right)"),
+                ExpressionFunction::Exponent => format!("e^{{{argument}}}"),
+                ExpressionFunction::Sine => format!(r"\sin\left({argument}\right)"),
+                ExpressionFunction::SquareRoot => format!(r"\sqrt{{{argument}}}"),
+            }
+        }
+        Expression::Infix {
+            left,
+            operator,
+            right,
+        } => match operator {
+            InfixOperator::Plus => format!(
+                "{} + {}",
+                render_operand(left, Precedence::Sum),
+                render_operand(right, Precedence::Negation)
+            ),
+            InfixOperator::Minus => format!(
+                "{} - {}",
+                render_operand(left, Precedence::Sum),
+                render_operand(right, Precedence::Product)
+            ),
+            InfixOperator::Star => format!(
+                r"{} \cdot {}",
+                render_operand(left, Precedence::Negation),
+                render_operand(right, Precedence::Product)
+            ),
+            InfixOperator::Slash => format!(r"\frac{{{}}}{{{}}}", render(left), render(right)),
+            InfixOperator::Caret => {
+                format!(
+                    "{}^{{{}}}",
+                    render_operand(left, Precedence::Atom),
+                    render(right)
+                )
+            }
+        },
+        Expression::Number(_) => expression.to_string(),
+        Expression::PiConstant => Symbol::Pi.to_string(),
+        Expression::Prefix {
+            operator,
+            expression,
+        } => {
+            let operand = render_operand(expression, Precedence::Product);
+            match operator {
+                PrefixOperator::Plus => format!("+{operand}"),
+                PrefixOperator::Minus => format!("-{operand}"),
+            }
+        }
+        Expression::Variable(name) => Symbol::from_str(name)
+            .unwrap_or_else(|never| match never {})
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::render;
+    use crate::expression::{Expression, InfixOperator};
+
+    #[rstest]
+    #[case("pi", r"\pi")]
+    #[case("pi/2", r"\frac{\pi}{2}")]
+    #[case("-pi/4", r"\frac{-\pi}{4}")]
+    #[case("%alpha", r"\alpha")]
+    #[case("%theta", r"\theta")]
+    #[case("%Omega", r"\Omega")]
+    #[case("%hbar", r"\hbar")]
+    #[case("%amplitude", r"\text{amplitude}")]
+    #[case("%theta_1", r"\theta_{1}")]
+    #[case("%theta_max", r"\theta_{\text{max}}")]
+    #[case("%phi_a_12", r"\phi_{\text{a}_{12}}")]
+    #[case("%a_0", r"\text{a}_{0}")]
+    #[case("%theta_", r"\text{theta\_}")]
+    #[case("theta[1]", r"\text{theta}[1]")]
+    #[case("my_theta[0]", r"\text{my\_theta}[0]")]
+    #[case("sqrt(2)", r"\sqrt{2}")]
+    #[case("exp(2)", r"e^{2}")]
+    #[case("cis(%beta)", r"\mathrm{cis}\left(\beta\right)")]
+    #[case("(1+2i)*pi", r"\left(1 + 2i\right) \cdot \pi")]
+    #[case("2*(%phi+1)", r"2 \cdot \left(\phi + 1\right)")]
+    #[case("(%phi+1)-(%phi - 1)", r"\phi + 1 - \left(\phi - 1\right)")]
+    #[case("-(%phi+1)", r"-\left(\phi + 1\right)")]
+    #[case(
+        "cos(sin(2*pi/3))*exp(1.0i*pi)",
+        r"\cos\left(\sin\left(2 \cdot \frac{\pi}{3}\right)\right) \cdot e^{1i \cdot \pi}"
+    )]
+    fn test_render(#[case] input: &str, #[case] expected: &str) {
+        let expression = Expression::from_str(input).unwrap();
+        assert_eq!(render(&expression), expected);
+    }
+
+    #[test]
+    fn test_render_complex_number() {
+        let expression = Expression::Infix {
+            left: Box::new(Expression::Number(num_complex::Complex64::new(1.0, -2.0))),
+            operator: InfixOperator::Star,
+            right: Box::new(Expression::PiConstant),
+        };
+        assert_eq!(render(&expression), r"\left(1-2i\right) \cdot \pi");
+    }
+
+    #[test]
+    fn test_render_power() {
+        // The parser does not read `^`, so build the expression directly.
+        let expression = Expression::Infix {
+            left: Box::new(Expression::from_str("%phi+1").unwrap()),
+            operator: InfixOperator::Caret,
+            right: Box::new(Expression::from_str("pi/2").unwrap()),
+        };
+        assert_eq!(
+            render(&expression),
+            r"\left(\phi + 1\right)^{\frac{\pi}{2}}"
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! Gate groups: labelled boxes drawn around a range of columns of a diagram, such as around the
+//! gates of an expanded circuit definition.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+
+/// A labelled box drawn around the cells of some wires over a range of columns, using the
+/// Quantikz `\gategroup` command.
+///
+/// The box spans every wire drawn between the topmost and bottommost of `qubits`. A group which
+/// crosses into a new row of the diagram is cut off at the end of the row it starts in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GateGroup {
+    /// The columns of the diagram in the group.
+    pub columns: Range<u32>,
+    /// The qubits whose wires are in the group. If empty, every wire is in the group.
+    pub qubits: Vec<Qubit>,
+    /// The label drawn above the group, inserted verbatim so it may contain LaTeX markup.
+    pub label: String,
+}
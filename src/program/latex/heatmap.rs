@@ -0,0 +1,143 @@
+//! Qubit utilization heatmaps drawn with TikZ.
+//!
+//! Each qubit is drawn as a square on a device grid, shaded in proportion to a per-qubit value
+//! such as the number of gates applied to it or its scheduled busy time.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::instruction::{Instruction, Qubit};
+use crate::Program;
+
+/// Settings which control how a heatmap is drawn.
+#[derive(Clone, Debug)]
+pub struct HeatmapSettings {
+    /// The number of qubits in each row of the device grid. Qubit `i` is drawn in row
+    /// `i / grid_width` and column `i % grid_width`.
+    pub grid_width: u64,
+    /// The number of qubits on the device. If `None`, the grid extends to the highest qubit which
+    /// has a value.
+    pub qubit_count: Option<u64>,
+    /// The TikZ color used to shade the qubit with the highest value; other qubits are shaded
+    /// with a proportional mix of this color and white.
+    pub color: String,
+}
+
+impl Default for HeatmapSettings {
+    fn default() -> Self {
+        Self {
+            grid_width: 8,
+            qubit_count: None,
+            color: "red".to_string(),
+        }
+    }
+}
+
+/// Render a standalone LaTeX document containing a TikZ heatmap of `values`, keyed on qubit
+/// index. Qubits without a value are drawn unshaded.
+pub fn render_heatmap(values: &BTreeMap<u64, f64>, settings: &HeatmapSettings) -> String {
+    let grid_width = settings.grid_width.max(1);
+    let qubit_count = settings
+        .qubit_count
+        .unwrap_or_else(|| values.keys().next_back().map_or(0, |last| last + 1));
+    let maximum = values.values().copied().fold(0f64, f64::max);
+
+    let mut output = String::from(
+        r"\documentclass[convert={density=300,outext=.png}]{standalone}
+\usepackage{tikz}
+\begin{document}
+\begin{tikzpicture}
+",
+    );
+
+    for qubit in 0..qubit_count {
+        let x = (qubit % grid_width) as f64 * 1.4;
+        let y = 0f64 - (qubit / grid_width) as f64 * 1.4;
+        let value = values.get(&qubit).copied().unwrap_or(0f64);
+        let intensity = if maximum > 0f64 {
+            (value / maximum * 100f64).round() as u64
+        } else {
+            0
+        };
+        // Writing to a String cannot fail.
+        let _ = writeln!(
+            output,
+            r"\node[draw, minimum size=1.2cm, align=center, fill={color}!{intensity}] at ({x:.1}, {y:.1}) {{$q_{{{qubit}}}$\\{value}}};",
+            color = settings.color,
+        );
+    }
+
+    output.push_str(
+        r"\end{tikzpicture}
+\end{document}",
+    );
+    output
+}
+
+impl Program {
+    /// Return the number of gates applied to each fixed qubit in the body of the program.
+    pub fn get_qubit_gate_counts(&self) -> BTreeMap<u64, usize> {
+        let mut counts = BTreeMap::new();
+        for instruction in &self.instructions {
+            if let Instruction::Gate(gate) = instruction {
+                for qubit in &gate.qubits {
+                    if let Qubit::Fixed(index) = qubit {
+                        *counts.entry(*index).or_insert(0) += 1;
+                    }
+                }
+            }
+        }
+        counts
+    }
+
+    /// Render a heatmap of the number of gates applied to each qubit in the program.
+    ///
+    /// To shade qubits by another metric, such as scheduled busy time, use [`render_heatmap`].
+    pub fn to_latex_heatmap(&self, settings: &HeatmapSettings) -> String {
+        let values = self
+            .get_qubit_gate_counts()
+            .into_iter()
+            .map(|(qubit, count)| (qubit, count as f64))
+            .collect();
+        render_heatmap(&values, settings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::HeatmapSettings;
+    use crate::Program;
+
+    #[test]
+    fn test_gate_counts() {
+        let program = Program::from_str("H 0\nCNOT 0 1\nX 3\nMEASURE 2\nRX(pi) q").unwrap();
+        let counts = program.get_qubit_gate_counts();
+        assert_eq!(counts, [(0, 2), (1, 1), (3, 1)].into_iter().collect());
+    }
+
+    #[test]
+    fn test_heatmap() {
+        let program = Program::from_str("H 0\nCNOT 0 1\nX 3").unwrap();
+        insta::assert_snapshot!(program.to_latex_heatmap(&HeatmapSettings {
+            grid_width: 2,
+            qubit_count: Some(5),
+            ..Default::default()
+        }));
+    }
+}
@@ -0,0 +1,173 @@
+//! A read-only view of a laid-out diagram, for tools which inspect or validate diagrams before
+//! they are compiled.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+
+use super::{Command, Diagram};
+
+/// What is drawn in one cell of a diagram.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CellKind {
+    /// Only the wire.
+    Empty,
+    /// A gate in a box on a single wire.
+    Gate,
+    /// A gate in a box spanning `wires` wires, starting at this one.
+    MultiWireGate { wires: usize },
+    /// Part of a box which starts on a wire above this one.
+    Spanned,
+    /// A gate drawn as a labelled dot.
+    Phase,
+    /// A control, joined to the cell `target_offset` wires below (or above, if negative).
+    Control { target_offset: i64 },
+    /// The target of a controlled-not.
+    Target,
+    /// A measurement.
+    Measurement,
+}
+
+/// The layout of a diagram: its wires, its columns, how the columns are split into rows, and what
+/// is drawn in each cell. Wires are indexed from top to bottom and columns from left to right.
+#[derive(Clone, Debug)]
+pub struct LatexLayout {
+    diagram: Diagram,
+}
+
+impl LatexLayout {
+    /// The qubit of each wire, from top to bottom.
+    pub fn qubits(&self) -> Vec<&Qubit> {
+        self.diagram.wires().map(|wire| wire.qubit()).collect()
+    }
+
+    /// The number of wires.
+    pub fn wire_count(&self) -> usize {
+        self.diagram.wires().count()
+    }
+
+    /// The number of columns on each wire, over all rows.
+    pub fn column_count(&self) -> u32 {
+        self.diagram.column_count()
+    }
+
+    /// The number of rows the columns are split into, such as by the `max_columns_per_row`
+    /// setting.
+    pub fn row_count(&self) -> usize {
+        self.diagram.row_count()
+    }
+
+    /// The columns drawn in each row.
+    pub fn rows(&self) -> Vec<Range<u32>> {
+        self.diagram.rows()
+    }
+
+    /// What is drawn on the wire at index `wire` at `column`, or `None` if there is no such cell.
+    pub fn cell(&self, wire: usize, column: u32) -> Option<CellKind> {
+        if column >= self.column_count() {
+            return None;
+        }
+        let commands: Vec<Command> = self
+            .diagram
+            .wires()
+            .take(wire + 1)
+            .map(|wire| wire.get_command(column))
+            .collect();
+
+        let kind = match commands.get(wire)? {
+            Command::Gate(_) | Command::StyledGate(..) => CellKind::Gate,
+            Command::WideGate { wires, .. } => CellKind::MultiWireGate { wires: *wires },
+            Command::Phase(_) => CellKind::Phase,
+            Command::Ctrl(offset) | Command::StyledCtrl(offset, _) => CellKind::Control {
+                target_offset: *offset,
+            },
+            Command::Targ | Command::StyledTarg(_) => CellKind::Target,
+            Command::Meter => CellKind::Measurement,
+            _ => {
+                let spanned = commands.iter().enumerate().any(|(row, command)| {
+                    matches!(command, Command::WideGate { wires, .. } if row + wires > wire)
+                });
+                if spanned {
+                    CellKind::Spanned
+                } else {
+                    CellKind::Empty
+                }
+            }
+        };
+        Some(kind)
+    }
+
+    /// The laid-out diagram, to be drawn by any [`super::Renderer`].
+    pub fn diagram(&self) -> &Diagram {
+        &self.diagram
+    }
+}
+
+impl From<Diagram> for LatexLayout {
+    fn from(diagram: Diagram) -> Self {
+        Self { diagram }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::CellKind;
+    use crate::instruction::Qubit;
+    use crate::program::latex::{CircuitDisplay, Latex, RenderSettings};
+    use crate::Program;
+
+    #[test]
+    fn test_layout() {
+        let program = Program::from_str(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nCNOT 2 0\nMEASURE 1",
+        )
+        .unwrap();
+        let layout = program
+            .to_latex_layout(RenderSettings {
+                circuit_display: CircuitDisplay::Box,
+                max_columns_per_row: Some(2),
+                ..Default::default()
+            })
+            .unwrap();
+
+        assert_eq!(
+            layout.qubits(),
+            vec![&Qubit::Fixed(0), &Qubit::Fixed(1), &Qubit::Fixed(2)]
+        );
+        assert_eq!(layout.wire_count(), 3);
+        assert_eq!(layout.column_count(), 3);
+        assert_eq!(layout.row_count(), 2);
+        assert_eq!(layout.rows(), vec![0..2, 2..3]);
+
+        assert_eq!(
+            layout.cell(0, 0),
+            Some(CellKind::MultiWireGate { wires: 2 })
+        );
+        assert_eq!(layout.cell(1, 0), Some(CellKind::Spanned));
+        assert_eq!(layout.cell(2, 0), Some(CellKind::Empty));
+        assert_eq!(layout.cell(0, 1), Some(CellKind::Target));
+        assert_eq!(
+            layout.cell(2, 1),
+            Some(CellKind::Control { target_offset: -2 })
+        );
+        assert_eq!(layout.cell(1, 2), Some(CellKind::Measurement));
+        assert_eq!(layout.cell(3, 0), None);
+        assert_eq!(layout.cell(0, 3), None);
+    }
+}
@@ -0,0 +1,2877 @@
+//! LaTeX diagram generation for Quil programs.
+//!
+//! Provides a feature to generate diagrams using the LaTeX subpackage TikZ/[`Quantikz`] for a
+//! given Quil [`Program`].
+//!
+//! - Usage: `program.to_latex(RenderSettings::default())`
+//!
+//! - Description: [`Quantikz`] is a subpackage in the TikZ package used to generate qubit
+//!   circuits. A qubit is represented as a wire separated into multiple columns. Each column
+//!   contains a symbol of an operation on the qubit. Multiple qubits can be stacked into rows with
+//!   interactions between any number of them drawn as a connecting bar to each involved qubit
+//!   wire. Commands are used to control what is rendered on a circuit, e.g. names of qubits,
+//!   identifying control/target qubits, gates, etc. View [`Quantikz`] for the documentation on its
+//!   usage and full set of commands.
+//!
+//! [`Quantikz`]: https://arxiv.org/pdf/1809.03842.pdf
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use crate::expression::{Expression, FloatFormat};
+use crate::instruction::{
+    Capture, CircuitDefinition, Delay, Gate, GateModifier, GateType, Identifier, Instruction,
+    Measurement, Pragma, PragmaArgument, Pulse, Qubit, RawCapture,
+};
+use crate::program::moments::Scheduler;
+use crate::Program;
+
+use self::cost::CostModel;
+use self::style::{CellShape, CellStyle, CellStyler};
+
+pub use self::builder::DiagramBuilder;
+pub use self::group::GateGroup;
+pub use self::layout::{CellKind, LatexLayout};
+
+mod builder;
+pub mod cost;
+mod expression;
+mod group;
+pub mod heatmap;
+mod layout;
+mod qcircuit;
+#[cfg(feature = "render")]
+pub mod render;
+pub mod style;
+
+/// The pragma which starts a new row of the diagram.
+const COLUMN_BREAK_PRAGMA: &str = "LATEX_COLUMN_BREAK";
+
+/// The pragma which sets the order of the wires of the diagram.
+const ROW_ORDER_PRAGMA: &str = "LATEX_ROW_ORDER";
+
+/// The pragma which writes a label above the next column of the diagram.
+const LABEL_PRAGMA: &str = "LATEX_LABEL";
+
+/// The pragma which draws a labelled slice after the last column of the diagram.
+const SLICE_PRAGMA: &str = "LATEX_SLICE";
+
+/// The pragma which opens a gate group at the next column of the diagram.
+const GROUP_PRAGMA: &str = "LATEX_GROUP";
+
+/// The pragma which closes the innermost gate group opened by a `PRAGMA LATEX_GROUP`.
+const END_GROUP_PRAGMA: &str = "LATEX_END_GROUP";
+
+/// The Quantikz style of the boxes marking pulse-level operations.
+const MARKER_STYLE: &str = r"dashed,font=\scriptsize";
+
+/// Available commands used for building circuits, with the same names taken from the Quantikz
+/// documentation for easy reference. The emitted LaTeX is shown in `backticks`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// `\lstick{\ket{q_{u64}}}`: make a qubit "stick out" from the left. A variable qubit is
+    /// labelled with its name, as in `\lstick{\ket{q}}`.
+    Lstick(Qubit),
+    /// `\lstick{label}`: make a qubit "stick out" from the left with a custom label.
+    LstickLabel(String),
+    /// `\rstick{\ket{q_{u64}}}`: make a qubit "stick out" from the right. A variable qubit is
+    /// labelled with its name, as in `\rstick{\ket{q}}`.
+    Rstick(Qubit),
+    /// `\rstick{label}`: make a qubit "stick out" from the right with a custom label.
+    RstickLabel(String),
+    /// `\gate{name}`: make a gate on the wire.
+    Gate(String),
+    /// `\gate[style={style}]{name}`: make a gate on the wire, drawn with the Quantikz style options
+    /// `style`, such as `fill=red!20`.
+    StyledGate(String, String),
+    /// `\gate[wires=wires]{name}`: make a gate spanning `wires` wires, starting from this one,
+    /// optionally drawn with the Quantikz style options `style`, as in
+    /// `\gate[wires=wires,style={style}]{name}`.
+    WideGate {
+        wires: usize,
+        name: String,
+        style: Option<String>,
+    },
+    /// `\phase{text}`: make a dot on the wire labelled with `text`.
+    Phase(String),
+    /// `\meter{}`: measure a qubit.
+    Meter,
+    /// `\qw`: connect the current cell to the previous cell i.e. "do nothing".
+    Qw,
+    /// `\cw`: connect the current cell to the previous cell with a classical (double) wire.
+    Cw,
+    /// `\vcw{wire}`: draw a classical wire down to the wire `wire` rows away, such as from a
+    /// measurement to the memory region it is written to.
+    Vcw(i64),
+    /// `\\`: start a new row.
+    Nr,
+    /// `\ctrl{wire}`: make a control qubit, connected to the qubit `wire` rows away.
+    Ctrl(i64),
+    /// `\targ{}`: make a controlled-not gate.
+    Targ,
+    /// `\ctrl[style={style}]{wire}`: make a control qubit like [`Command::Ctrl`], drawn with the
+    /// Quantikz style options `style`.
+    StyledCtrl(i64, String),
+    /// `\targ[style={style}]{}`: make a controlled-not gate like [`Command::Targ`], drawn with the
+    /// Quantikz style options `style`.
+    StyledTarg(String),
+    /// `\gategroup[1,steps=1,style={draw=none}]{text}`: write `text` above the current cell.
+    Annotation(String),
+    /// `\slice{label}`: draw a dashed line across every wire after the current column, labelled
+    /// `label` at the top.
+    Slice(String),
+    /// `\gategroup[wires,steps=steps,style={dashed,rounded corners}]{label}`: draw a box labelled
+    /// `label` around the `wires` wires and `steps` columns starting from the current cell.
+    GateGroup {
+        wires: usize,
+        steps: u32,
+        label: String,
+    },
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Command::Lstick(Qubit::Fixed(index)) => {
+                write!(f, r"\lstick{{\ket{{q_{{{index}}}}}}}")
+            }
+            Command::Lstick(Qubit::Variable(name)) => write!(f, r"\lstick{{\ket{{{name}}}}}"),
+            Command::LstickLabel(label) => write!(f, r"\lstick{{{label}}}"),
+            Command::Rstick(Qubit::Fixed(index)) => {
+                write!(f, r"\rstick{{\ket{{q_{{{index}}}}}}}")
+            }
+            Command::Rstick(Qubit::Variable(name)) => write!(f, r"\rstick{{\ket{{{name}}}}}"),
+            Command::RstickLabel(label) => write!(f, r"\rstick{{{label}}}"),
+            Command::Gate(name) => write!(f, r"\gate{{{name}}}"),
+            Command::StyledGate(name, style) => {
+                write!(f, r"\gate[style={{{style}}}]{{{name}}}")
+            }
+            Command::WideGate {
+                wires,
+                name,
+                style: None,
+            } => write!(f, r"\gate[wires={wires}]{{{name}}}"),
+            Command::WideGate {
+                wires,
+                name,
+                style: Some(style),
+            } => write!(f, r"\gate[wires={wires},style={{{style}}}]{{{name}}}"),
+            Command::Phase(text) => write!(f, r"\phase{{{text}}}"),
+            Command::Meter => write!(f, r"\meter{{}}"),
+            Command::Qw => write!(f, r"\qw"),
+            Command::Cw => write!(f, r"\cw"),
+            Command::Vcw(wire) => write!(f, r"\vcw{{{wire}}}"),
+            Command::Nr => write!(f, r"\\"),
+            Command::Ctrl(wire) => write!(f, r"\ctrl{{{wire}}}"),
+            Command::Targ => write!(f, r"\targ{{}}"),
+            Command::StyledCtrl(wire, style) => {
+                write!(f, r"\ctrl[style={{{style}}}]{{{wire}}}")
+            }
+            Command::StyledTarg(style) => write!(f, r"\targ[style={{{style}}}]{{}}"),
+            Command::Annotation(text) => {
+                write!(f, r"\gategroup[1,steps=1,style={{draw=none}}]{{{text}}}")
+            }
+            Command::Slice(label) => write!(f, r"\slice{{{label}}}"),
+            Command::GateGroup {
+                wires,
+                steps,
+                label,
+            } => write!(
+                f,
+                r"\gategroup[{wires},steps={steps},style={{dashed,rounded corners}}]{{{label}}}"
+            ),
+        }
+    }
+}
+
+/// The names of the Greek letters and constants which are written as the LaTeX command of the
+/// same name, such as `\theta`. Uppercase letters which look like Latin letters, such as `Alpha`,
+/// have no command and are written as text.
+const SYMBOL_NAMES: &[&str] = &[
+    // lowercase Greek letters, and their variant forms (all but omicron, which is a Latin "o",
+    // and pi, which is [`Symbol::Pi`])
+    "alpha",
+    "beta",
+    "gamma",
+    "delta",
+    "epsilon",
+    "varepsilon",
+    "zeta",
+    "eta",
+    "theta",
+    "vartheta",
+    "iota",
+    "kappa",
+    "lambda",
+    "mu",
+    "nu",
+    "xi",
+    "varpi",
+    "rho",
+    "varrho",
+    "sigma",
+    "varsigma",
+    "tau",
+    "upsilon",
+    "phi",
+    "varphi",
+    "chi",
+    "psi",
+    "omega",
+    // uppercase Greek letters
+    "Gamma",
+    "Delta",
+    "Theta",
+    "Lambda",
+    "Xi",
+    "Pi",
+    "Sigma",
+    "Upsilon",
+    "Phi",
+    "Psi",
+    "Omega",
+    // constants
+    "hbar",
+    "infty",
+];
+
+/// Escape the characters of `text` which are special in LaTeX text mode, such as the underscores
+/// of frame names.
+fn escape_text(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '_' | '&' | '%' | '#' | '$' | '{' | '}') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Types of parameters passed to commands.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Symbol {
+    /// `\pi`
+    Pi,
+    /// `\name`: a Greek letter or constant listed in [`SYMBOL_NAMES`].
+    Named(&'static str),
+    /// `digits`: a number, written as is.
+    Number(String),
+    /// `base_{subscript}`: a name with a subscript, written in Quil as `base_subscript`.
+    Subscripted(Box<Symbol>, Box<Symbol>),
+    /// `\text{text}`: any other name, with the characters special in text mode escaped.
+    Text(String),
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Symbol::Pi => write!(f, r"\pi"),
+            Symbol::Named(name) => write!(f, r"\{name}"),
+            Symbol::Number(digits) => write!(f, "{digits}"),
+            Symbol::Subscripted(base, subscript) => write!(f, "{base}_{{{subscript}}}"),
+            Symbol::Text(text) => write!(f, r"\text{{{}}}", escape_text(text)),
+        }
+    }
+}
+
+impl FromStr for Symbol {
+    type Err = std::convert::Infallible;
+
+    /// Read a variable name as a symbol. Everything after the first underscore in the name is a
+    /// subscript, so `theta_0` is written as `\theta_{0}` and `theta_max_1` as
+    /// `\theta_{\text{max}_{1}}`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some((base, subscript)) = s.split_once('_') {
+            if !base.is_empty() && !subscript.is_empty() {
+                let subscript = if subscript.chars().all(|c| c.is_ascii_digit()) {
+                    Symbol::Number(subscript.to_string())
+                } else {
+                    Symbol::from_str(subscript)?
+                };
+                return Ok(Symbol::Subscripted(
+                    Box::new(Symbol::from_str(base)?),
+                    Box::new(subscript),
+                ));
+            }
+        }
+
+        Ok(match s {
+            "pi" => Symbol::Pi,
+            _ => match SYMBOL_NAMES.iter().find(|name| **name == s) {
+                Some(name) => Symbol::Named(name),
+                None => Symbol::Text(s.to_string()),
+            },
+        })
+    }
+}
+
+/// How to render a diagram with no wires, such as that of an empty program or of a program with
+/// only classical instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EmptyDiagram {
+    /// Render an empty `tikzcd` environment.
+    #[default]
+    Empty,
+    /// Render a single unlabelled wire, so that the diagram has a visible extent.
+    PlaceholderWire,
+    /// Fail with [`LatexGenError::NoQubitsToRender`].
+    Error,
+}
+
+/// How to label the right ends of the qubit lines, such as with the expected output state of
+/// each qubit.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum OutputLabels {
+    /// Leave the right ends of the lines unlabelled.
+    #[default]
+    None,
+    /// Label each line with its qubit, as in `\rstick{\ket{q_{index}}}`.
+    Qubits,
+    /// Label the lines of the given fixed qubits, keyed on the qubit index. Each label is inserted
+    /// into the diagram verbatim, so it may contain LaTeX markup, such as `\ket{0}`.
+    Custom(HashMap<u64, String>),
+}
+
+/// Options of the `tikzcd` environment each row of the diagram is drawn in, for compressing or
+/// enlarging the diagram. When left at their defaults, no options are written.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TikzcdOptions {
+    /// The horizontal space between columns, as a TikZ length such as `0.5cm` or `small`.
+    pub column_sep: Option<String>,
+    /// The vertical space between wires, as a TikZ length such as `0.5cm` or `small`.
+    pub row_sep: Option<String>,
+    /// Leave out the background behind the cells, so that wires show through them.
+    pub transparent: bool,
+    /// Draw the wires with thin lines.
+    pub thin_lines: bool,
+}
+
+impl fmt::Display for TikzcdOptions {
+    /// Write the options as an optional argument of `\begin{tikzcd}`, such as
+    /// `[column sep=0.5cm, thin lines]`, or nothing if no option is set.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut options = vec![];
+        if let Some(column_sep) = &self.column_sep {
+            options.push(format!("column sep={column_sep}"));
+        }
+        if let Some(row_sep) = &self.row_sep {
+            options.push(format!("row sep={row_sep}"));
+        }
+        if self.transparent {
+            options.push("transparent".to_string());
+        }
+        if self.thin_lines {
+            options.push("thin lines".to_string());
+        }
+
+        if options.is_empty() {
+            Ok(())
+        } else {
+            write!(f, "[{}]", options.join(", "))
+        }
+    }
+}
+
+/// The LaTeX package a diagram is written for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LatexBackend {
+    /// The TikZ library [Quantikz](https://ctan.org/pkg/quantikz), drawing each row of the
+    /// diagram in a `tikzcd` environment.
+    #[default]
+    Quantikz,
+    /// The older [qcircuit](https://ctan.org/pkg/qcircuit) package, drawing each row of the
+    /// diagram in a `\Qcircuit` environment, for publications which require it. Gate styles,
+    /// annotations, slices, and the labels of phase gates and gate groups are not drawn, and of
+    /// the `tikzcd_options`, only the column and row separations are used.
+    Qcircuit,
+}
+
+/// How to draw invocations of the circuits defined by `DEFCIRCUIT` among the rendered
+/// instructions.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CircuitDisplay {
+    /// Draw each invocation like any other gate.
+    #[default]
+    Gate,
+    /// Draw each invocation as a single box, labelled with the name of the circuit, spanning the
+    /// wires of all of its qubits.
+    Box,
+    /// Draw each invocation as the gates of the circuit's body, in a gate group labelled with the
+    /// name of the circuit.
+    Grouped,
+}
+
+/// Settings which control how a circuit is rendered; use [`RenderSettings::default`] for the
+/// standard presentation.
+#[derive(Clone, Debug)]
+pub struct RenderSettings {
+    /// Render gate parameters as LaTeX math, e.g. `pi/2` as `\frac{\pi}{2}`, with Greek letters
+    /// and constants such as `%theta` drawn as symbols and names such as `%theta_1` drawn with
+    /// subscripts. When unset, parameters are rendered as plain text.
+    pub texify_numerical_constants: bool,
+    /// Include all qubits between the lowest and highest qubit used in the program, even those
+    /// which are not used by any instruction.
+    pub impute_missing_qubits: bool,
+    /// Leave out the wires of qubits which no gate or measurement is drawn on, such as those only
+    /// added by `impute_missing_qubits`. Wires of qubits named by `wire_order`, a
+    /// `PRAGMA LATEX_ROW_ORDER`, or `gate_groups` are kept.
+    pub omit_idle_wires: bool,
+    /// Label each qubit line with the qubit index.
+    pub label_qubit_lines: bool,
+    /// Custom labels for the lines of fixed qubits, keyed on the qubit index, used in place of
+    /// the default `\ket{q_{index}}` when `label_qubit_lines` is set. Each label is inserted into
+    /// the diagram verbatim, so it may contain LaTeX markup, such as `\ket{\text{ancilla}}`.
+    pub qubit_labels: HashMap<u64, String>,
+    /// How to label the right ends of the qubit lines, after the last row of the diagram.
+    pub output_labels: OutputLabels,
+    /// Maximum number of columns drawn side by side. Longer circuits are split into several
+    /// `tikzcd` environments stacked on top of each other, with `\cdots` markers at the ends of the
+    /// wires where they are broken. `None` or `Some(0)` draws the circuit on a single row.
+    pub max_columns_per_row: Option<u32>,
+    /// Draw gates and measurements which may be applied at the same time, as grouped by
+    /// [`Program::to_moments`], in the same column, so long as their boxes and the lines joining
+    /// their controls do not overlap on the drawn wires. Columns are not moved past a column
+    /// break, slice, label, or the edge of a gate group; the columns of `gate_groups` are counted
+    /// before any are moved.
+    pub pack_moments: bool,
+    /// Spacing and line options for the `tikzcd` environment of each row.
+    pub tikzcd_options: TikzcdOptions,
+    /// The LaTeX package the diagram is written for.
+    pub backend: LatexBackend,
+    /// The format of numbers in gate parameters.
+    pub float_format: FloatFormat,
+    /// The fixed qubits to draw first, from top to bottom, so that related qubits can be placed
+    /// on adjacent wires. The remaining wires are drawn below them in the default order. Each
+    /// qubit must be listed at most once and must have a wire in the diagram.
+    ///
+    /// When empty, the order given by a `PRAGMA LATEX_ROW_ORDER` in the program is used instead.
+    pub wire_order: Vec<u64>,
+    /// How to render a diagram with no wires.
+    pub empty_diagram: EmptyDiagram,
+    /// Labels to draw in place of the names of gates, keyed on the gate name, such as `R_{z}` for
+    /// a native `RZ12` gate. Each label is inserted into the diagram verbatim, so it may contain
+    /// LaTeX markup. A relabelled gate is drawn as a single box, so composite names such as `CNOT`
+    /// are no longer split into controls and a target.
+    pub gate_name_overrides: HashMap<String, String>,
+    /// Quantikz style options, such as `fill=red!20`, for the boxes of gates with the given
+    /// names.
+    pub gate_styles: HashMap<String, String>,
+    /// Quantikz style options for the boxes of the gates at the given indices of the instructions
+    /// being rendered. These take precedence over `gate_styles`.
+    pub instruction_styles: HashMap<usize, String>,
+    /// A callback which overrides the text, style, or shape of individual gates, taking
+    /// precedence over `gate_styles` and `instruction_styles`.
+    pub cell_styler: Option<CellStyler>,
+    /// Draw a classical wire at the bottom of the diagram for each memory region written by a
+    /// measurement, with an arrow from each measurement to the wire of its target, labelled with
+    /// the index written.
+    pub classical_wires: bool,
+    /// Draw `PULSE`, `CAPTURE`, `RAW-CAPTURE`, and `DELAY` instructions as small dashed boxes on
+    /// the wires of their qubits, annotated with their frame or duration, rather than leaving
+    /// them out.
+    pub pulse_markers: bool,
+    /// Annotate gates defined in the program `AS PERMUTATION` or `AS PAULI-SUM` with the kind of
+    /// their definition. Gates defined in the program are always drawn as boxes with their own
+    /// name, whatever the kind of their definition.
+    pub annotate_gate_definitions: bool,
+    /// Labelled boxes to draw around ranges of columns of the diagram. Columns are counted after
+    /// any circuits are expanded by [`CircuitDisplay::Grouped`].
+    pub gate_groups: Vec<GateGroup>,
+    /// How to draw invocations of circuits. Invocations with modifiers are drawn like any other
+    /// gate, except that those with only `DAGGER` modifiers may be drawn as boxes.
+    pub circuit_display: CircuitDisplay,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            texify_numerical_constants: true,
+            impute_missing_qubits: false,
+            omit_idle_wires: false,
+            label_qubit_lines: true,
+            qubit_labels: HashMap::new(),
+            output_labels: OutputLabels::None,
+            max_columns_per_row: None,
+            pack_moments: false,
+            tikzcd_options: TikzcdOptions::default(),
+            backend: LatexBackend::Quantikz,
+            float_format: FloatFormat::Shortest,
+            wire_order: vec![],
+            empty_diagram: EmptyDiagram::Empty,
+            gate_name_overrides: HashMap::new(),
+            gate_styles: HashMap::new(),
+            instruction_styles: HashMap::new(),
+            cell_styler: None,
+            classical_wires: false,
+            pulse_markers: false,
+            annotate_gate_definitions: false,
+            gate_groups: vec![],
+            circuit_display: CircuitDisplay::Gate,
+        }
+    }
+}
+
+impl RenderSettings {
+    /// Add an empty wire for every fixed qubit between the lowest and highest fixed qubit in the
+    /// circuit which does not already have one.
+    fn impute_missing_qubits(circuit: &mut BTreeMap<Qubit, Box<Wire>>) {
+        let mut indices = circuit.keys().filter_map(|qubit| match qubit {
+            Qubit::Fixed(index) => Some(*index),
+            Qubit::Variable(_) => None,
+        });
+        let (first, last) = match indices.next() {
+            Some(first) => (first, indices.next_back().unwrap_or(first)),
+            None => return,
+        };
+
+        for index in first..=last {
+            let name = Qubit::Fixed(index);
+            circuit
+                .entry(name.clone())
+                .or_insert_with(|| Box::new(Wire::new(name)));
+        }
+    }
+}
+
+/// The structure of a LaTeX document. Typically a LaTeX document contains metadata defining the
+/// setup and packages used in a document within a header and footer, while the body contains
+/// content and controls its presentation.
+struct Document {
+    header: String,
+    body: String,
+    footer: String,
+}
+
+impl Document {
+    /// Build a standalone document around `body`, loading the packages of `backend`. When
+    /// `stacked` is set, the document allows its body to contain several paragraphs, one for each
+    /// row of a wrapped circuit.
+    fn new(body: String, stacked: bool, backend: LatexBackend) -> Self {
+        let class_options = if stacked {
+            "convert={density=300,outext=.png},varwidth"
+        } else {
+            "convert={density=300,outext=.png}"
+        };
+        let packages = match backend {
+            LatexBackend::Quantikz => "\\usepackage{tikz}\n\\usetikzlibrary{quantikz}",
+            LatexBackend::Qcircuit => r"\usepackage[braket,qm]{qcircuit}",
+        };
+
+        Self {
+            header: format!(
+                r"\documentclass[{class_options}]{{standalone}}
+\usepackage[margin=1in]{{geometry}}
+{packages}
+\begin{{document}}
+"
+            ),
+            body,
+            footer: r"\end{document}".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Document {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}{}", self.header, self.body, self.footer)
+    }
+}
+
+/// The standard gates which are written in composite form, with the number of controls each
+/// applies to its base gate.
+const CONTROLLED_GATES: &[(&str, usize, &str)] = &[
+    ("CNOT", 1, "NOT"),
+    ("CCNOT", 2, "NOT"),
+    ("CZ", 1, "Z"),
+    ("CPHASE", 1, "PHASE"),
+];
+
+/// The qubits taking part in a controlled operation: the controls, each joined to the nearest of
+/// the targets, which hold the operation's gate.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ControlRoles {
+    controls: Vec<Qubit>,
+    targets: Vec<Qubit>,
+}
+
+/// A Diagram represents a collection of wires in a circuit. Each instruction in the program
+/// occupies its own column across every wire, so a program with 6 instructions acting on 5
+/// qubits has a diagram of 30 cells.
+///
+/// Diagrams are laid out from Quil instructions by the [`Latex`] trait, or cell by cell with a
+/// [`DiagramBuilder`].
+#[derive(Clone, Debug, Default)]
+pub struct Diagram {
+    /// customizes how the diagram renders the circuit
+    settings: RenderSettings,
+    /// total number of columns on each wire
+    column: u32,
+    /// the roles of the qubits taking part in a controlled operation at each column
+    relationships: HashMap<u32, ControlRoles>,
+    /// the wires of the circuit, keyed on their qubit
+    circuit: BTreeMap<Qubit, Box<Wire>>,
+    /// the qubits of the wires in the order they are drawn, from top to bottom
+    order: Vec<Qubit>,
+    /// the columns at which a `PRAGMA LATEX_COLUMN_BREAK` starts a new row
+    column_breaks: BTreeSet<u32>,
+    /// the wire order requested by a `PRAGMA LATEX_ROW_ORDER`, if any
+    pragma_wire_order: Option<Vec<u64>>,
+    /// the labels written above the top wire by a `PRAGMA LATEX_LABEL`, keyed on column
+    labels: HashMap<u32, String>,
+    /// the labels of the slices drawn by a `PRAGMA LATEX_SLICE`, keyed on the column they follow
+    slices: HashMap<u32, String>,
+    /// the gate groups opened by a `PRAGMA LATEX_GROUP` and not yet closed, innermost last
+    open_groups: Vec<GateGroup>,
+    /// the memory regions drawn as classical wires, each with the index written by the
+    /// measurement at each column
+    classical_wires: BTreeMap<String, HashMap<u32, u64>>,
+    /// the circuits which are drawn as set by the `circuit_display` setting, keyed on name
+    circuits: HashMap<String, CircuitDefinition>,
+    /// the kinds of the gates defined by `DEFGATE` among the rendered instructions, keyed on name
+    definitions: HashMap<String, GateType>,
+    /// the names of the circuits being expanded, innermost last
+    expanding: Vec<Identifier>,
+    /// the gate groups to draw, from the settings and from expanded circuits
+    gate_groups: Vec<GateGroup>,
+    /// the qubits spanned by each gate drawn as a box over several wires, such as a circuit
+    /// drawn as a box or a gate with several targets, keyed on column
+    wide_gates: HashMap<u32, Vec<Qubit>>,
+    /// whether gate names and parameters are written without LaTeX markup, for renderers other
+    /// than LaTeX
+    plain_text: bool,
+}
+
+impl Diagram {
+    /// Render the "controlled" relationships recorded at each column as `\ctrl{}` commands on the
+    /// control wires, joined to the nearest target, and, for a controlled `NOT`, `\targ{}` on the
+    /// target wire.
+    fn set_ctrl_targ(&mut self) -> Result<(), LatexGenError> {
+        let rows: HashMap<Qubit, i64> = self
+            .order
+            .iter()
+            .enumerate()
+            .map(|(row, name)| (name.clone(), row as i64))
+            .collect();
+
+        for (column, ControlRoles { controls, targets }) in &self.relationships {
+            if controls.is_empty() || targets.is_empty() {
+                return Err(LatexGenError::FoundCNOTWithNoTarget);
+            }
+
+            for control in controls {
+                let row = rows[control];
+                let offset = targets
+                    .iter()
+                    .map(|target| rows[target] - row)
+                    .min_by_key(|offset| offset.abs())
+                    .unwrap_or_default();
+                if let Some(wire) = self.circuit.get_mut(control) {
+                    wire.cells.insert(*column, Cell::Control(offset));
+                }
+            }
+
+            if let [target] = targets.as_slice() {
+                if let Some(wire) = self.circuit.get_mut(target) {
+                    if let Some(Cell::Gate(gate)) = wire.cells.get(column) {
+                        if gate.is_not() {
+                            wire.cells.insert(*column, Cell::Target);
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Order the wires with those listed in the `wire_order` setting first. The remaining wires
+    /// follow with fixed qubits in order of index and then variable qubits in order of name.
+    fn set_order(&mut self) -> Result<(), LatexGenError> {
+        let wire_order = match &self.pragma_wire_order {
+            Some(wire_order) if self.settings.wire_order.is_empty() => wire_order,
+            _ => &self.settings.wire_order,
+        };
+
+        let mut order = Vec::with_capacity(self.circuit.len());
+        for index in wire_order {
+            let qubit = Qubit::Fixed(*index);
+            if order.contains(&qubit) {
+                return Err(LatexGenError::DuplicateQubitInWireOrder(*index));
+            }
+            if !self.circuit.contains_key(&qubit) {
+                return Err(LatexGenError::UnknownQubitInWireOrder(*index));
+            }
+            order.push(qubit);
+        }
+
+        let remaining: Vec<Qubit> = self
+            .circuit
+            .keys()
+            .filter(|qubit| !order.contains(qubit))
+            .cloned()
+            .collect();
+        order.extend(remaining);
+
+        self.order = order;
+        Ok(())
+    }
+
+    /// Apply a layout hint given as a pragma. Pragmas which are not layout hints are ignored.
+    ///
+    /// - `PRAGMA LATEX_COLUMN_BREAK` starts a new row of the diagram at the next column.
+    /// - `PRAGMA LATEX_ROW_ORDER 2 0 1` draws the wires of the listed qubits first, in order, as
+    ///   the `wire_order` setting does.
+    /// - `PRAGMA LATEX_LABEL "state prep"` writes a label above the top wire at the next column,
+    ///   or, as in `PRAGMA LATEX_LABEL 1 "ancilla"`, above the wires of the given qubits.
+    /// - `PRAGMA LATEX_SLICE "t=1"` draws a labelled dashed line across every wire after the last
+    ///   column. A slice before the first column is not drawn.
+    /// - `PRAGMA LATEX_GROUP 0 1 "oracle"` and a later `PRAGMA LATEX_END_GROUP` draw a gate group
+    ///   with the given label around the columns between them, spanning the wires of the given
+    ///   qubits, or every wire if none are given. Groups may be nested.
+    ///
+    /// Labels are inserted into the diagram verbatim, so they may contain LaTeX markup.
+    fn push_pragma(&mut self, pragma: &Pragma) -> Result<(), LatexGenError> {
+        let label = || {
+            pragma
+                .data
+                .clone()
+                .ok_or_else(|| LatexGenError::MissingPragmaLabel(pragma.name.to_string()))
+        };
+        let qubits = || -> Vec<Qubit> {
+            pragma
+                .arguments
+                .iter()
+                .map(|argument| match argument {
+                    PragmaArgument::Integer(index) => Qubit::Fixed(*index),
+                    PragmaArgument::Identifier(name) => Qubit::Variable(name.clone()),
+                })
+                .collect()
+        };
+
+        match pragma.name.as_str() {
+            COLUMN_BREAK_PRAGMA => {
+                self.column_breaks.insert(self.column);
+            }
+            ROW_ORDER_PRAGMA => {
+                let wire_order = pragma
+                    .arguments
+                    .iter()
+                    .map(|argument| match argument {
+                        PragmaArgument::Integer(index) => Ok(*index),
+                        PragmaArgument::Identifier(_) => Err(LatexGenError::InvalidLayoutPragma),
+                    })
+                    .collect::<Result<_, _>>()?;
+                self.pragma_wire_order = Some(wire_order);
+            }
+            LABEL_PRAGMA => {
+                let label = label()?;
+                let qubits = qubits();
+                if qubits.is_empty() {
+                    self.labels.insert(self.column, label);
+                } else {
+                    for qubit in qubits {
+                        let mut wire = Wire::new(qubit);
+                        wire.annotations.insert(self.column, label.clone());
+                        self.push_wire(wire);
+                    }
+                }
+            }
+            SLICE_PRAGMA => {
+                if let Some(column) = self.column.checked_sub(1) {
+                    self.slices
+                        .insert(column, pragma.data.clone().unwrap_or_default());
+                }
+            }
+            GROUP_PRAGMA => self.open_groups.push(GateGroup {
+                columns: self.column..self.column,
+                qubits: qubits(),
+                label: label()?,
+            }),
+            END_GROUP_PRAGMA => {
+                let mut group = self
+                    .open_groups
+                    .pop()
+                    .ok_or(LatexGenError::UnmatchedEndGroupPragma)?;
+                group.columns.end = self.column;
+                self.gate_groups.push(group);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Merge the contents of `wire` into the wire of the same name in the circuit, adding it to
+    /// the circuit if it is not yet present.
+    fn push_wire(&mut self, wire: Wire) {
+        match self.circuit.get_mut(&wire.name) {
+            Some(existing) => {
+                existing.cells.extend(wire.cells);
+                existing.annotations.extend(wire.annotations);
+                existing.styles.extend(wire.styles);
+            }
+            None => {
+                self.circuit.insert(wire.name.clone(), Box::new(wire));
+            }
+        }
+    }
+
+    /// Add a gate to the next column of the diagram, annotated with its cost in `costs`, if any.
+    /// `index` is the index of the gate among the instructions being rendered.
+    fn push_gate(
+        &mut self,
+        gate: &Gate,
+        index: usize,
+        costs: Option<&CostModel>,
+    ) -> Result<(), LatexGenError> {
+        let qubits = &gate.qubits;
+
+        let boxed = self.settings.circuit_display == CircuitDisplay::Box
+            && self.circuits.contains_key(gate.name.as_str())
+            && gate
+                .modifiers
+                .iter()
+                .all(|modifier| *modifier == GateModifier::Dagger);
+
+        let overridden = self.settings.gate_name_overrides.get(gate.name.as_str());
+        let defined = self.definitions.get(gate.name.as_str());
+        let mut cell = GateCell::new(
+            overridden.cloned().unwrap_or_else(|| gate.name.to_string()),
+            self.plain_text,
+        );
+        let mut controls = 0;
+        for modifier in &gate.modifiers {
+            match modifier {
+                GateModifier::Controlled => controls += 1,
+                GateModifier::Dagger if self.plain_text => cell.superscripts.push("†".to_string()),
+                GateModifier::Dagger => cell.superscripts.push(r"\dagger".to_string()),
+                GateModifier::Forked => return Err(LatexGenError::UnsupportedModifierForked),
+            }
+        }
+        // Standard gates written in composite form, such as `CNOT` or `CCNOT`, are drawn as
+        // their base gate with controls, unless the program defines a gate of the same name.
+        if let (false, None, None, Some((_, composite_controls, base))) = (
+            boxed,
+            overridden,
+            defined,
+            CONTROLLED_GATES
+                .iter()
+                .find(|(name, ..)| *name == gate.name),
+        ) {
+            cell.name = base.to_string();
+            controls += composite_controls;
+        }
+        // In LaTeX, a name such as `RZ_12` is drawn with the part after the underscore as a
+        // subscript, as variables in parameters are.
+        if let (false, None, Some((base, subscript))) =
+            (self.plain_text, overridden, cell.name.split_once('_'))
+        {
+            if !base.is_empty() && !subscript.is_empty() {
+                cell.subscripts.push(subscript.to_string());
+                cell.name = base.to_string();
+            }
+        }
+        cell.params = gate
+            .parameters
+            .iter()
+            .map(|parameter| render_param(parameter, &self.settings, self.plain_text))
+            .collect();
+
+        // The controls come first, and the gate applies to the remaining qubits, whatever its
+        // arity.
+        if controls > 0 && controls >= qubits.len() {
+            return Err(LatexGenError::FoundCNOTWithNoTarget);
+        }
+        let (control_qubits, targets) = qubits.split_at(controls);
+        if controls > 0 {
+            self.relationships.insert(
+                self.column,
+                ControlRoles {
+                    controls: control_qubits.to_vec(),
+                    targets: targets.to_vec(),
+                },
+            );
+        }
+
+        let annotation = costs
+            .and_then(|costs| costs.get(gate))
+            .and_then(|cost| cost.to_annotation())
+            .or_else(|| {
+                defined
+                    .filter(|kind| {
+                        self.settings.annotate_gate_definitions && **kind != GateType::Matrix
+                    })
+                    .map(|kind| self.text_annotation(&kind.to_string()))
+            });
+        cell.style = self
+            .settings
+            .instruction_styles
+            .get(&index)
+            .or_else(|| self.settings.gate_styles.get(gate.name.as_str()))
+            .cloned();
+
+        // A gate with several targets, or a circuit drawn as a box, is drawn as a box over the
+        // wires of its targets. It is placed on its first target until the wires are ordered, and
+        // then moved to the topmost wire it spans.
+        let target = if boxed || targets.len() > 1 {
+            self.wide_gates.insert(self.column, targets.to_vec());
+            targets.first()
+        } else {
+            targets.last()
+        };
+        for qubit in qubits {
+            let mut wire = Wire::new(qubit.clone());
+            if Some(qubit) == target {
+                wire.cells.insert(self.column, Cell::Gate(cell.clone()));
+                if let Some(annotation) = &annotation {
+                    wire.annotations.insert(self.column, annotation.clone());
+                }
+            }
+            self.push_wire(wire);
+        }
+
+        self.column += 1;
+        Ok(())
+    }
+
+    /// Lay out the instruction at `index` of the instructions being rendered. Instructions other
+    /// than gates, measurements, and layout pragmas are skipped.
+    fn push_instruction(
+        &mut self,
+        instruction: &Instruction,
+        index: usize,
+        costs: Option<&CostModel>,
+    ) -> Result<(), LatexGenError> {
+        self.push_instruction_cells(instruction, index, costs)
+            .map_err(|error| match error {
+                LatexGenError::InInstruction { .. } => error,
+                error => LatexGenError::InInstruction {
+                    index,
+                    instruction: instruction.to_string(),
+                    source: Box::new(error),
+                },
+            })
+    }
+
+    /// Lay out `instruction` like [`Diagram::push_instruction`], without adding its context to
+    /// any error.
+    fn push_instruction_cells(
+        &mut self,
+        instruction: &Instruction,
+        index: usize,
+        costs: Option<&CostModel>,
+    ) -> Result<(), LatexGenError> {
+        match instruction {
+            Instruction::Gate(gate) if !self.expanding.contains(&gate.name) => {
+                let expansion = self
+                    .circuits
+                    .get(gate.name.as_str())
+                    .filter(|_| self.settings.circuit_display == CircuitDisplay::Grouped)
+                    .and_then(|definition| definition.expand(gate).ok());
+                match expansion {
+                    Some(body) => self.push_circuit(gate, &body, index, costs)?,
+                    None => self.push_styled_gate(instruction, gate, index, costs)?,
+                }
+            }
+            Instruction::Gate(gate) => self.push_styled_gate(instruction, gate, index, costs)?,
+            Instruction::CircuitDefinition(definition)
+                if self.settings.circuit_display != CircuitDisplay::Gate =>
+            {
+                self.circuits
+                    .insert(definition.name.to_string(), definition.clone());
+            }
+            Instruction::Measurement(measurement) => self.push_measurement(measurement),
+            Instruction::Pragma(pragma) => self.push_pragma(pragma)?,
+            Instruction::Pulse(Pulse { frame, .. }) if self.settings.pulse_markers => {
+                let annotation = self.text_annotation(&frame.name);
+                self.push_marker("PULSE", &frame.qubits, annotation);
+            }
+            Instruction::Capture(Capture { frame, .. }) if self.settings.pulse_markers => {
+                let annotation = self.text_annotation(&frame.name);
+                self.push_marker("CAPTURE", &frame.qubits, annotation);
+            }
+            Instruction::RawCapture(RawCapture { frame, .. }) if self.settings.pulse_markers => {
+                let annotation = self.text_annotation(&frame.name);
+                self.push_marker("RAW-CAPTURE", &frame.qubits, annotation);
+            }
+            Instruction::Delay(Delay {
+                duration, qubits, ..
+            }) if self.settings.pulse_markers => {
+                let annotation = render_param(duration, &self.settings, self.plain_text);
+                self.push_marker("DELAY", qubits, annotation);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Lay out the `body` of the circuit invoked by `gate`, in a gate group labelled with the name
+    /// of the circuit.
+    fn push_circuit(
+        &mut self,
+        gate: &Gate,
+        body: &[Instruction],
+        index: usize,
+        costs: Option<&CostModel>,
+    ) -> Result<(), LatexGenError> {
+        let start = self.column;
+        self.expanding.push(gate.name.clone());
+        for instruction in body {
+            self.push_instruction(instruction, index, costs)?;
+        }
+        self.expanding.pop();
+
+        self.gate_groups.push(GateGroup {
+            columns: start..self.column,
+            qubits: gate.qubits.clone(),
+            label: gate.name.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Add a gate to the next column of the diagram, with the cell of each of its wires styled by
+    /// the `cell_styler` setting, if any.
+    fn push_styled_gate(
+        &mut self,
+        instruction: &Instruction,
+        gate: &Gate,
+        index: usize,
+        costs: Option<&CostModel>,
+    ) -> Result<(), LatexGenError> {
+        self.push_gate(gate, index, costs)?;
+        if let Some(styler) = &self.settings.cell_styler {
+            let column = self.column - 1;
+            for qubit in &gate.qubits {
+                let style = styler.style(instruction, qubit, column);
+                if let Some(wire) = self.circuit.get_mut(qubit) {
+                    wire.styles.insert(column, style);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Add a marker for the pulse-level operation `kind` to the next column of the diagram, on the
+    /// wire of each of `qubits`, annotated with `annotation`.
+    fn push_marker(&mut self, kind: &str, qubits: &[Qubit], annotation: String) {
+        if qubits.is_empty() {
+            return;
+        }
+        let text = if self.plain_text {
+            kind.to_string()
+        } else {
+            Symbol::Text(kind.to_string()).to_string()
+        };
+        for qubit in qubits {
+            let mut wire = Wire::new(qubit.clone());
+            wire.cells.insert(self.column, Cell::Marker(text.clone()));
+            wire.annotations.insert(self.column, annotation.clone());
+            self.push_wire(wire);
+        }
+        self.column += 1;
+    }
+
+    /// The annotation writing `text`, such as the name of the frame of a pulse-level operation,
+    /// above a cell.
+    fn text_annotation(&self, text: &str) -> String {
+        if self.plain_text {
+            text.to_string()
+        } else {
+            Symbol::Text(text.to_string()).to_string()
+        }
+    }
+
+    /// Add a measurement to the next column of the diagram.
+    fn push_measurement(&mut self, measurement: &Measurement) {
+        let mut wire = Wire::new(measurement.qubit.clone());
+        wire.cells.insert(self.column, Cell::Measurement);
+        self.push_wire(wire);
+        if let (true, Some(target)) = (self.settings.classical_wires, &measurement.target) {
+            self.classical_wires
+                .entry(target.name.to_string())
+                .or_default()
+                .insert(self.column, target.index);
+        }
+        self.column += 1;
+    }
+
+    /// The row of the classical wire written to by the measurement at each column.
+    fn measurement_rows(&self) -> HashMap<u32, usize> {
+        self.classical_wires
+            .values()
+            .enumerate()
+            .flat_map(|(i, indices)| {
+                indices
+                    .keys()
+                    .map(move |column| (*column, self.order.len() + i))
+            })
+            .collect()
+    }
+
+    /// The gate groups starting in the row of the diagram drawing `columns`, keyed on their
+    /// top-left cell. Groups are cut off at the end of the row.
+    fn row_groups(&self, columns: &Range<u32>) -> HashMap<(usize, u32), Vec<Command>> {
+        let wire_rows: HashMap<&Qubit, usize> = self
+            .order
+            .iter()
+            .enumerate()
+            .map(|(row, name)| (name, row))
+            .collect();
+        let mut groups: HashMap<(usize, u32), Vec<Command>> = HashMap::new();
+        for group in &self.gate_groups {
+            if group.columns.is_empty() || !columns.contains(&group.columns.start) {
+                continue;
+            }
+            let rows: Vec<usize> = if group.qubits.is_empty() {
+                (0..self.order.len()).collect()
+            } else {
+                group
+                    .qubits
+                    .iter()
+                    .filter_map(|qubit| wire_rows.get(qubit).copied())
+                    .collect()
+            };
+            if let (Some(top), Some(bottom)) = (rows.iter().min(), rows.iter().max()) {
+                groups
+                    .entry((*top, group.columns.start))
+                    .or_default()
+                    .push(Command::GateGroup {
+                        wires: bottom - top + 1,
+                        steps: group.columns.end.min(columns.end) - group.columns.start,
+                        label: group.label.clone(),
+                    });
+            }
+        }
+        groups
+    }
+
+    /// What is drawn at the left end of `wire`: its label, if the qubit lines are labelled, or
+    /// else only the wire.
+    fn input_label(&self, wire: &Wire) -> Command {
+        if !self.settings.label_qubit_lines {
+            return Command::Qw;
+        }
+        let label = match &wire.name {
+            Qubit::Fixed(index) => self.settings.qubit_labels.get(index),
+            Qubit::Variable(_) => None,
+        };
+        match label {
+            Some(label) => Command::LstickLabel(label.clone()),
+            None => Command::Lstick(wire.name.clone()),
+        }
+    }
+
+    /// The label drawn at the right end of `wire` by the `output_labels` setting, if any.
+    fn output_label(&self, wire: &Wire) -> Option<Command> {
+        match (&self.settings.output_labels, &wire.name) {
+            (OutputLabels::None, _) => None,
+            (OutputLabels::Qubits, name) => Some(Command::Rstick(name.clone())),
+            (OutputLabels::Custom(labels), Qubit::Fixed(index)) => labels
+                .get(index)
+                .map(|label| Command::RstickLabel(label.clone())),
+            (OutputLabels::Custom(_), Qubit::Variable(_)) => None,
+        }
+    }
+
+    /// Write the `tikzcd` environment containing the columns `columns` of every wire.
+    fn write_row(
+        &self,
+        f: &mut fmt::Formatter,
+        columns: Range<u32>,
+        continued_from_left: bool,
+        continued_to_right: bool,
+    ) -> fmt::Result {
+        writeln!(f, r"\begin{{tikzcd}}{}", self.settings.tikzcd_options)?;
+
+        if self.order.is_empty() && self.settings.empty_diagram == EmptyDiagram::PlaceholderWire {
+            writeln!(f, "{} & {}", Command::Qw, Command::Qw)?;
+        }
+
+        let row_count = self.order.len() + self.classical_wires.len();
+        let measurement_rows = self.measurement_rows();
+        let groups = self.row_groups(&columns);
+
+        for (i, wire) in self
+            .order
+            .iter()
+            .map(|name| &self.circuit[name])
+            .enumerate()
+        {
+            write!(f, "{}", self.input_label(wire))?;
+            if continued_from_left {
+                write!(f, r" \cdots")?;
+            }
+
+            for column in columns.clone() {
+                let command = wire.get_command(column);
+                write!(f, " & {command}")?;
+                if let (Command::Meter, Some(row)) = (&command, measurement_rows.get(&column)) {
+                    write!(f, " {}", Command::Vcw(*row as i64 - i as i64))?;
+                }
+                if let Some(annotation) = wire.annotations.get(&column) {
+                    write!(f, " {}", Command::Annotation(annotation.clone()))?;
+                }
+                if i == 0 {
+                    if let Some(label) = self.labels.get(&column) {
+                        write!(f, " {}", Command::Annotation(label.clone()))?;
+                    }
+                    if let Some(label) = self.slices.get(&column) {
+                        write!(f, " {}", Command::Slice(label.clone()))?;
+                    }
+                }
+                for group in groups.get(&(i, column)).into_iter().flatten() {
+                    write!(f, " {group}")?;
+                }
+            }
+
+            write!(f, " & {}", Command::Qw)?;
+            if continued_to_right {
+                write!(f, r" \rstick{{\cdots}}")?;
+            } else if let Some(label) = self.output_label(wire) {
+                write!(f, " {label}")?;
+            }
+
+            if i + 1 < row_count {
+                write!(f, " {}", Command::Nr)?;
+            }
+            writeln!(f)?;
+        }
+
+        for (i, (name, indices)) in self.classical_wires.iter().enumerate() {
+            if self.settings.label_qubit_lines {
+                let label = Symbol::Text(name.clone()).to_string();
+                write!(f, "{}", Command::LstickLabel(label))?;
+            } else {
+                write!(f, "{}", Command::Cw)?;
+            }
+            if continued_from_left {
+                write!(f, r" \cdots")?;
+            }
+
+            for column in columns.clone() {
+                write!(f, " & {}", Command::Cw)?;
+                if let Some(index) = indices.get(&column) {
+                    write!(f, " {}", Command::Annotation(index.to_string()))?;
+                }
+            }
+
+            write!(f, " & {}", Command::Cw)?;
+            if continued_to_right {
+                write!(f, r" \rstick{{\cdots}}")?;
+            }
+
+            if self.order.len() + i + 1 < row_count {
+                write!(f, " {}", Command::Nr)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, r"\end{{tikzcd}}")
+    }
+
+    /// The columns drawn in each row of the diagram. A new row is started at every column break
+    /// and whenever a row reaches the `max_columns_per_row` setting.
+    pub fn rows(&self) -> Vec<Range<u32>> {
+        let mut boundaries = vec![0];
+        boundaries.extend(
+            self.column_breaks
+                .iter()
+                .copied()
+                .filter(|column| *column > 0 && *column < self.column),
+        );
+        boundaries.push(self.column);
+        boundaries.dedup();
+
+        let width = match self.settings.max_columns_per_row {
+            Some(width) if width > 0 => width,
+            _ => self.column.max(1),
+        };
+
+        let mut rows: Vec<_> = boundaries
+            .windows(2)
+            .flat_map(|segment| {
+                (segment[0]..segment[1])
+                    .step_by(width as usize)
+                    .map(move |start| start..(start + width).min(segment[1]))
+            })
+            .collect();
+        if rows.is_empty() {
+            // An empty diagram is drawn as a single row of empty wires.
+            rows.push(0..0);
+        }
+        rows
+    }
+
+    /// The number of rows the diagram is split into when rendered.
+    fn row_count(&self) -> usize {
+        self.rows().len()
+    }
+}
+
+impl fmt::Display for Diagram {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let rows = self.rows();
+
+        for (row, columns) in rows.iter().enumerate() {
+            if row > 0 {
+                writeln!(f)?;
+            }
+            let (continued_from_left, continued_to_right) = (row > 0, row < rows.len() - 1);
+            match self.settings.backend {
+                LatexBackend::Quantikz => {
+                    self.write_row(f, columns.clone(), continued_from_left, continued_to_right)?
+                }
+                LatexBackend::Qcircuit => self.write_qcircuit_row(
+                    f,
+                    columns.clone(),
+                    continued_from_left,
+                    continued_to_right,
+                )?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A backend which draws a laid-out [`Diagram`], such as [`LatexRenderer`] or the text renderer
+/// in [`crate::program::text_diagram`]. Renderers share the layout of the diagram: its wires, in
+/// [`Diagram::wires`], its rows, in [`Diagram::rows`], and the [`Command`] in each cell.
+pub trait Renderer {
+    fn render(&self, diagram: &Diagram) -> String;
+}
+
+/// Draws a diagram as the `tikzcd` environment(s) of a Quantikz circuit, as the diagram's
+/// [`fmt::Display`] implementation does.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct LatexRenderer;
+
+impl Renderer for LatexRenderer {
+    fn render(&self, diagram: &Diagram) -> String {
+        diagram.to_string()
+    }
+}
+
+/// A Wire represents a single qubit. A wire only needs to keep track of all the elements it
+/// contains mapped to some arbitrary column. The [`Diagram`] keeps track of where the wire
+/// belongs in the larger circuit and how each wire relates to the others at a given column.
+#[derive(Clone, Debug)]
+pub struct Wire {
+    /// the qubit this wire represents
+    name: Qubit,
+    /// what is drawn at each column which is not empty
+    cells: HashMap<u32, Cell>,
+    /// the text drawn above the cell at each column
+    annotations: HashMap<u32, String>,
+    /// the overrides chosen by the `cell_styler` setting for the cell at each column
+    styles: HashMap<u32, CellStyle>,
+}
+
+/// What is drawn in one cell of a [`Wire`], as laid out before being rendered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Cell {
+    /// A gate, drawn in a box.
+    Gate(GateCell),
+    /// A control, joined to the target the given number of wires below (or above, if negative).
+    Control(i64),
+    /// The target of a controlled-not.
+    Target,
+    /// A measurement.
+    Measurement,
+    /// A pulse-level operation, such as a `PULSE` or `CAPTURE`, drawn as a small dashed box with
+    /// the given text.
+    Marker(String),
+}
+
+/// A gate drawn in a [`Cell`]: its name, decorated with any subscripts and superscripts,
+/// followed by its rendered parameters, and how its box is drawn.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GateCell {
+    pub name: String,
+    /// The rendered parameters of the gate.
+    pub params: Vec<String>,
+    /// Drawn together as a single superscript, such as `\dagger` for each `DAGGER` modifier.
+    pub superscripts: Vec<String>,
+    /// Drawn together as a single subscript, separated by commas.
+    pub subscripts: Vec<String>,
+    /// The Quantikz style options of the box, from the `gate_styles` or `instruction_styles`
+    /// settings.
+    pub style: Option<String>,
+    /// The number of wires the box spans, if more than one.
+    pub span: Option<usize>,
+    /// whether the cell is written without LaTeX markup, so that its superscripts follow its
+    /// name directly and its subscripts are dropped
+    plain_text: bool,
+}
+
+impl GateCell {
+    fn new(name: String, plain_text: bool) -> Self {
+        Self {
+            name,
+            params: vec![],
+            superscripts: vec![],
+            subscripts: vec![],
+            style: None,
+            span: None,
+            plain_text,
+        }
+    }
+
+    /// Whether the gate is an undecorated `NOT` (or `X`), which is drawn as the target of a
+    /// controlled-not when controlled.
+    fn is_not(&self) -> bool {
+        matches!(self.name.as_str(), "NOT" | "X")
+            && self.superscripts.is_empty()
+            && self.subscripts.is_empty()
+    }
+}
+
+/// The text of the gate: its name and parameters, with its subscripts and superscripts. Any
+/// text chosen by the `cell_styler` setting is not included.
+impl fmt::Display for GateCell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name)?;
+        if self.plain_text {
+            write!(f, "{}", self.superscripts.concat())?;
+        } else {
+            if !self.subscripts.is_empty() {
+                write!(f, "_{{{}}}", self.subscripts.join(","))?;
+            }
+            if !self.superscripts.is_empty() {
+                write!(f, "^{{{}}}", self.superscripts.concat())?;
+            }
+        }
+        if !self.params.is_empty() {
+            write!(f, "({})", self.params.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Render a parameter of a gate. If `texify_numerical_constants` is set in `settings`, the
+/// parameter is rendered as LaTeX math; otherwise it is rendered as text. If `plain_text` is set,
+/// the parameter is written as Quil, without any LaTeX markup.
+fn render_param(param: &Expression, settings: &RenderSettings, plain_text: bool) -> String {
+    settings.float_format.scope(|| {
+        if plain_text {
+            // The outermost operation is parenthesized when displayed, which would double the
+            // parentheses around the gate's parameters.
+            match param {
+                Expression::Infix { .. } | Expression::Prefix { .. } => {
+                    let quil = param.to_string();
+                    quil[1..quil.len() - 1].to_string()
+                }
+                _ => param.to_string(),
+            }
+        } else if settings.texify_numerical_constants {
+            expression::render(param)
+        } else {
+            Symbol::Text(param.to_string()).to_string()
+        }
+    })
+}
+
+impl Wire {
+    /// Create an empty wire for the qubit `name`.
+    fn new(name: Qubit) -> Self {
+        Self {
+            name,
+            cells: HashMap::new(),
+            annotations: HashMap::new(),
+            styles: HashMap::new(),
+        }
+    }
+
+    /// Whether nothing is drawn on the wire.
+    fn is_idle(&self) -> bool {
+        self.cells.is_empty()
+    }
+
+    /// The gate at `column`, if any.
+    fn gate_mut(&mut self, column: u32) -> Option<&mut GateCell> {
+        match self.cells.get_mut(&column) {
+            Some(Cell::Gate(gate)) => Some(gate),
+            _ => None,
+        }
+    }
+
+    /// The qubit this wire represents.
+    pub fn qubit(&self) -> &Qubit {
+        &self.name
+    }
+
+    /// What is drawn at `column`, or `None` if only the wire is drawn.
+    pub fn cell(&self, column: u32) -> Option<&Cell> {
+        self.cells.get(&column)
+    }
+
+    /// The text drawn above the cell at `column`, if any, such as the cost of a gate.
+    pub fn annotation(&self, column: u32) -> Option<&str> {
+        self.annotations.get(&column).map(String::as_str)
+    }
+
+    /// The Quantikz style options chosen by the `cell_styler` setting for the cell at `column`, if
+    /// any.
+    fn override_style(&self, column: u32) -> Option<&String> {
+        self.styles
+            .get(&column)
+            .and_then(|style| style.style.as_ref())
+    }
+
+    /// Return the command to render at `column`.
+    pub fn get_command(&self, column: u32) -> Command {
+        match self.cells.get(&column) {
+            Some(Cell::Control(offset)) => match self.override_style(column) {
+                Some(style) => Command::StyledCtrl(*offset, style.clone()),
+                None => Command::Ctrl(*offset),
+            },
+            Some(Cell::Target) => match self.override_style(column) {
+                Some(style) => Command::StyledTarg(style.clone()),
+                None => Command::Targ,
+            },
+            Some(Cell::Measurement) => Command::Meter,
+            Some(Cell::Marker(text)) => Command::StyledGate(text.clone(), MARKER_STYLE.to_string()),
+            Some(Cell::Gate(gate)) => {
+                let overrides = self.styles.get(&column);
+                let text = match overrides.and_then(|o| o.text.as_ref()) {
+                    Some(text) => text.clone(),
+                    None => gate.to_string(),
+                };
+                let style = overrides
+                    .and_then(|o| o.style.as_ref())
+                    .or(gate.style.as_ref());
+                match (overrides.and_then(|o| o.shape).unwrap_or_default(), style) {
+                    (CellShape::Phase, _) => Command::Phase(text),
+                    (CellShape::Box, style) if gate.span.is_some() => Command::WideGate {
+                        wires: gate.span.unwrap_or(1),
+                        name: text,
+                        style: style.cloned(),
+                    },
+                    (CellShape::Box, Some(style)) => Command::StyledGate(text, style.clone()),
+                    (CellShape::Box, None) => Command::Gate(text),
+                }
+            }
+            None => Command::Qw,
+        }
+    }
+}
+
+/// Errors which may occur while generating a LaTeX diagram.
+#[derive(Clone, Debug, thiserror::Error, PartialEq, Eq)]
+pub enum LatexGenError {
+    #[error("Tried to parse CNOT and found a control qubit without a target.")]
+    FoundCNOTWithNoTarget,
+    #[error("The FORKED modifier is unsupported.")]
+    UnsupportedModifierForked,
+    #[error("Qubit {0} is listed more than once in the wire order.")]
+    DuplicateQubitInWireOrder(u64),
+    #[error("Qubit {0} is listed in the wire order but has no wire in the diagram.")]
+    UnknownQubitInWireOrder(u64),
+    #[error("There are no qubits to render.")]
+    NoQubitsToRender,
+    #[error("Qubit {0} is listed in a gate group but has no wire in the diagram.")]
+    UnknownQubitInGateGroup(Qubit),
+    #[error("The arguments of a layout pragma must be qubit indices.")]
+    InvalidLayoutPragma,
+    #[error("PRAGMA {0} requires a label, as in `PRAGMA {0} \"label\"`.")]
+    MissingPragmaLabel(String),
+    #[error("PRAGMA LATEX_END_GROUP has no matching PRAGMA LATEX_GROUP.")]
+    UnmatchedEndGroupPragma,
+    #[error("A PRAGMA LATEX_GROUP is never closed by a PRAGMA LATEX_END_GROUP.")]
+    UnclosedGroupPragma,
+    #[error("The instruction range {start}..{end} is out of bounds for a program of {len} instructions.")]
+    InstructionRangeOutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// An error laying out an instruction, with the instruction's index among those being
+    /// rendered. When the instruction is in the body of a circuit being expanded, the index is
+    /// that of the circuit's invocation.
+    #[error("In instruction {index} ({instruction}): {source}")]
+    InInstruction {
+        index: usize,
+        instruction: String,
+        source: Box<LatexGenError>,
+    },
+}
+
+/// Types which can be rendered as a LaTeX circuit diagram. Rendering only borrows the
+/// instructions, so a program can be drawn any number of times without being cloned.
+pub trait Latex {
+    /// Render `self` as a standalone LaTeX document containing a Quantikz circuit diagram.
+    ///
+    /// Gates and measurements are drawn, with a wire for each fixed or variable qubit; all other
+    /// instructions are skipped.
+    fn to_latex(&self, settings: RenderSettings) -> Result<String, LatexGenError>;
+
+    /// Render `self` as the `tikzcd` environment(s) of a Quantikz circuit diagram, without the
+    /// surrounding document, for inclusion in an existing LaTeX document, notebook, or Sphinx page.
+    /// The including document must load the `tikz` package and its `quantikz` library, or, with
+    /// [`LatexBackend::Qcircuit`], the `qcircuit` package.
+    fn to_latex_fragment(&self, settings: RenderSettings) -> Result<String, LatexGenError>;
+
+    /// Render `self` as a standalone LaTeX document like [`Latex::to_latex`], writing the cost of
+    /// each gate in `costs`, such as its duration or fidelity, above the gate.
+    fn to_latex_with_costs(
+        &self,
+        settings: RenderSettings,
+        costs: &CostModel,
+    ) -> Result<String, LatexGenError>;
+
+    /// Lay out `self` as a diagram as [`Latex::to_latex`] would, and return the layout without
+    /// rendering it, to inspect its dimensions and cells.
+    fn to_latex_layout(&self, settings: RenderSettings) -> Result<LatexLayout, LatexGenError>;
+}
+
+impl Diagram {
+    /// Lay out the gates and measurements of `instructions` as a diagram, annotating gates with
+    /// their cost in `costs`, if given.
+    fn build(
+        instructions: &[Instruction],
+        settings: RenderSettings,
+        costs: Option<&CostModel>,
+    ) -> Result<Self, LatexGenError> {
+        let diagram = Diagram {
+            settings,
+            ..Default::default()
+        };
+        diagram.lay_out(instructions, costs)
+    }
+
+    /// Lay out the gates and measurements of `instructions` like [`Diagram::build`], with gate
+    /// names and parameters written as plain Quil without any LaTeX markup.
+    pub(crate) fn build_plain_text(
+        instructions: &[Instruction],
+        settings: RenderSettings,
+    ) -> Result<Self, LatexGenError> {
+        let diagram = Diagram {
+            settings,
+            plain_text: true,
+            ..Default::default()
+        };
+        diagram.lay_out(instructions, None)
+    }
+
+    /// Lay out `instructions` in this empty diagram.
+    fn lay_out(
+        mut self,
+        instructions: &[Instruction],
+        costs: Option<&CostModel>,
+    ) -> Result<Self, LatexGenError> {
+        // Gates and circuits may be invoked before they are defined.
+        for instruction in instructions {
+            match instruction {
+                Instruction::CircuitDefinition(definition)
+                    if self.settings.circuit_display != CircuitDisplay::Gate =>
+                {
+                    self.circuits
+                        .insert(definition.name.to_string(), definition.clone());
+                }
+                Instruction::GateDefinition(definition) => {
+                    self.definitions.insert(
+                        definition.name.to_string(),
+                        definition.specification.gate_type(),
+                    );
+                }
+                _ => {}
+            }
+        }
+        for (index, instruction) in instructions.iter().enumerate() {
+            self.push_instruction(instruction, index, costs)?;
+        }
+
+        self.finish()?;
+        Ok(self)
+    }
+
+    /// Complete the layout once every cell is placed: impute missing wires if requested, order
+    /// the wires, and connect controls to their targets.
+    fn finish(&mut self) -> Result<(), LatexGenError> {
+        if self.settings.impute_missing_qubits {
+            RenderSettings::impute_missing_qubits(&mut self.circuit);
+        }
+        if self.settings.omit_idle_wires {
+            self.omit_idle_wires();
+        }
+        if self.circuit.is_empty() && self.settings.empty_diagram == EmptyDiagram::Error {
+            return Err(LatexGenError::NoQubitsToRender);
+        }
+        if !self.open_groups.is_empty() {
+            return Err(LatexGenError::UnclosedGroupPragma);
+        }
+        for group in &self.settings.gate_groups {
+            if let Some(qubit) = group
+                .qubits
+                .iter()
+                .find(|qubit| !self.circuit.contains_key(qubit))
+            {
+                return Err(LatexGenError::UnknownQubitInGateGroup(qubit.clone()));
+            }
+        }
+        self.gate_groups
+            .extend(self.settings.gate_groups.iter().cloned());
+        self.set_order()?;
+        self.set_wide_gates();
+        self.set_ctrl_targ()?;
+        if self.settings.pack_moments {
+            self.pack_columns();
+        }
+        Ok(())
+    }
+
+    /// Move each column as far left as it goes without its cells, or the lines joining them,
+    /// overlapping those of an earlier column on any wire, as set by the `pack_moments` setting.
+    fn pack_columns(&mut self) {
+        let row_count = self.order.len() + self.classical_wires.len();
+        let mut boundaries = self.column_breaks.clone();
+        boundaries.extend(self.labels.keys().copied());
+        boundaries.extend(self.slices.keys().map(|column| column + 1));
+        for group in &self.gate_groups {
+            boundaries.extend([group.columns.start, group.columns.end]);
+        }
+
+        // The column each column is moved to, and where each boundary is moved to.
+        let mut scheduler = Scheduler::default();
+        let mut moved = Vec::with_capacity(self.column as usize);
+        let mut starts = HashMap::new();
+        for column in 0..=self.column {
+            if boundaries.contains(&column) {
+                scheduler.barrier();
+                starts.insert(column, scheduler.len() as u32);
+            }
+            if column == self.column {
+                break;
+            }
+
+            let measured = self
+                .classical_wires
+                .values()
+                .any(|indices| indices.contains_key(&column));
+            let spans: Vec<(usize, usize)> = self
+                .wires()
+                .enumerate()
+                .filter_map(|(row, wire)| {
+                    let span = match wire.cells.get(&column)? {
+                        Cell::Gate(GateCell {
+                            span: Some(span), ..
+                        }) => (row, row + span - 1),
+                        Cell::Control(offset) => {
+                            let target = (row as i64 + offset) as usize;
+                            (row.min(target), row.max(target))
+                        }
+                        Cell::Measurement if measured => (row, row_count - 1),
+                        _ => (row, row),
+                    };
+                    Some(span)
+                })
+                .collect();
+            let rows = match (
+                spans.iter().map(|span| span.0).min(),
+                spans.iter().map(|span| span.1).max(),
+            ) {
+                (Some(top), Some(bottom)) => top..bottom + 1,
+                _ => 0..0,
+            };
+            moved.push(scheduler.place(rows) as u32);
+        }
+
+        for wire in self.circuit.values_mut() {
+            wire.cells = wire
+                .cells
+                .drain()
+                .map(|(column, cell)| (moved[column as usize], cell))
+                .collect();
+            wire.annotations = wire
+                .annotations
+                .drain()
+                .map(|(column, annotation)| (moved[column as usize], annotation))
+                .collect();
+            wire.styles = wire
+                .styles
+                .drain()
+                .map(|(column, style)| (moved[column as usize], style))
+                .collect();
+        }
+        for indices in self.classical_wires.values_mut() {
+            *indices = indices
+                .drain()
+                .map(|(column, index)| (moved[column as usize], index))
+                .collect();
+        }
+        self.labels = self
+            .labels
+            .drain()
+            .map(|(column, label)| (starts[&column], label))
+            .collect();
+        self.slices = self
+            .slices
+            .drain()
+            .map(|(column, label)| (starts[&(column + 1)] - 1, label))
+            .collect();
+        self.column_breaks = self
+            .column_breaks
+            .iter()
+            .map(|column| starts[column])
+            .collect();
+        for group in &mut self.gate_groups {
+            group.columns = starts[&group.columns.start]..starts[&group.columns.end];
+        }
+        self.column = scheduler.len() as u32;
+    }
+
+    /// Remove the wires which nothing is drawn on, unless their qubits are named by the settings or
+    /// a layout pragma.
+    fn omit_idle_wires(&mut self) {
+        let wire_order = match (&self.settings.wire_order[..], &self.pragma_wire_order) {
+            ([], Some(order)) => order.as_slice(),
+            (order, _) => order,
+        };
+        let mut active: HashSet<Qubit> = wire_order
+            .iter()
+            .map(|index| Qubit::Fixed(*index))
+            .collect();
+        active.extend(
+            self.settings
+                .gate_groups
+                .iter()
+                .flat_map(|group| group.qubits.iter().cloned()),
+        );
+        for roles in self.relationships.values() {
+            active.extend(roles.controls.iter().cloned());
+            active.extend(roles.targets.iter().cloned());
+        }
+        active.extend(self.wide_gates.values().flatten().cloned());
+
+        self.circuit
+            .retain(|qubit, wire| active.contains(qubit) || !wire.is_idle());
+    }
+
+    /// Move each gate drawn over several wires from the wire of its first qubit to the topmost
+    /// wire it spans, and record the number of wires it spans.
+    fn set_wide_gates(&mut self) {
+        let rows: HashMap<&Qubit, usize> = self
+            .order
+            .iter()
+            .enumerate()
+            .map(|(row, name)| (name, row))
+            .collect();
+
+        for (column, qubits) in &self.wide_gates {
+            let spanned: Vec<usize> = qubits.iter().map(|qubit| rows[qubit]).collect();
+            let (top, bottom) = match (spanned.iter().min(), spanned.iter().max()) {
+                (Some(top), Some(bottom)) => (*top, *bottom),
+                _ => continue,
+            };
+
+            let placed = &qubits[0];
+            let top_qubit = &self.order[top];
+            if placed != top_qubit {
+                let cell = self
+                    .circuit
+                    .get_mut(placed)
+                    .map(|wire| (wire.cells.remove(column), wire.annotations.remove(column)));
+                if let (Some((cell, annotation)), Some(wire)) =
+                    (cell, self.circuit.get_mut(top_qubit))
+                {
+                    wire.cells.extend(cell.map(|cell| (*column, cell)));
+                    wire.annotations
+                        .extend(annotation.map(|annotation| (*column, annotation)));
+                }
+            }
+            if let Some(gate) = self
+                .circuit
+                .get_mut(top_qubit)
+                .and_then(|wire| wire.gate_mut(*column))
+            {
+                gate.span = Some(bottom - top + 1);
+            }
+        }
+    }
+
+    /// The number of columns on each wire.
+    pub fn column_count(&self) -> u32 {
+        self.column
+    }
+
+    /// The wires of the diagram, in the order they are drawn from top to bottom.
+    pub fn wires(&self) -> impl Iterator<Item = &Wire> {
+        self.order.iter().map(|name| self.circuit[name].as_ref())
+    }
+
+    /// Render the diagram as a standalone LaTeX document. The diagram's [`fmt::Display`]
+    /// implementation renders only its `tikzcd` environment(s).
+    pub fn to_document(&self) -> String {
+        Document::new(
+            self.to_string(),
+            self.row_count() > 1,
+            self.settings.backend,
+        )
+        .to_string()
+    }
+}
+
+/// A sequence of instructions, such as one block or moment of a longer program, is drawn without
+/// constructing a temporary [`Program`].
+impl Latex for [Instruction] {
+    fn to_latex(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        Ok(Diagram::build(self, settings, None)?.to_document())
+    }
+
+    fn to_latex_fragment(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        let diagram = Diagram::build(self, settings, None)?;
+        Ok(diagram.to_string())
+    }
+
+    fn to_latex_with_costs(
+        &self,
+        settings: RenderSettings,
+        costs: &CostModel,
+    ) -> Result<String, LatexGenError> {
+        Ok(Diagram::build(self, settings, Some(costs))?.to_document())
+    }
+
+    fn to_latex_layout(&self, settings: RenderSettings) -> Result<LatexLayout, LatexGenError> {
+        Ok(Diagram::build(self, settings, None)?.into())
+    }
+}
+
+impl Latex for Program {
+    fn to_latex(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        self.instructions.as_slice().to_latex(settings)
+    }
+
+    fn to_latex_fragment(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        self.instructions.as_slice().to_latex_fragment(settings)
+    }
+
+    fn to_latex_with_costs(
+        &self,
+        settings: RenderSettings,
+        costs: &CostModel,
+    ) -> Result<String, LatexGenError> {
+        self.instructions
+            .as_slice()
+            .to_latex_with_costs(settings, costs)
+    }
+
+    fn to_latex_layout(&self, settings: RenderSettings) -> Result<LatexLayout, LatexGenError> {
+        self.instructions.as_slice().to_latex_layout(settings)
+    }
+}
+
+impl Program {
+    /// Render the instructions of the program at indices `range` as a standalone LaTeX document,
+    /// like [`Latex::to_latex`], to draw one block of a long program.
+    pub fn to_latex_range(
+        &self,
+        range: Range<usize>,
+        settings: RenderSettings,
+    ) -> Result<String, LatexGenError> {
+        match self.instructions.get(range.clone()) {
+            Some(instructions) => instructions.to_latex(settings),
+            None => Err(LatexGenError::InstructionRangeOutOfBounds {
+                start: range.start,
+                end: range.end,
+                len: self.instructions.len(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitDisplay, Latex, LatexGenError, RenderSettings};
+    use crate::Program;
+    use std::str::FromStr;
+
+    /// Build the LaTeX document for the program `input`.
+    fn get_latex(input: &str, settings: RenderSettings) -> String {
+        Program::from_str(input)
+            .expect("program should be valid")
+            .to_latex(settings)
+            .expect("program should render")
+    }
+
+    mod document {
+        use super::{get_latex, LatexGenError, RenderSettings};
+        use crate::program::latex::{EmptyDiagram, Latex};
+        use crate::Program;
+        use rstest::rstest;
+        use std::str::FromStr;
+
+        #[test]
+        fn test_empty_program() {
+            insta::assert_snapshot!(get_latex("", RenderSettings::default()));
+        }
+
+        #[test]
+        fn test_fragment() {
+            let program = Program::from_str("H 0\nCNOT 0 1").unwrap();
+            let fragment = program
+                .clone()
+                .to_latex_fragment(RenderSettings::default())
+                .unwrap();
+            insta::assert_snapshot!(fragment);
+
+            let document = program.to_latex(RenderSettings::default()).unwrap();
+            assert!(document.contains(&fragment));
+        }
+
+        #[test]
+        fn test_classical_instructions_are_skipped() {
+            insta::assert_snapshot!(get_latex(
+                "DECLARE ro BIT\nMOVE ro 1\nH 0",
+                RenderSettings::default()
+            ));
+        }
+
+        #[test]
+        fn test_empty_program_placeholder_wire() {
+            let settings = RenderSettings {
+                empty_diagram: EmptyDiagram::PlaceholderWire,
+                ..Default::default()
+            };
+            let fragment = Program::from_str("DECLARE ro BIT\nMOVE ro 1")
+                .unwrap()
+                .to_latex_fragment(settings)
+                .unwrap();
+            assert_eq!(fragment, "\\begin{tikzcd}\n\\qw & \\qw\n\\end{tikzcd}\n");
+        }
+
+        #[rstest]
+        #[case("")]
+        #[case("DECLARE ro BIT\nMOVE ro 1")]
+        #[case("PRAGMA LATEX_COLUMN_BREAK")]
+        fn test_empty_program_error(#[case] input: &str) {
+            let settings = RenderSettings {
+                empty_diagram: EmptyDiagram::Error,
+                ..Default::default()
+            };
+            assert_eq!(
+                Program::from_str(input).unwrap().to_latex(settings),
+                Err(LatexGenError::NoQubitsToRender)
+            );
+        }
+    }
+
+    mod gates {
+        use super::{get_latex, RenderSettings};
+        use crate::expression::FloatFormat;
+        use rstest::rstest;
+
+        #[test]
+        fn test_gate_h() {
+            insta::assert_snapshot!(get_latex("H 0", RenderSettings::default()));
+        }
+
+        #[rstest]
+        #[case("CUSTOM 0", &[r"\gate{CUSTOM}"], 0)]
+        #[case("CAN(pi) 0 1", &[r"\gate[wires=2]{CAN(\pi)}"], 0)]
+        #[case("CZ 0 1", &[r"\gate{Z}"], 1)]
+        #[case("CPHASE(pi) 0 1", &[r"\gate{PHASE(\pi)}"], 1)]
+        #[case("CCNOT 0 1 2", &[r"\targ{}"], 2)]
+        #[case("CONTROLLED CNOT 0 1 2", &[r"\targ{}"], 2)]
+        #[case("CONTROLLED CUSTOM 0 1", &[r"\gate{CUSTOM}"], 1)]
+        #[case("CONTROLLED CONTROLLED X 0 1 2", &[r"\ctrl{2}", r"\ctrl{1}", r"\targ{}"], 2)]
+        #[case("CONTROLLED SWAP 0 1 2", &[r"\ctrl{1}", r"\gate[wires=2]{SWAP}"], 1)]
+        #[case("CONTROLLED SWAP 2 0 1", &[r"\ctrl{-1}", r"\gate[wires=2]{SWAP}"], 1)]
+        #[case("SWAP 0 1", &[r"\gate[wires=2]{SWAP}"], 0)]
+        fn test_gate_controls(
+            #[case] input: &str,
+            #[case] expected: &[&str],
+            #[case] controls: usize,
+        ) {
+            let latex = get_latex(input, RenderSettings::default());
+            for command in expected {
+                assert!(latex.contains(command), "{latex}");
+            }
+            assert_eq!(latex.matches(r"\ctrl").count(), controls, "{latex}");
+        }
+
+        #[test]
+        fn test_gate_parameters() {
+            insta::assert_snapshot!(get_latex(
+                "RX(pi) 0\nRZ(%alpha) 0\nRY(pi/2) 0\nRX(cos(sin(2*pi/3))*exp(1.0i*pi)) 0",
+                RenderSettings::default()
+            ));
+        }
+
+        #[test]
+        fn test_gate_parameters_greek_and_subscripted() {
+            insta::assert_snapshot!(get_latex(
+                "RZ(%theta_1) 0\nRX(%Omega*%tau_max) 0",
+                RenderSettings::default()
+            ));
+        }
+
+        #[test]
+        fn test_gate_parameters_not_texified() {
+            insta::assert_snapshot!(get_latex(
+                "RX(pi) 0\nRZ(%alpha) 0",
+                RenderSettings {
+                    texify_numerical_constants: false,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_gate_parameters_float_format() {
+            let latex = get_latex(
+                "RX(0.5) 0",
+                RenderSettings {
+                    float_format: FloatFormat::Fixed(3),
+                    ..Default::default()
+                },
+            );
+            assert!(latex.contains(r"\gate{RX(0.500)}"));
+        }
+
+        #[test]
+        fn test_gate_cnot() {
+            insta::assert_snapshot!(get_latex("CNOT 0 1\nCNOT 1 0", RenderSettings::default()));
+        }
+
+        #[test]
+        fn test_gate_ccnot() {
+            insta::assert_snapshot!(get_latex("CCNOT 0 1 2", RenderSettings::default()));
+        }
+
+        #[test]
+        fn test_gate_cz() {
+            insta::assert_snapshot!(get_latex("CZ 0 2", RenderSettings::default()));
+        }
+
+        #[test]
+        fn test_variable_qubits() {
+            insta::assert_snapshot!(get_latex(
+                "H a\nCNOT a b\nCZ 0 b\nMEASURE a",
+                RenderSettings::default()
+            ));
+        }
+
+        #[test]
+        fn test_measurement() {
+            insta::assert_snapshot!(get_latex(
+                "DECLARE ro BIT[2]\nH 0\nMEASURE 0 ro[0]\nMEASURE 1",
+                RenderSettings::default()
+            ));
+        }
+    }
+
+    mod cells {
+        use super::RenderSettings;
+        use crate::instruction::Qubit;
+        use crate::program::latex::{Cell, Latex};
+        use crate::Program;
+        use std::str::FromStr;
+
+        #[test]
+        fn test_cells() {
+            let layout = Program::from_str("DAGGER RX(pi) 0\nCNOT 0 1\nMEASURE 1")
+                .unwrap()
+                .to_latex_layout(RenderSettings::default())
+                .unwrap();
+            let wires: Vec<_> = layout.diagram().wires().collect();
+            assert_eq!(wires[1].qubit(), &Qubit::Fixed(1));
+
+            let gate = match wires[0].cell(0) {
+                Some(Cell::Gate(gate)) => gate,
+                other => panic!("expected a gate, found {other:?}"),
+            };
+            assert_eq!(gate.name, "RX");
+            assert_eq!(gate.params, vec![r"\pi"]);
+            assert_eq!(gate.superscripts, vec![r"\dagger"]);
+            assert_eq!(wires[0].cell(1), Some(&Cell::Control(1)));
+            assert_eq!(wires[0].cell(2), None);
+            assert_eq!(wires[1].cell(0), None);
+            assert_eq!(wires[1].cell(1), Some(&Cell::Target));
+            assert_eq!(wires[1].cell(2), Some(&Cell::Measurement));
+        }
+    }
+
+    mod modifiers {
+        use super::{get_latex, LatexGenError, RenderSettings};
+        use crate::{program::latex::Latex, Program};
+        use rstest::rstest;
+        use std::str::FromStr;
+
+        #[test]
+        fn test_modifier_dagger() {
+            insta::assert_snapshot!(get_latex("DAGGER T 0", RenderSettings::default()));
+        }
+
+        #[test]
+        fn test_modifier_controlled() {
+            insta::assert_snapshot!(get_latex(
+                "CONTROLLED RX(pi) 1 0",
+                RenderSettings::default()
+            ));
+        }
+
+        #[rstest]
+        #[case("DAGGER CONTROLLED RX(pi) 0 1", r"\gate{RX^{\dagger}(\pi)}")]
+        #[case("CONTROLLED DAGGER RX(pi) 0 1", r"\gate{RX^{\dagger}(\pi)}")]
+        #[case("DAGGER DAGGER RX(pi) 0", r"\gate{RX^{\dagger\dagger}(\pi)}")]
+        #[case("DAGGER CNOT 0 1", r"\gate{NOT^{\dagger}}")]
+        #[case("DAGGER RZ_12(pi) 0", r"\gate{RZ_{12}^{\dagger}(\pi)}")]
+        fn test_modifier_superscripts(#[case] input: &str, #[case] expected: &str) {
+            let latex = get_latex(input, RenderSettings::default());
+            assert!(latex.contains(expected), "{latex}");
+        }
+
+        #[test]
+        fn test_modifier_forked() {
+            let program = Program::from_str("FORKED RX(pi, pi) 0 1").unwrap();
+            assert!(matches!(
+                program.to_latex(RenderSettings::default()),
+                Err(LatexGenError::InInstruction { index: 0, source, .. })
+                    if *source == LatexGenError::UnsupportedModifierForked
+            ));
+        }
+    }
+
+    mod pragmas {
+        use super::{get_latex, LatexGenError, RenderSettings};
+        use crate::{program::latex::Latex, Program};
+        use rstest::rstest;
+        use std::str::FromStr;
+
+        #[test]
+        fn test_column_break() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nPRAGMA LATEX_COLUMN_BREAK\nCNOT 0 1\nX 1\nH 1",
+                RenderSettings {
+                    max_columns_per_row: Some(2),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_row_order() {
+            let with_pragma = get_latex(
+                "PRAGMA LATEX_ROW_ORDER 2 0\nCNOT 0 2\nH 1",
+                RenderSettings::default(),
+            );
+            let with_setting = get_latex(
+                "CNOT 0 2\nH 1",
+                RenderSettings {
+                    wire_order: vec![2, 0],
+                    ..Default::default()
+                },
+            );
+            assert_eq!(with_pragma, with_setting);
+
+            // The setting takes precedence over the pragma.
+            let overridden = get_latex(
+                "PRAGMA LATEX_ROW_ORDER 2 0\nCNOT 0 2\nH 1",
+                RenderSettings {
+                    wire_order: vec![0],
+                    ..Default::default()
+                },
+            );
+            assert_eq!(
+                overridden,
+                get_latex("CNOT 0 2\nH 1", RenderSettings::default())
+            );
+        }
+
+        #[test]
+        fn test_invalid_row_order() {
+            let program = Program::from_str("PRAGMA LATEX_ROW_ORDER 1 a\nCNOT a 1").unwrap();
+            assert_eq!(
+                program.to_latex(RenderSettings::default()),
+                Err(LatexGenError::InInstruction {
+                    index: 0,
+                    instruction: "PRAGMA LATEX_ROW_ORDER 1 a".to_string(),
+                    source: Box::new(LatexGenError::InvalidLayoutPragma),
+                })
+            );
+        }
+
+        #[test]
+        fn test_annotation_pragmas() {
+            insta::assert_snapshot!(get_latex(
+                r#"PRAGMA LATEX_GROUP "state prep"
+H 0
+PRAGMA LATEX_LABEL 1 "ancilla"
+X 1
+PRAGMA LATEX_END_GROUP
+PRAGMA LATEX_SLICE "t=1"
+PRAGMA LATEX_LABEL "entangle"
+PRAGMA LATEX_GROUP 0 1 "bell"
+CNOT 0 1
+PRAGMA LATEX_END_GROUP
+MEASURE 1"#,
+                RenderSettings::default()
+            ));
+        }
+
+        #[rstest]
+        #[case("PRAGMA LATEX_LABEL\nH 0", LatexGenError::MissingPragmaLabel("LATEX_LABEL".to_string()))]
+        #[case("H 0\nPRAGMA LATEX_END_GROUP", LatexGenError::UnmatchedEndGroupPragma)]
+        #[case("PRAGMA LATEX_GROUP \"g\"\nH 0", LatexGenError::UnclosedGroupPragma)]
+        fn test_invalid_annotation_pragmas(#[case] input: &str, #[case] expected: LatexGenError) {
+            let program = Program::from_str(input).unwrap();
+            let error = program.to_latex(RenderSettings::default()).unwrap_err();
+            match error {
+                LatexGenError::InInstruction { source, .. } => assert_eq!(*source, expected),
+                error => assert_eq!(error, expected),
+            }
+        }
+    }
+
+    mod costs {
+        use super::RenderSettings;
+        use crate::program::latex::cost::{CostModel, GateCost};
+        use crate::{program::latex::Latex, Program};
+        use std::str::FromStr;
+
+        #[test]
+        fn test_cost_annotations() {
+            let mut costs = CostModel::new();
+            costs.set_gate_cost(
+                "H",
+                GateCost {
+                    duration: Some(40e-9),
+                    fidelity: Some(0.999),
+                },
+            );
+            costs.set_gate_cost(
+                "CNOT",
+                GateCost {
+                    duration: Some(200e-9),
+                    ..Default::default()
+                },
+            );
+
+            let program = Program::from_str("H 0\nCNOT 0 1\nX 1").unwrap();
+            insta::assert_snapshot!(program
+                .to_latex_with_costs(RenderSettings::default(), &costs)
+                .unwrap());
+        }
+    }
+
+    mod settings {
+        use super::{get_latex, LatexGenError, RenderSettings};
+        use crate::instruction::{Instruction, Qubit};
+        use crate::program::latex::style::{CellShape, CellStyle, CellStyler};
+        use crate::program::latex::{
+            CellKind, CircuitDisplay, GateGroup, OutputLabels, TikzcdOptions,
+        };
+        use crate::{program::latex::Latex, Program};
+        use rstest::rstest;
+        use std::str::FromStr;
+
+        #[test]
+        fn test_gate_styles() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nX 1\nH 1\nRX(pi) 0",
+                RenderSettings {
+                    gate_styles: [("H".to_string(), "fill=red!20".to_string())].into(),
+                    instruction_styles: [(2, "fill=blue!20".to_string())].into(),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_cell_styler() {
+            let styler = CellStyler::new(|instruction, qubit, column| match instruction {
+                Instruction::Gate(gate) if gate.name == "RZ" => CellStyle {
+                    text: Some(r"R_z(\pi)".to_string()),
+                    style: Some("fill=yellow!40".to_string()),
+                    ..Default::default()
+                },
+                _ if *qubit == Qubit::Fixed(1) && column == 3 => CellStyle {
+                    shape: Some(CellShape::Phase),
+                    ..Default::default()
+                },
+                _ => CellStyle::default(),
+            });
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 1\nRZ(pi) 1\nT 1\nH 0",
+                RenderSettings {
+                    gate_styles: [("H".to_string(), "fill=red!20".to_string())].into(),
+                    cell_styler: Some(styler),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_cell_styler_every_wire() {
+            let styler = CellStyler::new(|_, qubit, _| CellStyle {
+                style: Some(format!("fill=red!{}", 10 * (qubit_index(qubit) + 1))),
+                ..Default::default()
+            });
+            let latex = get_latex(
+                "CNOT 0 1\nCONTROLLED H 2 0\nCCNOT 0 1 2",
+                RenderSettings {
+                    cell_styler: Some(styler),
+                    ..Default::default()
+                },
+            );
+            for command in [
+                r"\ctrl[style={fill=red!10}]{1}",
+                r"\targ[style={fill=red!20}]{}",
+                r"\ctrl[style={fill=red!30}]{-2}",
+                r"\gate[style={fill=red!10}]{H}",
+                r"\ctrl[style={fill=red!20}]{1}",
+                r"\targ[style={fill=red!30}]{}",
+            ] {
+                assert!(latex.contains(command), "{command} not in {latex}");
+            }
+        }
+
+        fn qubit_index(qubit: &Qubit) -> u64 {
+            match qubit {
+                Qubit::Fixed(index) => *index,
+                _ => 0,
+            }
+        }
+
+        #[test]
+        fn test_classical_wires() {
+            insta::assert_snapshot!(get_latex(
+                "DECLARE ro BIT[2]\nDECLARE aux BIT\nH 0\nMEASURE 0 ro[1]\nMEASURE 1 aux\nMEASURE 1",
+                RenderSettings {
+                    classical_wires: true,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_gate_groups() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nH 1\nCNOT 0 1\nX 2",
+                RenderSettings {
+                    gate_groups: vec![
+                        GateGroup {
+                            columns: 0..2,
+                            qubits: vec![Qubit::Fixed(0), Qubit::Fixed(1)],
+                            label: "prepare".to_string(),
+                        },
+                        GateGroup {
+                            columns: 2..4,
+                            qubits: vec![],
+                            label: "entangle".to_string(),
+                        },
+                    ],
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_circuit_display_grouped() {
+            insta::assert_snapshot!(get_latex(
+                "BELL 2 0\nDEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nDAGGER BELL 0 1\nH 1",
+                RenderSettings {
+                    circuit_display: CircuitDisplay::Grouped,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_circuit_display_box() {
+            insta::assert_snapshot!(get_latex(
+                "DEFCIRCUIT CIRC(%theta) a b:\n    RX(%theta) a\n    CNOT a b\n\nCIRC(pi) 2 0\nDAGGER CIRC(pi) 0 1\nH 1",
+                RenderSettings {
+                    circuit_display: CircuitDisplay::Box,
+                    gate_styles: [("CIRC".to_string(), "fill=blue!20".to_string())].into(),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_gate_group_unknown_qubit() {
+            let settings = RenderSettings {
+                gate_groups: vec![GateGroup {
+                    columns: 0..1,
+                    qubits: vec![Qubit::Fixed(3)],
+                    label: "missing".to_string(),
+                }],
+                ..Default::default()
+            };
+            assert_eq!(
+                Program::from_str("H 0").unwrap().to_latex(settings),
+                Err(LatexGenError::UnknownQubitInGateGroup(Qubit::Fixed(3)))
+            );
+        }
+
+        #[test]
+        fn test_impute_missing_qubits() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 3",
+                RenderSettings {
+                    impute_missing_qubits: true,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_omit_idle_wires() {
+            let settings = RenderSettings {
+                impute_missing_qubits: true,
+                omit_idle_wires: true,
+                ..Default::default()
+            };
+            assert_eq!(
+                get_latex("H 0\nCNOT 0 3", settings.clone()),
+                get_latex("H 0\nCNOT 0 3", RenderSettings::default())
+            );
+
+            let ordered = RenderSettings {
+                wire_order: vec![2],
+                ..settings
+            };
+            let latex = get_latex("H 0\nCNOT 0 3", ordered);
+            assert!(latex.contains(r"\lstick{\ket{q_{2}}}"));
+            assert!(!latex.contains(r"\lstick{\ket{q_{1}}}"));
+        }
+
+        #[test]
+        fn test_gate_name_overrides() {
+            let settings = RenderSettings {
+                gate_name_overrides: [
+                    ("RZ12".to_string(), "R_{z}".to_string()),
+                    ("CNOT".to_string(), "CX".to_string()),
+                ]
+                .into(),
+                ..Default::default()
+            };
+            let latex = get_latex("RZ12(pi) 0\nDAGGER RZ12(pi) 1\nCNOT 0 1", settings);
+            assert!(latex.contains(r"\gate{R_{z}(\pi)}"));
+            assert!(latex.contains(r"\gate{R_{z}^{\dagger}(\pi)}"));
+            assert!(latex.contains(r"\gate[wires=2]{CX}"));
+            assert!(!latex.contains(r"\ctrl"));
+        }
+
+        #[test]
+        fn test_no_qubit_labels() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 1",
+                RenderSettings {
+                    label_qubit_lines: false,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_output_labels() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 1\nX 1\nH a",
+                RenderSettings {
+                    output_labels: OutputLabels::Qubits,
+                    max_columns_per_row: Some(2),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_custom_output_labels() {
+            let latex = get_latex(
+                "H 0\nCNOT 0 1",
+                RenderSettings {
+                    output_labels: OutputLabels::Custom([(1, r"\ket{+}".to_string())].into()),
+                    ..Default::default()
+                },
+            );
+            assert!(latex.contains(r"\targ{} & \qw \rstick{\ket{+}}"));
+            assert_eq!(latex.matches(r"\rstick").count(), 1);
+        }
+
+        #[test]
+        fn test_pulse_markers() {
+            let program = r#"DECLARE ro BIT
+X 0
+PULSE 0 "rf" flat(duration: 1e-6, iq: 1)
+DELAY 0 1 100e-9
+CAPTURE 1 "ro_rx" boxcar_kernel(duration: 1e-6) ro
+CZ 0 1"#;
+            insta::assert_snapshot!(get_latex(
+                program,
+                RenderSettings {
+                    pulse_markers: true,
+                    ..Default::default()
+                }
+            ));
+            assert!(!get_latex(program, RenderSettings::default()).contains("PULSE"));
+        }
+
+        #[test]
+        fn test_pack_moments() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nH 1\nCNOT 0 2\nX 1\nH 3\nCZ 1 3\nMEASURE 0",
+                RenderSettings {
+                    pack_moments: true,
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_pack_moments_boundaries() {
+            let program = Program::from_str(
+                "H 0\nPRAGMA LATEX_SLICE \"a\"\nH 1\nPRAGMA LATEX_GROUP \"g\"\nX 0\nX 1\nPRAGMA LATEX_END_GROUP\nY 0\nY 1",
+            )
+            .unwrap();
+            let layout = program
+                .to_latex_layout(RenderSettings {
+                    pack_moments: true,
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(layout.column_count(), 4);
+            assert_eq!(layout.cell(1, 1), Some(CellKind::Gate));
+            assert_eq!(layout.cell(0, 1), Some(CellKind::Empty));
+            assert_eq!(layout.cell(0, 2), Some(CellKind::Gate));
+            assert_eq!(layout.cell(1, 2), Some(CellKind::Gate));
+
+            let latex = program
+                .to_latex(RenderSettings {
+                    pack_moments: true,
+                    ..Default::default()
+                })
+                .unwrap();
+            assert!(latex.contains(r"\slice{a}"));
+            assert!(latex.contains("steps=1,style={dashed"));
+        }
+
+        #[test]
+        fn test_annotate_gate_definitions() {
+            let program = r#"DEFGATE CZ AS PERMUTATION:
+    0, 1, 2, 3
+
+DEFGATE ZZ_PHASE a b AS PAULI-SUM:
+    ZZ(pi/4) a b
+
+CZ 0 1
+ZZ_PHASE 1 2"#;
+            insta::assert_snapshot!(get_latex(
+                program,
+                RenderSettings {
+                    annotate_gate_definitions: true,
+                    ..Default::default()
+                }
+            ));
+            let latex = get_latex(program, RenderSettings::default());
+            assert!(!latex.contains("PERMUTATION"));
+            assert!(!latex.contains(r"\ctrl"));
+        }
+
+        #[test]
+        fn test_tikzcd_options() {
+            let latex = get_latex(
+                "H 0\nCNOT 0 1\nX 0",
+                RenderSettings {
+                    max_columns_per_row: Some(2),
+                    tikzcd_options: TikzcdOptions {
+                        column_sep: Some("0.3cm".to_string()),
+                        row_sep: Some("small".to_string()),
+                        transparent: false,
+                        thin_lines: true,
+                    },
+                    ..Default::default()
+                },
+            );
+            let begin = r"\begin{tikzcd}[column sep=0.3cm, row sep=small, thin lines]";
+            assert_eq!(latex.matches(begin).count(), 2);
+        }
+
+        #[test]
+        fn test_default_tikzcd_options() {
+            assert_eq!(TikzcdOptions::default().to_string(), "");
+            let options = TikzcdOptions {
+                transparent: true,
+                ..Default::default()
+            };
+            assert_eq!(options.to_string(), "[transparent]");
+        }
+
+        #[test]
+        fn test_qubit_labels() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 17",
+                RenderSettings {
+                    qubit_labels: [(17, r"\ket{\text{ancilla}}".to_string())]
+                        .into_iter()
+                        .collect(),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_wire_order() {
+            insta::assert_snapshot!(get_latex(
+                "CNOT 0 2\nH 1\nCZ 2 a",
+                RenderSettings {
+                    wire_order: vec![2, 0],
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[rstest]
+        #[case(vec![0, 0], LatexGenError::DuplicateQubitInWireOrder(0))]
+        #[case(vec![0, 3], LatexGenError::UnknownQubitInWireOrder(3))]
+        fn test_invalid_wire_order(#[case] wire_order: Vec<u64>, #[case] expected: LatexGenError) {
+            let program = Program::from_str("CNOT 0 1").unwrap();
+            assert_eq!(
+                program.to_latex(RenderSettings {
+                    wire_order,
+                    ..Default::default()
+                }),
+                Err(expected)
+            );
+        }
+
+        #[test]
+        fn test_max_columns_per_row() {
+            insta::assert_snapshot!(get_latex(
+                "H 0\nCNOT 0 1\nX 1\nH 1\nCNOT 1 0",
+                RenderSettings {
+                    max_columns_per_row: Some(2),
+                    ..Default::default()
+                }
+            ));
+        }
+
+        #[test]
+        fn test_max_columns_per_row_fits_on_one_row() {
+            assert_eq!(
+                get_latex(
+                    "H 0\nCNOT 0 1",
+                    RenderSettings {
+                        max_columns_per_row: Some(2),
+                        ..Default::default()
+                    }
+                ),
+                get_latex("H 0\nCNOT 0 1", RenderSettings::default())
+            );
+        }
+    }
+
+    mod ranges {
+        use super::{get_latex, LatexGenError, RenderSettings};
+        use crate::{instruction::Instruction, program::latex::Latex, Program};
+        use std::str::FromStr;
+
+        const INPUT: &str = "H 0\nCNOT 0 1\nX 1\nH 2\nCNOT 2 1";
+
+        #[test]
+        fn test_program_range() {
+            let program = Program::from_str(INPUT).unwrap();
+            assert_eq!(
+                program
+                    .to_latex_range(1..3, RenderSettings::default())
+                    .unwrap(),
+                get_latex("CNOT 0 1\nX 1", RenderSettings::default())
+            );
+        }
+
+        #[test]
+        fn test_instruction_slice() {
+            let program = Program::from_str(INPUT).unwrap();
+            let instructions: &[Instruction] = &program.instructions[3..];
+            assert_eq!(
+                instructions
+                    .to_latex_fragment(RenderSettings::default())
+                    .unwrap(),
+                Program::from_str("H 2\nCNOT 2 1")
+                    .unwrap()
+                    .to_latex_fragment(RenderSettings::default())
+                    .unwrap()
+            );
+        }
+
+        #[test]
+        fn test_range_out_of_bounds() {
+            let program = Program::from_str(INPUT).unwrap();
+            assert_eq!(
+                program.to_latex_range(4..6, RenderSettings::default()),
+                Err(LatexGenError::InstructionRangeOutOfBounds {
+                    start: 4,
+                    end: 6,
+                    len: 5
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_render_borrowed_program() {
+        let program = Program::from_str("H 0\nCNOT 0 1").unwrap();
+        let document = program.to_latex(RenderSettings::default()).unwrap();
+        let fragment = program
+            .to_latex_fragment(RenderSettings::default())
+            .unwrap();
+        assert!(document.contains(&fragment));
+        assert_eq!(
+            program
+                .instructions
+                .to_latex_fragment(RenderSettings::default()),
+            Ok(fragment)
+        );
+    }
+
+    #[test]
+    fn test_cnot_without_target() {
+        let program = Program::from_str("H 0\nCNOT 0").unwrap();
+        let error = program.to_latex(RenderSettings::default()).unwrap_err();
+        assert_eq!(
+            error,
+            LatexGenError::InInstruction {
+                index: 1,
+                instruction: "CNOT 0".to_string(),
+                source: Box::new(LatexGenError::FoundCNOTWithNoTarget),
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "In instruction 1 (CNOT 0): Tried to parse CNOT and found a control qubit without a target."
+        );
+    }
+
+    #[test]
+    fn test_error_in_expanded_circuit() {
+        let program =
+            Program::from_str("DEFCIRCUIT BAD a b:\n    FORKED RX(pi, pi) a b\n\nH 0\nBAD 0 1")
+                .unwrap();
+        assert!(matches!(
+            program.to_latex(RenderSettings {
+                circuit_display: CircuitDisplay::Grouped,
+                ..Default::default()
+            }),
+            Err(LatexGenError::InInstruction { index: 2, instruction, source })
+                if instruction.starts_with("FORKED RX")
+                    && *source == LatexGenError::UnsupportedModifierForked
+        ));
+    }
+}
@@ -0,0 +1,234 @@
+//! Writing diagrams for the qcircuit LaTeX package, as selected by [`LatexBackend::Qcircuit`].
+//!
+//! The diagram is laid out exactly as for Quantikz; only the commands written in each cell differ.
+//!
+//! [`LatexBackend::Qcircuit`]: super::LatexBackend::Qcircuit
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+
+use super::{Command, Diagram, EmptyDiagram, Symbol};
+
+/// The space between columns when the `tikzcd_options` setting does not give one.
+const DEFAULT_COLUMN_SEP: &str = "1em";
+
+/// The space between wires when the `tikzcd_options` setting does not give one.
+const DEFAULT_ROW_SEP: &str = ".7em";
+
+/// The qcircuit command drawn in place of `command`, or `None` if qcircuit has no equivalent.
+/// Gate groups are placed by absolute position in qcircuit, so they are written by
+/// [`Diagram::write_qcircuit_row`] instead.
+fn translate(command: &Command) -> Option<String> {
+    let translated = match command {
+        Command::Lstick(_)
+        | Command::LstickLabel(_)
+        | Command::Rstick(_)
+        | Command::RstickLabel(_)
+        | Command::Qw
+        | Command::Cw
+        | Command::Nr
+        | Command::Ctrl(_) => command.to_string(),
+        Command::StyledCtrl(wire, _) => Command::Ctrl(*wire).to_string(),
+        Command::Gate(name) | Command::StyledGate(name, _) => format!(r"\gate{{{name}}}"),
+        Command::WideGate { wires, name, .. } => {
+            format!(r"\multigate{{{}}}{{{name}}}", wires - 1)
+        }
+        Command::Phase(_) => r"\control \qw".to_string(),
+        Command::Meter => r"\meter".to_string(),
+        Command::Vcw(wire) => format!(r"\cwx[{wire}]"),
+        Command::Targ | Command::StyledTarg(_) => r"\targ".to_string(),
+        Command::Annotation(_) | Command::Slice(_) | Command::GateGroup { .. } => return None,
+    };
+    Some(translated)
+}
+
+impl Diagram {
+    /// Write the `\Qcircuit` environment containing the columns `columns` of every wire.
+    pub(super) fn write_qcircuit_row(
+        &self,
+        f: &mut fmt::Formatter,
+        columns: Range<u32>,
+        continued_from_left: bool,
+        continued_to_right: bool,
+    ) -> fmt::Result {
+        let options = &self.settings.tikzcd_options;
+        writeln!(
+            f,
+            r"\Qcircuit @C={} @R={} {{",
+            options.column_sep.as_deref().unwrap_or(DEFAULT_COLUMN_SEP),
+            options.row_sep.as_deref().unwrap_or(DEFAULT_ROW_SEP)
+        )?;
+
+        if self.order.is_empty() && self.settings.empty_diagram == EmptyDiagram::PlaceholderWire {
+            writeln!(f, r"\qw & \qw")?;
+        }
+
+        let row_count = self.order.len() + self.classical_wires.len();
+        let measurement_rows = self.measurement_rows();
+        let groups = self.row_groups(&columns);
+        let wires: Vec<_> = self.order.iter().map(|name| &self.circuit[name]).collect();
+
+        // The name of the box spanning each cell below the top wire of a multi-wire gate, which
+        // qcircuit draws as a `\ghost`.
+        let mut ghosts: HashMap<(usize, u32), String> = HashMap::new();
+        for column in columns.clone() {
+            for (i, wire) in wires.iter().enumerate() {
+                if let Command::WideGate { wires, name, .. } = wire.get_command(column) {
+                    for row in i + 1..i + wires {
+                        ghosts.insert((row, column), name.clone());
+                    }
+                }
+            }
+        }
+
+        for (i, wire) in wires.iter().enumerate() {
+            write!(f, "{}", self.input_label(wire))?;
+            if continued_from_left {
+                write!(f, r" \cdots")?;
+            }
+
+            for column in columns.clone() {
+                let command = wire.get_command(column);
+                match (&command, ghosts.get(&(i, column))) {
+                    (Command::Qw, Some(name)) => write!(f, r" & \ghost{{{name}}}")?,
+                    _ => write!(f, " & {}", translate(&command).unwrap_or_default())?,
+                }
+                if let (Command::Meter, Some(row)) = (&command, measurement_rows.get(&column)) {
+                    write!(
+                        f,
+                        " {}",
+                        translate(&Command::Vcw(*row as i64 - i as i64)).unwrap_or_default()
+                    )?;
+                }
+                for group in groups.get(&(i, column)).into_iter().flatten() {
+                    if let Command::GateGroup { wires, steps, .. } = group {
+                        // qcircuit numbers rows and columns from one, counting the column of the
+                        // wire labels.
+                        let first = column - columns.start + 2;
+                        write!(
+                            f,
+                            r" \gategroup{{{}}}{{{first}}}{{{}}}{{{}}}{{.7em}}{{--}}",
+                            i + 1,
+                            i + wires,
+                            first + steps - 1
+                        )?;
+                    }
+                }
+            }
+
+            write!(f, " & {}", Command::Qw)?;
+            if continued_to_right {
+                write!(f, r" & \rstick{{\cdots}}")?;
+            } else if let Some(label) = self.output_label(wire) {
+                write!(f, " & {label}")?;
+            }
+
+            if i + 1 < row_count {
+                write!(f, " {}", Command::Nr)?;
+            }
+            writeln!(f)?;
+        }
+
+        for (i, name) in self.classical_wires.keys().enumerate() {
+            if self.settings.label_qubit_lines {
+                let label = Symbol::Text(name.clone()).to_string();
+                write!(f, "{}", Command::LstickLabel(label))?;
+            } else {
+                write!(f, "{}", Command::Cw)?;
+            }
+            if continued_from_left {
+                write!(f, r" \cdots")?;
+            }
+
+            for _ in columns.clone() {
+                write!(f, " & {}", Command::Cw)?;
+            }
+
+            write!(f, " & {}", Command::Cw)?;
+            if continued_to_right {
+                write!(f, r" & \rstick{{\cdots}}")?;
+            }
+
+            if self.order.len() + i + 1 < row_count {
+                write!(f, " {}", Command::Nr)?;
+            }
+            writeln!(f)?;
+        }
+
+        writeln!(f, "}}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::translate;
+    use crate::program::latex::{
+        CircuitDisplay, Command, Latex, LatexBackend, OutputLabels, RenderSettings,
+    };
+    use crate::Program;
+
+    fn get_qcircuit(input: &str, settings: RenderSettings) -> String {
+        Program::from_str(input)
+            .unwrap()
+            .to_latex(RenderSettings {
+                backend: LatexBackend::Qcircuit,
+                ..settings
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_qcircuit() {
+        insta::assert_snapshot!(get_qcircuit(
+            "H 0\nCNOT 0 1\nCZ 1 2\nMEASURE 2 ro[0]",
+            RenderSettings {
+                classical_wires: true,
+                output_labels: OutputLabels::Qubits,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_qcircuit_boxes_groups_and_rows() {
+        insta::assert_snapshot!(get_qcircuit(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 2\nDAGGER BELL 0 2\nX 1",
+            RenderSettings {
+                circuit_display: CircuitDisplay::Grouped,
+                max_columns_per_row: Some(3),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_translate() {
+        assert_eq!(
+            translate(&Command::WideGate {
+                wires: 3,
+                name: "U".to_string(),
+                style: None,
+            }),
+            Some(r"\multigate{2}{U}".to_string())
+        );
+        assert_eq!(translate(&Command::Targ), Some(r"\targ".to_string()));
+        assert_eq!(translate(&Command::Annotation("1".to_string())), None);
+    }
+}
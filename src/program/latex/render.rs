@@ -0,0 +1,285 @@
+//! Compile LaTeX circuit diagrams to PDF or PNG images using a locally installed TeX toolchain.
+//!
+//! PDFs are compiled with `pdflatex` or `tectonic`, whichever is found first on the `PATH`; PNGs
+//! are then rasterized from the PDF with `pdftoppm` from Poppler.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::Program;
+
+use super::{Latex, LatexGenError, RenderSettings};
+
+/// The resolution, in dots per inch, of rendered PNG images.
+const PNG_DENSITY: u32 = 300;
+
+/// Programs which compile a LaTeX document to PDF.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LatexEngine {
+    Pdflatex,
+    Tectonic,
+}
+
+impl LatexEngine {
+    /// The name of the engine's executable.
+    pub fn executable(&self) -> &'static str {
+        match self {
+            LatexEngine::Pdflatex => "pdflatex",
+            LatexEngine::Tectonic => "tectonic",
+        }
+    }
+
+    /// Return the first engine which is installed, preferring `pdflatex`.
+    pub fn detect() -> Option<Self> {
+        [LatexEngine::Pdflatex, LatexEngine::Tectonic]
+            .into_iter()
+            .find(|engine| is_installed(engine.executable()))
+    }
+
+    /// The command which compiles `document` (a path relative to the working directory) into a
+    /// PDF of the same name in the working directory.
+    fn command(&self, document: &str) -> Command {
+        let mut command = Command::new(self.executable());
+        match self {
+            LatexEngine::Pdflatex => {
+                command.args(["-interaction=nonstopmode", "-halt-on-error", document]);
+            }
+            LatexEngine::Tectonic => {
+                command.args(["--chatter", "minimal", document]);
+            }
+        }
+        command
+    }
+}
+
+/// The formats a diagram may be rendered to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Pdf,
+    Png,
+}
+
+/// Errors which may occur while rendering a diagram to an image.
+#[derive(Debug, thiserror::Error)]
+pub enum RenderError {
+    #[error(transparent)]
+    Latex(#[from] LatexGenError),
+    #[error("No LaTeX engine was found; install pdflatex or tectonic.")]
+    NoLatexEngine,
+    #[error("{0} could not be run: {1}")]
+    ToolUnavailable(&'static str, io::Error),
+    #[error("{tool} failed:\n{log}")]
+    ToolFailed { tool: &'static str, log: String },
+    #[error("Could not use the working directory: {0}")]
+    Io(#[from] io::Error),
+}
+
+pub type RenderResult<T> = Result<T, RenderError>;
+
+/// Whether `executable` can be run.
+fn is_installed(executable: &str) -> bool {
+    Command::new(executable)
+        .arg("--version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+/// Run `command` in `directory` as `tool`, failing with its output if it does not succeed.
+fn run(tool: &'static str, mut command: Command, directory: &Path) -> RenderResult<()> {
+    let Output {
+        status,
+        stdout,
+        stderr,
+    } = command
+        .current_dir(directory)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|error| RenderError::ToolUnavailable(tool, error))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(RenderError::ToolFailed {
+            tool,
+            log: format!(
+                "{}{}",
+                String::from_utf8_lossy(&stdout),
+                String::from_utf8_lossy(&stderr)
+            ),
+        })
+    }
+}
+
+/// A scratch directory, removed with its contents when dropped.
+struct WorkingDirectory(PathBuf);
+
+impl WorkingDirectory {
+    /// How many names to try before giving up, should each already be taken.
+    const ATTEMPTS: usize = 16;
+
+    /// Create a fresh directory in the system's temporary directory. Its name is random, so that
+    /// it cannot be created first by another user, and the directory is never one which already
+    /// exists; on Unix, only its owner may enter it.
+    fn new() -> io::Result<Self> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let mut builder = fs::DirBuilder::new();
+        #[cfg(unix)]
+        std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+
+        let mut attempts = 0;
+        loop {
+            // Each `RandomState` is keyed from the operating system's source of randomness.
+            let mut hasher = RandomState::new().build_hasher();
+            hasher.write_u32(std::process::id());
+            hasher.write_usize(COUNT.fetch_add(1, Ordering::Relaxed));
+            if let Ok(elapsed) = SystemTime::now().duration_since(UNIX_EPOCH) {
+                hasher.write_u128(elapsed.as_nanos());
+            }
+            let path =
+                std::env::temp_dir().join(format!("quil-rs-render-{:016x}", hasher.finish()));
+
+            match builder.create(&path) {
+                Ok(()) => return Ok(Self(path)),
+                Err(error)
+                    if error.kind() == io::ErrorKind::AlreadyExists
+                        && attempts < Self::ATTEMPTS =>
+                {
+                    attempts += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+}
+
+impl Drop for WorkingDirectory {
+    fn drop(&mut self) {
+        // The directory is only scratch space, so failing to remove it is not worth reporting.
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Compile the LaTeX `document` with `engine` and return the bytes of the resulting image.
+pub fn compile_latex(
+    document: &str,
+    engine: LatexEngine,
+    format: ImageFormat,
+) -> RenderResult<Vec<u8>> {
+    let directory = WorkingDirectory::new()?;
+    fs::write(directory.0.join("diagram.tex"), document)?;
+
+    run(
+        engine.executable(),
+        engine.command("diagram.tex"),
+        &directory.0,
+    )?;
+
+    match format {
+        ImageFormat::Pdf => Ok(fs::read(directory.0.join("diagram.pdf"))?),
+        ImageFormat::Png => {
+            let mut command = Command::new("pdftoppm");
+            command.args([
+                "-png",
+                "-singlefile",
+                "-r",
+                &PNG_DENSITY.to_string(),
+                "diagram.pdf",
+                "diagram",
+            ]);
+            run("pdftoppm", command, &directory.0)?;
+            Ok(fs::read(directory.0.join("diagram.png"))?)
+        }
+    }
+}
+
+impl Program {
+    /// Render the program as a LaTeX diagram, as [`Latex::to_latex`] does, and compile it to a
+    /// PDF with the first LaTeX engine found by [`LatexEngine::detect`].
+    pub fn to_pdf(&self, settings: RenderSettings) -> RenderResult<Vec<u8>> {
+        self.compile_diagram(settings, ImageFormat::Pdf)
+    }
+
+    /// Render the program as a LaTeX diagram, as [`Latex::to_latex`] does, and compile it to a
+    /// PNG image with the first LaTeX engine found by [`LatexEngine::detect`] and `pdftoppm`.
+    pub fn to_image(&self, settings: RenderSettings) -> RenderResult<Vec<u8>> {
+        self.compile_diagram(settings, ImageFormat::Png)
+    }
+
+    fn compile_diagram(
+        &self,
+        settings: RenderSettings,
+        format: ImageFormat,
+    ) -> RenderResult<Vec<u8>> {
+        let document = self.to_latex(settings)?;
+        let engine = LatexEngine::detect().ok_or(RenderError::NoLatexEngine)?;
+        compile_latex(&document, engine, format)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::{LatexEngine, RenderError, WorkingDirectory};
+    use crate::program::latex::{LatexGenError, RenderSettings};
+    use crate::Program;
+
+    #[test]
+    fn test_diagram_errors_are_reported_first() {
+        let program = Program::from_str("FORKED RX(0, pi) 0 1").unwrap();
+        assert!(matches!(
+            program.to_pdf(RenderSettings::default()),
+            Err(RenderError::Latex(LatexGenError::InInstruction { .. }))
+        ));
+    }
+
+    #[test]
+    fn test_working_directories_are_distinct() {
+        let first = WorkingDirectory::new().unwrap();
+        let second = WorkingDirectory::new().unwrap();
+        assert_ne!(first.0, second.0);
+        assert!(first.0.is_dir() && second.0.is_dir());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&first.0).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        let path = first.0.clone();
+        drop(first);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn test_to_pdf() {
+        // Rendering requires a TeX installation, which is not available everywhere tests run.
+        if LatexEngine::detect().is_none() {
+            return;
+        }
+        let program = Program::from_str("H 0\nCNOT 0 1").unwrap();
+        let pdf = program.to_pdf(RenderSettings::default()).unwrap();
+        assert!(pdf.starts_with(b"%PDF"));
+    }
+}
@@ -0,0 +1,97 @@
+//! Override how individual cells of a circuit diagram are drawn, such as to highlight a gate.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::instruction::{Instruction, Qubit};
+
+/// The shape a gate is drawn as.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CellShape {
+    /// `\gate{text}`: a box containing the text.
+    #[default]
+    Box,
+    /// `\phase{text}`: a dot labelled with the text.
+    Phase,
+}
+
+/// Overrides for how the cell of a gate on one of its wires is drawn. Fields left as `None` are
+/// drawn as usual. On the wire of a control, or of the target of a controlled-not, only `style` is
+/// used.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CellStyle {
+    /// The text of the gate, inserted verbatim in place of its name and parameters, so it may
+    /// contain LaTeX markup.
+    pub text: Option<String>,
+    /// Quantikz style options, such as `fill=red!20`, which take precedence over the
+    /// `gate_styles` and `instruction_styles` settings.
+    pub style: Option<String>,
+    /// The shape of the gate.
+    pub shape: Option<CellShape>,
+}
+
+type CellStyleFn = dyn Fn(&Instruction, &Qubit, u32) -> CellStyle + Send + Sync;
+
+/// A callback which chooses the [`CellStyle`] of each cell of a gate in a diagram, given the
+/// instruction which applies the gate, the qubit of the wire the cell is on, and its column. It is
+/// called once for each qubit of the gate. A gate drawn as a box over several wires takes the
+/// style chosen for its topmost wire.
+///
+/// ```
+/// use quil_rs::instruction::{Instruction, Qubit};
+/// use quil_rs::program::latex::style::{CellStyle, CellStyler};
+/// use quil_rs::program::latex::{Latex, RenderSettings};
+/// use quil_rs::Program;
+/// use std::str::FromStr;
+///
+/// // Highlight the gate under discussion.
+/// let styler = CellStyler::new(|_: &Instruction, _: &Qubit, column: u32| match column {
+///     1 => CellStyle {
+///         style: Some("fill=yellow!40".to_string()),
+///         ..Default::default()
+///     },
+///     _ => CellStyle::default(),
+/// });
+/// let settings = RenderSettings {
+///     cell_styler: Some(styler),
+///     ..Default::default()
+/// };
+/// let latex = Program::from_str("H 0\nRX(pi) 0").unwrap().to_latex(settings).unwrap();
+/// assert!(latex.contains(r"\gate[style={fill=yellow!40}]{RX(\pi)}"));
+/// ```
+#[derive(Clone)]
+pub struct CellStyler(Arc<CellStyleFn>);
+
+impl CellStyler {
+    pub fn new(
+        styler: impl Fn(&Instruction, &Qubit, u32) -> CellStyle + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(styler))
+    }
+
+    /// Return the style of the cell of the gate applied by `instruction` on the wire of `qubit` at
+    /// `column`.
+    pub fn style(&self, instruction: &Instruction, qubit: &Qubit, column: u32) -> CellStyle {
+        (self.0)(instruction, qubit, column)
+    }
+}
+
+impl fmt::Debug for CellStyler {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("CellStyler").finish_non_exhaustive()
+    }
+}
@@ -16,17 +16,17 @@ use std::collections::HashSet;
 
 use crate::expression::Expression;
 use crate::instruction::{
-    Arithmetic, ArithmeticOperand, BinaryLogic, BinaryOperand, Capture, CircuitDefinition,
-    Comparison, ComparisonOperand, Delay, Exchange, Gate, GateDefinition, GateSpecification,
-    Instruction, Jump, JumpUnless, JumpWhen, Label, Load, MeasureCalibrationDefinition,
-    Measurement, MemoryReference, Move, Pulse, RawCapture, SetPhase, SetScale, ShiftPhase, Store,
-    UnaryLogic, Vector, WaveformInvocation,
+    Arithmetic, ArithmeticOperand, BinaryLogic, BinaryOperand, Call, CallArgument, Capture,
+    CircuitDefinition, Comparison, ComparisonOperand, Delay, Exchange, Gate, GateDefinition,
+    GateSpecification, Instruction, Jump, JumpUnless, JumpWhen, Load, MeasureCalibrationDefinition,
+    Measurement, MemoryReference, Move, Pulse, RawCapture, SetPhase, SetScale, Sharing, ShiftPhase,
+    Store, UnaryLogic, Vector, WaveformInvocation,
 };
 
 #[derive(Clone, Debug, Hash, PartialEq)]
 pub struct MemoryRegion {
     pub size: Vector,
-    pub sharing: Option<String>,
+    pub sharing: Option<Sharing>,
 }
 
 impl Eq for MemoryRegion {}
@@ -75,14 +75,18 @@ macro_rules! set_from_reference_vec {
 /// Build a HashSet<String> from an Option<&MemoryReference>
 macro_rules! set_from_optional_memory_reference {
     ($reference:expr) => {
-        set_from_reference_vec![$reference.map_or(vec![], |reference| vec![reference.name.clone()])]
+        set_from_reference_vec![
+            $reference.map_or(vec![], |reference| vec![reference.name.to_string()])
+        ]
     };
 }
 
 /// Build a HashSet<String> from a Vec<&MemoryReference>
 macro_rules! set_from_memory_references {
     ($references:expr) => {
-        set_from_reference_vec![$references.iter().map(|reference| reference.name.clone())]
+        set_from_reference_vec![$references
+            .iter()
+            .map(|reference| reference.name.to_string())]
     };
 }
 
@@ -90,11 +94,29 @@ impl Instruction {
     /// Return all memory accesses by the instruction - in expressions, captures, and memory manipulation
     pub fn get_memory_accesses(&self) -> MemoryAccesses {
         match self {
+            Instruction::Call(Call { arguments, .. }) => {
+                // Without the signature of the subroutine, any memory it is passed may be written.
+                let regions: HashSet<String> = arguments
+                    .iter()
+                    .filter_map(|argument| match argument {
+                        CallArgument::Identifier(name) => Some(name.clone()),
+                        CallArgument::MemoryReference(reference) => {
+                            Some(reference.name.to_string())
+                        }
+                        CallArgument::LiteralInteger(_) | CallArgument::LiteralReal(_) => None,
+                    })
+                    .collect();
+                MemoryAccesses {
+                    reads: regions.clone(),
+                    writes: regions,
+                    ..Default::default()
+                }
+            }
             Instruction::Comparison(Comparison { operands, .. }) => {
-                let mut reads = HashSet::from([operands.1.name.clone()]);
-                let writes = HashSet::from([operands.0.name.clone()]);
+                let mut reads = HashSet::from([operands.1.name.to_string()]);
+                let writes = HashSet::from([operands.0.name.to_string()]);
                 if let ComparisonOperand::MemoryReference(mem) = &operands.2 {
-                    reads.insert(mem.name.clone());
+                    reads.insert(mem.name.to_string());
                 }
 
                 MemoryAccesses {
@@ -106,10 +128,10 @@ impl Instruction {
             Instruction::BinaryLogic(BinaryLogic { operands, .. }) => {
                 let mut reads = HashSet::new();
                 let mut writes = HashSet::new();
-                reads.insert(operands.0.name.clone());
-                writes.insert(operands.0.name.clone());
+                reads.insert(operands.0.name.to_string());
+                writes.insert(operands.0.name.to_string());
                 if let BinaryOperand::MemoryReference(mem) = &operands.1 {
-                    reads.insert(mem.name.clone());
+                    reads.insert(mem.name.to_string());
                 }
 
                 MemoryAccesses {
@@ -119,8 +141,8 @@ impl Instruction {
                 }
             }
             Instruction::UnaryLogic(UnaryLogic { operand, .. }) => MemoryAccesses {
-                reads: HashSet::from([operand.name.clone()]),
-                writes: HashSet::from([operand.name.clone()]),
+                reads: HashSet::from([operand.name.to_string()]),
+                writes: HashSet::from([operand.name.to_string()]),
                 ..Default::default()
             },
             Instruction::Arithmetic(Arithmetic {
@@ -132,7 +154,7 @@ impl Instruction {
                 destination,
                 source,
             }) => MemoryAccesses {
-                writes: set_from_optional_memory_reference![destination.get_memory_reference()],
+                writes: HashSet::from([destination.name.to_string()]),
                 reads: set_from_optional_memory_reference![source.get_memory_reference()],
                 ..Default::default()
             },
@@ -173,10 +195,7 @@ impl Instruction {
                 ..Default::default()
             },
             Instruction::Exchange(Exchange { left, right }) => MemoryAccesses {
-                writes: merge_sets![
-                    set_from_optional_memory_reference!(left.get_memory_reference()),
-                    set_from_optional_memory_reference!(right.get_memory_reference())
-                ],
+                writes: HashSet::from([left.name.to_string(), right.name.to_string()]),
                 ..Default::default()
             },
             Instruction::Gate(Gate { parameters, .. }) => MemoryAccesses {
@@ -187,11 +206,25 @@ impl Instruction {
                 ..Default::default()
             },
             Instruction::GateDefinition(GateDefinition { specification, .. }) => {
-                if let GateSpecification::Matrix(matrix) = specification {
-                    let references = matrix
-                        .iter()
-                        .flat_map(|row| row.iter().flat_map(|cell| cell.get_memory_references()))
-                        .collect::<Vec<&MemoryReference>>();
+                let references = match specification {
+                    GateSpecification::Matrix(matrix) => Some(
+                        matrix
+                            .iter()
+                            .flat_map(|row| {
+                                row.iter().flat_map(|cell| cell.get_memory_references())
+                            })
+                            .collect::<Vec<&MemoryReference>>(),
+                    ),
+                    GateSpecification::PauliSum(pauli_sum) => Some(
+                        pauli_sum
+                            .terms
+                            .iter()
+                            .flat_map(|term| term.expression.get_memory_references())
+                            .collect::<Vec<&MemoryReference>>(),
+                    ),
+                    GateSpecification::Permutation(_) => None,
+                };
+                if let Some(references) = references {
                     MemoryAccesses {
                         reads: set_from_memory_references!(references),
                         ..Default::default()
@@ -217,7 +250,7 @@ impl Instruction {
                 offset,
             }) => MemoryAccesses {
                 writes: set_from_memory_references![vec![destination]],
-                reads: set_from_reference_vec![vec![source, &offset.name]],
+                reads: HashSet::from([source.to_string(), offset.name.to_string()]),
                 ..Default::default()
             },
             Instruction::Measurement(Measurement { target, .. }) => MemoryAccesses {
@@ -252,7 +285,7 @@ impl Instruction {
                     set_from_memory_references!(vec![offset]),
                     set_from_optional_memory_reference!(source.get_memory_reference())
                 ],
-                writes: set_from_reference_vec![vec![destination]],
+                writes: HashSet::from([destination.to_string()]),
                 ..Default::default()
             },
             Instruction::Convert(_)
@@ -262,13 +295,14 @@ impl Instruction {
             | Instruction::Halt
             | Instruction::Include(_)
             | Instruction::Jump(Jump { target: _ })
-            | Instruction::Label(Label(_))
+            | Instruction::Label(_)
             | Instruction::Nop
             | Instruction::Pragma(_)
             | Instruction::Reset(_)
             | Instruction::SetFrequency(_)
             | Instruction::ShiftFrequency(_)
             | Instruction::SwapPhases(_)
+            | Instruction::Wait
             | Instruction::WaveformDefinition(_) => Default::default(),
         }
     }
@@ -289,6 +323,10 @@ impl Expression {
     pub fn get_memory_references(&self) -> Vec<&MemoryReference> {
         match self {
             Expression::Address(reference) => vec![reference],
+            Expression::CustomFunctionCall { arguments, .. } => arguments
+                .iter()
+                .flat_map(Expression::get_memory_references)
+                .collect(),
             Expression::FunctionCall { expression, .. } => expression.get_memory_references(),
             Expression::Infix { left, right, .. } => {
                 let mut result = left.get_memory_references();
@@ -0,0 +1,250 @@
+//! Check that a program's instructions use its classical memory as declared.
+//!
+//! Each memory region an instruction reads or writes must be declared, each index into it must
+//! lie within its declared length, and classical instructions must respect its data type. A
+//! region which the program writes should not be read before the first instruction which writes
+//! it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeSet, HashMap};
+
+use thiserror::Error;
+
+use crate::instruction::visit::InstructionVisitor;
+use crate::instruction::{Instruction, MemoryReference};
+use crate::Program;
+
+use super::type_check::{type_check_instruction, TypeError};
+
+/// A misuse of memory by an instruction of a program, found by [`Program::validate_memory`].
+/// Each gives the index of the instruction in [`Program::instructions`].
+#[derive(Debug, Error)]
+pub enum MemoryError {
+    #[error("In instruction {index} ({instruction}): undeclared memory region {region}.")]
+    UndeclaredRegion {
+        index: usize,
+        instruction: Box<Instruction>,
+        region: String,
+    },
+
+    #[error(
+        "In instruction {index} ({instruction}): {reference} is out of bounds for a region of length {length}."
+    )]
+    IndexOutOfBounds {
+        index: usize,
+        instruction: Box<Instruction>,
+        reference: MemoryReference,
+        length: u64,
+    },
+
+    /// The error, as from [`type_check`](super::type_check::type_check), names the instruction.
+    #[error("{error}")]
+    TypeMismatch { index: usize, error: Box<TypeError> },
+
+    #[error(
+        "In instruction {index} ({instruction}): {region} is read before it is first written, by instruction {first_write}."
+    )]
+    ReadBeforeWrite {
+        index: usize,
+        instruction: Box<Instruction>,
+        region: String,
+        first_write: usize,
+    },
+}
+
+impl MemoryError {
+    /// The index in [`Program::instructions`] of the instruction which misuses memory.
+    pub fn index(&self) -> usize {
+        match self {
+            MemoryError::UndeclaredRegion { index, .. }
+            | MemoryError::IndexOutOfBounds { index, .. }
+            | MemoryError::TypeMismatch { index, .. }
+            | MemoryError::ReadBeforeWrite { index, .. } => *index,
+        }
+    }
+}
+
+/// Collects every memory reference made by an instruction.
+#[derive(Default)]
+pub(super) struct MemoryReferenceCollector(pub(super) Vec<MemoryReference>);
+
+impl InstructionVisitor for MemoryReferenceCollector {
+    fn visit_memory_reference(&mut self, memory_reference: &MemoryReference) {
+        self.0.push(memory_reference.clone());
+    }
+}
+
+impl Program {
+    /// Check how each instruction of the program uses memory, and return every misuse found, in
+    /// the order of the instructions:
+    ///
+    /// - A region read or written which is not declared.
+    /// - An index into a region past its declared length, such as `ro[7]` into a `BIT[2]`.
+    /// - A classical instruction given memory of the wrong type, as by
+    ///   [`type_check`](super::type_check::type_check).
+    /// - A region read before the first instruction which writes it. Regions which the program
+    ///   never writes are taken to be parameters, written before the program runs, and so may
+    ///   be read anywhere.
+    ///
+    /// Each is reported at most once for each region an instruction uses. Instructions are taken
+    /// in the order they appear, not the order in which control flow may reach them.
+    ///
+    /// [`Program::check`](crate::Program::check) reports these among its findings, as `E001`,
+    /// `E005` and `W005`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::memory_check::MemoryError;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT[2]\nMEASURE 0 ro[7]\nRX(theta) 0".parse().unwrap();
+    /// let errors = program.validate_memory();
+    /// assert!(matches!(errors[0], MemoryError::IndexOutOfBounds { index: 0, length: 2, .. }));
+    /// assert!(matches!(&errors[1], MemoryError::UndeclaredRegion { index: 1, region, .. } if region == "theta"));
+    /// ```
+    pub fn validate_memory(&self) -> Vec<MemoryError> {
+        let mut first_writes: HashMap<String, usize> = HashMap::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let accesses = instruction.get_memory_accesses();
+            for region in accesses.writes.into_iter().chain(accesses.captures) {
+                first_writes.entry(region).or_insert(index);
+            }
+        }
+
+        let mut errors = vec![];
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let accesses = instruction.get_memory_accesses();
+            let regions: BTreeSet<&String> = accesses
+                .reads
+                .iter()
+                .chain(&accesses.writes)
+                .chain(&accesses.captures)
+                .collect();
+            let mut declared = true;
+            for region in regions {
+                if !self.memory_regions.contains_key(region.as_str()) {
+                    declared = false;
+                    errors.push(MemoryError::UndeclaredRegion {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                        region: region.clone(),
+                    });
+                }
+            }
+
+            let mut collector = MemoryReferenceCollector::default();
+            collector.visit_instruction(instruction);
+            let mut reported = BTreeSet::new();
+            for reference in collector.0 {
+                let Some(region) = self.memory_regions.get(reference.name.as_str()) else {
+                    continue;
+                };
+                if reference.index >= region.size.length
+                    && reported.insert(reference.name.to_string())
+                {
+                    errors.push(MemoryError::IndexOutOfBounds {
+                        index,
+                        instruction: Box::new(instruction.clone()),
+                        reference,
+                        length: region.size.length,
+                    });
+                }
+            }
+
+            // Undeclared regions are already reported, and type checking would report them again.
+            if declared {
+                if let Err(error) = type_check_instruction(instruction, &self.memory_regions) {
+                    errors.push(MemoryError::TypeMismatch {
+                        index,
+                        error: Box::new(error),
+                    });
+                }
+            }
+
+            let reads: BTreeSet<&String> = accesses.reads.iter().collect();
+            for region in reads {
+                if let Some(&first_write) = first_writes.get(region) {
+                    if first_write > index {
+                        errors.push(MemoryError::ReadBeforeWrite {
+                            index,
+                            instruction: Box::new(instruction.clone()),
+                            region: region.clone(),
+                            first_write,
+                        });
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::MemoryError;
+    use crate::program::type_check::TypeError;
+    use crate::Program;
+
+    #[rstest]
+    #[case("DECLARE ro BIT[2]\nMEASURE 0 ro[1]\nMEASURE 1 ro[0]", vec![])]
+    #[case("DECLARE theta REAL\nRX(theta) 0", vec![])]
+    #[case(
+        "DECLARE ro BIT[2]\nMEASURE 0 ro[7]",
+        vec!["In instruction 0 (MEASURE 0 ro[7]): ro[7] is out of bounds for a region of length 2."]
+    )]
+    #[case(
+        "RX(theta) 0\nMOVE a 1",
+        vec![
+            "In instruction 0 (RX(theta[0]) 0): undeclared memory region theta.",
+            "In instruction 1 (MOVE a[0] 1): undeclared memory region a.",
+        ]
+    )]
+    #[case(
+        "DECLARE count INTEGER\nDECLARE total INTEGER\nMOVE total count\nMOVE count 1",
+        vec!["In instruction 0 (MOVE total[0] count[0]): count is read before it is first written, by instruction 1."]
+    )]
+    #[case(
+        "DECLARE ro BIT\nJUMP-WHEN @end ro\nMEASURE 0 ro\nLABEL @end",
+        vec!["In instruction 0 (JUMP-WHEN @end ro[0]): ro is read before it is first written, by instruction 1."]
+    )]
+    fn test_validate_memory(#[case] input: &str, #[case] expected: Vec<&str>) {
+        let program = Program::from_str(input).unwrap();
+        let errors: Vec<String> = program
+            .validate_memory()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn test_validate_memory_type_mismatch() {
+        let program =
+            Program::from_str("DECLARE ro BIT\nDECLARE theta REAL\nH 0\nMOVE ro theta").unwrap();
+        let errors = program.validate_memory();
+        assert_eq!(errors.len(), 1);
+        let MemoryError::TypeMismatch { index: 1, error } = &errors[0] else {
+            panic!("expected a type mismatch, found {:?}", errors[0]);
+        };
+        assert!(matches!(**error, TypeError::DataTypeMismatch { .. }));
+        assert_eq!(errors[0].index(), 1);
+    }
+}
@@ -0,0 +1,421 @@
+//! The layout of a program's classical memory, and where each region of it is accessed.
+//!
+//! An executor allocates one buffer for each declared region which shares no other's memory.
+//! A region declared with `SHARING` occupies part of the buffer of the region it shares,
+//! beginning after its `OFFSET`s. [`Program::memory_layout`] describes where each region lies, and
+//! [`Program::memory_accesses`] which instructions read and write it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use crate::instruction::ScalarType;
+use crate::Program;
+
+pub use super::memory::MemoryAccessType;
+
+/// Errors which may occur while computing a [`MemoryLayout`].
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum MemoryLayoutError {
+    #[error("region {region} shares undeclared region {shared}")]
+    UndeclaredSharedRegion { region: String, shared: String },
+
+    #[error("region {region} shares its own memory through SHARING")]
+    CyclicSharing { region: String },
+
+    #[error(
+        "region {region} occupies bytes {start} to {end} of region {root}, which has only {size}"
+    )]
+    OutOfBounds {
+        region: String,
+        root: String,
+        start: u64,
+        end: u64,
+        size: u64,
+    },
+
+    #[error("region {region} is too large for its offset in bytes to be represented")]
+    Overflow { region: String },
+}
+
+/// The number of bytes an element of `data_type` occupies. Each `BIT` occupies a whole byte.
+pub fn element_size(data_type: &ScalarType) -> u64 {
+    match data_type {
+        ScalarType::Bit | ScalarType::Octet => 1,
+        ScalarType::Integer | ScalarType::Real => 8,
+    }
+}
+
+/// Where a declared memory region lies.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionLayout {
+    pub data_type: ScalarType,
+    /// The declared length of the region, in elements of `data_type`.
+    pub length: u64,
+    /// The region named by the declaration's `SHARING`, if any.
+    pub shares: Option<String>,
+    /// The region which shares no other's memory and whose buffer this region occupies: the
+    /// region itself if it has no `SHARING`.
+    pub root: String,
+    /// The offset in bytes at which this region begins within the buffer of `root`.
+    pub byte_offset: u64,
+    /// The number of bytes the region occupies.
+    pub byte_size: u64,
+}
+
+impl RegionLayout {
+    /// The offset in bytes, within the buffer of [`RegionLayout::root`], just past the end of
+    /// the region.
+    pub fn byte_end(&self) -> u64 {
+        self.byte_offset + self.byte_size
+    }
+
+    fn overlaps(&self, other: &RegionLayout) -> bool {
+        self.root == other.root
+            && self.byte_offset < other.byte_end()
+            && other.byte_offset < self.byte_end()
+    }
+}
+
+/// The layout of every declared memory region of a program, keyed on its name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MemoryLayout {
+    pub regions: BTreeMap<String, RegionLayout>,
+}
+
+impl MemoryLayout {
+    /// Return the layout of the named region, if it is declared.
+    pub fn get(&self, name: &str) -> Option<&RegionLayout> {
+        self.regions.get(name)
+    }
+
+    /// The buffers an executor must allocate: the size in bytes of each region which shares no
+    /// other's memory, keyed on its name.
+    pub fn buffers(&self) -> BTreeMap<&str, u64> {
+        self.regions
+            .iter()
+            .filter(|(_, layout)| layout.shares.is_none())
+            .map(|(name, layout)| (name.as_str(), layout.byte_size))
+            .collect()
+    }
+
+    /// The other regions whose memory overlaps that of the named region, whether it shares
+    /// theirs, they share its own, or both share that of a third.
+    pub fn aliases(&self, name: &str) -> Vec<&str> {
+        let Some(region) = self.get(name) else {
+            return vec![];
+        };
+        self.regions
+            .iter()
+            .filter(|(other_name, other)| other_name.as_str() != name && region.overlaps(other))
+            .map(|(other_name, _)| other_name.as_str())
+            .collect()
+    }
+}
+
+/// A read or write of a memory region by an instruction of a program.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RegionAccess {
+    /// The index of the instruction in [`Program::instructions`].
+    pub index: usize,
+    pub access_type: MemoryAccessType,
+}
+
+impl Program {
+    /// The layout of the program's declared memory regions: each one's data type and size, the
+    /// region it shares by `SHARING`, if any, and the buffer and byte offset at which it lies.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a region shares one which is not declared, if regions share each other's memory
+    /// in a cycle, if a region extends past the end of the buffer it lies in, or if its size or
+    /// offset in bytes is too large to be represented.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE params REAL[4]\nDECLARE theta REAL[2] SHARING params OFFSET 1 REAL"
+    ///     .parse()
+    ///     .unwrap();
+    /// let layout = program.memory_layout().unwrap();
+    /// let theta = layout.get("theta").unwrap();
+    /// assert_eq!(theta.root, "params");
+    /// assert_eq!((theta.byte_offset, theta.byte_size), (8, 16));
+    /// assert_eq!(layout.buffers()["params"], 32);
+    /// assert_eq!(layout.aliases("params"), ["theta"]);
+    /// ```
+    pub fn memory_layout(&self) -> Result<MemoryLayout, MemoryLayoutError> {
+        let mut layout = MemoryLayout::default();
+        for name in self.memory_regions.keys() {
+            self.lay_out_region(name, &mut layout, &mut vec![])?;
+        }
+
+        for (name, region) in &layout.regions {
+            let root = &layout.regions[&region.root];
+            if region.byte_end() > root.byte_size {
+                return Err(MemoryLayoutError::OutOfBounds {
+                    region: name.clone(),
+                    root: region.root.clone(),
+                    start: region.byte_offset,
+                    end: region.byte_end(),
+                    size: root.byte_size,
+                });
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Add the layout of the named region to `layout`, along with those of the regions whose
+    /// memory it shares. `sharing` holds the regions being laid out which share this one.
+    fn lay_out_region(
+        &self,
+        name: &str,
+        layout: &mut MemoryLayout,
+        sharing: &mut Vec<String>,
+    ) -> Result<(), MemoryLayoutError> {
+        if layout.regions.contains_key(name) {
+            return Ok(());
+        }
+        if sharing.iter().any(|region| region == name) {
+            return Err(MemoryLayoutError::CyclicSharing {
+                region: name.to_string(),
+            });
+        }
+        let region = &self.memory_regions[name];
+        let data_type = region.size.data_type.clone();
+        let overflow = || MemoryLayoutError::Overflow {
+            region: name.to_string(),
+        };
+        let byte_size = region
+            .size
+            .length
+            .checked_mul(element_size(&data_type))
+            .ok_or_else(overflow)?;
+
+        let (root, byte_offset) = match &region.sharing {
+            None => (name.to_string(), 0),
+            Some(shared) => {
+                if !self.memory_regions.contains_key(&shared.name) {
+                    return Err(MemoryLayoutError::UndeclaredSharedRegion {
+                        region: name.to_string(),
+                        shared: shared.name.to_string(),
+                    });
+                }
+                sharing.push(name.to_string());
+                self.lay_out_region(&shared.name, layout, sharing)?;
+                sharing.pop();
+
+                let parent = &layout.regions[shared.name.as_str()];
+                let offset = shared
+                    .offsets
+                    .iter()
+                    .try_fold(parent.byte_offset, |total, offset| {
+                        total
+                            .checked_add(offset.count.checked_mul(element_size(&offset.data_type))?)
+                    })
+                    .ok_or_else(overflow)?;
+                (parent.root.clone(), offset)
+            }
+        };
+        // Every offset past the region is then representable, as `byte_end` assumes.
+        byte_offset.checked_add(byte_size).ok_or_else(overflow)?;
+
+        layout.regions.insert(
+            name.to_string(),
+            RegionLayout {
+                data_type,
+                length: region.size.length,
+                shares: region
+                    .sharing
+                    .as_ref()
+                    .map(|shared| shared.name.to_string()),
+                root,
+                byte_offset,
+                byte_size,
+            },
+        );
+        Ok(())
+    }
+
+    /// Every read and write of each memory region by the instructions of the program, in
+    /// order, keyed on the name of the region. An instruction which both reads and writes a
+    /// region, such as `NOT`, lists its read first. Writes by readout, such as by `MEASURE` and
+    /// `CAPTURE`, are listed as [`MemoryAccessType::Capture`].
+    ///
+    /// Accesses are listed under the name by which the instruction refers to the region, not
+    /// those of the regions it shares memory with; see [`MemoryLayout::aliases`].
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::memory_layout::MemoryAccessType;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT\nDECLARE theta REAL\nRX(theta) 0\nMEASURE 0 ro"
+    ///     .parse()
+    ///     .unwrap();
+    /// let accesses = program.memory_accesses();
+    /// assert_eq!(accesses["theta"][0].access_type, MemoryAccessType::Read);
+    /// assert_eq!(accesses["ro"][0].index, 1);
+    /// ```
+    pub fn memory_accesses(&self) -> BTreeMap<String, Vec<RegionAccess>> {
+        let mut accesses: BTreeMap<String, Vec<RegionAccess>> = BTreeMap::new();
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            let instruction_accesses = instruction.get_memory_accesses();
+            for (regions, access_type) in [
+                (instruction_accesses.reads, MemoryAccessType::Read),
+                (instruction_accesses.writes, MemoryAccessType::Write),
+                (instruction_accesses.captures, MemoryAccessType::Capture),
+            ] {
+                for region in regions {
+                    accesses.entry(region).or_default().push(RegionAccess {
+                        index,
+                        access_type: access_type.clone(),
+                    });
+                }
+            }
+        }
+        accesses
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{MemoryAccessType, MemoryLayoutError, RegionAccess};
+    use crate::Program;
+
+    #[test]
+    fn test_memory_layout() {
+        let program = Program::from_str(
+            "DECLARE buffer OCTET[32]
+DECLARE ro BIT[2]
+DECLARE words INTEGER[2] SHARING buffer OFFSET 8 OCTET
+DECLARE second REAL SHARING words OFFSET 1 INTEGER
+DECLARE tail OCTET[4] SHARING buffer OFFSET 3 REAL OFFSET 4 OCTET",
+        )
+        .unwrap();
+        let layout = program.memory_layout().unwrap();
+
+        let placements: Vec<(&str, &str, u64, u64)> = layout
+            .regions
+            .iter()
+            .map(|(name, region)| {
+                (
+                    name.as_str(),
+                    region.root.as_str(),
+                    region.byte_offset,
+                    region.byte_size,
+                )
+            })
+            .collect();
+        assert_eq!(
+            placements,
+            vec![
+                ("buffer", "buffer", 0, 32),
+                ("ro", "ro", 0, 2),
+                ("second", "buffer", 16, 8),
+                ("tail", "buffer", 28, 4),
+                ("words", "buffer", 8, 16),
+            ]
+        );
+        assert_eq!(
+            layout.get("second").unwrap().shares.as_deref(),
+            Some("words")
+        );
+        assert_eq!(
+            layout.buffers().into_iter().collect::<Vec<_>>(),
+            vec![("buffer", 32), ("ro", 2)]
+        );
+        assert_eq!(layout.aliases("words"), vec!["buffer", "second"]);
+        assert_eq!(layout.aliases("tail"), vec!["buffer"]);
+        assert!(layout.aliases("ro").is_empty());
+    }
+
+    #[rstest]
+    #[case(
+        "DECLARE a REAL SHARING b",
+        MemoryLayoutError::UndeclaredSharedRegion { region: "a".to_string(), shared: "b".to_string() }
+    )]
+    #[case(
+        "DECLARE a REAL SHARING b\nDECLARE b REAL SHARING a",
+        MemoryLayoutError::CyclicSharing { region: "a".to_string() }
+    )]
+    #[case(
+        "DECLARE a REAL[2]\nDECLARE b REAL[2] SHARING a OFFSET 1 REAL",
+        MemoryLayoutError::OutOfBounds {
+            region: "b".to_string(),
+            root: "a".to_string(),
+            start: 8,
+            end: 24,
+            size: 16,
+        }
+    )]
+    #[case(
+        "DECLARE a REAL[3000000000000000000]",
+        MemoryLayoutError::Overflow { region: "a".to_string() }
+    )]
+    #[case(
+        "DECLARE a OCTET[16]\nDECLARE b OCTET SHARING a OFFSET 3000000000000000000 REAL",
+        MemoryLayoutError::Overflow { region: "b".to_string() }
+    )]
+    #[case(
+        "DECLARE a OCTET[16]\nDECLARE b OCTET[18446744073709551615] SHARING a OFFSET 1 OCTET",
+        MemoryLayoutError::Overflow { region: "b".to_string() }
+    )]
+    fn test_memory_layout_errors(#[case] input: &str, #[case] expected: MemoryLayoutError) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.memory_layout(), Err(expected));
+    }
+
+    #[test]
+    fn test_memory_accesses() {
+        let program = Program::from_str(
+            "DECLARE ro BIT
+DECLARE theta REAL
+DECLARE count INTEGER
+RX(theta) 0
+NOT count
+MEASURE 0 ro
+JUMP-WHEN @end ro
+LABEL @end",
+        )
+        .unwrap();
+        let accesses = program.memory_accesses();
+
+        let access = |index, access_type| RegionAccess { index, access_type };
+        assert_eq!(accesses["theta"], vec![access(0, MemoryAccessType::Read)]);
+        assert_eq!(
+            accesses["count"],
+            vec![
+                access(1, MemoryAccessType::Read),
+                access(1, MemoryAccessType::Write)
+            ]
+        );
+        assert_eq!(
+            accesses["ro"],
+            vec![
+                access(2, MemoryAccessType::Capture),
+                access(3, MemoryAccessType::Read)
+            ]
+        );
+        assert_eq!(accesses.len(), 3);
+    }
+}
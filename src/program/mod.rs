@@ -12,27 +12,85 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
 
 use nom_locate::LocatedSpan;
 
+use self::comments::{Anchor, Comments};
+use crate::expression::FloatFormat;
 use crate::instruction::{
-    Declaration, FrameDefinition, FrameIdentifier, Instruction, Qubit, Waveform, WaveformDefinition,
+    Declaration, FrameDefinition, FrameIdentifier, Gate, Identifier, Instruction, Measurement,
+    Qubit, Waveform, WaveformDefinition,
+};
+use crate::parser::{
+    blank_unlexable_lines, lex, parse_instructions, parse_instructions_with_recovery,
+    parse_instructions_with_spans, ParseError,
 };
-use crate::parser::{lex, parse_instructions, ParseError};
 
-pub use self::calibration::CalibrationSet;
-pub use self::error::{disallow_leftover, map_parsed, recover, ProgramError, SyntaxError};
+pub use self::calibration::{
+    CalibrationCandidate, CalibrationDefinitionRef, CalibrationMatches, CalibrationSet,
+    ExpandedCalibrations, SelectionReason,
+};
+pub use self::dead_code::{DeadCodeOptions, DeadCodeReport, RemovalReason, RemovedInstruction};
+pub use self::equivalence::EquivalenceOptions;
+pub use self::error::{
+    disallow_leftover, map_parsed, recover, LeftoverError, ProgramError, SyntaxError,
+};
 pub use self::frame::FrameSet;
+pub use self::include::{FileSystemResolver, IncludeResolver};
 pub use self::memory::MemoryRegion;
-
+pub use self::options::{ParserOptions, QuilVersion, Requirement};
+pub use self::remap::QubitRemapError;
+pub use self::result_spec::{ProgramResultSpec, ResultBufferSpec, ResultType};
+pub use self::simulation::SimulationError;
+pub use self::span::{SourceLocation, SourceSpan};
+pub use self::statistics::ProgramStatistics;
+
+pub mod arity_check;
 mod calibration;
+pub mod check;
+mod circuit;
+mod comments;
+pub mod control_flow_graph;
+pub mod dag;
+mod dead_code;
+mod debug_tree;
+pub mod deferral;
+mod delay;
+pub mod diff;
+mod edit;
+mod equivalence;
 mod error;
+pub mod feedback;
+pub mod flatten;
 pub(crate) mod frame;
+mod fusion;
 pub mod graph;
+pub mod html;
+mod include;
+mod inverse;
+pub mod isa;
+mod label;
+pub mod latex;
 mod memory;
+pub mod memory_check;
+pub mod memory_layout;
+pub mod moments;
+pub mod namespace;
+mod options;
+pub mod parametric;
+mod remap;
+pub mod result_spec;
+pub mod routing;
+mod simulation;
+mod span;
+mod statistics;
+pub mod svg;
+pub mod text_diagram;
+pub mod translation;
 pub mod type_check;
+pub mod typst;
 
 pub type Result<O> = std::result::Result<O, ProgramError<O>>;
 
@@ -44,13 +102,54 @@ pub mod graphviz_dot;
 /// This contains not only instructions which are executed in turn on the quantum processor, but
 /// also the "headers" used to describe and manipulate those instructions, such as calibrations
 /// and frame definitions.
-#[derive(Clone, Debug, Default, PartialEq)]
+#[derive(Clone, Debug, Default)]
 pub struct Program {
     pub calibrations: CalibrationSet,
     pub frames: FrameSet,
-    pub memory_regions: BTreeMap<String, MemoryRegion>,
-    pub waveforms: BTreeMap<String, Waveform>,
+    pub memory_regions: BTreeMap<Identifier, MemoryRegion>,
+    pub waveforms: BTreeMap<Identifier, Waveform>,
+    /// The instructions of the body. Editing them with the methods of [`Program`], such as
+    /// [`Program::insert_instruction`], keeps their source text, spans, and comments attached to
+    /// them; editing this field directly drops the source text and span of each instruction it
+    /// changes.
     pub instructions: Vec<Instruction>,
+    /// The original text of instructions in the body, keyed on their index in `instructions`.
+    source: HashMap<usize, Source>,
+    /// The comments and blank lines of the text the program was parsed from.
+    comments: Comments,
+}
+
+/// The text an instruction of the body was parsed from, kept with the instruction itself so that
+/// it is not given to whatever instruction later takes its place.
+#[derive(Clone, Debug)]
+struct Source {
+    instruction: Instruction,
+    text: String,
+    span: SourceSpan,
+}
+
+/// Whether `instruction` is kept among the headers of a program, rather than its body.
+pub(crate) fn is_header(instruction: &Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::CalibrationDefinition(_)
+            | Instruction::Declaration(_)
+            | Instruction::FrameDefinition(_)
+            | Instruction::MeasureCalibrationDefinition(_)
+            | Instruction::WaveformDefinition(_)
+    )
+}
+
+/// Programs are compared on their contents alone; the source text they were parsed from, and its
+/// comments, if recorded, do not take part in the comparison.
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.calibrations == other.calibrations
+            && self.frames == other.frames
+            && self.memory_regions == other.memory_regions
+            && self.waveforms == other.waveforms
+            && self.instructions == other.instructions
+    }
 }
 
 impl Program {
@@ -61,10 +160,13 @@ impl Program {
             memory_regions: BTreeMap::new(),
             waveforms: BTreeMap::new(),
             instructions: vec![],
+            source: HashMap::new(),
+            comments: Comments::default(),
         }
     }
 
-    /// Add an instruction to the end of the program.
+    /// Add an instruction to the end of the program, or to the headers of the program if it is
+    /// one.
     pub fn add_instruction(&mut self, instruction: Instruction) {
         match instruction {
             Instruction::CalibrationDefinition(calibration) => {
@@ -104,22 +206,34 @@ impl Program {
     /// unchanged. Recurses though each instruction while ensuring there is no cycle in the expansion
     /// graph (i.e. no calibration expands directly or indirectly into itself)
     pub fn expand_calibrations(&self) -> Result<Self> {
-        let mut expanded_instructions: Vec<Instruction> = vec![];
+        self.collect_expanded_calibrations(self.expand_calibrations_iter())
+    }
 
-        // TODO: Do this more efficiently, possibly with Vec::splice
-        for instruction in &self.instructions {
-            match self.calibrations.expand(instruction, &[])? {
-                Some(expanded) => {
-                    expanded_instructions.extend(expanded.into_iter());
-                }
-                None => {
-                    expanded_instructions.push(instruction.clone());
-                }
-            }
-        }
+    /// As [`Program::expand_calibrations`], but failing with
+    /// [`ProgramError::MissingCalibration`], identifying the measurement, if a `MEASURE`, in the
+    /// program or in a calibration it expands into, has no `DEFCAL MEASURE` which matches it.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DEFCAL MEASURE 0 addr:\n    NOP\nMEASURE 0 ro[0]\nMEASURE 1 ro[1]".parse().unwrap();
+    /// let error = program.expand_calibrations_strict().unwrap_err();
+    /// assert_eq!(error.to_string(), "instruction MEASURE 1 ro[1] has no calibration");
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn expand_calibrations_strict(&self) -> Result<Self> {
+        self.collect_expanded_calibrations(self.expand_calibrations_iter().strict())
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn collect_expanded_calibrations(&self, expanded: ExpandedCalibrations<'_>) -> Result<Self> {
+        let expanded_instructions = expanded.collect::<std::result::Result<Vec<_>, _>>()?;
 
         let mut new_program = self.clone();
         new_program.instructions = vec![];
+        new_program.clear_source();
 
         for instruction in expanded_instructions {
             new_program.add_instruction(instruction);
@@ -128,6 +242,30 @@ impl Program {
         Ok(new_program)
     }
 
+    /// The instructions of [`Program::expand_calibrations`], yielded one at a time, without
+    /// building the expanded program: each instruction is expanded, recursively, only when it is
+    /// reached, so that only the calibrations being expanded are held in memory. If a
+    /// calibration expands directly or indirectly into itself, the instructions before it are
+    /// yielded, then the error, and then nothing more.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::Instruction;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DEFCAL X 0:\n    NOP\n    NOP\nX 0\nX 1".parse().unwrap();
+    /// let expanded = program
+    ///     .expand_calibrations_iter()
+    ///     .map(|instruction| instruction.map(|instruction| instruction.to_string()))
+    ///     .collect::<Result<Vec<_>, _>>()
+    ///     .unwrap();
+    /// assert_eq!(expanded, ["NOP", "NOP", "X 1"]);
+    /// ```
+    pub fn expand_calibrations_iter(&self) -> ExpandedCalibrations<'_> {
+        ExpandedCalibrations::new(&self.calibrations, &self.instructions)
+    }
+
     /// Build a program from a list of instructions
     pub fn from_instructions(instructions: Vec<Instruction>) -> Self {
         let mut program = Self::default();
@@ -159,6 +297,108 @@ impl Program {
             .map(|condition| self.frames.get_matching_keys(condition))
     }
 
+    /// Describe the shape and data type of each memory region written by readout instructions
+    /// (`MEASURE`, `CAPTURE`, and `RAW-CAPTURE`) in this program.
+    ///
+    /// See [`result_spec::get_result_spec`].
+    pub fn get_result_spec(&self) -> result_spec::ResultSpecResult<ProgramResultSpec> {
+        result_spec::get_result_spec(self)
+    }
+
+    /// Return the exact text from which the instruction at `index` in [`Program::instructions`]
+    /// was parsed, if the program was parsed with [`Program::parse_with_source_text`].
+    ///
+    /// The text moves with its instruction when the body is edited with
+    /// [`Program::insert_instruction`], [`Program::remove_instruction`], or
+    /// [`Program::retain_instructions`], and is forgotten when the instruction is replaced with
+    /// [`Program::replace_instruction`] or the body is rewritten by a transformation. If
+    /// [`Program::instructions`] is edited directly, the text is given only while the
+    /// instruction at `index` is still the one it was parsed into.
+    pub fn get_source_text(&self, index: usize) -> Option<&str> {
+        self.get_source(index).map(|source| source.text.as_str())
+    }
+
+    /// Return the span of the text from which the instruction at `index` in
+    /// [`Program::instructions`] was parsed, if the program was parsed with
+    /// [`Program::parse_with_source_text`].
+    ///
+    /// Like the source text, the span moves with its instruction when the body is edited, and is
+    /// forgotten when the instruction is replaced.
+    pub fn get_source_span(&self, index: usize) -> Option<SourceSpan> {
+        self.get_source(index).map(|source| source.span)
+    }
+
+    /// The source recorded for the instruction at `index`, if that is still the instruction
+    /// parsed from it.
+    fn get_source(&self, index: usize) -> Option<&Source> {
+        self.source
+            .get(&index)
+            .filter(|source| self.instructions.get(index) == Some(&source.instruction))
+    }
+
+    /// The instructions of the body of the program, in order: those other than the headers
+    /// kept in [`Program::calibrations`], [`Program::frames`], [`Program::memory_regions`], and
+    /// [`Program::waveforms`].
+    pub fn body_instructions(&self) -> impl Iterator<Item = &Instruction> {
+        self.instructions.iter()
+    }
+
+    /// The gates applied in the body of the program, in order. Gates within blocks, such as
+    /// those of a `DEFCIRCUIT`, are not included.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT\nH 0\nCNOT 0 1\nMEASURE 1 ro".parse().unwrap();
+    /// let names: Vec<&str> = program.gates().map(|gate| gate.name.as_str()).collect();
+    /// assert_eq!(names, ["H", "CNOT"]);
+    /// assert_eq!(program.measurements().count(), 1);
+    /// ```
+    pub fn gates(&self) -> impl Iterator<Item = &Gate> {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Gate(gate) => Some(gate),
+                _ => None,
+            })
+    }
+
+    /// The measurements made in the body of the program, in order. Measurements within blocks,
+    /// such as those of a `DEFCAL`, are not included.
+    pub fn measurements(&self) -> impl Iterator<Item = &Measurement> {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::Measurement(measurement) => Some(measurement),
+                _ => None,
+            })
+    }
+
+    /// A copy of the program with the same headers, and only those instructions of its body for
+    /// which `predicate` returns `true`. The copy does not record the text the program was
+    /// parsed from.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::Instruction;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT\nH 0\nMEASURE 0 ro".parse().unwrap();
+    /// let unitary = program.filter_instructions(|instruction| matches!(instruction, Instruction::Gate(_)));
+    /// assert_eq!(unitary.to_string(true), "DECLARE ro BIT[1]\nH 0\n");
+    /// ```
+    pub fn filter_instructions(&self, mut predicate: impl FnMut(&Instruction) -> bool) -> Self {
+        let mut new_program = self.clone();
+        new_program
+            .instructions
+            .retain(|instruction| predicate(instruction));
+        new_program.clear_source();
+        new_program
+    }
+
     /// Returns a HashSet consisting of every Qubit that is used in the program.
     pub fn get_used_qubits(&self) -> HashSet<Qubit> {
         self.instructions
@@ -197,7 +437,7 @@ impl Program {
         expanded_program.calibrations = CalibrationSet::default();
 
         let mut frames_used: HashSet<&FrameIdentifier> = HashSet::new();
-        let mut waveforms_used: HashSet<&String> = HashSet::new();
+        let mut waveforms_used: HashSet<&Identifier> = HashSet::new();
 
         for instruction in &expanded_program.instructions {
             if let Some(frames) = expanded_program.get_frames_for_instruction(instruction, false) {
@@ -217,6 +457,95 @@ impl Program {
         Ok(expanded_program)
     }
 
+    /// Parse a program as [`Program::from_str`] does, additionally recording the original text of
+    /// each instruction in the program body and where it was found, to be retrieved with
+    /// [`Program::get_source_text`] and [`Program::get_source_span`], and the comments and blank
+    /// lines around the instructions, to be written with [`Program::to_string_with_comments`].
+    #[allow(clippy::result_large_err)]
+    pub fn parse_with_source_text(input: &str) -> Result<Self> {
+        let lexed = lex(LocatedSpan::new(input)).map_err(ProgramError::from)?;
+        map_parsed(
+            disallow_leftover(
+                parse_instructions_with_spans(&lexed).map_err(ParseError::from_nom_internal_err),
+            ),
+            |instructions| Self::from_instructions_with_spans(input, instructions),
+        )
+    }
+
+    /// Build a program from `instructions`, parsed from `input`, recording the text and span each
+    /// instruction of the body was parsed from.
+    fn from_instructions_with_spans(
+        input: &str,
+        instructions: Vec<(Instruction, SourceSpan)>,
+    ) -> Self {
+        let mut program = Self::new();
+        let mut anchors = Vec::with_capacity(instructions.len());
+        for (instruction, span) in instructions {
+            let body_length = program.instructions.len();
+            anchors.push((Anchor::new(&instruction, body_length), span));
+            let source = Source {
+                instruction: instruction.clone(),
+                text: input[span.range()].to_string(),
+                span,
+            };
+            program.add_instruction(instruction);
+            if program.instructions.len() > body_length {
+                program.source.insert(body_length, source);
+            }
+        }
+        program.comments = Comments::record(input, anchors);
+        program
+    }
+
+    /// The names of the gates and circuits the program defines, with `DEFGATE` or `DEFCIRCUIT`,
+    /// which may be those of standard gates given other meanings.
+    pub(crate) fn defined_gate_names(&self) -> HashSet<&str> {
+        self.instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::GateDefinition(definition) => Some(definition.name.as_str()),
+                Instruction::CircuitDefinition(definition) => Some(definition.name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forget the text the program was parsed from, once its instructions no longer match it.
+    pub(crate) fn clear_source(&mut self) {
+        self.source.clear();
+        self.comments = Comments::default();
+    }
+
+    /// Parse a program as [`Program::parse_with_source_text`] does, but rather than failing at the
+    /// first syntax error, leave out the line it is found on, or the whole instruction if it spans
+    /// several lines, and carry on with the next. Return the program parsed from the rest of the
+    /// input, along with every syntax error found, in the order they appear in the input.
+    ///
+    /// If no errors are returned, the program is the same as that returned by
+    /// [`Program::from_str`].
+    pub fn parse_with_recovery(input: &str) -> (Self, Vec<SyntaxError<Self>>) {
+        let (text, lex_errors) = blank_unlexable_lines(input);
+        // The remaining text can be lexed, since every line which could not has been blanked.
+        let lexed = lex(LocatedSpan::new(&text)).unwrap_or_default();
+        let (instructions, parse_errors) = parse_instructions_with_recovery(&lexed);
+        let program = Self::from_instructions_with_spans(input, instructions);
+
+        let mut errors: Vec<_> = lex_errors
+            .into_iter()
+            .map(|error| ((error.line(), error.column()), SyntaxError::from(error)))
+            .chain(
+                parse_errors
+                    .into_iter()
+                    .map(|error| ((error.line(), error.column()), SyntaxError::from(error))),
+            )
+            .collect();
+        errors.sort_by_key(|(location, _)| *location);
+        (
+            program,
+            errors.into_iter().map(|(_, error)| error).collect(),
+        )
+    }
+
     pub fn to_instructions(&self, include_headers: bool) -> Vec<Instruction> {
         let mut result = vec![];
 
@@ -249,6 +578,12 @@ impl Program {
             .map(|inst| format!("{}\n", inst))
             .collect()
     }
+
+    /// Write the program as Quil like [`Program::to_string`], formatting every number with
+    /// `format`.
+    pub fn to_string_with_format(&self, include_headers: bool, format: FloatFormat) -> String {
+        format.scope(|| self.to_string(include_headers))
+    }
 }
 
 impl FromStr for Program {
@@ -279,6 +614,37 @@ impl From<Vec<Instruction>> for Program {
     }
 }
 
+/// Generate a program from arbitrary instructions, which are sorted into its headers and body as
+/// [`Program::from`] sorts them. The program need not be valid Quil.
+#[cfg(feature = "arbitrary")]
+impl<'a> arbitrary::Arbitrary<'a> for Program {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Self::from(Vec::<Instruction>::arbitrary(u)?))
+    }
+}
+
+/// Programs are serialized as the sequence of their instructions, headers first, as returned by
+/// [`Program::to_instructions`], so that the serialized form does not depend on how the program
+/// stores them. The text the program was parsed from is not serialized.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Program {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.to_instructions(true))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Program {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        Vec::<Instruction>::deserialize(deserializer).map(Self::from)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{collections::HashSet, str::FromStr};
@@ -286,7 +652,85 @@ mod tests {
     use crate::instruction::Instruction;
     use crate::instruction::Qubit;
 
-    use super::Program;
+    use super::{Program, SyntaxError};
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        let input = r#"DECLARE ro BIT[2]
+DECLARE theta REAL
+DEFFRAME 0 "rf":
+    SAMPLE-RATE: 1e9
+DEFWAVEFORM wf:
+    0.5, 0.5i
+DEFCAL RX(pi/2) 0:
+    PULSE 0 "rf" wf
+DEFGATE G(%a) p AS PAULI-SUM:
+    Z(%a/2) p
+DEFCIRCUIT BELL a b:
+    H a
+    CNOT a b
+
+BELL 0 1
+DAGGER RX(-theta[0]) 1
+MEASURE 0 ro[0]
+JUMP-WHEN @end ro[0]
+PRAGMA READOUT-POVM 1 "(0.9 0.1 0.1 0.9)"
+LABEL @end
+"#;
+        let program = Program::from_str(input).unwrap();
+        let json = serde_json::to_string(&program).unwrap();
+        let deserialized: Program = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, program);
+        assert_eq!(deserialized.to_string(true), program.to_string(true));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_schema() {
+        let program =
+            Program::from_str("DECLARE ro BIT\nRX(pi/2) 0\nMEASURE 0 ro[0]\nHALT").unwrap();
+        insta::assert_snapshot!(serde_json::to_string_pretty(&program).unwrap());
+    }
+
+    #[test]
+    fn accessors() {
+        let input = "DECLARE ro BIT[2]
+DEFCAL MEASURE 0 addr:
+    CAPTURE 0 \"ro_rx\" flat(duration: 1.0, iq: 1.0) addr
+DEFCIRCUIT BELL a b:
+    H a
+    CNOT a b
+    MEASURE a
+
+X 0
+MEASURE 0 ro[0]
+BELL 0 1
+MEASURE 1 ro[1]
+";
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.body_instructions().count(), 5);
+        assert_eq!(
+            program.gates().map(|gate| &gate.name).collect::<Vec<_>>(),
+            ["X", "BELL"]
+        );
+        assert_eq!(
+            program
+                .measurements()
+                .map(|measurement| measurement.qubit.clone())
+                .collect::<Vec<_>>(),
+            [Qubit::Fixed(0), Qubit::Fixed(1)]
+        );
+
+        let filtered = program
+            .filter_instructions(|instruction| !matches!(instruction, Instruction::Measurement(_)));
+        assert_eq!(filtered.calibrations, program.calibrations);
+        assert_eq!(filtered.memory_regions, program.memory_regions);
+        assert_eq!(
+            filtered.instructions,
+            [0, 1, 3].map(|index| program.instructions[index].clone())
+        );
+    }
 
     #[test]
     fn program_eq() {
@@ -592,6 +1036,85 @@ I 0
         assert_eq!(expected, actual);
     }
 
+    #[test]
+    fn test_source_text() {
+        let input = "DECLARE ro BIT
+RX( pi/2 )   0 # a comment
+MEASURE 0 ro
+
+DEFCAL X 0:
+    NOP
+H 0";
+        let program = Program::parse_with_source_text(input).unwrap();
+        assert_eq!(program, Program::from_str(input).unwrap());
+        assert_eq!(program.get_source_text(0), Some("RX( pi/2 )   0"));
+        assert_eq!(program.get_source_text(1), Some("MEASURE 0 ro"));
+        assert_eq!(program.get_source_text(2), Some("H 0"));
+        assert_eq!(program.get_source_text(3), None);
+        assert_eq!(
+            program.get_source_span(0).map(|span| span.to_string()),
+            Some("2:1-2:15".to_string())
+        );
+        assert_eq!(
+            program.get_source_span(2).map(|span| span.range()),
+            Some(76..79)
+        );
+
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(program.get_source_text(0), None);
+        assert_eq!(program.get_source_span(0), None);
+    }
+
+    #[test]
+    fn test_source_text_after_direct_edits() {
+        let mut program = Program::parse_with_source_text("H  0\nX  1\nY  2").unwrap();
+        program.instructions.remove(0);
+        program.instructions[1] = Instruction::Nop;
+        assert_eq!(program.get_source_text(0), None);
+        assert_eq!(program.get_source_span(0), None);
+        assert_eq!(program.get_source_text(1), None);
+
+        program.instructions.insert(0, Instruction::Nop);
+        assert_eq!(program.get_source_text(0), None);
+        assert_eq!(program.get_source_text(1), Some("X  1"));
+    }
+
+    #[test]
+    fn test_parse_with_recovery() {
+        let input = "H 0
+RX( 0
+X 1
+DEFCAL X 0:
+    BAD( 0
+    NOP
+CNOT 0 1; Y 2 )
+Y 2 `
+Z 3";
+        let (program, errors) = Program::parse_with_recovery(input);
+        assert_eq!(
+            program,
+            Program::from_str("H 0\nX 1\nCNOT 0 1\nZ 3").unwrap()
+        );
+        assert_eq!(program.get_source_text(3), Some("Z 3"));
+        let locations: Vec<_> = errors
+            .iter()
+            .map(|error| match error {
+                SyntaxError::LexError(error) => (error.line(), "lex"),
+                SyntaxError::ParseError(error) => (error.line(), "parse"),
+                SyntaxError::Leftover(_) => (0, "leftover"),
+            })
+            .collect();
+        assert_eq!(
+            locations,
+            vec![(2, "parse"), (5, "parse"), (7, "parse"), (8, "lex")]
+        );
+
+        let input = "DECLARE ro BIT\nH 0\nMEASURE 0 ro";
+        let (program, errors) = Program::parse_with_recovery(input);
+        assert!(errors.is_empty());
+        assert_eq!(program, Program::from_str(input).unwrap());
+    }
+
     #[test]
     fn test_add_instructions() {
         let mut p = Program::new();
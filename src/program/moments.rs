@@ -0,0 +1,254 @@
+//! Partitioning the gates of a program into moments: slices of time in which gates on disjoint
+//! qubits are applied in parallel.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+use crate::instruction::{Gate, Instruction, Measurement, Qubit, Reset};
+use crate::Program;
+
+/// Places items into the earliest moment after every earlier item which shares a resource with
+/// them, such as a qubit.
+#[derive(Clone, Debug)]
+pub(crate) struct Scheduler<T> {
+    /// the first moment in which each resource is free
+    free: HashMap<T, usize>,
+    /// the first moment in which any item may be placed, as set by a barrier
+    floor: usize,
+    /// the number of moments used so far
+    len: usize,
+}
+
+impl<T> Default for Scheduler<T> {
+    fn default() -> Self {
+        Self {
+            free: HashMap::new(),
+            floor: 0,
+            len: 0,
+        }
+    }
+}
+
+impl<T: Eq + Hash> Scheduler<T> {
+    /// Place an item using `resources` and return the index of its moment.
+    pub(crate) fn place(&mut self, resources: impl IntoIterator<Item = T>) -> usize {
+        let resources: Vec<T> = resources.into_iter().collect();
+        let moment = resources
+            .iter()
+            .filter_map(|resource| self.free.get(resource))
+            .fold(self.floor, |moment, free| moment.max(*free));
+        for resource in resources {
+            self.free.insert(resource, moment + 1);
+        }
+        self.len = self.len.max(moment + 1);
+        moment
+    }
+
+    /// Place every later item after all of the items placed so far.
+    pub(crate) fn barrier(&mut self) {
+        self.floor = self.len;
+    }
+
+    /// The number of moments used so far.
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+}
+
+/// What an instruction occupies while it is applied.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum Resource {
+    Qubit(Qubit),
+    Memory(String),
+}
+
+/// The resources used by `instruction` if it is placed in a moment, or `None` if it is not.
+fn resources(instruction: &Instruction) -> Option<Vec<Resource>> {
+    let qubits = match instruction {
+        Instruction::Gate(Gate { qubits, .. }) => qubits.clone(),
+        Instruction::Measurement(Measurement { qubit, .. })
+        | Instruction::Reset(Reset { qubit: Some(qubit) }) => vec![qubit.clone()],
+        _ => return None,
+    };
+    let accesses = instruction.get_memory_accesses();
+    let memory = accesses
+        .reads
+        .into_iter()
+        .chain(accesses.writes)
+        .chain(accesses.captures)
+        .collect::<HashSet<_>>();
+    Some(
+        qubits
+            .into_iter()
+            .map(Resource::Qubit)
+            .chain(memory.into_iter().map(Resource::Memory))
+            .collect(),
+    )
+}
+
+/// Whether the instructions after `instruction` must be placed in later moments than those before
+/// it. This is so of the instructions which are neither placed in moments nor only declare or
+/// define something, such as control flow, classical computation, and pulse-level instructions.
+fn is_barrier(instruction: &Instruction) -> bool {
+    !matches!(
+        instruction,
+        Instruction::CalibrationDefinition(_)
+            | Instruction::CircuitDefinition(_)
+            | Instruction::Declaration(_)
+            | Instruction::FrameDefinition(_)
+            | Instruction::GateDefinition(_)
+            | Instruction::Include(_)
+            | Instruction::MeasureCalibrationDefinition(_)
+            | Instruction::Nop
+            | Instruction::Pragma(_)
+            | Instruction::WaveformDefinition(_)
+    )
+}
+
+/// A slice of time in which the instructions of a program are applied in parallel. No two
+/// instructions of a moment act on the same qubit.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Moment<'p> {
+    instructions: Vec<(usize, &'p Instruction)>,
+}
+
+impl<'p> Moment<'p> {
+    /// The instructions of the moment, in the order they appear in the program.
+    pub fn instructions(&self) -> impl Iterator<Item = &'p Instruction> + '_ {
+        self.instructions
+            .iter()
+            .map(|(_, instruction)| *instruction)
+    }
+
+    /// The index of each instruction of the moment among the program's instructions.
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.instructions.iter().map(|(index, _)| *index)
+    }
+
+    /// The qubits acted on by the instructions of the moment.
+    pub fn qubits(&self) -> HashSet<&'p Qubit> {
+        self.instructions
+            .iter()
+            .flat_map(|(_, instruction)| match instruction {
+                Instruction::Gate(Gate { qubits, .. }) => qubits.iter().collect(),
+                Instruction::Measurement(Measurement { qubit, .. })
+                | Instruction::Reset(Reset { qubit: Some(qubit) }) => vec![qubit],
+                _ => vec![],
+            })
+            .collect()
+    }
+
+    /// The number of instructions in the moment.
+    pub fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    /// Whether the moment has no instructions.
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+}
+
+impl Program {
+    /// Partition the gates, measurements, and single-qubit resets of the program into moments,
+    /// placing each in the earliest moment after every earlier instruction acting on one of its
+    /// qubits, or on memory it reads or writes.
+    ///
+    /// Declarations, definitions, and pragmas are left out. Every other instruction, such as
+    /// control flow, classical computation, or a pulse, is also left out, but the instructions
+    /// after it are placed in later moments than those before it.
+    pub fn to_moments(&self) -> Vec<Moment<'_>> {
+        let mut scheduler = Scheduler::default();
+        let mut moments: Vec<Moment> = vec![];
+        for (index, instruction) in self.instructions.iter().enumerate() {
+            match resources(instruction) {
+                Some(resources) => {
+                    let moment = scheduler.place(resources);
+                    if moments.len() <= moment {
+                        moments.resize_with(moment + 1, Moment::default);
+                    }
+                    moments[moment].instructions.push((index, instruction));
+                }
+                None if is_barrier(instruction) => scheduler.barrier(),
+                None => {}
+            }
+        }
+        moments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::Scheduler;
+    use crate::Program;
+
+    /// The indices of the instructions of each moment of `input`.
+    fn moment_indices(input: &str) -> Vec<Vec<usize>> {
+        Program::from_str(input)
+            .unwrap()
+            .to_moments()
+            .iter()
+            .map(|moment| moment.indices().collect())
+            .collect()
+    }
+
+    #[rstest]
+    #[case("", vec![])]
+    #[case("H 0\nH 1\nCNOT 0 1\nX 2", vec![vec![0, 1, 3], vec![2]])]
+    #[case("H 0\nCNOT 0 1\nH 0\nX 1", vec![vec![0], vec![1], vec![2, 3]])]
+    #[case("DECLARE ro BIT\nH 0\nX 1\nMEASURE 1 ro\nRESET 0", vec![vec![0, 1], vec![2, 3]])]
+    #[case(
+        "DECLARE ro REAL\nMEASURE 0 ro[0]\nRX(ro[0]) 1\nX 2",
+        vec![vec![0, 2], vec![1]]
+    )]
+    #[case("H 0\nPRAGMA INITIAL_REWIRING \"NAIVE\"\nH 1", vec![vec![0, 2]])]
+    #[case("H 0\nLABEL @a\nH 1\nX 0", vec![vec![0], vec![2, 3]])]
+    #[case("H 0\nH 1\nRESET\nX 0", vec![vec![0, 1], vec![3]])]
+    fn test_to_moments(#[case] input: &str, #[case] expected: Vec<Vec<usize>>) {
+        assert_eq!(moment_indices(input), expected);
+    }
+
+    #[test]
+    fn test_moment_accessors() {
+        let program = Program::from_str("H 0\nCNOT 1 2").unwrap();
+        let moments = program.to_moments();
+        assert_eq!(moments.len(), 1);
+        let moment = &moments[0];
+        assert_eq!(moment.len(), 2);
+        assert_eq!(
+            moment.instructions().collect::<Vec<_>>(),
+            program.instructions.iter().collect::<Vec<_>>()
+        );
+        assert_eq!(moment.qubits().len(), 3);
+    }
+
+    #[test]
+    fn test_scheduler_barrier() {
+        let mut scheduler = Scheduler::default();
+        assert_eq!(scheduler.place([0]), 0);
+        assert_eq!(scheduler.place([0, 1]), 1);
+        assert_eq!(scheduler.place([2]), 0);
+        scheduler.barrier();
+        assert_eq!(scheduler.place([3]), 2);
+        assert_eq!(scheduler.place(Vec::<i32>::new()), 2);
+        assert_eq!(scheduler.len(), 3);
+    }
+}
@@ -0,0 +1,227 @@
+//! Combine gate libraries from several programs under distinct namespaces.
+//!
+//! A namespaced name is written as the namespace and the name separated by a `.`, such as
+//! `mylib.MYGATE`. Namespaces may themselves be nested, as in `vendor.mylib.MYGATE`.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashSet;
+
+use crate::instruction::{CircuitDefinition, GateDefinition, Instruction};
+use crate::Program;
+
+/// The character separating a namespace from the name it qualifies.
+pub const NAMESPACE_SEPARATOR: char = '.';
+
+/// Errors which may occur while importing definitions into a namespace.
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum NamespaceError {
+    #[error("{0:?} is not a valid namespace")]
+    InvalidNamespace(String),
+
+    #[error("a definition named {0} already exists in the program")]
+    DuplicateDefinition(String),
+}
+
+/// Qualify `name` with `namespace`, such that `qualify_name("mylib", "MYGATE")` is `mylib.MYGATE`.
+pub fn qualify_name(namespace: &str, name: &str) -> String {
+    format!("{namespace}{NAMESPACE_SEPARATOR}{name}")
+}
+
+/// Split a possibly-namespaced name into its namespace, if any, and the unqualified name.
+pub fn split_namespace(name: &str) -> (Option<&str>, &str) {
+    match name.rsplit_once(NAMESPACE_SEPARATOR) {
+        Some((namespace, name)) => (Some(namespace), name),
+        None => (None, name),
+    }
+}
+
+/// Whether `namespace` is made up of valid Quil identifiers separated by [`NAMESPACE_SEPARATOR`].
+fn is_valid_namespace(namespace: &str) -> bool {
+    namespace.split(NAMESPACE_SEPARATOR).all(|segment| {
+        let mut chars = segment.chars();
+        matches!(chars.next(), Some(first) if first.is_ascii_alphabetic() || first == '_')
+            && segment.ends_with(|chr: char| chr.is_ascii_alphanumeric() || chr == '_')
+            && chars.all(|chr| chr.is_ascii_alphanumeric() || chr == '_' || chr == '-')
+    })
+}
+
+/// Return the name of the gate or circuit defined by `instruction`, if it is such a definition.
+fn get_definition_name(instruction: &Instruction) -> Option<&str> {
+    match instruction {
+        Instruction::GateDefinition(GateDefinition { name, .. })
+        | Instruction::CircuitDefinition(CircuitDefinition { name, .. }) => Some(name),
+        _ => None,
+    }
+}
+
+/// Rename every gate in `instructions` which invokes one of `names`, qualifying it with `namespace`.
+fn qualify_gate_invocations(
+    instructions: &mut [Instruction],
+    names: &HashSet<String>,
+    namespace: &str,
+) {
+    for instruction in instructions {
+        match instruction {
+            Instruction::Gate(gate) if names.contains(gate.name.as_str()) => {
+                gate.name = qualify_name(namespace, &gate.name).into();
+            }
+            Instruction::CircuitDefinition(definition) => {
+                qualify_gate_invocations(&mut definition.instructions, names, namespace)
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Program {
+    /// Import the gate (`DEFGATE`) and circuit (`DEFCIRCUIT`) definitions of `other` into this
+    /// program, qualifying the name of each with `namespace`. Invocations of those definitions within
+    /// imported circuit bodies are qualified as well, so that the imported definitions remain
+    /// self-consistent.
+    ///
+    /// Nothing is imported if `namespace` is invalid or if any qualified name collides with a
+    /// definition already present in this program.
+    pub fn import_definitions(
+        &mut self,
+        other: &Program,
+        namespace: &str,
+    ) -> Result<(), NamespaceError> {
+        if !is_valid_namespace(namespace) {
+            return Err(NamespaceError::InvalidNamespace(namespace.to_string()));
+        }
+
+        let existing: HashSet<&str> = self
+            .instructions
+            .iter()
+            .filter_map(get_definition_name)
+            .collect();
+        let imported_names: HashSet<String> = other
+            .instructions
+            .iter()
+            .filter_map(get_definition_name)
+            .map(String::from)
+            .collect();
+
+        let mut imported: Vec<Instruction> = other
+            .instructions
+            .iter()
+            .filter(|instruction| get_definition_name(instruction).is_some())
+            .cloned()
+            .collect();
+        for instruction in &mut imported {
+            match instruction {
+                Instruction::GateDefinition(GateDefinition { name, .. })
+                | Instruction::CircuitDefinition(CircuitDefinition { name, .. }) => {
+                    *name = qualify_name(namespace, name).into();
+                    if existing.contains(name.as_str()) {
+                        return Err(NamespaceError::DuplicateDefinition(name.to_string()));
+                    }
+                }
+                _ => {}
+            }
+        }
+        qualify_gate_invocations(&mut imported, &imported_names, namespace);
+
+        self.add_instructions(imported);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{split_namespace, NamespaceError};
+    use crate::Program;
+
+    const LIBRARY: &str = r#"DEFGATE MYGATE:
+    0, 1
+    1, 0
+
+DEFCIRCUIT BELL a b:
+    H a
+    MYGATE b
+    CNOT a b
+"#;
+
+    #[test]
+    fn test_import_definitions() {
+        let library = Program::from_str(LIBRARY).unwrap();
+        let mut program = Program::from_str("mylib.BELL 0 1").unwrap();
+        program.import_definitions(&library, "mylib").unwrap();
+
+        let expected = Program::from_str(
+            r#"mylib.BELL 0 1
+DEFGATE mylib.MYGATE:
+    0, 1
+    1, 0
+
+DEFCIRCUIT mylib.BELL a b:
+    H a
+    mylib.MYGATE b
+    CNOT a b
+"#,
+        )
+        .unwrap();
+        assert_eq!(program, expected);
+
+        // The namespaced program survives a round trip through its string representation.
+        let roundtrip = Program::from_str(&program.to_string(true)).unwrap();
+        assert_eq!(program, roundtrip);
+    }
+
+    #[test]
+    fn test_import_same_library_twice() {
+        let library = Program::from_str(LIBRARY).unwrap();
+        let mut program = Program::new();
+        program.import_definitions(&library, "first").unwrap();
+        program.import_definitions(&library, "second").unwrap();
+        assert_eq!(program.instructions.len(), 4);
+
+        assert_eq!(
+            program.import_definitions(&library, "first"),
+            Err(NamespaceError::DuplicateDefinition(
+                "first.MYGATE".to_string()
+            ))
+        );
+        assert_eq!(program.instructions.len(), 4);
+    }
+
+    #[rstest]
+    #[case("")]
+    #[case("1lib")]
+    #[case("my lib")]
+    #[case("mylib.")]
+    #[case("mylib-")]
+    fn test_invalid_namespace(#[case] namespace: &str) {
+        let library = Program::from_str(LIBRARY).unwrap();
+        let mut program = Program::new();
+        assert_eq!(
+            program.import_definitions(&library, namespace),
+            Err(NamespaceError::InvalidNamespace(namespace.to_string()))
+        );
+    }
+
+    #[rstest]
+    #[case("MYGATE", (None, "MYGATE"))]
+    #[case("mylib.MYGATE", (Some("mylib"), "MYGATE"))]
+    #[case("vendor.mylib.MYGATE", (Some("vendor.mylib"), "MYGATE"))]
+    fn test_split_namespace(#[case] name: &str, #[case] expected: (Option<&str>, &str)) {
+        assert_eq!(split_namespace(name), expected);
+    }
+}
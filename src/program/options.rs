@@ -0,0 +1,376 @@
+//! Options setting which version of Quil programs are parsed against, and whether constructs
+//! which are not part of any version of the Quil specification are accepted.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+
+use nom_locate::LocatedSpan;
+
+use crate::instruction::{
+    Calibration, Capture, CircuitDefinition, Gate, GateDefinition, GateModifier, GateSpecification,
+    Instruction, MeasureCalibrationDefinition, Pulse, RawCapture,
+};
+use crate::parser::{lex, lex_case_insensitive, parse_instructions_with_spans, ParseError};
+
+use super::error::{disallow_leftover, map_parsed};
+use super::{Program, ProgramError, Result, SourceLocation, SourceSpan};
+
+/// The versions of the Quil specification, in the order they were published.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[non_exhaustive]
+pub enum QuilVersion {
+    /// The gate-level language: gates and their definitions, circuits, measurement, classical
+    /// memory and control flow, and pragmas.
+    Quil2021,
+    /// Adds the pulse-level instructions of Quil-T, such as `DEFCAL`, `DEFFRAME`, and `PULSE`,
+    /// and gates defined `AS PAULI-SUM`.
+    Quil2022,
+}
+
+impl QuilVersion {
+    /// The most recent version of the specification.
+    pub const LATEST: Self = Self::Quil2022;
+}
+
+impl fmt::Display for QuilVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Quil2021 => write!(f, "Quil 2021"),
+            Self::Quil2022 => write!(f, "Quil 2022"),
+        }
+    }
+}
+
+/// Options for [`Program::parse_with_options`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ParserOptions {
+    /// The version of the specification to accept constructs from. Constructs introduced by
+    /// later versions are rejected.
+    pub quil_version: QuilVersion,
+    /// Whether to accept constructs which are not part of the specification, such as the
+    /// `FORKED` gate modifier and `CALL`.
+    pub allow_extensions: bool,
+    /// Whether to recognize keywords written in any case, such as `measure 0 ro[0]`, as some
+    /// older tools emit. The names of gates and other identifiers remain case-sensitive, but an
+    /// identifier spelled like a keyword, such as a memory region named `real`, is read as that
+    /// keyword.
+    pub case_insensitive_keywords: bool,
+}
+
+/// Accept everything this crate can parse.
+impl Default for ParserOptions {
+    fn default() -> Self {
+        Self {
+            quil_version: QuilVersion::LATEST,
+            allow_extensions: true,
+            case_insensitive_keywords: false,
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Accept only the constructs of `quil_version`, without any extensions.
+    pub fn strict(quil_version: QuilVersion) -> Self {
+        Self {
+            quil_version,
+            allow_extensions: false,
+            case_insensitive_keywords: false,
+        }
+    }
+}
+
+/// What a construct of Quil requires to be accepted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Requirement {
+    /// The construct was introduced by this version of the specification.
+    Version(QuilVersion),
+    /// The construct is not part of the specification.
+    Extension,
+}
+
+impl fmt::Display for Requirement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Version(version) => write!(f, "{version} or later"),
+            Self::Extension => write!(f, "extensions to be allowed"),
+        }
+    }
+}
+
+impl ParserOptions {
+    /// Whether a construct with `requirement` is accepted.
+    fn accepts(&self, requirement: Requirement) -> bool {
+        match requirement {
+            Requirement::Version(version) => version <= self.quil_version,
+            Requirement::Extension => self.allow_extensions,
+        }
+    }
+}
+
+/// The constructs used by `instruction` which are not part of [`QuilVersion::Quil2021`], each
+/// named as in an error, and what they require. Instructions within blocks are not included.
+fn requirements(instruction: &Instruction) -> Vec<(&'static str, Requirement)> {
+    const QUIL_T: Requirement = Requirement::Version(QuilVersion::Quil2022);
+    let mut requirements = vec![];
+    let mut require_modifiers = |modifiers: &[GateModifier]| {
+        if modifiers.contains(&GateModifier::Forked) {
+            requirements.push(("the FORKED modifier", Requirement::Extension));
+        }
+    };
+    match instruction {
+        Instruction::Gate(Gate { modifiers, .. }) => require_modifiers(modifiers),
+        Instruction::Call(_) => requirements.push(("CALL", Requirement::Extension)),
+        Instruction::CalibrationDefinition(Calibration { modifiers, .. }) => {
+            require_modifiers(modifiers);
+            requirements.push(("DEFCAL", QUIL_T));
+        }
+        Instruction::GateDefinition(GateDefinition {
+            specification: GateSpecification::PauliSum(_),
+            ..
+        }) => requirements.push(("PAULI-SUM gate definitions", QUIL_T)),
+        Instruction::MeasureCalibrationDefinition(_) => {
+            requirements.push(("DEFCAL MEASURE", QUIL_T))
+        }
+        Instruction::FrameDefinition(_) => requirements.push(("DEFFRAME", QUIL_T)),
+        Instruction::WaveformDefinition(_) => requirements.push(("DEFWAVEFORM", QUIL_T)),
+        Instruction::Capture(Capture { blocking, .. })
+        | Instruction::Pulse(Pulse { blocking, .. })
+        | Instruction::RawCapture(RawCapture { blocking, .. }) => {
+            requirements.push((
+                match instruction {
+                    Instruction::Capture(_) => "CAPTURE",
+                    Instruction::Pulse(_) => "PULSE",
+                    _ => "RAW-CAPTURE",
+                },
+                QUIL_T,
+            ));
+            if !blocking {
+                requirements.push(("NONBLOCKING", QUIL_T));
+            }
+        }
+        Instruction::Delay(_) => requirements.push(("DELAY", QUIL_T)),
+        Instruction::Fence(_) => requirements.push(("FENCE", QUIL_T)),
+        Instruction::SetFrequency(_) => requirements.push(("SET-FREQUENCY", QUIL_T)),
+        Instruction::SetPhase(_) => requirements.push(("SET-PHASE", QUIL_T)),
+        Instruction::SetScale(_) => requirements.push(("SET-SCALE", QUIL_T)),
+        Instruction::ShiftFrequency(_) => requirements.push(("SHIFT-FREQUENCY", QUIL_T)),
+        Instruction::ShiftPhase(_) => requirements.push(("SHIFT-PHASE", QUIL_T)),
+        Instruction::SwapPhases(_) => requirements.push(("SWAP-PHASES", QUIL_T)),
+        _ => {}
+    }
+    requirements
+}
+
+/// The instructions within the block of `instruction`, if it has one.
+fn block(instruction: &Instruction) -> &[Instruction] {
+    match instruction {
+        Instruction::CalibrationDefinition(Calibration { instructions, .. })
+        | Instruction::CircuitDefinition(CircuitDefinition { instructions, .. })
+        | Instruction::MeasureCalibrationDefinition(MeasureCalibrationDefinition {
+            instructions,
+            ..
+        }) => instructions,
+        _ => &[],
+    }
+}
+
+/// Fail if `instruction`, or any instruction within its block, uses a construct which `options`
+/// do not accept. `location` is where `instruction` starts.
+#[allow(clippy::result_large_err)]
+fn check(
+    instruction: &Instruction,
+    options: &ParserOptions,
+    location: SourceLocation,
+) -> std::result::Result<(), ProgramError<Program>> {
+    for (construct, requirement) in requirements(instruction) {
+        if !options.accepts(requirement) {
+            return Err(ProgramError::UnsupportedConstruct {
+                construct: construct.to_string(),
+                required: requirement,
+                location,
+            });
+        }
+    }
+    for instruction in block(instruction) {
+        check(instruction, options, location)?;
+    }
+    Ok(())
+}
+
+impl Program {
+    /// Parse `input` like [`Program::from_str`](std::str::FromStr), rejecting any construct
+    /// which `options` do not accept with an error naming the version of the specification, or
+    /// the allowance of extensions, it requires.
+    #[allow(clippy::result_large_err)]
+    pub fn parse_with_options(input: &str, options: &ParserOptions) -> Result<Self> {
+        let without_spans = |instructions: Vec<(Instruction, SourceSpan)>| {
+            Self::from(
+                instructions
+                    .into_iter()
+                    .map(|(instruction, _)| instruction)
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let lexed = if options.case_insensitive_keywords {
+            lex_case_insensitive(LocatedSpan::new(input))
+        } else {
+            lex(LocatedSpan::new(input))
+        }
+        .map_err(ProgramError::from)?;
+        let instructions = map_parsed(
+            disallow_leftover(
+                parse_instructions_with_spans(&lexed).map_err(ParseError::from_nom_internal_err),
+            ),
+            |instructions| instructions,
+        )
+        .map_err(|error| error.map_parsed(without_spans))?;
+        for (instruction, span) in &instructions {
+            check(instruction, options, span.start)?;
+        }
+        Ok(without_spans(instructions))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rstest::rstest;
+
+    use super::{ParserOptions, QuilVersion, Requirement};
+    use crate::program::{ProgramError, SourceLocation};
+    use crate::Program;
+
+    const QUIL_T: &str = "DEFFRAME 0 \"rf\":\n    SAMPLE-RATE: 1.0\n\nDEFCAL X 0:\n    NONBLOCKING PULSE 0 \"rf\" flat(duration: 1.0, iq: 1.0)\n\nX 0\nFENCE 0\n";
+
+    #[rstest]
+    #[case(
+        "H 0\nCNOT 0 1\nMEASURE 0\n",
+        ParserOptions::strict(QuilVersion::Quil2021)
+    )]
+    #[case(QUIL_T, ParserOptions::strict(QuilVersion::Quil2022))]
+    #[case("FORKED RX(0, pi) 0 1\n", ParserOptions::default())]
+    #[case(
+        "FORKED RX(0, pi) 0 1\n",
+        ParserOptions {
+            quil_version: QuilVersion::Quil2021,
+            allow_extensions: true,
+            case_insensitive_keywords: false,
+        }
+    )]
+    fn test_accepted(#[case] input: &str, #[case] options: ParserOptions) {
+        let program = Program::parse_with_options(input, &options).unwrap();
+        assert_eq!(program, input.parse().unwrap());
+    }
+
+    #[rstest]
+    #[case(
+        QUIL_T,
+        ParserOptions::strict(QuilVersion::Quil2021),
+        "DEFFRAME",
+        Requirement::Version(QuilVersion::Quil2022),
+        (1, 1)
+    )]
+    #[case(
+        "H 0\nDEFCIRCUIT C q:\n    DELAY q 1.0\n",
+        ParserOptions::strict(QuilVersion::Quil2021),
+        "DELAY",
+        Requirement::Version(QuilVersion::Quil2022),
+        (2, 1)
+    )]
+    #[case(
+        "H 0\nX 1; FORKED RX(0, pi) 0 1\n",
+        ParserOptions::strict(QuilVersion::LATEST),
+        "the FORKED modifier",
+        Requirement::Extension,
+        (2, 6)
+    )]
+    #[case(
+        "DECLARE ro BIT\nCALL rng ro\n",
+        ParserOptions::strict(QuilVersion::LATEST),
+        "CALL",
+        Requirement::Extension,
+        (2, 1)
+    )]
+    #[case(
+        "DEFGATE P p q AS PAULI-SUM:\n    ZZ(1.0) p q\n",
+        ParserOptions::strict(QuilVersion::Quil2021),
+        "PAULI-SUM gate definitions",
+        Requirement::Version(QuilVersion::Quil2022),
+        (1, 1)
+    )]
+    fn test_rejected(
+        #[case] input: &str,
+        #[case] options: ParserOptions,
+        #[case] expected_construct: &str,
+        #[case] expected_requirement: Requirement,
+        #[case] expected_location: (u32, usize),
+    ) {
+        match Program::parse_with_options(input, &options) {
+            Err(ProgramError::UnsupportedConstruct {
+                construct,
+                required,
+                location,
+            }) => {
+                assert_eq!(construct, expected_construct);
+                assert_eq!(required, expected_requirement);
+                assert_eq!((location.line, location.column), expected_location);
+            }
+            other => panic!("expected an unsupported construct but found {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_case_insensitive_keywords() {
+        let options = ParserOptions {
+            case_insensitive_keywords: true,
+            ..ParserOptions::default()
+        };
+        let program = Program::parse_with_options(
+            "declare ro bit[2]\ndagger h 0\nmeasure 0 ro[0]\nJump-When @end ro[0]\nlabel @end\n",
+            &options,
+        )
+        .unwrap();
+        let expected: Program =
+            "DECLARE ro BIT[2]\nDAGGER h 0\nMEASURE 0 ro[0]\nJUMP-WHEN @end ro[0]\nLABEL @end\n"
+                .parse()
+                .unwrap();
+        assert_eq!(program, expected);
+
+        assert!(
+            Program::parse_with_options("measure 0 ro[0]\n", &ParserOptions::default()).is_err()
+        );
+    }
+
+    #[test]
+    fn test_error_message() {
+        let error = ProgramError::<Program>::UnsupportedConstruct {
+            construct: "PULSE".to_string(),
+            required: Requirement::Version(QuilVersion::Quil2022),
+            location: SourceLocation::default(),
+        };
+        assert_eq!(
+            error.to_string(),
+            "PULSE at line 1, column 1 requires Quil 2022 or later"
+        );
+    }
+
+    #[test]
+    fn test_syntax_error() {
+        assert!(matches!(
+            Program::parse_with_options("H(", &ParserOptions::default()),
+            Err(ProgramError::Syntax(_))
+        ));
+    }
+}
@@ -0,0 +1,317 @@
+//! Analyze gate parameters for parametric compilation.
+//!
+//! Backends which support parametric execution compile a program once and then update gate
+//! parameters from memory before each execution. They can only do so for parameters which are
+//! affine functions of declared memory values, such as `RX(2*theta[0] + pi/2) 0`.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::expression::{AffineExpression, Expression};
+use crate::instruction::{
+    visit::{walk_expression_mut, walk_instruction_mut},
+    Calibration, CircuitDefinition, Gate, GateDefinition, Instruction, InstructionRewriter,
+    WaveformDefinition,
+};
+use crate::Program;
+
+use super::{ProgramError, Result};
+
+/// The form of a single gate parameter.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ParameterForm {
+    /// The parameter is an affine function of declared memory values (or a constant).
+    Affine(AffineExpression),
+    /// The parameter is an affine function of memory values, but references a memory region which
+    /// the program does not declare: the first such region by name, if there are several.
+    UndeclaredMemoryReference(String),
+    /// The parameter is not an affine function of memory values.
+    NonAffine,
+}
+
+/// The analysis of one parameter of one gate in a program.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GateParameterAnalysis {
+    /// The index of the gate within the program's instructions.
+    pub instruction_index: usize,
+    /// The index of the parameter within the gate's parameters.
+    pub parameter_index: usize,
+    /// The parameter expression.
+    pub parameter: Expression,
+    /// The form of the parameter.
+    pub form: ParameterForm,
+}
+
+impl Program {
+    /// Determine, for every parameter of every gate in the body of the program, whether it is an
+    /// affine function of declared memory values, and if so extract its coefficients.
+    pub fn analyze_gate_parameters(&self) -> Vec<GateParameterAnalysis> {
+        let mut analyses = vec![];
+
+        for (instruction_index, instruction) in self.instructions.iter().enumerate() {
+            if let Instruction::Gate(Gate { parameters, .. }) = instruction {
+                for (parameter_index, parameter) in parameters.iter().enumerate() {
+                    let form = match parameter.to_affine() {
+                        None => ParameterForm::NonAffine,
+                        Some(affine) => match affine
+                            .terms
+                            .keys()
+                            .map(|reference| reference.name.as_str())
+                            .filter(|name| !self.memory_regions.contains_key(*name))
+                            .min()
+                        {
+                            Some(name) => {
+                                ParameterForm::UndeclaredMemoryReference(name.to_string())
+                            }
+                            None => ParameterForm::Affine(affine),
+                        },
+                    };
+                    analyses.push(GateParameterAnalysis {
+                        instruction_index,
+                        parameter_index,
+                        parameter: parameter.clone(),
+                        form,
+                    });
+                }
+            }
+        }
+
+        analyses
+    }
+
+    /// Return the analyses of gate parameters which are not affine functions of declared memory
+    /// values, and so cannot be updated by a parametric-execution backend.
+    pub fn get_non_affine_gate_parameters(&self) -> Vec<GateParameterAnalysis> {
+        self.analyze_gate_parameters()
+            .into_iter()
+            .filter(|analysis| !matches!(analysis.form, ParameterForm::Affine(_)))
+            .collect()
+    }
+
+    /// Return a copy of the program with each variable, such as `%theta`, replaced by its value
+    /// in `values`: in the parameters of gates, and in the expressions of calibrations, frames,
+    /// and every other instruction. Within the definition of a gate, circuit, calibration, or
+    /// waveform, the variables which are parameters of the definition are left as they are.
+    ///
+    /// Fails with [`ProgramError::UnboundVariables`], listing them in the order they are found,
+    /// if any variables other than those parameters are left without a value.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use quil_rs::expression::Expression;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DEFCIRCUIT ROT(%angle) q:\n    RX(%angle) q\n\nRX(%theta) 0\nROT(%theta/2) 1"
+    ///     .parse()
+    ///     .unwrap();
+    /// let values = HashMap::from([("theta".to_string(), Expression::PiConstant)]);
+    /// let substituted = program.substitute_variables(&values).unwrap();
+    /// assert_eq!(
+    ///     substituted.to_string(true),
+    ///     "DEFCIRCUIT ROT(%angle) q:\n\tRX(%angle) q\n\nRX(pi) 0\nROT((pi/2)) 1\n"
+    /// );
+    /// ```
+    #[allow(clippy::result_large_err)]
+    pub fn substitute_variables(&self, values: &HashMap<String, Expression>) -> Result<Self> {
+        let mut substitution = Substitution {
+            values,
+            parameters: vec![],
+            unbound: vec![],
+        };
+        let mut program = self.clone();
+        substitution.rewrite_program(&mut program);
+        if substitution.unbound.is_empty() {
+            Ok(program)
+        } else {
+            Err(ProgramError::UnboundVariables(substitution.unbound))
+        }
+    }
+}
+
+/// Replaces variables with their `values`, except those which are `parameters` of the
+/// definitions being rewritten, and records the other variables it finds no value for.
+struct Substitution<'v> {
+    values: &'v HashMap<String, Expression>,
+    /// The parameters of each definition being rewritten, innermost last.
+    parameters: Vec<HashSet<String>>,
+    unbound: Vec<String>,
+}
+
+impl InstructionRewriter for Substitution<'_> {
+    fn rewrite_instruction(&mut self, instruction: &mut Instruction) {
+        let parameters: HashSet<String> = match instruction {
+            Instruction::CalibrationDefinition(Calibration { parameters, .. }) => parameters
+                .iter()
+                .filter_map(|parameter| match parameter {
+                    Expression::Variable(name) => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            Instruction::CircuitDefinition(CircuitDefinition { parameters, .. })
+            | Instruction::GateDefinition(GateDefinition { parameters, .. }) => {
+                parameters.iter().cloned().collect()
+            }
+            Instruction::WaveformDefinition(WaveformDefinition { definition, .. }) => {
+                definition.parameters.iter().cloned().collect()
+            }
+            _ => return walk_instruction_mut(self, instruction),
+        };
+        self.parameters.push(parameters);
+        walk_instruction_mut(self, instruction);
+        self.parameters.pop();
+    }
+
+    fn rewrite_expression(&mut self, expression: &mut Expression) {
+        let name = match expression {
+            Expression::Variable(name) => name,
+            _ => return walk_expression_mut(self, expression),
+        };
+        if self.parameters.iter().any(|scope| scope.contains(name)) {
+            return;
+        }
+        match self.values.get(name) {
+            Some(value) => *expression = value.clone(),
+            None if !self.unbound.contains(name) => self.unbound.push(name.clone()),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use super::ParameterForm;
+    use crate::expression::{AffineExpression, Expression};
+    use crate::instruction::MemoryReference;
+    use crate::program::ProgramError;
+    use crate::{real, Program};
+
+    #[test]
+    fn test_analyze_gate_parameters() {
+        let program = Program::from_str(
+            "DECLARE theta REAL[2]
+RX(((2*theta[0]) - (theta[1]/2)) + 1) 0
+RZ(pi) 0
+RX(cos(theta[0])) 0
+RY(%alpha) 0
+RX(beta[0]) 0
+RX(gamma[0] + (beta[0] + alpha[0])) 0
+H 0",
+        )
+        .unwrap();
+        let forms: Vec<_> = program
+            .analyze_gate_parameters()
+            .into_iter()
+            .map(|analysis| (analysis.instruction_index, analysis.form))
+            .collect();
+
+        let theta = |index| MemoryReference {
+            name: "theta".into(),
+            index,
+        };
+        assert_eq!(
+            forms,
+            vec![
+                (
+                    0,
+                    ParameterForm::Affine(AffineExpression {
+                        terms: HashMap::from([(theta(0), real!(2.0)), (theta(1), real!(-0.5))]),
+                        constant: real!(1.0),
+                    })
+                ),
+                (
+                    1,
+                    ParameterForm::Affine(AffineExpression {
+                        terms: HashMap::new(),
+                        constant: real!(std::f64::consts::PI),
+                    })
+                ),
+                (2, ParameterForm::NonAffine),
+                (3, ParameterForm::NonAffine),
+                (
+                    4,
+                    ParameterForm::UndeclaredMemoryReference("beta".to_string())
+                ),
+                (
+                    5,
+                    ParameterForm::UndeclaredMemoryReference("alpha".to_string())
+                ),
+            ]
+        );
+
+        assert_eq!(program.get_non_affine_gate_parameters().len(), 4);
+    }
+
+    #[test]
+    fn test_substitute_variables() {
+        let program = Program::from_str(
+            r#"DEFFRAME 0 "rf":
+    CENTER-FREQUENCY: %frequency
+DEFWAVEFORM wf(%scale):
+    %scale, %offset
+DEFCAL RX(%theta) 0:
+    SHIFT-PHASE 0 "rf" %theta + %detuning
+DEFGATE PHASED(%phi) AS MATRIX:
+    1, 0
+    0, cis(%phi)
+
+RX(%theta) 0
+PULSE 0 "rf" wf(scale: %theta/2)
+PHASED(%theta) 0
+"#,
+        )
+        .unwrap();
+        let values = HashMap::from([
+            ("theta".to_string(), Expression::from_str("pi/2").unwrap()),
+            ("frequency".to_string(), Expression::from(5e9)),
+            ("detuning".to_string(), Expression::from(0.25)),
+            ("offset".to_string(), Expression::from(0.5)),
+        ]);
+        let substituted = program.substitute_variables(&values).unwrap();
+        let expected = Program::from_str(
+            r#"DEFFRAME 0 "rf":
+    CENTER-FREQUENCY: 5000000000.0
+DEFWAVEFORM wf(%scale):
+    %scale, 0.5
+DEFCAL RX(%theta) 0:
+    SHIFT-PHASE 0 "rf" %theta + 0.25
+DEFGATE PHASED(%phi) AS MATRIX:
+    1, 0
+    0, cis(%phi)
+
+RX(pi/2) 0
+PULSE 0 "rf" wf(scale: (pi/2)/2)
+PHASED(pi/2) 0
+"#,
+        )
+        .unwrap();
+        assert_eq!(substituted, expected);
+
+        let values = HashMap::from([("theta".to_string(), Expression::PiConstant)]);
+        assert_eq!(
+            program.substitute_variables(&values),
+            Err(ProgramError::UnboundVariables(vec![
+                "frequency".to_string(),
+                "offset".to_string(),
+                "detuning".to_string(),
+            ]))
+        );
+    }
+}
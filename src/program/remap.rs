@@ -0,0 +1,247 @@
+//! Relabelling the qubits of a program.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::instruction::visit::{InstructionRewriter, InstructionVisitor};
+use crate::instruction::Qubit;
+use crate::Program;
+
+/// Errors which may occur while remapping the qubits of a program.
+#[derive(Clone, Debug, PartialEq, Eq, thiserror::Error)]
+pub enum QubitRemapError {
+    #[error("qubits {first} and {second} would both become qubit {target}")]
+    Collision {
+        first: Qubit,
+        second: Qubit,
+        target: Qubit,
+    },
+}
+
+/// Collects every qubit named anywhere in a program.
+#[derive(Default)]
+struct QubitCollector(BTreeSet<Qubit>);
+
+impl InstructionVisitor for QubitCollector {
+    fn visit_qubit(&mut self, qubit: &Qubit) {
+        if !self.0.contains(qubit) {
+            self.0.insert(qubit.clone());
+        }
+    }
+}
+
+/// Replaces each qubit with the one it is mapped to, if any.
+pub(super) struct QubitRemapper<'a>(pub(super) &'a HashMap<Qubit, Qubit>);
+
+impl InstructionRewriter for QubitRemapper<'_> {
+    fn rewrite_qubit(&mut self, qubit: &mut Qubit) {
+        if let Some(target) = self.0.get(qubit) {
+            *qubit = target.clone();
+        }
+    }
+}
+
+impl Program {
+    /// A copy of the program with each qubit which is a key of `mapping` replaced by its value,
+    /// wherever it appears: in gates, measurements, resets, and other instructions of the body,
+    /// and in the headers, such as the qubits of calibrations and their instructions, and those
+    /// of frames. Qubits which are not keys of `mapping` are left as they are.
+    ///
+    /// The copy does not record the text the program was parsed from. Qubits given as the
+    /// arguments of pragmas are not remapped.
+    ///
+    /// # Errors
+    ///
+    /// Fails if two different qubits of the program would become the same qubit, whether both
+    /// are remapped to it or one is remapped to a qubit which the program already uses and which
+    /// is not itself remapped.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use quil_rs::instruction::Qubit;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "H 0\nCNOT 0 1".parse().unwrap();
+    /// let mapping = HashMap::from([(Qubit::Fixed(0), Qubit::Fixed(1)), (Qubit::Fixed(1), Qubit::Fixed(0))]);
+    /// let remapped = program.remap_qubits(&mapping).unwrap();
+    /// assert_eq!(remapped.to_string(true), "H 1\nCNOT 1 0\n");
+    ///
+    /// let collision = HashMap::from([(Qubit::Fixed(0), Qubit::Fixed(1))]);
+    /// assert!(program.remap_qubits(&collision).is_err());
+    /// ```
+    pub fn remap_qubits(&self, mapping: &HashMap<Qubit, Qubit>) -> Result<Self, QubitRemapError> {
+        let mut collector = QubitCollector::default();
+        collector.visit_program(self);
+        let mut targets: HashMap<&Qubit, &Qubit> = HashMap::new();
+        for qubit in &collector.0 {
+            let target = mapping.get(qubit).unwrap_or(qubit);
+            if let Some(first) = targets.insert(target, qubit) {
+                return Err(QubitRemapError::Collision {
+                    first: first.clone(),
+                    second: qubit.clone(),
+                    target: target.clone(),
+                });
+            }
+        }
+
+        let mut program = self.clone();
+        QubitRemapper(mapping).rewrite_program(&mut program);
+        Ok(program)
+    }
+
+    /// A copy of the program with its fixed qubits renumbered from zero without gaps, keeping
+    /// their order, along with the mapping from each original qubit to its new number.
+    ///
+    /// The qubits used by the instructions of the body are numbered first, and then any others
+    /// which only appear in the headers, such as those of calibrations for qubits the program
+    /// does not use. Qubit variables are left as they are.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::Qubit;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "H 4\nCNOT 4 7\nMEASURE 9".parse().unwrap();
+    /// let (compacted, mapping) = program.compact_qubits();
+    /// assert_eq!(compacted.to_string(true), "H 0\nCNOT 0 1\nMEASURE 2\n");
+    /// assert_eq!(mapping[&Qubit::Fixed(7)], Qubit::Fixed(1));
+    /// ```
+    pub fn compact_qubits(&self) -> (Self, HashMap<Qubit, Qubit>) {
+        let used: BTreeSet<Qubit> = self.get_used_qubits().into_iter().collect();
+        let mut collector = QubitCollector::default();
+        collector.visit_program(self);
+        let headers_only = collector.0.difference(&used).cloned().collect::<Vec<_>>();
+
+        let mapping: HashMap<Qubit, Qubit> = used
+            .into_iter()
+            .chain(headers_only)
+            .filter(|qubit| matches!(qubit, Qubit::Fixed(_)))
+            .enumerate()
+            .map(|(index, qubit)| (qubit, Qubit::Fixed(index as u64)))
+            .collect();
+        let mut program = self.clone();
+        QubitRemapper(&mapping).rewrite_program(&mut program);
+        (program, mapping)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::QubitRemapError;
+    use crate::instruction::Qubit;
+    use crate::Program;
+
+    #[test]
+    fn test_remap_qubits() {
+        let input = r#"DEFFRAME 0 "rf":
+    SAMPLE-RATE: 1.0
+DEFCAL X 0:
+    PULSE 0 "rf" flat(duration: 1e-6, iq: 1.0)
+DEFCAL MEASURE 0 addr:
+    CAPTURE 0 "rf" flat(duration: 1e-6, iq: 1.0) addr
+DEFCIRCUIT BELL a b:
+    H a
+    CNOT a b
+X 0
+BELL 0 2
+MEASURE 0
+RESET 2
+FENCE 0 2
+"#;
+        let expected = r#"DEFFRAME 5 "rf":
+	SAMPLE-RATE: 1
+DEFCAL X 5:
+	PULSE 5 "rf" flat(duration: 1e-6, iq: 1)
+DEFCAL MEASURE 5 addr:
+	CAPTURE 5 "rf" flat(duration: 1e-6, iq: 1) addr[0]
+
+DEFCIRCUIT BELL a b:
+	H a
+	CNOT a b
+
+X 5
+BELL 5 6
+MEASURE 5
+RESET 6
+FENCE 5 6
+"#;
+        let program = Program::from_str(input).unwrap();
+        let mapping = HashMap::from([
+            (Qubit::Fixed(0), Qubit::Fixed(5)),
+            (Qubit::Fixed(2), Qubit::Fixed(6)),
+        ]);
+        let remapped = program.remap_qubits(&mapping).unwrap();
+        assert_eq!(remapped.to_string(true), expected);
+    }
+
+    #[rstest]
+    #[case(
+        "H 0\nH 1",
+        vec![(0, 2), (1, 2)],
+        (Qubit::Fixed(0), Qubit::Fixed(1), Qubit::Fixed(2))
+    )]
+    #[case(
+        "H 0\nH 1",
+        vec![(0, 1)],
+        (Qubit::Fixed(0), Qubit::Fixed(1), Qubit::Fixed(1))
+    )]
+    fn test_remap_collisions(
+        #[case] input: &str,
+        #[case] mapping: Vec<(u64, u64)>,
+        #[case] expected: (Qubit, Qubit, Qubit),
+    ) {
+        let program = Program::from_str(input).unwrap();
+        let mapping = mapping
+            .into_iter()
+            .map(|(from, to)| (Qubit::Fixed(from), Qubit::Fixed(to)))
+            .collect();
+        let (first, second, target) = expected;
+        assert_eq!(
+            program.remap_qubits(&mapping),
+            Err(QubitRemapError::Collision {
+                first,
+                second,
+                target
+            })
+        );
+    }
+
+    #[test]
+    fn test_compact_qubits() {
+        let input = "DEFCAL X 8:\n    NOP\nH 3\nCNOT 3 1\n";
+        let (compacted, mapping) = Program::from_str(input).unwrap().compact_qubits();
+        assert_eq!(
+            compacted.to_string(true),
+            "DEFCAL X 2:\n\tNOP\nH 1\nCNOT 1 0\n"
+        );
+        assert_eq!(
+            mapping,
+            HashMap::from([
+                (Qubit::Fixed(1), Qubit::Fixed(0)),
+                (Qubit::Fixed(3), Qubit::Fixed(1)),
+                (Qubit::Fixed(8), Qubit::Fixed(2)),
+            ])
+        );
+    }
+}
@@ -0,0 +1,334 @@
+//! Describe the shape of the data a Quil program writes into its readout memory.
+//!
+//! Hardware returns readout data per shot for each memory region written by `MEASURE`,
+//! `CAPTURE`, or `RAW-CAPTURE`. A [`ProgramResultSpec`] tells client code how large each of
+//! those buffers is and how its values should be interpreted, so that results can be
+//! pre-allocated and deserialized without inspecting the program text.
+use std::collections::{BTreeMap, BTreeSet};
+
+use thiserror::Error;
+
+use crate::{
+    instruction::{
+        Capture, Identifier, Instruction, Measurement, MemoryReference, RawCapture, ScalarType,
+    },
+    program::MemoryRegion,
+    Program,
+};
+
+/// Errors which may occur while computing a [`ProgramResultSpec`].
+#[derive(Debug, Error, PartialEq)]
+pub enum ResultSpecError {
+    #[error("In instruction {instruction}: undefined memory reference {reference}.")]
+    UndefinedMemoryReference {
+        instruction: Box<Instruction>,
+        reference: String,
+    },
+
+    #[error(
+        "In instruction {instruction}: {reference} is of type {data_type}, which cannot hold {result_type} results."
+    )]
+    DataTypeMismatch {
+        instruction: Box<Instruction>,
+        reference: String,
+        data_type: ScalarType,
+        result_type: ResultType,
+    },
+
+    #[error(
+        "In instruction {instruction}: {reference} already holds {existing} results and cannot also hold {result_type} results."
+    )]
+    ConflictingResultType {
+        instruction: Box<Instruction>,
+        reference: String,
+        existing: ResultType,
+        result_type: ResultType,
+    },
+
+    #[error(
+        "In instruction {instruction}: {reference}[{index}] is out of bounds for a region of length {length}."
+    )]
+    IndexOutOfBounds {
+        instruction: Box<Instruction>,
+        reference: String,
+        index: u64,
+        length: u64,
+    },
+}
+
+pub type ResultSpecResult<T> = Result<T, ResultSpecError>;
+
+/// The kind of readout data written into a memory region.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ResultType {
+    /// One discriminated bit per `MEASURE`, stored in a `BIT` or `INTEGER` region.
+    Bit,
+
+    /// One complex IQ value per `CAPTURE`, stored as a (real, imaginary) pair of `REAL`s.
+    ComplexIq,
+
+    /// A contiguous series of complex IQ samples from `RAW-CAPTURE`, stored as
+    /// (real, imaginary) pairs of `REAL`s filling the region.
+    RawIq,
+}
+
+impl std::fmt::Display for ResultType {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ResultType::Bit => write!(f, "bit"),
+            ResultType::ComplexIq => write!(f, "complex IQ"),
+            ResultType::RawIq => write!(f, "raw IQ"),
+        }
+    }
+}
+
+/// The per-shot layout of a single readout buffer.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResultBufferSpec {
+    /// The data type of the declared memory region.
+    pub data_type: ScalarType,
+    /// The declared length of the memory region, in elements of `data_type`.
+    pub length: u64,
+    /// How the values written to this region should be interpreted.
+    pub result_type: ResultType,
+    /// The indices within the region which are written to by readout instructions. For
+    /// [`ResultType::ComplexIq`] this is the index of the real component of each pair.
+    pub indices: BTreeSet<u64>,
+}
+
+impl ResultBufferSpec {
+    /// The number of values the buffer yields per shot: bits for [`ResultType::Bit`], and complex
+    /// numbers for [`ResultType::ComplexIq`] and [`ResultType::RawIq`].
+    pub fn values_per_shot(&self) -> u64 {
+        match self.result_type {
+            ResultType::Bit => self.length,
+            ResultType::ComplexIq | ResultType::RawIq => self.length / 2,
+        }
+    }
+}
+
+/// The shape and data type of every readout buffer written by a program, keyed on the name of
+/// the memory region.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProgramResultSpec {
+    pub buffers: BTreeMap<String, ResultBufferSpec>,
+}
+
+impl ProgramResultSpec {
+    /// Return the layout of the named buffer, if it is written by readout.
+    pub fn get(&self, name: &str) -> Option<&ResultBufferSpec> {
+        self.buffers.get(name)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.buffers.len()
+    }
+
+    /// Record that `instruction` writes a result of `result_type` into `reference`, spanning
+    /// `width` elements.
+    fn record(
+        &mut self,
+        instruction: &Instruction,
+        reference: &MemoryReference,
+        result_type: ResultType,
+        width: u64,
+        memory_regions: &BTreeMap<Identifier, MemoryRegion>,
+    ) -> ResultSpecResult<()> {
+        let region = memory_regions.get(reference.name.as_str()).ok_or_else(|| {
+            ResultSpecError::UndefinedMemoryReference {
+                instruction: Box::new(instruction.clone()),
+                reference: reference.name.to_string(),
+            }
+        })?;
+        let data_type = region.size.data_type.clone();
+        let length = region.size.length;
+
+        let data_type_allowed = match result_type {
+            ResultType::Bit => matches!(data_type, ScalarType::Bit | ScalarType::Integer),
+            ResultType::ComplexIq | ResultType::RawIq => data_type == ScalarType::Real,
+        };
+        if !data_type_allowed {
+            return Err(ResultSpecError::DataTypeMismatch {
+                instruction: Box::new(instruction.clone()),
+                reference: reference.name.to_string(),
+                data_type,
+                result_type,
+            });
+        }
+
+        if reference
+            .index
+            .checked_add(width)
+            .is_none_or(|end| end > length)
+        {
+            return Err(ResultSpecError::IndexOutOfBounds {
+                instruction: Box::new(instruction.clone()),
+                reference: reference.name.to_string(),
+                index: reference.index.saturating_add(width.saturating_sub(1)),
+                length,
+            });
+        }
+
+        let buffer = self
+            .buffers
+            .entry(reference.name.to_string())
+            .or_insert_with(|| ResultBufferSpec {
+                data_type,
+                length,
+                result_type,
+                indices: BTreeSet::new(),
+            });
+        if buffer.result_type != result_type {
+            return Err(ResultSpecError::ConflictingResultType {
+                instruction: Box::new(instruction.clone()),
+                reference: reference.name.to_string(),
+                existing: buffer.result_type,
+                result_type,
+            });
+        }
+        buffer.indices.insert(reference.index);
+
+        Ok(())
+    }
+}
+
+/// Compute the [`ProgramResultSpec`] for the instructions in the body of the given program.
+///
+/// Only the program body is inspected; to describe the buffers written by calibrated `MEASURE`
+/// instructions at the pulse level, compute the spec of the program returned by
+/// [`Program::expand_calibrations`].
+pub fn get_result_spec(program: &Program) -> ResultSpecResult<ProgramResultSpec> {
+    let mut spec = ProgramResultSpec::default();
+
+    for instruction in &program.instructions {
+        match instruction {
+            Instruction::Measurement(Measurement {
+                target: Some(target),
+                ..
+            }) => spec.record(
+                instruction,
+                target,
+                ResultType::Bit,
+                1,
+                &program.memory_regions,
+            )?,
+            Instruction::Capture(Capture {
+                memory_reference, ..
+            }) => spec.record(
+                instruction,
+                memory_reference,
+                ResultType::ComplexIq,
+                2,
+                &program.memory_regions,
+            )?,
+            Instruction::RawCapture(RawCapture {
+                memory_reference, ..
+            }) => spec.record(
+                instruction,
+                memory_reference,
+                ResultType::RawIq,
+                2,
+                &program.memory_regions,
+            )?,
+            _ => {}
+        }
+    }
+
+    Ok(spec)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Program;
+    use rstest::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_measurement_buffers() {
+        let program = Program::from_str(
+            r#"
+DECLARE ro BIT[2]
+DECLARE unused INTEGER
+MEASURE 0 ro[0]
+MEASURE 1 ro[1]
+MEASURE 2
+"#,
+        )
+        .unwrap();
+        let spec = program.get_result_spec().unwrap();
+
+        assert_eq!(spec.len(), 1);
+        let ro = spec.get("ro").unwrap();
+        assert_eq!(ro.data_type, ScalarType::Bit);
+        assert_eq!(ro.result_type, ResultType::Bit);
+        assert_eq!(ro.values_per_shot(), 2);
+        assert_eq!(ro.indices, BTreeSet::from([0, 1]));
+    }
+
+    #[test]
+    fn test_capture_buffers() {
+        let program = Program::from_str(
+            r#"
+DECLARE iq REAL[4]
+DECLARE raw REAL[200]
+CAPTURE 0 "ro_rx" flat(duration: 1e-6, iq: 1) iq[0]
+CAPTURE 1 "ro_rx" flat(duration: 1e-6, iq: 1) iq[2]
+RAW-CAPTURE 0 "ro_rx" 1e-6 raw
+"#,
+        )
+        .unwrap();
+        let spec = program.get_result_spec().unwrap();
+
+        let iq = spec.get("iq").unwrap();
+        assert_eq!(iq.result_type, ResultType::ComplexIq);
+        assert_eq!(iq.values_per_shot(), 2);
+        assert_eq!(iq.indices, BTreeSet::from([0, 2]));
+
+        let raw = spec.get("raw").unwrap();
+        assert_eq!(raw.result_type, ResultType::RawIq);
+        assert_eq!(raw.values_per_shot(), 100);
+    }
+
+    #[rstest]
+    #[case("MEASURE 0 missing", "UndefinedMemoryReference")]
+    #[case("MEASURE 0 iq", "DataTypeMismatch")]
+    #[case(
+        r#"CAPTURE 0 "ro_rx" flat(duration: 1e-6, iq: 1) ro"#,
+        "DataTypeMismatch"
+    )]
+    #[case("MEASURE 0 ro[2]", "IndexOutOfBounds")]
+    #[case("MEASURE 0 ro[18446744073709551615]", "IndexOutOfBounds")]
+    #[case(
+        r#"CAPTURE 0 "ro_rx" flat(duration: 1e-6, iq: 1) iq[1]"#,
+        "IndexOutOfBounds"
+    )]
+    #[case(
+        r#"CAPTURE 0 "ro_rx" flat(duration: 1e-6, iq: 1) iq[18446744073709551615]"#,
+        "IndexOutOfBounds"
+    )]
+    #[case(
+        r#"CAPTURE 0 "ro_rx" flat(duration: 1e-6, iq: 1) iq
+RAW-CAPTURE 0 "ro_rx" 1e-6 iq"#,
+        "ConflictingResultType"
+    )]
+    fn test_invalid_result_buffers(#[case] body: &str, #[case] expected: &str) {
+        let program = Program::from_str(&format!(
+            r#"
+DECLARE ro BIT[2]
+DECLARE iq REAL[2]
+{body}
+"#
+        ))
+        .unwrap();
+        let error = program.get_result_spec().unwrap_err();
+        assert!(
+            format!("{:?}", error).starts_with(expected),
+            "expected {expected}, got {error:?}"
+        );
+    }
+}
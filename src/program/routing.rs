@@ -0,0 +1,498 @@
+//! Checking programs against the coupling map of a device, and routing them onto it.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+
+use crate::instruction::visit::InstructionRewriter;
+use crate::instruction::{Gate, Instruction, Qubit};
+use crate::Program;
+
+use super::remap::QubitRemapper;
+
+/// The pairs of qubits of a device between which two-qubit gates may be applied. Pairs are
+/// unordered: a gate may be applied to the qubits of a pair in either order.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Topology {
+    /// The neighbours of each qubit.
+    neighbours: BTreeMap<u64, BTreeSet<u64>>,
+}
+
+impl Topology {
+    /// The topology coupling each of the pairs of qubits in `edges`. Pairs of a qubit with itself
+    /// are ignored.
+    pub fn new(edges: impl IntoIterator<Item = (u64, u64)>) -> Self {
+        let mut neighbours: BTreeMap<u64, BTreeSet<u64>> = BTreeMap::new();
+        for (a, b) in edges {
+            if a != b {
+                neighbours.entry(a).or_default().insert(b);
+                neighbours.entry(b).or_default().insert(a);
+            }
+        }
+        Self { neighbours }
+    }
+
+    /// The topology of `qubit_count` qubits in a line, each coupled to the next.
+    pub fn line(qubit_count: u64) -> Self {
+        Self::new((1..qubit_count).map(|qubit| (qubit - 1, qubit)))
+    }
+
+    /// The coupled pairs, each once, with the lesser qubit first, in order.
+    pub fn edges(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.neighbours
+            .iter()
+            .flat_map(|(&a, neighbours)| neighbours.range(a + 1..).map(move |&b| (a, b)))
+    }
+
+    /// The qubits coupled to any other, in order.
+    pub fn qubits(&self) -> impl Iterator<Item = u64> + '_ {
+        self.neighbours.keys().copied()
+    }
+
+    /// Whether a two-qubit gate may be applied to `a` and `b`.
+    pub fn are_coupled(&self, a: u64, b: u64) -> bool {
+        self.neighbours
+            .get(&a)
+            .is_some_and(|neighbours| neighbours.contains(&b))
+    }
+
+    /// The qubits of a shortest path of coupled qubits from `from` to `to`, both included, or
+    /// `None` if there is none.
+    pub fn shortest_path(&self, from: u64, to: u64) -> Option<Vec<u64>> {
+        let mut previous: HashMap<u64, u64> = HashMap::from([(from, from)]);
+        let mut queue = VecDeque::from([from]);
+        while let Some(qubit) = queue.pop_front() {
+            if qubit == to {
+                let mut path = vec![to];
+                while let Some(&last) = path.last().filter(|&&last| last != from) {
+                    path.push(previous[&last]);
+                }
+                path.reverse();
+                return Some(path);
+            }
+            for &neighbour in self.neighbours.get(&qubit).into_iter().flatten() {
+                if let Entry::Vacant(entry) = previous.entry(neighbour) {
+                    entry.insert(qubit);
+                    queue.push_back(neighbour);
+                }
+            }
+        }
+        None
+    }
+}
+
+impl FromIterator<(u64, u64)> for Topology {
+    fn from_iter<I: IntoIterator<Item = (u64, u64)>>(edges: I) -> Self {
+        Self::new(edges)
+    }
+}
+
+/// The ways in which a program may fail to respect a [`Topology`], or be routed onto it. The
+/// index of each instruction is that among the program's instructions, with any circuits
+/// expanded when routing.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum ConnectivityError {
+    #[error("instruction {index}, {instruction}, acts on qubits {first} and {second}, which are not coupled")]
+    Uncoupled {
+        index: usize,
+        instruction: Box<Instruction>,
+        first: u64,
+        second: u64,
+    },
+
+    #[error("instruction {index}, {instruction}, acts on more than two qubits")]
+    TooManyQubits {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+
+    #[error("instruction {index}, {instruction}, acts on a qubit variable")]
+    VariableQubit {
+        index: usize,
+        instruction: Box<Instruction>,
+    },
+
+    #[error("no path of coupled qubits joins qubits {first} and {second} of instruction {index}, {instruction}")]
+    Disconnected {
+        index: usize,
+        instruction: Box<Instruction>,
+        first: u64,
+        second: u64,
+    },
+
+    #[error("the program's circuits could not be expanded: {0}")]
+    InvalidCircuit(String),
+}
+
+/// The indices of the fixed qubits of `gate`, the instruction at `index`, if it acts on one or
+/// two of them.
+fn gate_qubits(index: usize, gate: &Gate) -> Result<Vec<u64>, ConnectivityError> {
+    let mut qubits = vec![];
+    for qubit in &gate.qubits {
+        match qubit {
+            Qubit::Fixed(qubit) if gate.qubits.len() <= 2 => qubits.push(*qubit),
+            Qubit::Fixed(_) => {
+                return Err(ConnectivityError::TooManyQubits {
+                    index,
+                    instruction: Box::new(Instruction::Gate(gate.clone())),
+                })
+            }
+            Qubit::Variable(_) => {
+                return Err(ConnectivityError::VariableQubit {
+                    index,
+                    instruction: Box::new(Instruction::Gate(gate.clone())),
+                })
+            }
+        }
+    }
+    Ok(qubits)
+}
+
+/// Tracks which physical qubit holds the state of each of the program's qubits, as SWAPs are
+/// inserted. Qubits missing from its maps have not moved.
+#[derive(Default)]
+struct Layout {
+    /// The physical qubit holding the state of each program qubit.
+    physical: HashMap<u64, u64>,
+    /// The program qubit whose state each physical qubit holds.
+    logical: HashMap<u64, u64>,
+    /// The SWAPs inserted since the layout was last restored, in order.
+    swaps: Vec<(u64, u64)>,
+}
+
+impl Layout {
+    fn physical(&self, qubit: u64) -> u64 {
+        self.physical.get(&qubit).copied().unwrap_or(qubit)
+    }
+
+    fn logical(&self, qubit: u64) -> u64 {
+        self.logical.get(&qubit).copied().unwrap_or(qubit)
+    }
+
+    /// Exchange the states of physical qubits `a` and `b`.
+    fn exchange(&mut self, a: u64, b: u64, instructions: &mut Vec<Instruction>) {
+        let (logical_a, logical_b) = (self.logical(a), self.logical(b));
+        self.physical.insert(logical_a, b);
+        self.physical.insert(logical_b, a);
+        self.logical.insert(a, logical_b);
+        self.logical.insert(b, logical_a);
+        instructions.push(Instruction::Gate(
+            Gate::swap(a, b).expect("the qubits of a swap are different"),
+        ));
+    }
+
+    fn swap(&mut self, a: u64, b: u64, instructions: &mut Vec<Instruction>) {
+        self.exchange(a, b, instructions);
+        self.swaps.push((a, b));
+    }
+
+    /// Undo the SWAPs inserted since the layout was last restored.
+    fn restore(&mut self, instructions: &mut Vec<Instruction>) {
+        while let Some((a, b)) = self.swaps.pop() {
+            self.exchange(a, b, instructions);
+        }
+    }
+
+    /// The physical qubit of each program qubit which has moved.
+    fn mapping(&self) -> HashMap<Qubit, Qubit> {
+        self.physical
+            .iter()
+            .filter(|(logical, physical)| logical != physical)
+            .map(|(&logical, &physical)| (Qubit::Fixed(logical), Qubit::Fixed(physical)))
+            .collect()
+    }
+}
+
+impl Program {
+    /// Every gate of the body which cannot be applied on a device with the coupling map
+    /// `topology`: those which act on two qubits which are not coupled, those which act on more
+    /// than two qubits, and those which act on qubit variables. Gates which act on one qubit may
+    /// be applied to any qubit. Circuits are not expanded, and so an invocation of one is
+    /// checked as a gate on all of the qubits it is given.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::routing::{ConnectivityError, Topology};
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "CNOT 0 1\nCNOT 0 2".parse().unwrap();
+    /// let violations = program.validate_connectivity(&Topology::line(3));
+    /// assert!(matches!(
+    ///     violations.as_slice(),
+    ///     [ConnectivityError::Uncoupled { index: 1, first: 0, second: 2, .. }]
+    /// ));
+    /// ```
+    pub fn validate_connectivity(&self, topology: &Topology) -> Vec<ConnectivityError> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, instruction)| match instruction {
+                Instruction::Gate(gate) => match gate_qubits(index, gate) {
+                    Ok(qubits) => match qubits.as_slice() {
+                        &[first, second] if !topology.are_coupled(first, second) => {
+                            Some(ConnectivityError::Uncoupled {
+                                index,
+                                instruction: Box::new(instruction.clone()),
+                                first,
+                                second,
+                            })
+                        }
+                        _ => None,
+                    },
+                    Err(error) => Some(error),
+                },
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// A copy of the program which respects the coupling map `topology`, along with the physical
+    /// qubit holding the state of each of the program's qubits at its end.
+    ///
+    /// Circuits are expanded first. Before each two-qubit gate on qubits which are not coupled,
+    /// `SWAP`s are inserted along a shortest path of coupled qubits, moving the state of its first
+    /// qubit next to its second; the gate, and every later instruction, is then applied to the
+    /// qubits holding the states it acts on. So that every path through the program agrees on
+    /// where each state is, the SWAPs are undone before each label and jump. Gates which act on
+    /// one qubit may be applied to any qubit, and the headers, such as calibrations, are kept as
+    /// they are.
+    ///
+    /// The copy does not record the text the program was parsed from.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the program's circuits cannot be expanded, or if it applies a gate which acts on
+    /// more than two qubits or on qubit variables, or on two qubits which are not joined by any
+    /// path of coupled qubits.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::Qubit;
+    /// use quil_rs::program::routing::Topology;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT\nCNOT 0 2\nH 0\nMEASURE 0 ro".parse().unwrap();
+    /// let (routed, layout) = program.route(&Topology::line(3)).unwrap();
+    /// assert_eq!(
+    ///     routed.to_string(true),
+    ///     "DECLARE ro BIT[1]\nSWAP 0 1\nCNOT 1 2\nH 1\nMEASURE 1 ro[0]\n"
+    /// );
+    /// assert_eq!(layout[&Qubit::Fixed(0)], Qubit::Fixed(1));
+    /// ```
+    pub fn route(
+        &self,
+        topology: &Topology,
+    ) -> Result<(Self, HashMap<Qubit, Qubit>), ConnectivityError> {
+        let program = self
+            .expand_circuits()
+            .map_err(|error| ConnectivityError::InvalidCircuit(error.to_string()))?;
+
+        let mut layout = Layout::default();
+        let mut instructions = vec![];
+        for (index, instruction) in program.instructions.iter().enumerate() {
+            match instruction {
+                Instruction::Label(_)
+                | Instruction::Jump(_)
+                | Instruction::JumpWhen(_)
+                | Instruction::JumpUnless(_) => {
+                    layout.restore(&mut instructions);
+                    instructions.push(instruction.clone());
+                    continue;
+                }
+                Instruction::GateDefinition(_) => {
+                    instructions.push(instruction.clone());
+                    continue;
+                }
+                Instruction::Gate(gate) => {
+                    if let &[first, second] = gate_qubits(index, gate)?.as_slice() {
+                        let (a, b) = (layout.physical(first), layout.physical(second));
+                        if !topology.are_coupled(a, b) {
+                            let path = topology.shortest_path(a, b).ok_or_else(|| {
+                                ConnectivityError::Disconnected {
+                                    index,
+                                    instruction: Box::new(instruction.clone()),
+                                    first,
+                                    second,
+                                }
+                            })?;
+                            for pair in path[..path.len() - 1].windows(2) {
+                                layout.swap(pair[0], pair[1], &mut instructions);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+            let mut routed = instruction.clone();
+            QubitRemapper(&layout.mapping()).rewrite_instruction(&mut routed);
+            instructions.push(routed);
+        }
+
+        let final_layout = self
+            .get_used_qubits()
+            .into_iter()
+            .filter_map(|qubit| match qubit {
+                Qubit::Fixed(index) => {
+                    Some((Qubit::Fixed(index), Qubit::Fixed(layout.physical(index))))
+                }
+                Qubit::Variable(_) => None,
+            })
+            .collect();
+
+        let mut routed = program;
+        routed.instructions = instructions;
+        routed.clear_source();
+        Ok((routed, final_layout))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{ConnectivityError, Topology};
+    use crate::instruction::{Gate, Instruction, Qubit};
+    use crate::program::EquivalenceOptions;
+    use crate::Program;
+
+    #[test]
+    fn test_topology() {
+        let topology = Topology::new([(2, 1), (1, 0), (0, 1), (3, 3), (2, 4)]);
+        assert_eq!(
+            topology.edges().collect::<Vec<_>>(),
+            [(0, 1), (1, 2), (2, 4)]
+        );
+        assert_eq!(topology.qubits().collect::<Vec<_>>(), [0, 1, 2, 4]);
+        assert!(topology.are_coupled(1, 0));
+        assert!(!topology.are_coupled(0, 2));
+        assert!(!topology.are_coupled(3, 3));
+        assert_eq!(topology.shortest_path(0, 4), Some(vec![0, 1, 2, 4]));
+        assert_eq!(topology.shortest_path(4, 4), Some(vec![4]));
+        assert_eq!(topology.shortest_path(0, 3), None);
+        assert_eq!(Topology::line(3), Topology::new([(0, 1), (1, 2)]));
+    }
+
+    #[test]
+    fn test_validate_connectivity() {
+        let program =
+            Program::from_str("CNOT 0 1\nCZ 2 0\nH 7\nCCNOT 0 1 2\nMEASURE 0\nSWAP 2 1").unwrap();
+        let violations = program.validate_connectivity(&Topology::line(3));
+        assert_eq!(
+            violations,
+            [
+                ConnectivityError::Uncoupled {
+                    index: 1,
+                    instruction: Box::new(program.instructions[1].clone()),
+                    first: 2,
+                    second: 0,
+                },
+                ConnectivityError::TooManyQubits {
+                    index: 3,
+                    instruction: Box::new(program.instructions[3].clone()),
+                },
+            ]
+        );
+    }
+
+    #[rstest]
+    #[case("H 0\nCNOT 0 1", "H 0\nCNOT 0 1\n")]
+    #[case(
+        "CNOT 0 3\nCNOT 1 0",
+        "SWAP 0 1\nSWAP 1 2\nCNOT 2 3\nSWAP 0 1\nCNOT 1 2\n"
+    )]
+    #[case(
+        "CNOT 0 2\nLABEL @a\nCNOT 0 2\nJUMP @a",
+        "SWAP 0 1\nCNOT 1 2\nSWAP 0 1\nLABEL @a\nSWAP 0 1\nCNOT 1 2\nSWAP 0 1\nJUMP @a\n"
+    )]
+    #[case(
+        "DEFCIRCUIT FAR a b:\n    CZ a b\n\nFAR 2 0\nFENCE 0 1",
+        "SWAP 2 1\nCZ 1 0\nFENCE 0 2\n"
+    )]
+    fn test_route(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let (routed, _) = program.route(&Topology::line(4)).unwrap();
+        assert_eq!(routed.to_string(true), expected);
+    }
+
+    #[rstest]
+    #[case("CNOT 0 3\nH 3\nCZ 3 1\nCPHASE(0.5) 2 0", Topology::line(4))]
+    #[case("CNOT 0 2\nCNOT 1 3\nXY(0.3) 0 3\nT 0\nSWAP 2 0", Topology::line(4))]
+    #[case("CNOT 0 2\nCNOT 1 3\nCZ 0 3", Topology::new([(0, 1), (1, 2), (2, 3), (3, 0)]))]
+    fn test_routing_is_equivalent(#[case] input: &str, #[case] topology: Topology) {
+        let program = Program::from_str(input).unwrap();
+        let (mut routed, layout) = program.route(&topology).unwrap();
+        assert_eq!(routed.validate_connectivity(&topology), []);
+
+        // Move each state back to its own qubit, to compare the routed program with the original.
+        let mut layout: HashMap<u64, u64> = layout
+            .into_iter()
+            .map(|(logical, physical)| match (logical, physical) {
+                (Qubit::Fixed(logical), Qubit::Fixed(physical)) => (logical, physical),
+                _ => unreachable!("the program only uses fixed qubits"),
+            })
+            .collect();
+        while let Some((&logical, &physical)) = layout
+            .iter()
+            .find(|(logical, physical)| logical != physical)
+        {
+            routed
+                .instructions
+                .push(Instruction::Gate(Gate::swap(logical, physical).unwrap()));
+            // The state on the qubit `logical` moves to `physical`.
+            if let Some(other) = layout.values_mut().find(|other| **other == logical) {
+                *other = physical;
+            }
+            layout.insert(logical, logical);
+        }
+        let options = EquivalenceOptions {
+            unitary_qubit_limit: Some(4),
+            ..Default::default()
+        };
+        assert!(
+            routed.equivalent_to(&program, &options),
+            "{input} was routed into {}",
+            routed.to_string(true)
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "CNOT 0 3",
+        ConnectivityError::Disconnected {
+            index: 0,
+            instruction: Box::new(Program::from_str("CNOT 0 3").unwrap().instructions[0].clone()),
+            first: 0,
+            second: 3,
+        }
+    )]
+    #[case(
+        "CCNOT 0 1 2",
+        ConnectivityError::TooManyQubits {
+            index: 0,
+            instruction: Box::new(Program::from_str("CCNOT 0 1 2").unwrap().instructions[0].clone()),
+        }
+    )]
+    fn test_route_errors(#[case] input: &str, #[case] expected: ConnectivityError) {
+        let program = Program::from_str(input).unwrap();
+        let topology = Topology::new([(0, 1), (1, 2), (3, 4)]);
+        assert_eq!(program.route(&topology), Err(expected));
+    }
+}
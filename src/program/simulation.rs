@@ -0,0 +1,260 @@
+//! Simulating programs of a few qubits which only apply gates, by their unitary matrices or by
+//! evolving a state vector. Qubits and basis states are numbered as described in
+//! [`unitary`](crate::instruction::unitary).
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+
+use num_complex::Complex64;
+use thiserror::Error;
+
+use crate::instruction::unitary::{
+    apply, gate_unitary, local_unitary, Matrix, UnitaryError, MAX_QUBIT_COUNT,
+};
+use crate::instruction::{Gate, GateDefinition, Instruction};
+
+use super::Program;
+
+/// Why a program could not be simulated.
+#[derive(Clone, Debug, PartialEq, Error)]
+pub enum SimulationError {
+    #[error("instruction {0} cannot be simulated, as only gates can be")]
+    UnsupportedInstruction(Box<Instruction>),
+
+    #[error("a circuit could not be expanded: {0}")]
+    InvalidCircuit(String),
+
+    #[error("a state of {0} amplitudes is not a state of any number of qubits")]
+    InvalidState(usize),
+
+    #[error("{0} qubits are too many to compute the unitary matrix of")]
+    TooManyQubits(u64),
+
+    #[error("in instruction {instruction}: {error}")]
+    Unitary {
+        instruction: Box<Instruction>,
+        error: UnitaryError,
+    },
+}
+
+pub type SimulationResult<T> = Result<T, SimulationError>;
+
+impl Program {
+    /// The gates applied by the program in order, with circuits expanded, and the gates defined
+    /// in it by name. Fails if the program does anything but apply gates.
+    fn simulated_gates(&self) -> SimulationResult<(Vec<Gate>, HashMap<&str, &GateDefinition>)> {
+        let definitions = self
+            .instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::GateDefinition(definition) => {
+                    Some((definition.name.as_str(), definition))
+                }
+                _ => None,
+            })
+            .collect();
+
+        let expanded = self
+            .expand_circuits()
+            .map_err(|error| SimulationError::InvalidCircuit(error.to_string()))?;
+        let mut gates = vec![];
+        for instruction in expanded.instructions {
+            match instruction {
+                Instruction::Gate(gate) => gates.push(gate),
+                Instruction::Declaration(_)
+                | Instruction::GateDefinition(_)
+                | Instruction::Nop
+                | Instruction::Pragma(_) => {}
+                other => return Err(SimulationError::UnsupportedInstruction(Box::new(other))),
+            }
+        }
+        Ok((gates, definitions))
+    }
+
+    /// The unitary matrix of the program as it acts on `qubit_count` qubits: the product of the
+    /// matrices of its gates, the first of which is applied first. Gates defined in the program
+    /// may be used, but the parameters of every gate must be constants, and the program must do
+    /// nothing but apply gates.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::instruction::Gate;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "H 0\nH 0".parse().unwrap();
+    /// let identity = Gate::i(0).to_unitary(1).unwrap();
+    /// assert!(program.to_unitary(1).unwrap().approx_eq(&identity, 1e-12));
+    /// ```
+    pub fn to_unitary(&self, qubit_count: u64) -> SimulationResult<Matrix> {
+        if qubit_count > MAX_QUBIT_COUNT {
+            return Err(SimulationError::TooManyQubits(qubit_count));
+        }
+        let (gates, definitions) = self.simulated_gates()?;
+        let mut unitary = Matrix::identity(1 << qubit_count);
+        for gate in gates {
+            let matrix = gate_unitary(&gate, qubit_count, &definitions).map_err(|error| {
+                SimulationError::Unitary {
+                    instruction: Box::new(Instruction::Gate(gate.clone())),
+                    error,
+                }
+            })?;
+            unitary = &matrix * &unitary;
+        }
+        Ok(unitary)
+    }
+
+    /// The state the program evolves `initial_state` to, where `initial_state` holds the
+    /// amplitude of each basis state of some number of qubits. The program must be one which
+    /// [`Program::to_unitary`] could find the matrix of, but no matrix larger than those of its
+    /// gates is computed, so that programs of more qubits can be simulated.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use num_complex::Complex64;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "H 0\nCNOT 0 1".parse().unwrap();
+    /// let zero = Complex64::new(0.0, 0.0);
+    /// let state = program
+    ///     .simulate_statevector(&[Complex64::new(1.0, 0.0), zero, zero, zero])
+    ///     .unwrap();
+    /// // The Bell state (|00⟩ + |11⟩)/√2.
+    /// assert!((state[0].re - state[3].re).abs() < 1e-12);
+    /// assert!(state[1].norm() < 1e-12 && state[2].norm() < 1e-12);
+    /// ```
+    pub fn simulate_statevector(
+        &self,
+        initial_state: &[Complex64],
+    ) -> SimulationResult<Vec<Complex64>> {
+        if !initial_state.len().is_power_of_two() {
+            return Err(SimulationError::InvalidState(initial_state.len()));
+        }
+        let qubit_count = initial_state.len().trailing_zeros() as u64;
+
+        let (gates, definitions) = self.simulated_gates()?;
+        let mut state = initial_state.to_vec();
+        for gate in gates {
+            let (matrix, qubits) =
+                local_unitary(&gate, qubit_count, &definitions).map_err(|error| {
+                    SimulationError::Unitary {
+                        instruction: Box::new(Instruction::Gate(gate.clone())),
+                        error,
+                    }
+                })?;
+            apply(&matrix, &qubits, &mut state);
+        }
+        Ok(state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use num_complex::Complex64;
+    use rstest::rstest;
+
+    use super::SimulationError;
+    use crate::instruction::unitary::{Matrix, UnitaryError};
+    use crate::instruction::{Gate, Instruction};
+    use crate::{real, Program};
+
+    const TOLERANCE: f64 = 1e-10;
+
+    /// The basis state of `qubit_count` qubits numbered `index`.
+    fn basis_state(qubit_count: u32, index: usize) -> Vec<Complex64> {
+        let mut state = vec![Complex64::default(); 1 << qubit_count];
+        state[index] = real!(1.0);
+        state
+    }
+
+    #[rstest]
+    #[case("H 0\nCNOT 0 1\nRZ(0.3) 1\nCCNOT 1 0 2\nSWAP 2 0\n")]
+    #[case("DEFGATE SQRTX:\n    0.5+0.5i, 0.5-0.5i\n    0.5-0.5i, 0.5+0.5i\n\nSQRTX 1\nCONTROLLED SQRTX 1 2\nDAGGER SQRTX 0\n")]
+    #[case("DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nDECLARE ro BIT\nPRAGMA INITIAL_REWIRING \"NAIVE\"\nBELL 2 0\nNOP\n")]
+    fn test_simulation_agrees_with_unitary(#[case] input: &str) {
+        let program: Program = input.parse().unwrap();
+        let unitary = program.to_unitary(3).unwrap();
+        assert!(unitary.is_unitary(TOLERANCE));
+        for index in 0..8 {
+            let state = program
+                .simulate_statevector(&basis_state(3, index))
+                .unwrap();
+            let column: Vec<Complex64> = unitary.rows().map(|row| row[index]).collect();
+            for (simulated, expected) in state.iter().zip(&column) {
+                assert!((simulated - expected).norm() < TOLERANCE);
+            }
+        }
+    }
+
+    #[test]
+    fn test_to_unitary() {
+        let program: Program = "X 0\nCNOT 0 1".parse().unwrap();
+        // X 0 takes |00⟩ to |01⟩, which CNOT 0 1 takes to |11⟩.
+        let unitary = program.to_unitary(2).unwrap();
+        assert_eq!(unitary[(3, 0)], real!(1.0));
+
+        let empty = Program::new();
+        assert_eq!(empty.to_unitary(2).unwrap(), Matrix::identity(4));
+    }
+
+    #[test]
+    fn test_bell_state() {
+        let program: Program = "H 0\nCNOT 0 1".parse().unwrap();
+        let state = program.simulate_statevector(&basis_state(2, 0)).unwrap();
+        let amplitude = real!(std::f64::consts::FRAC_1_SQRT_2);
+        let expected = [amplitude, real!(0.0), real!(0.0), amplitude];
+        for (simulated, expected) in state.iter().zip(&expected) {
+            assert!((simulated - expected).norm() < TOLERANCE);
+        }
+    }
+
+    #[rstest]
+    #[case("DECLARE ro BIT\nH 0\nMEASURE 0 ro[0]")]
+    #[case("LABEL @start\nH 0\nJUMP @start")]
+    #[case("RESET")]
+    #[case("DEFFRAME 0 \"rf\":\n    SAMPLE-RATE: 1.0\nPULSE 0 \"rf\" flat(duration: 1.0, iq: 1.0)")]
+    fn test_unsupported(#[case] input: &str) {
+        let program: Program = input.parse().unwrap();
+        assert!(matches!(
+            program.to_unitary(1),
+            Err(SimulationError::UnsupportedInstruction(_))
+        ));
+        assert!(matches!(
+            program.simulate_statevector(&basis_state(1, 0)),
+            Err(SimulationError::UnsupportedInstruction(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid() {
+        let program: Program = "CNOT 0 1".parse().unwrap();
+        assert_eq!(
+            program.to_unitary(1),
+            Err(SimulationError::Unitary {
+                instruction: Box::new(Instruction::Gate(Gate::cnot(0, 1).unwrap())),
+                error: UnitaryError::QubitOutOfRange {
+                    qubit: 1,
+                    qubit_count: 1
+                },
+            })
+        );
+        assert_eq!(
+            program.simulate_statevector(&[real!(1.0), real!(0.0), real!(0.0)]),
+            Err(SimulationError::InvalidState(3))
+        );
+    }
+}
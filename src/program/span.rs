@@ -0,0 +1,133 @@
+//! Locations in the text a program was parsed from.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt;
+use std::ops::Range;
+
+/// A position in the text of a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SourceLocation {
+    /// The byte offset from the start of the text.
+    pub offset: usize,
+    /// The line, counted from 1.
+    pub line: u32,
+    /// The column of the line, counted in characters from 1.
+    pub column: usize,
+}
+
+/// The start of the text.
+impl Default for SourceLocation {
+    fn default() -> Self {
+        Self {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl SourceLocation {
+    /// The location just after `text`, when `text` starts at this location.
+    pub(crate) fn advance(&self, text: &str) -> Self {
+        let offset = self.offset + text.len();
+        match text.rsplit_once('\n') {
+            Some((before, after)) => Self {
+                offset,
+                line: self.line + before.matches('\n').count() as u32 + 1,
+                column: after.chars().count() + 1,
+            },
+            None => Self {
+                offset,
+                line: self.line,
+                column: self.column + text.chars().count(),
+            },
+        }
+    }
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// The text an instruction was parsed from, from the start of its first token to the end of its
+/// last, excluding any surrounding whitespace and comments.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SourceSpan {
+    /// The location of the first character.
+    pub start: SourceLocation,
+    /// The location just after the last character.
+    pub end: SourceLocation,
+}
+
+impl SourceSpan {
+    /// The byte offsets of the span, to slice the text it was parsed from.
+    pub fn range(&self) -> Range<usize> {
+        self.start.offset..self.end.offset
+    }
+}
+
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SourceLocation, SourceSpan};
+
+    #[test]
+    fn test_advance() {
+        let start = SourceLocation {
+            offset: 2,
+            line: 1,
+            column: 3,
+        };
+        assert_eq!(
+            start.advance("H 0"),
+            SourceLocation {
+                offset: 5,
+                line: 1,
+                column: 6,
+            }
+        );
+        assert_eq!(
+            start.advance("DEFCAL X 0:\n    NOP\n    NOP"),
+            SourceLocation {
+                offset: 29,
+                line: 3,
+                column: 8,
+            }
+        );
+    }
+
+    #[test]
+    fn test_display() {
+        let start = SourceLocation {
+            offset: 0,
+            line: 2,
+            column: 1,
+        };
+        let span = SourceSpan {
+            start,
+            end: start.advance("MEASURE 0"),
+        };
+        assert_eq!(span.to_string(), "2:1-2:10");
+        assert_eq!(span.range(), 0..9);
+    }
+}
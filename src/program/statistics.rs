@@ -0,0 +1,172 @@
+//! Counts of the gates, measurements, and qubits of a program, and the depth of its circuit.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::BTreeMap;
+
+use crate::instruction::{Gate, Instruction, Measurement, Reset};
+use crate::Program;
+
+use super::moments::Scheduler;
+
+/// Statistics describing the size of a program, as returned by [`Program::statistics`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ProgramStatistics {
+    /// The number of gates applied with each name, whatever their modifiers.
+    pub gate_counts: BTreeMap<String, usize>,
+    /// The number of gates applied to exactly two qubits, counting the control qubits of gates
+    /// with the `CONTROLLED` modifier.
+    pub two_qubit_gate_count: usize,
+    /// The number of `MEASURE` instructions.
+    pub measurement_count: usize,
+    /// The number of distinct qubits used by the program.
+    pub qubit_count: usize,
+    /// The number of layers of gates, measurements, and resets the program would take if each
+    /// were applied as soon as every earlier one on the same qubits had been.
+    pub depth: usize,
+}
+
+impl ProgramStatistics {
+    /// The number of gates applied, of any name.
+    pub fn gate_count(&self) -> usize {
+        self.gate_counts.values().sum()
+    }
+}
+
+impl Program {
+    /// Count the gates, measurements, and qubits of the program's instructions and measure the
+    /// depth of its circuit.
+    ///
+    /// Gates are counted as they are applied in the program's instructions, without expanding
+    /// those defined by a `DEFCIRCUIT` or calibration. The depth only accounts for the qubits each
+    /// instruction acts on, not for control flow or the memory it uses; a `RESET` of every qubit
+    /// follows every earlier instruction.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "DECLARE ro BIT[2]
+    /// H 0
+    /// CNOT 0 1
+    /// X 2
+    /// MEASURE 0 ro[0]
+    /// MEASURE 1 ro[1]"
+    ///     .parse()
+    ///     .unwrap();
+    /// let statistics = program.statistics();
+    ///
+    /// assert_eq!(statistics.gate_count(), 3);
+    /// assert_eq!(statistics.gate_counts["CNOT"], 1);
+    /// assert_eq!(statistics.two_qubit_gate_count, 1);
+    /// assert_eq!(statistics.measurement_count, 2);
+    /// assert_eq!(statistics.qubit_count, 3);
+    /// assert_eq!(statistics.depth, 3);
+    /// ```
+    pub fn statistics(&self) -> ProgramStatistics {
+        let mut statistics = ProgramStatistics {
+            qubit_count: self.get_used_qubits().len(),
+            ..Default::default()
+        };
+        let mut scheduler = Scheduler::default();
+        for instruction in &self.instructions {
+            match instruction {
+                Instruction::Gate(Gate { name, qubits, .. }) => {
+                    *statistics.gate_counts.entry(name.to_string()).or_default() += 1;
+                    if qubits.len() == 2 {
+                        statistics.two_qubit_gate_count += 1;
+                    }
+                    scheduler.place(qubits);
+                }
+                Instruction::Measurement(Measurement { qubit, .. }) => {
+                    statistics.measurement_count += 1;
+                    scheduler.place([qubit]);
+                }
+                Instruction::Reset(Reset { qubit: Some(qubit) }) => {
+                    scheduler.place([qubit]);
+                }
+                Instruction::Reset(Reset { qubit: None }) => {
+                    scheduler.barrier();
+                    scheduler.place([]);
+                    scheduler.barrier();
+                }
+                _ => {}
+            }
+        }
+        statistics.depth = scheduler.len();
+        statistics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::ProgramStatistics;
+    use crate::Program;
+
+    #[test]
+    fn test_empty_program() {
+        assert_eq!(Program::new().statistics(), ProgramStatistics::default());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let input = "DECLARE ro BIT[3]
+H 0
+H 1
+CONTROLLED RX(pi) 0 1
+CCNOT 0 1 2
+LABEL @a
+RX(pi/2) 2
+MEASURE 0 ro[0]
+MEASURE 2 ro[2]
+MEASURE 3
+";
+        let statistics = Program::from_str(input).unwrap().statistics();
+        assert_eq!(
+            statistics,
+            ProgramStatistics {
+                gate_counts: BTreeMap::from([
+                    ("CCNOT".to_string(), 1),
+                    ("H".to_string(), 2),
+                    ("RX".to_string(), 2),
+                ]),
+                two_qubit_gate_count: 1,
+                measurement_count: 3,
+                qubit_count: 4,
+                depth: 5,
+            }
+        );
+        assert_eq!(statistics.gate_count(), 5);
+    }
+
+    #[rstest]
+    #[case("H 0\nH 0\nH 1", 2)]
+    #[case("H 0\nCNOT 0 1\nH 2\nCNOT 1 2", 3)]
+    #[case("H 0\nRESET\nH 1", 3)]
+    #[case("H 0\nRESET 0\nH 1", 2)]
+    #[case("PULSE 0 \"rf\" flat(duration: 1e-6, iq: 1.0)\nH 0", 1)]
+    fn test_depth(#[case] input: &str, #[case] expected: usize) {
+        assert_eq!(
+            Program::from_str(input).unwrap().statistics().depth,
+            expected
+        );
+    }
+}
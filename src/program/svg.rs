@@ -0,0 +1,310 @@
+//! Draw circuit diagrams as SVG images, without needing a LaTeX toolchain.
+//!
+//! The diagram is laid out exactly as for LaTeX (see [`crate::program::latex`]); each row of the
+//! diagram is drawn below the last. Annotations, gate groups, and classical wires are only drawn
+//! by the LaTeX renderer.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+use crate::program::latex::{Command, Diagram, LatexGenError, RenderSettings, Renderer};
+use crate::Program;
+
+/// The assumed width of a character of text, since text cannot be measured without a font.
+const CHAR_WIDTH: u32 = 8;
+/// The padding between the text of a gate and its box, on each side.
+const GATE_PADDING: u32 = 8;
+/// The height of a gate box on a single wire.
+const GATE_HEIGHT: u32 = 30;
+/// The vertical distance between adjacent wires.
+const WIRE_SPACING: u32 = 50;
+/// The horizontal space around each column.
+const COLUMN_MARGIN: u32 = 10;
+/// The vertical space between rows of the diagram.
+const ROW_GAP: u32 = 30;
+/// The space around the whole diagram.
+const MARGIN: u32 = 10;
+/// The radius of a control dot.
+const CONTROL_RADIUS: u32 = 4;
+/// The radius of a CNOT target.
+const TARGET_RADIUS: u32 = 10;
+
+const STYLE: &str = r#"font-family="sans-serif" font-size="14""#;
+
+/// Draws a diagram as a standalone SVG document.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SvgRenderer;
+
+/// Escape `text` for use as the content of an XML element.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// The number of characters in `text`.
+fn text_width(text: &str) -> u32 {
+    text.chars().count() as u32 * CHAR_WIDTH
+}
+
+/// The width of the box or symbol drawn for `command`.
+fn command_width(command: &Command) -> u32 {
+    match command {
+        Command::Gate(text)
+        | Command::StyledGate(text, _)
+        | Command::WideGate { name: text, .. } => text_width(text) + 2 * GATE_PADDING,
+        Command::Phase(text) => text_width(text),
+        Command::Meter => GATE_HEIGHT,
+        Command::Targ => 2 * TARGET_RADIUS,
+        _ => 0,
+    }
+}
+
+/// The text drawn as a wire's label.
+fn label(qubit: &Qubit) -> String {
+    match qubit {
+        Qubit::Fixed(index) => format!("q{index}"),
+        Qubit::Variable(name) => name.clone(),
+    }
+}
+
+impl SvgRenderer {
+    /// Draw the columns `columns` of `diagram`, with the first wire at `top` and the wires
+    /// starting at `left`, returning the width of the row.
+    fn render_row(
+        &self,
+        svg: &mut String,
+        diagram: &Diagram,
+        columns: Range<u32>,
+        left: u32,
+        top: u32,
+    ) -> u32 {
+        let wires: Vec<_> = diagram.wires().collect();
+        let commands: Vec<Vec<Command>> = columns
+            .map(|column| wires.iter().map(|wire| wire.get_command(column)).collect())
+            .collect();
+        let widths: Vec<u32> = commands
+            .iter()
+            .map(|column| {
+                column
+                    .iter()
+                    .map(command_width)
+                    .max()
+                    .unwrap_or(0)
+                    .max(GATE_HEIGHT)
+                    + 2 * COLUMN_MARGIN
+            })
+            .collect();
+        let width = widths.iter().sum::<u32>() + 2 * COLUMN_MARGIN;
+        let wire_y = |row: usize| top + row as u32 * WIRE_SPACING;
+
+        for (row, wire) in wires.iter().enumerate() {
+            let y = wire_y(row);
+            let _ = writeln!(
+                svg,
+                r#"<text x="{}" y="{y}" text-anchor="end" dominant-baseline="middle">{}</text>"#,
+                left - COLUMN_MARGIN,
+                escape(&label(wire.qubit()))
+            );
+            let _ = writeln!(
+                svg,
+                r#"<line x1="{left}" y1="{y}" x2="{}" y2="{y}" stroke="black"/>"#,
+                left + width
+            );
+        }
+
+        let mut x = left + COLUMN_MARGIN;
+        for (column, width) in commands.iter().zip(widths) {
+            let center = x + width / 2;
+
+            // Vertical lines are drawn first, so that the gates they join are drawn over them.
+            for (row, command) in column.iter().enumerate() {
+                if let Command::Ctrl(offset) = command {
+                    let target = (row as i64 + offset) as usize;
+                    let _ = writeln!(
+                        svg,
+                        r#"<line x1="{center}" y1="{}" x2="{center}" y2="{}" stroke="black"/>"#,
+                        wire_y(row),
+                        wire_y(target)
+                    );
+                }
+            }
+
+            for (row, command) in column.iter().enumerate() {
+                let y = wire_y(row);
+                match command {
+                    Command::Gate(text)
+                    | Command::StyledGate(text, _)
+                    | Command::WideGate { name: text, .. } => {
+                        let wires = match command {
+                            Command::WideGate { wires, .. } => *wires as u32,
+                            _ => 1,
+                        };
+                        let box_width = command_width(command);
+                        let box_height = GATE_HEIGHT + (wires - 1) * WIRE_SPACING;
+                        let _ = writeln!(
+                            svg,
+                            r#"<rect x="{}" y="{}" width="{box_width}" height="{box_height}" fill="white" stroke="black"/>"#,
+                            center - box_width / 2,
+                            y - GATE_HEIGHT / 2
+                        );
+                        let _ = writeln!(
+                            svg,
+                            r#"<text x="{center}" y="{}" text-anchor="middle" dominant-baseline="middle">{}</text>"#,
+                            y + (wires - 1) * WIRE_SPACING / 2,
+                            escape(text)
+                        );
+                    }
+                    Command::Phase(text) => {
+                        let _ = writeln!(
+                            svg,
+                            r#"<circle cx="{center}" cy="{y}" r="{CONTROL_RADIUS}" fill="black"/>"#
+                        );
+                        let _ = writeln!(
+                            svg,
+                            r#"<text x="{center}" y="{}" text-anchor="middle">{}</text>"#,
+                            y - 2 * CONTROL_RADIUS,
+                            escape(text)
+                        );
+                    }
+                    Command::Meter => {
+                        let half = GATE_HEIGHT / 2;
+                        let _ = writeln!(
+                            svg,
+                            r#"<rect x="{}" y="{}" width="{GATE_HEIGHT}" height="{GATE_HEIGHT}" fill="white" stroke="black"/>"#,
+                            center - half,
+                            y - half
+                        );
+                        let _ = writeln!(
+                            svg,
+                            r#"<path d="M {} {} A {} {} 0 0 1 {} {} M {center} {} L {} {}" fill="none" stroke="black"/>"#,
+                            center - half + 4,
+                            y + 6,
+                            half - 4,
+                            half - 4,
+                            center + half - 4,
+                            y + 6,
+                            y + 6,
+                            center + half - 6,
+                            y - half + 6
+                        );
+                    }
+                    Command::Ctrl(_) => {
+                        let _ = writeln!(
+                            svg,
+                            r#"<circle cx="{center}" cy="{y}" r="{CONTROL_RADIUS}" fill="black"/>"#
+                        );
+                    }
+                    Command::Targ => {
+                        let _ = writeln!(
+                            svg,
+                            r#"<circle cx="{center}" cy="{y}" r="{TARGET_RADIUS}" fill="white" stroke="black"/>"#
+                        );
+                        let _ = writeln!(
+                            svg,
+                            r#"<path d="M {} {y} L {} {y} M {center} {} L {center} {}" stroke="black"/>"#,
+                            center - TARGET_RADIUS,
+                            center + TARGET_RADIUS,
+                            y - TARGET_RADIUS,
+                            y + TARGET_RADIUS
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            x += width;
+        }
+        width
+    }
+}
+
+impl Renderer for SvgRenderer {
+    fn render(&self, diagram: &Diagram) -> String {
+        let wire_count = diagram.wires().count() as u32;
+        let label_width = diagram
+            .wires()
+            .map(|wire| text_width(&label(wire.qubit())))
+            .max()
+            .unwrap_or(0);
+        let left = MARGIN + label_width + COLUMN_MARGIN;
+        let row_height = wire_count.saturating_sub(1) * WIRE_SPACING + GATE_HEIGHT;
+
+        let mut body = String::new();
+        let mut width = 0;
+        let mut top = MARGIN + GATE_HEIGHT / 2;
+        let rows = diagram.rows();
+        let row_count = rows.len() as u32;
+        for columns in rows {
+            width = width.max(self.render_row(&mut body, diagram, columns, left, top));
+            top += row_height + ROW_GAP;
+        }
+
+        let width = left + width + MARGIN;
+        let height = 2 * MARGIN + row_count * row_height + row_count.saturating_sub(1) * ROW_GAP;
+        format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\" {STYLE}>\n{body}</svg>\n"
+        )
+    }
+}
+
+impl Program {
+    /// Draw the gates and measurements of the program as a standalone SVG document, laid out as
+    /// by [`crate::program::latex::Latex::to_latex`] with `settings`.
+    pub fn to_svg(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        let diagram = Diagram::build_plain_text(&self.instructions, settings)?;
+        Ok(SvgRenderer.render(&diagram))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::escape;
+    use crate::program::latex::{CircuitDisplay, RenderSettings};
+    use crate::Program;
+
+    fn get_svg(input: &str, settings: RenderSettings) -> String {
+        Program::from_str(input).unwrap().to_svg(settings).unwrap()
+    }
+
+    #[test]
+    fn test_svg() {
+        insta::assert_snapshot!(get_svg(
+            "H 0\nCNOT 0 1\nCPHASE(pi) 1 2\nMEASURE 2",
+            RenderSettings::default()
+        ));
+    }
+
+    #[test]
+    fn test_svg_boxes_and_rows() {
+        insta::assert_snapshot!(get_svg(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nX 1\nH 0",
+            RenderSettings {
+                circuit_display: CircuitDisplay::Box,
+                max_columns_per_row: Some(2),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(escape("<a & b>"), "&lt;a &amp; b&gt;");
+    }
+}
@@ -0,0 +1,320 @@
+//! Draw circuit diagrams as plain text, for terminals and logs.
+//!
+//! The diagram is laid out exactly as for LaTeX (see [`crate::program::latex`]) and drawn with
+//! either ASCII or Unicode box-drawing characters. Annotations, gate groups, and classical wires
+//! are only drawn by the LaTeX renderer.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+use crate::program::latex::{Command, Diagram, LatexGenError, RenderSettings, Renderer};
+use crate::Program;
+
+/// The characters a text diagram is drawn with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Charset {
+    /// Only ASCII characters, for output which may not support Unicode.
+    Ascii,
+    /// Unicode box-drawing characters.
+    #[default]
+    Unicode,
+}
+
+/// The glyphs of a [`Charset`].
+struct Glyphs {
+    wire: char,
+    vertical: char,
+    crossing: char,
+    control: &'static str,
+    target: &'static str,
+    box_left: char,
+    box_right: char,
+}
+
+impl Charset {
+    fn glyphs(&self) -> Glyphs {
+        match self {
+            Charset::Ascii => Glyphs {
+                wire: '-',
+                vertical: '|',
+                crossing: '+',
+                control: "@",
+                target: "(+)",
+                box_left: '[',
+                box_right: ']',
+            },
+            Charset::Unicode => Glyphs {
+                wire: '─',
+                vertical: '│',
+                crossing: '┼',
+                control: "●",
+                target: "⊕",
+                box_left: '┤',
+                box_right: '├',
+            },
+        }
+    }
+}
+
+/// Draws a diagram as text, one line per wire, with the lines between wires carrying the vertical
+/// lines of controlled gates and multi-wire boxes.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TextRenderer {
+    pub charset: Charset,
+}
+
+/// The contents of one cell of the text diagram.
+enum Cell {
+    /// Nothing but the wire.
+    Empty,
+    /// A symbol drawn on the wire, such as a control dot.
+    Symbol(String),
+    /// A box containing text.
+    Box(String),
+    /// The part of a multi-wire box below its top wire, as wide as the box's text.
+    Covered(usize),
+}
+
+impl Cell {
+    /// The number of characters the cell occupies.
+    fn width(&self) -> usize {
+        match self {
+            Cell::Empty => 0,
+            Cell::Symbol(text) => text.chars().count(),
+            Cell::Box(text) => text.chars().count() + 2,
+            Cell::Covered(width) => width + 2,
+        }
+    }
+}
+
+/// A column of the diagram being drawn: one cell per wire, the vertical lines joining wires, and
+/// the character width of the column.
+struct Column {
+    cells: Vec<Cell>,
+    /// for each wire, whether a vertical line runs from it to the wire below
+    joined_below: Vec<bool>,
+    /// for each wire, whether a multi-wire box continues from it to the wire below
+    boxed_below: Vec<bool>,
+    width: usize,
+}
+
+impl TextRenderer {
+    /// Build the cells of `column` for each of `wires`.
+    fn column(&self, diagram: &Diagram, column: u32) -> Column {
+        let glyphs = self.charset.glyphs();
+        let commands: Vec<Command> = diagram
+            .wires()
+            .map(|wire| wire.get_command(column))
+            .collect();
+        let count = commands.len();
+
+        let mut cells: Vec<Cell> = Vec::with_capacity(count);
+        let mut joined_below = vec![false; count];
+        let mut boxed_below = vec![false; count];
+        let mut covered: Vec<Option<usize>> = vec![None; count];
+
+        for (row, command) in commands.into_iter().enumerate() {
+            let cell = match command {
+                Command::Gate(text) | Command::StyledGate(text, _) => Cell::Box(text),
+                Command::WideGate { wires, name, .. } => {
+                    let width = name.chars().count();
+                    for below in covered.iter_mut().take(row + wires).skip(row + 1) {
+                        *below = Some(width);
+                    }
+                    for joined in boxed_below.iter_mut().skip(row).take(wires - 1) {
+                        *joined = true;
+                    }
+                    Cell::Box(name)
+                }
+                Command::Phase(text) => Cell::Symbol(format!("{}({text})", glyphs.control)),
+                Command::Meter => Cell::Box("M".to_string()),
+                Command::Ctrl(offset) => {
+                    let target = row as i64 + offset;
+                    let (top, bottom) = (row.min(target as usize), row.max(target as usize));
+                    for joined in joined_below.iter_mut().take(bottom).skip(top) {
+                        *joined = true;
+                    }
+                    Cell::Symbol(glyphs.control.to_string())
+                }
+                Command::Targ => Cell::Symbol(glyphs.target.to_string()),
+                _ => match covered[row] {
+                    Some(width) => Cell::Covered(width),
+                    None => Cell::Empty,
+                },
+            };
+            cells.push(cell);
+        }
+
+        let width = cells.iter().map(Cell::width).max().unwrap_or(0) + 2;
+        Column {
+            cells,
+            joined_below,
+            boxed_below,
+            width,
+        }
+    }
+
+    /// Draw the columns `columns` of every wire of `diagram`.
+    fn render_row(&self, diagram: &Diagram, columns: Range<u32>) -> Vec<String> {
+        let glyphs = self.charset.glyphs();
+        let labels: Vec<String> = diagram
+            .wires()
+            .map(|wire| match wire.qubit() {
+                Qubit::Fixed(index) => format!("q{index}: "),
+                Qubit::Variable(name) => format!("{name}: "),
+            })
+            .collect();
+        let label_width = labels
+            .iter()
+            .map(|label| label.chars().count())
+            .max()
+            .unwrap_or(0);
+        let columns: Vec<Column> = columns.map(|column| self.column(diagram, column)).collect();
+
+        let mut lines = vec![];
+        for (row, label) in labels.iter().enumerate() {
+            let mut line = format!("{label:label_width$}{}", glyphs.wire);
+            let mut gap = " ".repeat(label_width + 1);
+
+            for column in &columns {
+                let cell = &column.cells[row];
+                let cell_width = cell.width();
+                let left = (column.width - cell_width) / 2;
+                let center = (column.width - 1) / 2;
+                let crossed = row > 0 && column.joined_below[row - 1] && column.joined_below[row];
+
+                let mut wire: Vec<char> = vec![glyphs.wire; column.width];
+                let mut below: Vec<char> = vec![' '; column.width];
+                match cell {
+                    Cell::Empty if crossed => wire[center] = glyphs.crossing,
+                    Cell::Empty => {}
+                    Cell::Symbol(text) => {
+                        for (i, c) in text.chars().enumerate() {
+                            wire[left + i] = c;
+                        }
+                    }
+                    Cell::Box(text) => {
+                        wire[left] = glyphs.box_left;
+                        for (i, c) in text.chars().enumerate() {
+                            wire[left + 1 + i] = c;
+                        }
+                        wire[left + cell_width - 1] = glyphs.box_right;
+                    }
+                    Cell::Covered(width) => {
+                        wire[left] = glyphs.box_left;
+                        for c in wire.iter_mut().skip(left + 1).take(*width) {
+                            *c = ' ';
+                        }
+                        wire[left + cell_width - 1] = glyphs.box_right;
+                    }
+                }
+                if column.joined_below[row] {
+                    below[center] = glyphs.vertical;
+                }
+                if column.boxed_below[row] {
+                    below[left] = glyphs.vertical;
+                    below[left + cell_width - 1] = glyphs.vertical;
+                }
+
+                line.extend(wire);
+                gap.extend(below);
+            }
+
+            line.push(glyphs.wire);
+            lines.push(line);
+            if row + 1 < labels.len() {
+                lines.push(gap.trim_end().to_string());
+            }
+        }
+        lines
+    }
+}
+
+impl Renderer for TextRenderer {
+    fn render(&self, diagram: &Diagram) -> String {
+        diagram
+            .rows()
+            .into_iter()
+            .map(|columns| {
+                let mut row = self.render_row(diagram, columns).join("\n");
+                row.push('\n');
+                row
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Program {
+    /// Draw the gates and measurements of the program as a text diagram, laid out as by
+    /// [`crate::program::latex::Latex::to_latex`] with `settings` and drawn with `charset`.
+    pub fn to_text_diagram(
+        &self,
+        settings: RenderSettings,
+        charset: Charset,
+    ) -> Result<String, LatexGenError> {
+        let diagram = Diagram::build_plain_text(&self.instructions, settings)?;
+        Ok(TextRenderer { charset }.render(&diagram))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::Charset;
+    use crate::program::latex::{CircuitDisplay, RenderSettings};
+    use crate::Program;
+
+    fn get_text(input: &str, settings: RenderSettings, charset: Charset) -> String {
+        Program::from_str(input)
+            .unwrap()
+            .to_text_diagram(settings, charset)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_text_diagram() {
+        insta::assert_snapshot!(get_text(
+            "H 0\nCNOT 0 2\nDAGGER RX(pi/2) 1\nCCNOT 0 1 2\nMEASURE 2",
+            RenderSettings::default(),
+            Charset::Unicode
+        ));
+    }
+
+    #[test]
+    fn test_text_diagram_ascii() {
+        assert_eq!(
+            get_text("H 0\nCNOT 0 1", RenderSettings::default(), Charset::Ascii),
+            "q0: --[H]---@---\n            |\nq1: -------(+)--\n"
+        );
+    }
+
+    #[test]
+    fn test_text_diagram_boxes_and_rows() {
+        insta::assert_snapshot!(get_text(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nX 2\nH 0",
+            RenderSettings {
+                circuit_display: CircuitDisplay::Box,
+                max_columns_per_row: Some(2),
+                impute_missing_qubits: true,
+                ..Default::default()
+            },
+            Charset::Unicode
+        ));
+    }
+}
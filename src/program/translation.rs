@@ -0,0 +1,814 @@
+//! Translating the gates of a program into a native gate set.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::collections::HashMap;
+use std::f64::consts::{FRAC_PI_2, PI};
+
+use num_complex::Complex64;
+
+use crate::expression::{Expression, InfixOperator};
+use crate::instruction::unitary::{gate_unitary, Matrix};
+use crate::instruction::{
+    Gate, GateDefinition, GateModifier, GateSpecification, Instruction, Qubit,
+};
+use crate::Program;
+
+use super::equivalence::equal_up_to_phase;
+use super::fusion::{normalize, real_value, zyz_angles, TOLERANCE};
+
+/// The name of the square root of `X`, which programs translated into [`SingleQubitBasis::RzSx`]
+/// define if they do not already.
+const SX: &str = "SX";
+
+/// The native gates which act on one qubit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum SingleQubitBasis {
+    /// `RZ` by any angle, and `RX` by π/2 or -π/2.
+    RzRx,
+    /// `RZ` by any angle, and `SX`, the square root of `X`.
+    RzSx,
+}
+
+/// The native gate which entangles two qubits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum EntanglingGate {
+    Cz,
+    Cnot,
+}
+
+/// The gates which a program is to be translated into, as by [`Program::translate`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TargetGateSet {
+    pub single_qubit: SingleQubitBasis,
+    pub two_qubit: EntanglingGate,
+}
+
+impl TargetGateSet {
+    pub fn new(single_qubit: SingleQubitBasis, two_qubit: EntanglingGate) -> Self {
+        Self {
+            single_qubit,
+            two_qubit,
+        }
+    }
+
+    /// The gate set `{RZ, RX(±π/2), CZ}`.
+    pub fn rz_rx_cz() -> Self {
+        Self::new(SingleQubitBasis::RzRx, EntanglingGate::Cz)
+    }
+
+    /// The gate set `{RZ, SX, CNOT}`.
+    pub fn rz_sx_cnot() -> Self {
+        Self::new(SingleQubitBasis::RzSx, EntanglingGate::Cnot)
+    }
+
+    /// Whether `gate` is one of the gates of the set, taking its name to be that of the
+    /// standard gate.
+    pub fn is_native(&self, gate: &Gate) -> bool {
+        if !gate.modifiers.is_empty() {
+            return false;
+        }
+        match (gate.name.as_str(), gate.parameters.as_slice()) {
+            ("RZ", [_]) => true,
+            ("RX", [angle]) => {
+                self.single_qubit == SingleQubitBasis::RzRx
+                    && real_value(angle)
+                        .is_some_and(|angle| (normalize(angle).abs() - FRAC_PI_2).abs() < TOLERANCE)
+            }
+            (SX, []) => self.single_qubit == SingleQubitBasis::RzSx,
+            ("CZ", []) => self.two_qubit == EntanglingGate::Cz,
+            ("CNOT", []) => self.two_qubit == EntanglingGate::Cnot,
+            _ => false,
+        }
+    }
+
+    /// The names of the gates of the set.
+    fn gate_names(&self) -> [&'static str; 3] {
+        let single_qubit = match self.single_qubit {
+            SingleQubitBasis::RzRx => "RX",
+            SingleQubitBasis::RzSx => SX,
+        };
+        let two_qubit = match self.two_qubit {
+            EntanglingGate::Cz => "CZ",
+            EntanglingGate::Cnot => "CNOT",
+        };
+        ["RZ", single_qubit, two_qubit]
+    }
+}
+
+/// Errors which may occur while translating a program into a native gate set.
+#[derive(Clone, Debug, PartialEq, thiserror::Error)]
+pub enum TranslationError {
+    #[error("{0} cannot be translated into the target gate set")]
+    UnsupportedGate(Box<Instruction>),
+
+    #[error("the program's circuits could not be expanded: {0}")]
+    InvalidCircuit(String),
+
+    #[error("the program defines {0}, which the target gate set uses, as a different gate")]
+    ConflictingDefinition(String),
+}
+
+/// A gate named `name`, which is taken to be valid.
+fn gate(name: &str, parameters: Vec<Expression>, qubits: &[&Qubit]) -> Gate {
+    Gate {
+        name: name.into(),
+        parameters,
+        qubits: qubits.iter().map(|&qubit| qubit.clone()).collect(),
+        modifiers: vec![],
+    }
+}
+
+/// `left + right`, simplified.
+fn sum(left: Expression, right: Expression) -> Expression {
+    let mut sum = Expression::Infix {
+        left: Box::new(left),
+        operator: InfixOperator::Plus,
+        right: Box::new(right),
+    };
+    sum.simplify();
+    sum
+}
+
+/// `factor * expression`, simplified.
+fn scaled(factor: f64, expression: &Expression) -> Expression {
+    let mut product = Expression::Infix {
+        left: Box::new(Expression::from(factor)),
+        operator: InfixOperator::Star,
+        right: Box::new(expression.clone()),
+    };
+    product.simplify();
+    product
+}
+
+/// Whether `angle` is a constant multiple of 2π.
+fn is_full_turn(angle: &Expression) -> bool {
+    real_value(angle).is_some_and(|angle| normalize(angle).abs() < TOLERANCE)
+}
+
+/// The matrix of `SX`, the square root of `X`.
+fn sx_matrix() -> Matrix {
+    let (a, b) = (Complex64::new(0.5, 0.5), Complex64::new(0.5, -0.5));
+    Matrix::from_rows(vec![vec![a, b], vec![b, a]]).expect("the matrix is square")
+}
+
+/// Translates gates into a [`TargetGateSet`].
+struct Translator<'a> {
+    target: TargetGateSet,
+    /// The gates defined by the program, keyed by name.
+    definitions: HashMap<&'a str, &'a GateDefinition>,
+}
+
+impl Translator<'_> {
+    /// `gate` as a sequence of native gates, given the program's own definition of it, if any.
+    fn translate(&self, gate: &Gate) -> Result<Vec<Gate>, TranslationError> {
+        let is_defined = self.definitions.contains_key(gate.name.as_str())
+            && !(gate.name == SX && self.target.single_qubit == SingleQubitBasis::RzSx);
+        if is_defined {
+            self.translate_single_qubit(gate, &self.definitions)
+                .or_else(|| self.translate_controlled(gate, &self.definitions))
+                .ok_or_else(|| unsupported(gate))
+        } else {
+            self.translate_standard(gate)
+        }
+    }
+
+    /// `standard`, a standard gate or a native one, as a sequence of native gates.
+    fn translate_standard(&self, standard: &Gate) -> Result<Vec<Gate>, TranslationError> {
+        if self.target.is_native(standard) {
+            return Ok(vec![standard.clone()]);
+        }
+        if let Some(gates) = self.translate_single_qubit(standard, &HashMap::new()) {
+            return Ok(gates);
+        }
+
+        // DAGGER commutes with CONTROLLED, so the inverse is taken of the gate being controlled,
+        // which is often a standard gate with negated angles.
+        let controls = standard
+            .modifiers
+            .iter()
+            .filter(|modifier| **modifier == GateModifier::Controlled)
+            .count();
+        let daggers = standard
+            .modifiers
+            .iter()
+            .filter(|modifier| **modifier == GateModifier::Dagger)
+            .count();
+        if controls > 0 && daggers > 0 && controls + daggers == standard.modifiers.len() {
+            let mut base = Gate {
+                modifiers: vec![],
+                ..standard.clone()
+            };
+            if daggers % 2 == 1 {
+                base = base.dagger();
+            }
+            let mut modifiers = vec![GateModifier::Controlled; controls];
+            modifiers.append(&mut base.modifiers);
+            let folded = Gate { modifiers, ..base };
+            if folded != *standard {
+                return self.translate_standard(&folded);
+            }
+        }
+
+        let qubits = standard.qubits.iter().collect::<Vec<_>>();
+        let parameters = standard.parameters.as_slice();
+        let gates = match (standard.modifiers.as_slice(), qubits.as_slice()) {
+            ([], _) => self.decompose(standard),
+            ([GateModifier::Dagger], _) => {
+                let base = standard.dagger();
+                let inverse = base.dagger();
+                if inverse.modifiers.is_empty() {
+                    return self.translate_standard(&inverse);
+                }
+                // The inverse of a sequence is that of each of its gates, in reverse order.
+                let mut gates = vec![];
+                for native in self.translate_standard(&base)?.iter().rev() {
+                    gates.extend(self.invert_native(native));
+                }
+                return Ok(gates);
+            }
+            ([GateModifier::Controlled], [control, rest @ ..]) => {
+                match (standard.name.as_str(), parameters, rest) {
+                    ("X", [], [target]) => Some(vec![gate("CNOT", vec![], &[control, target])]),
+                    ("Z", [], [target]) => Some(vec![gate("CZ", vec![], &[control, target])]),
+                    ("PHASE", [angle], [target]) => Some(vec![gate(
+                        "CPHASE",
+                        vec![angle.clone()],
+                        &[control, target],
+                    )]),
+                    ("RZ", [angle], [target]) => Some(vec![
+                        Gate::rz(scaled(0.5, angle), (*target).clone()),
+                        gate("CNOT", vec![], &[control, target]),
+                        Gate::rz(scaled(-0.5, angle), (*target).clone()),
+                        gate("CNOT", vec![], &[control, target]),
+                    ]),
+                    ("CNOT", [], [a, b]) => Some(vec![gate("CCNOT", vec![], &[control, a, b])]),
+                    ("SWAP", [], [a, b]) => Some(vec![gate("CSWAP", vec![], &[control, a, b])]),
+                    _ => self.translate_controlled(standard, &HashMap::new()),
+                }
+            }
+            ([GateModifier::Controlled, GateModifier::Dagger], _) => {
+                self.translate_controlled(standard, &HashMap::new())
+            }
+            _ => None,
+        };
+
+        let mut translated = vec![];
+        for gate in gates.ok_or_else(|| unsupported(standard))? {
+            translated.extend(self.translate_standard(&gate)?);
+        }
+        Ok(translated)
+    }
+
+    /// The inverse of `native`, one of the target's gates, as a sequence of native gates.
+    fn invert_native(&self, native: &Gate) -> Vec<Gate> {
+        if native.name == SX {
+            // SX† is SX conjugated by a half turn about Z, up to global phase.
+            let qubit = &native.qubits[0];
+            return vec![
+                Gate::rz(PI, qubit.clone()),
+                native.clone(),
+                Gate::rz(PI, qubit.clone()),
+            ];
+        }
+        vec![native.dagger()]
+    }
+
+    /// `standard`, a standard gate with no modifiers, as a sequence of simpler standard gates,
+    /// if it is one which acts on more than one qubit or has symbolic parameters.
+    fn decompose(&self, standard: &Gate) -> Option<Vec<Gate>> {
+        let qubits = standard.qubits.iter().collect::<Vec<_>>();
+        let cnot = |a: &Qubit, b: &Qubit| gate("CNOT", vec![], &[a, b]);
+        let phase = |angle: Expression, qubit: &Qubit| Gate::phase(angle, qubit.clone());
+        let h = |qubit: &Qubit| Gate::h(qubit.clone());
+        let x = |qubit: &Qubit| Gate::x(qubit.clone());
+        let t = |qubit: &Qubit| Gate::t(qubit.clone());
+        let tdg = |qubit: &Qubit| Gate::t(qubit.clone()).dagger();
+
+        Some(
+            match (
+                standard.name.as_str(),
+                standard.parameters.as_slice(),
+                qubits.as_slice(),
+            ) {
+                ("I", [], [_]) => vec![],
+                ("PHASE" | "RZ", [angle], [qubit]) => self.zyz(
+                    Expression::from(0.0),
+                    Expression::from(0.0),
+                    angle.clone(),
+                    qubit,
+                ),
+                ("RY", [angle], [qubit]) => self.zyz(
+                    Expression::from(0.0),
+                    angle.clone(),
+                    Expression::from(0.0),
+                    qubit,
+                ),
+                ("RX", [angle], [qubit]) => self.zyz(
+                    Expression::from(-FRAC_PI_2),
+                    angle.clone(),
+                    Expression::from(FRAC_PI_2),
+                    qubit,
+                ),
+                ("CNOT", [], [a, b]) => vec![h(b), gate("CZ", vec![], &[a, b]), h(b)],
+                ("CZ", [], [a, b]) => vec![h(b), cnot(a, b), h(b)],
+                ("SWAP", [], [a, b]) => vec![cnot(a, b), cnot(b, a), cnot(a, b)],
+                ("CPHASE", [angle], [a, b]) => vec![
+                    phase(scaled(0.5, angle), a),
+                    phase(scaled(0.5, angle), b),
+                    cnot(a, b),
+                    phase(scaled(-0.5, angle), b),
+                    cnot(a, b),
+                ],
+                ("CPHASE00", [angle], [a, b]) => vec![
+                    x(a),
+                    x(b),
+                    gate("CPHASE", vec![angle.clone()], &[a, b]),
+                    x(a),
+                    x(b),
+                ],
+                ("CPHASE01", [angle], [a, b]) => {
+                    vec![x(a), gate("CPHASE", vec![angle.clone()], &[a, b]), x(a)]
+                }
+                ("CPHASE10", [angle], [a, b]) => {
+                    vec![x(b), gate("CPHASE", vec![angle.clone()], &[a, b]), x(b)]
+                }
+                ("PSWAP", [angle], [a, b]) => vec![
+                    cnot(a, b),
+                    phase(angle.clone(), b),
+                    cnot(a, b),
+                    gate("SWAP", vec![], &[a, b]),
+                ],
+                ("ISWAP", [], [a, b]) => vec![gate("XY", vec![Expression::from(PI)], &[a, b])],
+                ("XY", [angle], [a, b]) => {
+                    // XY(θ) is exp(iθ(XX + YY)/4), and XX and YY commute; each is ZZ in
+                    // another basis.
+                    let angle = scaled(-0.5, angle);
+                    let zz = |gates: &mut Vec<Gate>| {
+                        gates.push(cnot(a, b));
+                        gates.push(Gate::rz(angle.clone(), (*b).clone()));
+                        gates.push(cnot(a, b));
+                    };
+                    let mut gates = vec![h(a), h(b)];
+                    zz(&mut gates);
+                    gates.extend([h(a), h(b)]);
+                    gates.extend([
+                        Gate::rx(FRAC_PI_2, (*a).clone()),
+                        Gate::rx(FRAC_PI_2, (*b).clone()),
+                    ]);
+                    zz(&mut gates);
+                    gates.extend([
+                        Gate::rx(-FRAC_PI_2, (*a).clone()),
+                        Gate::rx(-FRAC_PI_2, (*b).clone()),
+                    ]);
+                    gates
+                }
+                ("CCNOT", [], [a, b, c]) => vec![
+                    h(c),
+                    cnot(b, c),
+                    tdg(c),
+                    cnot(a, c),
+                    t(c),
+                    cnot(b, c),
+                    tdg(c),
+                    cnot(a, c),
+                    t(b),
+                    t(c),
+                    h(c),
+                    cnot(a, b),
+                    t(a),
+                    tdg(b),
+                    cnot(a, b),
+                ],
+                ("CSWAP", [], [a, b, c]) => {
+                    vec![cnot(c, b), gate("CCNOT", vec![], &[a, b, c]), cnot(c, b)]
+                }
+                _ => return None,
+            },
+        )
+    }
+
+    /// `gate` as a sequence of native gates, if it acts on one qubit, has constant parameters,
+    /// and has no modifiers but `DAGGER`, so that its matrix can be decomposed.
+    fn translate_single_qubit(
+        &self,
+        gate: &Gate,
+        definitions: &HashMap<&str, &GateDefinition>,
+    ) -> Option<Vec<Gate>> {
+        let [qubit] = gate.qubits.as_slice() else {
+            return None;
+        };
+        if gate
+            .modifiers
+            .iter()
+            .any(|modifier| *modifier != GateModifier::Dagger)
+            || gate
+                .parameters
+                .iter()
+                .any(|parameter| real_value(parameter).is_none())
+        {
+            return None;
+        }
+        let local = Gate {
+            qubits: vec![Qubit::Fixed(0)],
+            ..gate.clone()
+        };
+        let unitary = gate_unitary(&local, 1, definitions).ok()?;
+        let (beta, gamma, delta) = zyz_angles(&unitary);
+        Some(self.zyz(
+            Expression::from(beta),
+            Expression::from(gamma),
+            Expression::from(delta),
+            qubit,
+        ))
+    }
+
+    /// `gate` as a sequence of native gates, if it is a gate on one qubit with constant
+    /// parameters under one `CONTROLLED` modifier, and any number of `DAGGER` modifiers.
+    fn translate_controlled(
+        &self,
+        gate: &Gate,
+        definitions: &HashMap<&str, &GateDefinition>,
+    ) -> Option<Vec<Gate>> {
+        let [control, target] = gate.qubits.as_slice() else {
+            return None;
+        };
+        let mut local = Gate {
+            qubits: vec![Qubit::Fixed(0)],
+            modifiers: vec![],
+            ..gate.clone()
+        };
+        let mut controls = 0;
+        for modifier in &gate.modifiers {
+            match modifier {
+                GateModifier::Controlled => controls += 1,
+                GateModifier::Dagger => local.modifiers.push(GateModifier::Dagger),
+                GateModifier::Forked => return None,
+            }
+        }
+        if controls != 1
+            || gate
+                .parameters
+                .iter()
+                .any(|parameter| real_value(parameter).is_none())
+        {
+            return None;
+        }
+        let unitary = gate_unitary(&local, 1, definitions).ok()?;
+        let (beta, gamma, delta) = zyz_angles(&unitary);
+        let alpha = global_phase(&unitary, beta, gamma, delta);
+
+        // With U = e^(iα)·RZ(β)·RY(γ)·RZ(δ), take A = RZ(β)·RY(γ/2), B = RY(-γ/2)·RZ(-(δ+β)/2),
+        // and C = RZ((δ-β)/2). Then ABC = I and AXBXC = e^(-iα)·U, so applying C, CNOT, B, CNOT,
+        // and A to the target applies U up to the phase e^(iα), which is applied to the control.
+        let cnot = self
+            .translate_standard(&self::gate("CNOT", vec![], &[control, target]))
+            .ok()?;
+        let angle = Expression::from;
+        let mut gates = self.zyz(angle(0.0), angle(0.0), angle((delta - beta) / 2.0), target);
+        gates.extend(cnot.iter().cloned());
+        gates.extend(self.zyz(
+            angle(0.0),
+            angle(-gamma / 2.0),
+            angle(-(delta + beta) / 2.0),
+            target,
+        ));
+        gates.extend(cnot);
+        gates.extend(self.zyz(angle(beta), angle(gamma / 2.0), angle(0.0), target));
+        gates.extend(self.zyz(angle(0.0), angle(0.0), angle(alpha), control));
+        Some(gates)
+    }
+
+    /// The native gates applying `RZ(β)·RY(γ)·RZ(δ)` to `qubit`, up to global phase.
+    fn zyz(
+        &self,
+        beta: Expression,
+        gamma: Expression,
+        delta: Expression,
+        qubit: &Qubit,
+    ) -> Vec<Gate> {
+        // A rotation about Z, or none if the angle is a whole number of turns.
+        let rz = |angle: Expression| match real_value(&angle) {
+            Some(angle) => {
+                let angle = normalize(angle);
+                (angle.abs() >= TOLERANCE).then(|| Gate::rz(angle, qubit.clone()))
+            }
+            None => Some(Gate::rz(angle, qubit.clone())),
+        };
+        if is_full_turn(&gamma) {
+            return rz(sum(delta, beta)).into_iter().collect();
+        }
+
+        // RY(γ) is RZ(γ) in the basis which RX(π/2) takes Z to Y.
+        let gates = match self.target.single_qubit {
+            SingleQubitBasis::RzRx => [
+                rz(delta),
+                Some(Gate::rx(FRAC_PI_2, qubit.clone())),
+                rz(gamma),
+                Some(Gate::rx(-FRAC_PI_2, qubit.clone())),
+                rz(beta),
+            ],
+            // RX(π/2) is SX, and RX(-π/2) is SX between half turns about Z.
+            SingleQubitBasis::RzSx => [
+                rz(delta),
+                Some(gate(SX, vec![], &[qubit])),
+                rz(sum(gamma, Expression::from(PI))),
+                Some(gate(SX, vec![], &[qubit])),
+                rz(sum(beta, Expression::from(PI))),
+            ],
+        };
+        gates.into_iter().flatten().collect()
+    }
+}
+
+/// The phase `α` for which `unitary` is `e^(iα)·RZ(β)·RY(γ)·RZ(δ)`, given the angles found by
+/// [`zyz_angles`].
+fn global_phase(unitary: &Matrix, beta: f64, gamma: f64, delta: f64) -> f64 {
+    // The left column of RZ(β)·RY(γ)·RZ(δ) is e^(-i(β+δ)/2)·cos(γ/2) above e^(i(β-δ)/2)·sin(γ/2),
+    // neither magnitude of which is negative, since γ is between 0 and π.
+    if (gamma / 2.0).cos() >= (gamma / 2.0).sin() {
+        unitary[(0, 0)].arg() + (beta + delta) / 2.0
+    } else {
+        unitary[(1, 0)].arg() - (beta - delta) / 2.0
+    }
+}
+
+/// The error for a gate which cannot be translated.
+fn unsupported(gate: &Gate) -> TranslationError {
+    TranslationError::UnsupportedGate(Box::new(Instruction::Gate(gate.clone())))
+}
+
+impl Program {
+    /// A copy of the program with each gate rewritten as a sequence of the gates of `target`,
+    /// which applies the same operation up to global phase.
+    ///
+    /// Circuits are expanded first, and instructions other than gates are kept as they are.
+    /// The standard gates, with no modifiers or with `DAGGER`, are translated, as are some
+    /// controlled ones, such as `CONTROLLED X` and `CONTROLLED PHASE`; so are gates the program
+    /// defines which act on one qubit. Any gate on one qubit with constant parameters may be
+    /// under one `CONTROLLED` modifier, and `DAGGER` may be applied to any controlled gate.
+    /// Rotations by symbolic angles are kept symbolic, as angles of `RZ`. When the target includes
+    /// `SX`, the copy defines it as the square root of `X` if the program does not already.
+    ///
+    /// The copy does not record the text the program was parsed from.
+    ///
+    /// # Errors
+    ///
+    /// Fails if a gate cannot be translated, such as one which acts on more than one qubit and is
+    /// defined by the program, or one on one qubit with a symbolic parameter which is not a
+    /// standard rotation; if the program's circuits cannot be expanded; or if the program defines
+    /// any of the gates of `target`, other than `SX` as the square root of `X`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use quil_rs::program::translation::TargetGateSet;
+    /// use quil_rs::Program;
+    ///
+    /// let program: Program = "SWAP 0 1".parse().unwrap();
+    /// let translated = program.translate(&TargetGateSet::rz_sx_cnot()).unwrap();
+    /// assert_eq!(translated.to_string(true), "CNOT 0 1\nCNOT 1 0\nCNOT 0 1\n");
+    /// ```
+    pub fn translate(&self, target: &TargetGateSet) -> Result<Self, TranslationError> {
+        let program = self
+            .expand_circuits()
+            .map_err(|error| TranslationError::InvalidCircuit(error.to_string()))?;
+        let definitions: HashMap<&str, &GateDefinition> = program
+            .instructions
+            .iter()
+            .filter_map(|instruction| match instruction {
+                Instruction::GateDefinition(definition) => {
+                    Some((definition.name.as_str(), definition))
+                }
+                _ => None,
+            })
+            .collect();
+
+        // The translated gates are written by name, so must not be given other meanings.
+        for name in target.gate_names() {
+            let conflicts = definitions.contains_key(name)
+                && (name != SX || {
+                    let sx = gate(SX, vec![], &[&Qubit::Fixed(0)]);
+                    !gate_unitary(&sx, 1, &definitions)
+                        .is_ok_and(|unitary| equal_up_to_phase(&unitary, &sx_matrix()))
+                });
+            if conflicts {
+                return Err(TranslationError::ConflictingDefinition(name.to_string()));
+            }
+        }
+        let defines_sx = definitions.contains_key(SX);
+
+        let translator = Translator {
+            target: *target,
+            definitions,
+        };
+        let mut instructions = vec![];
+        for instruction in &program.instructions {
+            match instruction {
+                Instruction::Gate(gate) => instructions.extend(
+                    translator
+                        .translate(gate)?
+                        .into_iter()
+                        .map(Instruction::Gate),
+                ),
+                _ => instructions.push(instruction.clone()),
+            }
+        }
+
+        let uses_sx = instructions
+            .iter()
+            .any(|instruction| matches!(instruction, Instruction::Gate(gate) if gate.name == SX));
+        if uses_sx && !defines_sx {
+            let matrix = sx_matrix()
+                .rows()
+                .map(|row| {
+                    row.iter()
+                        .map(|&element| Expression::Number(element))
+                        .collect()
+                })
+                .collect();
+            instructions.insert(
+                0,
+                Instruction::GateDefinition(GateDefinition {
+                    name: SX.into(),
+                    parameters: vec![],
+                    specification: GateSpecification::Matrix(matrix),
+                }),
+            );
+        }
+
+        let mut translated = program;
+        translated.instructions = instructions;
+        translated.clear_source();
+        Ok(translated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use rstest::rstest;
+
+    use super::{TargetGateSet, TranslationError};
+    use crate::instruction::Instruction;
+    use crate::program::EquivalenceOptions;
+    use crate::Program;
+
+    #[rstest]
+    #[case("H 0")]
+    #[case("X 0\nY 1\nZ 2")]
+    #[case("S 0\nT 0\nDAGGER S 1\nDAGGER T 1")]
+    #[case("RX(0.3) 0\nRY(0.7) 0\nPHASE(1.1) 0\nRZ(-0.2) 0\nI 1")]
+    #[case("RX(pi/2) 0\nRX(-pi/2) 0\nSX 1")]
+    #[case("CNOT 0 1\nCZ 1 0")]
+    #[case("SWAP 0 1")]
+    #[case("ISWAP 0 1\nDAGGER ISWAP 1 0")]
+    #[case("CPHASE(0.4) 0 1\nCPHASE00(0.5) 0 1\nCPHASE01(0.6) 0 1\nCPHASE10(0.7) 0 1")]
+    #[case("PSWAP(0.8) 0 1\nXY(0.9) 1 0")]
+    #[case("DAGGER PSWAP(0.8) 0 1\nDAGGER CPHASE(0.3) 1 0")]
+    #[case("CCNOT 0 1 2")]
+    #[case("CSWAP 2 0 1")]
+    #[case("CONTROLLED X 0 1\nCONTROLLED Z 1 0\nCONTROLLED PHASE(0.3) 0 1\nCONTROLLED RZ(0.2) 1 0")]
+    #[case("CONTROLLED CNOT 0 1 2\nCONTROLLED SWAP 0 1 2")]
+    #[case("DAGGER CCNOT 0 1 2")]
+    #[case("CONTROLLED RX(0.5) 2 0\nDAGGER CONTROLLED H 1 2")]
+    #[case("CONTROLLED S 0 1\nDAGGER CONTROLLED T 1 0\nCONTROLLED DAGGER RY(0.3) 0 1")]
+    #[case("DAGGER CONTROLLED RZ(0.2) 0 1\nDAGGER CONTROLLED X 1 0\nDAGGER CONTROLLED SWAP 0 1 2")]
+    #[case("DEFGATE U:\n    0, i\n    1, 0\n\nCONTROLLED U 0 1\nDAGGER CONTROLLED U 1 0")]
+    #[case("DEFGATE U:\n    0, i\n    1, 0\n\nU 0\nDAGGER U 1")]
+    #[case("DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1")]
+    fn test_translation_is_equivalent(
+        #[case] input: &str,
+        #[values(TargetGateSet::rz_rx_cz(), TargetGateSet::rz_sx_cnot())] target: TargetGateSet,
+    ) {
+        let mut program = Program::from_str(input).unwrap();
+        if input.contains("SX") {
+            program = Program::from_str(&format!(
+                "DEFGATE SX:\n    0.5+0.5i, 0.5-0.5i\n    0.5-0.5i, 0.5+0.5i\n\n{input}"
+            ))
+            .unwrap();
+        }
+        let translated = program.translate(&target).unwrap();
+        let mut sx = None;
+        for instruction in &translated.instructions {
+            match instruction {
+                Instruction::Gate(gate) => assert!(
+                    target.is_native(gate),
+                    "{input} was translated into {}",
+                    translated.to_string(true)
+                ),
+                Instruction::GateDefinition(definition) if definition.name == "SX" => {
+                    sx = Some(definition.clone())
+                }
+                _ => {}
+            }
+        }
+
+        // The original program needs the definition of SX to be compared with the translation.
+        let mut original = program.expand_circuits().unwrap();
+        if let Some(sx) = sx {
+            if !input.contains("SX") {
+                original
+                    .instructions
+                    .insert(0, Instruction::GateDefinition(sx));
+            }
+        }
+        let options = EquivalenceOptions {
+            unitary_qubit_limit: Some(3),
+            ..Default::default()
+        };
+        assert!(
+            translated.equivalent_to(&original, &options),
+            "{input} was translated into {}",
+            translated.to_string(true)
+        );
+    }
+
+    #[rstest]
+    #[case(
+        "DECLARE theta REAL\nRZ(theta) 0\nRX(theta) 1",
+        "DECLARE theta REAL[1]\nRZ(theta[0]) 0\nRZ(1.5707963267948966) 1\nRX(1.5707963267948966) 1\nRZ(theta[0]) 1\nRX(-1.5707963267948966) 1\nRZ(-1.5707963267948966) 1\n"
+    )]
+    #[case(
+        "X 0\nMEASURE 0 ro",
+        "RX(1.5707963267948966) 0\nRZ(3.141592653589793) 0\nRX(-1.5707963267948966) 0\nRZ(3.141592653589793) 0\nMEASURE 0 ro[0]\n"
+    )]
+    #[case("I 0\nRZ(0.5) 0\nRY(2*pi) 0", "RZ(0.5) 0\n")]
+    fn test_translate(#[case] input: &str, #[case] expected: &str) {
+        let program = Program::from_str(input).unwrap();
+        let translated = program.translate(&TargetGateSet::rz_rx_cz()).unwrap();
+        assert_eq!(translated.to_string(true), expected);
+    }
+
+    #[test]
+    fn test_translate_defines_sx() {
+        let program = Program::from_str("H 0").unwrap();
+        let translated = program.translate(&TargetGateSet::rz_sx_cnot()).unwrap();
+        assert_eq!(
+            translated.to_string(true),
+            "DEFGATE SX AS MATRIX:\n\t0.5+0.5i,0.5-0.5i\n\t0.5-0.5i,0.5+0.5i\n\nRZ(3.141592653589793) 0\nSX 0\nRZ(-1.5707963267948966) 0\nSX 0\nRZ(3.141592653589793) 0\n"
+        );
+    }
+
+    #[rstest]
+    #[case("DECLARE theta REAL\nCONTROLLED RY(theta) 0 1")]
+    #[case("CONTROLLED CONTROLLED H 0 1 2")]
+    #[case("DECLARE theta REAL\nFORKED RX(theta, theta) 0 1")]
+    #[case("DEFGATE U:\n    1, 0, 0, 0\n    0, 1, 0, 0\n    0, 0, 0, 1\n    0, 0, 1, 0\n\nU 0 1")]
+    fn test_unsupported_gates(#[case] input: &str) {
+        let program = Program::from_str(input).unwrap();
+        let error = program.translate(&TargetGateSet::rz_rx_cz()).unwrap_err();
+        assert!(matches!(error, TranslationError::UnsupportedGate(_)));
+    }
+
+    #[rstest]
+    #[case(
+        "DEFGATE SX:\n    1, 0\n    0, 1\n\nH 0",
+        TargetGateSet::rz_sx_cnot(),
+        "SX"
+    )]
+    #[case(
+        "DEFGATE CZ:\n    1, 0, 0, 0\n    0, 1, 0, 0\n    0, 0, 0, 1\n    0, 0, 1, 0\n\nCNOT 0 1",
+        TargetGateSet::rz_rx_cz(),
+        "CZ"
+    )]
+    #[case(
+        "DEFGATE CNOT:\n    1, 0, 0, 0\n    0, 1, 0, 0\n    0, 0, 1, 0\n    0, 0, 0, -1\n\nCZ 0 1",
+        TargetGateSet::rz_sx_cnot(),
+        "CNOT"
+    )]
+    #[case(
+        "DEFGATE RX(%t):\n    1, 0\n    0, 1\n\nH 0",
+        TargetGateSet::rz_rx_cz(),
+        "RX"
+    )]
+    #[case(
+        "DEFGATE RZ(%t):\n    1, 0\n    0, 1\n\nH 0",
+        TargetGateSet::rz_sx_cnot(),
+        "RZ"
+    )]
+    fn test_conflicting_definition(
+        #[case] input: &str,
+        #[case] target: TargetGateSet,
+        #[case] name: &str,
+    ) {
+        let program = Program::from_str(input).unwrap();
+        assert_eq!(
+            program.translate(&target),
+            Err(TranslationError::ConflictingDefinition(name.to_string()))
+        );
+    }
+}
@@ -5,9 +5,9 @@ use crate::{
     expression::Expression,
     instruction::{
         Arithmetic, ArithmeticOperand, ArithmeticOperator, BinaryLogic, BinaryOperand,
-        BinaryOperator, Comparison, ComparisonOperand, ComparisonOperator, Exchange, Instruction,
-        Load, MemoryReference, Move, ScalarType, SetFrequency, SetPhase, SetScale, ShiftFrequency,
-        ShiftPhase, Store, UnaryLogic, UnaryOperator,
+        BinaryOperator, Comparison, ComparisonOperand, ComparisonOperator, Exchange, Identifier,
+        Instruction, Load, MemoryReference, Move, ScalarType, SetFrequency, SetPhase, SetScale,
+        ShiftFrequency, ShiftPhase, Store, UnaryLogic, UnaryOperator,
     },
     program::MemoryRegion,
     Program,
@@ -64,73 +64,65 @@ pub type TypeResult<T> = Result<T, TypeError>;
 /// See the [Quil spec](https://quil-lang.github.io/).
 pub fn type_check(program: &Program) -> TypeResult<()> {
     for instruction in &program.instructions {
-        match instruction {
-            Instruction::SetFrequency(SetFrequency { frequency, .. }) => {
-                should_be_real(instruction, frequency, &program.memory_regions)?
-            }
-            Instruction::SetPhase(SetPhase { phase, .. }) => {
-                should_be_real(instruction, phase, &program.memory_regions)?
-            }
-            Instruction::SetScale(SetScale { scale, .. }) => {
-                should_be_real(instruction, scale, &program.memory_regions)?
-            }
-            Instruction::ShiftFrequency(ShiftFrequency { frequency, .. }) => {
-                should_be_real(instruction, frequency, &program.memory_regions)?
-            }
-            Instruction::ShiftPhase(ShiftPhase { phase, .. }) => {
-                should_be_real(instruction, phase, &program.memory_regions)?
-            }
-            Instruction::Arithmetic(Arithmetic {
-                operator,
-                destination,
-                source,
-            }) => type_check_arithmetic(
-                instruction,
-                operator,
-                destination,
-                source,
-                &program.memory_regions,
-            )?,
-            Instruction::Comparison(Comparison { operator, operands }) => {
-                type_check_comparison(instruction, operator, operands, &program.memory_regions)?
-            }
-            Instruction::BinaryLogic(BinaryLogic { operator, operands }) => {
-                type_check_binary_logic(instruction, operator, operands, &program.memory_regions)?
-            }
-            Instruction::UnaryLogic(UnaryLogic { operator, operand }) => {
-                type_check_unary_logic(instruction, operator, operand, &program.memory_regions)?
-            }
-            Instruction::Move(Move {
-                destination,
-                source,
-            }) => type_check_move(instruction, destination, source, &program.memory_regions)?,
-            Instruction::Exchange(Exchange { left, right }) => {
-                type_check_exchange(instruction, left, right, &program.memory_regions)?
-            }
-            Instruction::Load(Load {
-                destination,
-                source,
-                offset,
-            }) => type_check_load(
-                instruction,
-                destination,
-                source,
-                offset,
-                &program.memory_regions,
-            )?,
-            Instruction::Store(Store {
-                destination,
-                offset,
-                source,
-            }) => type_check_store(
-                instruction,
-                destination,
-                offset,
-                source,
-                &program.memory_regions,
-            )?,
-            _ => {}
+        type_check_instruction(instruction, &program.memory_regions)?;
+    }
+    Ok(())
+}
+
+/// Type check a single instruction against the memory regions declared by its program.
+#[allow(clippy::result_large_err)]
+pub(crate) fn type_check_instruction(
+    instruction: &Instruction,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
+) -> TypeResult<()> {
+    match instruction {
+        Instruction::SetFrequency(SetFrequency { frequency, .. }) => {
+            should_be_real(instruction, frequency, memory_regions)?
+        }
+        Instruction::SetPhase(SetPhase { phase, .. }) => {
+            should_be_real(instruction, phase, memory_regions)?
+        }
+        Instruction::SetScale(SetScale { scale, .. }) => {
+            should_be_real(instruction, scale, memory_regions)?
+        }
+        Instruction::ShiftFrequency(ShiftFrequency { frequency, .. }) => {
+            should_be_real(instruction, frequency, memory_regions)?
         }
+        Instruction::ShiftPhase(ShiftPhase { phase, .. }) => {
+            should_be_real(instruction, phase, memory_regions)?
+        }
+        Instruction::Arithmetic(Arithmetic {
+            operator,
+            destination,
+            source,
+        }) => type_check_arithmetic(instruction, operator, destination, source, memory_regions)?,
+        Instruction::Comparison(Comparison { operator, operands }) => {
+            type_check_comparison(instruction, operator, operands, memory_regions)?
+        }
+        Instruction::BinaryLogic(BinaryLogic { operator, operands }) => {
+            type_check_binary_logic(instruction, operator, operands, memory_regions)?
+        }
+        Instruction::UnaryLogic(UnaryLogic { operator, operand }) => {
+            type_check_unary_logic(instruction, operator, operand, memory_regions)?
+        }
+        Instruction::Move(Move {
+            destination,
+            source,
+        }) => type_check_move(instruction, destination, source, memory_regions)?,
+        Instruction::Exchange(Exchange { left, right }) => {
+            type_check_exchange(instruction, left, right, memory_regions)?
+        }
+        Instruction::Load(Load {
+            destination,
+            source,
+            offset,
+        }) => type_check_load(instruction, destination, source, offset, memory_regions)?,
+        Instruction::Store(Store {
+            destination,
+            offset,
+            source,
+        }) => type_check_store(instruction, destination, offset, source, memory_regions)?,
+        _ => {}
     }
     Ok(())
 }
@@ -194,11 +186,11 @@ fn operator_operand_mismatch(
 fn should_be_real(
     instruction: &Instruction,
     this_expression: &Expression,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
     match this_expression {
         Expression::Address(reference) => {
-            if let Some(MemoryRegion { size, .. }) = memory_regions.get(&reference.name) {
+            if let Some(MemoryRegion { size, .. }) = memory_regions.get(reference.name.as_str()) {
                 let dt = &size.data_type;
                 if dt == &ScalarType::Real {
                     Ok(())
@@ -209,6 +201,12 @@ fn should_be_real(
                 undefined_memory_reference(instruction, reference)
             }
         }
+        Expression::CustomFunctionCall { arguments, .. } => {
+            for argument in arguments {
+                should_be_real(instruction, argument, memory_regions)?;
+            }
+            Ok(())
+        }
         Expression::FunctionCall { expression, .. } => {
             should_be_real(instruction, expression, memory_regions)
         }
@@ -233,64 +231,51 @@ fn should_be_real(
 fn type_check_arithmetic(
     instruction: &Instruction,
     operator: &ArithmeticOperator,
-    destination: &ArithmeticOperand,
+    dest_ref: &MemoryReference,
     source: &ArithmeticOperand,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
-    match destination {
-        ArithmeticOperand::LiteralInteger(_) | ArithmeticOperand::LiteralReal(_) => {
-            operator_operand_mismatch(
+    if let Some(dest_region) = memory_regions.get(dest_ref.name.as_str()) {
+        let dt = &dest_region.size.data_type;
+        match (source, dt) {
+            (ArithmeticOperand::LiteralInteger(_), ScalarType::Integer) => Ok(()),
+            (ArithmeticOperand::LiteralReal(_), ScalarType::Real) => Ok(()),
+            (ArithmeticOperand::LiteralInteger(_), ScalarType::Real) => {
+                data_type_mismatch(instruction, dest_ref, dt, source, "`literal integer`")
+            }
+            (ArithmeticOperand::LiteralReal(_), _) => {
+                data_type_mismatch(instruction, dest_ref, dt, source, "`literal real`")
+            }
+            (_, ScalarType::Bit) | (_, ScalarType::Octet) => operator_operand_mismatch(
                 instruction,
-                "operation",
-                "memory reference",
-                destination,
-                "`literal value`",
-            )
-        }
-        ArithmeticOperand::MemoryReference(dest_ref) => {
-            if let Some(dest_region) = memory_regions.get(&dest_ref.name) {
-                let dt = &dest_region.size.data_type;
-                match (source, dt) {
-                    (ArithmeticOperand::LiteralInteger(_), ScalarType::Integer) => Ok(()),
-                    (ArithmeticOperand::LiteralReal(_), ScalarType::Real) => Ok(()),
-                    (ArithmeticOperand::LiteralInteger(_), ScalarType::Real) => {
-                        data_type_mismatch(instruction, dest_ref, dt, source, "`literal integer`")
-                    }
-                    (ArithmeticOperand::LiteralReal(_), _) => {
-                        data_type_mismatch(instruction, dest_ref, dt, source, "`literal real`")
-                    }
-                    (_, ScalarType::Bit) | (_, ScalarType::Octet) => operator_operand_mismatch(
-                        instruction,
-                        operator,
-                        "real or integral-valued",
-                        dest_ref,
-                        dt,
-                    ),
-                    (ArithmeticOperand::MemoryReference(src_ref), _) => {
-                        if let Some(src_region) = memory_regions.get(&src_ref.name) {
-                            let st = &src_region.size.data_type;
-                            match st {
-                                ScalarType::Bit | ScalarType::Octet => operator_operand_mismatch(
-                                    instruction,
-                                    operator,
-                                    "real or integral-valued",
-                                    src_ref,
-                                    st,
-                                ),
-                                st if dt != st => {
-                                    data_type_mismatch(instruction, dest_ref, dt, src_ref, st)
-                                }
-                                _ => Ok(()),
-                            }
-                        } else {
-                            undefined_memory_reference(instruction, src_ref)
+                operator,
+                "real or integral-valued",
+                dest_ref,
+                dt,
+            ),
+            (ArithmeticOperand::MemoryReference(src_ref), _) => {
+                if let Some(src_region) = memory_regions.get(src_ref.name.as_str()) {
+                    let st = &src_region.size.data_type;
+                    match st {
+                        ScalarType::Bit | ScalarType::Octet => operator_operand_mismatch(
+                            instruction,
+                            operator,
+                            "real or integral-valued",
+                            src_ref,
+                            st,
+                        ),
+                        st if dt != st => {
+                            data_type_mismatch(instruction, dest_ref, dt, src_ref, st)
                         }
+                        _ => Ok(()),
                     }
+                } else {
+                    undefined_memory_reference(instruction, src_ref)
                 }
-            } else {
-                undefined_memory_reference(instruction, dest_ref)
             }
         }
+    } else {
+        undefined_memory_reference(instruction, dest_ref)
     }
 }
 
@@ -299,10 +284,13 @@ fn type_check_comparison(
     instruction: &Instruction,
     operator: &ComparisonOperator,
     operands: &(MemoryReference, MemoryReference, ComparisonOperand),
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
     let (x, y, z) = operands;
-    match (memory_regions.get(&x.name), memory_regions.get(&y.name)) {
+    match (
+        memory_regions.get(x.name.as_str()),
+        memory_regions.get(y.name.as_str()),
+    ) {
         (None, _) => undefined_memory_reference(instruction, x),
         (_, None) => undefined_memory_reference(instruction, y),
         (Some(x_region), Some(y_region)) => {
@@ -318,7 +306,7 @@ fn type_check_comparison(
                         data_type_mismatch(instruction, y, yt, z, "`literal real`")
                     }
                     (_, ComparisonOperand::MemoryReference(z_ref)) => {
-                        if let Some(z_region) = memory_regions.get(&z_ref.name) {
+                        if let Some(z_region) = memory_regions.get(z_ref.name.as_str()) {
                             let zt = &z_region.size.data_type;
                             if yt != zt {
                                 data_type_mismatch(instruction, y, yt, z, zt)
@@ -341,10 +329,10 @@ fn type_check_binary_logic(
     instruction: &Instruction,
     operator: &BinaryOperator,
     operands: &(MemoryReference, BinaryOperand),
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
     let (x, y) = operands;
-    if let Some(x_region) = memory_regions.get(&x.name) {
+    if let Some(x_region) = memory_regions.get(x.name.as_str()) {
         let xt = &x_region.size.data_type;
         if xt == &ScalarType::Real {
             operator_operand_mismatch(instruction, operator, "integral", x, xt)
@@ -352,7 +340,7 @@ fn type_check_binary_logic(
             match y {
                 BinaryOperand::LiteralInteger(_) => Ok(()),
                 BinaryOperand::MemoryReference(y_ref) => {
-                    if let Some(y_region) = memory_regions.get(&y_ref.name) {
+                    if let Some(y_region) = memory_regions.get(y_ref.name.as_str()) {
                         let yt = &y_region.size.data_type;
                         if yt == &ScalarType::Real {
                             operator_operand_mismatch(instruction, operator, "integral", y, yt)
@@ -375,9 +363,9 @@ fn type_check_unary_logic(
     instruction: &Instruction,
     operator: &UnaryOperator,
     operand: &MemoryReference,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
-    if let Some(MemoryRegion { size, .. }) = memory_regions.get(&operand.name) {
+    if let Some(MemoryRegion { size, .. }) = memory_regions.get(operand.name.as_str()) {
         let dt = &size.data_type;
         match (dt, operator) {
             (ScalarType::Real, UnaryOperator::Not) => {
@@ -407,101 +395,59 @@ fn type_check_unary_logic(
 /// Type check an [Instruction::Move].
 fn type_check_move(
     instruction: &Instruction,
-    destination: &ArithmeticOperand,
+    destination: &MemoryReference,
     source: &ArithmeticOperand,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
-    match destination {
-        ArithmeticOperand::LiteralInteger(_) | ArithmeticOperand::LiteralReal(_) => {
-            operator_operand_mismatch(
-                instruction,
-                "MOVE",
-                "memory reference",
-                destination,
-                "`literal value`",
-            )
-        }
-        ArithmeticOperand::MemoryReference(dest_ref) => {
-            if let Some(dest_region) = memory_regions.get(&dest_ref.name) {
-                let dt = &dest_region.size.data_type;
-                match (source, dt) {
-                    (ArithmeticOperand::LiteralInteger(_), ScalarType::Real) => data_type_mismatch(
-                        instruction,
-                        destination,
-                        dt,
-                        source,
-                        "`literal integer`",
-                    ),
-                    (ArithmeticOperand::LiteralReal(_), st) if st != &ScalarType::Real => {
-                        data_type_mismatch(instruction, destination, dt, source, "`literal real`")
-                    }
-                    (ArithmeticOperand::MemoryReference(src_ref), dt) => {
-                        if let Some(src_region) = memory_regions.get(&src_ref.name) {
-                            let st = &src_region.size.data_type;
-                            if st != dt {
-                                data_type_mismatch(instruction, destination, dt, source, st)
-                            } else {
-                                Ok(())
-                            }
-                        } else {
-                            undefined_memory_reference(instruction, src_ref)
-                        }
+    if let Some(dest_region) = memory_regions.get(destination.name.as_str()) {
+        let dt = &dest_region.size.data_type;
+        match (source, dt) {
+            (ArithmeticOperand::LiteralInteger(_), ScalarType::Real) => {
+                data_type_mismatch(instruction, destination, dt, source, "`literal integer`")
+            }
+            (ArithmeticOperand::LiteralReal(_), st) if st != &ScalarType::Real => {
+                data_type_mismatch(instruction, destination, dt, source, "`literal real`")
+            }
+            (ArithmeticOperand::MemoryReference(src_ref), dt) => {
+                if let Some(src_region) = memory_regions.get(src_ref.name.as_str()) {
+                    let st = &src_region.size.data_type;
+                    if st != dt {
+                        data_type_mismatch(instruction, destination, dt, source, st)
+                    } else {
+                        Ok(())
                     }
-                    _ => Ok(()),
+                } else {
+                    undefined_memory_reference(instruction, src_ref)
                 }
-            } else {
-                undefined_memory_reference(instruction, dest_ref)
             }
+            _ => Ok(()),
         }
+    } else {
+        undefined_memory_reference(instruction, destination)
     }
 }
 
 /// Type check an [Instruction::Exchange].
 fn type_check_exchange(
     instruction: &Instruction,
-    left: &ArithmeticOperand,
-    right: &ArithmeticOperand,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    left: &MemoryReference,
+    right: &MemoryReference,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
-    match (left, right) {
-        (
-            ArithmeticOperand::MemoryReference(left_ref),
-            ArithmeticOperand::MemoryReference(right_ref),
-        ) => {
-            match (
-                memory_regions.get(&left_ref.name),
-                memory_regions.get(&right_ref.name),
-            ) {
-                (None, _) => undefined_memory_reference(instruction, left_ref),
-                (_, None) => undefined_memory_reference(instruction, right_ref),
-                (Some(left_region), Some(right_region)) => {
-                    let (lt, rt) = (&left_region.size.data_type, &right_region.size.data_type);
-                    if lt != rt {
-                        data_type_mismatch(instruction, left, lt, right, rt)
-                    } else {
-                        Ok(())
-                    }
-                }
+    match (
+        memory_regions.get(left.name.as_str()),
+        memory_regions.get(right.name.as_str()),
+    ) {
+        (None, _) => undefined_memory_reference(instruction, left),
+        (_, None) => undefined_memory_reference(instruction, right),
+        (Some(left_region), Some(right_region)) => {
+            let (lt, rt) = (&left_region.size.data_type, &right_region.size.data_type);
+            if lt != rt {
+                data_type_mismatch(instruction, left, lt, right, rt)
+            } else {
+                Ok(())
             }
         }
-        (ArithmeticOperand::LiteralInteger(_), _) | (ArithmeticOperand::LiteralReal(_), _) => {
-            operator_operand_mismatch(
-                instruction,
-                "EXCHANGE",
-                "memory reference",
-                left,
-                "`literal value`",
-            )
-        }
-        (_, ArithmeticOperand::LiteralInteger(_)) | (_, ArithmeticOperand::LiteralReal(_)) => {
-            operator_operand_mismatch(
-                instruction,
-                "EXCHANGE",
-                "memory reference",
-                right,
-                "`literal value`",
-            )
-        }
     }
 }
 
@@ -511,12 +457,12 @@ fn type_check_load(
     destination: &MemoryReference,
     source: &str,
     offset: &MemoryReference,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
     match (
-        memory_regions.get(&destination.name),
+        memory_regions.get(destination.name.as_str()),
         memory_regions.get(source),
-        memory_regions.get(&offset.name),
+        memory_regions.get(offset.name.as_str()),
     ) {
         (None, _, _) => undefined_memory_reference(instruction, destination),
         (_, None, _) => undefined_memory_reference(instruction, source),
@@ -544,11 +490,11 @@ fn type_check_store(
     destination: &str,
     offset: &MemoryReference,
     source: &ArithmeticOperand,
-    memory_regions: &BTreeMap<String, MemoryRegion>,
+    memory_regions: &BTreeMap<Identifier, MemoryRegion>,
 ) -> TypeResult<()> {
     match (
         memory_regions.get(destination),
-        memory_regions.get(&offset.name),
+        memory_regions.get(offset.name.as_str()),
     ) {
         (None, _) => undefined_memory_reference(instruction, destination),
         (_, None) => undefined_memory_reference(instruction, offset),
@@ -579,7 +525,7 @@ fn type_check_store(
                     (ArithmeticOperand::LiteralInteger(_), ScalarType::Bit) => Ok(()),
                     // <d*> <int> <s>  => check that d & s match
                     (ArithmeticOperand::MemoryReference(src_ref), _) => {
-                        if let Some(src_region) = memory_regions.get(&src_ref.name) {
+                        if let Some(src_region) = memory_regions.get(src_ref.name.as_str()) {
                             let st = &src_region.size.data_type;
                             if st == dt {
                                 Ok(())
@@ -0,0 +1,147 @@
+//! Draw circuit diagrams as [Typst](https://typst.app) markup, using the
+//! [`quill`](https://typst.app/universe/package/quill) package.
+//!
+//! The diagram is laid out exactly as for LaTeX (see [`crate::program::latex`]); each row of the
+//! diagram is drawn as its own `quantum-circuit`. Annotations, gate groups, and classical wires
+//! are only drawn by the LaTeX renderer.
+
+// Copyright 2022 Rigetti Computing
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use crate::instruction::Qubit;
+use crate::program::latex::{Command, Diagram, LatexGenError, RenderSettings, Renderer};
+use crate::Program;
+
+/// The version of `quill` the markup is written for.
+const QUILL_PACKAGE: &str = "@preview/quill:0.6.0";
+
+/// Draws a diagram as a Typst document containing a `quill` circuit for each row.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TypstRenderer;
+
+/// Write `text` as a Typst string literal.
+fn string(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', r"\\").replace('"', "\\\""))
+}
+
+/// The ket drawn as the label of the wire of `qubit`.
+fn label(qubit: &Qubit) -> String {
+    match qubit {
+        Qubit::Fixed(index) => format!("$|q_{index} angle.r$"),
+        Qubit::Variable(name) => format!("$|{} angle.r$", string(name)),
+    }
+}
+
+impl TypstRenderer {
+    /// Write the `quantum-circuit` drawing the columns `columns` of every wire of `diagram`.
+    fn render_row(&self, typst: &mut String, diagram: &Diagram, columns: Range<u32>) {
+        let wires: Vec<_> = diagram.wires().collect();
+
+        let _ = writeln!(typst, "#quantum-circuit(");
+        for (row, wire) in wires.iter().enumerate() {
+            let mut items = vec![format!("lstick({})", label(wire.qubit()))];
+            for column in columns.clone() {
+                let item = match wire.get_command(column) {
+                    Command::Gate(text) | Command::StyledGate(text, _) => {
+                        format!("gate({})", string(&text))
+                    }
+                    Command::WideGate { wires, name, .. } => {
+                        format!("mqgate({}, n: {wires})", string(&name))
+                    }
+                    Command::Phase(text) => format!("phase({})", string(&text)),
+                    Command::Meter => "meter()".to_string(),
+                    Command::Ctrl(offset) => format!("ctrl({offset})"),
+                    Command::Targ => "targ()".to_string(),
+                    // Cells covered by a multi-wire gate are left empty, with the gate drawn over
+                    // them.
+                    _ => "1".to_string(),
+                };
+                items.push(item);
+            }
+            items.push("1".to_string());
+
+            let _ = write!(typst, "  {},", items.join(", "));
+            if row + 1 < wires.len() {
+                let _ = write!(typst, r" [\ ],");
+            }
+            let _ = writeln!(typst);
+        }
+        let _ = writeln!(typst, ")");
+    }
+}
+
+impl Renderer for TypstRenderer {
+    fn render(&self, diagram: &Diagram) -> String {
+        let mut typst = format!("#import \"{QUILL_PACKAGE}\": *\n");
+        for columns in diagram.rows() {
+            typst.push('\n');
+            self.render_row(&mut typst, diagram, columns);
+        }
+        typst
+    }
+}
+
+impl Program {
+    /// Draw the gates and measurements of the program as a Typst document, laid out as by
+    /// [`crate::program::latex::Latex::to_latex`] with `settings`. Each wire is labelled with its
+    /// qubit.
+    pub fn to_typst(&self, settings: RenderSettings) -> Result<String, LatexGenError> {
+        let diagram = Diagram::build_plain_text(&self.instructions, settings)?;
+        Ok(TypstRenderer.render(&diagram))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::string;
+    use crate::program::latex::{CircuitDisplay, RenderSettings};
+    use crate::Program;
+
+    fn get_typst(input: &str, settings: RenderSettings) -> String {
+        Program::from_str(input)
+            .unwrap()
+            .to_typst(settings)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_typst() {
+        insta::assert_snapshot!(get_typst(
+            "H 0\nCNOT 0 1\nDAGGER RX(pi/2) 1\nMEASURE 1",
+            RenderSettings::default()
+        ));
+    }
+
+    #[test]
+    fn test_typst_boxes_and_rows() {
+        insta::assert_snapshot!(get_typst(
+            "DEFCIRCUIT BELL a b:\n    H a\n    CNOT a b\n\nBELL 0 1\nX a\nH 0",
+            RenderSettings {
+                circuit_display: CircuitDisplay::Box,
+                max_columns_per_row: Some(2),
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_string() {
+        assert_eq!(string(r#"a "b" \c"#), r#""a \"b\" \\c""#);
+    }
+}